@@ -1,18 +1,37 @@
 use std::cmp::Ordering;
-use std::io;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
 use std::ops;
+use std::path::{Path, PathBuf};
 
 use derive_more::{Display, Error, From};
 use olympia_engine::{
+    disassembler::{self, DisassemblyLine},
+    events::Event,
     gameboy,
-    monitor::{parse_number, Breakpoint, BreakpointCondition, Comparison, RWTarget},
+    monitor::{
+        parse_number, BankHistory, Breakpoint, BreakpointCondition, Comparison, Expression,
+        InterruptStats, MemoryProtection, Protection, RWTarget, StackGuard, SymbolTable,
+    },
     registers::{ByteRegister as br, WordRegister as wr},
+    rom::RomPatch,
+    shared::{Lock, Shared},
 };
+use serde_json::{json, Map, Value};
 use structopt::StructOpt;
 
-const PROMPT: &str = "> ";
+pub(crate) const PROMPT: &str = "> ";
+/// Number of instructions that can be undone with reverse-step
+pub(crate) const REVERSE_STEP_LIMIT: usize = 1000;
+/// Number of bytes copied at a time by dump and load
+const MEMORY_CHUNK_SIZE: usize = 0x400;
+/// Clocks taken to draw one frame while the LCD is on
+const FRAME_CLOCKS: u64 = 70224;
+/// Number of bank switches shown by bank-history
+const BANK_HISTORY_LIMIT: usize = 100;
 
-type ByteRange = (ops::Bound<u16>, ops::Bound<u16>);
+pub(crate) type ByteRange = (ops::Bound<u16>, ops::Bound<u16>);
 
 #[derive(Debug, Display, From, Error)]
 pub enum RangeParseError {
@@ -57,6 +76,38 @@ const NAMED_BOUNDS: [(&str, ByteRange); 7] = [
     addr_bound("cpuram", 0xfe00, 0xffff),
 ];
 
+/// The first and last addresses in a range
+pub(crate) fn range_addresses(range: ByteRange) -> (u16, u16) {
+    let (min, max) = range;
+
+    let min_address = match min {
+        ops::Bound::Unbounded => 0,
+        ops::Bound::Included(x) => x,
+        ops::Bound::Excluded(x) => x + 1,
+    };
+
+    let max_address = match max {
+        ops::Bound::Unbounded => u16::MAX,
+        ops::Bound::Included(x) => x,
+        ops::Bound::Excluded(x) => x - 1,
+    };
+
+    (min_address, max_address)
+}
+
+/// Format an instruction for the disassemble command
+pub(crate) fn format_disassembly_line(line: &DisassemblyLine) -> String {
+    let marker = if line.is_current_pc { "=>" } else { "  " };
+    let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(
+        "{} {:04X}:  {:<8}  {}",
+        marker,
+        line.address,
+        bytes.join(" "),
+        line.text
+    )
+}
+
 fn parse_range(src: &str) -> Result<ByteRange, RangeParseError> {
     for (name, range) in NAMED_BOUNDS.iter() {
         if src == *name {
@@ -78,44 +129,192 @@ fn parse_range(src: &str) -> Result<ByteRange, RangeParseError> {
     }
 }
 
+/// Value a breakpoint should trigger on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BreakpointValue {
+    Equal(u16),
+    Changed,
+}
+
+fn parse_breakpoint_value(src: &str) -> Result<BreakpointValue, std::num::ParseIntError> {
+    if src.eq_ignore_ascii_case("changed") {
+        Ok(BreakpointValue::Changed)
+    } else {
+        parse_number(src).map(BreakpointValue::Equal)
+    }
+}
+
+/// How the results of debugger commands are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    /// Human readable text, with errors written separately
+    Text,
+    /// A JSON object on a single line for each command, including any errors
+    Json,
+}
+
 struct CliDebugger<'a> {
     breakpoints: Vec<Breakpoint>,
+    protection: Shared<Lock<MemoryProtection>>,
+    stack_guard: Shared<Lock<Option<StackGuard>>>,
+    /// Print stack guard violations as warnings, rather than breaking
+    stack_guard_warn: bool,
+    bank_history: Shared<Lock<BankHistory>>,
+    interrupt_stats: Shared<Lock<InterruptStats>>,
+    /// Bytes the ROM has sent to the serial console
+    serial_output: Shared<Lock<Vec<u8>>>,
+    /// Names that can be used in place of addresses, from load-symbols
+    symbols: SymbolTable,
     gb: gameboy::GameBoy,
     inb: &'a mut dyn io::BufRead,
     out: &'a mut dyn io::Write,
     err: &'a mut dyn io::Write,
+    format: OutputFormat,
+    /// Fields of the JSON object for the command being run
+    result: Map<String, Value>,
+    /// Errors from the command being run, with JSON output
+    errors: Vec<String>,
 }
 
 impl<'a> CliDebugger<'a> {
     fn new(
-        gb: gameboy::GameBoy,
+        mut gb: gameboy::GameBoy,
         inb: &'a mut dyn io::BufRead,
         out: &'a mut dyn io::Write,
         err: &'a mut dyn io::Write,
     ) -> CliDebugger<'a> {
+        let protection = Shared::new(Lock::new(MemoryProtection::new()));
+        let handler_protection = protection.clone();
+        gb.events.on(Box::new(move |evt| {
+            handler_protection.borrow_mut().record(evt)
+        }));
+        let stack_guard: Shared<Lock<Option<StackGuard>>> = Shared::new(Lock::new(None));
+        let handler_stack_guard = stack_guard.clone();
+        gb.events.on(Box::new(move |evt| {
+            if let Some(guard) = handler_stack_guard.borrow_mut().as_mut() {
+                guard.record(evt);
+            }
+        }));
+        let bank_history = Shared::new(Lock::new(BankHistory::new(BANK_HISTORY_LIMIT)));
+        let handler_bank_history = bank_history.clone();
+        gb.events.on_timestamped(Box::new(move |evt| {
+            handler_bank_history.borrow_mut().record(evt)
+        }));
+        gb.set_interrupt_tracing(true);
+        let interrupt_stats = Shared::new(Lock::new(InterruptStats::new()));
+        let handler_interrupt_stats = interrupt_stats.clone();
+        gb.events.on(Box::new(move |evt| {
+            handler_interrupt_stats.borrow_mut().record(evt)
+        }));
+        let serial_output = Shared::new(Lock::new(Vec::new()));
+        let handler_serial_output = serial_output.clone();
+        gb.events.on(Box::new(move |evt| {
+            if let Event::SerialOutput(evt) = evt {
+                handler_serial_output.borrow_mut().push(evt.value);
+            }
+        }));
         CliDebugger {
             breakpoints: Vec::new(),
+            protection,
+            stack_guard,
+            stack_guard_warn: false,
+            bank_history,
+            interrupt_stats,
+            serial_output,
+            symbols: SymbolTable::new(),
             gb,
             inb,
             out,
             err,
+            format: OutputFormat::Text,
+            result: Map::new(),
+            errors: Vec::new(),
         }
     }
 
-    fn print_bytes(&mut self, range: ByteRange) -> io::Result<()> {
-        let (min, max) = range;
+    /// Print a command's result as text, or add its fields to the JSON result
+    fn report(&mut self, text: fmt::Arguments, fields: Value) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Text => writeln!(self.out, "{}", text),
+            OutputFormat::Json => {
+                if let Value::Object(fields) = fields {
+                    self.result.extend(fields);
+                }
+                Ok(())
+            }
+        }
+    }
 
-        let min_address = match min {
-            ops::Bound::Unbounded => 0,
-            ops::Bound::Included(x) => x,
-            ops::Bound::Excluded(x) => x + 1,
-        };
+    /// Report an error with the command being run
+    fn error(&mut self, message: impl fmt::Display) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Text => writeln!(self.err, "{}", message),
+            OutputFormat::Json => {
+                self.errors.push(message.to_string());
+                Ok(())
+            }
+        }
+    }
 
-        let max_address = match max {
-            ops::Bound::Unbounded => std::u16::MAX,
-            ops::Bound::Included(x) => x,
-            ops::Bound::Excluded(x) => x - 1,
-        };
+    /// Parse a register or memory location, using the current registers and symbols
+    fn resolve_target(&mut self, src: &str) -> io::Result<Option<RWTarget>> {
+        match RWTarget::parse_with(src, Some(&self.gb), &self.symbols) {
+            Ok(target) => Ok(Some(target)),
+            Err(e) => self.error(e).map(|_| None),
+        }
+    }
+
+    /// Parse a memory location, using the current registers and symbols
+    fn resolve_address(&mut self, src: &str) -> io::Result<Option<u16>> {
+        match self.resolve_target(src)? {
+            Some(RWTarget::Address(addr)) => Ok(Some(addr.0)),
+            Some(RWTarget::BankedAddress(addr)) => Ok(Some(addr.addr.0)),
+            Some(target) => self
+                .error(format_args!("{} is not a memory location", target))
+                .map(|_| None),
+            None => Ok(None),
+        }
+    }
+
+    /// Report a problem which doesn't stop the command being run
+    fn warn(&mut self, message: impl fmt::Display) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Text => writeln!(self.err, "Warning: {}", message),
+            OutputFormat::Json => {
+                let warnings = self
+                    .result
+                    .entry("warnings")
+                    .or_insert_with(|| Value::Array(Vec::new()));
+                if let Value::Array(warnings) = warnings {
+                    warnings.push(message.to_string().into());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Print a JSON object as a single line
+    fn write_json(&mut self, value: &Value) -> io::Result<()> {
+        writeln!(self.out, "{}", value)?;
+        self.out.flush()
+    }
+
+    fn print_bytes(&mut self, range: ByteRange) -> io::Result<()> {
+        let (min_address, max_address) = range_addresses(range);
+
+        if self.format == OutputFormat::Json {
+            let len = usize::from(max_address.wrapping_sub(min_address)) + 1;
+            let bytes: Vec<Option<u8>> = (0..len)
+                .map(|offset| {
+                    let addr = min_address.wrapping_add(offset as u16);
+                    self.gb.get_memory_u8(addr).ok()
+                })
+                .collect();
+            self.result
+                .insert(String::from("start"), min_address.into());
+            self.result.insert(String::from("bytes"), json!(bytes));
+            return Ok(());
+        }
 
         let mut addr = min_address;
         let mut printed_first = false;
@@ -141,7 +340,103 @@ impl<'a> CliDebugger<'a> {
         writeln!(self.out)
     }
 
+    fn dump(&mut self, range: ByteRange, path: &Path) -> io::Result<()> {
+        let (min_address, max_address) = range_addresses(range);
+        let len = usize::from(max_address.wrapping_sub(min_address)) + 1;
+
+        let mut file = match fs::File::create(path) {
+            Ok(file) => io::BufWriter::new(file),
+            Err(e) => {
+                return self.error(format_args!("Could not create {}: {}", path.display(), e))
+            }
+        };
+        let mut buf = [0u8; MEMORY_CHUNK_SIZE];
+        let mut addr = min_address;
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = &mut buf[..remaining.min(MEMORY_CHUNK_SIZE)];
+            if let Err(e) = self.gb.get_memory_chunk(addr, chunk) {
+                return self.error(e);
+            }
+            if let Err(e) = file.write_all(chunk) {
+                return self.error(format_args!("Could not write {}: {}", path.display(), e));
+            }
+            addr = addr.wrapping_add(chunk.len() as u16);
+            remaining -= chunk.len();
+        }
+        if let Err(e) = file.flush() {
+            return self.error(format_args!("Could not write {}: {}", path.display(), e));
+        }
+
+        self.report(
+            format_args!(
+                "Dumped {} bytes from {:04X} to {}",
+                len,
+                min_address,
+                path.display()
+            ),
+            json!({"length": len, "start": min_address, "file": path.display().to_string()}),
+        )
+    }
+
+    fn load(&mut self, path: &Path, start: u16) -> io::Result<()> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) => return self.error(format_args!("Could not read {}: {}", path.display(), e)),
+        };
+        let space = 0x10000 - usize::from(start);
+        if data.len() > space {
+            return self.error(format_args!(
+                "{} is {} bytes, but only {} bytes fit from {:04X}",
+                path.display(),
+                data.len(),
+                space,
+                start
+            ));
+        }
+
+        let mut addr = start;
+        for chunk in data.chunks(MEMORY_CHUNK_SIZE) {
+            if let Err(e) = self.gb.set_memory_chunk(addr, chunk) {
+                return self.error(e);
+            }
+            addr = addr.wrapping_add(chunk.len() as u16);
+        }
+
+        self.report(
+            format_args!(
+                "Loaded {} bytes from {} to {:04X}",
+                data.len(),
+                path.display(),
+                start
+            ),
+            json!({"length": data.len(), "start": start, "file": path.display().to_string()}),
+        )
+    }
+
     fn print_registers(&mut self) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            let flags_register = self.gb.read_register_u8(br::F);
+            let mut registers = Map::new();
+            for reg in &[br::A, br::F, br::B, br::C, br::D, br::E, br::H, br::L] {
+                registers.insert(format!("{:?}", reg), self.gb.read_register_u8(*reg).into());
+            }
+            for reg in &[wr::AF, wr::BC, wr::DE, wr::HL, wr::SP, wr::PC] {
+                registers.insert(format!("{:?}", reg), self.gb.read_register_u16(*reg).into());
+            }
+            self.result
+                .insert(String::from("registers"), Value::Object(registers));
+            self.result.insert(
+                String::from("flags"),
+                json!({
+                    "zero": flags_register & 0x80 != 0,
+                    "add_subtract": flags_register & 0x40 != 0,
+                    "half_carry": flags_register & 0x20 != 0,
+                    "carry": flags_register & 0x10 != 0,
+                }),
+            );
+            return Ok(());
+        }
         writeln!(
             self.out,
             "A: {:02X}, F: {:02x}, AF: {:04X}",
@@ -188,11 +483,67 @@ impl<'a> CliDebugger<'a> {
         Ok(())
     }
 
+    /// Run a single step, returning the address of the instruction run
+    fn run_step(&mut self) -> gameboy::StepResult<u16> {
+        let pc = self.gb.read_register_u16(wr::PC);
+        self.protection.borrow_mut().begin_step(&self.gb);
+        self.gb.step()?;
+        Ok(pc)
+    }
+
+    /// Report memory protection and stack violations from the step of the
+    /// instruction at `pc`, returning whether to break
+    fn check_violations(&mut self, pc: u16) -> io::Result<bool> {
+        let violation = self.protection.borrow_mut().take_violation();
+        if let Some(violation) = violation {
+            let description = violation.to_string();
+            self.report(
+                format_args!("Broke on {}", description),
+                json!({ "broke_on": description }),
+            )?;
+            return Ok(true);
+        }
+        let violations = match self.stack_guard.borrow_mut().as_mut() {
+            Some(guard) => guard.take_violations(),
+            None => return Ok(false),
+        };
+        for violation in violations {
+            let description = format!("{}, by the instruction at {:04X}h", violation, pc);
+            if self.stack_guard_warn {
+                self.warn(description)?;
+            } else {
+                self.report(
+                    format_args!("Broke on stack guard: {}", description),
+                    json!({ "broke_on": format!("stack guard: {}", description) }),
+                )?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     fn step(&mut self, steps: u16) -> io::Result<()> {
+        // Only instructions which are stepped through are recorded, as
+        // recording slows down running
+        self.gb.set_history_limit(REVERSE_STEP_LIMIT);
+        for _ in 0..steps {
+            match self.run_step() {
+                Ok(pc) => {
+                    if self.check_violations(pc)? {
+                        break;
+                    }
+                }
+                Err(e) => self.error(e)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn reverse_step(&mut self, steps: u16) -> io::Result<()> {
         for _ in 0..steps {
-            match self.gb.step() {
-                Ok(_) => (),
-                Err(e) => writeln!(self.err, "{:?}", e)?,
+            if !self.gb.reverse_step() {
+                self.error("No earlier state to step back to")?;
+                break;
             }
         }
         Ok(())
@@ -200,163 +551,780 @@ impl<'a> CliDebugger<'a> {
 
     fn cycle_count(&mut self) -> io::Result<()> {
         let cycles = self.gb.clocks_elapsed();
-        writeln!(self.out, "Cycles: {} / M-Cycles: {}", cycles, cycles / 4)?;
-        Ok(())
+        self.report(
+            format_args!("Cycles: {} / M-Cycles: {}", cycles, cycles / 4),
+            json!({"cycles": cycles, "m_cycles": cycles / 4}),
+        )
+    }
+
+    fn ppu_timing(&mut self) -> io::Result<()> {
+        let lengths = self.gb.ppu_mode_lengths();
+        self.report(
+            format_args!(
+                "Line {}: OAM scan {} / Drawing {} / HBlank {} clocks",
+                lengths.line, lengths.oam_scan, lengths.drawing, lengths.hblank
+            ),
+            json!({
+                "line": lengths.line,
+                "oam_scan": lengths.oam_scan,
+                "drawing": lengths.drawing,
+                "hblank": lengths.hblank,
+            }),
+        )
     }
 
     fn read(&mut self, target: RWTarget) -> io::Result<()> {
         match target.read(&self.gb) {
-            Ok(val) => writeln!(self.out, "{:X}", val)?,
-            Err(e) => writeln!(self.err, "{}", e)?,
-        };
-        Ok(())
+            Ok(val) => self.report(
+                format_args!("{:X}", val),
+                json!({"target": target.to_string(), "value": val}),
+            ),
+            Err(e) => self.error(e),
+        }
     }
 
     fn write(&mut self, target: RWTarget, value: u16) -> io::Result<()> {
         match target.write(&mut self.gb, value) {
-            Ok(old) => writeln!(self.out, "Wrote {:X} (was {:X})", value, old)?,
-            Err(e) => writeln!(self.err, "{}", e)?,
+            Ok(old) => self.report(
+                format_args!("Wrote {:X} (was {:X})", value, old),
+                json!({"target": target.to_string(), "value": value, "old": old}),
+            ),
+            Err(e) => self.error(e),
+        }
+    }
+
+    fn patch(&mut self, target: RWTarget, bytes: &[u16]) -> io::Result<()> {
+        let (start, bank) = match target {
+            RWTarget::Address(addr) => (addr.0, None),
+            RWTarget::BankedAddress(addr) => (addr.addr.0, Some(addr.bank)),
+            target => return self.error(format_args!("{} is not a memory location", target)),
         };
-        Ok(())
+        if let Some(value) = bytes.iter().find(|value| **value > 0xFF) {
+            return self.error(format_args!("{:X} is not a byte", value));
+        }
+        let mut patches = Vec::with_capacity(bytes.len());
+        for (offset, value) in bytes.iter().enumerate() {
+            let addr = start.wrapping_add(offset as u16);
+            match bank.or_else(|| self.gb.rom_bank(addr)) {
+                Some(bank) if addr < 0x8000 => {
+                    patches.push(RomPatch::new(addr, *value as u8).in_bank(bank))
+                }
+                _ => return self.error(format_args!("{:04X} is not in ROM", addr)),
+            }
+        }
+        for patch in patches {
+            if let Err(e) = self.gb.patch_rom(patch) {
+                return self.error(e);
+            }
+        }
+        self.report(
+            format_args!("Patched {} bytes at {:04X}", bytes.len(), start),
+            json!({"address": start, "bytes": bytes}),
+        )
+    }
+
+    fn unpatch(&mut self, addr: u16) -> io::Result<()> {
+        let removed = self.gb.remove_rom_patches(addr);
+        self.report(
+            format_args!("Removed {} patches", removed),
+            json!({ "removed": removed }),
+        )
+    }
+
+    fn game_genie(&mut self, code: &str) -> io::Result<()> {
+        let patch = match RomPatch::from_game_genie(code) {
+            Ok(patch) => patch,
+            Err(e) => return self.error(e),
+        };
+        if let Err(e) = self.gb.patch_rom(patch) {
+            return self.error(e);
+        }
+        match patch.compare {
+            Some(compare) => self.report(
+                format_args!(
+                    "Patched {:04X} to {:02X} where it was {:02X}",
+                    patch.address, patch.value, compare
+                ),
+                json!({"address": patch.address, "value": patch.value, "compare": compare}),
+            ),
+            None => self.report(
+                format_args!("Patched {:04X} to {:02X}", patch.address, patch.value),
+                json!({"address": patch.address, "value": patch.value}),
+            ),
+        }
+    }
+
+    fn eval(&mut self, src: &str) -> io::Result<()> {
+        let expr = match src.parse::<Expression>() {
+            Ok(expr) => expr,
+            Err(e) => return self.error(e),
+        };
+        match expr.evaluate_in(Some(&self.gb), &self.symbols) {
+            Ok(value) if value < 0 => self.report(
+                format_args!("-0x{:X} ({})", value.wrapping_neg() as u64, value),
+                json!({ "value": value }),
+            ),
+            Ok(value) => self.report(
+                format_args!("0x{:X} ({})", value, value),
+                json!({ "value": value }),
+            ),
+            Err(e) => self.error(e),
+        }
+    }
+
+    fn load_symbols(&mut self, path: &Path) -> io::Result<()> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => return self.error(format_args!("Could not read {}: {}", path.display(), e)),
+        };
+        match text.parse::<SymbolTable>() {
+            Ok(symbols) => {
+                self.symbols = symbols;
+                self.report(
+                    format_args!(
+                        "Loaded {} symbols from {}",
+                        self.symbols.len(),
+                        path.display()
+                    ),
+                    json!({ "symbols": self.symbols.len() }),
+                )
+            }
+            Err(e) => self.error(format_args!("Could not parse {}: {}", path.display(), e)),
+        }
     }
 
     fn print_current(&mut self) -> io::Result<()> {
         let ci = self.gb.current_instruction();
         let disassembly = match ci {
             Ok(instr) => instr.disassemble(),
-            Err(gameboy::StepError::InvalidOpcode(i)) => format!("DAT {:X}h", i),
+            Err(gameboy::StepError::InvalidOpcode(i, _)) => format!("DAT {:X}h", i),
             Err(gameboy::StepError::Memory(_)) => String::from("--"),
         };
-        writeln!(self.out, "{}", disassembly)?;
+        self.report(
+            format_args!("{}", disassembly),
+            json!({ "instruction": disassembly }),
+        )
+    }
+
+    fn print_disassembly(&mut self, start: Option<u16>, count: u16) -> io::Result<()> {
+        let start = start.unwrap_or_else(|| self.gb.read_register_u16(wr::PC));
+        let lines = disassembler::disassemble_memory(&self.gb, start, usize::from(count));
+        if self.format == OutputFormat::Json {
+            let instructions: Vec<Value> = lines
+                .iter()
+                .map(|line| {
+                    json!({
+                        "address": line.address,
+                        "bytes": line.bytes,
+                        "text": line.text,
+                        "is_current_pc": line.is_current_pc,
+                    })
+                })
+                .collect();
+            self.result
+                .insert(String::from("instructions"), Value::Array(instructions));
+            return Ok(());
+        }
+        for line in lines {
+            writeln!(self.out, "{}", format_disassembly_line(&line))?;
+        }
         Ok(())
     }
 
-    fn add_breakpoint(&mut self, target: RWTarget, value: u16) -> io::Result<()> {
-        self.breakpoints.push(Breakpoint::new(
-            target,
-            BreakpointCondition::Test(Comparison::Equal, value.into()),
-        ));
-        writeln!(self.out, "Added breakpoint for {} == {:X}", target, value)?;
+    fn add_breakpoint(
+        &mut self,
+        target: RWTarget,
+        value: Option<BreakpointValue>,
+    ) -> io::Result<()> {
+        match (target, value) {
+            (_, Some(BreakpointValue::Equal(value))) => {
+                self.breakpoints.push(Breakpoint::new(
+                    target,
+                    BreakpointCondition::Test(Comparison::Equal, value.into()),
+                ));
+                self.report_breakpoint_added(format_args!("{} == {:X}", target, value))?;
+            }
+            (_, Some(BreakpointValue::Changed)) => {
+                let mut breakpoint = Breakpoint::new(target, BreakpointCondition::Changed);
+                // Record the starting value so the first step can trigger it
+                breakpoint.check(&self.gb);
+                self.breakpoints.push(breakpoint);
+                self.report_breakpoint_added(format_args!("{} Changed", target))?;
+            }
+            (RWTarget::Address(_), None) | (RWTarget::BankedAddress(_), None) => {
+                self.breakpoints
+                    .push(Breakpoint::new(target, BreakpointCondition::Execute));
+                self.report_breakpoint_added(format_args!("{} Execute", target))?;
+            }
+            (_, None) => {
+                self.error(format_args!("A value is required to break on {}", target))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn protect(&mut self, range: ByteRange, protection: Protection) -> io::Result<()> {
+        let (start, end) = range_addresses(range);
+        self.protection.borrow_mut().protect(start, end, protection);
+        self.report(
+            format_args!("Protected {:04X}-{:04X} as {}", start, end, protection),
+            json!({"start": start, "end": end, "protection": protection.to_string()}),
+        )
+    }
+
+    fn unprotect(&mut self, range: ByteRange) -> io::Result<()> {
+        let (start, end) = range_addresses(range);
+        let removed = self.protection.borrow_mut().unprotect(start, end);
+        self.report(
+            format_args!("Removed {} protected regions", removed),
+            json!({ "removed": removed }),
+        )
+    }
+
+    fn guard_stack(&mut self, range: Option<ByteRange>, warn: bool, off: bool) -> io::Result<()> {
+        let range = match range {
+            Some(range) if !off => range,
+            _ => {
+                *self.stack_guard.borrow_mut() = None;
+                return self.report(format_args!("Removed stack guard"), json!({}));
+            }
+        };
+        let (start, end) = range_addresses(range);
+        let sp = self.gb.read_register_u16(wr::SP);
+        *self.stack_guard.borrow_mut() = Some(StackGuard::new(start, end, sp));
+        self.stack_guard_warn = warn;
+        self.report(
+            format_args!("Guarding stack in {:04X}-{:04X}", start, end),
+            json!({"start": start, "end": end, "warn": warn}),
+        )
+    }
+
+    fn print_bank_history(&mut self, count: Option<usize>) -> io::Result<()> {
+        let history = self.bank_history.borrow();
+        let skip = count.map_or(0, |count| history.len().saturating_sub(count));
+        let switches: Vec<_> = history.iter().skip(skip).cloned().collect();
+        drop(history);
+
+        if self.format == OutputFormat::Json {
+            let switches: Vec<Value> = switches
+                .iter()
+                .map(|switch| {
+                    json!({
+                        "clocks": switch.timestamp.clocks,
+                        "kind": switch.event.kind.to_string(),
+                        "old": switch.event.old,
+                        "new": switch.event.new,
+                    })
+                })
+                .collect();
+            self.result
+                .insert(String::from("switches"), Value::Array(switches));
+            return Ok(());
+        }
+        if switches.is_empty() {
+            return writeln!(self.out, "No bank switches recorded");
+        }
+        for switch in switches {
+            writeln!(
+                self.out,
+                "Clock {}: {} bank {:X} -> {:X}",
+                switch.timestamp.clocks, switch.event.kind, switch.event.old, switch.event.new
+            )?;
+        }
+        Ok(())
+    }
+
+    fn print_interrupts(&mut self, reset: bool) -> io::Result<()> {
+        let summaries: Vec<_> = self.interrupt_stats.borrow().dispatched().collect();
+        if reset {
+            self.interrupt_stats.borrow_mut().clear();
+        }
+
+        if self.format == OutputFormat::Json {
+            let summaries: Vec<Value> = summaries
+                .iter()
+                .map(|(interrupt, summary)| {
+                    json!({
+                        "interrupt": interrupt.to_string(),
+                        "count": summary.count,
+                        "worst_latency": summary.worst_latency,
+                        "average_latency": summary.average_latency(),
+                    })
+                })
+                .collect();
+            self.result
+                .insert(String::from("interrupts"), Value::Array(summaries));
+            return Ok(());
+        }
+        if summaries.is_empty() {
+            return writeln!(self.out, "No interrupts dispatched");
+        }
+        for (interrupt, summary) in summaries {
+            let latency = match (summary.worst_latency, summary.average_latency()) {
+                (Some(worst), Some(average)) => {
+                    format!("worst latency {} clocks, average {} clocks", worst, average)
+                }
+                _ => String::from("latency unknown"),
+            };
+            writeln!(
+                self.out,
+                "{}: {} dispatched, {}",
+                interrupt, summary.count, latency
+            )?;
+        }
         Ok(())
     }
 
-    fn fast_forward(&mut self) -> io::Result<()> {
-        'ff: loop {
-            match self.gb.step() {
-                Ok(_) => (),
+    fn print_serial_output(&mut self, clear: bool) -> io::Result<()> {
+        let text = String::from_utf8_lossy(&self.serial_output.borrow()).into_owned();
+        if clear {
+            self.serial_output.borrow_mut().clear();
+        }
+
+        if text.is_empty() && self.format == OutputFormat::Text {
+            return writeln!(self.out, "No serial output captured");
+        }
+        self.report(
+            format_args!("{}", text.trim_end_matches('\n')),
+            json!({ "serial": text }),
+        )
+    }
+
+    fn report_breakpoint_added(&mut self, description: fmt::Arguments) -> io::Result<()> {
+        let description = description.to_string();
+        self.report(
+            format_args!("Added breakpoint for {}", description),
+            json!({ "breakpoint": description }),
+        )
+    }
+
+    /// Check every breakpoint, and report the first one triggered
+    /// Report the first breakpoint triggered by the last step, and return it
+    fn check_breakpoints(&mut self) -> io::Result<Option<Breakpoint>> {
+        let gb = &self.gb;
+        let triggered = self.breakpoints.iter_mut().find_map(|breakpoint| {
+            if breakpoint.check(gb) {
+                Some(breakpoint.clone())
+            } else {
+                None
+            }
+        });
+        if let Some(breakpoint) = &triggered {
+            let description = breakpoint.to_string();
+            self.report(
+                format_args!("Broke on {}", description),
+                json!({ "broke_on": description }),
+            )?;
+        }
+        Ok(triggered)
+    }
+
+    /// Run until a breakpoint is triggered, returning the breakpoint
+    ///
+    /// Stops without a breakpoint on errors and memory or stack violations.
+    /// The last `recorded_steps` instructions are recorded so they can be undone.
+    fn fast_forward(&mut self, recorded_steps: usize) -> io::Result<Option<Breakpoint>> {
+        self.gb.set_history_limit(recorded_steps);
+        let mut triggered = None;
+        loop {
+            match self.run_step() {
+                Ok(pc) => {
+                    if self.check_violations(pc)? {
+                        break;
+                    }
+                }
                 Err(e) => {
-                    writeln!(self.err, "Broke due to error {:?}", e)?;
+                    self.error(format_args!("Broke due to error: {}", e))?;
                     break;
                 }
             };
-            for breakpoint in &self.breakpoints {
-                if breakpoint.should_break(&self.gb) {
-                    writeln!(self.out, "Broke on {}", breakpoint)?;
-                    break 'ff;
+            triggered = self.check_breakpoints()?;
+            if triggered.is_some() {
+                break;
+            }
+        }
+        self.breakpoints.retain(|bp| !bp.is_temporary());
+        Ok(triggered)
+    }
+
+    fn run_until(&mut self, target: RWTarget) -> io::Result<()> {
+        match target {
+            RWTarget::Address(_) | RWTarget::BankedAddress(_) => {
+                self.breakpoints
+                    .push(Breakpoint::new(target, BreakpointCondition::Execute).temporary());
+                self.fast_forward(0).map(drop)
+            }
+            _ => self.error(format_args!(
+                "Can only run until a memory location, not {}",
+                target
+            )),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let mut breakpoint = Breakpoint::function_return().temporary();
+        // Follow calls and returns from the current instruction
+        breakpoint.check(&self.gb);
+        self.breakpoints.push(breakpoint);
+        self.fast_forward(0).map(drop)
+    }
+
+    /// Run until a condition is met, then undo steps to show what led up to it
+    fn break_before(
+        &mut self,
+        steps: u16,
+        target: RWTarget,
+        value: Option<BreakpointValue>,
+    ) -> io::Result<()> {
+        if usize::from(steps) > REVERSE_STEP_LIMIT {
+            return self.error(format_args!(
+                "Can only break up to {} instructions before",
+                REVERSE_STEP_LIMIT
+            ));
+        }
+        let condition = match (target, value) {
+            (_, Some(BreakpointValue::Equal(value))) => {
+                BreakpointCondition::Test(Comparison::Equal, value.into())
+            }
+            (_, Some(BreakpointValue::Changed)) => BreakpointCondition::Changed,
+            (RWTarget::Address(_), None) | (RWTarget::BankedAddress(_), None) => {
+                BreakpointCondition::Execute
+            }
+            (_, None) => {
+                return self.error(format_args!("A value is required to break on {}", target))
+            }
+        };
+        let mut breakpoint = Breakpoint::new(target, condition).temporary();
+        // Record the starting value so the first step can trigger a `Changed` condition
+        breakpoint.check(&self.gb);
+        self.breakpoints.push(breakpoint);
+        match self.fast_forward(usize::from(steps))? {
+            Some(breakpoint) if breakpoint.is_temporary() => {}
+            _ => return Ok(()),
+        }
+
+        let mut rewound = 0;
+        while rewound < steps && self.gb.reverse_step() {
+            rewound += 1;
+        }
+        if rewound < steps {
+            self.warn(format_args!(
+                "Only {} earlier instructions were recorded",
+                rewound
+            ))?;
+        }
+        let pc = self.gb.read_register_u16(wr::PC);
+        self.report(
+            format_args!("Rewound {} instructions to {:04X}h", rewound, pc),
+            json!({ "rewound": rewound, "pc": pc }),
+        )
+    }
+
+    fn frame_advance(&mut self, frames: u32) -> io::Result<()> {
+        let vblanks = Shared::new(Lock::new(0u32));
+        let handler_vblanks = vblanks.clone();
+        let handler = self.gb.events.on(Box::new(move |evt| {
+            if let Event::VBlank(_) = evt {
+                *handler_vblanks.borrow_mut() += 1;
+            }
+        }));
+
+        self.gb.set_history_limit(0);
+        let start_clocks = self.gb.clocks_elapsed();
+        let mut last_vblank_clocks = start_clocks;
+        let mut seen_vblanks = 0;
+        while *vblanks.borrow() < frames {
+            match self.run_step() {
+                Ok(pc) => {
+                    if self.check_violations(pc)? {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    self.error(format_args!("Broke due to error: {}", e))?;
+                    break;
                 }
             }
+            if self.check_breakpoints()?.is_some() {
+                break;
+            }
+            if *vblanks.borrow() != seen_vblanks {
+                seen_vblanks = *vblanks.borrow();
+                last_vblank_clocks = self.gb.clocks_elapsed();
+            } else if self.gb.clocks_elapsed() - last_vblank_clocks > FRAME_CLOCKS * 2 {
+                self.error("No frames are being drawn. Is the LCD off?")?;
+                break;
+            }
         }
-        Ok(())
+        self.gb.events.off(handler);
+
+        let cycles = self.gb.clocks_elapsed() - start_clocks;
+        let frames_run = *vblanks.borrow();
+        self.report(
+            format_args!(
+                "Ran {} frames. Cycles: {} / M-Cycles: {}",
+                frames_run,
+                cycles,
+                cycles / 4
+            ),
+            json!({"frames": frames_run, "cycles": cycles, "m_cycles": cycles / 4}),
+        )
     }
 
     fn debug(&mut self) -> io::Result<()> {
         loop {
-            write!(self.err, "{}", PROMPT)?;
-            self.err.flush()?;
-            let mut input = String::new();
-            let read_result = self.inb.read_line(&mut input);
-
-            match read_result {
-                Ok(0) => return Ok(()),
-                Ok(_) => (),
-                Err(e) => return Err(e),
+            let input = match self.format {
+                OutputFormat::Text => read_command(self.inb, self.out, self.err)?,
+                OutputFormat::Json => self.read_json_command()?,
+            };
+            let command = match input {
+                Input::Command(command) => command,
+                Input::Invalid => continue,
+                Input::Closed => return Ok(()),
+            };
+            if !self.run_command(command)? {
+                break;
             }
+        }
+        Ok(())
+    }
 
-            let trimmed_input = input.trim();
+    /// Read the next command without prompting, reporting parse errors as JSON
+    fn read_json_command(&mut self) -> io::Result<Input> {
+        let mut input = String::new();
+        if self.inb.read_line(&mut input)? == 0 {
+            return Ok(Input::Closed);
+        }
+        let mut help = Vec::new();
+        let mut errors = Vec::new();
+        let parsed = parse_command(&input, &mut help, &mut errors)?;
+        if let Input::Invalid = parsed {
+            let mut result = json!({ "command": null, "errors": [] });
+            if !help.is_empty() {
+                result["help"] = String::from_utf8_lossy(&help).trim().into();
+            }
+            if !errors.is_empty() {
+                result["errors"] = json!([String::from_utf8_lossy(&errors).trim()]);
+            }
+            self.write_json(&result)?;
+        }
+        Ok(parsed)
+    }
 
-            let parsed_command = if trimmed_input.is_empty() {
-                let empty_iter: std::slice::Iter<&str> = [].iter();
-                DebugCommand::from_iter_safe(empty_iter)
-            } else {
-                DebugCommand::from_iter_safe(trimmed_input.split(' '))
-            };
+    /// Run a single command, returning false if the session should end
+    fn run_command(&mut self, command: DebugCommand) -> io::Result<bool> {
+        let name = command.name();
+        let keep_going = self.execute(command)?;
+        if self.format == OutputFormat::Json {
+            let mut result = Map::new();
+            result.insert(String::from("command"), name.into());
+            result.append(&mut self.result);
+            result.insert(
+                String::from("errors"),
+                std::mem::take(&mut self.errors).into(),
+            );
+            self.write_json(&Value::Object(result))?;
+        }
+        self.out.flush()?;
+        self.err.flush()?;
+        Ok(keep_going)
+    }
 
-            match parsed_command {
-                Ok(DebugCommand::Exit) => {
-                    writeln!(self.out, "Exiting")?;
-                    break;
+    fn execute(&mut self, command: DebugCommand) -> io::Result<bool> {
+        match command {
+            DebugCommand::Exit => {
+                self.report(format_args!("Exiting"), json!({}))?;
+                return Ok(false);
+            }
+            DebugCommand::PrintBytes { range } => self.print_bytes(range)?,
+            DebugCommand::Dump { range, file } => self.dump(range, &file)?,
+            DebugCommand::Load { file, addr } => self.load(&file, addr)?,
+            DebugCommand::PrintRegisters => self.print_registers()?,
+            DebugCommand::Step { steps } => self.step(steps)?,
+            DebugCommand::ReverseStep { steps } => self.reverse_step(steps)?,
+            DebugCommand::CycleCount => self.cycle_count()?,
+            DebugCommand::PpuTiming => self.ppu_timing()?,
+            DebugCommand::Read { target } => {
+                if let Some(target) = self.resolve_target(&target)? {
+                    self.read(target)?
                 }
-                Ok(DebugCommand::PrintBytes { range }) => self.print_bytes(range)?,
-                Ok(DebugCommand::PrintRegisters) => self.print_registers()?,
-                Ok(DebugCommand::Step { steps }) => self.step(steps)?,
-                Ok(DebugCommand::CycleCount) => self.cycle_count()?,
-                Ok(DebugCommand::Read { target }) => self.read(target)?,
-                Ok(DebugCommand::Write { target, value }) => self.write(target, value)?,
-                Ok(DebugCommand::Breakpoint { target, value }) => {
-                    self.add_breakpoint(target, value)?
+            }
+            DebugCommand::Write { target, value } => {
+                if let Some(target) = self.resolve_target(&target)? {
+                    self.write(target, value)?
+                }
+            }
+            DebugCommand::Patch { target, bytes } => {
+                if let Some(target) = self.resolve_target(&target)? {
+                    self.patch(target, &bytes)?
+                }
+            }
+            DebugCommand::Unpatch { target } => {
+                if let Some(addr) = self.resolve_address(&target)? {
+                    self.unpatch(addr)?
                 }
-                Ok(DebugCommand::FastForward) => self.fast_forward()?,
-                Ok(DebugCommand::Current) => self.print_current()?,
-                Err(clap::Error {
-                    kind: clap::ErrorKind::HelpDisplayed,
-                    message,
-                    ..
-                }) => {
-                    writeln!(self.out, "{}", message)?;
+            }
+            DebugCommand::GameGenie { code } => self.game_genie(&code)?,
+            DebugCommand::Breakpoint { target, value } => {
+                if let Some(target) = self.resolve_target(&target)? {
+                    self.add_breakpoint(target, value)?
                 }
-                Err(clap::Error {
-                    kind: clap::ErrorKind::VersionDisplayed,
-                    message,
-                    ..
-                }) => {
-                    writeln!(self.out, "{}", message)?;
+            }
+            DebugCommand::BreakOnInterrupt { interrupt } => {
+                self.breakpoints.push(Breakpoint::interrupt(interrupt));
+                self.report_breakpoint_added(format_args!("{} interrupt", interrupt))?;
+            }
+            DebugCommand::Protect { range, protection } => self.protect(range, protection)?,
+            DebugCommand::Unprotect { range } => self.unprotect(range)?,
+            DebugCommand::StackGuard { range, warn, off } => self.guard_stack(range, warn, off)?,
+            DebugCommand::BankHistory { count } => self.print_bank_history(count)?,
+            DebugCommand::Interrupts { reset } => self.print_interrupts(reset)?,
+            DebugCommand::Serial { clear } => self.print_serial_output(clear)?,
+            DebugCommand::FastForward => {
+                self.fast_forward(0)?;
+            }
+            DebugCommand::Until { target } => {
+                if let Some(target) = self.resolve_target(&target)? {
+                    self.run_until(target)?
                 }
-                Err(
-                    ref
-                    e
-                    @
-                    clap::Error {
-                        kind: clap::ErrorKind::UnknownArgument,
-                        ..
-                    },
-                ) => {
-                    let command = e
-                        .info
-                        .as_ref()
-                        .and_then(|args| args.get(0).cloned())
-                        .unwrap_or_else(|| String::from(""));
-                    writeln!(
-                        self.err,
-                        "Unknown command: {:?}. List commands with \"help\"",
-                        command
-                    )?;
+            }
+            DebugCommand::Finish => self.finish()?,
+            DebugCommand::BreakBefore {
+                steps,
+                target,
+                value,
+            } => {
+                if let Some(target) = self.resolve_target(&target)? {
+                    self.break_before(steps, target, value)?
                 }
-                Err(clap::Error { message, .. }) => {
-                    writeln!(self.err, "{}", message)?;
+            }
+            DebugCommand::Frame { frames } => self.frame_advance(frames)?,
+            DebugCommand::Current => self.print_current()?,
+            DebugCommand::Disassemble { start: None, count } => {
+                self.print_disassembly(None, count)?
+            }
+            DebugCommand::Disassemble {
+                start: Some(start),
+                count,
+            } => {
+                if let Some(start) = self.resolve_address(&start)? {
+                    self.print_disassembly(Some(start), count)?
                 }
             }
-            self.out.flush()?;
-            self.err.flush()?;
+            DebugCommand::LoadSymbols { file } => self.load_symbols(&file)?,
+            DebugCommand::Eval { expression } => self.eval(&expression.join(" "))?,
+            DebugCommand::Run | DebugCommand::Pause | DebugCommand::Wait => {
+                self.error("This command is only available with --background")?;
+            }
         }
-        Ok(())
+        Ok(true)
     }
 }
 
-#[derive(StructOpt)]
-#[structopt(no_version,
-    global_settings=&[
-        clap::AppSettings::DisableVersion,
-        clap::AppSettings::DisableHelpFlags,
-        clap::AppSettings::NoBinaryName
-    ],
-    settings = &[
-        clap::AppSettings::SubcommandRequiredElseHelp
-    ],
-    usage="<SUBCOMMAND> [OPTIONS]"
-)]
-enum DebugCommand {
-    /// Print out the given bytes that are mapped in the CPU's memory map. (alias: pb)
-    ///
+/// A line of input read from the debug prompt
+pub(crate) enum Input {
+    Command(DebugCommand),
+    /// Help was printed, or the command could not be parsed
+    Invalid,
+    /// No more input is available
+    Closed,
+}
+
+/// Prompt for and parse the next debugger command
+///
+/// Help and parse errors are printed here, rather than returned.
+pub(crate) fn read_command(
+    inb: &mut dyn io::BufRead,
+    out: &mut dyn io::Write,
+    err: &mut dyn io::Write,
+) -> io::Result<Input> {
+    write!(err, "{}", PROMPT)?;
+    err.flush()?;
+    let mut input = String::new();
+    if inb.read_line(&mut input)? == 0 {
+        return Ok(Input::Closed);
+    }
+
+    parse_command(&input, out, err)
+}
+
+/// Parse a line of debugger input
+///
+/// Help and parse errors are printed here, rather than returned.
+fn parse_command(
+    input: &str,
+    out: &mut dyn io::Write,
+    err: &mut dyn io::Write,
+) -> io::Result<Input> {
+    let trimmed_input = input.trim();
+
+    let parsed_command = if trimmed_input.is_empty() {
+        let empty_iter: std::slice::Iter<&str> = [].iter();
+        DebugCommand::from_iter_safe(empty_iter)
+    } else {
+        DebugCommand::from_iter_safe(trimmed_input.split(' '))
+    };
+
+    match parsed_command {
+        Ok(command) => return Ok(Input::Command(command)),
+        Err(clap::Error {
+            kind: clap::ErrorKind::HelpDisplayed,
+            message,
+            ..
+        }) => {
+            writeln!(out, "{}", message)?;
+        }
+        Err(clap::Error {
+            kind: clap::ErrorKind::VersionDisplayed,
+            message,
+            ..
+        }) => {
+            writeln!(out, "{}", message)?;
+        }
+        Err(
+            ref
+            e
+            @
+            clap::Error {
+                kind: clap::ErrorKind::UnknownArgument,
+                ..
+            },
+        ) => {
+            let command = e
+                .info
+                .as_ref()
+                .and_then(|args| args.first().cloned())
+                .unwrap_or_else(|| String::from(""));
+            writeln!(
+                err,
+                "Unknown command: {:?}. List commands with \"help\"",
+                command
+            )?;
+        }
+        Err(clap::Error { message, .. }) => {
+            writeln!(err, "{}", message)?;
+        }
+    }
+    out.flush()?;
+    err.flush()?;
+    Ok(Input::Invalid)
+}
+
+#[derive(StructOpt)]
+#[structopt(no_version,
+    global_settings=&[
+        clap::AppSettings::DisableVersion,
+        clap::AppSettings::DisableHelpFlags,
+        clap::AppSettings::NoBinaryName
+    ],
+    settings = &[
+        clap::AppSettings::SubcommandRequiredElseHelp
+    ],
+    usage="<SUBCOMMAND> [OPTIONS]"
+)]
+pub(crate) enum DebugCommand {
+    /// Print out the given bytes that are mapped in the CPU's memory map. (alias: pb)
+    ///
     /// You may provide a range using the syntax such as START-END, such as 2:5
     /// to print bytes 2 to 5 inclusive. You may omit either of START, END or both. For example,
     /// 200: prints all bytes from location 200, while : dumps the entire memory space.
@@ -386,23 +1354,187 @@ enum DebugCommand {
         #[structopt(parse(try_from_str = parse_range))]
         range: ByteRange,
     },
+    /// Write the given bytes in the CPU's memory map to a file
+    ///
+    /// Ranges use the same syntax as print-bytes. Fails if any byte in the
+    /// range cannot be read.
+    #[structopt(no_version)]
+    Dump {
+        #[structopt(parse(try_from_str = parse_range))]
+        range: ByteRange,
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Write the contents of a file into the CPU's memory map
+    ///
+    /// Writing to ROM addresses writes to the cartridge's bank registers,
+    /// rather than changing the ROM.
+    #[structopt(no_version)]
+    Load {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// The address to write the first byte of the file to
+        #[structopt(parse(try_from_str = parse_number))]
+        addr: u16,
+    },
     /// Print cycles since emulator startup (alias: cc)
     #[structopt(no_version, alias = "cc")]
     CycleCount,
+    /// Print how long each PPU mode took on the last line drawn (alias: pt)
+    ///
+    /// Drawing takes longer when the background is scrolled part way through
+    /// a tile or the window starts on the line, leaving less time in HBlank.
+    #[structopt(no_version, alias = "pt")]
+    PpuTiming,
+    /// Print the most recent ROM and RAM bank switches, oldest first (alias: bh)
+    ///
+    /// The last 100 switches are kept. Each is shown with the CPU clock
+    /// it happened at, which can be compared with cycle-count.
+    #[structopt(no_version, alias = "bh")]
+    BankHistory {
+        /// Only print this many of the most recent switches
+        count: Option<usize>,
+    },
+    /// Print how often each interrupt has been dispatched, and how long they took (alias: irq)
+    ///
+    /// Latency is the time in clocks from an interrupt being requested to its handler
+    /// being called, which grows while interrupts are disabled or another is being handled.
+    #[structopt(no_version, alias = "irq")]
+    Interrupts {
+        /// Forget the dispatches recorded so far, after printing them
+        #[structopt(long)]
+        reset: bool,
+    },
+    /// Print the text the ROM has sent over the serial port, such as test ROM results
+    ///
+    /// Output is only captured while no link cable is connected. Bytes that
+    /// aren't valid UTF-8 are printed as \u{FFFD}.
+    #[structopt(no_version)]
+    Serial {
+        /// Forget the output captured so far, after printing it
+        #[structopt(long)]
+        clear: bool,
+    },
     /// Prints out all registers (alias: pr)
     #[structopt(no_version, alias = "pr")]
     PrintRegisters,
     /// Run emulation as quickly as possible until a breakpoint is triggered (alias: ff)
+    ///
+    /// With --background, this returns straight away and emulation continues
+    /// while you enter commands.
     #[structopt(no_version, alias = "ff")]
     FastForward,
+    /// Run until the instruction at the given location is executed (alias: u)
+    ///
+    /// Stops early if a breakpoint is triggered.
+    #[structopt(no_version, alias = "u")]
+    Until {
+        /// A memory location such as 0x150, 3:4123 (bank 3), PC+2 or a symbol name
+        target: String,
+    },
+    /// Run until the current function returns to its caller (alias: fin)
+    ///
+    /// Calls made by the current function, and interrupt handlers, run to
+    /// completion. Stops early if a breakpoint is triggered.
+    #[structopt(no_version, alias = "fin")]
+    Finish,
+    /// Run until a condition is met, then step back to an earlier instruction (alias: bb)
+    ///
+    /// Targets and values are the same as for breakpoint. Once the condition is
+    /// met, the given number of instructions are undone, so the code leading up
+    /// to it can be stepped through. Up to 1000 instructions can be undone, and
+    /// the PPU and cartridge bank state are not rewound.
+    #[structopt(no_version, alias = "bb")]
+    BreakBefore {
+        /// How many instructions before the condition to stop at
+        steps: u16,
+        /// A register such as PC or B, or a memory location such as 0x8000, 3:4123 (bank 3),
+        /// HL+2, a symbol name such as LoadLevel+2, or [HL+1] for wherever HL+1 points
+        target: String,
+        /// Break when the target has this value, or `changed` to break when it changes
+        #[structopt(parse(try_from_str = parse_breakpoint_value))]
+        value: Option<BreakpointValue>,
+    },
+    /// Run emulation at normal speed in the background. Requires --background
+    ///
+    /// Emulation continues while you enter commands, until a breakpoint is
+    /// triggered or emulation is paused.
+    #[structopt(no_version)]
+    Run,
+    /// Pause emulation running in the background. Requires --background
+    #[structopt(no_version)]
+    Pause,
+    /// Wait until emulation running in the background stops. Requires --background
+    #[structopt(no_version)]
+    Wait,
+    /// Run until the given number of frames have been drawn (alias: f)
+    ///
+    /// A frame ends when the PPU enters VBlank. Stops early if a breakpoint
+    /// is triggered.
+    #[structopt(no_version, alias = "f")]
+    Frame {
+        #[structopt(default_value = "1")]
+        frames: u32,
+    },
     /// Adds a breakpoint at the given location (alias: br)
+    ///
+    /// If no value is given for a memory location, breaks when the instruction
+    /// at that location is executed. Use `changed` as the value to break
+    /// whenever the target's value changes.
     #[structopt(no_version, alias = "br")]
     Breakpoint {
-        /// Can be a register such as PC or B, or a memory location such as 0x8000
-        target: RWTarget,
-        /// Break when the target has this value. For 8-bit registers and memory locations, must be in the range 0-FF
-        #[structopt(parse(try_from_str = parse_number))]
-        value: u16,
+        /// A register such as PC or B, or a memory location such as 0x8000, 3:4123 (bank 3),
+        /// HL+2, a symbol name such as LoadLevel+2, or [HL+1] for wherever HL+1 points
+        target: String,
+        /// Break when the target has this value, or `changed` to break when it changes. For 8-bit registers and memory locations, must be in the range 0-FF
+        #[structopt(parse(try_from_str = parse_breakpoint_value))]
+        value: Option<BreakpointValue>,
+    },
+    /// Adds a breakpoint for when an interrupt is dispatched (alias: bi)
+    ///
+    /// Emulation stops after jumping to the interrupt handler, before the
+    /// first instruction of the handler runs.
+    #[structopt(no_version, alias = "bi")]
+    BreakOnInterrupt {
+        /// One of vblank, stat, timer, serial or joypad
+        interrupt: gameboy::Interrupt,
+    },
+    /// Break when the ROM accesses memory in a way a region doesn't allow
+    ///
+    /// A read-only region breaks on writes. An execute-only region also
+    /// breaks on reads, other than running the instructions in it, so
+    /// self-modifying code and data in code can be found. Ranges use the
+    /// same syntax as print-bytes.
+    #[structopt(no_version)]
+    Protect {
+        #[structopt(parse(try_from_str = parse_range))]
+        range: ByteRange,
+        /// Either read-only (ro) or execute-only (xo)
+        protection: Protection,
+    },
+    /// Remove protection from every region overlapping the given range
+    #[structopt(no_version)]
+    Unprotect {
+        #[structopt(parse(try_from_str = parse_range))]
+        range: ByteRange,
+    },
+    /// Break when the stack overflows or underflows
+    ///
+    /// Breaks when SP moves outside the given range, or when a value is popped
+    /// from above the base of the stack. The base starts as the end of the
+    /// range, and moves to SP when SP is set other than by a push or pop, such
+    /// as by LD SP, nn. Ranges use the same syntax as print-bytes.
+    #[structopt(no_version, name = "stackguard")]
+    StackGuard {
+        /// The SP values the stack may use, such as 0xC000:0xDFFF
+        #[structopt(parse(try_from_str = parse_range), required_unless = "off")]
+        range: Option<ByteRange>,
+        /// Print a warning and keep running, rather than breaking
+        #[structopt(long)]
+        warn: bool,
+        /// Remove the stack guard
+        #[structopt(long, conflicts_with_all = &["range", "warn"])]
+        off: bool,
     },
     /// Steps the CPU by a specified number of cycles (alias: s)
     #[structopt(no_version, alias = "s")]
@@ -410,53 +1542,196 @@ enum DebugCommand {
         #[structopt(default_value = "1")]
         steps: u16,
     },
+    /// Undoes the given number of steps (alias: rs)
+    ///
+    /// Only the most recent 1000 steps can be undone. The PPU and cartridge
+    /// bank state are not rewound.
+    #[structopt(no_version, alias = "rs")]
+    ReverseStep {
+        #[structopt(default_value = "1")]
+        steps: u16,
+    },
     /// Reads the given register or memory location (alias: r)
     #[structopt(no_version, alias = "r")]
     Read {
-        /// Can be a register such as PC or B, or a memory location such as 0x8000
-        target: RWTarget,
+        /// A register such as PC or B, or a memory location such as 0x8000, 3:4123 (bank 3),
+        /// HL+2 or a symbol name such as LoadLevel+2
+        target: String,
     },
     /// Writes the given register or memory location (alias: w)
     #[structopt(no_version, alias = "w")]
     Write {
-        /// Can be a register such as PC or B, or a memory location such as 0x8000
-        target: RWTarget,
+        /// A register such as PC or B, or a memory location such as 0x8000, 3:4123 (bank 3),
+        /// HL+2 or a symbol name such as LoadLevel+2
+        target: String,
         /// The value to write. For 8-bit registers and memory locations, must be in the range 0-FF
         #[structopt(parse(try_from_str = parse_number))]
         value: u16,
     },
+    /// Change the values read from ROM, without changing the ROM itself
+    ///
+    /// Patches the bank mapped at the location now, unless a bank is given such
+    /// as 3:4123. Bytes must be in the range 0-FF.
+    #[structopt(no_version)]
+    Patch {
+        /// A memory location in ROM such as 0x150, 3:4123 (bank 3) or a symbol name
+        target: String,
+        /// The bytes to read instead, starting at the location
+        #[structopt(parse(try_from_str = parse_number), required = true)]
+        bytes: Vec<u16>,
+    },
+    /// Remove every patch from the given ROM location
+    #[structopt(no_version)]
+    Unpatch {
+        /// A memory location in ROM such as 0x150 or a symbol name
+        target: String,
+    },
+    /// Apply a Game Genie code, such as 00A-17B-C49
+    #[structopt(no_version)]
+    GameGenie { code: String },
+    /// Evaluate an expression and print the result (alias: e)
+    ///
+    /// Expressions can use registers, numbers, symbols, memory locations such as [HL+2],
+    /// parentheses and the operators + - * / & |. For example: eval [HL+2] + BC*2
+    #[structopt(no_version, alias = "e")]
+    Eval {
+        #[structopt(required = true, allow_hyphen_values = true)]
+        expression: Vec<String>,
+    },
     /// Print current instruction disassembly (alias: ci)
     #[structopt(no_version, alias = "ci")]
     Current,
+    /// Print the disassembly of several instructions (alias: disasm)
+    ///
+    /// Starts at the current instruction if no address is given. The
+    /// current instruction is marked with =>.
+    #[structopt(no_version, alias = "disasm")]
+    Disassemble {
+        /// The address of the first instruction, such as 0x150, PC+2 or a symbol name
+        start: Option<String>,
+        /// Number of instructions to print
+        #[structopt(short = "n", long, default_value = "10")]
+        count: u16,
+    },
+    /// Load names for addresses from a symbol file, such as one written by rgblink (alias: sym)
+    ///
+    /// Each line has a bank and address in hex followed by a name, such as
+    /// `03:4123 LoadLevel`. Symbols can then be used wherever a memory location
+    /// or expression is accepted. Loading a file replaces any symbols loaded before.
+    #[structopt(no_version, alias = "sym")]
+    LoadSymbols {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
     /// Exit out of this debugging session.
     #[structopt(no_version)]
     Exit,
 }
 
+impl DebugCommand {
+    /// The full name of the command, as used in JSON output
+    fn name(&self) -> &'static str {
+        match self {
+            DebugCommand::PrintBytes { .. } => "print-bytes",
+            DebugCommand::Dump { .. } => "dump",
+            DebugCommand::Load { .. } => "load",
+            DebugCommand::CycleCount => "cycle-count",
+            DebugCommand::PpuTiming => "ppu-timing",
+            DebugCommand::BankHistory { .. } => "bank-history",
+            DebugCommand::Interrupts { .. } => "interrupts",
+            DebugCommand::Serial { .. } => "serial",
+            DebugCommand::PrintRegisters => "print-registers",
+            DebugCommand::FastForward => "fast-forward",
+            DebugCommand::Until { .. } => "until",
+            DebugCommand::Finish => "finish",
+            DebugCommand::BreakBefore { .. } => "break-before",
+            DebugCommand::Run => "run",
+            DebugCommand::Pause => "pause",
+            DebugCommand::Wait => "wait",
+            DebugCommand::Frame { .. } => "frame",
+            DebugCommand::Breakpoint { .. } => "breakpoint",
+            DebugCommand::BreakOnInterrupt { .. } => "break-on-interrupt",
+            DebugCommand::Protect { .. } => "protect",
+            DebugCommand::Unprotect { .. } => "unprotect",
+            DebugCommand::StackGuard { .. } => "stackguard",
+            DebugCommand::Step { .. } => "step",
+            DebugCommand::ReverseStep { .. } => "reverse-step",
+            DebugCommand::Read { .. } => "read",
+            DebugCommand::Write { .. } => "write",
+            DebugCommand::Patch { .. } => "patch",
+            DebugCommand::Unpatch { .. } => "unpatch",
+            DebugCommand::GameGenie { .. } => "game-genie",
+            DebugCommand::Eval { .. } => "eval",
+            DebugCommand::Current => "current",
+            DebugCommand::Disassemble { .. } => "disassemble",
+            DebugCommand::LoadSymbols { .. } => "load-symbols",
+            DebugCommand::Exit => "exit",
+        }
+    }
+}
+
 pub(crate) fn debug(
     gb: gameboy::GameBoy,
+    format: OutputFormat,
     in_: &mut dyn io::Read,
     out: &mut dyn io::Write,
     err: &mut dyn io::Write,
 ) -> io::Result<()> {
     let mut inb = io::BufReader::new(in_);
     let mut debugger = CliDebugger::new(gb, &mut inb, out, err);
+    debugger.format = format;
     debugger.debug()?;
     Ok(())
 }
 
+#[derive(Debug, Display, From, Error)]
+pub enum ExecError {
+    #[display(fmt = "IO error: {}", "_0")]
+    Io(io::Error),
+    #[display(fmt = "Invalid command: {:?}", "_0")]
+    #[from(ignore)]
+    InvalidCommand(#[error(ignore)] String),
+}
+
+/// Run debugger commands one after another, without prompting for input
+///
+/// Output is the same as entering each command at the prompt. Stops at
+/// `exit`, or at the first command which can't be parsed.
+pub(crate) fn exec(
+    gb: gameboy::GameBoy,
+    format: OutputFormat,
+    commands: &[String],
+    out: &mut dyn io::Write,
+    err: &mut dyn io::Write,
+) -> Result<(), ExecError> {
+    let mut inb = io::empty();
+    let mut debugger = CliDebugger::new(gb, &mut inb, out, err);
+    debugger.format = format;
+    for input in commands {
+        let command = match parse_command(input, debugger.out, debugger.err)? {
+            Input::Command(command) => command,
+            _ => return Err(ExecError::InvalidCommand(input.clone())),
+        };
+        if !debugger.run_command(command)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use olympia_engine::console::SerialConsole;
     use olympia_engine::registers::WordRegister;
     use olympia_engine::rom;
 
     fn get_test_gbcpu() -> gameboy::GameBoy {
-        let cartridge = rom::Cartridge {
-            data: vec![0xF1u8; 0x8000],
-            controller: rom::MBC2::new(5).into(),
-            target: rom::TargetConsole::GameBoyOnly,
-        };
+        let cartridge = rom::Cartridge::new(
+            vec![0xF1u8; 0x8000],
+            rom::MBC2::new(5).into(),
+            rom::TargetConsole::GameBoyOnly,
+        );
         gameboy::GameBoy::new(cartridge, gameboy::GameBoyModel::GameBoy)
     }
 
@@ -472,6 +1747,7 @@ mod test {
 
         debug(
             gb,
+            OutputFormat::Text,
             &mut io::BufReader::new(input.as_bytes()),
             &mut captured_output,
             &mut captured_error,
@@ -490,6 +1766,7 @@ mod test {
 
         debug(
             gb,
+            OutputFormat::Text,
             &mut io::BufReader::new(input.as_bytes()),
             &mut captured_output,
             &mut captured_error,
@@ -730,6 +2007,35 @@ mod test {
         assert_debug_output(gb, "ci\n", "--\n");
     }
 
+    #[test]
+    fn test_disassemble() {
+        let mut gb = get_test_gbcpu();
+
+        gb.write_register_u16(wr::PC, 0xC001);
+        gb.set_memory_u8(0xC000, 0x00).unwrap(); // NOP
+        gb.set_memory_u8(0xC001, 0x26).unwrap(); // LD H, 20h
+        gb.set_memory_u8(0xC002, 0x20).unwrap();
+        gb.set_memory_u8(0xC003, 0xD3).unwrap(); // Invalid
+
+        let expected_output = [
+            "   C000:  00        NOP",
+            "=> C001:  26 20     LD H, 20h",
+            "   C003:  D3        DAT D3h\n",
+        ]
+        .join("\n");
+        assert_debug_output(gb, "disasm 0xC000 -n 3\n", &expected_output);
+    }
+
+    #[test]
+    fn step_invalid_opcode() {
+        let mut gb = get_test_gbcpu();
+
+        gb.set_memory_u8(0xC000, 0xFD).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        assert_debug_error_contains(gb, "s\n", "invalid opcode FD at 0xC000\n");
+    }
+
     #[test]
     fn write_reg16() {
         let mut gb = get_test_gbcpu();
@@ -788,6 +2094,21 @@ mod test {
         assert_eq!(result.gb.get_memory_u8(0x8000).unwrap(), 0x52);
     }
 
+    #[test]
+    fn rw_mem_while_drawing() {
+        let mut gb = get_test_gbcpu();
+        // The PPU locks VRAM from the CPU while drawing, but not from the debugger
+        while gb.get_memory_u8(0xFF41).unwrap() & 0b11 != 0b11 {
+            gb.step().unwrap();
+        }
+
+        let result = run_debug_script(gb, &["w 0x8000 12", "r 0x8000"]).unwrap();
+
+        assert_eq!(result.output, vec!["Wrote C (was 0)", "C"]);
+        assert_eq!(result.gb.get_memory_u8(0xFF41).unwrap() & 0b11, 0b11);
+        assert_eq!(result.gb.get_memory_u8(0x8000).unwrap(), 12);
+    }
+
     #[test]
     fn breakpoint_fast_forward() {
         let mut gb = get_test_gbcpu();
@@ -810,4 +2131,640 @@ mod test {
         );
         assert_eq!(result.gb.read_register_u16(wr::SP), 0x8024);
     }
+
+    #[test]
+    fn breakpoint_changed() {
+        let mut gb = get_test_gbcpu();
+
+        gb.set_memory_u8(0xC000, 0x00).unwrap(); // NOP
+        gb.set_memory_u8(0xC001, 0x00).unwrap(); // NOP
+        gb.set_memory_u8(0xC002, 0x33).unwrap(); // INC SP
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.write_register_u16(wr::SP, 0x8000);
+
+        let result = run_debug_script(gb, &["br SP changed", "ff"]).unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "Added breakpoint for register SP Changed",
+                "Broke on Breakpoint: register SP Changed"
+            ]
+        );
+        assert_eq!(result.gb.read_register_u16(wr::SP), 0x8001);
+        assert_eq!(result.gb.read_register_u16(wr::PC), 0xC003);
+    }
+
+    #[test]
+    fn breakpoint_indirect() {
+        let mut gb = get_test_gbcpu();
+        let code = [0x23, 0x23, 0x23]; // INC HL
+        gb.set_memory_chunk(0xC000, &code).unwrap();
+        gb.set_memory_u8(0xC012, 0x42).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.write_register_u16(wr::HL, 0xC010);
+
+        let result = run_debug_script(gb, &["br [HL] 0x42", "ff"]).unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "Added breakpoint for memory location [HL] == 42",
+                "Broke on Breakpoint: memory location [HL] == 42"
+            ]
+        );
+        assert_eq!(result.gb.read_register_u16(wr::PC), 0xC002);
+    }
+
+    #[test]
+    fn breakpoint_banked_execute() {
+        let mut data = vec![0u8; 0x10000];
+        data[0x147] = 1;
+        data[0x0200] = 0x3E; // LD A, 3
+        data[0x0201] = 0x03;
+        data[0x0202] = 0xEA; // LD (2000h), A
+        data[0x0203] = 0x00;
+        data[0x0204] = 0x20;
+        data[0x0205] = 0xC3; // JP 4123h
+        data[0x0206] = 0x23;
+        data[0x0207] = 0x41;
+        let cartridge = rom::Cartridge::from_data(data).unwrap();
+        let mut gb = gameboy::GameBoy::new(cartridge, gameboy::GameBoyModel::GameBoy);
+        gb.write_register_u16(wr::PC, 0x0200);
+
+        let result = run_debug_script(gb, &["br 3:4123", "ff"]).unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "Added breakpoint for memory location 3:4123 Execute",
+                "Broke on Breakpoint: memory location 3:4123 Execute"
+            ]
+        );
+        assert_eq!(result.gb.read_register_u16(wr::PC), 0x4123);
+    }
+
+    #[test]
+    fn dump_and_load() {
+        let mut gb = get_test_gbcpu();
+        let data: Vec<u8> = (0..0x500).map(|x| x as u8).collect();
+        gb.set_memory_chunk(0xC000, &data).unwrap();
+        let mut path = std::env::temp_dir();
+        path.push(format!("olympia_dump_test_{}.bin", std::process::id()));
+        let path_str = path.to_str().unwrap();
+
+        let result = run_debug_script(
+            gb,
+            &[
+                &format!("dump 0xC000:0xC4FF {}", path_str),
+                &format!("load {} 0xD000", path_str),
+            ],
+        )
+        .unwrap();
+        let dumped = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                format!("Dumped 1280 bytes from C000 to {}", path_str),
+                format!("Loaded 1280 bytes from {} to D000", path_str)
+            ]
+        );
+        assert_eq!(dumped, data);
+        let mut loaded = vec![0u8; 0x500];
+        result.gb.get_memory_chunk(0xD000, &mut loaded).unwrap();
+        assert_eq!(loaded, data);
+    }
+
+    #[test]
+    fn load_too_large() {
+        let gb = get_test_gbcpu();
+        let mut path = std::env::temp_dir();
+        path.push(format!("olympia_load_test_{}.bin", std::process::id()));
+        fs::write(&path, [0u8; 0x20]).unwrap();
+
+        let result = run_debug_script(gb, &[&format!("load {} 0xFFF0", path.display())]).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.errors[0].contains("is 32 bytes, but only 16 bytes fit from FFF0"));
+    }
+
+    #[test]
+    fn eval_expression() {
+        let mut gb = get_test_gbcpu();
+        gb.write_register_u16(wr::HL, 0xC000);
+        gb.write_register_u16(wr::BC, 0x0010);
+        gb.set_memory_u8(0xC002, 0x25).unwrap();
+
+        let result = run_debug_script(gb, &["eval [HL+2] + BC*2", "e 2 - BC", "e BC / 0"]).unwrap();
+
+        assert_eq!(result.output, vec!["0x45 (69)", "-0xE (-14)"]);
+        assert!(result.errors.iter().any(|e| e.contains("Division by zero")));
+    }
+
+    #[test]
+    fn symbols_and_arithmetic() {
+        let mut gb = get_test_gbcpu();
+        gb.write_register_u16(wr::HL, 0xC000);
+        gb.set_memory_u8(0xC002, 0x25).unwrap();
+        let mut path = std::env::temp_dir();
+        path.push(format!("olympia_symbols_test_{}.sym", std::process::id()));
+        fs::write(&path, "; rgblink\n00:C002 wCounter\n").unwrap();
+
+        let result = run_debug_script(
+            gb,
+            &[
+                "r HL+2",
+                "e wCounter",
+                &format!("sym {}", path.display()),
+                "r wCounter",
+                "w wCounter+1 7",
+                "r 0:C003",
+                "r Missing",
+            ],
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "25".to_string(),
+                format!("Loaded 1 symbols from {}", path.display()),
+                "25".to_string(),
+                "Wrote 7 (was 0)".to_string(),
+                "7".to_string(),
+            ]
+        );
+        assert!(result.errors[0].contains("No symbol named wCounter"));
+        assert!(result.errors[1].contains("Missing is not a valid register or memory location"));
+    }
+
+    #[test]
+    fn frame_advance() {
+        let mut gb = get_test_gbcpu();
+        gb.set_memory_u8(0xC000, 0x18).unwrap(); // JR -2
+        gb.set_memory_u8(0xC001, 0xFE).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        let result = run_debug_script(gb, &["frame 2"]).unwrap();
+
+        assert_eq!(result.output.len(), 1);
+        assert!(result.output[0].starts_with("Ran 2 frames. Cycles: "));
+        assert!(result.gb.clocks_elapsed() > FRAME_CLOCKS);
+        assert!(result.gb.clocks_elapsed() <= FRAME_CLOCKS * 2);
+    }
+
+    #[test]
+    fn ppu_timing() {
+        let mut gb = get_test_gbcpu();
+        gb.set_memory_u8(0xFF43, 0x04).unwrap(); // SCX
+        gb.set_memory_u8(0xC000, 0x18).unwrap(); // JR -2
+        gb.set_memory_u8(0xC001, 0xFE).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        let result = run_debug_script(gb, &["frame 1", "pt"]).unwrap();
+
+        assert_eq!(
+            result.output[1],
+            "Line 143: OAM scan 80 / Drawing 164 / HBlank 212 clocks"
+        );
+    }
+
+    #[test]
+    fn frame_advance_lcd_off() {
+        let mut gb = get_test_gbcpu();
+        gb.set_memory_u8(0xFF40, 0x00).unwrap();
+        gb.set_memory_u8(0xC000, 0x18).unwrap(); // JR -2
+        gb.set_memory_u8(0xC001, 0xFE).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        let result = run_debug_script(gb, &["f"]).unwrap();
+
+        assert!(result.errors[0].contains("No frames are being drawn"));
+        assert!(result.output[0].starts_with("Ran 0 frames."));
+    }
+
+    #[test]
+    fn reverse_step() {
+        let mut gb = get_test_gbcpu();
+
+        gb.set_memory_u8(0xC000, 0x33).unwrap(); // INC SP
+        gb.set_memory_u8(0xC001, 0x33).unwrap(); // INC SP
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.write_register_u16(wr::SP, 0x8000);
+
+        let result = run_debug_script(gb, &["s 2", "rs", "r SP", "rs 2"]).unwrap();
+
+        assert_eq!(result.output, vec!["8001"]);
+        assert!(result
+            .errors
+            .iter()
+            .any(|e| e.contains("No earlier state to step back to")));
+        assert_eq!(result.gb.read_register_u16(wr::PC), 0xC000);
+        assert_eq!(result.gb.read_register_u16(wr::SP), 0x8000);
+    }
+
+    #[test]
+    fn reverse_step_only_after_stepping() {
+        let mut gb = get_test_gbcpu();
+
+        gb.set_memory_u8(0xC000, 0x33).unwrap(); // INC SP
+        gb.set_memory_u8(0xC001, 0x33).unwrap(); // INC SP
+        gb.set_memory_u8(0xC002, 0x33).unwrap(); // INC SP
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.write_register_u16(wr::SP, 0x8000);
+
+        let result = run_debug_script(gb, &["s", "u 0xC002", "rs", "s", "rs"]).unwrap();
+
+        let no_history = result
+            .errors
+            .iter()
+            .filter(|e| e.contains("No earlier state to step back to"))
+            .count();
+        assert_eq!(no_history, 1);
+        assert_eq!(result.gb.read_register_u16(wr::PC), 0xC002);
+        assert_eq!(result.gb.read_register_u16(wr::SP), 0x8002);
+    }
+
+    #[test]
+    fn break_on_interrupt() {
+        let mut gb = get_test_gbcpu();
+
+        gb.set_memory_u8(0xC000, 0xFB).unwrap(); // EI
+        gb.set_memory_u8(0xC001, 0x18).unwrap(); // JR -2
+        gb.set_memory_u8(0xC002, 0xFE).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.set_memory_u8(0xFFFF, 0x04).unwrap(); // Enable the timer interrupt
+        gb.set_memory_u8(0xFF07, 0x05).unwrap(); // Start the timer
+
+        let result = run_debug_script(gb, &["bi timer", "ff", "bi nmi"]).unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "Added breakpoint for Timer interrupt",
+                "Broke on Breakpoint: Timer interrupt"
+            ]
+        );
+        assert!(result
+            .errors
+            .iter()
+            .any(|e| e.contains("nmi is not an interrupt")));
+        assert_eq!(result.gb.read_register_u16(wr::PC), 0x50);
+    }
+
+    #[test]
+    fn until_and_finish() {
+        let mut gb = get_test_gbcpu();
+
+        let code: &[(u16, &[u8])] = &[
+            (0xC000, &[0xCD, 0x10, 0xC0]), // CALL C010h
+            (0xC003, &[0x18, 0xFE]),       // JR -2
+            (0xC010, &[0xCD, 0x20, 0xC0]), // CALL C020h
+            (0xC013, &[0xC9]),             // RET
+            (0xC020, &[0xC9]),             // RET
+        ];
+        for (addr, bytes) in code {
+            for (i, byte) in bytes.iter().enumerate() {
+                gb.set_memory_u8(addr + i as u16, *byte).unwrap();
+            }
+        }
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        let result = run_debug_script(
+            gb,
+            &[
+                "until 0xC020",
+                "finish",
+                "r pc",
+                "finish",
+                "r pc",
+                "until a",
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "Broke on Breakpoint: memory location [C020h] Execute",
+                "Broke on Breakpoint: return to caller",
+                "C013",
+                "Broke on Breakpoint: return to caller",
+                "C003",
+            ]
+        );
+        assert!(result
+            .errors
+            .iter()
+            .any(|e| e.contains("Can only run until a memory location")));
+    }
+
+    #[test]
+    fn test_break_before() {
+        let mut gb = get_test_gbcpu();
+        let code = [
+            0x3C, // INC A
+            0x18, 0xFD, // JR -3
+        ];
+        gb.set_memory_chunk(0xC000, &code).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.write_register_u8(br::A, 0);
+
+        let result = run_debug_script(
+            gb,
+            &["bb 4 a 5", "r a", "break-before 2000 a 9", "bb 100 pc"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "Broke on Breakpoint: register A == 5",
+                "Rewound 4 instructions to C001h",
+                "3",
+            ]
+        );
+        assert!(result
+            .errors
+            .iter()
+            .any(|e| e.contains("Can only break up to 1000 instructions before")));
+        assert!(result
+            .errors
+            .iter()
+            .any(|e| e.contains("A value is required to break on")));
+    }
+
+    #[test]
+    fn test_memory_protection() {
+        let mut gb = get_test_gbcpu();
+        let code = [
+            0x3E, 0x3C, // LD A, 3Ch
+            0xEA, 0x10, 0xC0, // LD (C010h), A
+            0x18, 0xFE, // JR -2
+        ];
+        gb.set_memory_chunk(0xC000, &code).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        let result = run_debug_script(
+            gb,
+            &[
+                "protect 0xC000:0xC00F xo",
+                "protect 0xC010:0xC01F read-only",
+                "ff",
+                "r pc",
+                "unprotect 0xC00F:0xC010",
+                "protect 0xC000:0xC00F rw",
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "Protected C000-C00F as execute-only",
+                "Protected C010-C01F as read-only",
+                "Broke on Write of 3Ch to C010h by the instruction at C002h, \
+                 in read-only region C010-C01F",
+                "C005",
+                "Removed 2 protected regions",
+            ]
+        );
+        assert!(result
+            .errors
+            .iter()
+            .any(|e| e.contains("Unknown protection")));
+    }
+
+    #[test]
+    fn test_rom_patches() {
+        let gb = get_test_gbcpu();
+
+        let result = run_debug_script(
+            gb,
+            &[
+                "patch 0x150 0x18 0xFE",
+                "r 0x151",
+                "patch 0x4000 0x100",
+                "patch 0x7FFF 1 2",
+                "game-genie 3E1-D7B",
+                "r 0x41D7",
+                "unpatch 0x150",
+                "r 0x150",
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "Patched 2 bytes at 0150",
+                "FE",
+                "Patched 41D7 to 3E",
+                "3E",
+                "Removed 1 patches",
+                "F1",
+            ]
+        );
+        assert!(result
+            .errors
+            .iter()
+            .any(|e| e.contains("100 is not a byte")));
+        assert!(result
+            .errors
+            .iter()
+            .any(|e| e.contains("8000 is not in ROM")));
+        assert_eq!(result.gb.rom_patches().len(), 2);
+        assert_eq!(result.gb.get_memory_u8(0x0151), Ok(0xFE));
+    }
+
+    #[test]
+    fn test_stack_guard() {
+        let mut gb = get_test_gbcpu();
+        let code = [
+            0x31, 0x00, 0xD0, // LD SP, D000h
+            0xC5, 0xC5, 0xC5, // PUSH BC x3
+            0xC1, 0xC1, 0xC1, 0xC1, // POP BC x4
+            0x18, 0xFE, // JR -2
+        ];
+        gb.set_memory_chunk(0xC000, &code).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        let result = run_debug_script(
+            gb,
+            &[
+                "stackguard 0xCFF0:0xDFFF --warn",
+                "s 8",
+                "stackguard 0xCFFE:0xDFFF",
+                "w pc 0xC003",
+                "ff",
+                "r sp",
+                "stackguard --off",
+                "stackguard",
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "Guarding stack in CFF0-DFFF",
+                "Guarding stack in CFFE-DFFF",
+                "Wrote C003 (was C00A)",
+                "Broke on stack guard: SP moved to CFFCh, outside the stack range CFFE-DFFF, \
+                 by the instruction at C005h",
+                "CFFC",
+                "Removed stack guard",
+            ]
+        );
+        assert!(result.errors[0].ends_with(
+            "Warning: Popped past the base of the stack at D000h, SP moved to D002h, \
+             by the instruction at C009h"
+        ));
+        assert!(result.errors[1].contains("required arguments were not provided"));
+    }
+
+    #[test]
+    fn test_bank_history() {
+        let mut gb = get_test_gbcpu();
+        let code = [
+            0x3E, 0x03, // LD A, 3h
+            0xEA, 0x00, 0x21, // LD (2100h), A
+            0x3C, // INC A
+            0xEA, 0x00, 0x21, // LD (2100h), A
+        ];
+        gb.set_memory_chunk(0xC000, &code).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        let result = run_debug_script(gb, &["bh", "s 4", "bh", "cc", "bh 1"]).unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "No bank switches recorded",
+                "Clock 20: switchable ROM bank 1 -> 3",
+                "Clock 40: switchable ROM bank 3 -> 4",
+                "Cycles: 44 / M-Cycles: 11",
+                "Clock 40: switchable ROM bank 3 -> 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interrupts() {
+        let mut gb = get_test_gbcpu();
+        let code = [
+            0xF3, // DI
+            0x00, // NOP
+            0xFB, // EI
+            0x00, // NOP
+        ];
+        gb.set_memory_chunk(0xC000, &code).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.set_memory_u8(0xFFFF, gameboy::Interrupt::Timer.mask())
+            .unwrap();
+
+        let result =
+            run_debug_script(gb, &["irq", "s", "w 0xFF0F 4", "s 4", "irq --reset", "irq"]).unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "No interrupts dispatched",
+                "Wrote 4 (was E0)",
+                "Timer: 1 dispatched, worst latency 16 clocks, average 16 clocks",
+                "No interrupts dispatched",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_serial_output() {
+        let mut gb = get_test_gbcpu();
+        gb.connect_serial(SerialConsole::new());
+        let code = [
+            0x3E, b'o', // LD A, 'o'
+            0xE0, 0x01, // LDH (SB), A
+            0x3E, 0x81, // LD A, 81h
+            0xE0, 0x02, // LDH (SC), A
+            0x18, 0xFE, // JR -2
+        ];
+        gb.set_memory_chunk(0xC000, &code).unwrap();
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        let script = ["serial", "s 400", "serial --clear", "serial"];
+        let result = run_debug_script(gb, &script).unwrap();
+
+        assert_eq!(
+            result.output,
+            vec![
+                "No serial output captured",
+                "o",
+                "No serial output captured"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_json_output() {
+        let mut gb = get_test_gbcpu();
+        gb.write_register_u16(wr::HL, 0xC000);
+        gb.set_memory_u8(0xC000, 0x12).unwrap();
+        let input = "pb 0xBFFF:0xC000\nr HL\nw A 0x300\nbr 0x150\njump\npr\nexit\n";
+        let mut captured_output = Vec::new();
+        let mut captured_error = Vec::new();
+
+        debug(
+            gb,
+            OutputFormat::Json,
+            &mut io::BufReader::new(input.as_bytes()),
+            &mut captured_output,
+            &mut captured_error,
+        )
+        .unwrap();
+
+        let output = String::from_utf8_lossy(&captured_output);
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines[..4],
+            [
+                json!({
+                    "command": "print-bytes",
+                    "start": 0xBFFF,
+                    "bytes": [null, 0x12],
+                    "errors": [],
+                }),
+                json!({
+                    "command": "read",
+                    "target": "register HL",
+                    "value": 0xC000,
+                    "errors": [],
+                }),
+                json!({
+                    "command": "write",
+                    "errors": ["The value 300 is too large for the destination"],
+                }),
+                json!({
+                    "command": "breakpoint",
+                    "breakpoint": "memory location [150h] Execute",
+                    "errors": [],
+                }),
+            ]
+        );
+        assert_eq!(lines[4]["command"], Value::Null);
+        assert!(lines[4]["errors"][0]
+            .as_str()
+            .unwrap()
+            .contains("'jump' wasn't recognized"));
+        assert_eq!(lines[5]["registers"]["HL"], 0xC000);
+        assert_eq!(lines[5]["flags"]["zero"], true);
+        assert_eq!(lines[6], json!({"command": "exit", "errors": []}));
+        assert_eq!(lines.len(), 7);
+        assert!(captured_error.is_empty());
+    }
 }
@@ -1,15 +1,32 @@
 mod debugger;
-use olympia_engine::disassembler;
+mod remote_debugger;
 
 use derive_more::{Display, Error, From};
-use olympia_engine::disassembler::DisassemblyFormat;
+use olympia_engine::disassembler::{self, DisassemblyFormat, DisassemblyIterator};
 
-use std::io;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Instant;
 
+use olympia_engine::address::BankedAddress;
+use olympia_engine::console::SerialConsole;
+use olympia_engine::events;
 use olympia_engine::gameboy;
+use olympia_engine::gbs;
+use olympia_engine::instructions::ExtensionType;
+use olympia_engine::instructionsn::{self, InstructionReference};
+use olympia_engine::logging;
+use olympia_engine::monitor::{parse_number, verify_trace, AccessHeatmap, TraceLine, VerifyError};
+use olympia_engine::remote::NetLink;
 use olympia_engine::rom;
+use olympia_engine::romfile;
+use olympia_engine::scheduler::{CLOCKS_PER_FRAME, CLOCK_FREQ};
+use olympia_engine::shared::{Lock, Shared};
 use structopt::StructOpt;
 
 #[derive(Debug, Display, From, Error)]
@@ -18,6 +35,27 @@ enum OlympiaError {
     Io(std::io::Error),
     #[display(fmt = "Cartridge error: {}", "_0")]
     Cartridge(rom::CartridgeLoadError),
+    #[display(fmt = "ROM file error: {}", "_0")]
+    RomFile(romfile::RomFileError),
+    #[display(fmt = "GBS error: {}", "_0")]
+    Gbs(gbs::GbsLoadError),
+    #[display(fmt = "Invalid disassembly range: {}", "_0")]
+    Range(DisassemblyRangeError),
+    #[display(fmt = "{}", "_0")]
+    Exec(debugger::ExecError),
+    #[display(fmt = "Emulation did not match the trace log")]
+    #[from(ignore)]
+    VerifyFailed,
+}
+
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+enum DisassemblyRangeError {
+    #[display(fmt = "{} is not a ROM address", "_0")]
+    NotRomAddress(#[error(ignore)] BankedAddress),
+    #[display(fmt = "{} is past the end of the ROM", "_0")]
+    PastEndOfRom(#[error(ignore)] BankedAddress),
+    #[display(fmt = "End address is before the start address")]
+    EndBeforeStart,
 }
 
 type OlympiaResult<T> = Result<T, OlympiaError>;
@@ -29,15 +67,192 @@ enum OlympiaCommand {
         rom: PathBuf,
     },
     Debug {
+        /// Run the emulator on a background thread, so it can keep running while commands are entered
+        #[structopt(
+            long,
+            conflicts_with_all = &["link-host", "link-join", "serial-out", "json"]
+        )]
+        background: bool,
+        /// Print the result of each command as a JSON object on a single line
+        #[structopt(long)]
+        json: bool,
+        #[structopt(flatten)]
+        link: LinkArgs,
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+    /// Run debugger commands against a ROM without an interactive session
+    ///
+    /// For example: exec game.gb -c "step 100" -c "r PC" -c "pb 0xFF40:0xFF4B"
+    Exec {
+        /// A debugger command to run, as entered at the debug prompt. Can be given multiple times
+        #[structopt(short = "c", long = "command", required = true, number_of_values = 1)]
+        commands: Vec<String>,
+        /// Print the result of each command as a JSON object on a single line
+        #[structopt(long)]
+        json: bool,
+        #[structopt(flatten)]
+        link: LinkArgs,
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+    /// Run a ROM for a number of frames without displaying it
+    Run {
+        /// Number of frames to run for
+        #[structopt(short = "f", long, default_value = "600")]
+        frames: u32,
+        /// Write how often each address was read and written to this file, as JSON if it ends in .json or CSV otherwise
+        #[structopt(long, parse(from_os_str))]
+        heatmap: Option<PathBuf>,
+        /// Write the CPU state before each instruction to this file, in the format read by verify
+        #[structopt(long, parse(from_os_str))]
+        trace: Option<PathBuf>,
+        /// Print a hash of each frame when it is complete, and a hash of the whole run at the end
+        #[structopt(long)]
+        hash_every_frame: bool,
+        #[structopt(flatten)]
+        link: LinkArgs,
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+    /// Time how fast a ROM runs, and where the engine spends its time if built with profiling
+    BenchRom {
+        /// Number of frames to run for
+        #[structopt(short = "f", long, default_value = "600")]
+        frames: u32,
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+    /// Play a track from a GBS music file without sound, counting sound register writes
+    Play {
+        /// Track to play, starting from 1. Defaults to the file's first track
+        #[structopt(short = "t", long)]
+        track: Option<u8>,
+        /// Number of seconds to play for
+        #[structopt(short = "s", long, default_value = "10")]
+        seconds: u32,
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Run a ROM, checking the CPU state before each instruction against a trace log
+    ///
+    /// Log lines look like A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100, as written by
+    /// other emulators or by run --trace. Stops at the first line which doesn't match.
+    Verify {
         #[structopt(parse(from_os_str))]
         rom: PathBuf,
+        #[structopt(parse(from_os_str))]
+        log: PathBuf,
     },
     Disassemble {
         #[structopt(short = "v", long)]
         verbose: bool,
+        /// Print addresses as bank:address rather than ROM offsets
+        #[structopt(short = "b", long)]
+        banked: bool,
+        /// Print a listing with labels and cross references for called and jumped to addresses
+        #[structopt(short = "l", long, conflicts_with_all = &["verbose", "banked"])]
+        listing: bool,
+        /// Don't add labels for jumped to and called addresses
+        #[structopt(long, conflicts_with = "listing")]
+        no_labels: bool,
+        /// Only disassemble this ROM bank. Implies --banked
+        #[structopt(long, parse(try_from_str = parse_number))]
+        bank: Option<u16>,
+        /// First address to disassemble
+        #[structopt(long, parse(try_from_str = parse_number))]
+        start: Option<u16>,
+        /// Last address to disassemble (inclusive)
+        #[structopt(long, parse(try_from_str = parse_number))]
+        end: Option<u16>,
+        /// Add this to every printed address, for disassembling data not loaded at 0x0000
+        #[structopt(long, parse(try_from_str = parse_number))]
+        offset: Option<u16>,
+        /// Write the disassembly to this file instead of stdout
+        #[structopt(short = "o", long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// ROM file to disassemble, or - to read from stdin
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+    /// Print every opcode the emulator supports, with how many cycles each takes
+    Opcodes {
+        /// Print 16x16 tables of opcodes instead of a list
+        #[structopt(short = "m", long)]
+        matrix: bool,
+    },
+    /// Check reachable code for invalid opcodes, jumps outside the ROM and unmapped IO accesses
+    Lint {
+        /// ROM file to check, or - to read from stdin
         #[structopt(parse(from_os_str))]
         rom: PathBuf,
     },
+    /// Summarise a ROM's layout: entry point, interrupt handlers, used and free space per bank
+    Map {
+        /// ROM file to map, or - to read from stdin
+        #[structopt(parse(from_os_str))]
+        rom: PathBuf,
+    },
+}
+
+/// Connect the serial port to another emulator over the network, or capture what the ROM sends
+#[derive(Debug, Default, StructOpt)]
+struct LinkArgs {
+    /// Wait for another emulator to join on this address, such as 0.0.0.0:5000, and link to it
+    #[structopt(long, conflicts_with = "link-join")]
+    link_host: Option<String>,
+    /// Link to another emulator hosting on this address
+    #[structopt(long)]
+    link_join: Option<String>,
+    /// Write the bytes the ROM sends over the serial port to this file, or - for stdout
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["link-host", "link-join"])]
+    serial_out: Option<PathBuf>,
+}
+
+impl LinkArgs {
+    /// Connect the link cable if asked to, waiting for the other emulator
+    ///
+    /// Otherwise a serial console is connected, so the ROM's serial output
+    /// can be written to `serial_out` and read by the debugger.
+    fn connect(&self, gb: &mut gameboy::GameBoy, err: &mut dyn io::Write) -> OlympiaResult<()> {
+        let link = if let Some(addr) = &self.link_host {
+            writeln!(err, "Waiting for another emulator to join on {}", addr)?;
+            NetLink::host(addr.as_str())?
+        } else if let Some(addr) = &self.link_join {
+            NetLink::join(addr.as_str())?
+        } else {
+            gb.connect_serial(SerialConsole::new());
+            if let Some(path) = &self.serial_out {
+                write_serial_output(gb, path)?;
+            }
+            return Ok(());
+        };
+        writeln!(err, "Link cable connected")?;
+        gb.connect_serial(link);
+        Ok(())
+    }
+}
+
+/// Write each byte sent to the serial console to a file, or stdout if the path is `-`
+///
+/// Bytes are written as soon as they are sent, so output from a ROM that
+/// hangs or crashes isn't lost.
+fn write_serial_output(gb: &mut gameboy::GameBoy, path: &Path) -> OlympiaResult<()> {
+    let output: Box<dyn io::Write + Send> = if path == Path::new("-") {
+        Box::new(io::stdout())
+    } else {
+        Box::new(fs::File::create(path)?)
+    };
+    let output = RefCell::new(output);
+    gb.events.on(Box::new(move |evt| {
+        if let events::Event::SerialOutput(evt) = evt {
+            let mut output = output.borrow_mut();
+            if let Err(e) = output.write_all(&[evt.value]).and_then(|_| output.flush()) {
+                log::warn!("Could not write serial output: {}", e);
+            }
+        }
+    }));
+    Ok(())
 }
 
 #[derive(Debug, StructOpt)]
@@ -51,6 +266,7 @@ struct OlympiaArgs {
 }
 
 fn print_rom_info(cartridge: rom::Cartridge, out: &mut dyn io::Write) -> OlympiaResult<()> {
+    let warnings = cartridge.warnings().to_vec();
     write!(out, "Cartridge Type: ")?;
     match cartridge.controller {
         rom::ControllerEnum::StaticRom(_srom) => writeln!(out, "Static ROM")?,
@@ -74,9 +290,23 @@ fn print_rom_info(cartridge: rom::Cartridge, out: &mut dyn io::Write) -> Olympia
                 rom::CartridgeController::ram_size(&mbc3) / 1024
             )?
         }
+        rom::ControllerEnum::Type5(mbc5) => {
+            writeln!(out, "MBC5")?;
+            writeln!(
+                out,
+                "RAM Size: {}KiB",
+                rom::CartridgeController::ram_size(&mbc5) / 1024
+            )?;
+            if mbc5.has_rumble() {
+                writeln!(out, "Rumble: Yes")?
+            }
+        }
     }
 
     write!(out, "ROM Size: {}KiB", cartridge.data.len() / 1024)?;
+    for warning in warnings {
+        write!(out, "\nWarning: {}", warning)?;
+    }
     Ok(())
 }
 
@@ -107,12 +337,472 @@ fn find_err_out(args: &OlympiaArgs) -> Box<dyn io::Write> {
     }
 }
 
+/// Read a ROM file, or stdin if the path is `-`
+///
+/// ROMs in zip archives are extracted.
+fn read_input(path: &Path, in_: &mut dyn io::Read) -> OlympiaResult<Vec<u8>> {
+    if path == Path::new("-") {
+        let mut data = Vec::new();
+        in_.read_to_end(&mut data)?;
+        Ok(romfile::from_data(data)?)
+    } else {
+        Ok(romfile::read(path)?)
+    }
+}
+
 fn parse_cartridge(rom_path: &Path) -> OlympiaResult<rom::Cartridge> {
-    let data = std::fs::read(rom_path)?;
+    let data = romfile::read(rom_path)?;
     let cartridge = rom::Cartridge::from_data(data)?;
     Ok(cartridge)
 }
 
+/// The ROM offsets covered by an address range
+///
+/// Addresses are resolved in `bank` if given. Otherwise addresses are
+/// resolved as they are mapped at startup, with bank 1 at 0x4000-0x7FFF.
+/// Omitted bounds default to the whole bank, or the whole ROM.
+fn disassembly_range(
+    rom_size: usize,
+    bank: Option<u16>,
+    start: Option<u16>,
+    end: Option<u16>,
+) -> Result<RangeInclusive<usize>, DisassemblyRangeError> {
+    let (bank_start, bank_end) = match bank {
+        Some(0) => (Some(0x0000), Some(0x3FFF)),
+        Some(_) => (Some(0x4000), Some(0x7FFF)),
+        None => (None, None),
+    };
+    let resolve = |addr: u16| {
+        let default_bank = if addr >= 0x4000 { 1 } else { 0 };
+        let banked_addr = BankedAddress::new(bank.unwrap_or(default_bank), addr);
+        banked_addr
+            .to_rom_offset()
+            .map(|offset| (banked_addr, offset))
+            .ok_or(DisassemblyRangeError::NotRomAddress(banked_addr))
+    };
+    let start_offset = match start.or(bank_start) {
+        Some(addr) => {
+            let (banked_addr, offset) = resolve(addr)?;
+            if offset >= rom_size {
+                return Err(DisassemblyRangeError::PastEndOfRom(banked_addr));
+            }
+            offset
+        }
+        None => 0,
+    };
+    let end_offset = match end.or(bank_end) {
+        Some(addr) => resolve(addr)?.1.min(rom_size.saturating_sub(1)),
+        None => rom_size.saturating_sub(1),
+    };
+    if end_offset < start_offset {
+        return Err(DisassemblyRangeError::EndBeforeStart);
+    }
+    Ok(start_offset..=end_offset)
+}
+
+/// Size of the ROM that is mapped into memory at once
+const MAPPED_ROM_SIZE: usize = 0x8000;
+
+/// Write a listing of `data`, which starts at `start_offset` in the ROM
+///
+/// Listings use memory addresses, so when disassembling a whole file only
+/// the banks that are mapped in at startup are listed.
+fn write_listing(
+    data: &[u8],
+    start_offset: usize,
+    offset: u16,
+    whole_file: bool,
+    out: &mut dyn io::Write,
+) -> OlympiaResult<()> {
+    let start_addr = BankedAddress::from_rom_offset(start_offset)
+        .map(|banked_addr| banked_addr.addr.0)
+        .unwrap_or(0)
+        .wrapping_add(offset);
+    let mut len = 0x10000 - usize::from(start_addr);
+    if whole_file {
+        len = len.min(MAPPED_ROM_SIZE);
+    }
+    let data = &data[..data.len().min(len)];
+    let analysis = disassembler::CodeAnalysis::from_start(data, start_addr);
+    for line in disassembler::listing(data, start_addr, &analysis) {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Opcodes with their instruction, ordered with CB prefixed opcodes last
+fn sorted_opcodes(
+    instructions: &[InstructionReference],
+) -> Vec<(&InstructionReference, &instructionsn::OpcodeReference)> {
+    let mut opcodes: Vec<_> = instructions
+        .iter()
+        .flat_map(|instruction| {
+            instruction
+                .opcodes
+                .iter()
+                .map(move |opcode| (instruction, opcode))
+        })
+        .collect();
+    opcodes.sort_by_key(|(instruction, opcode)| {
+        (
+            instruction.definition.extension_type == ExtensionType::Extended,
+            opcode.opcode,
+        )
+    });
+    opcodes
+}
+
+fn print_opcode_list(
+    instructions: &[InstructionReference],
+    out: &mut dyn io::Write,
+) -> OlympiaResult<()> {
+    writeln!(
+        out,
+        "{:<6}  {:<16}  {:<6}  Flags",
+        "Opcode", "Disassembly", "Cycles"
+    )?;
+    for (instruction, opcode) in sorted_opcodes(instructions) {
+        let prefix = match instruction.definition.extension_type {
+            ExtensionType::None => "",
+            ExtensionType::Extended => "CB",
+        };
+        writeln!(
+            out,
+            "{:<6}  {:<16}  {:<6}  {}",
+            format!("{}{:02X}", prefix, opcode.opcode),
+            opcode.disassembly,
+            opcode.cycles.to_string(),
+            opcode.flags
+        )?;
+    }
+    Ok(())
+}
+
+fn print_opcode_matrix(
+    instructions: &[InstructionReference],
+    out: &mut dyn io::Write,
+) -> OlympiaResult<()> {
+    let opcodes = sorted_opcodes(instructions);
+    let width = opcodes
+        .iter()
+        .map(|(_, opcode)| opcode.disassembly.len())
+        .max()
+        .unwrap_or(0);
+    let tables = [
+        (ExtensionType::None, "Opcodes"),
+        (ExtensionType::Extended, "CB prefixed opcodes"),
+    ];
+    for (i, (extension_type, title)) in tables.iter().enumerate() {
+        if i != 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{}", title)?;
+        write!(out, "  ")?;
+        for column in 0..16 {
+            write!(
+                out,
+                " | {:<width$}",
+                format!("x{:X}", column),
+                width = width
+            )?;
+        }
+        writeln!(out)?;
+        for row in 0..16u8 {
+            write!(out, "{:X}x", row)?;
+            for column in 0..16u8 {
+                let value = row << 4 | column;
+                let text = opcodes
+                    .iter()
+                    .find(|(instruction, opcode)| {
+                        instruction.definition.extension_type == *extension_type
+                            && opcode.opcode == value
+                    })
+                    .map_or("--", |(_, opcode)| opcode.disassembly.as_str());
+                write!(out, " | {:<width$}", text, width = width)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// What to record while running a ROM with the run command
+struct RunOptions<'a> {
+    frames: u32,
+    heatmap_path: Option<&'a Path>,
+    trace_path: Option<&'a Path>,
+    hash_every_frame: bool,
+}
+
+/// Run a ROM as fast as possible, and report its speed and where time was spent
+fn bench_rom(
+    mut gb: gameboy::GameBoy,
+    frames: u32,
+    out: &mut dyn io::Write,
+    err: &mut dyn io::Write,
+) -> OlympiaResult<()> {
+    gb.reset_profile();
+    let end_clocks = gb.clocks_elapsed() + u64::from(frames) * CLOCKS_PER_FRAME;
+    let start = Instant::now();
+    while gb.clocks_elapsed() < end_clocks {
+        if let Err(e) = gb.step() {
+            writeln!(err, "Stopped due to error: {}", e)?;
+            break;
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let frames_run = gb.clocks_elapsed() as f64 / CLOCKS_PER_FRAME as f64;
+    let emulated = gb.clocks_elapsed() as f64 / CLOCK_FREQ as f64;
+    writeln!(
+        out,
+        "Ran {:.0} frames in {:.3} s: {:.1} frames per second, {:.0}% of full speed",
+        frames_run,
+        elapsed,
+        frames_run / elapsed,
+        emulated * 100.0 / elapsed
+    )?;
+    match gb.profile() {
+        Some(profile) => writeln!(out, "{}", profile)?,
+        None => writeln!(
+            err,
+            "Build with the profiling feature to see the time spent in each component"
+        )?,
+    }
+    Ok(())
+}
+
+/// First of the sound registers, including wave RAM
+const SOUND_REGISTERS_START: u16 = 0xFF10;
+/// Last of the sound registers, including wave RAM
+const SOUND_REGISTERS_END: u16 = 0xFF3F;
+
+/// Play a GBS track for `clocks` clocks, and summarise what the music code did
+///
+/// `track` starts from 1, as shown by music players.
+fn play_gbs(
+    data: &[u8],
+    track: Option<u8>,
+    clocks: u64,
+    out: &mut dyn io::Write,
+    err: &mut dyn io::Write,
+) -> OlympiaResult<()> {
+    let file = gbs::GbsFile::from_data(data)?;
+    let header = &file.header;
+    let track = match track {
+        Some(track) => track.saturating_sub(1),
+        None => header.first_track,
+    };
+    let mut player = gbs::GbsPlayer::new(&file, track, gameboy::GameBoyModel::GameBoy)?;
+    writeln!(out, "Title: {}", header.title)?;
+    writeln!(out, "Author: {}", header.author)?;
+    writeln!(out, "Copyright: {}", header.copyright)?;
+    writeln!(out, "Track {} of {}", track + 1, header.track_count)?;
+    writeln!(
+        err,
+        "Warning: Sound is not emulated yet, so the track plays silently"
+    )?;
+
+    let sound_writes = Shared::new(Lock::new(0u64));
+    let handler_sound_writes = sound_writes.clone();
+    player.gameboy_mut().events.on(Box::new(move |evt| {
+        if let events::Event::Memory(events::MemoryEvent::Write { address, .. }) = evt {
+            if (SOUND_REGISTERS_START..=SOUND_REGISTERS_END).contains(&address.0) {
+                *handler_sound_writes.borrow_mut() += 1;
+            }
+        }
+    }));
+
+    if let Err(e) = player.run_for(clocks) {
+        writeln!(err, "Stopped due to error: {}", e)?;
+    }
+    writeln!(
+        out,
+        "Played {:.1} seconds: {} calls to the play routine, {} writes to sound registers",
+        player.gameboy().clocks_elapsed() as f64 / CLOCK_FREQ as f64,
+        player.play_calls(),
+        *sound_writes.borrow()
+    )?;
+    Ok(())
+}
+
+/// Run the gameboy without a display, recording memory accesses if a heatmap path is given
+/// and the state before each instruction if a trace path is given
+fn run_rom(
+    mut gb: gameboy::GameBoy,
+    options: RunOptions,
+    out: &mut dyn io::Write,
+    err: &mut dyn io::Write,
+) -> OlympiaResult<()> {
+    let RunOptions {
+        frames,
+        heatmap_path,
+        trace_path,
+        hash_every_frame,
+    } = options;
+    let heatmap = Shared::new(Lock::new(AccessHeatmap::new()));
+    if heatmap_path.is_some() {
+        let handler_heatmap = heatmap.clone();
+        gb.events.on(Box::new(move |evt| {
+            handler_heatmap.borrow_mut().record(evt)
+        }));
+    }
+
+    let mut trace = match trace_path {
+        Some(path) => Some(io::BufWriter::new(fs::File::create(path)?)),
+        None => None,
+    };
+
+    let frame_ended = Shared::new(Lock::new(false));
+    if hash_every_frame {
+        let handler_frame_ended = frame_ended.clone();
+        gb.events.on(Box::new(move |evt| {
+            if let events::Event::VBlank(_) = evt {
+                *handler_frame_ended.borrow_mut() = true;
+            }
+        }));
+    }
+
+    let end_clocks = gb.clocks_elapsed() + u64::from(frames) * CLOCKS_PER_FRAME;
+    let mut frames_hashed = 0;
+    while gb.clocks_elapsed() < end_clocks {
+        if let Some(trace) = trace.as_mut() {
+            writeln!(trace, "{}", TraceLine::capture(&gb))?;
+        }
+        if let Err(e) = gb.step() {
+            writeln!(err, "Stopped due to error: {}", e)?;
+            break;
+        }
+        if frame_ended.replace(false) {
+            frames_hashed += 1;
+            writeln!(out, "Frame {}: {:016X}", frames_hashed, gb.frame_hash())?;
+        }
+    }
+    if let Some(mut trace) = trace {
+        trace.flush()?;
+    }
+    let cycles = gb.clocks_elapsed();
+    writeln!(out, "Cycles: {} / M-Cycles: {}", cycles, cycles / 4)?;
+    if hash_every_frame {
+        writeln!(out, "Run hash: {:016X}", gb.run_hash())?;
+    }
+
+    if let Some(path) = heatmap_path {
+        let mut contents = String::new();
+        let is_json = path.extension().and_then(|ext| ext.to_str()) == Some("json");
+        let result = if is_json {
+            heatmap.borrow().write_json(&mut contents)
+        } else {
+            heatmap.borrow().write_csv(&mut contents)
+        };
+        // Writing to a String can't fail
+        result.expect("Could not format heatmap");
+        fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+fn output_format(json: bool) -> debugger::OutputFormat {
+    if json {
+        debugger::OutputFormat::Json
+    } else {
+        debugger::OutputFormat::Text
+    }
+}
+
+/// Check emulation of a ROM against a trace log, printing where they differ
+fn verify_rom(mut gb: gameboy::GameBoy, log: &str, out: &mut dyn io::Write) -> OlympiaResult<()> {
+    match verify_trace(&mut gb, log.lines()) {
+        Ok(lines) => {
+            writeln!(out, "Verified {} instructions", lines)?;
+            Ok(())
+        }
+        Err(e) => {
+            write!(out, "{}", e)?;
+            if !matches!(e, VerifyError::Diverged(_)) {
+                writeln!(out)?;
+            }
+            Err(OlympiaError::VerifyFailed)
+        }
+    }
+}
+
+fn print_lints(data: &[u8], out: &mut dyn io::Write) -> OlympiaResult<()> {
+    let lints = disassembler::lint(data);
+    for lint in lints.iter() {
+        writeln!(out, "{}", lint)?;
+    }
+    if lints.is_empty() {
+        writeln!(out, "No problems found")?;
+    } else {
+        writeln!(out, "Found {} problems", lints.len())?;
+    }
+    Ok(())
+}
+
+fn print_rom_map(data: &[u8], out: &mut dyn io::Write) -> OlympiaResult<()> {
+    let map = disassembler::rom_map(data);
+    match map.entry_target {
+        Some(target) => writeln!(out, "Entry point: 0100h, jumps to {:04X}h", target)?,
+        None => writeln!(out, "Entry point: 0100h")?,
+    }
+    writeln!(out, "Interrupt handlers:")?;
+    for handler in map.interrupts.iter() {
+        let instruction = handler
+            .instruction
+            .as_ref()
+            .map_or("unused", |instruction| &instruction.text);
+        writeln!(
+            out,
+            "  {} ({:04X}h): {}",
+            handler.name, handler.address, instruction
+        )?;
+    }
+    match map.header_banks {
+        Some(header_banks) => writeln!(
+            out,
+            "Banks: {} (header declares {})",
+            map.banks.len(),
+            header_banks
+        )?,
+        None => writeln!(out, "Banks: {}", map.banks.len())?,
+    }
+    writeln!(out, "Padding: {:02X}h", map.padding)?;
+    for bank in map.banks.iter() {
+        let end = usize::from(bank.start) + bank.len - 1;
+        write!(out, "Bank {} ({:04X}-{:04X}): ", bank.bank, bank.start, end)?;
+        match (bank.first_used, bank.last_used) {
+            (Some(first), Some(last)) => write!(out, "used {:04X}-{:04X}", first, last)?,
+            _ => write!(out, "empty")?,
+        }
+        write!(out, ", {} bytes free", bank.free())?;
+        match bank.largest_free_block() {
+            Some(block) => writeln!(
+                out,
+                ", largest block {:04X}-{:04X}",
+                block.start,
+                usize::from(block.start) + block.len - 1
+            )?,
+            None => writeln!(out)?,
+        }
+        if let Some(coverage) = bank.coverage {
+            writeln!(
+                out,
+                "  Code: {} bytes, text: {} bytes, graphics: {} bytes",
+                coverage.code, coverage.text, coverage.graphics
+            )?;
+        }
+    }
+    let free = map.free();
+    let percent = if data.is_empty() {
+        0
+    } else {
+        free * 100 / data.len()
+    };
+    writeln!(out, "Total free: {} bytes ({}%)", free, percent)?;
+    Ok(())
+}
+
 fn run_cli(
     args: OlympiaArgs,
     in_: &mut dyn io::Read,
@@ -121,27 +811,154 @@ fn run_cli(
 ) -> OlympiaResult<()> {
     match args.cmd {
         OlympiaCommand::RomInfo { rom } => print_rom_info(parse_cartridge(&rom)?, out)?,
-        OlympiaCommand::Debug { rom } => debugger::debug(
-            gameboy::GameBoy::new(parse_cartridge(&rom)?, gameboy::GameBoyModel::GameBoy),
-            in_,
-            out,
-            err,
-        )?,
-        OlympiaCommand::Disassemble { verbose, rom } => {
-            let data = std::fs::read(rom)?;
+        OlympiaCommand::Debug {
+            background: true,
+            rom,
+            ..
+        } => {
+            let data = romfile::read(&rom)?;
+            // Check the ROM here so it fails the same way as without --background
+            rom::Cartridge::from_data(data.clone())?;
+            remote_debugger::debug(data, in_, out, err)?
+        }
+        OlympiaCommand::Debug {
+            background: false,
+            json,
+            link,
+            rom,
+        } => {
+            let mut gb =
+                gameboy::GameBoy::new(parse_cartridge(&rom)?, gameboy::GameBoyModel::GameBoy);
+            link.connect(&mut gb, err)?;
+            debugger::debug(gb, output_format(json), in_, out, err)?
+        }
+        OlympiaCommand::Exec {
+            commands,
+            json,
+            link,
+            rom,
+        } => {
+            let mut gb =
+                gameboy::GameBoy::new(parse_cartridge(&rom)?, gameboy::GameBoyModel::GameBoy);
+            link.connect(&mut gb, err)?;
+            debugger::exec(gb, output_format(json), &commands, out, err)?
+        }
+        OlympiaCommand::Run {
+            frames,
+            heatmap,
+            trace,
+            hash_every_frame,
+            link,
+            rom,
+        } => {
+            let mut gb =
+                gameboy::GameBoy::new(parse_cartridge(&rom)?, gameboy::GameBoyModel::GameBoy);
+            link.connect(&mut gb, err)?;
+            let options = RunOptions {
+                frames,
+                heatmap_path: heatmap.as_deref(),
+                trace_path: trace.as_deref(),
+                hash_every_frame,
+            };
+            run_rom(gb, options, out, err)?
+        }
+        OlympiaCommand::BenchRom { frames, rom } => {
+            let gb = gameboy::GameBoy::new(parse_cartridge(&rom)?, gameboy::GameBoyModel::GameBoy);
+            bench_rom(gb, frames, out, err)?
+        }
+        OlympiaCommand::Play {
+            track,
+            seconds,
+            file,
+        } => {
+            let clocks = u64::from(seconds) * CLOCK_FREQ;
+            play_gbs(&fs::read(&file)?, track, clocks, out, err)?
+        }
+        OlympiaCommand::Verify { rom, log } => {
+            let gb = gameboy::GameBoy::new(parse_cartridge(&rom)?, gameboy::GameBoyModel::GameBoy);
+            verify_rom(gb, &fs::read_to_string(&log)?, out)?
+        }
+        OlympiaCommand::Opcodes { matrix } => {
+            let instructions = instructionsn::instruction_set();
+            if matrix {
+                print_opcode_matrix(&instructions, out)?
+            } else {
+                print_opcode_list(&instructions, out)?
+            }
+        }
+        OlympiaCommand::Lint { rom } => print_lints(&read_input(&rom, in_)?, out)?,
+        OlympiaCommand::Map { rom } => print_rom_map(&read_input(&rom, in_)?, out)?,
+        OlympiaCommand::Disassemble {
+            verbose,
+            banked,
+            listing,
+            no_labels,
+            bank,
+            start,
+            end,
+            offset,
+            output,
+            rom,
+        } => {
+            let data = read_input(&rom, in_)?;
             let format = if verbose {
                 DisassemblyFormat::Verbose
             } else {
                 DisassemblyFormat::Normal
             };
-            disassembler::disassemble(data, format, out)?
+            let whole_file = bank.is_none() && start.is_none() && end.is_none();
+            let (data, start_offset) = if whole_file {
+                (data, 0)
+            } else {
+                let range = disassembly_range(data.len(), bank, start, end)?;
+                let start_offset = *range.start();
+                (data[range].to_vec(), start_offset)
+            };
+            let mut output_file = match output {
+                Some(path) => Some(io::BufWriter::new(fs::File::create(path)?)),
+                None => None,
+            };
+            let out: &mut dyn io::Write = match output_file {
+                Some(ref mut file) => file,
+                None => out,
+            };
+            if listing {
+                write_listing(&data, start_offset, offset.unwrap_or(0), whole_file, out)?;
+                return Ok(());
+            }
+            let initial_offset = start_offset + usize::from(offset.unwrap_or(0));
+            let labels = if no_labels {
+                BTreeSet::new()
+            } else {
+                disassembler::jump_targets(&data, initial_offset)
+            };
+            let instructions = DisassemblyIterator::new(data.into_iter(), format, initial_offset)
+                .banked(banked || bank.is_some())
+                .labels(labels);
+            for instruction in instructions {
+                writeln!(out, "{}", instruction)?;
+            }
+            out.flush()?;
         }
     }
     Ok(())
 }
 
+/// Log through the engine's logger, with levels from `RUST_LOG`
+fn init_logging() {
+    let output = pretty_env_logger::formatted_builder()
+        .filter_level(log::LevelFilter::Trace)
+        .build();
+    if let Ok(spec) = std::env::var("RUST_LOG") {
+        if let Err(e) = logging::set_levels_from_spec(&spec) {
+            eprintln!("Ignoring RUST_LOG: {}", e);
+        }
+    }
+    logging::install(Box::new(output)).expect("No other logger should be set yet");
+}
+
 fn main() -> OlympiaResult<()> {
-    pretty_env_logger::init();
+    init_logging();
     let args = OlympiaArgs::from_args();
     let mut err = find_err_out(&args);
     run_cli(args, &mut io::stdin(), &mut io::stdout(), err.as_mut())
@@ -182,7 +999,12 @@ pub mod test {
         let mut err = Vec::new();
         let args = OlympiaArgs {
             quiet: false,
-            cmd: OlympiaCommand::Debug { rom },
+            cmd: OlympiaCommand::Debug {
+                background: false,
+                json: false,
+                link: LinkArgs::default(),
+                rom,
+            },
         };
 
         run_cli(args, &mut in_, &mut out, &mut err).unwrap();
@@ -193,6 +1015,635 @@ pub mod test {
         assert_eq!(actual_output, expected_output);
     }
 
+    #[test]
+    fn test_exec_e2e() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Exec {
+                commands: vec![
+                    String::from("step 2"),
+                    String::from("r PC"),
+                    String::from("pb 0xFF40:0xFF4B"),
+                ],
+                json: false,
+                link: LinkArgs::default(),
+                rom,
+            },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+
+        let actual_output = String::from_utf8_lossy(&out);
+        let expected_output = "150\nFF40: 91 80 00 00 00 00 00 FC FF FF 00 00 \n";
+
+        assert_eq!(actual_output, expected_output);
+        assert!(err.is_empty());
+    }
+
+    #[test]
+    fn test_exec_invalid_command() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Exec {
+                commands: vec![
+                    String::from("step"),
+                    String::from("jump"),
+                    String::from("cc"),
+                ],
+                json: false,
+                link: LinkArgs::default(),
+                rom,
+            },
+        };
+
+        let result = run_cli(args, &mut in_, &mut out, &mut err);
+
+        assert_eq!(result.unwrap_err().to_string(), "Invalid command: \"jump\"");
+        // Commands after the invalid one are not run
+        assert!(out.is_empty());
+        assert!(String::from_utf8_lossy(&err).contains("'jump' wasn't recognized"));
+    }
+
+    #[test]
+    fn test_run_heatmap_e2e() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let mut heatmap = std::env::temp_dir();
+        heatmap.push(format!("olympia_heatmap_test_{}.csv", std::process::id()));
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Run {
+                frames: 1,
+                heatmap: Some(heatmap.clone()),
+                trace: None,
+                hash_every_frame: false,
+                link: LinkArgs::default(),
+                rom,
+            },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+        let actual_heatmap = fs::read_to_string(&heatmap).unwrap();
+        fs::remove_file(&heatmap).unwrap();
+
+        assert!(String::from_utf8_lossy(&out).starts_with("Cycles: "));
+        let mut lines = actual_heatmap.lines();
+        assert_eq!(lines.next(), Some("address,reads,writes"));
+        // The entry point is run once: NOP; JP $150h
+        assert!(actual_heatmap.contains("\n0100,1,0\n"));
+    }
+
+    #[test]
+    fn test_run_serial_out_e2e() {
+        let send = [
+            0xE0, 0x01, // LDH (SB), A
+            0x3E, 0x81, // LD A, 81h
+            0xE0, 0x02, // LDH (SC), A
+            0xF0, 0x02, // LDH A, (SC)
+            0x17, // RLA
+            0x38, 0xFB, // JR C, -5
+            0xC9, // RET
+        ];
+        let main = [
+            0x3E, b'o', // LD A, 'o'
+            0xCD, 0x50, 0x01, // CALL 150h
+            0x3E, b'k', // LD A, 'k'
+            0xCD, 0x50, 0x01, // CALL 150h
+            0x18, 0xFE, // JR -2
+        ];
+        let program = olympia_engine::testing::Program::new(&[])
+            .with_segment(0x100, &main)
+            .with_segment(0x150, &send);
+        let mut rom = std::env::temp_dir();
+        rom.push(format!("olympia_serial_test_{}.gb", std::process::id()));
+        fs::write(&rom, program.rom()).unwrap();
+        let mut serial_out = std::env::temp_dir();
+        serial_out.push(format!("olympia_serial_test_{}.txt", std::process::id()));
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Run {
+                frames: 1,
+                heatmap: None,
+                trace: None,
+                hash_every_frame: false,
+                link: LinkArgs {
+                    serial_out: Some(serial_out.clone()),
+                    ..LinkArgs::default()
+                },
+                rom: rom.clone(),
+            },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+        let actual_output = fs::read_to_string(&serial_out).unwrap();
+        fs::remove_file(&rom).unwrap();
+        fs::remove_file(&serial_out).unwrap();
+
+        assert_eq!(actual_output, "ok");
+    }
+
+    #[test]
+    fn test_play_gbs() {
+        let mut data = vec![0; 0x70];
+        data[0..4].copy_from_slice(b"GBS\x01");
+        data[4] = 2; // Tracks
+        data[5] = 1; // First track
+        data[6..8].copy_from_slice(&0x400u16.to_le_bytes());
+        data[8..0xA].copy_from_slice(&0x400u16.to_le_bytes());
+        data[0xA..0xC].copy_from_slice(&0x401u16.to_le_bytes());
+        data[0xC..0xE].copy_from_slice(&0xFFFEu16.to_le_bytes());
+        data[0x10..0x14].copy_from_slice(b"Song");
+        data.extend_from_slice(&[
+            0xC9, // 0x400: RET
+            0x3E, 0x77, // 0x401: LD A, 77h
+            0xE0, 0x24, // 0x403: LDH (24h), A
+            0xC9, // 0x405: RET
+        ]);
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+
+        play_gbs(&data, Some(2), CLOCKS_PER_FRAME * 3, &mut out, &mut err).unwrap();
+
+        let out = String::from_utf8_lossy(&out);
+        assert!(out.starts_with("Title: Song\nAuthor: \nCopyright: \nTrack 2 of 2\n"));
+        assert!(out.contains(": 3 calls to the play routine, 3 writes to sound registers"));
+        assert!(String::from_utf8_lossy(&err).contains("plays silently"));
+
+        let result = play_gbs(&data, Some(3), CLOCKS_PER_FRAME, &mut Vec::new(), &mut err);
+        assert!(matches!(result, Err(OlympiaError::Gbs(_))));
+    }
+
+    #[test]
+    fn test_bench_rom_e2e() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::BenchRom { frames: 2, rom },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("Ran 2 frames in "));
+        assert!(out.contains("% of full speed\n"));
+        if cfg!(feature = "profiling") {
+            assert!(out.contains("\ntotal "));
+        } else {
+            assert!(String::from_utf8(err)
+                .unwrap()
+                .contains("profiling feature"));
+        }
+    }
+
+    #[test]
+    fn test_run_hash_every_frame_e2e() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let run = || {
+            let mut in_: &[u8] = &[];
+            let mut out = Vec::new();
+            let mut err = Vec::new();
+            let args = OlympiaArgs {
+                quiet: false,
+                cmd: OlympiaCommand::Run {
+                    frames: 3,
+                    heatmap: None,
+                    trace: None,
+                    hash_every_frame: true,
+                    link: LinkArgs::default(),
+                    rom: rom.clone(),
+                },
+            };
+            run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let output = run();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].starts_with("Frame 1: "));
+        assert!(lines[1].starts_with("Frame 2: "));
+        assert!(lines[lines.len() - 1].starts_with("Run hash: "));
+        // Hashes are the same every run
+        assert_eq!(output, run());
+    }
+
+    #[test]
+    fn test_run_trace_verify_e2e() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let mut trace = std::env::temp_dir();
+        trace.push(format!("olympia_trace_test_{}.log", std::process::id()));
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Run {
+                frames: 1,
+                heatmap: None,
+                trace: Some(trace.clone()),
+                hash_every_frame: false,
+                link: LinkArgs::default(),
+                rom: rom.clone(),
+            },
+        };
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+
+        let log = fs::read_to_string(&trace).unwrap();
+        assert!(log.starts_with(
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01\n"
+        ));
+        let lines = log.lines().count();
+
+        let mut out = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Verify {
+                rom: rom.clone(),
+                log: trace.clone(),
+            },
+        };
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&out),
+            format!("Verified {} instructions\n", lines)
+        );
+
+        // JP $150h should have gone somewhere else
+        fs::write(&trace, log.replacen("PC:0150", "PC:0151", 1)).unwrap();
+        let mut out = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Verify {
+                rom,
+                log: trace.clone(),
+            },
+        };
+        let result = run_cli(args, &mut in_, &mut out, &mut err);
+        fs::remove_file(&trace).unwrap();
+
+        assert!(matches!(result, Err(OlympiaError::VerifyFailed)));
+        let actual_output = String::from_utf8_lossy(&out);
+        assert!(actual_output.starts_with(
+            "Diverged from line 3 after 2 instructions, running the instruction at 0101\n"
+        ));
+        assert!(actual_output.ends_with("\n  PC: expected 0151, was 0150\n"));
+    }
+
+    #[test]
+    fn test_run_link_join_e2e() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let host = std::thread::spawn(move || listener.accept().unwrap());
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Run {
+                frames: 1,
+                heatmap: None,
+                trace: None,
+                hash_every_frame: false,
+                link: LinkArgs {
+                    link_host: None,
+                    link_join: Some(addr.to_string()),
+                    serial_out: None,
+                },
+                rom,
+            },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+        host.join().unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&err), "Link cable connected\n");
+        assert!(String::from_utf8_lossy(&out).starts_with("Cycles: "));
+    }
+
+    #[test]
+    fn test_opcodes_e2e() {
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Opcodes { matrix: false },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+
+        let actual_output = String::from_utf8_lossy(&out);
+        let lines: Vec<&str> = actual_output.lines().collect();
+        assert_eq!(lines[0], "Opcode  Disassembly       Cycles  Flags");
+        assert_eq!(lines[1], "00      NOP               1       - - - -");
+        assert!(lines.contains(&"22      LD (HL+), A       2       - - - -"));
+        assert!(lines.contains(&"27      DAA               1       Z - 0 C"));
+        assert!(lines.contains(&"C4      CALL NZ, $0h      3-6     - - - -"));
+        assert_eq!(
+            lines.last(),
+            Some(&"CBFF    SET 7h, A         2       - - - -")
+        );
+        // A header, and every opcode except the 11 unused ones and the CB prefix
+        assert_eq!(lines.len(), 1 + 244 + 256);
+    }
+
+    #[test]
+    fn test_opcode_matrix() {
+        let instructions = instructionsn::instruction_set();
+        let mut out = Vec::new();
+
+        print_opcode_matrix(&instructions, &mut out).unwrap();
+
+        let actual_output = String::from_utf8_lossy(&out);
+        let lines: Vec<&str> = actual_output.lines().collect();
+        assert_eq!(lines[0], "Opcodes");
+        assert!(lines[1].starts_with("   | x0             | x1"));
+        assert!(lines[15].starts_with("Dx | RET NC         | POP DE         | JP NC, $0h     | --"));
+        assert_eq!(lines[19], "CB prefixed opcodes");
+    }
+
+    #[test]
+    fn test_lint_e2e() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Lint { rom },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+
+        assert_eq!(String::from_utf8_lossy(&out), "No problems found\n");
+    }
+
+    #[test]
+    fn test_map_e2e() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Map { rom },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+
+        let output = String::from_utf8_lossy(&out);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "Entry point: 0100h, jumps to 0150h");
+        assert_eq!(lines[2], "  VBlank (0040h): RETI");
+        assert_eq!(lines[7], "Banks: 2 (header declares 2)");
+        assert_eq!(lines[8], "Padding: 00h");
+        assert_eq!(
+            lines[9],
+            "Bank 0 (0000-3FFF): used 0000-01B7, 16103 bytes free, largest block 01B8-3FFF"
+        );
+        assert_eq!(
+            lines[10],
+            "  Code: 114 bytes, text: 11 bytes, graphics: 0 bytes"
+        );
+        assert_eq!(
+            lines[11],
+            "Bank 1 (4000-7FFF): empty, 16384 bytes free, largest block 4000-7FFF"
+        );
+        assert_eq!(lines[13], "Total free: 32487 bytes (99%)");
+    }
+
+    #[test]
+    fn test_lint_stdin() {
+        // LD ($FF03h), A; JR NZ, 1h; DAT D3h; JP $8000h
+        let mut in_: &[u8] = &[0xE0, 0x03, 0x20, 0x01, 0xD3, 0xC3, 0x00, 0x80];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Lint {
+                rom: PathBuf::from("-"),
+            },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(&out),
+            concat!(
+                "0000: Write to unmapped IO address FF03h\n",
+                "0004: Invalid opcode D3h\n",
+                "0005: Jump to 8000h, which can't contain code\n",
+                "Found 3 problems\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_disassemble_range_e2e() {
+        let mut rom = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        rom.pop(); // workspace folder
+        rom.push("res/fizzbuzz.gb");
+        let mut in_: &[u8] = &[];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Disassemble {
+                verbose: false,
+                banked: false,
+                listing: false,
+                no_labels: false,
+                bank: Some(0),
+                start: Some(0x100),
+                end: Some(0x103),
+                offset: None,
+                output: None,
+                rom,
+            },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+
+        let actual_output = String::from_utf8_lossy(&out);
+        let expected_output = "0:0100:\t\tNOP\n       \t\tJP $150h\n";
+
+        assert_eq!(actual_output, expected_output);
+    }
+
+    #[test]
+    fn test_disassemble_listing() {
+        // CALL $4004h; HALT; RET
+        let mut in_: &[u8] = &[0xCD, 0x04, 0x40, 0x76, 0xC9];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Disassemble {
+                verbose: false,
+                banked: false,
+                listing: true,
+                no_labels: false,
+                bank: None,
+                start: None,
+                end: None,
+                offset: Some(0x4000),
+                output: None,
+                rom: PathBuf::from("-"),
+            },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+
+        assert_eq!(
+            String::from_utf8_lossy(&out),
+            concat!(
+                "4000:  CD 04 40  CALL sub_4004\n",
+                "4003:  76        HALT\n",
+                "sub_4004:\n",
+                "4004:  C9        RET                    ; called from 0x4000\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_disassemble_labels() {
+        // NOP; JR -3h
+        let input = [0x00, 0x18, 0xFD];
+        let run = |no_labels| {
+            let mut in_: &[u8] = &input;
+            let mut out = Vec::new();
+            let mut err = Vec::new();
+            let args = OlympiaArgs {
+                quiet: false,
+                cmd: OlympiaCommand::Disassemble {
+                    verbose: false,
+                    banked: false,
+                    listing: false,
+                    no_labels,
+                    bank: None,
+                    start: None,
+                    end: None,
+                    offset: None,
+                    output: None,
+                    rom: PathBuf::from("-"),
+                },
+            };
+            run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            run(false),
+            "label_0000:\n     0:\t\tNOP\n       \t\tJR label_0000\n"
+        );
+        assert!(!run(true).contains("label"));
+    }
+
+    #[test]
+    fn test_disassemble_stdin_to_file() {
+        let mut output = std::env::temp_dir();
+        output.push(format!(
+            "olympia_disassemble_test_{}.txt",
+            std::process::id()
+        ));
+        // NOP; JP $4150h
+        let mut in_: &[u8] = &[0x00, 0xC3, 0x50, 0x41];
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let args = OlympiaArgs {
+            quiet: false,
+            cmd: OlympiaCommand::Disassemble {
+                verbose: true,
+                banked: false,
+                listing: false,
+                no_labels: false,
+                bank: None,
+                start: None,
+                end: None,
+                offset: Some(0x4000),
+                output: Some(output.clone()),
+                rom: PathBuf::from("-"),
+            },
+        };
+
+        run_cli(args, &mut in_, &mut out, &mut err).unwrap();
+        let actual_output = fs::read_to_string(&output).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert!(out.is_empty());
+        assert_eq!(
+            actual_output,
+            "  4000:\t\t    00\t\tNOP\n  4001:\t\tC35041\t\tJP $4150h\n"
+        );
+    }
+
+    #[test]
+    fn test_disassembly_range() {
+        assert_eq!(disassembly_range(0x8000, None, None, None), Ok(0..=0x7FFF));
+        assert_eq!(
+            disassembly_range(0x8000, None, Some(0x4000), None),
+            Ok(0x4000..=0x7FFF)
+        );
+        assert_eq!(
+            disassembly_range(0x10000, Some(2), None, None),
+            Ok(0x8000..=0xBFFF)
+        );
+        assert_eq!(
+            disassembly_range(0x10000, Some(3), Some(0x4010), Some(0x4020)),
+            Ok(0xC010..=0xC020)
+        );
+        assert_eq!(
+            disassembly_range(0x8000, Some(0), Some(0x100), Some(0x9000)),
+            Err(DisassemblyRangeError::NotRomAddress(BankedAddress::new(
+                0, 0x9000
+            )))
+        );
+        assert_eq!(
+            disassembly_range(0x8000, Some(2), None, None),
+            Err(DisassemblyRangeError::PastEndOfRom(BankedAddress::new(
+                2, 0x4000
+            )))
+        );
+        assert_eq!(
+            disassembly_range(0x8000, None, Some(0x200), Some(0x100)),
+            Err(DisassemblyRangeError::EndBeforeStart)
+        );
+    }
+
     #[test]
     fn test_rom_info_srom() {
         let cartridge = rom::Cartridge::from_data(vec![0; 0x2000]).unwrap();
@@ -201,13 +1652,18 @@ pub mod test {
         print_rom_info(cartridge, &mut captured_output).unwrap();
 
         let actual_output = String::from_utf8_lossy(&captured_output);
-        let expected_output = ["Cartridge Type: Static ROM", "ROM Size: 8KiB"].join("\n");
+        let expected_output = [
+            "Cartridge Type: Static ROM",
+            "ROM Size: 32KiB",
+            "Warning: ROM is only 0x2000 bytes, padding it to 0x8000 bytes with 0xFF",
+        ]
+        .join("\n");
         assert_eq!(actual_output, expected_output);
     }
 
     #[test]
     fn test_rom_info_mbc1() {
-        let mut data = vec![0; 0x2000];
+        let mut data = vec![0; 0x8000];
         data[0x147] = 1;
         let cartridge = rom::Cartridge::from_data(data).unwrap();
         let mut captured_output = Vec::new();
@@ -216,13 +1672,13 @@ pub mod test {
 
         let actual_output = String::from_utf8_lossy(&captured_output);
         let expected_output =
-            ["Cartridge Type: MBC1", "RAM Size: 0KiB", "ROM Size: 8KiB"].join("\n");
+            ["Cartridge Type: MBC1", "RAM Size: 0KiB", "ROM Size: 32KiB"].join("\n");
         assert_eq!(actual_output, expected_output);
     }
 
     #[test]
     fn test_rom_info_mbc1_ram() {
-        let mut data = vec![0; 0x2000];
+        let mut data = vec![0; 0x8000];
         data[0x147] = 2;
         data[0x149] = 2;
         let cartridge = rom::Cartridge::from_data(data).unwrap();
@@ -232,14 +1688,34 @@ pub mod test {
 
         let actual_output = String::from_utf8_lossy(&captured_output);
         let expected_output =
-            ["Cartridge Type: MBC1", "RAM Size: 8KiB", "ROM Size: 8KiB"].join("\n");
+            ["Cartridge Type: MBC1", "RAM Size: 8KiB", "ROM Size: 32KiB"].join("\n");
         assert_eq!(actual_output, expected_output);
     }
 
     #[test]
     fn test_rom_info_mbc2() {
-        let mut data = vec![0; 0x2000];
+        let mut data = vec![0; 0x8000];
+        data[0x147] = 5;
+        let cartridge = rom::Cartridge::from_data(data).unwrap();
+        let mut captured_output = Vec::new();
+
+        print_rom_info(cartridge, &mut captured_output).unwrap();
+
+        let actual_output = String::from_utf8_lossy(&captured_output);
+        let expected_output = [
+            "Cartridge Type: MBC2",
+            "RAM Size: 512 x 4 bits",
+            "ROM Size: 32KiB",
+        ]
+        .join("\n");
+        assert_eq!(actual_output, expected_output);
+    }
+
+    #[test]
+    fn test_rom_info_warnings() {
+        let mut data = vec![0; 0x8000];
         data[0x147] = 5;
+        data[0x149] = 1;
         let cartridge = rom::Cartridge::from_data(data).unwrap();
         let mut captured_output = Vec::new();
 
@@ -249,7 +1725,8 @@ pub mod test {
         let expected_output = [
             "Cartridge Type: MBC2",
             "RAM Size: 512 x 4 bits",
-            "ROM Size: 8KiB",
+            "ROM Size: 32KiB",
+            "Warning: Cartridge type 0x05 has no external RAM, but the header declares 2 KiB. Ignoring it",
         ]
         .join("\n");
         assert_eq!(actual_output, expected_output);
@@ -0,0 +1,609 @@
+//! A debugger for an emulator running on a background thread
+//!
+//! Unlike the debugger in `debugger`, emulation can keep running while
+//! commands are entered, in the same way as the GUI.
+
+use std::cell::RefCell;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use olympia_engine::{
+    events::{ModeChangeEvent, Repeat},
+    gameboy::Interrupt,
+    monitor::{Breakpoint, BreakpointCondition, Comparison, RWTarget, SymbolTable},
+    remote::{
+        mpsc_channel, EmulatorThread, EventSendError, ExecMode, MpscEmulatorChannel,
+        MpscEventListeners, OutputQueue, QueryRegistersResponse, RemoteEmulator,
+    },
+};
+
+use crate::debugger::{
+    format_disassembly_line, range_addresses, read_command, BreakpointValue, ByteRange,
+    DebugCommand, Input, REVERSE_STEP_LIMIT,
+};
+
+fn emulator_stopped(_: EventSendError) -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "Emulator thread stopped")
+}
+
+fn is_running(mode: &ExecMode) -> bool {
+    matches!(mode, ExecMode::Standard | ExecMode::Uncapped)
+}
+
+struct RemoteDebugger<'a> {
+    emulator: RemoteEmulator,
+    queue: Rc<OutputQueue>,
+    mode: Rc<RefCell<ExecMode>>,
+    /// Breakpoints hit since messages were last printed
+    hit_breakpoints: Rc<RefCell<Vec<Breakpoint>>>,
+    /// Names that can be used in place of addresses, from load-symbols
+    symbols: SymbolTable,
+    inb: &'a mut dyn io::BufRead,
+    out: &'a mut dyn io::Write,
+    err: &'a mut dyn io::Write,
+}
+
+impl<'a> RemoteDebugger<'a> {
+    fn new(
+        channel: MpscEmulatorChannel,
+        inb: &'a mut dyn io::BufRead,
+        out: &'a mut dyn io::Write,
+        err: &'a mut dyn io::Write,
+    ) -> RemoteDebugger<'a> {
+        let queue = channel.output_queue();
+        let emulator =
+            RemoteEmulator::new(Box::new(MpscEventListeners::default()), Box::new(channel));
+        let mode = Rc::new(RefCell::new(ExecMode::Unloaded));
+        let hit_breakpoints = Rc::new(RefCell::new(Vec::new()));
+        let handler_mode = mode.clone();
+        let handler_breakpoints = hit_breakpoints.clone();
+        emulator.on(move |evt: ModeChangeEvent| {
+            if let ExecMode::HitBreakpoint(ref bp) = evt.new_mode {
+                handler_breakpoints.borrow_mut().push(bp.clone());
+            }
+            handler_mode.replace(evt.new_mode);
+            Repeat(true)
+        });
+        RemoteDebugger {
+            emulator,
+            queue,
+            mode,
+            hit_breakpoints,
+            symbols: SymbolTable::new(),
+            inb,
+            out,
+            err,
+        }
+    }
+
+    /// Handle emulator output until `future` completes
+    fn wait_for<F: Future>(&self, future: F) -> io::Result<F::Output> {
+        self.queue.block_on(future).map_err(emulator_stopped)
+    }
+
+    /// Print anything that happened in the background since the last command
+    fn print_messages(&mut self) -> io::Result<()> {
+        self.queue.handle_pending();
+        for e in self.queue.take_errors() {
+            writeln!(self.err, "Broke due to error: {}", e)?;
+        }
+        for bp in self.hit_breakpoints.replace(Vec::new()) {
+            writeln!(self.out, "Broke on {}", bp)?;
+        }
+        Ok(())
+    }
+
+    fn query_registers(&mut self) -> io::Result<Option<QueryRegistersResponse>> {
+        match self.wait_for(self.emulator.query_registers())? {
+            Ok(registers) => Ok(Some(registers)),
+            Err(e) => {
+                writeln!(self.err, "{}", e)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn load_rom(&mut self, data: Vec<u8>) -> io::Result<()> {
+        self.wait_for(self.emulator.load_rom(data))?
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Parse a register or memory location
+    ///
+    /// Registers can't be used in memory locations, as the emulator may be
+    /// running in the background.
+    fn resolve_target(&mut self, src: &str) -> io::Result<Option<RWTarget>> {
+        match RWTarget::parse_with(src, None, &self.symbols) {
+            Ok(target) => Ok(Some(target)),
+            Err(e) => writeln!(self.err, "{}", e).map(|_| None),
+        }
+    }
+
+    fn resolve_address(&mut self, src: &str) -> io::Result<Option<u16>> {
+        match self.resolve_target(src)? {
+            Some(RWTarget::Address(addr)) => Ok(Some(addr.0)),
+            Some(RWTarget::BankedAddress(addr)) => Ok(Some(addr.addr.0)),
+            Some(target) => writeln!(self.err, "{} is not a memory location", target).map(|_| None),
+            None => Ok(None),
+        }
+    }
+
+    fn load_symbols(&mut self, path: &Path) -> io::Result<()> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => return writeln!(self.err, "Could not read {}: {}", path.display(), e),
+        };
+        match text.parse::<SymbolTable>() {
+            Ok(symbols) => {
+                self.symbols = symbols;
+                writeln!(
+                    self.out,
+                    "Loaded {} symbols from {}",
+                    self.symbols.len(),
+                    path.display()
+                )
+            }
+            Err(e) => writeln!(self.err, "Could not parse {}: {}", path.display(), e),
+        }
+    }
+
+    fn print_bytes(&mut self, range: ByteRange) -> io::Result<()> {
+        let (min_address, max_address) = range_addresses(range);
+        // Ranges can wrap around the end of memory
+        let mut data = Vec::new();
+        let mut query_start = min_address;
+        loop {
+            let query_end = if max_address < query_start {
+                0xFFFF
+            } else {
+                max_address
+            };
+            match self.wait_for(self.emulator.query_memory(query_start, query_end))? {
+                Ok(memory) => data.extend(memory.data),
+                Err(e) => return writeln!(self.err, "{}", e),
+            }
+            if query_end == max_address {
+                break;
+            }
+            query_start = 0;
+        }
+
+        for (i, val) in data.iter().enumerate() {
+            if i % 16 == 0 {
+                if i != 0 {
+                    writeln!(self.out)?;
+                }
+                write!(self.out, "{:04X}: ", min_address.wrapping_add(i as u16))?;
+            }
+            match val {
+                Some(val) => write!(self.out, "{:02X} ", val)?,
+                None => write!(self.out, "-- ")?,
+            }
+        }
+        writeln!(self.out)
+    }
+
+    fn print_registers(&mut self) -> io::Result<()> {
+        let registers = match self.query_registers()? {
+            Some(registers) => registers,
+            None => return Ok(()),
+        };
+        let [a, f] = registers.af.to_be_bytes();
+        let [b, c] = registers.bc.to_be_bytes();
+        let [d, e] = registers.de.to_be_bytes();
+        let [h, l] = registers.hl.to_be_bytes();
+        writeln!(
+            self.out,
+            "A: {:02X}, F: {:02x}, AF: {:04X}",
+            a, f, registers.af
+        )?;
+        writeln!(
+            self.out,
+            "B: {:02X}, C: {:02X}, BC: {:04X}",
+            b, c, registers.bc
+        )?;
+        writeln!(
+            self.out,
+            "D: {:02X}, E: {:02X}, DE: {:04X}",
+            d, e, registers.de
+        )?;
+        writeln!(
+            self.out,
+            "H: {:02X}, L: {:02X}, HL: {:04X}",
+            h, l, registers.hl
+        )?;
+        writeln!(
+            self.out,
+            "SP: {:04X}, PC: {:04X}",
+            registers.sp, registers.pc
+        )?;
+        writeln!(
+            self.out,
+            "Flags - Zero: {}, AddSubtract: {}, HalfCarry: {}, Carry: {}",
+            f & 0x80 != 0,
+            f & 0x40 != 0,
+            f & 0x20 != 0,
+            f & 0x10 != 0
+        )
+    }
+
+    fn print_current(&mut self) -> io::Result<()> {
+        let pc = match self.query_registers()? {
+            Some(registers) => registers.pc,
+            None => return Ok(()),
+        };
+        match self.wait_for(self.emulator.query_disassembly(pc, 1))? {
+            Ok(disassembly) => match disassembly.instructions.first() {
+                Some(instruction) => writeln!(self.out, "{}", instruction.text),
+                None => writeln!(self.out, "--"),
+            },
+            Err(e) => writeln!(self.err, "{}", e),
+        }
+    }
+
+    fn print_disassembly(&mut self, start: Option<u16>, count: u16) -> io::Result<()> {
+        let start = match start {
+            Some(start) => start,
+            None => match self.query_registers()? {
+                Some(registers) => registers.pc,
+                None => return Ok(()),
+            },
+        };
+        match self.wait_for(self.emulator.query_disassembly(start, count))? {
+            Ok(disassembly) => {
+                for line in disassembly.instructions.iter() {
+                    writeln!(self.out, "{}", format_disassembly_line(line))?;
+                }
+                Ok(())
+            }
+            Err(e) => writeln!(self.err, "{}", e),
+        }
+    }
+
+    fn step(&mut self, steps: u16) -> io::Result<()> {
+        // Only instructions which are stepped through are recorded, as
+        // recording slows down running
+        if self
+            .wait_for(self.emulator.set_history_limit(REVERSE_STEP_LIMIT))?
+            .is_err()
+        {
+            writeln!(self.err, "Could not record steps to undo")?;
+        }
+        for _ in 0..steps {
+            if let Err(e) = self.wait_for(self.emulator.step())? {
+                writeln!(self.err, "{}", e)?;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn reverse_step(&mut self, steps: u16) -> io::Result<()> {
+        for _ in 0..steps {
+            if let Err(e) = self.wait_for(self.emulator.reverse_step())? {
+                writeln!(self.err, "{}", e)?;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_breakpoint(
+        &mut self,
+        target: RWTarget,
+        value: Option<BreakpointValue>,
+    ) -> io::Result<()> {
+        let (breakpoint, description) = match (target, value) {
+            (_, Some(BreakpointValue::Equal(value))) => (
+                Breakpoint::new(
+                    target,
+                    BreakpointCondition::Test(Comparison::Equal, value.into()),
+                ),
+                format!("{} == {:X}", target, value),
+            ),
+            (_, Some(BreakpointValue::Changed)) => (
+                Breakpoint::new(target, BreakpointCondition::Changed),
+                format!("{} Changed", target),
+            ),
+            (RWTarget::Address(_), None) | (RWTarget::BankedAddress(_), None) => (
+                Breakpoint::new(target, BreakpointCondition::Execute),
+                format!("{} Execute", target),
+            ),
+            (_, None) => {
+                return writeln!(self.err, "A value is required to break on {}", target);
+            }
+        };
+        self.send_breakpoint(breakpoint, description)
+    }
+
+    fn add_interrupt_breakpoint(&mut self, interrupt: Interrupt) -> io::Result<()> {
+        let description = format!("{} interrupt", interrupt);
+        self.send_breakpoint(Breakpoint::interrupt(interrupt), description)
+    }
+
+    fn send_breakpoint(&mut self, breakpoint: Breakpoint, description: String) -> io::Result<()> {
+        match self.wait_for(self.emulator.add_breakpoint(breakpoint))? {
+            Ok(_) => writeln!(self.out, "Added breakpoint for {}", description),
+            Err(_) => writeln!(self.err, "Could not add breakpoint for {}", description),
+        }
+    }
+
+    fn run_until(&mut self, target: RWTarget) -> io::Result<()> {
+        match target {
+            RWTarget::Address(_) | RWTarget::BankedAddress(_) => {
+                self.run_to(Breakpoint::new(target, BreakpointCondition::Execute).temporary())
+            }
+            _ => writeln!(
+                self.err,
+                "Can only run until a memory location, not {}",
+                target
+            ),
+        }
+    }
+
+    /// Run at full speed until the given temporary breakpoint is hit
+    fn run_to(&mut self, breakpoint: Breakpoint) -> io::Result<()> {
+        if self
+            .wait_for(self.emulator.add_breakpoint(breakpoint))?
+            .is_err()
+        {
+            return writeln!(self.err, "Could not add breakpoint");
+        }
+        self.set_mode(ExecMode::Uncapped)
+    }
+
+    fn set_mode(&mut self, mode: ExecMode) -> io::Result<()> {
+        if is_running(&mode) && self.wait_for(self.emulator.set_history_limit(0))?.is_err() {
+            writeln!(self.err, "Could not stop recording steps to undo")?;
+        }
+        if self
+            .wait_for(self.emulator.set_mode(mode.clone()))?
+            .is_err()
+        {
+            return writeln!(self.err, "Could not switch to {:?} mode", mode);
+        }
+        match mode {
+            ExecMode::Standard => writeln!(self.out, "Running"),
+            ExecMode::Uncapped => writeln!(self.out, "Running at full speed"),
+            _ => match self.query_registers()? {
+                Some(registers) => writeln!(self.out, "Paused at {:04X}", registers.pc),
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn wait(&mut self) -> io::Result<()> {
+        while is_running(&self.mode.borrow()) {
+            self.queue.handle_next().map_err(emulator_stopped)?;
+        }
+        Ok(())
+    }
+
+    fn debug(&mut self) -> io::Result<()> {
+        loop {
+            self.print_messages()?;
+            let command = match read_command(self.inb, self.out, self.err)? {
+                Input::Command(command) => command,
+                Input::Invalid => continue,
+                Input::Closed => return Ok(()),
+            };
+
+            match command {
+                DebugCommand::Exit => {
+                    writeln!(self.out, "Exiting")?;
+                    break;
+                }
+                DebugCommand::PrintBytes { range } => self.print_bytes(range)?,
+                DebugCommand::PrintRegisters => self.print_registers()?,
+                DebugCommand::Step { steps } => self.step(steps)?,
+                DebugCommand::ReverseStep { steps } => self.reverse_step(steps)?,
+                DebugCommand::Breakpoint { target, value } => {
+                    if let Some(target) = self.resolve_target(&target)? {
+                        self.add_breakpoint(target, value)?
+                    }
+                }
+                DebugCommand::BreakOnInterrupt { interrupt } => {
+                    self.add_interrupt_breakpoint(interrupt)?
+                }
+                DebugCommand::Current => self.print_current()?,
+                DebugCommand::Disassemble { start: None, count } => {
+                    self.print_disassembly(None, count)?
+                }
+                DebugCommand::Disassemble {
+                    start: Some(start),
+                    count,
+                } => {
+                    if let Some(start) = self.resolve_address(&start)? {
+                        self.print_disassembly(Some(start), count)?
+                    }
+                }
+                DebugCommand::LoadSymbols { file } => self.load_symbols(&file)?,
+                DebugCommand::Run => self.set_mode(ExecMode::Standard)?,
+                DebugCommand::FastForward => self.set_mode(ExecMode::Uncapped)?,
+                DebugCommand::Until { target } => {
+                    if let Some(target) = self.resolve_target(&target)? {
+                        self.run_until(target)?
+                    }
+                }
+                DebugCommand::Finish => self.run_to(Breakpoint::function_return().temporary())?,
+                DebugCommand::Pause => self.set_mode(ExecMode::Paused)?,
+                DebugCommand::Wait => self.wait()?,
+                DebugCommand::Dump { .. }
+                | DebugCommand::BreakBefore { .. }
+                | DebugCommand::Load { .. }
+                | DebugCommand::CycleCount
+                | DebugCommand::PpuTiming
+                | DebugCommand::Read { .. }
+                | DebugCommand::Write { .. }
+                | DebugCommand::Patch { .. }
+                | DebugCommand::Unpatch { .. }
+                | DebugCommand::GameGenie { .. }
+                | DebugCommand::Eval { .. }
+                | DebugCommand::Frame { .. }
+                | DebugCommand::Protect { .. }
+                | DebugCommand::Unprotect { .. }
+                | DebugCommand::StackGuard { .. }
+                | DebugCommand::BankHistory { .. }
+                | DebugCommand::Interrupts { .. }
+                | DebugCommand::Serial { .. } => {
+                    writeln!(self.err, "This command is not available with --background")?;
+                }
+            }
+            self.print_messages()?;
+            self.out.flush()?;
+            self.err.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Debug a ROM running on a background thread
+pub(crate) fn debug(
+    rom: Vec<u8>,
+    in_: &mut dyn io::Read,
+    out: &mut dyn io::Write,
+    err: &mut dyn io::Write,
+) -> io::Result<()> {
+    let (channel, commands, output) = mpsc_channel();
+    let thread = EmulatorThread::spawn(commands, output);
+
+    let mut inb = io::BufReader::new(in_);
+    let mut debugger = RemoteDebugger::new(channel, &mut inb, out, err);
+    let result = debugger.load_rom(rom).and_then(|_| debugger.debug());
+    // Closing the command channel stops the emulator thread
+    drop(debugger);
+    if let Err(panic) = thread.join() {
+        std::panic::resume_unwind(panic);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_rom() -> Vec<u8> {
+        let mut data = vec![0u8; 0x8000];
+        data[0x150] = 0x3E; // LD A, 12h
+        data[0x151] = 0x12;
+        data[0x152] = 0x18; // JR -2
+        data[0x153] = 0xFE;
+        data
+    }
+
+    fn run_debug_script(input: &[&str]) -> (Vec<String>, Vec<String>) {
+        let mut captured_output = Vec::new();
+        let mut captured_error = Vec::new();
+        let input = input.join("\n");
+
+        debug(
+            test_rom(),
+            &mut input.as_bytes(),
+            &mut captured_output,
+            &mut captured_error,
+        )
+        .unwrap();
+
+        let output = String::from_utf8_lossy(&captured_output)
+            .lines()
+            .map(String::from)
+            .collect();
+        let errors = String::from_utf8_lossy(&captured_error)
+            .split(crate::debugger::PROMPT)
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect();
+        (output, errors)
+    }
+
+    #[test]
+    fn test_step() {
+        let (output, errors) = run_debug_script(&["s 2", "ci", "pb 0x100:0x103", "rs 3"]);
+
+        assert_eq!(output, vec!["NOP", "0100: 00 00 00 00 "]);
+        assert_eq!(errors, vec!["No earlier state to step back to"]);
+    }
+
+    #[test]
+    fn test_reverse_step_after_running() {
+        let (output, errors) =
+            run_debug_script(&["s", "until 0x150", "wait", "rs", "s", "rs", "ci"]);
+
+        assert_eq!(
+            output,
+            vec![
+                "Running at full speed",
+                "Broke on Breakpoint: memory location [150h] Execute",
+                "LD A, 12h",
+            ]
+        );
+        assert_eq!(errors, vec!["No earlier state to step back to"]);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let (output, errors) = run_debug_script(&["br 0x152", "run", "wait", "disasm 0x150 -n 3"]);
+
+        assert_eq!(
+            output[3..],
+            [
+                "   0150:  3E 12     LD A, 12h",
+                "=> 0152:  18 FE     JR -2h",
+                "   0154:  00        NOP",
+            ]
+        );
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_run_to_breakpoint() {
+        let (output, errors) = run_debug_script(&["br 0x152", "run", "wait", "pr", "pause"]);
+
+        assert_eq!(
+            output,
+            vec![
+                "Added breakpoint for memory location [152h] Execute",
+                "Running",
+                "Broke on Breakpoint: memory location [152h] Execute",
+                "A: 12, F: b0, AF: 12B0",
+                "B: 00, C: 13, BC: 0013",
+                "D: 00, E: D8, DE: 00D8",
+                "H: 01, L: 4D, HL: 014D",
+                "SP: FFFE, PC: 0152",
+                "Flags - Zero: true, AddSubtract: false, HalfCarry: true, Carry: true",
+                "Paused at 0152",
+            ]
+        );
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_until() {
+        let (output, errors) = run_debug_script(&["until 0x152", "wait", "pause"]);
+
+        assert_eq!(
+            output,
+            vec![
+                "Running at full speed",
+                "Broke on Breakpoint: memory location [152h] Execute",
+                "Paused at 0152",
+            ]
+        );
+        assert_eq!(errors, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_unavailable_command() {
+        let (output, errors) = run_debug_script(&["cc"]);
+
+        assert_eq!(output, Vec::<String>::new());
+        assert_eq!(
+            errors,
+            vec!["This command is not available with --background"]
+        );
+    }
+}
@@ -3,7 +3,10 @@
 
 use derive_more::{Display, From, FromStr, Into};
 
+const BANK_SIZE: usize = 0x4000;
+
 #[derive(PartialEq, Eq, Debug, Copy, Clone, From, FromStr, Into, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents a literal memory address
 #[display(fmt = "[{:X}h]", _0)]
 pub struct LiteralAddress(pub u16);
@@ -27,7 +30,102 @@ impl From<HighAddress> for LiteralAddress {
     }
 }
 
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Represents an address within a specific ROM or RAM bank
+///
+/// Addresses in switchable regions (such as 0x4000-0x7FFF) are
+/// ambiguous without knowing which bank is mapped there. Regions that
+/// can't be switched are always considered to be bank 0.
+pub struct BankedAddress {
+    pub bank: u16,
+    pub addr: LiteralAddress,
+}
+
+impl BankedAddress {
+    pub fn new<A: Into<LiteralAddress>>(bank: u16, addr: A) -> BankedAddress {
+        BankedAddress {
+            bank,
+            addr: addr.into(),
+        }
+    }
+
+    /// Find the banked address an offset in a ROM file is mapped to
+    ///
+    /// Bank 0 is mapped to 0x0000-0x3FFF, all other banks are mapped
+    /// to 0x4000-0x7FFF. Returns `None` if the offset is beyond the
+    /// largest possible ROM.
+    pub fn from_rom_offset(offset: usize) -> Option<BankedAddress> {
+        use core::convert::TryFrom;
+        let bank = u16::try_from(offset / BANK_SIZE).ok()?;
+        let bank_offset = offset % BANK_SIZE;
+        let base = if bank == 0 { 0 } else { BANK_SIZE };
+        let addr = u16::try_from(base + bank_offset).ok()?;
+        Some(BankedAddress::new(bank, addr))
+    }
+
+    /// Find the offset in a ROM file this address is mapped from
+    ///
+    /// The inverse of `from_rom_offset`. Returns `None` if the address
+    /// is outside of the region its bank is mapped to.
+    pub fn to_rom_offset(&self) -> Option<usize> {
+        let addr = usize::from(self.addr.0);
+        match self.bank {
+            0 if addr < BANK_SIZE => Some(addr),
+            0 => None,
+            bank if (BANK_SIZE..BANK_SIZE * 2).contains(&addr) => {
+                Some(usize::from(bank) * BANK_SIZE + addr - BANK_SIZE)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for BankedAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:X}:{:04X}", self.bank, self.addr.0)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Display)]
+/// Failure to parse a banked address
+pub enum BankedAddressParseError {
+    #[display(fmt = "Banked addresses must be in the format <bank>:<address>")]
+    MissingSeparator,
+    #[display(fmt = "Invalid bank number")]
+    InvalidBank,
+    #[display(fmt = "Invalid address")]
+    InvalidAddress,
+}
+
+fn parse_hex(src: &str) -> Option<u16> {
+    let lowered = src.trim().to_lowercase();
+    let digits = lowered
+        .strip_prefix("0x")
+        .or_else(|| lowered.strip_suffix('h'))
+        .unwrap_or(&lowered);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+impl core::str::FromStr for BankedAddress {
+    type Err = BankedAddressParseError;
+
+    /// Parses addresses in the `<bank>:<address>` format used by most
+    /// gameboy debuggers, such as `3:4123`. Both parts are hexadecimal.
+    fn from_str(s: &str) -> Result<BankedAddress, BankedAddressParseError> {
+        let mut parts = s.splitn(2, ':');
+        let bank = parts.next().unwrap_or("");
+        let addr = parts
+            .next()
+            .ok_or(BankedAddressParseError::MissingSeparator)?;
+        let bank = parse_hex(bank).ok_or(BankedAddressParseError::InvalidBank)?;
+        let addr = parse_hex(addr).ok_or(BankedAddressParseError::InvalidAddress)?;
+        Ok(BankedAddress::new(bank, addr))
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Copy, Clone, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents an address in high memory (offset from 0xFF00)
 pub struct HighAddress(pub u8);
 
@@ -40,6 +138,7 @@ pub struct OffsetResolveResult {
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents an address that is offset from the program counter
 pub struct AddressOffset(pub i8);
 
@@ -73,16 +172,15 @@ impl AddressOffset {
     #[doc(hidden)]
     #[allow(clippy::branches_sharing_code)]
     pub fn resolve_internal(self, base: LiteralAddress) -> OffsetResolveResult {
-        use core::convert::TryFrom;
         let raw_base = base.0;
         let offset = self.0;
         let (new_addr, half_carry, carry) = if offset < 0 {
-            let to_sub = u16::try_from(offset.abs()).unwrap();
+            let to_sub = u16::from(offset.unsigned_abs());
             let (new, carry) = raw_base.overflowing_sub(to_sub);
             let half_carry = ((raw_base & 0xF) + 0x10) - (to_sub & 0xF) < 0x10;
             (new, half_carry, carry)
         } else {
-            let to_add = u16::try_from(offset.abs()).unwrap();
+            let to_add = u16::from(offset.unsigned_abs());
             let half_add = ((raw_base & 0xF) + (to_add & 0xF)) & 0xF0;
             let (new, carry) = raw_base.overflowing_add(to_add);
             (new, half_add != 0, carry)
@@ -103,6 +201,55 @@ mod test {
         assert_eq!(LiteralAddress::from([0x54, 0x32]), LiteralAddress(0x3254));
     }
 
+    #[test]
+    fn test_banked_address_from_rom_offset() {
+        assert_eq!(
+            BankedAddress::from_rom_offset(0x0150),
+            Some(BankedAddress::new(0, 0x0150))
+        );
+        assert_eq!(
+            BankedAddress::from_rom_offset(0x4123),
+            Some(BankedAddress::new(1, 0x4123))
+        );
+        assert_eq!(
+            BankedAddress::from_rom_offset(0xC123),
+            Some(BankedAddress::new(3, 0x4123))
+        );
+    }
+
+    #[test]
+    fn test_banked_address_to_rom_offset() {
+        assert_eq!(BankedAddress::new(0, 0x0150).to_rom_offset(), Some(0x0150));
+        assert_eq!(BankedAddress::new(3, 0x4123).to_rom_offset(), Some(0xC123));
+        assert_eq!(BankedAddress::new(0, 0x4123).to_rom_offset(), None);
+        assert_eq!(BankedAddress::new(3, 0x0150).to_rom_offset(), None);
+        assert_eq!(BankedAddress::new(3, 0xC123).to_rom_offset(), None);
+    }
+
+    #[test]
+    fn test_banked_address_parse_display() {
+        let addr: BankedAddress = "3:4123".parse().unwrap();
+        assert_eq!(addr, BankedAddress::new(3, 0x4123));
+        assert_eq!(alloc::format!("{}", addr), "3:4123");
+
+        let addr: BankedAddress = "0x1F:7FFFh".parse().unwrap();
+        assert_eq!(addr, BankedAddress::new(0x1F, 0x7FFF));
+        assert_eq!(alloc::format!("{}", addr), "1F:7FFF");
+
+        assert_eq!(
+            "4123".parse::<BankedAddress>(),
+            Err(BankedAddressParseError::MissingSeparator)
+        );
+        assert_eq!(
+            "x:4123".parse::<BankedAddress>(),
+            Err(BankedAddressParseError::InvalidBank)
+        );
+        assert_eq!(
+            "3:".parse::<BankedAddress>(),
+            Err(BankedAddressParseError::InvalidAddress)
+        );
+    }
+
     #[test]
     fn test_resolve_address_postive_offset() {
         let positive_offset = AddressOffset(0x2C);
@@ -188,5 +335,14 @@ mod test {
                 half_carry: true,
             }
         );
+
+        assert_eq!(
+            AddressOffset(-0x80).resolve_internal(0x1000.into()),
+            OffsetResolveResult {
+                addr: 0x0F80.into(),
+                carry: false,
+                half_carry: false,
+            }
+        );
     }
 }
@@ -97,6 +97,7 @@ impl AppendableParam for address::HighAddress {
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Checks for conditional instructions
 pub enum Condition {
     /// The Zero flag is not set
@@ -131,6 +132,7 @@ impl EmbeddableParam for Condition {
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Whether to include the Carry bit in operations.
 /// This does not affect setting the carry bit, only
 /// reading it.
@@ -157,6 +159,7 @@ impl EmbeddableParam for Carry {
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Direction to rotate/shift operand
 pub enum RotateDirection {
     Left,
@@ -181,6 +184,7 @@ impl EmbeddableParam for RotateDirection {
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Whether an instruction should increment or decrement
 /// its operand.
 pub enum Increment {
@@ -206,6 +210,7 @@ impl EmbeddableParam for Increment {
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// All supported ALU operations
 pub enum ALOp {
     Add,
@@ -251,6 +256,7 @@ impl EmbeddableParam for ALOp {
 /// register (excluding F) or the memory address
 /// referenced by the HL register.
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ByteRegisterTarget {
     A,
     B,
@@ -292,6 +298,7 @@ impl EmbeddableParam for ByteRegisterTarget {
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// 16bit Register group that includes the accumalator
 /// register.
 ///
@@ -328,6 +335,7 @@ impl EmbeddableParam for AccRegister {
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Registers group that includes the stack register
 ///
 /// This is mainly used for operations that do not operate on the stack,
@@ -390,6 +398,7 @@ pub enum AppendedParam {
 }
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ByteRegisterOffset(pub(crate) registers::ByteRegister);
 
 #[derive(PartialEq, Eq, Debug, Copy, Clone)]
@@ -9,6 +9,7 @@ use alloc::string::String;
 pub struct RegisterParseError(String);
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// All 8-bit registers
 pub enum ByteRegister {
     A,
@@ -40,6 +41,7 @@ impl core::str::FromStr for ByteRegister {
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// All 16-bit registers
 pub enum WordRegister {
     AF,
@@ -107,6 +109,7 @@ impl From<StackRegister> for WordRegister {
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WordByte {
     High,
     Low,
@@ -165,7 +168,8 @@ impl TryFrom<ByteRegisterTarget> for ByteRegister {
 ///
 /// Note that many instructions leave flags alone,
 /// and others may repurpose them for side channel information.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Flag {
     /// The last arithmetic operation resulted in 0
     Zero,
@@ -0,0 +1,238 @@
+//! Drawing frames to a screen
+
+/// A screen that frames are drawn to
+pub trait FrameDisplay {
+    type Error;
+
+    /// Draw a frame
+    ///
+    /// `screen` has `SCREEN_WIDTH * SCREEN_HEIGHT` shades from the engine's
+    /// `harness` module, row by row from the top left. Each shade is from
+    /// 0 (white) to 3 (black).
+    fn draw_frame(&mut self, screen: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// The colour shown for each shade, from white to black
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette<C>(pub [C; 4]);
+
+impl<C: Copy> Palette<C> {
+    /// The colour for a shade from 0 (white) to 3 (black)
+    pub fn color(&self, shade: u8) -> C {
+        self.0[usize::from(shade & 0x3)]
+    }
+}
+
+#[cfg(feature = "embedded-graphics")]
+mod graphics {
+    use super::{FrameDisplay, Palette};
+    use embedded_graphics_core::draw_target::DrawTarget;
+    use embedded_graphics_core::geometry::{Point, Size};
+    use embedded_graphics_core::pixelcolor::{BinaryColor, Gray2, GrayColor, Rgb565, RgbColor};
+    use embedded_graphics_core::primitives::Rectangle;
+    use olympia_engine::harness::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    /// The area of the display a frame covers, with its top left corner at `origin`
+    fn frame_area(origin: Point) -> Rectangle {
+        Rectangle::new(origin, Size::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32))
+    }
+
+    /// Draws frames to an `embedded-graphics` draw target, such as a display driver
+    ///
+    /// Each frame is sent with a single `fill_contiguous` call, which most
+    /// drivers turn into one transfer to the display. Displays smaller than
+    /// 160x144 show the part of the frame that fits.
+    pub struct GraphicsDisplay<T: DrawTarget> {
+        target: T,
+        palette: Palette<T::Color>,
+        origin: Point,
+    }
+
+    impl<T: DrawTarget> GraphicsDisplay<T> {
+        /// Draw frames to the top left of `target`
+        pub fn new(target: T, palette: Palette<T::Color>) -> GraphicsDisplay<T> {
+            GraphicsDisplay::with_origin(target, palette, Point::zero())
+        }
+
+        /// Draw frames with their top left corner at `origin`, to center them on larger displays
+        pub fn with_origin(
+            target: T,
+            palette: Palette<T::Color>,
+            origin: Point,
+        ) -> GraphicsDisplay<T> {
+            GraphicsDisplay {
+                target,
+                palette,
+                origin,
+            }
+        }
+
+        pub fn target(&self) -> &T {
+            &self.target
+        }
+
+        pub fn target_mut(&mut self) -> &mut T {
+            &mut self.target
+        }
+
+        pub fn into_target(self) -> T {
+            self.target
+        }
+    }
+
+    impl<T: DrawTarget> FrameDisplay for GraphicsDisplay<T> {
+        type Error = T::Error;
+
+        fn draw_frame(&mut self, screen: &[u8]) -> Result<(), T::Error> {
+            let palette = self.palette;
+            let colors = screen.iter().map(|shade| palette.color(*shade));
+            self.target
+                .fill_contiguous(&frame_area(self.origin), colors)
+        }
+    }
+
+    impl Default for Palette<Gray2> {
+        fn default() -> Self {
+            Palette([Gray2::WHITE, Gray2::new(2), Gray2::new(1), Gray2::BLACK])
+        }
+    }
+
+    impl Default for Palette<Rgb565> {
+        fn default() -> Self {
+            Palette([
+                Rgb565::WHITE,
+                Rgb565::new(21, 42, 21),
+                Rgb565::new(10, 21, 10),
+                Rgb565::BLACK,
+            ])
+        }
+    }
+
+    /// For monochrome displays, showing the two lightest shades as off
+    impl Default for Palette<BinaryColor> {
+        fn default() -> Self {
+            Palette([
+                BinaryColor::Off,
+                BinaryColor::Off,
+                BinaryColor::On,
+                BinaryColor::On,
+            ])
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use embedded_graphics_core::geometry::{Dimensions, OriginDimensions};
+        use embedded_graphics_core::Pixel;
+
+        /// A display that keeps the pixels drawn to it
+        struct MemoryDisplay {
+            width: usize,
+            height: usize,
+            pixels: Vec<Option<BinaryColor>>,
+        }
+
+        impl MemoryDisplay {
+            fn new(width: usize, height: usize) -> MemoryDisplay {
+                MemoryDisplay {
+                    width,
+                    height,
+                    pixels: vec![None; width * height],
+                }
+            }
+
+            fn pixel(&self, x: usize, y: usize) -> Option<BinaryColor> {
+                self.pixels[y * self.width + x]
+            }
+        }
+
+        impl OriginDimensions for MemoryDisplay {
+            fn size(&self) -> Size {
+                Size::new(self.width as u32, self.height as u32)
+            }
+        }
+
+        impl DrawTarget for MemoryDisplay {
+            type Color = BinaryColor;
+            type Error = ();
+
+            fn draw_iter<I>(&mut self, pixels: I) -> Result<(), ()>
+            where
+                I: IntoIterator<Item = Pixel<BinaryColor>>,
+            {
+                for Pixel(point, color) in pixels {
+                    if self.bounding_box().contains(point) {
+                        self.pixels[point.y as usize * self.width + point.x as usize] = Some(color);
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        /// A frame with the given shade in the top left pixel, and white elsewhere
+        fn frame(corner: u8) -> Vec<u8> {
+            let mut screen = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+            screen[0] = corner;
+            screen
+        }
+
+        #[test]
+        fn test_draw_frame() {
+            let target = MemoryDisplay::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+            let mut display = GraphicsDisplay::new(target, Palette::default());
+
+            display.draw_frame(&frame(3)).unwrap();
+
+            let target = display.into_target();
+            assert_eq!(target.pixel(0, 0), Some(BinaryColor::On));
+            assert_eq!(target.pixel(1, 0), Some(BinaryColor::Off));
+            assert!(target.pixels.iter().all(Option::is_some));
+        }
+
+        #[test]
+        fn test_draw_frame_at_origin() {
+            let target = MemoryDisplay::new(240, 240);
+            let mut display =
+                GraphicsDisplay::with_origin(target, Palette::default(), Point::new(40, 48));
+
+            display.draw_frame(&frame(2)).unwrap();
+
+            let target = display.target();
+            assert_eq!(target.pixel(39, 47), None);
+            assert_eq!(target.pixel(40, 48), Some(BinaryColor::On));
+            assert_eq!(target.pixel(41, 48), Some(BinaryColor::Off));
+            assert_eq!(target.pixel(199, 191), Some(BinaryColor::Off));
+            assert_eq!(target.pixel(200, 192), None);
+        }
+
+        #[test]
+        fn test_draw_frame_on_small_display() {
+            let target = MemoryDisplay::new(128, 64);
+            let mut display = GraphicsDisplay::new(target, Palette::default());
+
+            display.draw_frame(&frame(3)).unwrap();
+
+            let target = display.target();
+            assert_eq!(target.pixel(0, 0), Some(BinaryColor::On));
+            assert!(target.pixels.iter().all(Option::is_some));
+        }
+    }
+}
+
+#[cfg(feature = "embedded-graphics")]
+pub use graphics::GraphicsDisplay;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_color() {
+        let palette = Palette(['w', 'l', 'd', 'b']);
+        assert_eq!(palette.color(0), 'w');
+        assert_eq!(palette.color(3), 'b');
+        // Only the shade bits are used
+        assert_eq!(palette.color(0x82), 'd');
+    }
+}
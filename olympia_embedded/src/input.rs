@@ -0,0 +1,144 @@
+//! Reading the buttons held down by the player
+
+use olympia_engine::gameboy::Button;
+
+/// Buttons that the player holds down
+pub trait ButtonInput {
+    type Error;
+
+    /// Whether `button` is held down right now
+    fn is_pressed(&mut self, button: Button) -> Result<bool, Self::Error>;
+}
+
+/// The level a GPIO pin is at while its button is pressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Active {
+    /// The button pulls the pin high, which needs a pull-down resistor
+    High,
+    /// The button pulls the pin low, which needs a pull-up resistor.
+    /// This is the usual wiring for buttons.
+    Low,
+}
+
+/// A GPIO pin for each button
+///
+/// HALs usually have a type for pins whose number is only known at runtime,
+/// so that all buttons can share a pin type.
+#[derive(Debug)]
+pub struct ButtonPins<P> {
+    pub right: P,
+    pub left: P,
+    pub up: P,
+    pub down: P,
+    pub a: P,
+    pub b: P,
+    pub select: P,
+    pub start: P,
+}
+
+impl<P> ButtonPins<P> {
+    pub fn pin_mut(&mut self, button: Button) -> &mut P {
+        match button {
+            Button::Right => &mut self.right,
+            Button::Left => &mut self.left,
+            Button::Up => &mut self.up,
+            Button::Down => &mut self.down,
+            Button::A => &mut self.a,
+            Button::B => &mut self.b,
+            Button::Select => &mut self.select,
+            Button::Start => &mut self.start,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+mod gpio {
+    use super::{Active, ButtonInput, ButtonPins};
+    use embedded_hal::digital::InputPin;
+    use olympia_engine::gameboy::Button;
+
+    /// Reads buttons wired to `embedded-hal` GPIO input pins
+    ///
+    /// Pins are read directly, without debouncing, as the games themselves
+    /// only check buttons once a frame.
+    pub struct GpioButtons<P> {
+        pins: ButtonPins<P>,
+        active: Active,
+    }
+
+    impl<P: InputPin> GpioButtons<P> {
+        pub fn new(pins: ButtonPins<P>, active: Active) -> GpioButtons<P> {
+            GpioButtons { pins, active }
+        }
+
+        pub fn into_pins(self) -> ButtonPins<P> {
+            self.pins
+        }
+    }
+
+    impl<P: InputPin> ButtonInput for GpioButtons<P> {
+        type Error = P::Error;
+
+        fn is_pressed(&mut self, button: Button) -> Result<bool, P::Error> {
+            let pin = self.pins.pin_mut(button);
+            match self.active {
+                Active::High => pin.is_high(),
+                Active::Low => pin.is_low(),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use core::convert::Infallible;
+        use embedded_hal::digital::ErrorType;
+
+        struct FixedPin(bool);
+
+        impl ErrorType for FixedPin {
+            type Error = Infallible;
+        }
+
+        impl InputPin for FixedPin {
+            fn is_high(&mut self) -> Result<bool, Infallible> {
+                Ok(self.0)
+            }
+
+            fn is_low(&mut self) -> Result<bool, Infallible> {
+                Ok(!self.0)
+            }
+        }
+
+        /// Pins with only the start button's pin high
+        fn start_high() -> ButtonPins<FixedPin> {
+            ButtonPins {
+                right: FixedPin(false),
+                left: FixedPin(false),
+                up: FixedPin(false),
+                down: FixedPin(false),
+                a: FixedPin(false),
+                b: FixedPin(false),
+                select: FixedPin(false),
+                start: FixedPin(true),
+            }
+        }
+
+        #[test]
+        fn test_active_high() {
+            let mut buttons = GpioButtons::new(start_high(), Active::High);
+            assert_eq!(buttons.is_pressed(Button::Start), Ok(true));
+            assert_eq!(buttons.is_pressed(Button::Select), Ok(false));
+        }
+
+        #[test]
+        fn test_active_low() {
+            let mut buttons = GpioButtons::new(start_high(), Active::Low);
+            assert_eq!(buttons.is_pressed(Button::Start), Ok(false));
+            assert_eq!(buttons.is_pressed(Button::Select), Ok(true));
+        }
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+pub use gpio::GpioButtons;
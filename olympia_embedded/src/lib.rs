@@ -0,0 +1,212 @@
+#![cfg_attr(not(test), no_std)]
+//! A reference frontend for running olympia on microcontrollers
+//!
+//! This runs `olympia_engine` built without default features, so only the
+//! emulation core is included, and keeps the engine's `no_std` support
+//! honest. Frames are drawn to a [`FrameDisplay`], and buttons are read from a
+//! [`ButtonInput`] before each frame.
+//!
+//! The following features are enabled by default:
+//!
+//! * `embedded-graphics` - Adds [`display::GraphicsDisplay`], which draws frames to any
+//!   `embedded-graphics` draw target, such as an SPI LCD or OLED driver.
+//! * `embedded-hal` - Adds [`input::GpioButtons`], which reads buttons from `embedded-hal`
+//!   GPIO input pins.
+//!
+//! The engine needs `alloc`, so the firmware must set a global allocator with
+//! room for the ROM, cartridge RAM and the emulator itself. Emulating the
+//! original Game Boy needs around 120KiB plus the size of the ROM.
+//!
+//! ```ignore
+//! let display = GraphicsDisplay::new(lcd, Palette::default());
+//! let buttons = GpioButtons::new(pins, Active::Low);
+//! let rom = include_bytes!("game.gb").to_vec();
+//! let mut frontend = Frontend::new(rom, GameBoyModel::GameBoy, display, buttons).unwrap();
+//! loop {
+//!     frontend.run_frame().unwrap();
+//! }
+//! ```
+//!
+//! [`FrameDisplay`]: display/trait.FrameDisplay.html
+//! [`ButtonInput`]: input/trait.ButtonInput.html
+//! [`display::GraphicsDisplay`]: display/struct.GraphicsDisplay.html
+//! [`input::GpioButtons`]: input/struct.GpioButtons.html
+
+extern crate alloc;
+
+pub mod display;
+pub mod input;
+
+use display::FrameDisplay;
+use input::ButtonInput;
+
+use alloc::vec::Vec;
+use derive_more::Display;
+use olympia_engine::gameboy::{Button, GameBoyModel, StepError};
+use olympia_engine::harness::Harness;
+use olympia_engine::rom::CartridgeLoadError;
+
+#[derive(Debug, Display, PartialEq, Eq)]
+/// Error running a frame
+pub enum FrontendError<D, I> {
+    /// The emulator stopped with an error
+    #[display(fmt = "Emulation failed: {}", _0)]
+    Emulation(StepError),
+    /// The frame couldn't be drawn
+    #[display(fmt = "Could not draw frame: {:?}", _0)]
+    Display(D),
+    /// The buttons couldn't be read
+    #[display(fmt = "Could not read buttons: {:?}", _0)]
+    Input(I),
+}
+
+/// Runs the emulator a frame at a time, on the calling thread
+pub struct Frontend<D, I> {
+    harness: Harness,
+    display: D,
+    input: I,
+}
+
+impl<D: FrameDisplay, I: ButtonInput> Frontend<D, I> {
+    /// Create a frontend running the given ROM
+    pub fn new(
+        rom: Vec<u8>,
+        model: GameBoyModel,
+        display: D,
+        input: I,
+    ) -> Result<Frontend<D, I>, CartridgeLoadError> {
+        Ok(Frontend {
+            harness: Harness::new(rom, model)?,
+            display,
+            input,
+        })
+    }
+
+    /// Read the buttons, run until the next frame is drawn, then show it
+    ///
+    /// This doesn't wait between frames, so call it from a timer interrupt or
+    /// loop with a delay to run at the gameboy's speed of about 60 frames a second.
+    pub fn run_frame(&mut self) -> Result<(), FrontendError<D::Error, I::Error>> {
+        for button in Button::ALL.iter() {
+            let pressed = self
+                .input
+                .is_pressed(*button)
+                .map_err(FrontendError::Input)?;
+            self.harness.gameboy_mut().set_button(*button, pressed);
+        }
+        self.harness.run_frame().map_err(FrontendError::Emulation)?;
+        self.display
+            .draw_frame(self.harness.screen())
+            .map_err(FrontendError::Display)
+    }
+
+    /// The emulator, for saving cartridge RAM or reading its state
+    pub fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    /// The emulator, for changing its state directly
+    pub fn harness_mut(&mut self) -> &mut Harness {
+        &mut self.harness
+    }
+
+    /// The display and button input, for reuse once the frontend is no longer needed
+    pub fn into_parts(self) -> (D, I) {
+        (self.display, self.input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use olympia_engine::harness::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    /// P1, which reads the joypad
+    const JOYPAD_ADDR: u16 = 0xFF00;
+
+    #[derive(Default)]
+    struct RecordingDisplay {
+        frames: Vec<Vec<u8>>,
+    }
+
+    impl FrameDisplay for RecordingDisplay {
+        type Error = ();
+
+        fn draw_frame(&mut self, screen: &[u8]) -> Result<(), ()> {
+            self.frames.push(screen.to_vec());
+            Ok(())
+        }
+    }
+
+    struct HeldButtons(&'static [Button]);
+
+    impl ButtonInput for HeldButtons {
+        type Error = ();
+
+        fn is_pressed(&mut self, button: Button) -> Result<bool, ()> {
+            Ok(self.0.contains(&button))
+        }
+    }
+
+    struct BrokenInput;
+
+    impl ButtonInput for BrokenInput {
+        type Error = &'static str;
+
+        fn is_pressed(&mut self, _button: Button) -> Result<bool, &'static str> {
+            Err("disconnected")
+        }
+    }
+
+    fn blank_rom() -> Vec<u8> {
+        vec![0; 0x8000]
+    }
+
+    #[test]
+    fn test_frame_drawn() {
+        let display = RecordingDisplay::default();
+        let mut frontend = Frontend::new(
+            blank_rom(),
+            GameBoyModel::GameBoy,
+            display,
+            HeldButtons(&[]),
+        )
+        .unwrap();
+
+        frontend.run_frame().unwrap();
+        frontend.run_frame().unwrap();
+
+        assert_eq!(frontend.harness().frame_count(), 2);
+        let (display, _) = frontend.into_parts();
+        assert_eq!(display.frames.len(), 2);
+        assert_eq!(display.frames[1].len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    }
+
+    #[test]
+    fn test_buttons_read() {
+        let input = HeldButtons(&[Button::Start]);
+        let display = RecordingDisplay::default();
+        let mut frontend =
+            Frontend::new(blank_rom(), GameBoyModel::GameBoy, display, input).unwrap();
+
+        frontend.run_frame().unwrap();
+
+        let gb = frontend.harness_mut().gameboy_mut();
+        // Select the action buttons, where start is the highest line
+        gb.set_memory_u8(JOYPAD_ADDR, 0x10).unwrap();
+        assert_eq!(gb.get_memory_u8(JOYPAD_ADDR).unwrap() & 0x0F, 0x07);
+    }
+
+    #[test]
+    fn test_input_error() {
+        let display = RecordingDisplay::default();
+        let mut frontend =
+            Frontend::new(blank_rom(), GameBoyModel::GameBoy, display, BrokenInput).unwrap();
+
+        assert_eq!(
+            frontend.run_frame(),
+            Err(FrontendError::Input("disconnected"))
+        );
+        assert_eq!(frontend.harness().frame_count(), 0);
+    }
+}
@@ -0,0 +1,35 @@
+//! Fixtures shared by the benchmarks
+
+use olympia_engine::gameboy::{GameBoy, GameBoyModel};
+use olympia_engine::rom::Cartridge;
+
+const PROGRAM_START: usize = 0x150;
+
+/// A mix of common base and extended instructions
+pub const MIXED: &[u8] = &[
+    0x3C, // INC A
+    0x80, // ADD A, B
+    0x21, 0x00, 0xC0, // LD HL, C000h
+    0x77, // LD (HL), A
+    0xCB, 0x47, // BIT 0, A
+    0xCB, 0x37, // SWAP A
+    0xCB, 0x86, // RES 0, (HL)
+    0x05, // DEC B
+];
+
+/// A program that runs `body` in an endless loop
+pub fn looped(body: &[u8]) -> Vec<u8> {
+    let mut program = body.to_vec();
+    let jump_back = -(body.len() as i8 + 2);
+    program.extend_from_slice(&[0x18, jump_back as u8]); // JR to start
+    program
+}
+
+/// A gameboy which jumps to `program` after the cartridge header
+pub fn make_gameboy(program: &[u8]) -> GameBoy {
+    let mut data = vec![0u8; 0x8000];
+    data[0x100..0x103].copy_from_slice(&[0xC3, 0x50, 0x01]); // JP 150h
+    data[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(program);
+    let cartridge = Cartridge::from_data(data).unwrap();
+    GameBoy::new(cartridge, GameBoyModel::GameBoy)
+}
@@ -0,0 +1,103 @@
+//! Measures throughput of whole frames, breakpoint checks and the decoder
+//!
+//! Run with `cargo bench -p olympia_engine`. Step throughput for individual
+//! instruction mixes is measured in `benches/step.rs`.
+
+mod common;
+
+use common::{looped, make_gameboy, MIXED};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use olympia_engine::events::Event;
+use olympia_engine::gameboy::GameBoy;
+use olympia_engine::instructionsn::RuntimeDecoder;
+use olympia_engine::monitor::{
+    Breakpoint, BreakpointCondition, Comparison, DebugMonitor, RWTarget,
+};
+use olympia_engine::registers::WordRegister;
+use olympia_engine::scheduler::CLOCKS_PER_FRAME;
+use std::sync::{Arc, Mutex};
+
+const LCD_CONTROL_ADDR: u16 = 0xFF40;
+/// Breakpoints of each kind set by the breakpoint benchmark
+const BREAKPOINTS: u16 = 100;
+
+/// Step for as many clocks as a frame takes to draw
+fn run_frame(gb: &mut GameBoy) {
+    let start_clocks = gb.clocks_elapsed();
+    while gb.clocks_elapsed() - start_clocks < CLOCKS_PER_FRAME {
+        gb.step().unwrap();
+    }
+}
+
+/// Emulate frames with the LCD on and off, which shows the cost of the PPU
+fn frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame");
+    group.throughput(Throughput::Elements(1));
+    for (name, lcd_control) in [("bg + sprites", 0x93), ("lcd off", 0x00)].iter() {
+        let mut gb = make_gameboy(&looped(MIXED));
+        gb.set_memory_u8(LCD_CONTROL_ADDR, *lcd_control).unwrap();
+        group.bench_function(*name, |b| b.iter(|| run_frame(&mut gb)));
+    }
+    group.finish();
+}
+
+/// Step while checking breakpoints that are never hit, as in fast forward
+fn breakpoints(c: &mut Criterion) {
+    let mut gb = make_gameboy(&looped(MIXED));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let handler_events = events.clone();
+    gb.events.on(Box::new(move |evt: &Event| {
+        handler_events.lock().unwrap().push(evt.clone())
+    }));
+    let mut monitor = DebugMonitor::new();
+    for i in 0..BREAKPOINTS {
+        let addr = 0xD000 + i;
+        monitor
+            .add_breakpoint(Breakpoint::new(
+                RWTarget::Address(addr.into()),
+                BreakpointCondition::Write,
+            ))
+            .unwrap();
+        monitor
+            .add_breakpoint(Breakpoint::new(
+                RWTarget::Address((0x4000 + i).into()),
+                BreakpointCondition::Execute,
+            ))
+            .unwrap();
+        monitor
+            .add_breakpoint(Breakpoint::new(
+                RWTarget::WordRegister(WordRegister::SP),
+                BreakpointCondition::Test(Comparison::Equal, u64::from(addr)),
+            ))
+            .unwrap();
+    }
+    let mut group = c.benchmark_group("step");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("300 breakpoints", |b| {
+        b.iter(|| {
+            gb.step().unwrap();
+            for evt in events.lock().unwrap().drain(..) {
+                monitor.handle_event(&evt);
+            }
+            monitor.poll(&gb);
+        })
+    });
+    group.finish();
+}
+
+fn decoder(c: &mut Criterion) {
+    let decoder = RuntimeDecoder::new();
+    let mut group = c.benchmark_group("decoder");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("mixed", |b| {
+        let mut iter = MIXED.iter().copied().cycle();
+        b.iter(|| {
+            let value = iter.next().unwrap();
+            decoder.decode_from_iter(value, &mut iter).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, frame, breakpoints, decoder);
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+//! Measures how many instructions per second `GameBoy::step` can run
+//!
+//! Run with `cargo bench -p olympia_engine`. Criterion reports the rate in
+//! steps per second, and compares it against the last run on the same machine.
+
+mod common;
+
+use common::{looped, make_gameboy, MIXED};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+/// Arithmetic and logic on registers
+const ALU_MIX: &[u8] = &[
+    0x3C, // INC A
+    0x80, // ADD A, B
+    0x91, // SUB C
+    0xAA, // XOR D
+    0xA3, // AND E
+    0xB4, // OR H
+    0xBD, // CP L
+    0x05, // DEC B
+];
+
+/// Loads, stores and stack operations
+const MEMORY_MIX: &[u8] = &[
+    0x21, 0x00, 0xC0, // LD HL, C000h
+    0x11, 0x00, 0xC1, // LD DE, C100h
+    0x22, // LD (HL+), A
+    0x3A, // LD A, (HL-)
+    0x12, // LD (DE), A
+    0xC5, // PUSH BC
+    0xC1, // POP BC
+];
+
+/// CB prefixed bit operations
+const EXTENDED_MIX: &[u8] = &[
+    0x21, 0x00, 0xC0, // LD HL, C000h
+    0xCB, 0x47, // BIT 0, A
+    0xCB, 0x37, // SWAP A
+    0xCB, 0x86, // RES 0, (HL)
+    0xCB, 0xF8, // SET 7, B
+    0xCB, 0x11, // RL C
+    0xCB, 0x3A, // SRL D
+];
+
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    group.throughput(Throughput::Elements(1));
+    let mixes = [
+        ("instruction mix", MIXED),
+        ("alu", ALU_MIX),
+        ("memory", MEMORY_MIX),
+        ("extended", EXTENDED_MIX),
+    ];
+    for (name, body) in mixes.iter() {
+        let mut gb = make_gameboy(&looped(body));
+        group.bench_function(*name, |b| b.iter(|| gb.step().unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, step);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! Runs a ROM using only the emulation core
+//!
+//! Build with `cargo build -p olympia_engine --release --no-default-features --example minimal`
+//! to see roughly how much the engine adds to a microcontroller frontend.
+//! CI checks the size of this binary, so that debugging machinery doesn't
+//! creep back into minimal builds.
+//!
+//! Usage: `minimal [ROM] [FRAMES]`. Without a ROM, a blank cartridge is run.
+
+use olympia_engine::gameboy::GameBoyModel;
+use olympia_engine::harness::Harness;
+
+use std::env;
+use std::fs;
+use std::process;
+
+const DEFAULT_FRAMES: u64 = 60;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let rom = match args.next() {
+        Some(path) => fs::read(&path).unwrap_or_else(|err| {
+            eprintln!("Could not read {}: {}", path, err);
+            process::exit(1);
+        }),
+        None => vec![0; 0x8000],
+    };
+    let frames = args
+        .next()
+        .and_then(|frames| frames.parse().ok())
+        .unwrap_or(DEFAULT_FRAMES);
+
+    let mut harness = Harness::new(rom, GameBoyModel::GameBoy).unwrap_or_else(|err| {
+        eprintln!("Could not load ROM: {:?}", err);
+        process::exit(1);
+    });
+    for _ in 0..frames {
+        if let Err(err) = harness.run_frame() {
+            eprintln!("Emulation failed: {:?}", err);
+            process::exit(1);
+        }
+    }
+    println!(
+        "{} frames, frame hash {:016x}",
+        harness.frame_count(),
+        harness.gameboy().frame_hash()
+    );
+}
@@ -0,0 +1,86 @@
+//! Capture the bytes a ROM sends over the serial port
+//!
+//! Test ROMs such as blargg's report their results by sending text over
+//! the serial port, and homebrew can use it for printf style debugging.
+//! Connect a [`SerialConsole`] with [`GameBoy::connect_serial`], then
+//! listen for [`SerialOutputEvent`]s to receive each byte sent.
+//!
+//! [`SerialConsole`]: struct.SerialConsole.html
+//! [`GameBoy::connect_serial`]: ../gameboy/struct.GameBoy.html#method.connect_serial
+//! [`SerialOutputEvent`]: ../events/struct.SerialOutputEvent.html
+
+use crate::events::{SerialEvent, SerialOutputEvent};
+use crate::gameboy::SerialDevice;
+
+/// The byte received when nothing is sending, as the line is pulled high
+const IDLE_LINE: u8 = 0xFF;
+
+/// A serial device which records each byte sent to it
+///
+/// Nothing is sent back, so the ROM receives the same bytes as with
+/// nothing connected. Only transfers the ROM clocks itself are captured.
+#[derive(Debug, Default)]
+pub struct SerialConsole {
+    pending_event: Option<SerialEvent>,
+}
+
+impl SerialConsole {
+    pub fn new() -> SerialConsole {
+        SerialConsole::default()
+    }
+}
+
+impl SerialDevice for SerialConsole {
+    fn transfer(&mut self, value: u8) -> u8 {
+        self.pending_event = Some(SerialOutputEvent::new(value).into());
+        IDLE_LINE
+    }
+
+    fn take_event(&mut self) -> Option<SerialEvent> {
+        self.pending_event.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{Event, EventHandler};
+    use crate::registers::{ByteRegister, WordRegister};
+    use crate::shared::{Lock, Shared};
+    use crate::testing::{self, Program};
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    #[test]
+    #[cfg(feature = "events")]
+    fn test_console_captures_output() {
+        let mut gb = Program::new(&[
+            0x3E, b'o', // LD A, 'o'
+            0xE0, 0x01, // LDH (SB), A
+            0x3E, 0x81, // LD A, 81h
+            0xE0, 0x02, // LDH (SC), A
+            0xF0, 0x02, // LDH A, (SC)
+            0x17, // RLA
+            0x38, 0xFB, // JR C, -5
+            0xF0, 0x01, // LDH A, (SB)
+        ])
+        .load();
+        gb.connect_serial(SerialConsole::new());
+        let output: Shared<Lock<Vec<u8>>> = Shared::new(Lock::new(Vec::new()));
+        let handler_output = Shared::clone(&output);
+        let handler: EventHandler<Event> = Box::new(move |evt| {
+            if let Event::SerialOutput(evt) = evt {
+                handler_output.borrow_mut().push(evt.value);
+            }
+        });
+        gb.events.on(handler);
+
+        let end = testing::PROGRAM_START + 15;
+        while gb.read_register_u16(WordRegister::PC) != end {
+            gb.step().unwrap();
+        }
+
+        assert_eq!(*output.borrow(), vec![b'o']);
+        testing::assert_byte_register(&gb, ByteRegister::A, IDLE_LINE);
+    }
+}
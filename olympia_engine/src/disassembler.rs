@@ -1,5 +1,23 @@
+mod analysis;
+mod lint;
+mod rom_map;
+
+use crate::address::BankedAddress;
+use crate::gameboy::GameBoy;
 use crate::instructionsn::RuntimeDecoder;
-use alloc::string::String;
+use crate::registers::WordRegister;
+use alloc::{collections::BTreeSet, string::String, vec::Vec};
+
+pub use analysis::{CodeAnalysis, DataKind, DataRegion, Reference, ReferenceKind};
+pub use lint::{lint, Lint, LintKind};
+pub use rom_map::{rom_map, BankMap, Coverage, FreeBlock, InterruptHandler, RomMap};
+
+/// Column the cross reference comments in a listing start at
+const LISTING_COMMENT_COLUMN: usize = 40;
+/// Most characters of text in each `.db` line of a listing
+const LISTING_TEXT_WIDTH: usize = 32;
+/// Most bytes of graphics in each `.db` line of a listing
+const LISTING_DATA_WIDTH: usize = 8;
 
 /// Format to print disassembly in
 #[derive(Debug, PartialEq, Eq)]
@@ -24,7 +42,13 @@ pub struct DisassemblyIterator<T: Iterator<Item = u8>> {
     next_addr: usize,
     addr: usize,
     source_iterator: T,
-    decoder: RuntimeDecoder,
+    decoder: &'static RuntimeDecoder,
+    banked: bool,
+    labels: BTreeSet<usize>,
+    /// Instruction to emit after the label line for its address
+    pending: Option<String>,
+    /// Bytes read for an instruction that didn't fit, to decode again, last first
+    leftover: Vec<u8>,
 }
 
 impl<T: Iterator<Item = u8>> DisassemblyIterator<T> {
@@ -39,7 +63,42 @@ impl<T: Iterator<Item = u8>> DisassemblyIterator<T> {
             source_iterator,
             next_addr: initial_offset,
             addr: initial_offset,
-            decoder: RuntimeDecoder::new(),
+            decoder: RuntimeDecoder::shared(),
+            banked: false,
+            labels: BTreeSet::new(),
+            pending: None,
+            leftover: Vec::new(),
+        }
+    }
+
+    /// Print addresses as `bank:address` pairs rather than ROM offsets
+    ///
+    /// This should be used when the source is a complete ROM,
+    /// starting at offset 0.
+    pub fn banked(mut self, banked: bool) -> Self {
+        self.banked = banked;
+        self
+    }
+
+    /// Print a label before each of these addresses, and use it in place
+    /// of the address in instructions which jump to or call it
+    ///
+    /// Use [`jump_targets`] to find the addresses to label.
+    ///
+    /// [`jump_targets`]: ./fn.jump_targets.html
+    pub fn labels(mut self, labels: BTreeSet<usize>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        self.leftover.pop().or_else(|| self.source_iterator.next())
+    }
+
+    fn format_addr(&self, addr: usize) -> String {
+        match BankedAddress::from_rom_offset(addr) {
+            Some(banked_addr) if self.banked => format!("{}", banked_addr),
+            _ => format!("{:X}", addr),
         }
     }
 }
@@ -47,56 +106,412 @@ impl<T: Iterator<Item = u8>> DisassemblyIterator<T> {
 impl<T: Iterator<Item = u8>> Iterator for DisassemblyIterator<T> {
     type Item = String;
     fn next(&mut self) -> Option<Self::Item> {
-        let val = self.source_iterator.next()?;
+        if let Some(line) = self.pending.take() {
+            return Some(line);
+        }
+        let val = self.next_byte()?;
 
-        let instr = self
-            .decoder
-            .decode_from_iter(val, &mut self.source_iterator);
-        let text = instr
+        let decoder = self.decoder;
+        let mut read = Vec::new();
+        let instr = decoder.decode_from_iter(
+            val,
+            &mut core::iter::from_fn(|| self.next_byte()).inspect(|value| read.push(*value)),
+        );
+        // An instruction cut off by the end of the data is shown as DAT
+        // entries, rather than with made up arguments
+        let instr = match instr {
+            Some(instr) if instr.as_bytes().len() > read.len() + 1 => {
+                self.leftover.extend(read.iter().rev());
+                None
+            }
+            instr => instr,
+        };
+        let mut text = instr
             .as_ref()
             .map(|i| i.disassemble())
             .unwrap_or_else(|| format!("DAT {:X}h", val));
         let bytes = instr.map(|i| i.as_bytes()).unwrap_or_else(|| vec![val]);
         let size = bytes.len();
         let mut numeric = String::with_capacity(size * 2);
-        for byte in bytes {
+        for byte in &bytes {
             numeric.push_str(&format!("{:02X}", byte))
         }
 
         let current_addr = self.addr;
         self.addr += size;
-        if self.format == DisassemblyFormat::Verbose {
+        if !self.labels.is_empty() {
+            let instruction = DisassembledInstruction {
+                address: rom_memory_address(current_addr),
+                bytes,
+                text,
+            };
+            text = instruction.text.clone();
+            let target = analysis::branch_target(&instruction)
+                .and_then(|target| rom_target_offset(current_addr, target))
+                .filter(|target| self.labels.contains(target));
+            if let Some(target) = target {
+                replace_operand(&mut text, &rom_label(target));
+            }
+        }
+        let line = if self.format == DisassemblyFormat::Verbose {
             Some(format!(
-                "{:>6X}:\t\t{:>6}\t\t{}",
-                current_addr, numeric, text
+                "{:>6}:\t\t{:>6}\t\t{}",
+                self.format_addr(current_addr),
+                numeric,
+                text
             ))
         } else if self.format == DisassemblyFormat::Columnar {
-            let addr_text = format!("{:04X}:", current_addr);
+            let addr_text = if self.banked {
+                format!("{}:", self.format_addr(current_addr))
+            } else {
+                format!("{:04X}:", current_addr)
+            };
             Some(format!("{:<7}{:>10}    {}", addr_text, numeric, text))
         } else {
             let addr_to_print = if current_addr >= self.next_addr {
                 self.next_addr += 0x10;
-                format!("{:>6X}:", current_addr)
+                format!("{:>6}:", self.format_addr(current_addr))
             } else {
                 format!("{:>7}", &"")
             };
             Some(format!("{}\t\t{}", addr_to_print, text))
+        };
+        if self.labels.contains(&current_addr) {
+            self.pending = line;
+            return Some(format!("{}:", rom_label(current_addr)));
+        }
+        line
+    }
+}
+
+/// The address the ROM offset is mapped to in memory
+fn rom_memory_address(offset: usize) -> u16 {
+    BankedAddress::from_rom_offset(offset)
+        .map(|banked_addr| banked_addr.addr.0)
+        .unwrap_or(offset as u16)
+}
+
+/// The ROM offset of `target`, when jumped to from the instruction at `offset`
+///
+/// Targets in 0x4000-0x7FFF are in the same bank as the instruction.
+fn rom_target_offset(offset: usize, target: u16) -> Option<usize> {
+    let bank = if target < 0x4000 {
+        0
+    } else {
+        BankedAddress::from_rom_offset(offset)?.bank.max(1)
+    };
+    BankedAddress::new(bank, target).to_rom_offset()
+}
+
+fn rom_label(offset: usize) -> String {
+    format!("label_{:04X}", rom_memory_address(offset))
+}
+
+/// Replace the last operand of an instruction's text, such as a jump target
+fn replace_operand(text: &mut String, operand: &str) {
+    if let Some(operand_start) = text.rfind(' ') {
+        text.truncate(operand_start + 1);
+        text.push_str(operand);
+    }
+}
+
+/// Finds the addresses jumped to or called by instructions in `data`
+///
+/// `data` is decoded in the same way as [`DisassemblyIterator`], starting at
+/// `start_offset` in a ROM. Only targets which are the start of an instruction
+/// in `data` are included. Targets in 0x4000-0x7FFF are taken to be in the
+/// same bank as the instruction that jumps to them.
+///
+/// [`DisassemblyIterator`]: ./struct.DisassemblyIterator.html
+pub fn jump_targets(data: &[u8], start_offset: usize) -> BTreeSet<usize> {
+    let decoder = RuntimeDecoder::shared();
+    let mut starts = BTreeSet::new();
+    let mut targets = BTreeSet::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let rom_offset = start_offset + offset;
+        let instruction = decode_or_dat(decoder, data, offset, rom_memory_address(rom_offset));
+        offset += instruction.bytes.len();
+        starts.insert(rom_offset);
+        if let Some(target) = analysis::branch_target(&instruction)
+            .and_then(|target| rom_target_offset(rom_offset, target))
+        {
+            targets.insert(target);
         }
     }
+    targets.intersection(&starts).copied().collect()
+}
+
+/// A decoded instruction and the address it was decoded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisassembledInstruction {
+    /// Address of the first byte of the instruction
+    pub address: u16,
+    /// The bytes that make up the instruction
+    pub bytes: Vec<u8>,
+    /// Disassembled text of the instruction, such as `LD H, 20h`
+    pub text: String,
+}
+
+/// Decodes the instruction at `offset` in `data`, which is located at `address`
+///
+/// Returns `None` if there is no valid instruction that fits in `data` at that offset.
+fn decode_at(
+    decoder: &RuntimeDecoder,
+    data: &[u8],
+    offset: usize,
+    address: u16,
+) -> Option<DisassembledInstruction> {
+    let remaining = data.get(offset..)?;
+    let mut source_iterator = remaining.iter().copied();
+    let val = source_iterator.next()?;
+    let instr = decoder.decode_from_iter(val, &mut source_iterator)?;
+    let bytes = instr.as_bytes();
+    if bytes.len() > remaining.len() {
+        return None;
+    }
+    Some(DisassembledInstruction {
+        address,
+        bytes,
+        text: instr.disassemble(),
+    })
+}
+
+/// Decodes the instruction at `offset`, or a `DAT` entry if it isn't valid
+fn decode_or_dat(
+    decoder: &RuntimeDecoder,
+    data: &[u8],
+    offset: usize,
+    address: u16,
+) -> DisassembledInstruction {
+    decode_at(decoder, data, offset, address).unwrap_or_else(|| DisassembledInstruction {
+        address,
+        bytes: vec![data[offset]],
+        text: format!("DAT {:X}h", data[offset]),
+    })
+}
+
+/// Decodes every instruction in `data`, which is located at `start_addr`
+///
+/// Bytes that are not a valid instruction are decoded as `DAT` entries.
+pub fn decode_instructions(data: &[u8], start_addr: u16) -> Vec<DisassembledInstruction> {
+    let decoder = RuntimeDecoder::shared();
+    let mut address = start_addr;
+    let mut offset = 0;
+    let mut instructions = Vec::new();
+    while offset < data.len() {
+        let instruction = decode_or_dat(decoder, data, offset, address);
+        let size = instruction.bytes.len();
+        offset += size;
+        address = address.wrapping_add(size as u16);
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/// Most bytes used by a single instruction
+const MAX_INSTRUCTION_SIZE: u16 = 3;
+
+/// An instruction decoded from the memory of a running Game Boy
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DisassemblyLine {
+    /// Address of the first byte of the instruction
+    pub address: u16,
+    /// The bytes that make up the instruction
+    pub bytes: Vec<u8>,
+    /// Disassembled text of the instruction, such as `LD H, 20h`
+    pub text: String,
+    /// Whether this is the next instruction the CPU will run
+    pub is_current_pc: bool,
+}
+
+/// Decodes `count` instructions from the memory map of `gb`, starting at `start_addr`
+///
+/// Memory that can't be read is decoded as if it held `0xFF`. Decoding wraps
+/// around from the end of memory to the start.
+pub fn disassemble_memory(gb: &GameBoy, start_addr: u16, count: usize) -> Vec<DisassemblyLine> {
+    let decoder = RuntimeDecoder::shared();
+    let pc = gb.read_register_u16(WordRegister::PC);
+    let mut address = start_addr;
+    let mut lines = Vec::with_capacity(count);
+    for _ in 0..count {
+        let data: Vec<u8> = (0..MAX_INSTRUCTION_SIZE)
+            .map(|offset| {
+                gb.get_memory_u8(address.wrapping_add(offset))
+                    .unwrap_or(0xFF)
+            })
+            .collect();
+        let instruction = decode_or_dat(decoder, &data, 0, address);
+        address = address.wrapping_add(instruction.bytes.len() as u16);
+        lines.push(DisassemblyLine {
+            is_current_pc: instruction.address == pc,
+            address: instruction.address,
+            bytes: instruction.bytes,
+            text: instruction.text,
+        });
+    }
+    lines
+}
+
+fn format_references(kind: ReferenceKind, references: &[Reference]) -> Option<String> {
+    let sources: Vec<String> = references
+        .iter()
+        .filter(|reference| reference.kind == kind)
+        .map(|reference| format!("0x{:04X}", reference.source))
+        .collect();
+    if sources.is_empty() {
+        return None;
+    }
+    let description = match kind {
+        ReferenceKind::Call => "called from",
+        ReferenceKind::Jump => "jumped to from",
+    };
+    Some(format!("{} {}", description, sources.join(", ")))
+}
+
+fn format_listing_line(address: u16, bytes: &[u8], text: &str) -> String {
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    format!("{:04X}:  {:<8}  {}", address, bytes.join(" "), text)
+}
+
+fn format_listing_instruction(
+    instruction: DisassembledInstruction,
+    analysis: &CodeAnalysis,
+    target_symbol: Option<String>,
+) -> String {
+    let mut text = instruction.text;
+    if let Some(symbol) = target_symbol {
+        replace_operand(&mut text, &symbol);
+    }
+    let mut line = format_listing_line(instruction.address, &instruction.bytes, &text);
+
+    let references = analysis.references_to(instruction.address);
+    let comments: Vec<String> = [ReferenceKind::Call, ReferenceKind::Jump]
+        .iter()
+        .filter_map(|kind| format_references(*kind, references))
+        .collect();
+    if !comments.is_empty() {
+        let padding = LISTING_COMMENT_COLUMN.saturating_sub(line.len()).max(1);
+        line.push_str(&" ".repeat(padding));
+        line.push_str("; ");
+        line.push_str(&comments.join("; "));
+    }
+    line
+}
+
+fn format_bytes(bytes: &[u8]) -> String {
+    let values: Vec<String> = bytes.iter().map(|byte| format!("{:02X}h", byte)).collect();
+    values.join(", ")
+}
+
+/// Formats a data region as `.db` directives
+fn format_data_region(region: &DataRegion, data: &[u8], lines: &mut Vec<String>) {
+    if region.kind == DataKind::Graphics {
+        lines.push(format!(
+            "; {} tiles of 2bpp graphics",
+            data.len() / analysis::TILE_SIZE
+        ));
+    }
+    let mut address = region.start;
+    let mut push_db = |len: usize, operands: String| {
+        let text = format!(".db {}", operands);
+        lines.push(format_listing_line(address, &[], &text));
+        address = address.wrapping_add(len as u16);
+    };
+    match region.kind {
+        DataKind::Text => {
+            let mut remaining = data;
+            while !remaining.is_empty() {
+                let string_len = remaining
+                    .iter()
+                    .position(|value| *value == 0)
+                    .unwrap_or(remaining.len());
+                let chunk_len = string_len.min(LISTING_TEXT_WIDTH);
+                let text: String = remaining[..chunk_len]
+                    .iter()
+                    .map(|value| char::from(*value))
+                    .collect();
+                let mut operands = format!("\"{}\"", text.replace('"', "\\\""));
+                let mut len = chunk_len;
+                if chunk_len == string_len && len < remaining.len() {
+                    operands.push_str(", 00h");
+                    len += 1;
+                }
+                push_db(len, operands);
+                remaining = &remaining[len..];
+            }
+        }
+        DataKind::Graphics => {
+            for row in data.chunks(LISTING_DATA_WIDTH) {
+                push_db(row.len(), format_bytes(row));
+            }
+        }
+    }
+}
+
+/// Produces an assembler style listing of `data`, which is located at `start_addr`
+///
+/// Every line contains an address, the raw bytes and the decoded instruction
+/// in fixed width columns. Addresses that `analysis` found are called or jumped to
+/// get a generated label, which is also used in place of the address in the
+/// instructions that reference it, and a comment listing where they are referenced from.
+/// Regions the analysis found to be text or graphics are printed as `.db` directives.
+pub fn listing(data: &[u8], start_addr: u16, analysis: &CodeAnalysis) -> Vec<String> {
+    let decoder = RuntimeDecoder::shared();
+    let end_addr = u32::from(start_addr) + data.len() as u32;
+    let in_listing = |addr: u16| (u32::from(start_addr)..end_addr).contains(&u32::from(addr));
+    let offset_of = |addr: u16| usize::from(addr.wrapping_sub(start_addr));
+    let mut regions = analysis.data_regions().iter().peekable();
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let address = start_addr.wrapping_add(offset as u16);
+        if let Some(symbol) = analysis.symbol(address) {
+            lines.push(format!("{}:", symbol));
+        }
+
+        if let Some(region) = regions.peek().filter(|region| region.start == address) {
+            format_data_region(region, &data[offset..offset + region.len], &mut lines);
+            offset += region.len;
+            regions.next();
+            continue;
+        }
+
+        let next_region = regions
+            .peek()
+            .map(|region| offset_of(region.start))
+            .unwrap_or(data.len());
+        let instruction = decode_or_dat(decoder, &data[..next_region], offset, address);
+        offset += instruction.bytes.len();
+        let target_symbol = analysis::branch_target(&instruction)
+            .filter(|target| in_listing(*target))
+            .and_then(|target| analysis.symbol(target));
+        lines.push(format_listing_instruction(
+            instruction,
+            analysis,
+            target_symbol,
+        ));
+    }
+    lines
 }
 
 /// Disassembles a complete program
 ///
 /// `verbose` includes hex values of instructions as well as disassembly
 ///
+/// `banked` prints addresses as `bank:address` rather than ROM offsets
+///
 /// See [`FormattingIterator`] for more customisable options
 #[cfg(feature = "std")]
 pub fn disassemble(
     data: Vec<u8>,
     format: DisassemblyFormat,
+    banked: bool,
     output: &mut dyn std::io::Write,
 ) -> std::io::Result<()> {
-    let formatting_iterator = DisassemblyIterator::new(data.into_iter(), format, 0);
+    let formatting_iterator = DisassemblyIterator::new(data.into_iter(), format, 0).banked(banked);
 
     for disassembled_instruction in formatting_iterator {
         writeln!(output, "{}", disassembled_instruction)?;
@@ -106,6 +521,7 @@ pub fn disassemble(
 
 #[cfg(test)]
 pub mod test {
+    use alloc::string::String;
 
     #[cfg(feature = "std")]
     #[test]
@@ -122,7 +538,7 @@ pub mod test {
 
         let mut output: Vec<u8> = alloc::vec::Vec::new();
 
-        super::disassemble(data, super::DisassemblyFormat::Normal, &mut output).unwrap();
+        super::disassemble(data, super::DisassemblyFormat::Normal, false, &mut output).unwrap();
 
         let expected_result = concat!(
             "     0:\t\tLD H, 20h\n",
@@ -160,7 +576,7 @@ pub mod test {
 
         let mut output: Vec<u8> = alloc::vec::Vec::new();
 
-        super::disassemble(data, super::DisassemblyFormat::Verbose, &mut output).unwrap();
+        super::disassemble(data, super::DisassemblyFormat::Verbose, false, &mut output).unwrap();
 
         let expected_result = concat!(
             "     0:\t\t  2620\t\tLD H, 20h\n",
@@ -182,4 +598,230 @@ pub mod test {
             String::from(expected_result)
         );
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_disassembly_truncated() {
+        let data = vec![
+            0x26, 0x20, // LD H, 20h
+            0xC3, 0xFD, // JP missing its last byte
+        ];
+
+        let mut output: Vec<u8> = alloc::vec::Vec::new();
+
+        super::disassemble(data, super::DisassemblyFormat::Verbose, false, &mut output).unwrap();
+
+        let expected_result = concat!(
+            "     0:\t\t  2620\t\tLD H, 20h\n",
+            "     2:\t\t    C3\t\tDAT C3h\n",
+            "     3:\t\t    FD\t\tDAT FDh\n",
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output),
+            String::from(expected_result)
+        );
+    }
+
+    #[test]
+    fn test_decode_instructions() {
+        let data = [
+            0x26, 0x20, // LD H, 20h
+            0xC3, 0x22, 0x11, // JP $1122h
+            0xFD, // Invalid
+        ];
+
+        let instructions = super::decode_instructions(&data, 0x150);
+
+        assert_eq!(
+            instructions,
+            vec![
+                super::DisassembledInstruction {
+                    address: 0x150,
+                    bytes: vec![0x26, 0x20],
+                    text: String::from("LD H, 20h"),
+                },
+                super::DisassembledInstruction {
+                    address: 0x152,
+                    bytes: vec![0xC3, 0x22, 0x11],
+                    text: String::from("JP $1122h"),
+                },
+                super::DisassembledInstruction {
+                    address: 0x155,
+                    bytes: vec![0xFD],
+                    text: String::from("DAT FDh"),
+                },
+            ]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_disassembly_extended() {
+        let data = vec![
+            0xCB, 0x37, // SWAP A
+            0x00, // NOP
+            0xCB, 0x7C, // BIT 7h, H
+            0xCB, // Prefix with no sub-opcode
+        ];
+
+        let mut output: Vec<u8> = alloc::vec::Vec::new();
+
+        super::disassemble(data, super::DisassemblyFormat::Verbose, false, &mut output).unwrap();
+
+        let expected_result = concat!(
+            "     0:\t\t  CB37\t\tSWAP A\n",
+            "     2:\t\t    00\t\tNOP\n",
+            "     3:\t\t  CB7C\t\tBIT 7h, H\n",
+            "     5:\t\t    CB\t\tDAT CBh\n",
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output),
+            String::from(expected_result)
+        );
+    }
+
+    #[test]
+    fn test_decode_extended_instructions() {
+        let data = [0xCB, 0x11, 0xCB, 0xFE, 0xCB];
+
+        let instructions = super::decode_instructions(&data, 0x200);
+
+        assert_eq!(
+            instructions,
+            vec![
+                super::DisassembledInstruction {
+                    address: 0x200,
+                    bytes: vec![0xCB, 0x11],
+                    text: String::from("RL C"),
+                },
+                super::DisassembledInstruction {
+                    address: 0x202,
+                    bytes: vec![0xCB, 0xFE],
+                    text: String::from("SET 7h, (HL)"),
+                },
+                super::DisassembledInstruction {
+                    address: 0x204,
+                    bytes: vec![0xCB],
+                    text: String::from("DAT CBh"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassembly_labels() {
+        let data = vec![
+            0xC3, 0x06, 0x00, // JP $0006h
+            0x18, 0xFE, // JR -2h
+            0x00, // NOP
+            0xCD, 0x04, 0x00, // CALL $4h, which is inside JR
+        ];
+
+        let labels = super::jump_targets(&data, 0);
+        assert_eq!(labels, vec![3, 6].into_iter().collect());
+
+        let iter =
+            super::DisassemblyIterator::new(data.into_iter(), super::DisassemblyFormat::Verbose, 0)
+                .labels(labels);
+        let output: alloc::vec::Vec<String> = iter.collect();
+
+        assert_eq!(
+            output,
+            vec![
+                String::from("     0:\t\tC30600\t\tJP label_0006"),
+                String::from("label_0003:"),
+                String::from("     3:\t\t  18FE\t\tJR label_0003"),
+                String::from("     5:\t\t    00\t\tNOP"),
+                String::from("label_0006:"),
+                String::from("     6:\t\tCD0400\t\tCALL $4h"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jump_targets_in_bank() {
+        let mut data = vec![0x00; 0x4010];
+        data[0..3].copy_from_slice(&[0xC3, 0x08, 0x40]); // JP $4008h
+        data[0x4000..0x4003].copy_from_slice(&[0xC3, 0x08, 0x40]); // JP $4008h
+
+        let labels = super::jump_targets(&data, 0x8000);
+
+        assert_eq!(labels, vec![0x8008, 0xC008].into_iter().collect());
+    }
+
+    #[test]
+    fn test_listing() {
+        let data = [
+            0xCD, 0x07, 0x40, // CALL $4007h
+            0x18, 0xFB, // JR -5h
+            0x00, // NOP
+            0xFD, // Invalid
+            0xC9, // RET
+        ];
+
+        let analysis = super::CodeAnalysis::new(&data, 0x4000, &[0x4000]);
+        let lines = super::listing(&data, 0x4000, &analysis);
+
+        assert_eq!(
+            lines,
+            vec![
+                String::from("loc_4000:"),
+                String::from("4000:  CD 07 40  CALL sub_4007          ; jumped to from 0x4003"),
+                String::from("4003:  18 FB     JR loc_4000"),
+                String::from("4005:  00        NOP"),
+                String::from("4006:  FD        DAT FDh"),
+                String::from("sub_4007:"),
+                String::from("4007:  C9        RET                    ; called from 0x4000"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_listing_data_regions() {
+        let mut data = b"Say \"Hi there\"!\0".to_vec();
+        for row in 0..16 {
+            data.extend_from_slice(&[0x18, 0x18 * (row % 2)]);
+        }
+        data.push(0xC9); // RET
+
+        let analysis = super::CodeAnalysis::new(&data, 0x4000, &[0x4030]);
+        let lines = super::listing(&data, 0x4000, &analysis);
+
+        assert_eq!(
+            lines,
+            vec![
+                String::from("4000:            .db \"Say \\\"Hi there\\\"!\", 00h"),
+                String::from("; 2 tiles of 2bpp graphics"),
+                String::from("4010:            .db 18h, 00h, 18h, 18h, 18h, 00h, 18h, 18h"),
+                String::from("4018:            .db 18h, 00h, 18h, 18h, 18h, 00h, 18h, 18h"),
+                String::from("4020:            .db 18h, 00h, 18h, 18h, 18h, 00h, 18h, 18h"),
+                String::from("4028:            .db 18h, 00h, 18h, 18h, 18h, 00h, 18h, 18h"),
+                String::from("4030:  C9        RET"),
+            ]
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_disassembly_banked() {
+        let mut data = vec![0x00; 0xC125];
+        data[0xC123] = 0xF3; // DI
+        data[0xC124] = 0xFB; // EI
+
+        let iter = super::DisassemblyIterator::new(
+            data.into_iter().skip(0xC123),
+            super::DisassemblyFormat::Verbose,
+            0xC123,
+        )
+        .banked(true);
+        let output: alloc::vec::Vec<String> = iter.collect();
+
+        assert_eq!(
+            output,
+            vec![
+                String::from("3:4123:\t\t    F3\t\tDI"),
+                String::from("3:4124:\t\t    FB\t\tEI"),
+            ]
+        );
+    }
 }
@@ -0,0 +1,522 @@
+use super::{decode_at, DisassembledInstruction};
+use crate::instructionsn::RuntimeDecoder;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Addresses a cartridge's code can start running from without being referenced
+const ENTRY_POINTS: [u16; 6] = [0x0040, 0x0048, 0x0050, 0x0058, 0x0060, 0x0100];
+/// Fewest printable characters in a row treated as text
+const MIN_TEXT_LEN: usize = 8;
+/// Fewest tiles in a row treated as graphics
+const MIN_GRAPHICS_TILES: usize = 2;
+/// Most different byte values in a tile that still looks like graphics,
+/// when its rows don't repeat
+const MAX_TILE_DISTINCT_BYTES: usize = 6;
+/// Fewest rows that match the previous row in a tile that looks like graphics
+const MIN_TILE_REPEATED_ROWS: usize = 4;
+/// Fewest rows with any pixels set in a tile that looks like graphics,
+/// so that padding after a short routine isn't mistaken for graphics
+const MIN_TILE_DRAWN_ROWS: usize = 3;
+pub(super) const TILE_SIZE: usize = 16;
+
+/// How control reaches an address from elsewhere in the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReferenceKind {
+    /// A `CALL` or `RST` instruction
+    Call,
+    /// A `JP` or `JR` instruction
+    Jump,
+}
+
+/// An instruction that transfers control to another address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    /// Address of the referencing instruction
+    pub source: u16,
+    pub kind: ReferenceKind,
+}
+
+/// Where execution can go after an instruction
+enum Flow {
+    /// Continues on to the next instruction
+    Next,
+    /// Transfers control to `target`, and to the next instruction if `falls_through`
+    Branch {
+        target: u16,
+        kind: ReferenceKind,
+        falls_through: bool,
+    },
+    /// Leaves to somewhere that can't be known ahead of time
+    End,
+}
+
+impl Flow {
+    fn of(instruction: &DisassembledInstruction) -> Flow {
+        let next_addr = instruction
+            .address
+            .wrapping_add(instruction.bytes.len() as u16);
+        let branch = |target, kind, falls_through| Flow::Branch {
+            target,
+            kind,
+            falls_through,
+        };
+        match instruction.bytes[..] {
+            [0xC3, lo, hi] => branch(u16::from_le_bytes([lo, hi]), ReferenceKind::Jump, false),
+            [0xC2, lo, hi] | [0xCA, lo, hi] | [0xD2, lo, hi] | [0xDA, lo, hi] => {
+                branch(u16::from_le_bytes([lo, hi]), ReferenceKind::Jump, true)
+            }
+            [0xCD, lo, hi] | [0xC4, lo, hi] | [0xCC, lo, hi] | [0xD4, lo, hi] | [0xDC, lo, hi] => {
+                branch(u16::from_le_bytes([lo, hi]), ReferenceKind::Call, true)
+            }
+            [0x18, offset] => branch(
+                relative_target(next_addr, offset),
+                ReferenceKind::Jump,
+                false,
+            ),
+            [0x20, offset] | [0x28, offset] | [0x30, offset] | [0x38, offset] => branch(
+                relative_target(next_addr, offset),
+                ReferenceKind::Jump,
+                true,
+            ),
+            [opcode] if opcode & 0xC7 == 0xC7 => {
+                branch(u16::from(opcode & 0x38), ReferenceKind::Call, true)
+            }
+            // RET, RETI and JP HL
+            [0xC9] | [0xD9] | [0xE9] => Flow::End,
+            _ => Flow::Next,
+        }
+    }
+}
+
+/// The address a jump or call instruction transfers control to
+pub(super) fn branch_target(instruction: &DisassembledInstruction) -> Option<u16> {
+    match Flow::of(instruction) {
+        Flow::Branch { target, .. } => Some(target),
+        _ => None,
+    }
+}
+
+fn relative_target(next_addr: u16, offset: u8) -> u16 {
+    next_addr.wrapping_add(i16::from(offset as i8) as u16)
+}
+
+/// What a range of bytes outside of reachable code probably contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataKind {
+    /// ASCII strings, each optionally followed by a 0 terminator
+    Text,
+    /// 2bpp tile graphics, starting at a tile boundary
+    Graphics,
+}
+
+/// A range of bytes that is likely to be data rather than code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataRegion {
+    /// Address of the first byte in the region
+    pub start: u16,
+    /// Number of bytes in the region
+    pub len: usize,
+    pub kind: DataKind,
+}
+
+fn is_text_char(value: u8) -> bool {
+    (0x20..=0x7E).contains(&value)
+}
+
+/// Checks a run of printable bytes is long enough and mostly made up of
+/// letters, digits and spaces, which rules out most runs of load instructions
+fn looks_like_text(run: &[u8]) -> bool {
+    let printable = run.iter().filter(|value| is_text_char(**value)).count();
+    let wordlike = run
+        .iter()
+        .filter(|value| value.is_ascii_alphanumeric() || **value == b' ')
+        .count();
+    printable >= MIN_TEXT_LEN && wordlike * 4 >= printable * 3
+}
+
+/// Finds runs of printable characters, where a 0 byte may end each string
+fn find_text(data: &[u8], range: Range<usize>) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut run_start = None;
+    for offset in range.clone() {
+        let value = data[offset];
+        let terminates_string = value == 0 && offset > 0 && is_text_char(data[offset - 1]);
+        match run_start {
+            None if is_text_char(value) => run_start = Some(offset),
+            Some(start) if !is_text_char(value) && !terminates_string => {
+                if looks_like_text(&data[start..offset]) {
+                    runs.push(start..offset);
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = run_start.filter(|start| looks_like_text(&data[*start..range.end])) {
+        runs.push(start..range.end);
+    }
+    runs
+}
+
+/// Tiles have two bytes per row, and real graphics tend to use only a few
+/// different bytes or repeat rows. Code and other data rarely do either.
+fn looks_like_tile(tile: &[u8]) -> bool {
+    let mut seen = [false; 256];
+    for value in tile {
+        seen[usize::from(*value)] = true;
+    }
+    let distinct_bytes = seen.iter().filter(|seen| **seen).count();
+    let rows: Vec<&[u8]> = tile.chunks(2).collect();
+    let repeated_rows = rows.windows(2).filter(|pair| pair[0] == pair[1]).count();
+    let drawn_rows = rows
+        .iter()
+        .filter(|row| row.iter().any(|value| *value != 0))
+        .count();
+    drawn_rows >= MIN_TILE_DRAWN_ROWS
+        && (distinct_bytes <= MAX_TILE_DISTINCT_BYTES || repeated_rows >= MIN_TILE_REPEATED_ROWS)
+}
+
+/// Finds runs of tiles that look like graphics, aligned to tile boundaries in memory
+///
+/// Blank tiles are included if they are surrounded by other graphics,
+/// but can't start or end a run as they are more likely to be padding.
+fn find_graphics(data: &[u8], start_addr: u16, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let misalignment = (usize::from(start_addr) + range.start) % TILE_SIZE;
+    let first_tile = range.start + (TILE_SIZE - misalignment) % TILE_SIZE;
+    let mut run: Option<(usize, usize, usize)> = None;
+    let mut tile_start = first_tile;
+    while tile_start + TILE_SIZE <= range.end {
+        let tile_end = tile_start + TILE_SIZE;
+        let tile = &data[tile_start..tile_end];
+        let blank = tile.iter().all(|value| *value == tile[0]);
+        run = match run {
+            Some((start, _, tiles)) if !blank && looks_like_tile(tile) => {
+                Some((start, tile_end, tiles + 1))
+            }
+            None if !blank && looks_like_tile(tile) => Some((tile_start, tile_end, 1)),
+            Some(current) if blank => Some(current),
+            Some((start, end, tiles)) => {
+                if tiles >= MIN_GRAPHICS_TILES {
+                    runs.push(start..end);
+                }
+                None
+            }
+            None => None,
+        };
+        tile_start = tile_end;
+    }
+    if let Some((start, end, tiles)) = run {
+        if tiles >= MIN_GRAPHICS_TILES {
+            runs.push(start..end);
+        }
+    }
+    runs
+}
+
+/// Classify bytes which aren't reachable code as text or graphics where they look like it
+fn find_data_regions(data: &[u8], start_addr: u16, covered: &[bool]) -> Vec<DataRegion> {
+    let mut regions = Vec::new();
+    let mut push_region = |range: Range<usize>, kind| {
+        regions.push(DataRegion {
+            start: start_addr.wrapping_add(range.start as u16),
+            len: range.len(),
+            kind,
+        })
+    };
+    let mut offset = 0;
+    while offset < data.len() {
+        if covered[offset] {
+            offset += 1;
+            continue;
+        }
+        let end = (offset..data.len())
+            .find(|offset| covered[*offset])
+            .unwrap_or(data.len());
+        let mut gap_start = offset;
+        for text in find_text(data, offset..end) {
+            for graphics in find_graphics(data, start_addr, gap_start..text.start) {
+                push_region(graphics, DataKind::Graphics);
+            }
+            gap_start = text.end;
+            push_region(text, DataKind::Text);
+        }
+        for graphics in find_graphics(data, start_addr, gap_start..end) {
+            push_region(graphics, DataKind::Graphics);
+        }
+        offset = end;
+    }
+    regions
+}
+
+/// Finds which parts of a program are reachable code, and which
+/// addresses are called or jumped to from that code
+///
+/// Starting from a set of entry points, every path through the program
+/// is followed through jumps, calls and returns. Bytes which are never
+/// reached are likely to be data rather than code. Jumps to a computed
+/// address (such as `JP HL`) can't be followed, so code only reached that
+/// way is not found.
+///
+/// Unreachable bytes are then checked with heuristics to find
+/// text and tile graphics, which are reported as [`DataRegion`]s.
+#[derive(Debug, Clone, Default)]
+pub struct CodeAnalysis {
+    instructions: BTreeSet<u16>,
+    undecodable: BTreeSet<u16>,
+    references: BTreeMap<u16, Vec<Reference>>,
+    data_regions: Vec<DataRegion>,
+}
+
+impl CodeAnalysis {
+    /// Analyse `data`, which is located at `start_addr`, following code from `entry_points`
+    ///
+    /// Entry points outside of `data` are ignored.
+    pub fn new(data: &[u8], start_addr: u16, entry_points: &[u16]) -> CodeAnalysis {
+        let decoder = RuntimeDecoder::shared();
+        let mut analysis = CodeAnalysis::default();
+        let mut covered = vec![false; data.len()];
+        let mut pending: Vec<u16> = entry_points.to_vec();
+        while let Some(addr) = pending.pop() {
+            if analysis.instructions.contains(&addr) {
+                continue;
+            }
+            let offset = usize::from(addr.wrapping_sub(start_addr));
+            let instruction = match decode_at(decoder, data, offset, addr) {
+                Some(instruction) => instruction,
+                None => {
+                    if offset < data.len() {
+                        analysis.undecodable.insert(addr);
+                    }
+                    continue;
+                }
+            };
+            analysis.instructions.insert(addr);
+            for covered in &mut covered[offset..offset + instruction.bytes.len()] {
+                *covered = true;
+            }
+            let next_addr = addr.wrapping_add(instruction.bytes.len() as u16);
+            match Flow::of(&instruction) {
+                Flow::Next => pending.push(next_addr),
+                Flow::Branch {
+                    target,
+                    kind,
+                    falls_through,
+                } => {
+                    let references = analysis.references.entry(target).or_default();
+                    references.push(Reference { source: addr, kind });
+                    pending.push(target);
+                    if falls_through {
+                        pending.push(next_addr);
+                    }
+                }
+                Flow::End => {}
+            }
+        }
+        for references in analysis.references.values_mut() {
+            references.sort_by_key(|reference| reference.source);
+        }
+        analysis.data_regions = find_data_regions(data, start_addr, &covered);
+        analysis
+    }
+
+    /// Analyse `data` located at `start_addr`, starting from the start of the
+    /// data and any cartridge entry points and interrupt handlers it contains
+    pub fn from_start(data: &[u8], start_addr: u16) -> CodeAnalysis {
+        let mut entry_points = vec![start_addr];
+        entry_points.extend(ENTRY_POINTS.iter().rev());
+        CodeAnalysis::new(data, start_addr, &entry_points)
+    }
+
+    /// Check if an instruction starting at `addr` is reachable
+    pub fn is_code(&self, addr: u16) -> bool {
+        self.instructions.contains(&addr)
+    }
+
+    /// Addresses of every reachable instruction, in address order
+    pub fn instructions(&self) -> impl Iterator<Item = u16> + '_ {
+        self.instructions.iter().copied()
+    }
+
+    /// Reachable addresses in the data which don't start a valid instruction, in address order
+    ///
+    /// This is either an invalid opcode, or an instruction whose parameters
+    /// would run past the end of the data.
+    pub fn undecodable(&self) -> impl Iterator<Item = u16> + '_ {
+        self.undecodable.iter().copied()
+    }
+
+    /// Ranges of unreachable bytes which look like text or graphics, in address order
+    pub fn data_regions(&self) -> &[DataRegion] {
+        &self.data_regions
+    }
+
+    /// Reachable instructions which call or jump to `addr`, in address order
+    pub fn references_to(&self, addr: u16) -> &[Reference] {
+        self.references
+            .get(&addr)
+            .map(|references| &references[..])
+            .unwrap_or(&[])
+    }
+
+    /// A generated name for `addr`, if it is called or jumped to
+    ///
+    /// Called addresses are named like `sub_0150` and addresses that
+    /// are only jumped to like `loc_0150`.
+    pub fn symbol(&self, addr: u16) -> Option<String> {
+        let references = self.references.get(&addr)?;
+        let prefix = if references
+            .iter()
+            .any(|reference| reference.kind == ReferenceKind::Call)
+        {
+            "sub"
+        } else {
+            "loc"
+        };
+        Some(format!("{}_{:04X}", prefix, addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_follows_branches() {
+        let data = [
+            0xCD, 0x08, 0x01, // 100: CALL $0108h
+            0x20, 0xFB, //       103: JR NZ, -5h
+            0xC3, 0x0A, 0x01, // 105: JP $010Ah
+            0xC9, //             108: RET
+            0xFD, //             109: DAT FDh
+            0xC7, //             10A: RST $00h
+            0xE9, //             10B: JP HL
+            0x00, //             10C: NOP
+        ];
+
+        let analysis = CodeAnalysis::new(&data, 0x100, &[0x100]);
+
+        let code: Vec<u16> = (0x100..0x10D)
+            .filter(|addr| analysis.is_code(*addr))
+            .collect();
+        assert_eq!(code, vec![0x100, 0x103, 0x105, 0x108, 0x10A, 0x10B]);
+        assert_eq!(
+            analysis.references_to(0x100),
+            &[Reference {
+                source: 0x103,
+                kind: ReferenceKind::Jump
+            }]
+        );
+        assert_eq!(
+            analysis.references_to(0x0),
+            &[Reference {
+                source: 0x10A,
+                kind: ReferenceKind::Call
+            }]
+        );
+        assert_eq!(analysis.symbol(0x100), Some(String::from("loc_0100")));
+        assert_eq!(analysis.symbol(0x108), Some(String::from("sub_0108")));
+        assert_eq!(analysis.symbol(0x10A), Some(String::from("loc_010A")));
+        assert_eq!(analysis.symbol(0x10C), None);
+    }
+
+    #[test]
+    fn test_undecodable() {
+        let data = [
+            0x20, 0x01, // 100: JR NZ, 1h
+            0xD3, //       102: DAT D3h
+            0xC3, 0x00, // 103: JP, missing the high byte
+        ];
+
+        let analysis = CodeAnalysis::new(&data, 0x100, &[0x100]);
+
+        assert_eq!(analysis.instructions().collect::<Vec<_>>(), vec![0x100]);
+        assert_eq!(
+            analysis.undecodable().collect::<Vec<_>>(),
+            vec![0x102, 0x103]
+        );
+    }
+
+    #[test]
+    fn test_data_regions() {
+        let mut data = vec![0x18, 0x4E]; // JR 4Eh
+        data.extend_from_slice(b"Hello World\0FIZZ\0\x01");
+        // Pad out to 0x20 before two tiles of a letter A, with a blank tile between them
+        data.resize(0x20, 0x01);
+        let tile = [
+            0x00, 0x00, 0x3C, 0x3C, 0x66, 0x66, 0x66, 0x66, //
+            0x7E, 0x7E, 0x66, 0x66, 0x66, 0x66, 0x00, 0x00,
+        ];
+        data.extend_from_slice(&tile);
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&tile);
+        data.push(0xC9); // RET
+
+        let analysis = CodeAnalysis::new(&data, 0x4000, &[0x4000]);
+
+        assert_eq!(
+            analysis.data_regions(),
+            &[
+                DataRegion {
+                    start: 0x4002,
+                    len: 17,
+                    kind: DataKind::Text
+                },
+                DataRegion {
+                    start: 0x4020,
+                    len: 0x30,
+                    kind: DataKind::Graphics
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_code_is_not_data() {
+        // Clear some memory, turn on the LCD, then loop on a counter
+        let code = [
+            0x21, 0x00, 0xC0, 0x06, 0x10, 0xAF, 0x22, 0x05, //
+            0x20, 0xFC, 0x3E, 0x91, 0xE0, 0x40, 0xCD, 0x50, //
+            0x01, 0xFA, 0x00, 0xC1, 0xFE, 0x03, 0x28, 0x06, //
+            0x3C, 0xEA, 0x00, 0xC1, 0x18, 0xF3, 0xC9, 0x00,
+        ];
+
+        let analysis = CodeAnalysis::new(&code, 0, &[]);
+
+        assert_eq!(analysis.data_regions(), &[]);
+    }
+
+    #[test]
+    fn test_padding_is_not_graphics() {
+        // RET followed by padding, like the unused restart vectors in many ROMs
+        let mut data = vec![];
+        for _ in 0..4 {
+            data.push(0xC9);
+            data.extend_from_slice(&[0; 7]);
+        }
+
+        let analysis = CodeAnalysis::new(&data, 0, &[]);
+
+        assert_eq!(analysis.data_regions(), &[]);
+    }
+
+    #[test]
+    fn test_entry_points() {
+        let mut data = vec![0xFD; 0x104];
+        data[0x40] = 0xD9; // RETI
+        data[0x100] = 0x00; // NOP
+        data[0x101] = 0x18; // JR -3h
+        data[0x102] = 0xFD;
+
+        let analysis = CodeAnalysis::from_start(&data, 0);
+
+        assert!(analysis.is_code(0x40));
+        assert!(analysis.is_code(0x100));
+        assert!(!analysis.is_code(0x48));
+        assert!(!analysis.is_code(0x104));
+        assert_eq!(analysis.symbol(0x0), None);
+    }
+}
@@ -0,0 +1,193 @@
+use super::analysis::branch_target;
+use super::{decode_at, CodeAnalysis};
+use crate::instructionsn::RuntimeDecoder;
+
+use alloc::vec::Vec;
+use derive_more::Display;
+
+/// Size of the ROM banks mapped into memory at startup
+pub(super) const STARTUP_ROM_SIZE: usize = 0x8000;
+
+/// A likely mistake in a ROM's code
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// Execution reaches a byte which isn't an opcode the CPU has
+    #[display(fmt = "Invalid opcode {:02X}h", _0)]
+    InvalidOpcode(u8),
+    /// Execution reaches an instruction which runs past the end of the ROM
+    #[display(fmt = "Instruction runs past the end of the ROM")]
+    Truncated,
+    /// A jump or call to an address that can't contain code, such as VRAM,
+    /// IO registers or past the end of the ROM
+    #[display(fmt = "Jump to {:04X}h, which can't contain code", _0)]
+    SuspiciousJump(u16),
+    /// A read from an IO address which has no register
+    #[display(fmt = "Read from unmapped IO address {:04X}h", _0)]
+    UnmappedRead(u16),
+    /// A write to an IO address which has no register
+    #[display(fmt = "Write to unmapped IO address {:04X}h", _0)]
+    UnmappedWrite(u16),
+}
+
+/// A likely mistake, and the address of the instruction it was found at
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[display(fmt = "{:04X}: {}", address, kind)]
+pub struct Lint {
+    pub address: u16,
+    pub kind: LintKind,
+}
+
+/// Whether `addr` is a Game Boy or Game Boy Color IO register
+fn is_io_register(addr: u16) -> bool {
+    matches!(
+        addr,
+        0xFF00..=0xFF02
+            | 0xFF04..=0xFF07
+            | 0xFF0F..=0xFF14
+            | 0xFF16..=0xFF1E
+            | 0xFF20..=0xFF26
+            | 0xFF30..=0xFF4B
+            | 0xFF4D
+            | 0xFF4F..=0xFF56
+            | 0xFF68..=0xFF6C
+            | 0xFF70
+    )
+}
+
+fn is_unmapped_io(addr: u16) -> bool {
+    (0xFF00..0xFF80).contains(&addr) && !is_io_register(addr)
+}
+
+/// Whether code can run from `addr` in a ROM of `rom_size` bytes
+fn can_contain_code(addr: u16, rom_size: usize) -> bool {
+    match addr {
+        0x0000..=0x7FFF => usize::from(addr) < rom_size,
+        // Cartridge RAM, work RAM and high RAM
+        0xA000..=0xDFFF | 0xFF80..=0xFFFE => true,
+        _ => false,
+    }
+}
+
+/// Check the code reachable from a ROM's entry points for likely mistakes
+///
+/// Only the first two banks are checked, as they are mapped at startup. Code
+/// in other banks can't be found, as which bank is mapped can't be known
+/// without running the ROM. Only reads and writes to fixed addresses are
+/// checked, not those through registers such as `LD A, (HL)`.
+///
+/// Lints are returned in address order.
+pub fn lint(rom: &[u8]) -> Vec<Lint> {
+    let data = &rom[..rom.len().min(STARTUP_ROM_SIZE)];
+    let analysis = CodeAnalysis::from_start(data, 0);
+    let decoder = RuntimeDecoder::shared();
+    let mut lints = Vec::new();
+    for address in analysis.undecodable() {
+        let opcode = data[usize::from(address)];
+        let kind = if opcode == 0xCB || decoder.decode(opcode).is_some() {
+            LintKind::Truncated
+        } else {
+            LintKind::InvalidOpcode(opcode)
+        };
+        lints.push(Lint { address, kind });
+    }
+    for address in analysis.instructions() {
+        let instruction = match decode_at(decoder, data, usize::from(address), address) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        if let Some(target) = branch_target(&instruction) {
+            if !can_contain_code(target, rom.len()) {
+                let kind = LintKind::SuspiciousJump(target);
+                lints.push(Lint { address, kind });
+            }
+        }
+        let kind = match instruction.bytes[..] {
+            [0xF0, low] => LintKind::UnmappedRead(0xFF00 | u16::from(low)),
+            [0xFA, low, high] => LintKind::UnmappedRead(u16::from_le_bytes([low, high])),
+            [0xE0, low] => LintKind::UnmappedWrite(0xFF00 | u16::from(low)),
+            [0xEA, low, high] | [0x08, low, high] => {
+                LintKind::UnmappedWrite(u16::from_le_bytes([low, high]))
+            }
+            _ => continue,
+        };
+        match kind {
+            LintKind::UnmappedRead(target) | LintKind::UnmappedWrite(target)
+                if is_unmapped_io(target) =>
+            {
+                lints.push(Lint { address, kind })
+            }
+            _ => {}
+        }
+    }
+    lints.sort_by_key(|lint| lint.address);
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_lint() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x100..0x103].copy_from_slice(&[0xCD, 0x50, 0x01]); // CALL $0150h
+        rom[0x150..0x160].copy_from_slice(&[
+            0xE0, 0x40, //       150: LD ($FF40h), A
+            0xE0, 0x03, //       152: LD ($FF03h), A
+            0xFA, 0x7F, 0xFF, // 154: LD A, ($FF7Fh)
+            0xCD, 0x00, 0x80, // 157: CALL $8000h
+            0xCD, 0x80, 0xFF, // 15A: CALL $FF80h
+            0x20, 0x01, //       15D: JR NZ, 1h
+            0xD3, //             15F: DAT D3h
+        ]);
+        rom[0x7FFF] = 0xCB;
+        rom[0x160..0x163].copy_from_slice(&[0xC3, 0xFF, 0x7F]); // JP $7FFFh
+
+        assert_eq!(
+            lint(&rom),
+            vec![
+                Lint {
+                    address: 0x152,
+                    kind: LintKind::UnmappedWrite(0xFF03)
+                },
+                Lint {
+                    address: 0x154,
+                    kind: LintKind::UnmappedRead(0xFF7F)
+                },
+                Lint {
+                    address: 0x157,
+                    kind: LintKind::SuspiciousJump(0x8000)
+                },
+                Lint {
+                    address: 0x15F,
+                    kind: LintKind::InvalidOpcode(0xD3)
+                },
+                Lint {
+                    address: 0x7FFF,
+                    kind: LintKind::Truncated
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jumps_past_end_of_rom() {
+        let mut rom = vec![0u8; 0x200];
+        rom[0x100..0x103].copy_from_slice(&[0xC3, 0x00, 0x40]); // JP $4000h
+
+        let lints = lint(&rom);
+
+        assert_eq!(
+            lints,
+            vec![Lint {
+                address: 0x100,
+                kind: LintKind::SuspiciousJump(0x4000)
+            }]
+        );
+        assert_eq!(
+            lints[0].to_string(),
+            "0100: Jump to 4000h, which can't contain code"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use super::analysis::branch_target;
+use super::lint::STARTUP_ROM_SIZE;
+use super::{decode_at, CodeAnalysis, DataKind, DisassembledInstruction};
+use crate::instructionsn::RuntimeDecoder;
+use crate::rom::CartridgeHeader;
+
+use alloc::vec::Vec;
+
+const BANK_SIZE: usize = 0x4000;
+/// Where execution starts once the boot ROM finishes
+const ENTRY_POINT: u16 = 0x100;
+/// Bytes reserved for the code at the entry point, before the cartridge header
+const ENTRY_POINT_SIZE: usize = 4;
+/// Bytes between each interrupt vector
+const VECTOR_SIZE: usize = 8;
+const INTERRUPT_VECTORS: [(&str, u16); 5] = [
+    ("VBlank", 0x40),
+    ("LCD STAT", 0x48),
+    ("Timer", 0x50),
+    ("Serial", 0x58),
+    ("Joypad", 0x60),
+];
+/// Fewest padding bytes in a row counted as free space, so that
+/// padding between routines and in tables isn't counted
+const MIN_FREE_RUN: usize = 16;
+
+/// The handler for an interrupt, found at its interrupt vector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterruptHandler {
+    /// Name of the interrupt, such as `VBlank`
+    pub name: &'static str,
+    /// Address of the interrupt vector
+    pub address: u16,
+    /// The first instruction at the vector, or `None` if the vector
+    /// only contains padding
+    pub instruction: Option<DisassembledInstruction>,
+}
+
+/// Bytes of a bank the code analysis found to be code or data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Bytes that are part of reachable instructions
+    pub code: usize,
+    /// Bytes that look like text
+    pub text: usize,
+    /// Bytes that look like tile graphics
+    pub graphics: usize,
+}
+
+/// A run of padding bytes which could be reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreeBlock {
+    /// Address of the first byte in the block, as mapped into memory
+    pub start: u16,
+    /// Number of bytes in the block
+    pub len: usize,
+}
+
+/// How the bytes of a single ROM bank are used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BankMap {
+    pub bank: usize,
+    /// Address the bank starts at when mapped into memory
+    pub start: u16,
+    /// Number of bytes in the bank, which is less than a full bank
+    /// only if the ROM is truncated
+    pub len: usize,
+    /// Address of the first byte that isn't padding, if any
+    pub first_used: Option<u16>,
+    /// Address of the last byte that isn't padding, if any
+    pub last_used: Option<u16>,
+    /// Runs of padding at least 16 bytes long that aren't reachable code, in address order
+    pub free_blocks: Vec<FreeBlock>,
+    /// What the analysis found in the bank, for banks mapped at startup
+    pub coverage: Option<Coverage>,
+}
+
+impl BankMap {
+    /// Total bytes in the bank's free blocks
+    pub fn free(&self) -> usize {
+        self.free_blocks.iter().map(|block| block.len).sum()
+    }
+
+    /// The largest free block, or the first of them if several are the same size
+    pub fn largest_free_block(&self) -> Option<FreeBlock> {
+        self.free_blocks
+            .iter()
+            .rev()
+            .max_by_key(|block| block.len)
+            .copied()
+    }
+}
+
+/// A summary of the layout of a ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomMap {
+    /// Where the code at the entry point jumps to, if it starts with a jump
+    ///
+    /// Most ROMs have a `NOP` followed by a `JP` at the entry point, as
+    /// there is only space for a few bytes before the cartridge header.
+    pub entry_target: Option<u16>,
+    pub interrupts: Vec<InterruptHandler>,
+    /// Number of banks the cartridge header declares, if the header can be read
+    pub header_banks: Option<usize>,
+    /// The byte used to pad unused space, either 00h or FFh
+    pub padding: u8,
+    pub banks: Vec<BankMap>,
+}
+
+impl RomMap {
+    /// Total bytes in the free blocks of every bank
+    pub fn free(&self) -> usize {
+        self.banks.iter().map(BankMap::free).sum()
+    }
+}
+
+/// The more common of 00h and FFh, which ROMs are usually padded with
+fn find_padding(rom: &[u8]) -> u8 {
+    let zeroes = rom.iter().filter(|value| **value == 0x00).count();
+    let ones = rom.iter().filter(|value| **value == 0xFF).count();
+    if zeroes > ones {
+        0x00
+    } else {
+        0xFF
+    }
+}
+
+fn find_entry_target(decoder: &RuntimeDecoder, rom: &[u8]) -> Option<u16> {
+    let entry_end = usize::from(ENTRY_POINT) + ENTRY_POINT_SIZE;
+    let entry = rom.get(..entry_end)?;
+    let mut address = ENTRY_POINT;
+    while usize::from(address) < entry_end {
+        let instruction = decode_at(decoder, entry, usize::from(address), address)?;
+        if instruction.bytes != [0x00] {
+            return branch_target(&instruction);
+        }
+        address += 1;
+    }
+    None
+}
+
+fn find_interrupts(decoder: &RuntimeDecoder, rom: &[u8], padding: u8) -> Vec<InterruptHandler> {
+    INTERRUPT_VECTORS
+        .iter()
+        .map(|(name, address)| {
+            let offset = usize::from(*address);
+            let vector = rom.get(offset..offset + VECTOR_SIZE).unwrap_or(&[]);
+            let instruction = if vector.iter().all(|value| *value == padding) {
+                None
+            } else {
+                decode_at(decoder, rom, offset, *address)
+            };
+            InterruptHandler {
+                name,
+                address: *address,
+                instruction,
+            }
+        })
+        .collect()
+}
+
+/// Runs of at least `MIN_FREE_RUN` padding bytes which aren't code
+fn find_free_blocks(
+    bank: &[u8],
+    start: u16,
+    padding: u8,
+    is_code: impl Fn(usize) -> bool,
+) -> Vec<FreeBlock> {
+    let mut blocks = Vec::new();
+    let mut run_start = None;
+    for offset in 0..=bank.len() {
+        let free = offset < bank.len() && bank[offset] == padding && !is_code(offset);
+        match run_start {
+            None if free => run_start = Some(offset),
+            Some(run) if !free => {
+                if offset - run >= MIN_FREE_RUN {
+                    blocks.push(FreeBlock {
+                        start: start + run as u16,
+                        len: offset - run,
+                    });
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+    blocks
+}
+
+/// Summarise the layout of a ROM for finding space to add code or data
+///
+/// Code and data coverage is only found for the first two banks, as they
+/// are mapped at startup. Free space is estimated from runs of padding
+/// bytes, so may include data that happens to be made of padding bytes,
+/// or code only reachable from other banks.
+pub fn rom_map(rom: &[u8]) -> RomMap {
+    let decoder = RuntimeDecoder::shared();
+    let padding = find_padding(rom);
+    let startup = &rom[..rom.len().min(STARTUP_ROM_SIZE)];
+    let analysis = CodeAnalysis::from_start(startup, 0);
+    let mut code = vec![false; startup.len()];
+    let mut coverage = [Coverage::default(); STARTUP_ROM_SIZE / BANK_SIZE];
+    for address in analysis.instructions() {
+        let offset = usize::from(address);
+        if let Some(instruction) = decode_at(decoder, startup, offset, address) {
+            for offset in offset..offset + instruction.bytes.len() {
+                code[offset] = true;
+                coverage[offset / BANK_SIZE].code += 1;
+            }
+        }
+    }
+    for region in analysis.data_regions() {
+        let start = usize::from(region.start);
+        for offset in start..start + region.len {
+            let bank = &mut coverage[offset / BANK_SIZE];
+            match region.kind {
+                DataKind::Text => bank.text += 1,
+                DataKind::Graphics => bank.graphics += 1,
+            }
+        }
+    }
+
+    let banks = rom
+        .chunks(BANK_SIZE)
+        .enumerate()
+        .map(|(bank, data)| {
+            let start = if bank == 0 { 0 } else { BANK_SIZE as u16 };
+            let is_code = |offset| code.get(bank * BANK_SIZE + offset) == Some(&true);
+            let address = |offset: usize| start + offset as u16;
+            BankMap {
+                bank,
+                start,
+                len: data.len(),
+                first_used: data.iter().position(|value| *value != padding).map(address),
+                last_used: data
+                    .iter()
+                    .rposition(|value| *value != padding)
+                    .map(address),
+                free_blocks: find_free_blocks(data, start, padding, is_code),
+                coverage: coverage.get(bank).copied(),
+            }
+        })
+        .collect();
+
+    RomMap {
+        entry_target: find_entry_target(decoder, rom),
+        interrupts: find_interrupts(decoder, rom, padding),
+        header_banks: CartridgeHeader::from_data(rom)
+            .ok()
+            .map(|header| header.rom_size / BANK_SIZE),
+        padding,
+        banks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_map() {
+        let mut rom = vec![0xFFu8; 0x10000];
+        // VBlank: RETI
+        rom[0x40] = 0xD9;
+        // NOP; JP 0150h
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x104..0x150].iter_mut().for_each(|value| *value = 0);
+        // 64KiB ROM
+        rom[0x148] = 1;
+        // LD A, 1; JR -4h
+        rom[0x150..0x154].copy_from_slice(&[0x3E, 0x01, 0x18, 0xFC]);
+        rom[0xA123] = 0x12;
+
+        let map = rom_map(&rom);
+
+        assert_eq!(map.entry_target, Some(0x150));
+        assert_eq!(map.padding, 0xFF);
+        assert_eq!(map.header_banks, Some(4));
+        let vblank = map.interrupts[0].instruction.as_ref().unwrap();
+        assert_eq!(vblank.bytes, vec![0xD9]);
+        assert_eq!(map.interrupts[1].instruction, None);
+
+        assert_eq!(map.banks.len(), 4);
+        let bank0 = &map.banks[0];
+        assert_eq!(
+            (bank0.first_used, bank0.last_used),
+            (Some(0x40), Some(0x153))
+        );
+        // RST 38h returns to the next byte, so the padding around the
+        // vectors is reachable, other than between the RETI and 0048h
+        assert_eq!(bank0.coverage.unwrap().code, 0x100 - 7 + 8);
+        assert_eq!(
+            bank0.largest_free_block(),
+            Some(FreeBlock {
+                start: 0x154,
+                len: 0x4000 - 0x154
+            })
+        );
+        let bank2 = &map.banks[2];
+        assert_eq!(
+            (bank2.first_used, bank2.last_used),
+            (Some(0x6123), Some(0x6123))
+        );
+        assert_eq!(bank2.free(), 0x3FFF);
+        assert_eq!(bank2.coverage, None);
+        assert_eq!(map.banks[3].first_used, None);
+        assert_eq!(map.banks[3].free(), 0x4000);
+    }
+}
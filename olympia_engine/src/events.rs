@@ -6,20 +6,29 @@
 //! [`crate::remote`]: ../remote/index.html
 
 use crate::address;
-use crate::gameboy::GBPixel;
+use crate::gameboy::{ErrorContext, GBPixel, Interrupt, StepError};
+#[cfg(feature = "monitor")]
+use crate::monitor::{Breakpoint, BreakpointIdentifier};
 use crate::registers;
+#[cfg(feature = "events")]
+use crate::shared::{Lock, MaybeSend, Shared};
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
+#[cfg(feature = "events")]
 use core::borrow::Borrow;
-use core::cell::RefCell;
-use hashbrown::HashMap;
+#[cfg(not(feature = "events"))]
+use core::marker::PhantomData;
 
-use derive_more::{Constructor, From, TryInto};
+use derive_more::{Constructor, Display, From, TryInto};
 
+#[cfg(feature = "remote")]
 use crate::remote::ExecMode;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents a change in running mode of an emulator
+#[cfg(feature = "remote")]
 pub struct ModeChangeEvent {
     /// Previous execution mode
     pub old_mode: ExecMode,
@@ -27,21 +36,104 @@ pub struct ModeChangeEvent {
     pub new_mode: ExecMode,
 }
 
+#[cfg(feature = "remote")]
 impl ModeChangeEvent {
     pub fn new(old_mode: ExecMode, new_mode: ExecMode) -> ModeChangeEvent {
         ModeChangeEvent { old_mode, new_mode }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A remote emulator stopped because a step failed
+///
+/// The emulator is then in `ExecMode::Error`, and a full report can be
+/// requested with `RemoteEmulator::query_post_mortem`.
+pub struct EmulationErrorEvent {
+    pub error: StepError,
+    /// Where the error happened, and the registers at the time
+    pub context: ErrorContext,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The breakpoints in a remote emulator have been added, removed or toggled
+#[cfg(feature = "monitor")]
+pub struct BreakpointsChangedEvent {
+    /// All breakpoints now set in the emulator
+    pub breakpoints: Vec<(BreakpointIdentifier, Breakpoint)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Periodic report of how fast a remote emulator is running
+pub struct EmulationSpeedEvent {
+    /// Target speed as a percentage of gameboy speed
+    pub speed_percent: u32,
+    /// Frames emulated in the last second
+    pub fps: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A remote emulator has decided the running game is idle, or no longer idle
+///
+/// This is only sent once idle detection is enabled with an `IdlePolicy`.
+/// Frontends can redraw and poll less often while the game is idle.
+pub struct IdleEvent {
+    pub idle: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A remote emulator has been running a single command for longer than allowed
+///
+/// This is only sent once a budget is set with a `WatchdogPolicy`, and at most
+/// once for each command or run to a breakpoint.
+pub struct WatchdogEvent {
+    /// Wall-clock time spent on the command so far
+    pub elapsed: core::time::Duration,
+    /// Whether emulation was paused as a result
+    pub paused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A message was logged while a remote emulator was streaming logs
+///
+/// See the [`logging`] module for the targets the engine logs to.
+///
+/// [`logging`]: ../logging/index.html
+pub struct LogEvent {
+    pub level: log::Level,
+    pub target: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A remote emulator has written battery backed RAM to its save file
+///
+/// This is only sent once a save file is set, each time changed RAM is saved.
+pub struct BatterySaveEvent {
+    /// The save file written to
+    pub path: String,
+    /// Why the save failed, if it did
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A ROM has been loaded into a remote emulator
 pub struct RomLoadedEvent;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A step has happened by a manual user request
 pub struct ManualStepEvent;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Memory has been accessed
 pub enum MemoryEvent {
     /// Memory has been read from
@@ -60,6 +152,14 @@ pub enum MemoryEvent {
         /// The actual new value after the write
         new_value: u8,
     },
+    /// The CPU tried to write to VRAM or OAM while the PPU was using it,
+    /// so memory was not changed
+    BlockedWrite {
+        /// Location the CPU tried to write to
+        address: address::LiteralAddress,
+        /// Value the CPU tried to write
+        value: u8,
+    },
 }
 
 impl MemoryEvent {
@@ -73,6 +173,9 @@ impl MemoryEvent {
             new_value,
         }
     }
+    pub(crate) fn blocked_write(address: address::LiteralAddress, value: u8) -> MemoryEvent {
+        MemoryEvent::BlockedWrite { address, value }
+    }
 }
 
 /// A register has been written to
@@ -81,12 +184,14 @@ impl MemoryEvent {
 /// when an 8-bit write occurs, the event will cover
 /// the 16-bit register the 8-bit register is part of
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RegisterWriteEvent {
     pub reg: registers::WordRegister,
     pub value: u16,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// PPU has entered the HBlank phase
 pub struct HBlankEvent {
     pub current_line: u8,
@@ -94,25 +199,167 @@ pub struct HBlankEvent {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// PPU has entered the VBlank phase
 pub struct VBlankEvent;
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The LCD has been switched on or off
+///
+/// Frontends should blank the display while the LCD is off. The first
+/// frame after the LCD is switched on is not shown on real hardware.
+pub struct LcdPowerEvent {
+    pub enabled: bool,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A single instruction has completed
 pub struct StepCompleteEvent;
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The CPU jumped to an interrupt's handler
+pub struct InterruptEvent {
+    pub interrupt: Interrupt,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Details of an interrupt dispatch, sent while interrupt tracing is enabled
+///
+/// See `GameBoy::set_interrupt_tracing`.
+pub struct InterruptDispatchEvent {
+    pub interrupt: Interrupt,
+    /// CPU clocks elapsed when the interrupt was dispatched
+    pub clocks: u64,
+    /// The address of the instruction the interrupt was taken before
+    pub interrupted_pc: u16,
+    /// The address of the interrupt's handler
+    pub handler: u16,
+    /// Clocks between the interrupt being requested and dispatched
+    ///
+    /// This is `None` if the request was made before tracing was enabled.
+    pub latency: Option<u64>,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, From)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Events from the PPU
 pub enum PPUEvent {
     VBlank(VBlankEvent),
     HBlank(HBlankEvent),
+    LcdPower(LcdPowerEvent),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The cartridge's rumble motor has been switched on or off
+pub struct RumbleEvent {
+    pub active: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, From)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Events from cartridge peripherals
+pub enum PeripheralEvent {
+    Rumble(RumbleEvent),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A region of cartridge memory which can have different banks mapped
+pub enum BankKind {
+    /// ROM at 0x0000-0x3FFF, which only some controllers can switch
+    #[display(fmt = "static ROM")]
+    StaticRom,
+    /// ROM at 0x4000-0x7FFF
+    #[display(fmt = "switchable ROM")]
+    SwitchableRom,
+    /// Cartridge RAM at 0xA000-0xBFFF
+    #[display(fmt = "RAM")]
+    Ram,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The cartridge controller mapped a different bank
+pub struct BankSwitchEvent {
+    /// The bank mapped before the switch
+    pub old: u16,
+    /// The bank mapped after the switch
+    pub new: u16,
+    pub kind: BankKind,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, From)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Events from a cartridge's controller and peripherals
+pub enum CartridgeEvent {
+    Rumble(RumbleEvent),
+    BankSwitch(BankSwitchEvent),
+}
+
+impl From<PeripheralEvent> for CartridgeEvent {
+    fn from(pe: PeripheralEvent) -> CartridgeEvent {
+        match pe {
+            PeripheralEvent::Rumble(e) => CartridgeEvent::Rumble(e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A Game Boy Printer has finished printing an image
+pub struct PrintEvent {
+    /// Width of the image in pixels
+    pub width: usize,
+    /// Height of the image in pixels
+    pub height: usize,
+    /// Shade of each pixel, row by row, from 0 (white) to 3 (black)
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Constructor)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The ROM sent a byte to a serial console
+pub struct SerialOutputEvent {
+    /// The byte sent
+    pub value: u8,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, From)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Events from devices connected to the serial port
+pub enum SerialEvent {
+    Print(PrintEvent),
+    Output(SerialOutputEvent),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 /// Indicates whether an event handler should repeat
 pub struct Repeat(pub bool);
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Default)]
+/// The order event handlers run in
+///
+/// Handlers with a higher priority run first, and handlers with the same
+/// priority run in the order they were added. Handlers added without a
+/// priority have the default priority of 0.
+pub struct Priority(pub i32);
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// Whether an event should be passed on to lower priority handlers
+pub enum Propagation {
+    /// Pass the event on
+    Continue,
+    /// Consume the event, so lower priority handlers don't see it
+    Stop,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, From, TryInto)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Gameboy events that frontends might be interested in
 pub enum Event {
     /// A write occured to a memory mapped location
@@ -123,8 +370,22 @@ pub enum Event {
     HBlank(HBlankEvent),
     /// The PPU reached its vblank cycle
     VBlank(VBlankEvent),
+    /// The LCD was switched on or off
+    LcdPower(LcdPowerEvent),
     /// An instruction cycle completed
     StepComplete(StepCompleteEvent),
+    /// An interrupt was dispatched
+    Interrupt(InterruptEvent),
+    /// An interrupt was dispatched while interrupt tracing is enabled
+    InterruptDispatch(InterruptDispatchEvent),
+    /// The cartridge rumble motor changed state
+    Rumble(RumbleEvent),
+    /// The cartridge controller mapped a different ROM or RAM bank
+    BankSwitch(BankSwitchEvent),
+    /// A printer on the serial port printed an image
+    Print(PrintEvent),
+    /// A serial console received a byte from the ROM
+    SerialOutput(SerialOutputEvent),
 }
 
 impl From<PPUEvent> for Event {
@@ -132,44 +393,173 @@ impl From<PPUEvent> for Event {
         match ppue {
             PPUEvent::VBlank(e) => Event::VBlank(e),
             PPUEvent::HBlank(e) => Event::HBlank(e),
+            PPUEvent::LcdPower(e) => Event::LcdPower(e),
+        }
+    }
+}
+
+impl From<CartridgeEvent> for Event {
+    fn from(ce: CartridgeEvent) -> Event {
+        match ce {
+            CartridgeEvent::Rumble(e) => Event::Rumble(e),
+            CartridgeEvent::BankSwitch(e) => Event::BankSwitch(e),
+        }
+    }
+}
+
+impl From<SerialEvent> for Event {
+    fn from(se: SerialEvent) -> Event {
+        match se {
+            SerialEvent::Print(e) => Event::Print(e),
+            SerialEvent::Output(e) => Event::SerialOutput(e),
         }
     }
 }
 
 /// A method to handle a local event
+#[cfg(not(feature = "sync"))]
 pub type EventHandler<T> = Box<dyn Fn(&T) + 'static>;
 
+/// A method to handle a local event
+///
+/// Handlers must be `Send` so the emulator can move between threads
+#[cfg(feature = "sync")]
+pub type EventHandler<T> = Box<dyn Fn(&T) + Send + 'static>;
+
+/// A method to handle a local event, which may stop it reaching other handlers
+#[cfg(not(feature = "sync"))]
+pub type InterceptHandler<T> = Box<dyn Fn(&T) -> Propagation + 'static>;
+
+/// A method to handle a local event, which may stop it reaching other handlers
+///
+/// Handlers must be `Send` so the emulator can move between threads
+#[cfg(feature = "sync")]
+pub type InterceptHandler<T> = Box<dyn Fn(&T) -> Propagation + Send + 'static>;
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
 /// An identifer for a event handler
 pub struct EventHandlerId(pub u64);
 
+#[derive(Debug, Default)]
+/// A count of CPU clocks shared between an emulator and its event emitters
+#[cfg(feature = "events")]
+pub struct EventClock(Lock<u64>);
+
+#[cfg(feature = "events")]
+impl EventClock {
+    pub fn new(clocks: u64) -> EventClock {
+        EventClock(Lock::new(clocks))
+    }
+
+    /// The number of CPU clocks elapsed
+    pub fn clocks(&self) -> u64 {
+        *self.0.borrow()
+    }
+
+    pub(crate) fn set(&self, clocks: u64) {
+        *self.0.borrow_mut() = clocks
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// When an event was emitted
+///
+/// Timestamps order by sequence number first, so events emitted
+/// during the same clock still sort in the order they happened.
+pub struct EventTimestamp {
+    /// The number of events emitted by the same emitter before this one
+    pub sequence: u64,
+    /// CPU clocks elapsed when the event was emitted, or 0 if the
+    /// emitter has no clock
+    pub clocks: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// An event along with when it was emitted
+pub struct TimestampedEvent<T> {
+    pub timestamp: EventTimestamp,
+    pub event: T,
+}
+
+#[cfg(feature = "events")]
+enum Handler<T> {
+    Plain(EventHandler<T>),
+    Timestamped(EventHandler<TimestampedEvent<T>>),
+    Intercept(InterceptHandler<T>),
+}
+
 /// A producer of events that can be listened to
+///
+/// Every event is given a sequence number, and if the emitter was created
+/// with [`EventEmitter::with_clock`], the CPU clock it was emitted at.
+/// Listen with [`EventEmitter::on_timestamped`] to receive these.
+///
+/// Handlers run in order of their [`Priority`]. Handlers added with
+/// [`EventEmitter::intercept`] can consume an event, so that lower
+/// priority handlers never see it.
+///
+/// With the `sync` feature, event handlers must not emit events
+/// from the emitter that called them.
+#[cfg(feature = "events")]
 pub struct EventEmitter<T> {
-    event_handlers: RefCell<HashMap<EventHandlerId, EventHandler<T>>>,
-    next_event_handler_id: RefCell<u64>,
-    is_emitting: RefCell<bool>,
-    queued_handlers: RefCell<Vec<(EventHandlerId, EventHandler<T>)>>,
-    queued_removals: RefCell<Vec<EventHandlerId>>,
+    /// Handlers in the order they run
+    event_handlers: Lock<Vec<(EventHandlerId, Priority, Handler<T>)>>,
+    next_event_handler_id: Lock<u64>,
+    is_emitting: Lock<bool>,
+    queued_handlers: Lock<Vec<(EventHandlerId, Priority, Handler<T>)>>,
+    queued_removals: Lock<Vec<EventHandlerId>>,
+    next_sequence: Lock<u64>,
+    clock: Option<Shared<EventClock>>,
 }
 
+#[cfg(feature = "events")]
 impl<'a, T> EventEmitter<T> {
     pub fn new() -> EventEmitter<T> {
         EventEmitter {
-            event_handlers: RefCell::new(HashMap::new()),
-            next_event_handler_id: RefCell::new(0),
-            is_emitting: RefCell::new(false),
-            queued_handlers: RefCell::new(Vec::new()),
-            queued_removals: RefCell::new(Vec::new()),
+            event_handlers: Lock::new(Vec::new()),
+            next_event_handler_id: Lock::new(0),
+            is_emitting: Lock::new(false),
+            queued_handlers: Lock::new(Vec::new()),
+            queued_removals: Lock::new(Vec::new()),
+            next_sequence: Lock::new(0),
+            clock: None,
+        }
+    }
+
+    /// Create an emitter that timestamps events with the given clock
+    pub fn with_clock(clock: Shared<EventClock>) -> EventEmitter<T> {
+        EventEmitter {
+            clock: Some(clock),
+            ..EventEmitter::new()
         }
     }
 
     /// Listen to events of a given type
     pub fn on(&self, f: EventHandler<T>) -> EventHandlerId {
+        self.add_handler(Priority::default(), Handler::Plain(f))
+    }
+
+    /// Listen to events of a given type, along with when they were emitted
+    pub fn on_timestamped(&self, f: EventHandler<TimestampedEvent<T>>) -> EventHandlerId {
+        self.add_handler(Priority::default(), Handler::Timestamped(f))
+    }
+
+    /// Listen to events before handlers with a lower priority, optionally consuming them
+    ///
+    /// If `f` returns `Propagation::Stop`, handlers with a lower priority, or added
+    /// later with the same priority, aren't called for that event.
+    pub fn intercept(&self, priority: Priority, f: InterceptHandler<T>) -> EventHandlerId {
+        self.add_handler(priority, Handler::Intercept(f))
+    }
+
+    fn add_handler(&self, priority: Priority, f: Handler<T>) -> EventHandlerId {
         let event_handler_id = self.next_handler_id();
         if *self.is_emitting.borrow() {
-            self.queue_handler(event_handler_id, f)
+            self.queue_handler(event_handler_id, priority, f)
         } else {
-            self.register_handler(event_handler_id, f)
+            self.register_handler(event_handler_id, priority, f)
         }
         event_handler_id
     }
@@ -179,40 +569,114 @@ impl<'a, T> EventEmitter<T> {
         if *self.is_emitting.borrow() {
             self.queued_removals.borrow_mut().push(id);
         } else {
-            self.event_handlers.borrow_mut().remove(&id);
+            remove_handler(&mut self.event_handlers.borrow_mut(), id);
         }
     }
 
     fn next_handler_id(&self) -> EventHandlerId {
-        EventHandlerId(self.next_event_handler_id.replace_with(|old| *old + 1))
+        let mut next_id = self.next_event_handler_id.borrow_mut();
+        let id = EventHandlerId(*next_id);
+        *next_id += 1;
+        id
     }
 
-    fn register_handler(&self, event_handler_id: EventHandlerId, f: EventHandler<T>) {
-        self.event_handlers.borrow_mut().insert(event_handler_id, f);
+    fn register_handler(
+        &self,
+        event_handler_id: EventHandlerId,
+        priority: Priority,
+        f: Handler<T>,
+    ) {
+        insert_handler(
+            &mut self.event_handlers.borrow_mut(),
+            (event_handler_id, priority, f),
+        );
     }
 
-    fn queue_handler(&self, event_handler_id: EventHandlerId, f: EventHandler<T>) {
+    fn queue_handler(&self, event_handler_id: EventHandlerId, priority: Priority, f: Handler<T>) {
         self.queued_handlers
             .borrow_mut()
-            .push((event_handler_id, f));
+            .push((event_handler_id, priority, f));
+    }
+
+    fn next_timestamp(&self) -> EventTimestamp {
+        let mut next_sequence = self.next_sequence.borrow_mut();
+        let sequence = *next_sequence;
+        *next_sequence += 1;
+        EventTimestamp {
+            sequence,
+            clocks: self.clock.as_ref().map_or(0, |clock| clock.clocks()),
+        }
     }
 
     /// Notify all listeners of a given event
-    pub fn emit(&self, evt: T) {
+    ///
+    /// Returns `Propagation::Stop` if a handler consumed the event, so the
+    /// emitter can skip its own handling of it.
+    pub fn emit(&self, evt: T) -> Propagation {
+        let timestamped = TimestampedEvent {
+            timestamp: self.next_timestamp(),
+            event: evt,
+        };
         self.is_emitting.replace(true);
-        for handler in self.event_handlers.borrow().values() {
-            handler(&evt);
+        let mut propagation = Propagation::Continue;
+        for (_, _, handler) in self.event_handlers.borrow().iter() {
+            match handler {
+                Handler::Plain(f) => f(&timestamped.event),
+                Handler::Timestamped(f) => f(&timestamped),
+                Handler::Intercept(f) => propagation = f(&timestamped.event),
+            }
+            if propagation == Propagation::Stop {
+                break;
+            }
         }
         self.is_emitting.replace(false);
 
         let mut event_handlers = self.event_handlers.borrow_mut();
-        for (id, f) in self.queued_handlers.borrow_mut().drain(..) {
-            event_handlers.insert(id, f);
+        for handler in self.queued_handlers.borrow_mut().drain(..) {
+            insert_handler(&mut event_handlers, handler);
         }
 
         for id in self.queued_removals.borrow_mut().drain(..) {
-            event_handlers.remove(&id);
+            remove_handler(&mut event_handlers, id);
         }
+        propagation
+    }
+}
+
+/// Add a handler after those with the same or a higher priority
+#[cfg(feature = "events")]
+fn insert_handler<H>(
+    handlers: &mut Vec<(EventHandlerId, Priority, H)>,
+    handler: (EventHandlerId, Priority, H),
+) {
+    let index = handlers
+        .iter()
+        .position(|(_, priority, _)| *priority < handler.1)
+        .unwrap_or(handlers.len());
+    handlers.insert(index, handler);
+}
+
+#[cfg(feature = "events")]
+fn remove_handler<H>(handlers: &mut Vec<(EventHandlerId, Priority, H)>, id: EventHandlerId) {
+    handlers.retain(|(handler_id, _, _)| *handler_id != id);
+}
+
+/// Stands in for the event emitter when the `events` feature is disabled
+///
+/// Events can't be listened to, and are dropped as soon as they are emitted,
+/// so emitting them costs nothing.
+#[cfg(not(feature = "events"))]
+pub struct EventEmitter<T>(PhantomData<fn(T)>);
+
+#[cfg(not(feature = "events"))]
+impl<T> EventEmitter<T> {
+    pub fn new() -> EventEmitter<T> {
+        EventEmitter(PhantomData)
+    }
+
+    #[inline(always)]
+    pub fn emit(&self, _evt: T) -> Propagation {
+        Propagation::Continue
     }
 }
 
@@ -223,13 +687,86 @@ impl<'a, T> Default for EventEmitter<T> {
 }
 
 /// Propagate events from one event emitter to another
+#[cfg(feature = "events")]
 pub fn propagate_events<I, O, E>(inner_events: &EventEmitter<I>, outer_events: E) -> EventHandlerId
 where
     I: Into<O> + Clone,
-    E: 'static + Borrow<EventEmitter<O>>,
+    E: 'static + Borrow<EventEmitter<O>> + MaybeSend,
 {
     inner_events.on(Box::new(move |inner_item| {
         let cloned: I = inner_item.clone();
-        outer_events.borrow().emit(cloned.into())
+        outer_events.borrow().emit(cloned.into());
     }))
 }
+
+#[cfg(all(test, feature = "events"))]
+mod tests {
+    use super::*;
+
+    type Log = Shared<Lock<Vec<&'static str>>>;
+
+    fn logger(log: &Log, name: &'static str) -> EventHandler<u8> {
+        let log = Shared::clone(log);
+        Box::new(move |_| log.borrow_mut().push(name))
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let log: Log = Shared::new(Lock::new(Vec::new()));
+        let emitter: EventEmitter<u8> = EventEmitter::new();
+        emitter.on(logger(&log, "first default"));
+        let low_log = Shared::clone(&log);
+        emitter.intercept(
+            Priority(-1),
+            Box::new(move |_| {
+                low_log.borrow_mut().push("low");
+                Propagation::Continue
+            }),
+        );
+        let high_log = Shared::clone(&log);
+        emitter.intercept(
+            Priority(10),
+            Box::new(move |_| {
+                high_log.borrow_mut().push("high");
+                Propagation::Continue
+            }),
+        );
+        emitter.on(logger(&log, "second default"));
+
+        assert_eq!(emitter.emit(0), Propagation::Continue);
+
+        assert_eq!(
+            *Lock::borrow(&log),
+            vec!["high", "first default", "second default", "low"]
+        );
+    }
+
+    #[test]
+    fn test_intercept_consumes_event() {
+        let log: Log = Shared::new(Lock::new(Vec::new()));
+        let emitter: EventEmitter<u8> = EventEmitter::new();
+        emitter.on(logger(&log, "default"));
+        let consumer_log = Shared::clone(&log);
+        let consumer = emitter.intercept(
+            Priority(1),
+            Box::new(move |evt| {
+                consumer_log.borrow_mut().push("consumer");
+                if *evt == 0 {
+                    Propagation::Stop
+                } else {
+                    Propagation::Continue
+                }
+            }),
+        );
+
+        assert_eq!(emitter.emit(0), Propagation::Stop);
+        assert_eq!(emitter.emit(1), Propagation::Continue);
+        emitter.off(consumer);
+        assert_eq!(emitter.emit(0), Propagation::Continue);
+
+        assert_eq!(
+            *Lock::borrow(&log),
+            vec!["consumer", "consumer", "default", "default"]
+        );
+    }
+}
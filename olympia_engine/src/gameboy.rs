@@ -14,28 +14,50 @@
 //! [Gameboy::new]: struct.GameBoy.html#method.new
 pub(crate) mod cpu;
 mod dma;
+mod history;
+mod input;
+mod io_observer;
+mod joypad;
 pub(crate) mod memory;
+mod micro_ops;
+pub(crate) mod oam_bug;
+mod overclock;
 mod ppu;
+mod profiler;
+mod rng;
+mod serial;
 mod timer;
 
-pub use cpu::CYCLE_FREQ;
+pub use cpu::{Interrupt, InterruptParseError, CYCLE_FREQ};
+pub use input::{InputLatching, InputQueue};
+pub use io_observer::{IoObserver, IoObserverId, IoWrite};
+pub use joypad::Button;
 pub use memory::{MemoryError, MemoryRegion, MemoryResult, VRAM};
-pub use ppu::{GBPixel, Palette};
+pub use micro_ops::MicroOp;
+pub use overclock::MAX_OVERCLOCK_CYCLES;
+pub use ppu::{GBPixel, ModeLengths, Palette, RenderLayers};
+pub use profiler::CycleProfile;
+pub use rng::{Rng, DEFAULT_SEED};
+pub use serial::SerialDevice;
 
 use crate::events;
 use crate::gameboy::cpu::Cpu;
 use crate::gameboy::cpu::PowerSavingMode;
-use crate::gameboy::dma::DmaUnit;
+use crate::gameboy::dma::{DmaUnit, HdmaUnit};
+use crate::gameboy::profiler::Component;
 use crate::instructions;
 use crate::instructionsn as new_instructions;
+use crate::logging;
 use crate::registers;
 use crate::registers::WordRegister as wr;
 use crate::rom;
 use crate::rom::TargetConsole;
+use crate::shared::Shared;
 
 use alloc::boxed::Box;
-use alloc::rc::Rc;
+use alloc::vec::Vec;
 use core::convert::TryFrom;
+use core::ops::RangeInclusive;
 use derive_more::Display;
 use olympia_core::address;
 
@@ -61,14 +83,73 @@ pub struct GameBoy {
     pub(crate) mem: memory::Memory,
     pub(crate) ppu: ppu::Ppu,
     pub(crate) timer: timer::Timer,
+    pub(crate) serial: serial::Serial,
+    overclock: overclock::Overclock,
+    profiler: profiler::Profiler,
+    /// Whether the model being emulated has the OAM corruption bug
+    has_oam_bug: bool,
+    /// Whether to emulate the OAM corruption bug, where the model has it
+    oam_corruption: bool,
     dma: DmaUnit,
-    runtime_decoder: Rc<new_instructions::RuntimeDecoder>,
+    hdma: HdmaUnit,
+    history: history::History,
+    runtime_decoder: &'static new_instructions::RuntimeDecoder,
     clocks_elapsed: u64,
+    /// Clocks elapsed, shared with the event emitter to timestamp events
+    #[cfg(feature = "events")]
+    clock: Shared<events::EventClock>,
     time_elapsed: f64,
-    pub events: Rc<events::EventEmitter<events::Event>>,
+    dispatched_interrupt: Option<cpu::Interrupt>,
+    input: InputQueue,
+    input_latching: InputLatching,
+    /// PPU frame count when input was last latched
+    input_latched_frame: u64,
+    rng: Rng,
+    /// Clock each pending interrupt was requested at, while tracing interrupts
+    interrupt_requests: Option<[Option<u64>; 5]>,
+    /// Interrupt flags when requests were last checked
+    requested_iflag: u8,
+    /// Records micro-ops while running `GameBoy::traced_step`
+    micro_ops: Option<micro_ops::MicroOpRecorder>,
+    pub events: Shared<events::EventEmitter<events::Event>>,
 }
 
-#[derive(PartialEq, Eq, Debug, Display)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The values of all 16-bit CPU registers at a point in time
+pub struct RegisterSnapshot {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The state of the gameboy when an instruction failed
+pub struct ErrorContext {
+    /// Address of the instruction that failed
+    pub pc: u16,
+    /// ROM bank mapped at `pc`, or `None` if `pc` is outside cartridge ROM
+    pub bank: Option<u16>,
+    /// Register values at the time of failure
+    pub registers: RegisterSnapshot,
+}
+
+impl core::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "0x{:04X}", self.pc)?;
+        if let Some(bank) = self.bank {
+            write!(f, " (bank {})", bank)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents an error that occurred while performing
 /// an emulated instruction.
 pub enum StepError {
@@ -76,8 +157,18 @@ pub enum StepError {
     #[display(fmt = "Accessing invalid memory location: {0}", _0)]
     Memory(memory::MemoryError),
     /// Opcodes that don't map to a valid instruction
-    #[display(fmt = "Attempted to exec invalid opcode {}", _0)]
-    InvalidOpcode(u8),
+    #[display(fmt = "invalid opcode {:02X} at {}", _0, _1)]
+    InvalidOpcode(u8, ErrorContext),
+}
+
+impl StepError {
+    /// The gameboy state when this error occurred, if known
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            StepError::InvalidOpcode(_, context) => Some(context),
+            StepError::Memory(_) => None,
+        }
+    }
 }
 
 #[cfg(feature = "std")]
@@ -104,25 +195,281 @@ impl GameBoy {
     ///   or exclusive.
     ///
     pub fn new(cartridge: rom::Cartridge, model: GameBoyModel) -> GameBoy {
-        let gb = GameBoy {
+        let color_mode = model.supports_color() && cartridge.target != TargetConsole::GameBoyOnly;
+        #[cfg(feature = "events")]
+        let clock = Shared::new(events::EventClock::default());
+        let mut gb = GameBoy {
             cpu: Cpu::new(model, cartridge.target),
             mem: memory::Memory::new(cartridge),
             dma: Default::default(),
+            hdma: Default::default(),
+            history: Default::default(),
             ppu: Default::default(),
             timer: timer::Timer::default(),
-            runtime_decoder: Rc::new(new_instructions::RuntimeDecoder::new()),
+            serial: serial::Serial::default(),
+            overclock: overclock::Overclock::default(),
+            profiler: profiler::Profiler::new(),
+            has_oam_bug: model.has_oam_bug(),
+            oam_corruption: false,
+            runtime_decoder: new_instructions::RuntimeDecoder::shared(),
             clocks_elapsed: 0,
+            #[cfg(feature = "events")]
+            clock: clock.clone(),
             time_elapsed: 0.0,
-            events: Rc::new(events::EventEmitter::new()),
+            dispatched_interrupt: None,
+            input: InputQueue::new(),
+            input_latching: InputLatching::default(),
+            input_latched_frame: 0,
+            rng: Rng::default(),
+            interrupt_requests: None,
+            requested_iflag: 0,
+            micro_ops: None,
+            #[cfg(feature = "events")]
+            events: Shared::new(events::EventEmitter::with_clock(clock)),
+            #[cfg(not(feature = "events"))]
+            events: Shared::new(events::EventEmitter::new()),
         };
-
-        events::propagate_events(&gb.cpu.events, gb.events.clone());
-        events::propagate_events(&gb.mem.events, gb.events.clone());
-        events::propagate_events(&gb.ppu.events, gb.events.clone());
+        gb.mem.registers_mut().color_mode = color_mode;
+        gb.mem.registers_mut().stat_write_bug = model.has_stat_write_bug();
+
+        #[cfg(feature = "events")]
+        {
+            events::propagate_events(&gb.cpu.events, gb.events.clone());
+            events::propagate_events(&gb.mem.events, gb.events.clone());
+            events::propagate_events(&gb.ppu.events, gb.events.clone());
+            events::propagate_events(&gb.mem.cartridge().events, gb.events.clone());
+            events::propagate_events(&gb.serial.events, gb.events.clone());
+        }
 
         gb
     }
 
+    /// Connect a device to the serial port, replacing any existing device
+    pub fn connect_serial<D: SerialDevice + 'static>(&mut self, device: D) {
+        self.serial.connect(Box::new(device));
+    }
+
+    /// Disconnect the device attached to the serial port, if any
+    pub fn disconnect_serial(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.serial.disconnect()
+    }
+
+    /// Call `observer` when a write changes the value at an address in `addresses`
+    ///
+    /// This is meant for hardware effects a frontend can show, such as a
+    /// rumble motor or infrared LED. Like events, writes made with
+    /// `set_memory_u8` aren't observed.
+    pub fn observe_io<O: IoObserver + 'static>(
+        &mut self,
+        addresses: RangeInclusive<u16>,
+        observer: O,
+    ) -> IoObserverId {
+        self.mem.io_observers.add(addresses, Box::new(observer))
+    }
+
+    /// Stop calling an observer added with `observe_io`, returning whether it was found
+    pub fn remove_io_observer(&mut self, id: IoObserverId) -> bool {
+        self.mem.io_observers.remove(id)
+    }
+
+    /// Set whether a joypad button is held down
+    ///
+    /// Pressing a button raises the joypad interrupt if the game is
+    /// reading that group of buttons, and wakes the CPU from STOP mode.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let triggered = joypad::set_button(self.mem.registers_mut(), button, pressed);
+        if triggered && self.cpu.power_saving == PowerSavingMode::Stop {
+            self.set_power_saving_mode(PowerSavingMode::None);
+        }
+    }
+
+    /// A handle to the queue of button changes waiting to be applied
+    ///
+    /// Changes pushed to it are applied to the joypad at a time set by
+    /// [`set_input_latching`], rather than immediately as with [`set_button`].
+    ///
+    /// [`set_input_latching`]: #method.set_input_latching
+    /// [`set_button`]: #method.set_button
+    pub fn input_queue(&self) -> InputQueue {
+        self.input.clone()
+    }
+
+    /// Extra CPU cycles run in each line of the display
+    pub fn overclock(&self) -> u16 {
+        self.overclock.extra_cycles_per_line()
+    }
+
+    /// Run extra CPU cycles in each line of the display, to reduce slowdown
+    ///
+    /// This is experimental. A line is normally 114 cycles, so 114 extra
+    /// cycles runs the CPU at twice its usual speed. The extra cycles don't
+    /// advance the PPU, timer, serial port or elapsed clocks, so frames and
+    /// timer interrupts keep their usual timing, but games which rely on how
+    /// long their code takes may misbehave. Values above
+    /// `MAX_OVERCLOCK_CYCLES` are limited to it, and 0 turns overclocking off.
+    pub fn set_overclock(&mut self, extra_cycles_per_line: u16) {
+        self.overclock
+            .set_extra_cycles_per_line(extra_cycles_per_line);
+    }
+
+    /// When changes from the input queue are applied to the joypad
+    pub fn input_latching(&self) -> InputLatching {
+        self.input_latching
+    }
+
+    /// Set when changes from the input queue are applied to the joypad
+    ///
+    /// This defaults to `InputLatching::Frame`.
+    pub fn set_input_latching(&mut self, latching: InputLatching) {
+        self.input_latching = latching;
+    }
+
+    /// Apply queued button changes if input is latched now
+    ///
+    /// With frame latching, changes are applied once VBlank has started. When
+    /// there are no frames to wait for, because the LCD is off or the CPU is
+    /// stopped until a button is pressed, changes are applied immediately.
+    fn latch_input(&mut self) {
+        let frame = self.ppu.frame_count();
+        let latch = match self.input_latching {
+            InputLatching::Immediate => true,
+            InputLatching::Frame => {
+                frame != self.input_latched_frame
+                    || !self.ppu.is_enabled(&self.mem)
+                    || self.cpu.power_saving == PowerSavingMode::Stop
+            }
+        };
+        if !latch {
+            return;
+        }
+        self.input_latched_frame = frame;
+        for (button, pressed) in self.input.take() {
+            self.set_button(button, pressed);
+        }
+    }
+
+    /// Whether the CPU is blocked from accessing VRAM and OAM while the PPU uses them
+    pub fn ppu_access_restricted(&self) -> bool {
+        self.mem.restrict_ppu_access
+    }
+
+    /// Enable or disable blocking CPU access to VRAM and OAM while the PPU uses them
+    ///
+    /// This is enabled by default, as on real hardware, VRAM reads return 0xFF
+    /// while the PPU is drawing, and OAM reads return 0xFF while the PPU is also
+    /// scanning for sprites. Writes at these times are ignored. Disabling this is
+    /// less accurate, but can make homebrew with timing bugs easier to debug.
+    pub fn set_ppu_access_restricted(&mut self, restricted: bool) {
+        self.mem.restrict_ppu_access = restricted;
+    }
+
+    /// Whether the OAM corruption bug is emulated
+    pub fn oam_corruption(&self) -> bool {
+        self.oam_corruption
+    }
+
+    /// Enable or disable emulating the OAM corruption bug
+    ///
+    /// On the original Game Boy, Game Boy Pocket and Super Game Boy,
+    /// incrementing or decrementing a 16-bit register holding an address in
+    /// 0xFE00-0xFEFF while the PPU scans OAM corrupts the sprites it is reading.
+    /// This includes `INC`/`DEC` of a register pair, `LD` through `(HL+)` or
+    /// `(HL-)`, and pushing or popping while SP points there.
+    /// A few games and test ROMs rely on or detect this. This is disabled by
+    /// default, and has no effect on Game Boy Color and Advance models, which
+    /// don't have the bug.
+    pub fn set_oam_corruption(&mut self, enabled: bool) {
+        self.oam_corruption = enabled;
+    }
+
+    /// Corrupt OAM if a 16-bit increment or decrement of `addr` triggers the OAM bug
+    ///
+    /// `access` is how memory was accessed at `addr` in the same cycle.
+    pub(crate) fn oam_bug_trigger(&mut self, addr: u16, access: oam_bug::Access) {
+        if !self.oam_corruption || !self.has_oam_bug || !(0xFE00..=0xFEFF).contains(&addr) {
+            return;
+        }
+        if let Some(row) = self.ppu.oam_scan_row(&self.mem) {
+            log::trace!(target: logging::PPU, "OAM corrupted at row {} by {:?}", row, access);
+            self.mem.corrupt_oam_row(row, access);
+        }
+    }
+
+    /// Layers currently drawn into the framebuffer
+    pub fn render_layers(&self) -> RenderLayers {
+        self.ppu.render_layers()
+    }
+
+    /// Choose which of the background, window and sprites are drawn
+    ///
+    /// This only affects the framebuffer, to help find which layer a
+    /// glitch comes from. The game and the PPU timing behave the same.
+    pub fn set_render_layers(&mut self, layers: RenderLayers) {
+        self.ppu.set_render_layers(layers);
+    }
+
+    /// The generator used for anything the emulator fills with noise
+    pub fn rng(&self) -> &Rng {
+        &self.rng
+    }
+
+    /// Replace the generator used for anything the emulator fills with noise
+    ///
+    /// Use `Rng::new(seed)` to choose a seed, or a generator saved from
+    /// [`rng`] to continue its sequence.
+    ///
+    /// [`rng`]: #method.rng
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    /// Fill work RAM and high RAM with values from the gameboy's [`Rng`]
+    ///
+    /// RAM starts as zeroes, but on real hardware it holds an unpredictable
+    /// pattern at power on. Call this before the first step to find code that
+    /// reads RAM before writing it. The pattern only depends on the seed, so
+    /// runs can be repeated exactly. No memory events are emitted.
+    ///
+    /// ```
+    /// use olympia_engine::gameboy::{GameBoy, GameBoyModel, Rng};
+    /// use olympia_engine::rom::Cartridge;
+    ///
+    /// let mut first = GameBoy::new(
+    ///     Cartridge::from_data(vec![0u8; 0x8000]).unwrap(),
+    ///     GameBoyModel::GameBoy,
+    /// );
+    /// let mut second = GameBoy::new(
+    ///     Cartridge::from_data(vec![0u8; 0x8000]).unwrap(),
+    ///     GameBoyModel::GameBoy,
+    /// );
+    /// first.set_rng(Rng::new(5));
+    /// second.set_rng(Rng::new(5));
+    /// first.randomize_ram();
+    /// second.randomize_ram();
+    /// assert_eq!(first.get_memory_u8(0xC000), second.get_memory_u8(0xC000));
+    /// ```
+    pub fn randomize_ram(&mut self) {
+        self.mem.randomize_ram(&mut self.rng);
+    }
+
+    /// A hash of the shades in the framebuffer, to compare frames between runs
+    ///
+    /// The framebuffer is only complete once VBlank starts. The hash only
+    /// depends on the pixels, so is the same on every platform and version.
+    pub fn frame_hash(&self) -> u64 {
+        self.ppu.frame_hash()
+    }
+
+    /// A hash of the [`frame_hash`] of every frame completed so far
+    ///
+    /// Two runs of a ROM with the same input draw the same frames if this
+    /// matches once they have drawn the same number of frames.
+    ///
+    /// [`frame_hash`]: #method.frame_hash
+    pub fn run_hash(&self) -> u64 {
+        self.ppu.run_hash()
+    }
+
     pub fn add_exec_time(&mut self, time: f64) {
         self.time_elapsed += time;
     }
@@ -160,7 +507,7 @@ impl GameBoy {
 
     /// Write a value to the given memory address.
     ///
-    /// This should only be used by the gameboy engine as it will trigger read breakpoints
+    /// This should only be used by the gameboy engine as it will trigger write breakpoints
     pub(crate) fn write_memory_u8<A: Into<address::LiteralAddress>>(
         &mut self,
         addr: A,
@@ -169,6 +516,38 @@ impl GameBoy {
         self.mem.write_u8(addr, val)
     }
 
+    /// Read a value from the given memory address as part of an instruction
+    ///
+    /// Locations locked by the PPU read as 0xFF, as they do for the CPU.
+    /// This is recorded as a micro-op while tracing.
+    pub(crate) fn exec_read_memory_u8<A: Into<address::LiteralAddress>>(
+        &mut self,
+        addr: A,
+    ) -> memory::MemoryResult<u8> {
+        let addr = addr.into();
+        let value = self.mem.cpu_read_u8(addr)?;
+        if let Some(recorder) = self.micro_ops.as_mut() {
+            recorder.read(addr.0, value);
+        }
+        Ok(value)
+    }
+
+    /// Write a value to the given memory address as part of an instruction
+    ///
+    /// Writes to locations locked by the PPU are ignored, as they are for the CPU.
+    /// This is recorded as a micro-op while tracing.
+    pub(crate) fn exec_write_memory_u8<A: Into<address::LiteralAddress>>(
+        &mut self,
+        addr: A,
+        val: u8,
+    ) -> memory::MemoryResult<()> {
+        let addr = addr.into();
+        if let Some(recorder) = self.micro_ops.as_mut() {
+            recorder.write(addr.0, val);
+        }
+        self.mem.cpu_write_u8(addr, val)
+    }
+
     /// Read an value at the given memory address as a signed integer.
     ///
     /// This is primarily useful for reading the target of a JR instruction.
@@ -218,6 +597,42 @@ impl GameBoy {
         Ok(())
     }
 
+    /// Fill `buf` with the memory starting at `start`
+    ///
+    /// Addresses wrap around to 0x0000 after 0xFFFF. Fails on the first address that
+    /// cannot be read. This should be used by external consumers, as it will not
+    /// trigger read breakpoints
+    pub fn get_memory_chunk<A: Into<address::LiteralAddress>>(
+        &self,
+        start: A,
+        buf: &mut [u8],
+    ) -> memory::MemoryResult<()> {
+        let mut addr = start.into();
+        for value in buf.iter_mut() {
+            *value = self.mem.read_u8_internal(addr)?;
+            addr = addr.next();
+        }
+        Ok(())
+    }
+
+    /// Write `data` to memory starting at `start`
+    ///
+    /// Addresses wrap around to 0x0000 after 0xFFFF. Stops at the first address that
+    /// cannot be written, leaving earlier writes in place. This should be used by
+    /// external consumers, as it will not trigger write breakpoints
+    pub fn set_memory_chunk<A: Into<address::LiteralAddress>>(
+        &mut self,
+        start: A,
+        data: &[u8],
+    ) -> memory::MemoryResult<()> {
+        let mut addr = start.into();
+        for value in data.iter() {
+            self.mem.write_u8_internal(addr, *value)?;
+            addr = addr.next();
+        }
+        Ok(())
+    }
+
     pub(crate) fn exec_write_memory_u16<A: Into<address::LiteralAddress>>(
         &mut self,
         target: A,
@@ -226,9 +641,9 @@ impl GameBoy {
         let addr = target.into();
         let bytes = value.to_le_bytes();
 
-        self.mem.write_u8(addr, bytes[0])?;
+        self.exec_write_memory_u8(addr, bytes[0])?;
         self.cycle();
-        self.mem.write_u8(addr.next(), bytes[1])?;
+        self.exec_write_memory_u8(addr.next(), bytes[1])?;
         self.cycle();
         Ok(())
     }
@@ -261,7 +676,7 @@ impl GameBoy {
             Ok(reg) => Ok(self.read_register_u8(reg)),
             Err(_) => {
                 let addr = self.read_register_u16(wr::HL);
-                let value = self.read_memory_u8(addr)?;
+                let value = self.exec_read_memory_u8(addr)?;
                 self.cycle();
                 Ok(value)
             }
@@ -280,15 +695,87 @@ impl GameBoy {
             }
             Err(_) => {
                 let addr = self.read_register_u16(wr::HL);
-                self.write_memory_u8(addr, value)?;
+                self.exec_write_memory_u8(addr, value)?;
                 self.cycle();
                 Ok(())
             }
         }
     }
 
+    /// Capture the current value of all 16-bit registers
+    pub fn register_snapshot(&self) -> RegisterSnapshot {
+        RegisterSnapshot {
+            af: self.read_register_u16(wr::AF),
+            bc: self.read_register_u16(wr::BC),
+            de: self.read_register_u16(wr::DE),
+            hl: self.read_register_u16(wr::HL),
+            sp: self.read_register_u16(wr::SP),
+            pc: self.read_register_u16(wr::PC),
+        }
+    }
+
+    /// RAM kept by the cartridge's battery, to write to a save file
+    ///
+    /// Returns `None` if the cartridge has no battery backed RAM.
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        self.mem.cartridge().battery_ram()
+    }
+
+    /// Restore battery backed RAM from a save file
+    pub fn load_battery_ram(&mut self, data: &[u8]) -> rom::CartridgeIOResult<()> {
+        self.mem.cartridge_mut().load_battery_ram(data)
+    }
+
+    /// Whether battery backed RAM has changed since it was loaded or last saved
+    pub fn battery_ram_modified(&self) -> bool {
+        self.mem.cartridge().battery_ram_modified()
+    }
+
+    /// Record that battery backed RAM has been saved as it is now
+    pub fn mark_battery_ram_saved(&mut self) {
+        self.mem.cartridge_mut().mark_battery_ram_saved()
+    }
+
+    /// Change the value read from cartridge ROM, such as to change code while debugging
+    ///
+    /// The ROM data is unchanged, so removing the patch restores the original value.
+    pub fn patch_rom(&mut self, patch: rom::RomPatch) -> rom::CartridgeIOResult<()> {
+        self.mem.cartridge_mut().add_patch(patch)
+    }
+
+    /// Remove every ROM patch for `addr`, returning how many were removed
+    pub fn remove_rom_patches(&mut self, addr: u16) -> usize {
+        self.mem.cartridge_mut().remove_patches(addr)
+    }
+
+    /// Patches applied to values read from cartridge ROM
+    pub fn rom_patches(&self) -> &[rom::RomPatch] {
+        self.mem.cartridge().patches()
+    }
+
+    /// The ROM bank currently mapped at `addr`, if `addr` is in cartridge ROM
+    pub fn rom_bank(&self, addr: u16) -> Option<u16> {
+        self.mem.cartridge().rom_bank(addr)
+    }
+
+    /// Resolve an address to the bank currently mapped there
+    pub fn banked_address<A: Into<address::LiteralAddress>>(
+        &self,
+        addr: A,
+    ) -> address::BankedAddress {
+        self.mem.banked_address(addr.into())
+    }
+
+    pub(crate) fn error_context(&self, pc: address::LiteralAddress) -> ErrorContext {
+        ErrorContext {
+            pc: pc.0,
+            bank: self.rom_bank(pc.0),
+            registers: self.register_snapshot(),
+        }
+    }
+
     pub(crate) fn set_interrupt_state(&mut self, state: cpu::InterruptState) {
-        log::trace!(target: "cpu", "set interrupt mode: {:?}", state);
+        log::trace!(target: logging::CPU, "set interrupt mode: {:?}", state);
         self.cpu.interrupts_enabled = state;
     }
 
@@ -297,10 +784,38 @@ impl GameBoy {
     }
 
     pub fn set_power_saving_mode(&mut self, mode: cpu::PowerSavingMode) {
-        log::trace!(target: "cpu", "set power saving mode: {:?}", mode);
+        log::trace!(target: logging::CPU, "set power saving mode: {:?}", mode);
         self.cpu.power_saving = mode
     }
 
+    /// Run the STOP instruction
+    ///
+    /// This resets DIV. If a Game Boy Color speed switch has been requested
+    /// through KEY1, this switches speed instead of entering STOP mode.
+    pub(crate) fn stop(&mut self) {
+        self.timer.reset_divider(&mut self.mem);
+        let registers = self.mem.registers_mut();
+        if registers.color_mode && (registers.key1 & memory::SPEED_SWITCH_PREPARE) != 0 {
+            registers.key1 =
+                (registers.key1 ^ memory::SPEED_SWITCH_DOUBLE) & !memory::SPEED_SWITCH_PREPARE;
+            log::trace!(target: logging::CPU, "double speed: {}", registers.double_speed());
+        } else {
+            self.set_power_saving_mode(PowerSavingMode::Stop);
+        }
+    }
+
+    /// Whether the CPU is running in Game Boy Color double speed mode
+    pub fn double_speed(&self) -> bool {
+        self.mem.registers().double_speed()
+    }
+
+    /// How long each PPU mode lasted on the most recently drawn line
+    ///
+    /// Useful for checking the timing of code which polls STAT.
+    pub fn ppu_mode_lengths(&self) -> ModeLengths {
+        self.ppu.mode_lengths()
+    }
+
     pub fn read_flag(&self, flag: registers::Flag) -> bool {
         self.cpu.read_flag(flag)
     }
@@ -327,7 +842,7 @@ impl GameBoy {
 
     pub(crate) fn exec_read_inc_pc(&mut self) -> StepResult<u8> {
         let pc_value = self.cpu.read_register_u16(wr::PC);
-        let val = self.read_memory_u8(pc_value)?;
+        let val = self.exec_read_memory_u8(pc_value)?;
         self.cpu
             .write_register_u16(wr::PC, pc_value.wrapping_add(1));
         self.cycle();
@@ -337,11 +852,13 @@ impl GameBoy {
     pub(crate) fn exec_push<T: Into<u16>>(&mut self, value: T) -> StepResult<()> {
         let stack_addr = self.cpu.read_register_u16(registers::WordRegister::SP);
         let [low, high] = value.into().to_le_bytes();
+        self.oam_bug_trigger(stack_addr, oam_bug::Access::Write);
         let stack_addr = stack_addr.wrapping_sub(1);
-        self.write_memory_u8(stack_addr, high)?;
+        self.exec_write_memory_u8(stack_addr, high)?;
         self.cycle();
+        self.oam_bug_trigger(stack_addr, oam_bug::Access::Write);
         let stack_addr = stack_addr.wrapping_sub(1);
-        self.write_memory_u8(stack_addr, low)?;
+        self.exec_write_memory_u8(stack_addr, low)?;
         self.cycle();
         self.cpu
             .write_register_u16(registers::WordRegister::SP, stack_addr);
@@ -351,10 +868,12 @@ impl GameBoy {
 
     pub(crate) fn exec_pop<T: From<u16>>(&mut self) -> StepResult<T> {
         let stack_addr = self.cpu.read_register_u16(registers::WordRegister::SP);
-        let low = self.read_memory_u8(stack_addr)?;
+        let low = self.exec_read_memory_u8(stack_addr)?;
+        self.oam_bug_trigger(stack_addr, oam_bug::Access::Read);
         self.cycle();
         let stack_addr = stack_addr.wrapping_add(1);
-        let high = self.read_memory_u8(stack_addr)?;
+        let high = self.exec_read_memory_u8(stack_addr)?;
+        self.oam_bug_trigger(stack_addr, oam_bug::Access::Read);
         self.cycle();
         self.cpu
             .write_register_u16(registers::WordRegister::SP, stack_addr.wrapping_add(1));
@@ -372,6 +891,136 @@ impl GameBoy {
         address::LiteralAddress(value)
     }
 
+    /// Set whether to send an `InterruptDispatchEvent` for every interrupt dispatched
+    ///
+    /// While enabled, the clock each interrupt is requested at is tracked, so
+    /// the latency of each dispatch can be reported. Dispatches are also logged
+    /// at trace level, under the `cpu/interrupt` target.
+    pub fn set_interrupt_tracing(&mut self, enabled: bool) {
+        if !enabled {
+            self.interrupt_requests = None;
+        } else if self.interrupt_requests.is_none() {
+            self.interrupt_requests = Some([None; 5]);
+            self.requested_iflag = self.mem.registers().iflag;
+        }
+    }
+
+    /// Whether an `InterruptDispatchEvent` is sent for every interrupt dispatched
+    pub fn interrupt_tracing(&self) -> bool {
+        self.interrupt_requests.is_some()
+    }
+
+    /// Note the clock of any interrupts requested since the last check
+    fn track_interrupt_requests(&mut self) {
+        let iflag = self.mem.registers().iflag;
+        if iflag == self.requested_iflag {
+            return;
+        }
+        let clocks = self.clocks_elapsed();
+        if let Some(requests) = self.interrupt_requests.as_mut() {
+            for (bit, request) in requests.iter_mut().enumerate() {
+                let mask = 1 << bit;
+                if iflag & mask == 0 {
+                    *request = None;
+                } else if self.requested_iflag & mask == 0 {
+                    *request = Some(clocks);
+                }
+            }
+        }
+        self.requested_iflag = iflag;
+    }
+
+    /// Forget when an interrupt was requested, returning the clock it was requested at
+    fn take_interrupt_request(&mut self, interrupt: cpu::Interrupt) -> Option<u64> {
+        let bit = interrupt.mask().trailing_zeros() as usize;
+        self.interrupt_requests
+            .as_mut()
+            .and_then(|requests| requests[bit].take())
+    }
+
+    /// Send an `InterruptDispatchEvent` for an interrupt dispatched at `clocks`
+    fn trace_dispatch(
+        &mut self,
+        interrupt: cpu::Interrupt,
+        clocks: u64,
+        interrupted_pc: u16,
+        requested: Option<u64>,
+    ) {
+        if self.interrupt_requests.is_none() {
+            return;
+        }
+        let handler = interrupt.handler_address().0;
+        let latency = requested.map(|requested| clocks - requested);
+        log::trace!(
+            target: logging::CPU_INTERRUPTS,
+            "Dispatched {} at clock {} from {:04X} to {:04X}, latency {:?}",
+            interrupt,
+            clocks,
+            interrupted_pc,
+            handler,
+            latency
+        );
+        self.events.emit(
+            events::InterruptDispatchEvent::new(
+                interrupt,
+                clocks,
+                interrupted_pc,
+                handler,
+                latency,
+            )
+            .into(),
+        );
+    }
+
+    /// Push PC and jump to the handler of the highest priority pending interrupt
+    ///
+    /// The interrupt isn't chosen until PC is being pushed. IE is read after
+    /// the upper byte is pushed and IF after the lower byte, so a push onto
+    /// either register can switch to another interrupt, or cancel the dispatch
+    /// and jump to 0x0000 instead.
+    fn dispatch_interrupt(&mut self) -> StepResult<()> {
+        let clocks = self.clocks_elapsed();
+        let interrupted_pc = self.read_pc();
+        self.cycle();
+        self.cycle();
+        self.set_interrupt_state(cpu::InterruptState::Disabled);
+        let [low, high] = interrupted_pc.0.to_le_bytes();
+        let stack_addr = self.cpu.read_register_u16(registers::WordRegister::SP);
+        let stack_addr = stack_addr.wrapping_sub(1);
+        self.exec_write_memory_u8(stack_addr, high)?;
+        self.cycle();
+        let ie = self.mem.registers().ie;
+        let stack_addr = stack_addr.wrapping_sub(1);
+        self.exec_write_memory_u8(stack_addr, low)?;
+        self.cycle();
+        self.cpu
+            .write_register_u16(registers::WordRegister::SP, stack_addr);
+        let itest = cpu::Interrupt::test(ie, self.mem.registers().iflag);
+        let requested = itest.and_then(|interrupt| {
+            interrupt.clear(&mut self.mem.registers_mut().iflag);
+            self.take_interrupt_request(interrupt)
+        });
+        self.cycle();
+        let interrupt = match itest {
+            Some(interrupt) => interrupt,
+            None => {
+                log::trace!(
+                    target: logging::CPU_INTERRUPTS,
+                    "Dispatch from {:04X} cancelled",
+                    interrupted_pc.0
+                );
+                self.set_pc(0x0000u16);
+                return Ok(());
+            }
+        };
+        self.set_pc(interrupt.handler_address());
+        self.dispatched_interrupt = Some(interrupt);
+        self.events
+            .emit(events::InterruptEvent::new(interrupt).into());
+        self.trace_dispatch(interrupt, clocks, interrupted_pc.0, requested);
+        Ok(())
+    }
+
     fn check_interrupts(&mut self) -> StepResult<bool> {
         use cpu::InterruptState::{Disabled, Enabled, Pending};
         match self.cpu.interrupts_enabled {
@@ -383,14 +1032,8 @@ impl GameBoy {
             Enabled => {
                 let itest =
                     cpu::Interrupt::test(self.mem.registers().ie, self.mem.registers().iflag);
-                if let Some(interrupt) = itest {
-                    self.cycle();
-                    self.cycle();
-                    self.set_interrupt_state(cpu::InterruptState::Disabled);
-                    interrupt.clear(&mut self.mem.registers_mut().iflag);
-                    let addr = interrupt.handler_address();
-                    self.exec_push(self.read_pc())?;
-                    self.set_pc(addr);
+                if itest.is_some() {
+                    self.dispatch_interrupt()?;
                     Ok(true)
                 } else {
                     Ok(false)
@@ -405,70 +1048,237 @@ impl GameBoy {
     /// execute. All components of the gameboy will run for this many machine
     /// cycles. To find out how many clocks elapsed, use `GameBoy::clocks_elapsed`.
     pub fn step(&mut self) -> StepResult<()> {
-        log::trace!(target: "gb", "Step");
+        self.profiler.start_step();
+        let result = if self.history.is_enabled() {
+            self.run_recorded_step()
+        } else {
+            self.run_step()
+        };
+        self.profiler.finish_step();
+        result
+    }
+
+    /// Runs a single instruction, recording how to undo it
+    fn run_recorded_step(&mut self) -> StepResult<()> {
+        let mut entry = self.history_entry();
+        self.mem.start_journal();
+        let result = self.run_step();
+        entry.writes = self.mem.finish_journal();
+        self.history.push(entry);
+        result
+    }
+
+    /// Runs a single instruction, recording the micro-ops it performs
+    ///
+    /// This is useful for checking instructions cycle by cycle, such as
+    /// against CPU test vectors. See [`MicroOp`] for how the trace is laid out.
+    pub fn traced_step(&mut self) -> StepResult<Vec<MicroOp>> {
+        self.micro_ops = Some(micro_ops::MicroOpRecorder::new(self.register_snapshot()));
+        let result = self.step();
+        let registers = self.register_snapshot();
+        let recorder = self
+            .micro_ops
+            .take()
+            .expect("Micro-ops were being recorded");
+        result.map(|_| recorder.finish(registers))
+    }
+
+    /// Set how many steps can be undone with `GameBoy::reverse_step`
+    ///
+    /// Recording is disabled when this is 0, which is the default.
+    pub fn set_history_limit(&mut self, steps: usize) {
+        self.history.set_limit(steps);
+    }
+
+    /// The number of steps that can currently be undone
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// The PC at the start of each step that can be undone, oldest first
+    pub fn history_pcs(&self) -> impl Iterator<Item = u16> + '_ {
+        self.history.iter().map(|entry| entry.registers.pc)
+    }
+
+    /// Undo the most recent step
+    ///
+    /// This restores the CPU registers, memory, timer, DMA, PPU and cartridge
+    /// bank state from before the step. Pixels already drawn aren't undone, so
+    /// the framebuffer is out of date until they are drawn again.
+    ///
+    /// Returns false if there are no recorded steps to undo.
+    pub fn reverse_step(&mut self) -> bool {
+        let entry = match self.history.pop() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        for (addr, value) in entry.writes.into_iter().rev() {
+            let _ = self.mem.write_u8_internal(addr.into(), value);
+        }
+        self.mem.restore_bank_registers(entry.cartridge_banks);
+        *self.mem.registers_mut() = entry.mem_registers;
+        self.ppu.restore_timing(entry.ppu);
+        self.dma = entry.dma;
+        self.hdma = entry.hdma;
+        self.timer = entry.timer;
+        self.overclock.rewind_to(entry.overclock);
+        self.cpu.interrupts_enabled = entry.interrupts_enabled;
+        self.cpu.power_saving = entry.power_saving;
+        self.set_clocks_elapsed(entry.clocks_elapsed);
+        self.dispatched_interrupt = None;
+        let registers = entry.registers;
+        self.write_register_u16(wr::AF, registers.af);
+        self.write_register_u16(wr::BC, registers.bc);
+        self.write_register_u16(wr::DE, registers.de);
+        self.write_register_u16(wr::HL, registers.hl);
+        self.write_register_u16(wr::SP, registers.sp);
+        self.write_register_u16(wr::PC, registers.pc);
+        true
+    }
+
+    fn history_entry(&self) -> history::HistoryEntry {
+        history::HistoryEntry {
+            registers: self.register_snapshot(),
+            interrupts_enabled: self.cpu.interrupts_enabled,
+            power_saving: self.cpu.power_saving,
+            mem_registers: self.mem.registers().clone(),
+            dma: self.dma.clone(),
+            hdma: self.hdma.clone(),
+            timer: self.timer.clone(),
+            overclock: self.overclock,
+            ppu: self.ppu.timing(),
+            cartridge_banks: self.mem.bank_registers(),
+            clocks_elapsed: self.clocks_elapsed(),
+            writes: Vec::new(),
+        }
+    }
+
+    fn run_step(&mut self) -> StepResult<()> {
+        log::trace!(target: logging::CPU_STEP, "Step at {}", self.banked_address(self.read_pc()));
+        self.dispatched_interrupt = None;
+        self.latch_input();
+        if self.interrupt_requests.is_some() {
+            // Catch requests made between steps, such as by pressing a button
+            self.track_interrupt_requests();
+        }
         if self.cpu.power_saving == PowerSavingMode::Stop {
             return Ok(());
         }
+        while self.hdma.is_stalling() {
+            self.cycle();
+        }
         let pc_value = self.read_pc();
-        let opcode = self.read_memory_u8(pc_value)?;
+        let opcode = self.exec_read_memory_u8(pc_value)?;
         self.cycle();
         let interrupted = self.check_interrupts()?;
         if !interrupted {
             self.set_pc(pc_value.next());
-            let non_borrowing_decoder = self.runtime_decoder.clone();
-            let exe_code = if non_borrowing_decoder.is_extended(opcode) {
+            let decoder = self.runtime_decoder;
+            let exe_code = if decoder.is_extended(opcode) {
                 let extended_opcode = self.exec_read_inc_pc()?;
-                non_borrowing_decoder.decode_extended(extended_opcode)
-            } else if let Some(exe_code) = non_borrowing_decoder.decode(opcode) {
+                decoder.decode_extended(extended_opcode)
+            } else if let Some(exe_code) = decoder.decode(opcode) {
                 exe_code
             } else {
-                return Err(StepError::InvalidOpcode(opcode));
+                return Err(StepError::InvalidOpcode(
+                    opcode,
+                    self.error_context(pc_value),
+                ));
             };
-            exe_code
-                .to_instruction(&mut self.cycling_memory_iter())
-                .execute(self)?;
+            exe_code.decode_and_execute(self)?;
         }
         Ok(())
     }
 
+    /// The interrupt dispatched by the last step, if any
+    ///
+    /// When an interrupt is dispatched, the step jumps to its handler
+    /// without running an instruction, so PC is at the first instruction
+    /// of the handler.
+    pub fn dispatched_interrupt(&self) -> Option<cpu::Interrupt> {
+        self.dispatched_interrupt
+    }
+
     /// Returns the instruction at the current PC.
+    ///
+    /// Like `get_memory_u8`, this doesn't emit memory events.
     pub fn current_instruction(
         &self,
     ) -> StepResult<Box<dyn crate::instructionsn::RuntimeInstruction>> {
         let mut pc_value = self.read_pc();
-        let opcode = self.read_memory_u8(pc_value)?;
+        let opcode = self.get_memory_u8(pc_value)?;
         let exe_code = if self.runtime_decoder.is_extended(opcode) {
             pc_value = pc_value.next();
-            let extended_opcode = self.read_memory_u8(pc_value)?;
+            let extended_opcode = self.get_memory_u8(pc_value)?;
             self.runtime_decoder.decode_extended(extended_opcode)
         } else if let Some(exe_code) = self.runtime_decoder.decode(opcode) {
             exe_code
         } else {
-            return Err(StepError::InvalidOpcode(opcode));
+            return Err(StepError::InvalidOpcode(
+                opcode,
+                self.error_context(pc_value),
+            ));
         };
         Ok(exe_code.to_instruction(&mut self.memory_iter(pc_value.next())))
     }
 
     pub(crate) fn cycle(&mut self) {
+        self.profiler.start_cycle();
         // TODO: Use this. a memory error can occur if the DMA operation tries to
         // write to cartridge RAM that is not present. As with actual hardware,
         // the DMA operation continues, and so we shouldn't abort emulation early,
         // but it would be useful to surface this information somewhere for ROM developers.
         let _dma_result = self.dma.run_cycle(&mut self.mem);
-        self.ppu.run_cycle(&mut self.mem);
-        self.add_clocks_elapsed(4);
+        let _hdma_result = self.hdma.run_cycle(&mut self.mem, self.ppu.in_hblank());
+        self.profiler.record(Component::Dma);
+        // Extra cycles from overclocking only run the CPU and DMA
+        if !self.overclock.take_cycle() {
+            self.ppu.run_cycle(&mut self.mem);
+            self.profiler.record(Component::Ppu);
+            self.mem.cartridge_mut().tick();
+            self.profiler.record(Component::Cartridge);
+            self.serial.run_cycle(&mut self.mem);
+            self.profiler.record(Component::Serial);
+            self.add_clocks_elapsed(4);
+            self.profiler.record(Component::Timer);
+            if self.interrupt_requests.is_some() {
+                self.track_interrupt_requests();
+            }
+        }
+        if self.micro_ops.is_some() {
+            let registers = self.register_snapshot();
+            if let Some(recorder) = self.micro_ops.as_mut() {
+                recorder.end_cycle(registers);
+            }
+        }
     }
 
     pub fn add_clocks_elapsed(&mut self, count: u64) {
-        self.clocks_elapsed += u64::from(CLOCKS_PER_CYCLE);
+        // In double speed mode, the CPU and timer run twice as fast as everything else
+        let clocks = if self.double_speed() {
+            u64::from(CLOCKS_PER_CYCLE) / 2
+        } else {
+            u64::from(CLOCKS_PER_CYCLE)
+        };
+        self.set_clocks_elapsed(self.clocks_elapsed + clocks);
         self.timer.tick(&mut self.mem, count);
+        self.overclock.add_clocks(clocks as u32);
     }
 
     /// Query how many CPU clocks have elapsed since the emulator started
+    ///
+    /// Clocks are counted at normal speed, so in Game Boy Color double
+    /// speed mode, each machine cycle takes 2 clocks instead of 4.
     pub fn clocks_elapsed(&self) -> u64 {
         self.clocks_elapsed
     }
 
+    fn set_clocks_elapsed(&mut self, clocks: u64) {
+        self.clocks_elapsed = clocks;
+        #[cfg(feature = "events")]
+        self.clock.set(clocks);
+    }
+
     /// Query how many machine cycles have elapsed since the emulator started
     ///
     /// Each machine cycle represents 4 CPU clocks.
@@ -476,6 +1286,18 @@ impl GameBoy {
         self.clocks_elapsed() / 4
     }
 
+    /// Host time spent emulating each component since profiling was reset
+    ///
+    /// This is `None` unless the `profiling` feature is enabled.
+    pub fn profile(&self) -> Option<CycleProfile> {
+        self.profiler.profile()
+    }
+
+    /// Start profiling again from zero
+    pub fn reset_profile(&mut self) {
+        self.profiler.reset();
+    }
+
     /// Query much clock time has been spent emulating
     pub fn time_elapsed(&self) -> f64 {
         self.time_elapsed
@@ -491,7 +1313,7 @@ impl<'a> Iterator for CyclingMemoryIterator<'a> {
 
     fn next(&mut self) -> Option<u8> {
         let addr = self.gb.read_pc();
-        let value = self.gb.read_memory_u8(addr).ok();
+        let value = self.gb.exec_read_memory_u8(addr).ok();
         self.gb.cycle();
         self.gb.set_pc(addr.next());
         value
@@ -503,6 +1325,7 @@ impl<'a> Iterator for CyclingMemoryIterator<'a> {
 /// Note that the presence of GBA models do not imply support
 /// for GBA ROMs. However, the GBA has some differing behaviors
 /// when running GB games compared to standard GB hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GameBoyModel {
     GameBoy,          // DMG
     GameBoyPocket,    // MGB
@@ -513,6 +1336,28 @@ pub enum GameBoyModel {
 }
 
 impl GameBoyModel {
+    /// Indicates if this model can run Game Boy Color software
+    pub fn supports_color(&self) -> bool {
+        matches!(
+            self,
+            GameBoyModel::GameBoyColor
+                | GameBoyModel::GameBoyAdvance
+                | GameBoyModel::GameBoyAdvanceSP
+        )
+    }
+
+    /// Indicates if this model corrupts OAM when 16-bit registers pointing
+    /// to it are incremented or decremented while the PPU scans it
+    pub(crate) fn has_oam_bug(&self) -> bool {
+        !self.supports_color()
+    }
+
+    /// Indicates if this model raises the STAT interrupt when STAT is
+    /// written during HBlank, VBlank or a LY=LYC match
+    pub(crate) fn has_stat_write_bug(&self) -> bool {
+        !self.supports_color()
+    }
+
     pub(crate) fn default_af(&self) -> u16 {
         match self {
             GameBoyModel::GameBoy => 0x01B0,
@@ -566,9 +1411,6 @@ impl GameBoyModel {
     }
 }
 
-#[cfg(test)]
-pub(crate) mod testutils;
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -784,10 +1626,46 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_mem_chunk_sysram() -> memory::MemoryResult<()> {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+
+        gb.set_memory_chunk(0xc100, &[0x12, 0x34, 0x56])?;
+
+        let mut buf = [0u8; 4];
+        gb.get_memory_chunk(0xc0ff, &mut buf)?;
+        assert_eq!(buf, [0x00, 0x12, 0x34, 0x56]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mem_chunk_unreadable() {
+        let gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+
+        let mut buf = [0u8; 0x10];
+        assert!(gb.get_memory_chunk(0xff4c, &mut buf).is_err());
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_step_on_other_thread() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        let gb = std::thread::spawn(move || {
+            gb.step().unwrap();
+            gb
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(gb.read_register_u16(wr::PC), 0xC001);
+    }
+
     #[test]
     fn test_cycle_count() {
         let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
-        gb.clocks_elapsed = 16;
+        gb.set_clocks_elapsed(16);
         assert_eq!(gb.cycles_elapsed(), 4);
     }
 
@@ -803,10 +1681,73 @@ mod test {
     }
 
     #[test]
+    fn test_invalid_opcode_context() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.set_memory_u8(0xC123, 0xFD).unwrap();
+        gb.write_register_u16(wr::PC, 0xC123);
+        gb.write_register_u16(wr::BC, 0x1234);
+
+        let error = gb.step().unwrap_err();
+        let context = error.context().unwrap();
+
+        assert_eq!(context.pc, 0xC123);
+        assert_eq!(context.bank, None);
+        assert_eq!(context.registers.bc, 0x1234);
+        assert_eq!(format!("{}", error), "invalid opcode FD at 0xC123");
+    }
+
+    #[test]
+    fn test_invalid_opcode_context_rom_bank() {
+        let mut data = vec![0u8; 0x8000];
+        data[0x4123] = 0xFD;
+        let cartridge = rom::Cartridge::from_data(data).unwrap();
+        let mut gb = GameBoy::new(cartridge, GameBoyModel::GameBoy);
+        gb.write_register_u16(wr::PC, 0x4123);
+
+        let error = gb.step().unwrap_err();
+
+        assert_eq!(format!("{}", error), "invalid opcode FD at 0x4123 (bank 1)");
+    }
+
+    #[test]
+    fn test_banked_address() {
+        let mut data = vec![0u8; 0x10000];
+        data[0x147] = 1;
+        let cartridge = rom::Cartridge::from_data(data).unwrap();
+        let mut gb = GameBoy::new(cartridge, GameBoyModel::GameBoy);
+
+        assert_eq!(
+            gb.banked_address(0x4123),
+            address::BankedAddress::new(1, 0x4123)
+        );
+        gb.set_memory_u8(0x2000, 3).unwrap();
+        assert_eq!(
+            gb.banked_address(0x4123),
+            address::BankedAddress::new(3, 0x4123)
+        );
+        assert_eq!(
+            gb.banked_address(0x0150),
+            address::BankedAddress::new(0, 0x0150)
+        );
+        assert_eq!(
+            gb.banked_address(0xC000),
+            address::BankedAddress::new(0, 0xC000)
+        );
+    }
+
+    #[test]
+    fn test_gameboys_share_decoder() {
+        let first = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        let second = GameBoy::new(make_cartridge(), GameBoyModel::GameBoyColor);
+        assert!(core::ptr::eq(first.runtime_decoder, second.runtime_decoder));
+    }
+
+    #[test]
+    #[cfg(feature = "events")]
     fn test_write_events() {
-        use core::cell::RefCell;
-        let event_log: Rc<RefCell<Vec<events::Event>>> = Rc::new(RefCell::new(Vec::new()));
-        let handler_log = Rc::clone(&event_log);
+        use crate::shared::Lock;
+        let event_log: Shared<Lock<Vec<events::Event>>> = Shared::new(Lock::new(Vec::new()));
+        let handler_log = Shared::clone(&event_log);
 
         let handler: events::EventHandler<events::Event> = Box::new(move |evt| {
             handler_log.borrow_mut().push(evt.clone());
@@ -827,4 +1768,547 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    #[cfg(feature = "events")]
+    fn test_palette_write_mid_line() {
+        use crate::shared::Lock;
+        let lines: Shared<Lock<Vec<events::HBlankEvent>>> = Shared::new(Lock::new(Vec::new()));
+        let handler_lines = Shared::clone(&lines);
+        let handler: events::EventHandler<events::Event> = Box::new(move |evt| {
+            if let events::Event::HBlank(hblank) = evt {
+                handler_lines.borrow_mut().push(hblank.clone());
+            }
+        });
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.events.on(handler);
+        let mode = |gb: &GameBoy| gb.mem.registers().lcdstat & ppu::MODE_MASK;
+
+        // VRAM is blank, so every pixel is colour 0
+        gb.write_memory_u8(memory::BG_PALETTE_ADDR, 0b00).unwrap();
+        while mode(&gb) != ppu::MODE_DRAWING {
+            gb.cycle();
+        }
+        for _ in 0..10 {
+            gb.cycle();
+        }
+        gb.write_memory_u8(memory::BG_PALETTE_ADDR, 0b11).unwrap();
+        while mode(&gb) == ppu::MODE_DRAWING {
+            gb.cycle();
+        }
+
+        let lines = lines.borrow();
+        let pixels = &lines.last().expect("A line was drawn").pixels;
+        let changed_at = pixels
+            .iter()
+            .position(|px| px.color == 3)
+            .expect("Palette change was applied");
+        assert!(changed_at > 0);
+        assert!(pixels[..changed_at].iter().all(|px| px.color == 0));
+        assert!(pixels[changed_at..].iter().all(|px| px.color == 3));
+        assert!(pixels.iter().all(|px| px.index == 0));
+    }
+
+    #[test]
+    #[cfg(feature = "events")]
+    fn test_event_timestamps() {
+        use crate::shared::Lock;
+        let event_log: Shared<Lock<Vec<events::TimestampedEvent<events::Event>>>> =
+            Shared::new(Lock::new(Vec::new()));
+        let handler_log = Shared::clone(&event_log);
+
+        let handler: events::EventHandler<events::TimestampedEvent<events::Event>> =
+            Box::new(move |evt| {
+                handler_log.borrow_mut().push(evt.clone());
+            });
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.set_memory_u8(0xC000, 0x00).unwrap(); // NOP
+        gb.set_memory_u8(0xC001, 0x00).unwrap(); // NOP
+        gb.step().unwrap();
+        gb.events.on_timestamped(handler);
+
+        gb.step().unwrap();
+        gb.write_memory_u8(0xC100, 0x24).unwrap();
+
+        let timestamps: Vec<_> = event_log
+            .borrow()
+            .iter()
+            .filter(|evt| evt.event != events::StepCompleteEvent.into())
+            .map(|evt| (evt.event.clone(), evt.timestamp.clocks))
+            .collect();
+        assert_eq!(
+            timestamps,
+            vec![
+                (events::MemoryEvent::read(0xC001.into(), 0x00).into(), 4),
+                (events::RegisterWriteEvent::new(wr::PC, 0xC002).into(), 8),
+                (
+                    events::MemoryEvent::write(0xC100.into(), 0x24, 0x24).into(),
+                    8
+                ),
+            ]
+        );
+        let log = event_log.borrow();
+        assert!(log
+            .windows(2)
+            .all(|pair| pair[1].timestamp.sequence == pair[0].timestamp.sequence + 1));
+    }
+
+    #[test]
+    fn test_reverse_step() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.set_history_limit(2);
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.set_memory_u8(0xC000, 0x3E).unwrap(); // LD A, 42h
+        gb.set_memory_u8(0xC001, 0x42).unwrap();
+        gb.set_memory_u8(0xC002, 0xEA).unwrap(); // LD (C100h), A
+        gb.set_memory_u8(0xC003, 0x00).unwrap();
+        gb.set_memory_u8(0xC004, 0xC1).unwrap();
+        gb.set_memory_u8(0xC005, 0x00).unwrap(); // NOP
+
+        gb.step().unwrap();
+        let clocks_after_load = gb.clocks_elapsed();
+        gb.step().unwrap();
+        gb.step().unwrap();
+
+        assert_eq!(gb.history_len(), 2);
+        assert_eq!(gb.get_memory_u8(0xC100).unwrap(), 0x42);
+        assert_eq!(gb.history_pcs().collect::<Vec<_>>(), vec![0xC002, 0xC005]);
+
+        assert!(gb.reverse_step());
+        assert_eq!(gb.read_register_u16(wr::PC), 0xC005);
+        assert_eq!(gb.get_memory_u8(0xC100).unwrap(), 0x42);
+
+        assert!(gb.reverse_step());
+        assert_eq!(gb.read_register_u16(wr::PC), 0xC002);
+        assert_eq!(gb.read_register_u8(registers::ByteRegister::A), 0x42);
+        assert_eq!(gb.get_memory_u8(0xC100).unwrap(), 0x00);
+        assert_eq!(gb.clocks_elapsed(), clocks_after_load);
+
+        assert!(!gb.reverse_step(), "Only 2 steps are kept");
+        assert_eq!(gb.read_register_u16(wr::PC), 0xC002);
+    }
+
+    #[test]
+    fn test_reverse_step_restores_cartridge_banks() {
+        let mut data = vec![0u8; 0x8000];
+        data[0x147] = 0x1A; // MBC5 with RAM
+        data[0x149] = 3; // 4 RAM banks
+        let mut gb = GameBoy::new(
+            rom::Cartridge::from_data(data).unwrap(),
+            GameBoyModel::GameBoy,
+        );
+        let read_ram_bank = |gb: &mut GameBoy, bank: u8| {
+            gb.set_memory_u8(0x0000, 0x0A).unwrap();
+            gb.set_memory_u8(0x4000, bank).unwrap();
+            let value = gb.get_memory_u8(0xA000).unwrap();
+            gb.set_memory_u8(0x4000, 0).unwrap();
+            gb.set_memory_u8(0x0000, 0).unwrap();
+            value
+        };
+        let program = [
+            0x3E, 0x0A, // LD A, 0Ah
+            0xEA, 0x00, 0x00, // LD (0000h), A to enable RAM
+            0xEA, 0x00, 0xA0, // LD (A000h), A in RAM bank 0
+            0x3E, 0x01, // LD A, 01h
+            0xEA, 0x00, 0x40, // LD (4000h), A to select RAM bank 1
+        ];
+        for (offset, value) in program.iter().enumerate() {
+            gb.set_memory_u8(0xC000 + offset as u16, *value).unwrap();
+        }
+        gb.write_register_u16(wr::PC, 0xC000);
+        let banks = gb.mem.bank_registers();
+        gb.set_history_limit(5);
+
+        for _ in 0..5 {
+            gb.step().unwrap();
+        }
+        assert_eq!(gb.mem.bank_registers().ram, 1);
+        while gb.reverse_step() {}
+
+        assert_eq!(gb.mem.bank_registers(), banks);
+        assert_eq!(read_ram_bank(&mut gb, 0), 0x00);
+        assert_eq!(read_ram_bank(&mut gb, 1), 0x00);
+    }
+
+    #[test]
+    fn test_reverse_step_restores_ppu() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.set_history_limit(100);
+        let ppu_state = |gb: &GameBoy| (gb.mem.registers().ly, gb.mem.registers().lcdstat);
+
+        let mut states = Vec::new();
+        for _ in 0..100 {
+            gb.step().unwrap();
+            states.push(ppu_state(&gb));
+        }
+        while gb.reverse_step() {}
+        let mut replayed = Vec::new();
+        for _ in 0..100 {
+            gb.step().unwrap();
+            replayed.push(ppu_state(&gb));
+        }
+
+        assert_eq!(replayed, states);
+    }
+
+    #[test]
+    fn test_reverse_step_disabled_by_default() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.write_register_u16(wr::PC, 0xC000);
+
+        gb.step().unwrap();
+
+        assert_eq!(gb.history_len(), 0);
+        assert!(!gb.reverse_step());
+    }
+
+    #[test]
+    fn test_interrupt_push_to_ie_cancels_dispatch() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.set_interrupt_state(cpu::InterruptState::Enabled);
+        gb.mem.registers_mut().ie = cpu::Interrupt::Timer.mask();
+        gb.mem.registers_mut().iflag = cpu::Interrupt::Timer.mask();
+        gb.write_register_u16(wr::SP, 0x0000);
+        gb.write_register_u16(wr::PC, 0x0150);
+        gb.step().unwrap();
+
+        // The upper byte of PC replaces IE, leaving only VBlank enabled
+        assert_eq!(gb.mem.registers().ie, 0x01);
+        assert_eq!(gb.read_register_u16(wr::PC), 0x0000);
+        assert_eq!(gb.read_register_u16(wr::SP), 0xFFFE);
+        assert_eq!(gb.mem.registers().iflag, cpu::Interrupt::Timer.mask());
+        assert_eq!(gb.dispatched_interrupt(), None);
+        assert_eq!(gb.cpu.interrupts_enabled, cpu::InterruptState::Disabled);
+    }
+
+    #[test]
+    fn test_interrupt_push_to_ie_redirects_dispatch() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.set_interrupt_state(cpu::InterruptState::Enabled);
+        gb.mem.registers_mut().ie = cpu::Interrupt::Timer.mask();
+        gb.mem.registers_mut().iflag =
+            cpu::Interrupt::Timer.mask() | cpu::Interrupt::LCDStatus.mask();
+        gb.write_register_u16(wr::SP, 0x0000);
+        gb.write_register_u16(wr::PC, 0x0250);
+        gb.step().unwrap();
+
+        // The upper byte of PC replaces IE, leaving only LCD status enabled
+        assert_eq!(gb.read_register_u16(wr::PC), 0x0048);
+        assert_eq!(gb.dispatched_interrupt(), Some(cpu::Interrupt::LCDStatus));
+        assert_eq!(gb.mem.registers().iflag, cpu::Interrupt::Timer.mask());
+    }
+
+    #[test]
+    #[cfg(feature = "events")]
+    fn test_interrupt_event() {
+        use crate::shared::Lock;
+        let interrupts: Shared<Lock<Vec<events::InterruptEvent>>> =
+            Shared::new(Lock::new(Vec::new()));
+        let handler_interrupts = Shared::clone(&interrupts);
+        let handler: events::EventHandler<events::Event> = Box::new(move |evt| {
+            if let events::Event::Interrupt(interrupt) = evt {
+                handler_interrupts.borrow_mut().push(*interrupt);
+            }
+        });
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.events.on(handler);
+
+        gb.set_interrupt_state(cpu::InterruptState::Enabled);
+        gb.mem.registers_mut().ie = cpu::Interrupt::Timer.mask();
+        gb.mem.registers_mut().iflag = cpu::Interrupt::Timer.mask();
+        gb.step().unwrap();
+
+        assert_eq!(gb.dispatched_interrupt(), Some(cpu::Interrupt::Timer));
+        assert_eq!(
+            *interrupts.borrow(),
+            vec![events::InterruptEvent::new(cpu::Interrupt::Timer)]
+        );
+    }
+
+    #[test]
+    fn test_joypad_input() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.set_memory_u8(memory::JOYPAD_ADDR, 0x10).unwrap(); // Select actions
+
+        gb.set_button(Button::Down, true);
+        assert_eq!(gb.get_memory_u8(memory::JOYPAD_ADDR).unwrap(), 0xDF);
+        assert_eq!(gb.mem.registers().iflag, 0);
+
+        gb.set_power_saving_mode(PowerSavingMode::Stop);
+        gb.set_button(Button::Start, true);
+        assert_eq!(gb.get_memory_u8(memory::JOYPAD_ADDR).unwrap(), 0xD7);
+        assert_eq!(gb.mem.registers().iflag, cpu::Interrupt::Input.mask());
+        assert_eq!(gb.power_saving_mode(), PowerSavingMode::None);
+
+        gb.set_button(Button::Start, false);
+        assert_eq!(gb.get_memory_u8(memory::JOYPAD_ADDR).unwrap(), 0xDF);
+    }
+
+    #[test]
+    fn test_input_queue_latches_at_frame() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.set_memory_u8(memory::JOYPAD_ADDR, 0x10).unwrap(); // Select actions
+        let input = gb.input_queue();
+
+        input.push(Button::A, true);
+        input.push(Button::Start, true);
+        input.push(Button::Start, false);
+        while !gb.ppu.in_vblank() {
+            gb.step().unwrap();
+            assert_eq!(gb.get_memory_u8(memory::JOYPAD_ADDR).unwrap(), 0xDF);
+        }
+        gb.step().unwrap();
+
+        assert_eq!(gb.get_memory_u8(memory::JOYPAD_ADDR).unwrap(), 0xDE);
+        assert!(input.is_empty());
+
+        // Nothing else is applied until the next frame
+        input.push(Button::A, false);
+        gb.step().unwrap();
+        assert_eq!(gb.get_memory_u8(memory::JOYPAD_ADDR).unwrap(), 0xDE);
+        assert_eq!(input.len(), 1);
+    }
+
+    #[test]
+    fn test_input_queue_immediate() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.set_memory_u8(memory::JOYPAD_ADDR, 0x10).unwrap(); // Select actions
+        gb.set_input_latching(InputLatching::Immediate);
+
+        gb.input_queue().push(Button::B, true);
+        assert_eq!(gb.get_memory_u8(memory::JOYPAD_ADDR).unwrap(), 0xDF);
+        gb.step().unwrap();
+
+        assert_eq!(gb.get_memory_u8(memory::JOYPAD_ADDR).unwrap(), 0xDD);
+    }
+
+    #[test]
+    fn test_input_queue_wakes_from_stop() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.set_memory_u8(memory::JOYPAD_ADDR, 0x10).unwrap(); // Select actions
+        gb.set_power_saving_mode(PowerSavingMode::Stop);
+
+        gb.input_queue().push(Button::Start, true);
+        gb.step().unwrap();
+
+        assert_eq!(gb.power_saving_mode(), PowerSavingMode::None);
+    }
+
+    fn make_color_gameboy() -> GameBoy {
+        let mut rom_data = vec![0u8; 0x8000];
+        rom_data[0x143] = 0xC0;
+        GameBoy::new(
+            rom::Cartridge::from_data(rom_data).unwrap(),
+            GameBoyModel::GameBoyColor,
+        )
+    }
+
+    #[test]
+    fn test_stop_resets_div() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.set_memory_u8(0xC000, 0x10).unwrap(); // STOP
+        gb.set_memory_u8(0xC001, 0x00).unwrap();
+        for _ in 0..200 {
+            gb.cycle();
+        }
+        assert_ne!(gb.get_memory_u8(memory::TIMER_DIVIDER_REGISTER).unwrap(), 0);
+
+        gb.step().unwrap();
+        assert_eq!(gb.get_memory_u8(memory::TIMER_DIVIDER_REGISTER).unwrap(), 0);
+        assert_eq!(gb.power_saving_mode(), PowerSavingMode::Stop);
+        assert!(!gb.double_speed());
+    }
+
+    #[test]
+    fn test_speed_switch() {
+        let mut gb = make_color_gameboy();
+        gb.write_register_u16(wr::PC, 0xC000);
+        gb.set_memory_u8(0xC000, 0x10).unwrap(); // STOP
+        gb.set_memory_u8(0xC001, 0x10).unwrap(); // STOP
+        assert_eq!(gb.get_memory_u8(memory::SPEED_SWITCH_ADDR).unwrap(), 0x7E);
+
+        gb.set_memory_u8(memory::SPEED_SWITCH_ADDR, 0x01).unwrap();
+        gb.step().unwrap();
+        assert!(gb.double_speed());
+        assert_eq!(gb.power_saving_mode(), PowerSavingMode::None);
+        assert_eq!(gb.get_memory_u8(memory::SPEED_SWITCH_ADDR).unwrap(), 0xFE);
+
+        gb.set_memory_u8(memory::SPEED_SWITCH_ADDR, 0x01).unwrap();
+        gb.step().unwrap();
+        assert!(!gb.double_speed());
+        assert_eq!(gb.get_memory_u8(memory::SPEED_SWITCH_ADDR).unwrap(), 0x7E);
+    }
+
+    #[test]
+    fn test_speed_switch_requires_color_mode() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoyColor);
+        gb.set_memory_u8(memory::SPEED_SWITCH_ADDR, 0x01).unwrap();
+        assert!(gb.get_memory_u8(memory::SPEED_SWITCH_ADDR).is_err());
+        gb.stop();
+        assert!(!gb.double_speed());
+        assert_eq!(gb.power_saving_mode(), PowerSavingMode::Stop);
+    }
+
+    #[test]
+    fn test_double_speed_timer() {
+        // One line of the display, in clocks at normal speed
+        const LINE_CLOCKS: u64 = 456;
+        let mut gb = make_color_gameboy();
+        gb.set_memory_u8(memory::SPEED_SWITCH_ADDR, 0x01).unwrap();
+        gb.stop();
+        gb.timer.reset_divider(&mut gb.mem);
+        let start_clocks = gb.clocks_elapsed();
+        let start_line = gb.mem.registers().ly;
+
+        // DIV normally increments every 256 clocks, but in double speed
+        // mode it increments every 128 clocks of wall clock time
+        while gb.clocks_elapsed() - start_clocks < LINE_CLOCKS * 2 {
+            gb.cycle();
+        }
+        assert_eq!(
+            u64::from(gb.get_memory_u8(memory::TIMER_DIVIDER_REGISTER).unwrap()),
+            LINE_CLOCKS * 2 / 128
+        );
+        // The PPU still runs at normal speed
+        assert_eq!(gb.mem.registers().ly, start_line + 2);
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn test_profile() {
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        for _ in 0..10 {
+            gb.step().unwrap();
+        }
+
+        let profile = gb.profile().unwrap();
+        assert_eq!(profile.steps, 10);
+        assert_eq!(profile.cycles, gb.cycles_elapsed());
+        assert!(profile.total() > core::time::Duration::from_secs(0));
+        gb.reset_profile();
+        assert_eq!(gb.profile(), Some(CycleProfile::default()));
+    }
+
+    #[test]
+    fn test_overclock() {
+        // One line of the display, in clocks
+        const LINE_CLOCKS: u64 = 456;
+        let mut gb = GameBoy::new(make_cartridge(), GameBoyModel::GameBoy);
+        gb.set_overclock(u16::MAX);
+        assert_eq!(gb.overclock(), MAX_OVERCLOCK_CYCLES);
+        gb.set_overclock(114);
+        gb.timer.reset_divider(&mut gb.mem);
+        let start_clocks = gb.clocks_elapsed();
+        let start_line = gb.mem.registers().ly;
+
+        let mut cpu_cycles = 0;
+        while gb.clocks_elapsed() - start_clocks < LINE_CLOCKS * 2 {
+            gb.cycle();
+            cpu_cycles += 1;
+        }
+        // The CPU runs twice as many cycles, apart from the last extra cycle
+        // which is owed but not run yet. The timer and PPU keep their speed.
+        assert_eq!(cpu_cycles, LINE_CLOCKS * 2 / 4 * 2 - 1);
+        assert_eq!(
+            u64::from(gb.get_memory_u8(memory::TIMER_DIVIDER_REGISTER).unwrap()),
+            LINE_CLOCKS * 2 / 256
+        );
+        assert_eq!(gb.mem.registers().ly, start_line + 2);
+    }
+
+    fn oam_pattern(index: u16) -> u8 {
+        (index as u8).wrapping_mul(0x3D)
+    }
+
+    /// OAM filled with `oam_pattern`, except for the given rows
+    fn oam_with_rows(rows: &[(usize, [u8; 8])]) -> Vec<u8> {
+        let mut oam: Vec<u8> = (0..160).map(oam_pattern).collect();
+        for (row, data) in rows {
+            oam[row * 8..row * 8 + 8].copy_from_slice(data);
+        }
+        oam
+    }
+
+    /// Run `code` from the first cycle of an OAM scan, with HL and SP pointing into OAM
+    fn run_oam_bug_instruction(model: GameBoyModel, enabled: bool, code: &[u8]) -> Vec<u8> {
+        let mut gb = GameBoy::new(make_cartridge(), model);
+        gb.set_oam_corruption(enabled);
+        for i in 0..160u16 {
+            gb.mem.write_u8(0xFE00 + i, oam_pattern(i)).unwrap();
+        }
+        gb.set_memory_chunk(0xC000, code).unwrap();
+        gb.write_register_u16(registers::WordRegister::PC, 0xC000);
+        gb.write_register_u16(registers::WordRegister::HL, 0xFE10);
+        gb.write_register_u16(registers::WordRegister::SP, 0xFE10);
+        gb.mem.registers_mut().lcdc = 0x91;
+        while gb.ppu.oam_scan_row(&gb.mem) != Some(0) {
+            gb.cycle();
+        }
+        gb.step().unwrap();
+        (0..160u16)
+            .map(|i| gb.mem.read_u8(0xFE00 + i).unwrap())
+            .collect()
+    }
+
+    // Row 1 mixed with row 0, which holds 00 3D 7A B7 F4 31 6E AB
+    const OAM_WRITE_ROW: [u8; 8] = [0xE0, 0x35, 0x7A, 0xB7, 0xF4, 0x31, 0x6E, 0xAB];
+    const OAM_READ_ROW: [u8; 8] = [0xE0, 0x3D, 0x7A, 0xB7, 0xF4, 0x31, 0x6E, 0xAB];
+
+    #[test]
+    fn test_oam_corruption() {
+        let inc_hl = [0x23];
+
+        assert_eq!(
+            run_oam_bug_instruction(GameBoyModel::GameBoy, false, &inc_hl),
+            oam_with_rows(&[])
+        );
+        assert_eq!(
+            run_oam_bug_instruction(GameBoyModel::GameBoyColor, true, &inc_hl),
+            oam_with_rows(&[])
+        );
+        // ((0x25E8 ^ 0x31F4) & (0x3D00 ^ 0x31F4)) ^ 0x31F4
+        assert_eq!(
+            run_oam_bug_instruction(GameBoyModel::GameBoy, true, &inc_hl),
+            oam_with_rows(&[(1, OAM_WRITE_ROW)])
+        );
+    }
+
+    #[test]
+    fn test_oam_corruption_hl_and_stack() {
+        let run = |code: &[u8]| run_oam_bug_instruction(GameBoyModel::GameBoy, true, code);
+
+        // LD (HL+), A
+        assert_eq!(run(&[0x22]), oam_with_rows(&[(1, OAM_WRITE_ROW)]));
+        // LD A, (HL-): 0x3D00 | (0x25E8 & 0x31F4)
+        assert_eq!(run(&[0x3A]), oam_with_rows(&[(1, OAM_READ_ROW)]));
+        // Each byte pushed or popped corrupts the next row, mixed with the last
+        let pushed = [0xF0, 0x35, 0x7A, 0xB7, 0xF4, 0x31, 0x6E, 0xAB];
+        let popped = [0xF0, 0x3D, 0x7A, 0xB7, 0xF4, 0x31, 0x6E, 0xAB];
+        // PUSH BC
+        assert_eq!(
+            run(&[0xC5]),
+            oam_with_rows(&[(1, OAM_WRITE_ROW), (2, pushed)])
+        );
+        // POP BC
+        assert_eq!(
+            run(&[0xC1]),
+            oam_with_rows(&[(1, OAM_READ_ROW), (2, popped)])
+        );
+        // RET
+        assert_eq!(
+            run(&[0xC9]),
+            oam_with_rows(&[(1, OAM_READ_ROW), (2, popped)])
+        );
+        // CALL C100h, which pushes after reading the address
+        assert_eq!(
+            run(&[0xCD, 0x00, 0xC1]),
+            oam_with_rows(&[
+                (3, [0xD0, 0x05, 0x4A, 0x87, 0xC4, 0x01, 0x3E, 0x7B]),
+                (4, [0xC0, 0x05, 0x4A, 0x87, 0xC4, 0x01, 0x3E, 0x7B]),
+            ])
+        );
+    }
 }
@@ -1,7 +1,12 @@
 use crate::events;
+use crate::logging;
 use crate::registers;
 use crate::rom;
-use alloc::rc::Rc;
+use crate::shared::Shared;
+
+use alloc::string::String;
+use core::str::FromStr;
+use derive_more::Display;
 
 // Re-export long name, but use short name internally
 pub use crate::registers::{ByteRegister, WordRegister};
@@ -17,15 +22,45 @@ pub(crate) enum InterruptState {
     Disabled,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A source of CPU interrupts
 pub enum Interrupt {
+    #[display(fmt = "VBlank")]
     VBlank,
+    #[display(fmt = "STAT")]
     LCDStatus,
+    #[display(fmt = "Timer")]
     Timer,
+    #[display(fmt = "Serial")]
     Serial,
+    #[display(fmt = "Joypad")]
     Input,
 }
 
+/// Indicates a value could not be parsed as an interrupt
+#[derive(Debug, Display)]
+#[display(
+    fmt = "{} is not an interrupt. Expected one of vblank, stat, timer, serial or joypad",
+    _0
+)]
+pub struct InterruptParseError(String);
+
+impl FromStr for Interrupt {
+    type Err = InterruptParseError;
+
+    fn from_str(s: &str) -> Result<Interrupt, InterruptParseError> {
+        match s.to_lowercase().as_str() {
+            "vblank" => Ok(Interrupt::VBlank),
+            "stat" | "lcdstat" => Ok(Interrupt::LCDStatus),
+            "timer" => Ok(Interrupt::Timer),
+            "serial" => Ok(Interrupt::Serial),
+            "joypad" | "input" => Ok(Interrupt::Input),
+            _ => Err(InterruptParseError(s.into())),
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum PowerSavingMode {
     Stop,
@@ -63,12 +98,12 @@ impl Interrupt {
     }
 
     pub(crate) fn set(&self, register: &mut u8) {
-        log::trace!(target: "cpu/interrupt", "Enabled interrupt {:?}", self);
+        log::trace!(target: logging::CPU_INTERRUPTS, "Enabled interrupt {:?}", self);
         *register |= self.mask();
     }
 
     pub(crate) fn clear(&self, register: &mut u8) {
-        log::trace!(target: "cpu/interrupt", "Cleared interrupt {:?}", self);
+        log::trace!(target: logging::CPU_INTERRUPTS, "Cleared interrupt {:?}", self);
         *register &= !self.mask();
     }
 
@@ -106,7 +141,7 @@ impl Registers {
             br::H => self.hl.to_le_bytes()[1],
             br::L => self.hl.to_le_bytes()[0],
         };
-        log::trace!(target: "cpu/registers", "Read {:X} from {:?}", val, reg);
+        log::trace!(target: logging::CPU_REGISTERS, "Read {:X} from {:?}", val, reg);
         val
     }
 
@@ -119,12 +154,12 @@ impl Registers {
             wr::SP => self.sp.to_le(),
             wr::PC => self.pc.to_le(),
         };
-        log::trace!(target: "cpu/registers", "Read {:X} from {:?}", val, reg);
+        log::trace!(target: logging::CPU_REGISTERS, "Read {:X} from {:?}", val, reg);
         val
     }
 
     fn write_u8(&mut self, reg: registers::ByteRegister, value: u8) {
-        log::trace!(target: "cpu/registers", "Writing {:X} to {:?}", value, reg);
+        log::trace!(target: logging::CPU_REGISTERS, "Writing {:X} to {:?}", value, reg);
         let full_register = reg.lookup_word_register();
         let byte = reg.lookup_byte();
 
@@ -150,8 +185,13 @@ impl Registers {
     }
 
     fn write_raw(&mut self, reg: registers::WordRegister, value: u16) {
+        // The low nibble of F is always 0, so report the value actually stored
+        let value = match reg {
+            registers::WordRegister::AF => value & 0xfff0,
+            _ => value,
+        };
         match reg {
-            registers::WordRegister::AF => self.af = value & 0xfff0,
+            registers::WordRegister::AF => self.af = value,
             registers::WordRegister::BC => self.bc = value,
             registers::WordRegister::DE => self.de = value,
             registers::WordRegister::HL => self.hl = value,
@@ -163,7 +203,7 @@ impl Registers {
     }
 
     fn write_u16(&mut self, reg: registers::WordRegister, value: u16) {
-        log::trace!(target: "cpu/registers", "Writing {:X} to {:?}", value, reg);
+        log::trace!(target: logging::CPU_REGISTERS, "Writing {:X} to {:?}", value, reg);
         self.write_raw(reg, value.to_le());
     }
 
@@ -181,17 +221,17 @@ impl Registers {
 
     fn read_flag(&self, flag: registers::Flag) -> bool {
         let value = self.af & (1u16 << flag.bit()) != 0;
-        log::trace!(target: "cpu/flags", "Read flag {:?}: {}", flag, value);
+        log::trace!(target: logging::CPU_FLAGS, "Read flag {:?}: {}", flag, value);
         value
     }
 
     fn set_flag(&mut self, flag: registers::Flag) {
-        log::trace!(target: "cpu/flags", "Enabled flag {:?}", flag);
+        log::trace!(target: logging::CPU_FLAGS, "Enabled flag {:?}", flag);
         self.af |= 1 << flag.bit();
     }
 
     fn reset_flag(&mut self, flag: registers::Flag) {
-        log::trace!(target: "cpu/flags", "Reset flag {:?}", flag);
+        log::trace!(target: logging::CPU_FLAGS, "Reset flag {:?}", flag);
         self.af &= !(1u16 << flag.bit());
     }
 }
@@ -219,7 +259,8 @@ pub(crate) struct Cpu {
     registers: Registers,
     pub(crate) interrupts_enabled: InterruptState,
     pub(crate) power_saving: PowerSavingMode,
-    pub(crate) events: Rc<events::EventEmitter<events::RegisterWriteEvent>>, // address_bus: AddressBus
+    #[cfg_attr(not(feature = "events"), allow(dead_code))]
+    pub(crate) events: Shared<events::EventEmitter<events::RegisterWriteEvent>>, // address_bus: AddressBus
 }
 
 impl Cpu {
@@ -228,10 +269,11 @@ impl Cpu {
             registers: Registers::default_for_model(model, target),
             interrupts_enabled: InterruptState::Disabled,
             power_saving: PowerSavingMode::None,
-            events: Rc::new(events::EventEmitter::new()),
+            events: Shared::new(events::EventEmitter::new()),
             // address_bus: AddressBus::default()
         };
 
+        #[cfg(feature = "events")]
         events::propagate_events(&cpu.registers.events, cpu.events.clone());
         cpu
     }
@@ -395,11 +437,12 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "events")]
     fn test_write_event() {
-        use core::cell::RefCell;
-        let event_log: Rc<RefCell<Vec<events::RegisterWriteEvent>>> =
-            Rc::new(RefCell::new(Vec::new()));
-        let handler_log = Rc::clone(&event_log);
+        use crate::shared::Lock;
+        let event_log: Shared<Lock<Vec<events::RegisterWriteEvent>>> =
+            Shared::new(Lock::new(Vec::new()));
+        let handler_log = Shared::clone(&event_log);
 
         let handler = move |evt: &events::RegisterWriteEvent| {
             handler_log.borrow_mut().push(*evt);
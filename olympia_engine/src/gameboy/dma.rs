@@ -1,13 +1,22 @@
 use crate::gameboy::memory;
+use crate::logging;
 
 pub const OAM_BASE: u16 = 0xFE00;
 
-#[derive(PartialEq, Eq, Debug)]
+/// Bytes copied per block of a CGB HDMA transfer
+pub const HDMA_BLOCK_SIZE: u16 = 0x10;
+/// Bytes copied each machine cycle by a CGB HDMA transfer
+const HDMA_BYTES_PER_CYCLE: u16 = 2;
+const HDMA_HBLANK_MODE: u8 = 0x80;
+const HDMA_INACTIVE: u8 = 0x80;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 enum DmaState {
     Idle,
     Copying,
 }
 
+#[derive(Clone)]
 pub(crate) struct DmaUnit {
     state: DmaState,
     idx: u16,
@@ -53,12 +62,142 @@ impl Default for DmaUnit {
     }
 }
 
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum HdmaMode {
+    Idle,
+    /// Copy all blocks at once, stalling the CPU until done
+    General,
+    /// Copy one block at the start of each HBlank
+    HBlank,
+}
+
+/// Game Boy Color VRAM DMA unit
+#[derive(Clone)]
+pub(crate) struct HdmaUnit {
+    mode: HdmaMode,
+    source: u16,
+    dest: u16,
+    blocks_remaining: u16,
+    block_offset: u16,
+    copying_block: bool,
+    was_in_hblank: bool,
+}
+
+impl HdmaUnit {
+    fn start(&mut self, mem: &mut memory::Memory, control: u8) {
+        let registers = mem.registers_mut();
+        if self.mode == HdmaMode::HBlank && control & HDMA_HBLANK_MODE == 0 {
+            log::info!(target: logging::DMA, "Cancelled HDMA with {} blocks left", self.blocks_remaining);
+            self.mode = HdmaMode::Idle;
+            self.copying_block = false;
+            registers.hdma_control = HDMA_INACTIVE | (self.remaining_length() & 0x7F);
+            return;
+        }
+        self.source = registers.hdma_source;
+        self.dest = registers.hdma_dest;
+        self.blocks_remaining = u16::from(control & 0x7F) + 1;
+        self.block_offset = 0;
+        self.mode = if control & HDMA_HBLANK_MODE == 0 {
+            HdmaMode::General
+        } else {
+            HdmaMode::HBlank
+        };
+        // An HBlank transfer started mid-HBlank waits for the next one
+        self.copying_block = self.mode == HdmaMode::General;
+        registers.hdma_control = control & 0x7F;
+        log::info!(
+            target: logging::DMA,
+            "Started {:?} HDMA of {} blocks from {:04X} to {:04X}",
+            self.mode,
+            self.blocks_remaining,
+            self.source,
+            self.dest + memory::VRAM.start
+        );
+    }
+
+    fn remaining_length(&self) -> u8 {
+        (self.blocks_remaining.saturating_sub(1) & 0x7F) as u8
+    }
+
+    /// Indicates if the CPU is halted while a block is copied
+    pub(crate) fn is_stalling(&self) -> bool {
+        self.mode != HdmaMode::Idle && self.copying_block
+    }
+
+    pub(crate) fn run_cycle(
+        &mut self,
+        mem: &mut memory::Memory,
+        in_hblank: bool,
+    ) -> memory::MemoryResult<()> {
+        if let Some(control) = mem.registers_mut().hdma_request.take() {
+            self.start(mem, control);
+        }
+        if self.mode == HdmaMode::HBlank && in_hblank && !self.was_in_hblank {
+            self.copying_block = true;
+        }
+        self.was_in_hblank = in_hblank;
+        if self.is_stalling() {
+            self.copy_bytes(mem)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn copy_bytes(&mut self, mem: &mut memory::Memory) -> memory::MemoryResult<()> {
+        for _ in 0..HDMA_BYTES_PER_CYCLE {
+            let value = mem.read_u8(self.source.wrapping_add(self.block_offset))?;
+            let dest = (self.dest + self.block_offset) & (memory::VRAM.len - 1);
+            mem.write_u8(memory::VRAM.start + dest, value)?;
+            self.block_offset += 1;
+        }
+        if self.block_offset == HDMA_BLOCK_SIZE {
+            self.finish_block(mem);
+        }
+        Ok(())
+    }
+
+    fn finish_block(&mut self, mem: &mut memory::Memory) {
+        self.source = self.source.wrapping_add(HDMA_BLOCK_SIZE);
+        self.dest = (self.dest + HDMA_BLOCK_SIZE) & (memory::VRAM.len - 1);
+        self.block_offset = 0;
+        self.blocks_remaining -= 1;
+        if self.mode == HdmaMode::HBlank {
+            self.copying_block = false;
+        }
+        let registers = mem.registers_mut();
+        registers.hdma_source = self.source;
+        registers.hdma_dest = self.dest;
+        if self.blocks_remaining == 0 {
+            self.mode = HdmaMode::Idle;
+            self.copying_block = false;
+            registers.hdma_control = 0xFF;
+        } else {
+            registers.hdma_control = self.remaining_length();
+        }
+    }
+}
+
+impl Default for HdmaUnit {
+    fn default() -> HdmaUnit {
+        HdmaUnit {
+            mode: HdmaMode::Idle,
+            source: 0,
+            dest: 0,
+            blocks_remaining: 0,
+            block_offset: 0,
+            copying_block: false,
+            was_in_hblank: false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::gameboy::testutils;
     use crate::gameboy::{GameBoy, GameBoyModel};
+    use crate::registers;
     use crate::rom::Cartridge;
+    use crate::testing;
     use alloc::vec::Vec;
 
     fn make_gameboy_dma_data(start_index: usize, sample_data: Vec<u8>) -> GameBoy {
@@ -152,7 +291,7 @@ mod test {
 
         let total_ins_cnt = dma_instruction_cnt + loader_instruction_cnt;
 
-        let gb = testutils::run_program(total_ins_cnt, &loader_code).unwrap();
+        let gb = testing::run_program(total_ins_cnt, &loader_code).unwrap();
 
         assert_eq!(gb.dma.register_value, 0xc0);
         assert_eq!(gb.dma.state, DmaState::Idle);
@@ -169,4 +308,114 @@ mod test {
         }
         assert_eq!(gb.clocks_elapsed(), (loader_cycles + dma_cycles) * 4);
     }
+
+    fn make_color_gameboy() -> GameBoy {
+        let mut rom_data = vec![0; 0x8000];
+        for i in 0..0x40 {
+            rom_data[0x2000 + i] = i as u8;
+        }
+        rom_data[0x143] = 0xC0;
+        GameBoy::new(
+            Cartridge::from_data(rom_data).unwrap(),
+            GameBoyModel::GameBoyColor,
+        )
+    }
+
+    fn setup_hdma(gameboy: &mut GameBoy) {
+        gameboy.write_memory_u8(0xFF51, 0x20).unwrap();
+        gameboy.write_memory_u8(0xFF52, 0x00).unwrap();
+        gameboy.write_memory_u8(0xFF53, 0x81).unwrap();
+        gameboy.write_memory_u8(0xFF54, 0x05).unwrap();
+    }
+
+    #[test]
+    fn test_gdma_copy() {
+        let mut gameboy = make_color_gameboy();
+        setup_hdma(&mut gameboy);
+        gameboy.write_memory_u8(0xFF55, 0x01).unwrap();
+
+        for _ in 0..16 {
+            gameboy.hdma.run_cycle(&mut gameboy.mem, false).unwrap();
+        }
+
+        for i in 0..0x20 {
+            assert_eq!(gameboy.read_memory_u8(0x8100 + i).unwrap(), i as u8);
+        }
+        assert_eq!(gameboy.read_memory_u8(0x8120).unwrap(), 0);
+        assert_eq!(gameboy.read_memory_u8(0xFF55).unwrap(), 0xFF);
+        assert!(!gameboy.hdma.is_stalling());
+    }
+
+    #[test]
+    fn test_gdma_stalls_cpu() {
+        let mut gameboy = make_color_gameboy();
+        setup_hdma(&mut gameboy);
+        gameboy.write_memory_u8(0xFF55, 0x03).unwrap();
+        gameboy.write_register_u16(registers::WordRegister::PC, 0x150);
+        gameboy.hdma.run_cycle(&mut gameboy.mem, false).unwrap();
+        assert!(gameboy.hdma.is_stalling());
+
+        // NOP
+        gameboy.step().unwrap();
+
+        assert!(!gameboy.hdma.is_stalling());
+        assert_eq!(gameboy.cycles_elapsed(), 31 + 1);
+        assert_eq!(gameboy.get_memory_u8(0x813F).unwrap(), 0x3F);
+    }
+
+    #[test]
+    fn test_hdma_copies_block_per_hblank() {
+        let mut gameboy = make_color_gameboy();
+        setup_hdma(&mut gameboy);
+        gameboy.write_memory_u8(0xFF55, 0x81).unwrap();
+
+        for _ in 0..20 {
+            gameboy.hdma.run_cycle(&mut gameboy.mem, false).unwrap();
+        }
+        assert_eq!(gameboy.read_memory_u8(0x8100).unwrap(), 0);
+        assert_eq!(gameboy.read_memory_u8(0xFF55).unwrap(), 0x01);
+
+        for _ in 0..20 {
+            gameboy.hdma.run_cycle(&mut gameboy.mem, true).unwrap();
+        }
+        assert_eq!(gameboy.read_memory_u8(0x810F).unwrap(), 0x0F);
+        assert_eq!(gameboy.read_memory_u8(0x8110).unwrap(), 0);
+        assert_eq!(gameboy.read_memory_u8(0xFF55).unwrap(), 0x00);
+
+        gameboy.hdma.run_cycle(&mut gameboy.mem, false).unwrap();
+        for _ in 0..8 {
+            gameboy.hdma.run_cycle(&mut gameboy.mem, true).unwrap();
+        }
+        assert_eq!(gameboy.read_memory_u8(0x811F).unwrap(), 0x1F);
+        assert_eq!(gameboy.read_memory_u8(0xFF55).unwrap(), 0xFF);
+    }
+
+    #[test]
+    fn test_hdma_cancel() {
+        let mut gameboy = make_color_gameboy();
+        setup_hdma(&mut gameboy);
+        gameboy.write_memory_u8(0xFF55, 0x82).unwrap();
+        gameboy.hdma.run_cycle(&mut gameboy.mem, false).unwrap();
+        for _ in 0..8 {
+            gameboy.hdma.run_cycle(&mut gameboy.mem, true).unwrap();
+        }
+
+        gameboy.write_memory_u8(0xFF55, 0x00).unwrap();
+        gameboy.hdma.run_cycle(&mut gameboy.mem, false).unwrap();
+        gameboy.hdma.run_cycle(&mut gameboy.mem, true).unwrap();
+
+        assert_eq!(gameboy.read_memory_u8(0xFF55).unwrap(), 0x81);
+        assert_eq!(gameboy.read_memory_u8(0x8110).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_hdma_unavailable_without_color() {
+        let mut gameboy = make_gameboy_dma_data(0x2000, vec![0x23; 0x10]);
+
+        gameboy.write_memory_u8(0xFF55, 0x00).unwrap();
+        gameboy.hdma.run_cycle(&mut gameboy.mem, false).unwrap();
+
+        assert!(!gameboy.hdma.is_stalling());
+        assert!(gameboy.read_memory_u8(0xFF55).is_err());
+    }
 }
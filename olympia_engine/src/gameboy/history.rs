@@ -0,0 +1,72 @@
+use super::{
+    cpu::{InterruptState, PowerSavingMode},
+    dma::{DmaUnit, HdmaUnit},
+    memory::MemoryRegisters,
+    overclock::Overclock,
+    ppu::PpuTiming,
+    timer::Timer,
+    RegisterSnapshot,
+};
+use crate::rom::BankRegisters;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// The machine state before a single step
+pub(crate) struct HistoryEntry {
+    pub(crate) registers: RegisterSnapshot,
+    pub(crate) interrupts_enabled: InterruptState,
+    pub(crate) power_saving: PowerSavingMode,
+    pub(crate) mem_registers: MemoryRegisters,
+    pub(crate) dma: DmaUnit,
+    pub(crate) hdma: HdmaUnit,
+    pub(crate) timer: Timer,
+    pub(crate) overclock: Overclock,
+    pub(crate) ppu: PpuTiming,
+    pub(crate) cartridge_banks: BankRegisters,
+    pub(crate) clocks_elapsed: u64,
+    /// Previous values of memory written during the step, in write order
+    pub(crate) writes: Vec<(u16, u8)>,
+}
+
+/// A bounded ring of the most recent steps
+#[derive(Default)]
+pub(crate) struct History {
+    limit: usize,
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl History {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.limit > 0
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.entries.len() > limit {
+            self.entries.pop_front();
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn push(&mut self, entry: HistoryEntry) {
+        if !self.is_enabled() {
+            return;
+        }
+        if self.entries.len() == self.limit {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<HistoryEntry> {
+        self.entries.pop_back()
+    }
+
+    /// Recorded steps, oldest first
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+}
@@ -0,0 +1,79 @@
+use super::joypad::Button;
+use crate::shared::{Lock, Shared};
+
+use alloc::vec::Vec;
+
+/// When button changes pushed to an [`InputQueue`] reach the joypad
+///
+/// [`InputQueue`]: ./struct.InputQueue.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InputLatching {
+    /// Changes are applied when the next frame's VBlank starts, so the game
+    /// sees the same input regardless of when it was pushed during a frame
+    #[default]
+    Frame,
+    /// Changes are applied before the next step
+    Immediate,
+}
+
+/// Button changes waiting to be applied to a gameboy's joypad
+///
+/// Get a handle to a gameboy's queue with [`GameBoy::input_queue`]. Handles
+/// can be cloned and kept by a frontend, which can push changes at any time,
+/// such as from its UI thread when the `sync` feature is enabled. Changes are
+/// applied in the order they were pushed, at the time given by the gameboy's
+/// [`InputLatching`].
+///
+/// ```
+/// use olympia_engine::gameboy::{Button, GameBoy, GameBoyModel};
+/// use olympia_engine::rom::Cartridge;
+///
+/// let cartridge = Cartridge::from_data(vec![0u8; 0x8000]).unwrap();
+/// let mut gb = GameBoy::new(cartridge, GameBoyModel::GameBoy);
+/// let input = gb.input_queue();
+///
+/// input.push(Button::Start, true);
+/// gb.step().unwrap();
+/// // Waiting for the next frame
+/// assert_eq!(input.len(), 1);
+/// ```
+///
+/// [`GameBoy::input_queue`]: ./struct.GameBoy.html#method.input_queue
+/// [`InputLatching`]: ./enum.InputLatching.html
+#[derive(Debug, Clone, Default)]
+pub struct InputQueue {
+    pending: Shared<Lock<Vec<(Button, bool)>>>,
+}
+
+impl InputQueue {
+    /// Create an empty queue
+    pub fn new() -> InputQueue {
+        InputQueue::default()
+    }
+
+    /// Queue pressing or releasing a button
+    pub fn push(&self, button: Button, pressed: bool) {
+        self.pending.borrow_mut().push((button, pressed));
+    }
+
+    /// The number of changes waiting to be applied
+    pub fn len(&self) -> usize {
+        self.pending.borrow().len()
+    }
+
+    /// Whether there are no changes waiting to be applied
+    pub fn is_empty(&self) -> bool {
+        self.pending.borrow().is_empty()
+    }
+
+    /// Discard all waiting changes
+    pub fn clear(&self) {
+        self.pending.borrow_mut().clear();
+    }
+
+    /// Remove all waiting changes, in the order they were pushed
+    pub(crate) fn take(&self) -> Vec<(Button, bool)> {
+        core::mem::take(&mut *self.pending.borrow_mut())
+    }
+}
@@ -0,0 +1,170 @@
+//! Callbacks for writes to memory mapped IO
+//!
+//! Hardware effects such as a cartridge's rumble motor or the Game Boy
+//! Color's infrared LED are switched by writing to an address. An
+//! [`IoObserver`] is told when the value written to one of its addresses
+//! changes, so frontends can surface these effects, such as by rumbling a
+//! controller, without the engine needing support for each one.
+//!
+//! [`IoObserver`]: trait.IoObserver.html
+
+use crate::shared::MaybeSend;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+/// A write which changed the value at an observed address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IoWrite {
+    /// Address written to
+    pub address: u16,
+    /// Value written
+    pub value: u8,
+    /// Value last written to the address, or `None` if this is the first write observed
+    pub previous: Option<u8>,
+}
+
+/// Told about writes to a range of addresses, added with [`GameBoy::observe_io`]
+///
+/// Observers see the value written, rather than the value read back,
+/// as many cartridge and IO registers can't be read. Writes of the same
+/// value as the last write aren't reported. Closures taking an [`IoWrite`]
+/// are observers.
+///
+/// Observers must be `Send` when the `sync` feature is enabled
+///
+/// [`GameBoy::observe_io`]: struct.GameBoy.html#method.observe_io
+/// [`IoWrite`]: struct.IoWrite.html
+pub trait IoObserver: MaybeSend {
+    /// Called after a write changes the value at an observed address
+    fn changed(&mut self, write: IoWrite);
+}
+
+impl<F: FnMut(IoWrite) + MaybeSend> IoObserver for F {
+    fn changed(&mut self, write: IoWrite) {
+        self(write)
+    }
+}
+
+/// Identifies an observer, so it can be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct IoObserverId(u32);
+
+struct Observer {
+    id: IoObserverId,
+    addresses: RangeInclusive<u16>,
+    /// Value last written to each address in the range
+    last_written: Vec<Option<u8>>,
+    observer: Box<dyn IoObserver>,
+}
+
+#[derive(Default)]
+pub(crate) struct IoObservers {
+    observers: Vec<Observer>,
+    next_id: u32,
+}
+
+impl IoObservers {
+    pub(crate) fn add(
+        &mut self,
+        addresses: RangeInclusive<u16>,
+        observer: Box<dyn IoObserver>,
+    ) -> IoObserverId {
+        let id = IoObserverId(self.next_id);
+        self.next_id += 1;
+        let len = addresses.clone().count();
+        self.observers.push(Observer {
+            id,
+            addresses,
+            last_written: vec![None; len],
+            observer,
+        });
+        id
+    }
+
+    /// Remove an observer, returning whether it was found
+    pub(crate) fn remove(&mut self, id: IoObserverId) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|observer| observer.id != id);
+        self.observers.len() != len
+    }
+
+    /// Tell observers of `address` about a write, if it changed the value written there
+    pub(crate) fn record(&mut self, address: u16, value: u8) {
+        for observer in self.observers.iter_mut() {
+            if !observer.addresses.contains(&address) {
+                continue;
+            }
+            let index = usize::from(address - observer.addresses.start());
+            let previous = observer.last_written[index].replace(value);
+            if previous != Some(value) {
+                observer.observer.changed(IoWrite {
+                    address,
+                    value,
+                    previous,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::{Lock, Shared};
+    use crate::testing::{self, Program};
+
+    #[test]
+    fn test_observer_sees_changes() {
+        let mut gb = Program::new(&[
+            0x3E, 0x08, // LD A, 08h
+            0xEA, 0x00, 0x40, // LD (4000h), A
+            0xEA, 0x00, 0x40, // LD (4000h), A
+            0xAF, // XOR A
+            0xEA, 0x01, 0x40, // LD (4001h), A
+            0xEA, 0x00, 0xC0, // LD (C000h), A
+            0xEA, 0x00, 0x40, // LD (4000h), A
+        ])
+        .load();
+        let writes: Shared<Lock<Vec<IoWrite>>> = Shared::new(Lock::new(Vec::new()));
+        let observer_writes = Shared::clone(&writes);
+        gb.observe_io(0x4000..=0x5FFF, move |write: IoWrite| {
+            observer_writes.borrow_mut().push(write)
+        });
+
+        testing::run_steps(&mut gb, 7).unwrap();
+
+        let write = |address, value, previous| IoWrite {
+            address,
+            value,
+            previous,
+        };
+        assert_eq!(
+            *writes.borrow(),
+            vec![
+                write(0x4000, 0x08, None),
+                write(0x4001, 0x00, None),
+                write(0x4000, 0x00, Some(0x08)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_observer() {
+        let mut observers = IoObservers::default();
+        let count = Shared::new(Lock::new(0));
+        let observer_count = Shared::clone(&count);
+        let id = observers.add(
+            0xFF56..=0xFF56,
+            Box::new(move |_: IoWrite| *observer_count.borrow_mut() += 1),
+        );
+
+        observers.record(0xFF56, 0x01);
+        assert!(observers.remove(id));
+        observers.record(0xFF56, 0x00);
+
+        assert_eq!(*count.borrow(), 1);
+        assert!(!observers.remove(id));
+    }
+}
@@ -0,0 +1,124 @@
+use super::cpu::Interrupt;
+use super::memory::MemoryRegisters;
+use alloc::string::ToString;
+use core::str::FromStr;
+use derive_more::Display;
+
+/// Bit 4 of P1 is cleared to read the direction buttons
+const SELECT_DIRECTIONS_MASK: u8 = 0x10;
+/// Bit 5 of P1 is cleared to read the action buttons
+const SELECT_ACTIONS_MASK: u8 = 0x20;
+
+/// A button on the gameboy's joypad
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /// All buttons on the joypad
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    /// Bit representing this button in the pressed button state
+    ///
+    /// Directions are in the lower nibble and actions in the upper nibble
+    const fn mask(self) -> u8 {
+        match self {
+            Button::Right => 0x01,
+            Button::Left => 0x02,
+            Button::Up => 0x04,
+            Button::Down => 0x08,
+            Button::A => 0x10,
+            Button::B => 0x20,
+            Button::Select => 0x40,
+            Button::Start => 0x80,
+        }
+    }
+}
+
+impl FromStr for Button {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Button, ()> {
+        Button::ALL
+            .iter()
+            .copied()
+            .find(|button| button.to_string().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+/// The button lines visible in P1, with set bits for pressed buttons
+fn selected_lines(select: u8, pressed: u8) -> u8 {
+    let mut lines = 0;
+    if select & SELECT_DIRECTIONS_MASK == 0 {
+        lines |= pressed & 0x0F;
+    }
+    if select & SELECT_ACTIONS_MASK == 0 {
+        lines |= pressed >> 4;
+    }
+    lines
+}
+
+/// Value of the P1 register for the given select bits and pressed buttons
+pub(crate) fn read_p1(select: u8, pressed: u8) -> u8 {
+    0xC0 | (select & 0x30) | (!selected_lines(select, pressed) & 0x0F)
+}
+
+/// Updates the pressed state of a button
+///
+/// Returns true if this caused a selected input line to go low, which
+/// raises the joypad interrupt
+pub(crate) fn set_button(registers: &mut MemoryRegisters, button: Button, pressed: bool) -> bool {
+    let before = selected_lines(registers.p1, registers.buttons_pressed);
+    if pressed {
+        registers.buttons_pressed |= button.mask();
+    } else {
+        registers.buttons_pressed &= !button.mask();
+    }
+    let after = selected_lines(registers.p1, registers.buttons_pressed);
+    let triggered = after & !before != 0;
+    if triggered {
+        Interrupt::Input.set(&mut registers.iflag);
+    }
+    triggered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_p1() {
+        let pressed = Button::A.mask() | Button::Down.mask();
+
+        assert_eq!(read_p1(0x30, pressed), 0xFF);
+        assert_eq!(read_p1(0x20, pressed), 0xE7);
+        assert_eq!(read_p1(0x10, pressed), 0xDE);
+        assert_eq!(read_p1(0x00, pressed), 0xC6);
+    }
+
+    #[test]
+    fn test_parse_button() {
+        assert_eq!("start".parse::<Button>(), Ok(Button::Start));
+        assert_eq!("A".parse::<Button>(), Ok(Button::A));
+        assert_eq!("turbo".parse::<Button>(), Err(()));
+    }
+}
@@ -1,9 +1,23 @@
 use crate::events;
-use crate::rom::Cartridge;
+use crate::gameboy::cpu::Interrupt;
+use crate::gameboy::io_observer::IoObservers;
+use crate::gameboy::joypad;
+use crate::gameboy::oam_bug;
+use crate::gameboy::ppu;
+use crate::gameboy::rng::Rng;
+use crate::rom::{BankRegisters, Cartridge, CartridgeController};
+use alloc::vec::Vec;
 use derive_more::Display;
 
 use olympia_core::address;
 
+mod registers;
+
+pub(crate) const JOYPAD_ADDR: u16 = 0xff00;
+
+pub(crate) const SERIAL_DATA_ADDR: u16 = 0xff01;
+pub(crate) const SERIAL_CONTROL_ADDR: u16 = 0xff02;
+
 pub(crate) const TIMER_DIVIDER_REGISTER: u16 = 0xff04;
 pub(crate) const TIMER_COUNTER_REGISTER: u16 = 0xff05;
 pub(crate) const TIMER_MODULO_REGISTER: u16 = 0xff06;
@@ -11,10 +25,25 @@ pub(crate) const TIMER_CONTROL_REGISTER: u16 = 0xff07;
 
 pub(crate) const DMA_REGISTER_ADDR: u16 = 0xff46;
 
+pub(crate) const SPEED_SWITCH_ADDR: u16 = 0xff4d;
+/// Set in KEY1 to switch speed at the next STOP instruction
+pub(crate) const SPEED_SWITCH_PREPARE: u8 = 0x01;
+/// Set in KEY1 while in double speed mode
+pub(crate) const SPEED_SWITCH_DOUBLE: u8 = 0x80;
+
+pub(crate) const HDMA_SOURCE_HIGH_ADDR: u16 = 0xff51;
+pub(crate) const HDMA_SOURCE_LOW_ADDR: u16 = 0xff52;
+pub(crate) const HDMA_DEST_HIGH_ADDR: u16 = 0xff53;
+pub(crate) const HDMA_DEST_LOW_ADDR: u16 = 0xff54;
+pub(crate) const HDMA_CONTROL_ADDR: u16 = 0xff55;
+
 pub(crate) const LCD_CONTROL_ADDR: u16 = 0xFF40;
 pub(crate) const LCD_STATUS_ADDR: u16 = 0xFF41;
 pub(crate) const SCROLL_Y_ADDR: u16 = 0xFF42;
 pub(crate) const SCROLL_X_ADDR: u16 = 0xFF43;
+pub(crate) const BG_PALETTE_ADDR: u16 = 0xFF47;
+pub(crate) const SPRITE_PALETTE_0_ADDR: u16 = 0xFF48;
+pub(crate) const SPRITE_PALETTE_1_ADDR: u16 = 0xFF49;
 pub(crate) const WINDOW_Y_ADDR: u16 = 0xFF4A;
 pub(crate) const WINDOW_X_ADDR: u16 = 0xFF4B;
 pub(crate) const CURRENT_LINE_ADDR: u16 = 0xFF44;
@@ -59,6 +88,7 @@ pub const CPU_RAM: MemoryRegion = MemoryRegion::new(0xFF80, 0x7F, "cpuram");
 pub const MODEL_RESERVED: MemoryRegion = MemoryRegion::new(0xFEA0, 0x60, "modelreserved");
 
 #[derive(PartialEq, Eq, Debug, Clone, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents a failure to read from memory.
 pub enum MemoryError {
     /// The address maps to the Cartridge ROM area,
@@ -91,7 +121,7 @@ impl<'a> Iterator for MemoryIterator<'a> {
     type Item = u8;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let val = self.mem.read_u8(self.addr);
+        let val = self.mem.read_u8_internal(self.addr);
         self.addr = self.addr.next();
         Some(val.unwrap_or(0))
     }
@@ -101,7 +131,13 @@ fn masked_write(current: &mut u8, new: u8, mask: u8) {
     *current = (new & mask) | (*current & !mask);
 }
 
+#[derive(Clone)]
 pub struct MemoryRegisters {
+    /// Joypad - Bits 4-5 select direction/action buttons, bits 0-3 read
+    /// the selected buttons (0 = pressed)
+    pub(crate) p1: u8,
+    /// Buttons currently held down, see `joypad::Button`
+    pub(crate) buttons_pressed: u8,
     /// Write upper byte of start addresses here to trigger DMA transfers
     /// to OAM RAM
     pub(crate) dma: u8,
@@ -125,6 +161,13 @@ pub struct MemoryRegisters {
     pub(crate) wy: u8,
     /// X Pixel offset (in screen co-ordinates, not tile map) to start window
     pub(crate) wx: u8,
+    /// Background and window palette - Bits 0-1 are the shade of colour 0,
+    /// bits 2-3 the shade of colour 1, and so on
+    pub(crate) bgp: u8,
+    /// Palette for sprites using palette 0. Colour 0 is transparent
+    pub(crate) obp0: u8,
+    /// Palette for sprites using palette 1. Colour 0 is transparent
+    pub(crate) obp1: u8,
     /// Interrupts where their conditions have been triggered
     pub(crate) iflag: u8,
     /// Interrupts that are enabled and can cause CPU interrupts
@@ -137,11 +180,31 @@ pub struct MemoryRegisters {
     pub(crate) tma: u8,
     /// Timer control - Controls TIMA enabled + tick rate
     pub(crate) tac: u8,
+    /// Serial data - Byte to send, replaced by the received byte after a transfer
+    pub(crate) sb: u8,
+    /// Serial control - Bit 7 starts a transfer, bit 0 selects the internal clock
+    pub(crate) sc: u8,
+    /// Source address for CGB HDMA transfers. Lower 4 bits are ignored
+    pub(crate) hdma_source: u16,
+    /// VRAM destination address for CGB HDMA transfers. Lower 4 bits are ignored
+    pub(crate) hdma_dest: u16,
+    /// Bit 7 = 0 while a HDMA transfer is active, bits 0-6 = remaining 16 byte blocks - 1
+    pub(crate) hdma_control: u8,
+    /// Value most recently written to HDMA control, until the DMA unit handles it
+    pub(crate) hdma_request: Option<u8>,
+    /// CGB speed switch - Bit 7 = double speed active, bit 0 = switch at next STOP
+    pub(crate) key1: u8,
+    /// Enables registers only present when running in Game Boy Color mode
+    pub(crate) color_mode: bool,
+    /// Whether writing STAT can raise the STAT interrupt, as on the original Game Boy
+    pub(crate) stat_write_bug: bool,
 }
 
 impl MemoryRegisters {
     fn new() -> MemoryRegisters {
         MemoryRegisters {
+            p1: 0,
+            buttons_pressed: 0,
             dma: 0,
             lcdc: 0x91,
             lcdstat: 0,
@@ -151,66 +214,149 @@ impl MemoryRegisters {
             lyc: 0,
             wy: 0,
             wx: 0,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
             iflag: 0,
             ie: 0,
             div: 0x18,
             tima: 0,
             tma: 0,
             tac: 0xF8,
+            sb: 0,
+            sc: 0x7E,
+            hdma_source: 0,
+            hdma_dest: 0,
+            hdma_control: 0xFF,
+            hdma_request: None,
+            key1: 0,
+            color_mode: false,
+            stat_write_bug: false,
         }
     }
 
+    /// Whether the CPU is running in Game Boy Color double speed mode
+    pub(crate) fn double_speed(&self) -> bool {
+        (self.key1 & SPEED_SWITCH_DOUBLE) != 0
+    }
+
+    /// Read a register as the CPU sees it, with unused bits set
     fn read(&self, addr: u16) -> Option<u8> {
-        match addr {
-            TIMER_DIVIDER_REGISTER => Some(self.div),
-            TIMER_COUNTER_REGISTER => Some(self.tima),
-            TIMER_MODULO_REGISTER => Some(self.tma),
-            TIMER_CONTROL_REGISTER => Some(self.tac),
-
-            DMA_REGISTER_ADDR => Some(self.dma),
-
-            LCD_CONTROL_ADDR => Some(self.lcdc),
-            LCD_STATUS_ADDR => Some(self.lcdstat),
-            SCROLL_Y_ADDR => Some(self.scy),
-            SCROLL_X_ADDR => Some(self.scx),
-            CURRENT_LINE_ADDR => Some(self.ly),
-            LINE_CHECK_ADDR => Some(self.lyc),
-            WINDOW_Y_ADDR => Some(self.wy),
-            WINDOW_X_ADDR => Some(self.wx),
-
-            INTERRUPT_FLAG_ADDR => Some(self.iflag),
-            INTERRUPT_ENABLE_ADDR => Some(self.ie),
-
-            _ => None,
-        }
+        let mask = registers::lookup(addr, self.color_mode)?;
+        let value = match addr {
+            JOYPAD_ADDR => joypad::read_p1(self.p1, self.buttons_pressed),
+            HDMA_SOURCE_HIGH_ADDR => (self.hdma_source >> 8) as u8,
+            HDMA_SOURCE_LOW_ADDR => self.hdma_source as u8,
+            HDMA_DEST_HIGH_ADDR => (self.hdma_dest >> 8) as u8,
+            HDMA_DEST_LOW_ADDR => self.hdma_dest as u8,
+            HDMA_CONTROL_ADDR => self.hdma_control,
+            _ => *self.field(addr)?,
+        };
+        Some(value | mask.read_ones)
     }
 
+    /// The field storing a register, see `field_mut`
+    fn field(&self, addr: u16) -> Option<&u8> {
+        let field = match addr {
+            JOYPAD_ADDR => &self.p1,
+            SERIAL_DATA_ADDR => &self.sb,
+            SERIAL_CONTROL_ADDR => &self.sc,
+            TIMER_DIVIDER_REGISTER => &self.div,
+            TIMER_COUNTER_REGISTER => &self.tima,
+            TIMER_MODULO_REGISTER => &self.tma,
+            TIMER_CONTROL_REGISTER => &self.tac,
+            INTERRUPT_FLAG_ADDR => &self.iflag,
+            LCD_CONTROL_ADDR => &self.lcdc,
+            LCD_STATUS_ADDR => &self.lcdstat,
+            SCROLL_Y_ADDR => &self.scy,
+            SCROLL_X_ADDR => &self.scx,
+            CURRENT_LINE_ADDR => &self.ly,
+            LINE_CHECK_ADDR => &self.lyc,
+            DMA_REGISTER_ADDR => &self.dma,
+            BG_PALETTE_ADDR => &self.bgp,
+            SPRITE_PALETTE_0_ADDR => &self.obp0,
+            SPRITE_PALETTE_1_ADDR => &self.obp1,
+            WINDOW_Y_ADDR => &self.wy,
+            WINDOW_X_ADDR => &self.wx,
+            SPEED_SWITCH_ADDR => &self.key1,
+            INTERRUPT_ENABLE_ADDR => &self.ie,
+            _ => return None,
+        };
+        Some(field)
+    }
+    /// The field storing a register which writes can change directly
+    fn field_mut(&mut self, addr: u16) -> Option<&mut u8> {
+        let field = match addr {
+            JOYPAD_ADDR => &mut self.p1,
+            SERIAL_DATA_ADDR => &mut self.sb,
+            SERIAL_CONTROL_ADDR => &mut self.sc,
+            TIMER_DIVIDER_REGISTER => &mut self.div,
+            TIMER_COUNTER_REGISTER => &mut self.tima,
+            TIMER_MODULO_REGISTER => &mut self.tma,
+            TIMER_CONTROL_REGISTER => &mut self.tac,
+            INTERRUPT_FLAG_ADDR => &mut self.iflag,
+            LCD_CONTROL_ADDR => &mut self.lcdc,
+            LCD_STATUS_ADDR => &mut self.lcdstat,
+            SCROLL_Y_ADDR => &mut self.scy,
+            SCROLL_X_ADDR => &mut self.scx,
+            CURRENT_LINE_ADDR => &mut self.ly,
+            LINE_CHECK_ADDR => &mut self.lyc,
+            DMA_REGISTER_ADDR => &mut self.dma,
+            BG_PALETTE_ADDR => &mut self.bgp,
+            SPRITE_PALETTE_0_ADDR => &mut self.obp0,
+            SPRITE_PALETTE_1_ADDR => &mut self.obp1,
+            WINDOW_Y_ADDR => &mut self.wy,
+            WINDOW_X_ADDR => &mut self.wx,
+            SPEED_SWITCH_ADDR => &mut self.key1,
+            INTERRUPT_ENABLE_ADDR => &mut self.ie,
+            _ => return None,
+        };
+        Some(field)
+    }
+
+    /// Write a register as the CPU does, only changing writable bits
     fn write(&mut self, addr: u16, value: u8) {
+        let mask = match registers::lookup(addr, self.color_mode) {
+            Some(mask) => mask,
+            None => return,
+        };
         match addr {
             TIMER_DIVIDER_REGISTER => self.div = 0,
-            TIMER_COUNTER_REGISTER => self.tima = value,
-            TIMER_MODULO_REGISTER => self.tma = value,
-            TIMER_CONTROL_REGISTER => masked_write(&mut self.tac, value, 0b111),
-
-            DMA_REGISTER_ADDR => self.dma = value,
-
-            LCD_CONTROL_ADDR => self.lcdc = value,
-            // Top bit doesn't exist
-            // Lower two bits are mode flag
-            LCD_STATUS_ADDR => masked_write(&mut self.lcdstat, value, 0b0111_1100),
-            SCROLL_Y_ADDR => self.scy = value,
-            SCROLL_X_ADDR => self.scx = value,
-            CURRENT_LINE_ADDR => (), // Read only
-            LINE_CHECK_ADDR => self.lyc = value,
-            WINDOW_Y_ADDR => self.wy = value,
-            WINDOW_X_ADDR => self.wx = value,
-
-            INTERRUPT_FLAG_ADDR => masked_write(&mut self.iflag, value, 0x1F),
-            INTERRUPT_ENABLE_ADDR => masked_write(&mut self.ie, value, 0x1F),
-
-            _ => (),
+            HDMA_SOURCE_HIGH_ADDR => {
+                self.hdma_source = (u16::from(value) << 8) | (self.hdma_source & 0xFF)
+            }
+            HDMA_SOURCE_LOW_ADDR => {
+                self.hdma_source = (self.hdma_source & 0xFF00) | u16::from(value & 0xF0)
+            }
+            HDMA_DEST_HIGH_ADDR => {
+                self.hdma_dest = (u16::from(value & 0x1F) << 8) | (self.hdma_dest & 0xFF)
+            }
+            HDMA_DEST_LOW_ADDR => {
+                self.hdma_dest = (self.hdma_dest & 0xFF00) | u16::from(value & 0xF0)
+            }
+            HDMA_CONTROL_ADDR => self.hdma_request = Some(value),
+            _ => {
+                if addr == LCD_STATUS_ADDR && self.stat_write_triggers_interrupt() {
+                    Interrupt::LCDStatus.set(&mut self.iflag);
+                }
+                if let Some(field) = self.field_mut(addr) {
+                    masked_write(field, value, mask.writable);
+                }
+            }
         }
     }
+
+    /// Whether writing STAT now raises the STAT interrupt
+    ///
+    /// On the original Game Boy, writing STAT briefly enables every STAT
+    /// interrupt source, so the interrupt is raised if the PPU is in HBlank
+    /// or VBlank, or LY matches LYC, whatever value is written.
+    fn stat_write_triggers_interrupt(&self) -> bool {
+        let mode = self.lcdstat & ppu::MODE_MASK;
+        self.stat_write_bug
+            && (self.lcdc & ppu::LCDC_ENABLED) != 0
+            && (mode == ppu::MODE_HBLANK || mode == ppu::MODE_VBLANK || self.ly == self.lyc)
+    }
 }
 
 fn is_mem_register(addr: u16) -> bool {
@@ -228,7 +374,15 @@ pub struct MemoryData {
 
 pub struct Memory {
     data: MemoryData,
+    /// Previous values of locations written while recording history
+    journal: Option<Vec<(u16, u8)>>,
+    /// Block CPU access to VRAM and OAM while the PPU is using them
+    pub(crate) restrict_ppu_access: bool,
+    /// Plain RAM at every address, replacing the memory map, for running CPU test vectors
+    #[cfg(test)]
+    pub(crate) flat_ram: Option<alloc::boxed::Box<[u8]>>,
     pub events: events::EventEmitter<events::MemoryEvent>,
+    pub(crate) io_observers: IoObservers,
 }
 
 impl Memory {
@@ -242,7 +396,12 @@ impl Memory {
                 cartridge,
                 registers: MemoryRegisters::new(),
             },
+            journal: None,
+            restrict_ppu_access: true,
+            #[cfg(test)]
+            flat_ram: None,
             events: events::EventEmitter::new(),
+            io_observers: IoObservers::default(),
         }
     }
 
@@ -250,10 +409,36 @@ impl Memory {
         &self.data.registers
     }
 
+    /// Fill work RAM and high RAM with noise from `rng`
+    pub(crate) fn randomize_ram(&mut self, rng: &mut Rng) {
+        rng.fill_bytes(&mut self.data.sysram);
+        rng.fill_bytes(&mut self.data.cpuram);
+    }
+
     pub fn registers_mut(&mut self) -> &mut MemoryRegisters {
         &mut self.data.registers
     }
 
+    pub(crate) fn cartridge(&self) -> &Cartridge {
+        &self.data.cartridge
+    }
+
+    pub(crate) fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.data.cartridge
+    }
+
+    /// Find which bank is currently mapped at the given address
+    ///
+    /// Addresses outside switchable regions are reported as bank 0
+    pub fn banked_address(&self, address: address::LiteralAddress) -> address::BankedAddress {
+        let cartridge = &self.data.cartridge;
+        let bank = cartridge
+            .rom_bank(address.0)
+            .or_else(|| cartridge.ram_bank(address.0))
+            .unwrap_or(0);
+        address::BankedAddress::new(bank, address)
+    }
+
     pub fn read_u8<A: Into<address::LiteralAddress>>(&self, target: A) -> MemoryResult<u8> {
         let address = target.into();
         let result = self.read_u8_internal(address);
@@ -270,6 +455,12 @@ impl Memory {
         address: address::LiteralAddress,
     ) -> Result<u8, MemoryError> {
         let addr = address.0;
+        #[cfg(test)]
+        {
+            if let Some(ram) = self.flat_ram.as_ref() {
+                return Ok(ram[usize::from(addr)]);
+            }
+        }
         if CARTRIDGE_ROM.contains(addr) || SWITCHABLE_ROM.contains(addr) {
             self.data
                 .cartridge
@@ -309,6 +500,7 @@ impl Memory {
         value: u8,
     ) -> MemoryResult<()> {
         let address = target.into();
+        self.record_write(address);
         let write_result = self.write_u8_internal(address, value);
 
         if write_result.is_ok() {
@@ -317,17 +509,126 @@ impl Memory {
             let new_value = self.read_u8_internal(address).unwrap_or(0xFF);
             self.events
                 .emit(events::MemoryEvent::write(address, value, new_value));
+            self.io_observers.record(address.0, value);
         }
 
         write_result
     }
 
+    /// Whether the PPU's current mode locks the CPU out of an address
+    ///
+    /// VRAM is inaccessible while the PPU is drawing, and OAM is also
+    /// inaccessible while the PPU is scanning it for sprites.
+    fn is_locked_by_ppu(&self, addr: u16) -> bool {
+        let registers = &self.data.registers;
+        if !self.restrict_ppu_access || (registers.lcdc & ppu::LCDC_ENABLED) == 0 {
+            return false;
+        }
+        match registers.lcdstat & ppu::MODE_MASK {
+            ppu::MODE_DRAWING => VRAM.contains(addr) || OAM_RAM.contains(addr),
+            ppu::MODE_OAMSCAN => OAM_RAM.contains(addr),
+            _ => false,
+        }
+    }
+
+    /// Read a value as the CPU would see it
+    ///
+    /// Locations locked by the PPU read as 0xFF
+    pub(crate) fn cpu_read_u8<A: Into<address::LiteralAddress>>(
+        &self,
+        target: A,
+    ) -> MemoryResult<u8> {
+        let address = target.into();
+        if self.is_locked_by_ppu(address.0) {
+            self.events.emit(events::MemoryEvent::read(address, 0xFF));
+            Ok(0xFF)
+        } else {
+            self.read_u8(address)
+        }
+    }
+
+    /// Write a value as the CPU would
+    ///
+    /// Writes to locations locked by the PPU are ignored, and emit a
+    /// `BlockedWrite` event rather than a write
+    pub(crate) fn cpu_write_u8<A: Into<address::LiteralAddress>>(
+        &mut self,
+        target: A,
+        value: u8,
+    ) -> MemoryResult<()> {
+        let address = target.into();
+        if self.is_locked_by_ppu(address.0) {
+            self.events
+                .emit(events::MemoryEvent::blocked_write(address, value));
+            Ok(())
+        } else {
+            self.write_u8(address, value)
+        }
+    }
+
+    /// Start recording the previous value of each written location
+    pub(crate) fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    /// Stop recording writes, returning the previous values in write order
+    pub(crate) fn finish_journal(&mut self) -> Vec<(u16, u8)> {
+        self.journal.take().unwrap_or_default()
+    }
+
+    pub(crate) fn bank_registers(&self) -> BankRegisters {
+        self.data.cartridge.controller.bank_registers()
+    }
+
+    pub(crate) fn restore_bank_registers(&mut self, registers: BankRegisters) {
+        self.data
+            .cartridge
+            .controller
+            .restore_bank_registers(registers)
+    }
+
+    /// Corrupt a row of OAM as the OAM bug does, see the `oam_bug` module
+    pub(crate) fn corrupt_oam_row(&mut self, row: usize, access: oam_bug::Access) {
+        let start = OAM_RAM.start + (row * 8) as u16;
+        for addr in start..start + 8 {
+            self.record_write(addr.into());
+        }
+        oam_bug::corrupt(&mut self.data.oamram, row, access);
+    }
+
+    fn record_write(&mut self, address: address::LiteralAddress) {
+        let addr = address.0;
+        // Registers are restored wholesale, and ROM writes only change the
+        // controller's bank registers, which history saves before each step.
+        // No instruction writes to both ROM and cartridge RAM, so undoing a
+        // step's RAM writes before its bank switches puts them in the right bank.
+        if self.journal.is_none()
+            || is_mem_register(addr)
+            || CARTRIDGE_ROM.contains(addr)
+            || SWITCHABLE_ROM.contains(addr)
+        {
+            return;
+        }
+        if let Ok(old_value) = self.read_u8_internal(address) {
+            if let Some(journal) = self.journal.as_mut() {
+                journal.push((addr, old_value));
+            }
+        }
+    }
+
     pub(crate) fn write_u8_internal(
         &mut self,
         address: address::LiteralAddress,
         value: u8,
     ) -> Result<(), MemoryError> {
         let addr = address.0;
+        #[cfg(test)]
+        {
+            if let Some(ram) = self.flat_ram.as_mut() {
+                ram[usize::from(addr)] = value;
+                return Ok(());
+            }
+        }
         let write_result = if CARTRIDGE_ROM.contains(addr) || SWITCHABLE_ROM.contains(addr) {
             self.data
                 .cartridge
@@ -376,8 +677,8 @@ impl Memory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::shared::{Lock, Shared};
     use alloc::boxed::Box;
-    use alloc::rc::Rc;
     use alloc::vec::Vec;
 
     #[test]
@@ -492,12 +793,12 @@ mod tests {
         memory.write_u8(INTERRUPT_ENABLE_ADDR, 0xFE).unwrap();
 
         assert_eq!(memory.data.registers.iflag, 0x1F);
-        assert_eq!(memory.data.registers.ie, 0x1E);
+        assert_eq!(memory.data.registers.ie, 0xFE);
 
         memory.data.registers.iflag = 0x04;
         memory.data.registers.ie = 0x12;
 
-        assert_eq!(memory.read_u8(INTERRUPT_FLAG_ADDR).unwrap(), 0x04);
+        assert_eq!(memory.read_u8(INTERRUPT_FLAG_ADDR).unwrap(), 0xE4);
         assert_eq!(memory.read_u8(INTERRUPT_ENABLE_ADDR).unwrap(), 0x12);
     }
 
@@ -519,7 +820,7 @@ mod tests {
         assert_eq!(memory.data.registers.lcdc, 0xFF);
         assert_eq!(memory.data.registers.lcdstat, 0x7F);
 
-        assert_eq!(memory.read_u8(LCD_STATUS_ADDR).unwrap(), 0x7F);
+        assert_eq!(memory.read_u8(LCD_STATUS_ADDR).unwrap(), 0xFF);
         assert_eq!(memory.read_u8(LCD_CONTROL_ADDR).unwrap(), 0xFF);
         assert_eq!(memory.read_u8(SCROLL_Y_ADDR).unwrap(), 0xAA);
         assert_eq!(memory.read_u8(SCROLL_X_ADDR).unwrap(), 0x33);
@@ -527,6 +828,31 @@ mod tests {
         assert_eq!(memory.read_u8(WINDOW_X_ADDR).unwrap(), 0xA3);
     }
 
+    #[test]
+    fn test_stat_write_bug() {
+        let cartridge = Cartridge::from_data(vec![0u8; 0x8000]).unwrap();
+        let mut memory = Memory::new(cartridge);
+        memory.data.registers.ly = 10;
+        memory.data.registers.lyc = 20;
+        memory.data.registers.lcdstat = 3;
+
+        memory.write_u8(LCD_STATUS_ADDR, 0).unwrap();
+        assert_eq!(memory.data.registers.iflag, 0);
+
+        memory.data.registers.stat_write_bug = true;
+        memory.write_u8(LCD_STATUS_ADDR, 0).unwrap();
+        assert_eq!(memory.data.registers.iflag, 0);
+
+        memory.data.registers.lcdstat = 0;
+        memory.write_u8(LCD_STATUS_ADDR, 0).unwrap();
+        assert_eq!(memory.data.registers.iflag, 0x02);
+
+        memory.data.registers.iflag = 0;
+        memory.data.registers.lcdc = 0;
+        memory.write_u8(LCD_STATUS_ADDR, 0).unwrap();
+        assert_eq!(memory.data.registers.iflag, 0);
+    }
+
     #[test]
     fn test_unmapped_address() {
         let cartridge = Cartridge::from_data(vec![0u8; 0x8000]).unwrap();
@@ -541,10 +867,37 @@ mod tests {
     }
 
     #[test]
+    fn test_cpu_access_locked_by_ppu() {
+        let cartridge = Cartridge::from_data(vec![0u8; 0x8000]).unwrap();
+        let mut memory = Memory::new(cartridge);
+        memory.write_u8(VRAM.start, 0x12).unwrap();
+        memory.write_u8(OAM_RAM.start, 0x34).unwrap();
+
+        memory.registers_mut().lcdstat = ppu::MODE_OAMSCAN;
+        assert_eq!(memory.cpu_read_u8(VRAM.start), Ok(0x12));
+        assert_eq!(memory.cpu_read_u8(OAM_RAM.start), Ok(0xFF));
+        memory.cpu_write_u8(OAM_RAM.start, 0x56).unwrap();
+        assert_eq!(memory.read_u8(OAM_RAM.start), Ok(0x34));
+
+        memory.registers_mut().lcdstat = ppu::MODE_DRAWING;
+        assert_eq!(memory.cpu_read_u8(VRAM.start), Ok(0xFF));
+        memory.cpu_write_u8(VRAM.start, 0x56).unwrap();
+        assert_eq!(memory.read_u8(VRAM.start), Ok(0x12));
+
+        memory.registers_mut().lcdc &= !ppu::LCDC_ENABLED;
+        assert_eq!(memory.cpu_read_u8(VRAM.start), Ok(0x12));
+
+        memory.registers_mut().lcdc |= ppu::LCDC_ENABLED;
+        memory.restrict_ppu_access = false;
+        memory.cpu_write_u8(VRAM.start, 0x56).unwrap();
+        assert_eq!(memory.cpu_read_u8(VRAM.start), Ok(0x56));
+    }
+
+    #[test]
+    #[cfg(feature = "events")]
     fn test_write_event() {
-        use core::cell::RefCell;
-        let event_log: Rc<RefCell<Vec<events::MemoryEvent>>> = Rc::new(RefCell::new(Vec::new()));
-        let handler_log = Rc::clone(&event_log);
+        let event_log: Shared<Lock<Vec<events::MemoryEvent>>> = Shared::new(Lock::new(Vec::new()));
+        let handler_log = Shared::clone(&event_log);
 
         let handler = move |evt: &events::MemoryEvent| {
             handler_log.borrow_mut().push(*evt);
@@ -565,10 +918,10 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "events")]
     fn test_write_unwriteable() {
-        use core::cell::RefCell;
-        let event_log: Rc<RefCell<Vec<events::MemoryEvent>>> = Rc::new(RefCell::new(Vec::new()));
-        let handler_log = Rc::clone(&event_log);
+        let event_log: Shared<Lock<Vec<events::MemoryEvent>>> = Shared::new(Lock::new(Vec::new()));
+        let handler_log = Shared::clone(&event_log);
 
         let handler = move |evt: &events::MemoryEvent| {
             handler_log.borrow_mut().push(*evt);
@@ -0,0 +1,154 @@
+//! Which bits of each IO register the CPU can read and write
+//!
+//! Unused bits, and registers that can only be written, read as 1. Writes
+//! only change the writable bits. Listing a register here maps it into
+//! memory, so new registers get the right masking without special cases.
+
+use super::*;
+
+/// The bits of an IO register visible to the CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RegisterMask {
+    pub(crate) addr: u16,
+    pub(crate) name: &'static str,
+    /// Bits that always read as 1, because they are unused or write only
+    pub(crate) read_ones: u8,
+    /// Bits that are changed by writes
+    pub(crate) writable: u8,
+    /// Whether the register only exists in Game Boy Color mode
+    pub(crate) color_only: bool,
+}
+
+const fn register(addr: u16, name: &'static str, read_ones: u8, writable: u8) -> RegisterMask {
+    RegisterMask {
+        addr,
+        name,
+        read_ones,
+        writable,
+        color_only: false,
+    }
+}
+
+const fn color_register(
+    addr: u16,
+    name: &'static str,
+    read_ones: u8,
+    writable: u8,
+) -> RegisterMask {
+    RegisterMask {
+        color_only: true,
+        ..register(addr, name, read_ones, writable)
+    }
+}
+
+/// Every IO register which is mapped into memory
+///
+/// Registers with side effects, such as DIV resetting when written, list
+/// no writable bits and are handled in `MemoryRegisters::write`.
+pub(crate) const REGISTER_MASKS: &[RegisterMask] = &[
+    register(JOYPAD_ADDR, "P1", 0xC0, 0x30),
+    register(SERIAL_DATA_ADDR, "SB", 0x00, 0xFF),
+    register(SERIAL_CONTROL_ADDR, "SC", 0x7E, 0x81),
+    register(TIMER_DIVIDER_REGISTER, "DIV", 0x00, 0x00),
+    register(TIMER_COUNTER_REGISTER, "TIMA", 0x00, 0xFF),
+    register(TIMER_MODULO_REGISTER, "TMA", 0x00, 0xFF),
+    register(TIMER_CONTROL_REGISTER, "TAC", 0xF8, 0x07),
+    register(INTERRUPT_FLAG_ADDR, "IF", 0xE0, 0x1F),
+    register(LCD_CONTROL_ADDR, "LCDC", 0x00, 0xFF),
+    // The mode bits are set by the PPU
+    register(LCD_STATUS_ADDR, "STAT", 0x80, 0x7C),
+    register(SCROLL_Y_ADDR, "SCY", 0x00, 0xFF),
+    register(SCROLL_X_ADDR, "SCX", 0x00, 0xFF),
+    register(CURRENT_LINE_ADDR, "LY", 0x00, 0x00),
+    register(LINE_CHECK_ADDR, "LYC", 0x00, 0xFF),
+    register(DMA_REGISTER_ADDR, "DMA", 0x00, 0xFF),
+    register(BG_PALETTE_ADDR, "BGP", 0x00, 0xFF),
+    register(SPRITE_PALETTE_0_ADDR, "OBP0", 0x00, 0xFF),
+    register(SPRITE_PALETTE_1_ADDR, "OBP1", 0x00, 0xFF),
+    register(WINDOW_Y_ADDR, "WY", 0x00, 0xFF),
+    register(WINDOW_X_ADDR, "WX", 0x00, 0xFF),
+    color_register(SPEED_SWITCH_ADDR, "KEY1", 0x7E, SPEED_SWITCH_PREPARE),
+    color_register(HDMA_SOURCE_HIGH_ADDR, "HDMA1", 0xFF, 0x00),
+    color_register(HDMA_SOURCE_LOW_ADDR, "HDMA2", 0xFF, 0x00),
+    color_register(HDMA_DEST_HIGH_ADDR, "HDMA3", 0xFF, 0x00),
+    color_register(HDMA_DEST_LOW_ADDR, "HDMA4", 0xFF, 0x00),
+    color_register(HDMA_CONTROL_ADDR, "HDMA5", 0x00, 0x00),
+    register(INTERRUPT_ENABLE_ADDR, "IE", 0x00, 0xFF),
+];
+
+/// The mask for the register at `addr`, if one is mapped there
+pub(crate) fn lookup(addr: u16, color_mode: bool) -> Option<&'static RegisterMask> {
+    REGISTER_MASKS
+        .iter()
+        .find(|register| register.addr == addr && (color_mode || !register.color_only))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_memory(color_mode: bool) -> Memory {
+        let mut memory = Memory::new(Cartridge::from_data(vec![0u8; 0x8000]).unwrap());
+        memory.registers_mut().color_mode = color_mode;
+        memory
+    }
+
+    #[test]
+    fn test_register_masks() {
+        for register in REGISTER_MASKS {
+            // DIV is reset by any write
+            if register.addr == TIMER_DIVIDER_REGISTER {
+                continue;
+            }
+            let mut memory = make_memory(register.color_only);
+            for value in [0x00, 0xFF, 0xA5, 0x5A].iter().copied() {
+                let before = memory.read_u8(register.addr).unwrap();
+                memory.write_u8(register.addr, value).unwrap();
+                let expected = (value & register.writable)
+                    | (before & !register.writable)
+                    | register.read_ones;
+                assert_eq!(
+                    memory.read_u8(register.addr),
+                    Ok(expected),
+                    "{} after writing {:02X}",
+                    register.name,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_color_registers_unmapped() {
+        let mut memory = make_memory(false);
+        for register in REGISTER_MASKS.iter().filter(|register| register.color_only) {
+            memory.write_u8(register.addr, 0x01).unwrap();
+            assert_eq!(
+                memory.read_u8(register.addr),
+                Err(MemoryError::UnmappedAddress(register.addr)),
+                "{}",
+                register.name
+            );
+        }
+        assert_eq!(memory.registers().key1, 0);
+    }
+
+    #[test]
+    fn test_registers_listed_once() {
+        for (i, register) in REGISTER_MASKS.iter().enumerate() {
+            assert!(
+                REGISTER_MASKS[i + 1..]
+                    .iter()
+                    .all(|other| other.addr != register.addr),
+                "{} is listed twice",
+                register.name
+            );
+            assert_eq!(
+                register.read_ones & register.writable,
+                0,
+                "{}",
+                register.name
+            );
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use super::RegisterSnapshot;
+use crate::registers::WordRegister;
+use alloc::vec::Vec;
+
+/// A single piece of work done by the CPU while running an instruction
+///
+/// Every `Read`, `Write` and `Internal` op takes exactly one machine cycle.
+/// `Register` ops take no time of their own, and follow the op for the cycle
+/// in which the register changed. Register changes after the final cycle of an
+/// instruction, such as the result of `LD B, C`, come at the end of the trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MicroOp {
+    /// Read `value` from `address` over the memory bus
+    Read { address: u16, value: u8 },
+    /// Write `value` to `address` over the memory bus
+    Write { address: u16, value: u8 },
+    /// A machine cycle without any memory access
+    Internal,
+    /// A 16-bit register was set to `value`
+    Register { register: WordRegister, value: u16 },
+}
+
+impl MicroOp {
+    /// Whether this op takes up a machine cycle
+    pub fn takes_cycle(&self) -> bool {
+        !matches!(self, MicroOp::Register { .. })
+    }
+}
+
+/// Builds up the micro-ops of a step as it runs
+pub(crate) struct MicroOpRecorder {
+    ops: Vec<MicroOp>,
+    /// Registers at the end of the last cycle recorded
+    registers: RegisterSnapshot,
+    /// Whether the current cycle has accessed memory yet
+    accessed_memory: bool,
+}
+
+impl MicroOpRecorder {
+    pub(crate) fn new(registers: RegisterSnapshot) -> MicroOpRecorder {
+        MicroOpRecorder {
+            ops: Vec::new(),
+            registers,
+            accessed_memory: false,
+        }
+    }
+
+    pub(crate) fn read(&mut self, address: u16, value: u8) {
+        self.accessed_memory = true;
+        self.ops.push(MicroOp::Read { address, value });
+    }
+
+    pub(crate) fn write(&mut self, address: u16, value: u8) {
+        self.accessed_memory = true;
+        self.ops.push(MicroOp::Write { address, value });
+    }
+
+    /// Finish the current cycle, given the registers at the end of it
+    pub(crate) fn end_cycle(&mut self, registers: RegisterSnapshot) {
+        if !self.accessed_memory {
+            self.ops.push(MicroOp::Internal);
+        }
+        self.accessed_memory = false;
+        self.record_registers(registers);
+    }
+
+    /// Finish the step, given the registers at the end of it
+    pub(crate) fn finish(mut self, registers: RegisterSnapshot) -> Vec<MicroOp> {
+        self.record_registers(registers);
+        self.ops
+    }
+
+    fn record_registers(&mut self, registers: RegisterSnapshot) {
+        let old = self.registers;
+        let changes = [
+            (WordRegister::AF, old.af, registers.af),
+            (WordRegister::BC, old.bc, registers.bc),
+            (WordRegister::DE, old.de, registers.de),
+            (WordRegister::HL, old.hl, registers.hl),
+            (WordRegister::SP, old.sp, registers.sp),
+            (WordRegister::PC, old.pc, registers.pc),
+        ];
+        for (register, old_value, value) in changes.iter().copied() {
+            if old_value != value {
+                self.ops.push(MicroOp::Register { register, value });
+            }
+        }
+        self.registers = registers;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_cycles_and_register_changes() {
+        let start = RegisterSnapshot {
+            pc: 0xC000,
+            ..RegisterSnapshot::default()
+        };
+        let mut recorder = MicroOpRecorder::new(start);
+
+        recorder.read(0xC000, 0x03);
+        recorder.end_cycle(RegisterSnapshot {
+            pc: 0xC001,
+            ..start
+        });
+        recorder.end_cycle(RegisterSnapshot {
+            pc: 0xC001,
+            bc: 1,
+            ..start
+        });
+        let ops = recorder.finish(RegisterSnapshot {
+            pc: 0xC001,
+            bc: 1,
+            ..start
+        });
+
+        assert_eq!(
+            ops,
+            vec![
+                MicroOp::Read {
+                    address: 0xC000,
+                    value: 0x03
+                },
+                MicroOp::Register {
+                    register: WordRegister::PC,
+                    value: 0xC001
+                },
+                MicroOp::Internal,
+                MicroOp::Register {
+                    register: WordRegister::BC,
+                    value: 1
+                },
+            ]
+        );
+        assert_eq!(ops.iter().filter(|op| op.takes_cycle()).count(), 2);
+    }
+}
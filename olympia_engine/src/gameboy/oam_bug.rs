@@ -0,0 +1,93 @@
+//! The OAM corruption bug of the original Game Boy
+//!
+//! While the PPU scans OAM for sprites, it reads one 8 byte row each cycle.
+//! If the CPU puts an address in OAM on the address bus at the same time,
+//! such as by incrementing or decrementing a 16-bit register that points
+//! into 0xFE00-0xFEFF, the row being read is overwritten with a mix of
+//! itself and the row before it. The first row is never corrupted.
+//!
+//! The mix depends on whether the CPU was reading memory at the time, as
+//! `LD A, (HL+)` and `POP` do, or not.
+
+/// Bytes in each row of OAM read by the PPU in one cycle
+const ROW_SIZE: usize = 8;
+/// Rows of OAM, one for each cycle of the OAM scan
+pub(crate) const OAM_ROWS: usize = 20;
+
+fn read_word(oam: &[u8], index: usize) -> u16 {
+    u16::from_le_bytes([oam[index], oam[index + 1]])
+}
+
+fn write_word(oam: &mut [u8], index: usize, value: u16) {
+    oam[index..index + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+/// The kind of CPU access that triggered the bug
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// A write to memory, or an increment or decrement without any access
+    Write,
+    /// A read from memory
+    Read,
+}
+
+/// Corrupt the given row as an access of the given kind does
+pub(crate) fn corrupt(oam: &mut [u8], row: usize, access: Access) {
+    if row == 0 || row >= OAM_ROWS {
+        return;
+    }
+    let current = row * ROW_SIZE;
+    let previous = current - ROW_SIZE;
+    let a = read_word(oam, current);
+    let b = read_word(oam, previous);
+    let c = read_word(oam, previous + 4);
+    let first = match access {
+        Access::Write => ((a ^ c) & (b ^ c)) ^ c,
+        Access::Read => b | (a & c),
+    };
+    write_word(oam, current, first);
+    oam.copy_within(previous + 2..current, current + 2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corrupt_write() {
+        let mut oam = [0u8; 160];
+        oam[8..16].copy_from_slice(&[0x0F, 0xF0, 1, 2, 0x33, 0x55, 5, 6]);
+        oam[16..24].copy_from_slice(&[0xAA, 0x0F, 9, 9, 9, 9, 9, 9]);
+
+        corrupt(&mut oam, 2, Access::Write);
+
+        // ((0x0FAA ^ 0x5533) & (0xF00F ^ 0x5533)) ^ 0x5533
+        assert_eq!(&oam[16..24], &[0x2B, 0x55, 1, 2, 0x33, 0x55, 5, 6]);
+        assert_eq!(&oam[8..16], &[0x0F, 0xF0, 1, 2, 0x33, 0x55, 5, 6]);
+    }
+
+    #[test]
+    fn test_corrupt_read() {
+        let mut oam = [0u8; 160];
+        oam[8..16].copy_from_slice(&[0x0F, 0xF0, 1, 2, 0x33, 0x55, 5, 6]);
+        oam[16..24].copy_from_slice(&[0xAA, 0x0F, 9, 9, 9, 9, 9, 9]);
+
+        corrupt(&mut oam, 2, Access::Read);
+
+        // 0xF00F | (0x0FAA & 0x5533)
+        assert_eq!(&oam[16..24], &[0x2F, 0xF5, 1, 2, 0x33, 0x55, 5, 6]);
+        assert_eq!(&oam[8..16], &[0x0F, 0xF0, 1, 2, 0x33, 0x55, 5, 6]);
+    }
+
+    #[test]
+    fn test_first_row_not_corrupted() {
+        let mut oam = [0x12u8; 160];
+        oam[0] = 0x34;
+
+        corrupt(&mut oam, 0, Access::Write);
+        corrupt(&mut oam, OAM_ROWS, Access::Read);
+
+        assert_eq!(oam[0], 0x34);
+        assert!(oam[1..].iter().all(|byte| *byte == 0x12));
+    }
+}
@@ -0,0 +1,85 @@
+use super::{cpu::CLOCKS_PER_CYCLE, ppu::LINE_CYCLES};
+
+/// Clocks in a single line of the display, at normal speed
+const LINE_CLOCKS: u32 = LINE_CYCLES as u32 * CLOCKS_PER_CYCLE;
+
+/// The most extra CPU cycles that can be run per line, ten times normal speed
+pub const MAX_OVERCLOCK_CYCLES: u16 = LINE_CYCLES * 9;
+
+/// Hands out extra CPU cycles, spread evenly over each line of the display
+///
+/// Extra cycles only run the CPU and DMA. The PPU, timer, serial port and
+/// cartridge clock don't see them, and neither does the count of elapsed
+/// clocks, so video and timer timing stay the same.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Overclock {
+    extra_cycles_per_line: u16,
+    /// Extra cycles owed, multiplied by the clocks in a line
+    credit: u32,
+    /// Extra cycles ready to be run
+    pending_cycles: u32,
+}
+
+impl Overclock {
+    pub(crate) fn extra_cycles_per_line(&self) -> u16 {
+        self.extra_cycles_per_line
+    }
+
+    pub(crate) fn set_extra_cycles_per_line(&mut self, cycles: u16) {
+        self.extra_cycles_per_line = cycles.min(MAX_OVERCLOCK_CYCLES);
+        if self.extra_cycles_per_line == 0 {
+            self.credit = 0;
+            self.pending_cycles = 0;
+        }
+    }
+
+    /// Go back to the cycles owed at an earlier point, keeping the current setting
+    pub(crate) fn rewind_to(&mut self, earlier: Overclock) {
+        self.credit = earlier.credit;
+        self.pending_cycles = earlier.pending_cycles;
+    }
+
+    /// Record that `clocks` clocks have passed for the PPU
+    pub(crate) fn add_clocks(&mut self, clocks: u32) {
+        self.credit += u32::from(self.extra_cycles_per_line) * clocks;
+        self.pending_cycles += self.credit / LINE_CLOCKS;
+        self.credit %= LINE_CLOCKS;
+    }
+
+    /// Use up an extra cycle, if one is ready
+    pub(crate) fn take_cycle(&mut self) -> bool {
+        if self.pending_cycles > 0 {
+            self.pending_cycles -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycles_spread_over_line() {
+        let mut overclock = Overclock::default();
+        overclock.set_extra_cycles_per_line(LINE_CYCLES / 2);
+
+        let mut extra_cycles = 0;
+        for cycle in 1..=LINE_CYCLES {
+            overclock.add_clocks(CLOCKS_PER_CYCLE);
+            while overclock.take_cycle() {
+                extra_cycles += 1;
+            }
+            assert_eq!(extra_cycles, cycle / 2);
+        }
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut overclock = Overclock::default();
+        overclock.set_extra_cycles_per_line(u16::MAX);
+        assert_eq!(overclock.extra_cycles_per_line(), MAX_OVERCLOCK_CYCLES);
+    }
+}
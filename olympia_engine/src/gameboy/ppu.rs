@@ -1,14 +1,15 @@
-use std::cmp::Ordering;
+use core::cmp::Ordering;
 
 use alloc::collections::VecDeque;
 use alloc::vec::Vec;
 
 use crate::{
-    events::{EventEmitter, HBlankEvent, PPUEvent, VBlankEvent},
+    events::{EventEmitter, HBlankEvent, LcdPowerEvent, PPUEvent, VBlankEvent},
     gameboy::{
         cpu::Interrupt,
         memory::{Memory, OAM_RAM},
     },
+    logging,
 };
 
 use log::trace;
@@ -17,13 +18,18 @@ const VISIBLE_WIDTH: u8 = 160;
 const VISIBLE_LINES: u8 = 144;
 const TOTAL_LINES: u8 = 154;
 const OAM_SCAN_CYCLES: u16 = 20;
-const LINE_CYCLES: u16 = 114;
-
-const MODE_MASK: u8 = 3;
-const MODE_HBLANK: u8 = 0b00;
-const MODE_VBLANK: u8 = 0b01;
-const MODE_OAMSCAN: u8 = 0b10;
-const MODE_DRAWING: u8 = 0b11;
+pub(crate) const LINE_CYCLES: u16 = 114;
+/// Clocks the first line after the LCD is switched on starts at, as
+/// that line is slightly shorter than usual
+const FIRST_LINE_START_CLOCKS: u16 = 4;
+/// Clocks drawing is paused for when the window starts on a line
+const WINDOW_START_PENALTY: u16 = 6;
+
+pub(crate) const MODE_MASK: u8 = 3;
+pub(crate) const MODE_HBLANK: u8 = 0b00;
+pub(crate) const MODE_VBLANK: u8 = 0b01;
+pub(crate) const MODE_OAMSCAN: u8 = 0b10;
+pub(crate) const MODE_DRAWING: u8 = 0b11;
 
 const LCDSTAT_MATCH_ON_EQUAL: u8 = 1 << 2;
 const LCDSTAT_HBLANK_INTERRUPT: u8 = 1 << 3;
@@ -37,14 +43,14 @@ const LCDC_HIGH_BG_MAP: u8 = 1 << 3;
 const LCDC_LOW_BG_TILES: u8 = 1 << 4;
 const LCDC_WINDOW_ENABLED: u8 = 1 << 5;
 const LCDC_HIGH_WINDOW_MAP: u8 = 1 << 6;
-const LCDC_ENABLED: u8 = 1 << 7;
+pub(crate) const LCDC_ENABLED: u8 = 1 << 7;
 
 const MEM_LOW_TILES: u16 = 0x8000;
 const MEM_HIGH_TILES: u16 = 0x8800;
 const MEM_LOW_MAP: u16 = 0x9800;
 const MEM_HIGH_MAP: u16 = 0x9C00;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub(crate) enum PPUPhase {
     ObjectScan,
     Drawing,
@@ -53,6 +59,7 @@ pub(crate) enum PPUPhase {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Palette {
     Background,
     Window,
@@ -60,6 +67,46 @@ pub enum Palette {
     Sprite1,
 }
 
+/// How many clocks the PPU spent in each mode on a visible line
+///
+/// A line always takes 456 clocks. Drawing takes at least 160 clocks, and
+/// is extended by fine horizontal scrolling and by the window starting part
+/// way through the line, which shortens HBlank by the same amount.
+/// Modes change every 2 clocks, so lengths are rounded up to an even number.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModeLengths {
+    /// The line these lengths were measured on
+    pub line: u8,
+    pub oam_scan: u16,
+    pub drawing: u16,
+    pub hblank: u16,
+}
+
+/// Which layers are drawn into the framebuffer, for finding which layer
+/// a graphical glitch comes from
+///
+/// Hidden layers are still fetched, so disabling them doesn't affect timing.
+/// Where the background or window is hidden, colour 0 of the background
+/// palette is drawn instead.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RenderLayers {
+    pub background: bool,
+    pub window: bool,
+    pub sprites: bool,
+}
+
+impl Default for RenderLayers {
+    fn default() -> Self {
+        RenderLayers {
+            background: true,
+            window: true,
+            sprites: true,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum SpriteMode {
     Square,
@@ -82,14 +129,36 @@ impl Default for Palette {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GBPixel {
     pub palette: Palette,
+    /// Colour number within the tile, from 0-3
     pub index: u8,
+    /// Shade to display, from 0 (white) to 3 (black)
+    ///
+    /// This is the colour number mapped through the palette register
+    /// as it was when the pixel was drawn, so palette changes part way
+    /// through a line only affect the pixels drawn after them.
+    pub color: u8,
 }
 
 impl GBPixel {
+    /// Create a pixel displayed with the same shade as its colour number
     pub fn new(palette: Palette, index: u8) -> GBPixel {
-        GBPixel { palette, index }
+        GBPixel {
+            palette,
+            index,
+            color: index,
+        }
+    }
+
+    /// Create a pixel with its shade looked up from a palette register
+    pub fn with_palette_register(palette: Palette, index: u8, register: u8) -> GBPixel {
+        GBPixel {
+            palette,
+            index,
+            color: (register >> (index * 2)) & 0b11,
+        }
     }
 }
 
@@ -117,6 +186,28 @@ impl Sprite {
     }
 }
 
+/// How far the PPU is through the frame, saved so a step can be undone
+///
+/// Pixels already drawn into the framebuffer aren't included.
+#[derive(Clone)]
+pub(crate) struct PpuTiming {
+    phase: PPUPhase,
+    current_line: u8,
+    clocks_on_line: u16,
+    current_pixel: u8,
+    pixel_queue: VecDeque<GBPixel>,
+    line_sprites: Vec<Sprite>,
+    stall_clocks: u16,
+    window_started: bool,
+    line_start: u16,
+    drawing_start: u16,
+    hblank_start: u16,
+    mode_lengths: ModeLengths,
+    lcd_on: bool,
+    frames: u64,
+    run_hash: u64,
+}
+
 pub(crate) struct Ppu {
     framebuffer: [GBPixel; (VISIBLE_LINES as usize) * (VISIBLE_WIDTH as usize)],
     pixel_queue: VecDeque<GBPixel>,
@@ -125,9 +216,37 @@ pub(crate) struct Ppu {
     clocks_on_line: u16,
     current_pixel: u8,
     line_sprites: Vec<Sprite>,
+    /// Clocks to wait before drawing the next pixel
+    stall_clocks: u16,
+    /// Whether the window has been reached on the current line
+    window_started: bool,
+    /// Clocks into the current line that it, drawing and HBlank started at
+    line_start: u16,
+    drawing_start: u16,
+    hblank_start: u16,
+    /// Mode lengths of the most recently completed visible line
+    mode_lengths: ModeLengths,
+    /// Whether the LCD was on at the last cycle, to detect LCDC changes
+    lcd_on: bool,
+    /// Number of times VBlank has started
+    frames: u64,
+    /// Layers drawn into the framebuffer
+    layers: RenderLayers,
+    /// Hash of every frame completed so far
+    run_hash: u64,
     pub(crate) events: EventEmitter<PPUEvent>,
 }
 
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// Continue an FNV-1a hash with `bytes`
+fn fnv1a(hash: u64, bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
 impl Ppu {
     fn new() -> Ppu {
         Ppu {
@@ -138,13 +257,34 @@ impl Ppu {
             clocks_on_line: 0,
             current_pixel: 0,
             line_sprites: Vec::with_capacity(10),
+            stall_clocks: 0,
+            window_started: false,
+            line_start: 0,
+            drawing_start: 0,
+            hblank_start: 0,
+            mode_lengths: ModeLengths::default(),
+            lcd_on: true,
+            frames: 0,
+            layers: RenderLayers::default(),
+            run_hash: FNV_OFFSET_BASIS,
             events: EventEmitter::new(),
         }
     }
 
     pub(crate) fn run_cycle(&mut self, mem: &mut Memory) {
-        if self.is_enabled(mem) {
-            for i in 0..4 {
+        let enabled = self.is_enabled(mem);
+        if enabled != self.lcd_on {
+            if enabled {
+                self.lcd_switched_on(mem);
+            } else {
+                self.lcd_switched_off(mem);
+            }
+        }
+        if enabled {
+            // The PPU runs at the same speed in double speed mode, so it
+            // only gets half as many clocks per CPU cycle
+            let clocks = if mem.registers().double_speed() { 2 } else { 4 };
+            for i in 0..clocks {
                 if self.phase == PPUPhase::Drawing {
                     self.draw(mem);
                 }
@@ -155,6 +295,38 @@ impl Ppu {
         }
     }
 
+    /// Stop the PPU, resetting LY and the STAT mode to 0
+    fn lcd_switched_off(&mut self, mem: &mut Memory) {
+        trace!(target: logging::PPU, "LCD off");
+        self.lcd_on = false;
+        self.phase = PPUPhase::ObjectScan;
+        self.current_line = 0;
+        self.clocks_on_line = 0;
+        self.current_pixel = 0;
+        self.pixel_queue.clear();
+        mem.registers_mut().ly = 0;
+        mem.registers_mut().lcdstat &= !MODE_MASK;
+        self.events.emit(LcdPowerEvent::new(false).into());
+    }
+
+    /// Restart the PPU from the top of the screen
+    ///
+    /// The first line is shorter than usual, and reports mode 0 instead
+    /// of mode 2 while scanning OAM.
+    fn lcd_switched_on(&mut self, mem: &mut Memory) {
+        trace!(target: logging::PPU, "LCD on");
+        self.lcd_on = true;
+        self.phase = PPUPhase::ObjectScan;
+        self.current_line = 0;
+        self.clocks_on_line = FIRST_LINE_START_CLOCKS;
+        self.line_start = FIRST_LINE_START_CLOCKS;
+        self.current_pixel = 0;
+        self.oam_scan(mem);
+        mem.registers_mut().ly = 0;
+        mem.registers_mut().lcdstat = (mem.registers().lcdstat & !MODE_MASK) | MODE_HBLANK;
+        self.events.emit(LcdPowerEvent::new(true).into());
+    }
+
     fn sprite_mode(&self, mem: &Memory) -> SpriteMode {
         if (mem.registers().lcdc & 0b100) != 0 {
             SpriteMode::DoubleHeight
@@ -185,8 +357,9 @@ impl Ppu {
                 }
                 .into(),
             );
-            trace!(target: "ppu", "HBlank");
+            trace!(target: logging::PPU, "HBlank");
             self.phase = PPUPhase::HBlank;
+            self.hblank_start = self.clocks_on_line;
             mem.registers_mut().lcdstat = (mem.registers().lcdstat & !MODE_MASK) | MODE_HBLANK;
             if (mem.registers().lcdstat & LCDSTAT_HBLANK_INTERRUPT) != 0 {
                 Interrupt::LCDStatus.set(&mut mem.registers_mut().iflag);
@@ -195,8 +368,12 @@ impl Ppu {
             && self.current_line < VISIBLE_LINES
             && self.phase != PPUPhase::Drawing
         {
-            trace!(target: "ppu", "Begin Drawing");
+            trace!(target: logging::PPU, "Begin Drawing");
             self.phase = PPUPhase::Drawing;
+            self.drawing_start = self.clocks_on_line;
+            // Pixels scrolled off the left of the first tile are fetched and thrown away
+            self.stall_clocks = u16::from(mem.registers().scx % 8);
+            self.window_started = false;
             mem.registers_mut().lcdstat = (mem.registers().lcdstat & !MODE_MASK) | MODE_DRAWING;
         }
     }
@@ -216,11 +393,20 @@ impl Ppu {
     }
 
     fn end_of_line(&mut self, mem: &mut Memory) {
+        if self.current_line < VISIBLE_LINES {
+            self.mode_lengths = ModeLengths {
+                line: self.current_line,
+                oam_scan: self.drawing_start - self.line_start,
+                drawing: self.hblank_start - self.drawing_start,
+                hblank: self.clocks_on_line - self.hblank_start,
+            };
+        }
         self.clocks_on_line = 0;
+        self.line_start = 0;
         self.current_pixel = 0;
         self.current_line += 1;
         if self.current_line == TOTAL_LINES {
-            trace!(target: "ppu", "Frame end");
+            trace!(target: logging::PPU, "Frame end");
             self.current_line = 0;
         }
         if self.should_trigger_line_interrupt(
@@ -228,13 +414,15 @@ impl Ppu {
             mem.registers().lyc,
             self.current_line,
         ) {
-            trace!(target: "ppu", "LYC Interrupt");
+            trace!(target: logging::PPU, "LYC Interrupt");
             Interrupt::LCDStatus.set(&mut mem.registers_mut().iflag);
         }
         match self.current_line.cmp(&VISIBLE_LINES) {
             Ordering::Equal => {
                 self.events.emit(VBlankEvent.into());
-                trace!(target: "ppu", "VBLANK Start");
+                trace!(target: logging::PPU, "VBLANK Start");
+                self.frames += 1;
+                self.run_hash = fnv1a(self.run_hash, self.frame_hash().to_le_bytes());
                 self.phase = PPUPhase::VBlank;
                 mem.registers_mut().lcdstat = (mem.registers().lcdstat & !MODE_MASK) | MODE_VBLANK;
                 Interrupt::VBlank.set(&mut mem.registers_mut().iflag);
@@ -243,7 +431,7 @@ impl Ppu {
                 }
             }
             Ordering::Less => {
-                trace!(target: "ppu", "Object Scan");
+                trace!(target: logging::PPU, "Object Scan");
                 self.phase = PPUPhase::ObjectScan;
                 mem.registers_mut().lcdstat = (mem.registers().lcdstat & !MODE_MASK) | MODE_OAMSCAN;
                 if (mem.registers().lcdstat & LCDSTAT_OAM_SCAN_INTERRUPT) != 0 {
@@ -273,12 +461,22 @@ impl Ppu {
         if self.current_pixel >= VISIBLE_WIDTH {
             return;
         }
-        let actual_x = mem.registers().scx + self.current_pixel;
-        let actual_y = mem.registers().scy + self.current_line;
+        if !self.window_started && self.in_window(mem) {
+            // Fetching restarts from the window's tile map
+            self.window_started = true;
+            self.stall_clocks += WINDOW_START_PENALTY;
+        }
+        if self.stall_clocks > 0 {
+            self.stall_clocks -= 1;
+            return;
+        }
+        let actual_x = mem.registers().scx.wrapping_add(self.current_pixel);
+        let actual_y = mem.registers().scy.wrapping_add(self.current_line);
 
         let pixel = self.calculate_pixel(mem, actual_x, actual_y);
         self.pixel_queue.push_back(pixel);
-        let fb_index = usize::from(actual_x) + (usize::from(actual_y) * usize::from(VISIBLE_WIDTH));
+        let fb_index = usize::from(self.current_pixel)
+            + (usize::from(self.current_line) * usize::from(VISIBLE_WIDTH));
         self.framebuffer[fb_index] = pixel;
 
         self.current_pixel += 1;
@@ -311,20 +509,24 @@ impl Ppu {
                     return None;
                 }
 
-                let palette = if (sprite.flags & 0x10) == 0 {
-                    Palette::Sprite0
+                let (palette, register) = if (sprite.flags & 0x10) == 0 {
+                    (Palette::Sprite0, mem.registers().obp0)
                 } else {
-                    Palette::Sprite1
+                    (Palette::Sprite1, mem.registers().obp1)
                 };
 
-                return Some(GBPixel::new(palette, palette_index));
+                return Some(GBPixel::with_palette_register(
+                    palette,
+                    palette_index,
+                    register,
+                ));
             }
         }
         None
     }
 
     fn calculate_pixel(&mut self, mem: &Memory, x: u8, y: u8) -> GBPixel {
-        if self.sprites_enabled(mem) {
+        if self.layers.sprites && self.sprites_enabled(mem) {
             if let Some(px) = self.calculate_sprite_pixel(mem, x, y) {
                 return px;
             }
@@ -333,9 +535,11 @@ impl Ppu {
         let tile_x = x / 8;
         let tile_y = y / 8;
 
-        let is_window = (self.current_pixel >= mem.registers().wx)
-            && (self.current_line >= mem.registers().wy)
-            && self.window_enabled(mem);
+        // A hidden window shows the background behind it
+        let is_window = self.layers.window && self.in_window(mem);
+        if !is_window && !self.layers.background {
+            return GBPixel::with_palette_register(Palette::Background, 0, mem.registers().bgp);
+        }
 
         let map_offset = if is_window {
             self.window_map_offset(mem)
@@ -357,7 +561,13 @@ impl Ppu {
         } else {
             Palette::Background
         };
-        GBPixel::new(palette, palette_index)
+        GBPixel::with_palette_register(palette, palette_index, mem.registers().bgp)
+    }
+
+    fn in_window(&self, mem: &Memory) -> bool {
+        (self.current_pixel >= mem.registers().wx)
+            && (self.current_line >= mem.registers().wy)
+            && self.window_enabled(mem)
     }
 
     fn sprites_enabled(&self, mem: &Memory) -> bool {
@@ -400,7 +610,99 @@ impl Ppu {
         }
     }
 
-    fn is_enabled(&self, mem: &Memory) -> bool {
+    pub(crate) fn render_layers(&self) -> RenderLayers {
+        self.layers
+    }
+
+    pub(crate) fn set_render_layers(&mut self, layers: RenderLayers) {
+        self.layers = layers;
+    }
+
+    pub(crate) fn mode_lengths(&self) -> ModeLengths {
+        self.mode_lengths
+    }
+
+    /// Pixels drawn so far, row by row from the top left of the screen
+    pub(crate) fn framebuffer(&self) -> &[GBPixel] {
+        &self.framebuffer
+    }
+
+    /// Hash of the shade of every pixel in the framebuffer
+    pub(crate) fn frame_hash(&self) -> u64 {
+        fnv1a(
+            FNV_OFFSET_BASIS,
+            self.framebuffer.iter().map(|pixel| pixel.color),
+        )
+    }
+
+    /// Hash of the frame hashes of every frame completed so far
+    pub(crate) fn run_hash(&self) -> u64 {
+        self.run_hash
+    }
+
+    /// Number of times VBlank has started
+    pub(crate) fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    pub(crate) fn timing(&self) -> PpuTiming {
+        PpuTiming {
+            phase: self.phase,
+            current_line: self.current_line,
+            clocks_on_line: self.clocks_on_line,
+            current_pixel: self.current_pixel,
+            pixel_queue: self.pixel_queue.clone(),
+            line_sprites: self.line_sprites.clone(),
+            stall_clocks: self.stall_clocks,
+            window_started: self.window_started,
+            line_start: self.line_start,
+            drawing_start: self.drawing_start,
+            hblank_start: self.hblank_start,
+            mode_lengths: self.mode_lengths,
+            lcd_on: self.lcd_on,
+            frames: self.frames,
+            run_hash: self.run_hash,
+        }
+    }
+
+    pub(crate) fn restore_timing(&mut self, timing: PpuTiming) {
+        self.phase = timing.phase;
+        self.current_line = timing.current_line;
+        self.clocks_on_line = timing.clocks_on_line;
+        self.current_pixel = timing.current_pixel;
+        self.pixel_queue = timing.pixel_queue;
+        self.line_sprites = timing.line_sprites;
+        self.stall_clocks = timing.stall_clocks;
+        self.window_started = timing.window_started;
+        self.line_start = timing.line_start;
+        self.drawing_start = timing.drawing_start;
+        self.hblank_start = timing.hblank_start;
+        self.mode_lengths = timing.mode_lengths;
+        self.lcd_on = timing.lcd_on;
+        self.frames = timing.frames;
+        self.run_hash = timing.run_hash;
+    }
+
+    /// The row of OAM being read while scanning for sprites, if any
+    ///
+    /// Each of the 20 rows of 8 bytes is read in one cycle of the scan.
+    pub(crate) fn oam_scan_row(&self, mem: &Memory) -> Option<usize> {
+        if !self.is_enabled(mem) || (mem.registers().lcdstat & MODE_MASK) != MODE_OAMSCAN {
+            return None;
+        }
+        let row = usize::from(self.clocks_on_line / 4);
+        Some(row).filter(|row| *row < usize::from(OAM_SCAN_CYCLES))
+    }
+
+    pub(crate) fn in_vblank(&self) -> bool {
+        self.phase == PPUPhase::VBlank
+    }
+
+    pub(crate) fn in_hblank(&self) -> bool {
+        self.phase == PPUPhase::HBlank
+    }
+
+    pub(crate) fn is_enabled(&self, mem: &Memory) -> bool {
         (mem.registers().lcdc & LCDC_ENABLED) != 0
     }
 }
@@ -414,15 +716,20 @@ impl Default for Ppu {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::gameboy::memory::BG_PALETTE_ADDR;
     use crate::rom::Cartridge;
+    use crate::shared::{Lock, Shared};
     use alloc::boxed::Box;
-    use alloc::rc::Rc;
     use alloc::vec::Vec;
-    use core::cell::RefCell;
+
+    /// Palette register which shows each colour number as the same shade
+    const IDENTITY_PALETTE: u8 = 0b1110_0100;
 
     fn create_memory() -> Memory {
         let cart = Cartridge::from_data(vec![0; 0x1000]).unwrap();
-        Memory::new(cart)
+        let mut memory = Memory::new(cart);
+        memory.registers_mut().bgp = IDENTITY_PALETTE;
+        memory
     }
 
     fn gameboy_graphics(pixels: [u8; 8]) -> [u8; 2] {
@@ -561,6 +868,7 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "events")]
     fn hblank_event() {
         let mut ppu = Ppu::new();
         let mut memory = create_memory();
@@ -569,7 +877,7 @@ mod test {
             GBPixel::new(Palette::Background, 1),
             GBPixel::new(Palette::Background, 2),
         ];
-        let recieved_events = Rc::new(RefCell::new(Vec::new()));
+        let recieved_events = Shared::new(Lock::new(Vec::new()));
         let recvd_events_clone = recieved_events.clone();
         ppu.events.on(Box::new(move |evt| {
             recvd_events_clone.borrow_mut().push(evt.clone())
@@ -647,10 +955,11 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "events")]
     fn vblank_event() {
         let mut ppu = Ppu::new();
         let mut memory = create_memory();
-        let recieved_events = Rc::new(RefCell::new(Vec::new()));
+        let recieved_events = Shared::new(Lock::new(Vec::new()));
         let recvd_events_clone = recieved_events.clone();
         ppu.events.on(Box::new(move |evt| {
             recvd_events_clone.borrow_mut().push(evt.clone())
@@ -672,6 +981,70 @@ mod test {
         assert_eq!(*events, vec![PPUEvent::VBlank(VBlankEvent)]);
     }
 
+    #[test]
+    #[cfg(feature = "events")]
+    fn lcd_off_resets_ppu() {
+        let mut ppu = Ppu::new();
+        let mut memory = create_memory();
+        let recieved_events = Shared::new(Lock::new(Vec::new()));
+        let recvd_events_clone = recieved_events.clone();
+        ppu.events.on(Box::new(move |evt| {
+            recvd_events_clone.borrow_mut().push(evt.clone())
+        }));
+        ppu.phase = PPUPhase::Drawing;
+        ppu.current_line = 100;
+        ppu.clocks_on_line = 200;
+        ppu.current_pixel = 50;
+        memory.registers_mut().ly = 100;
+        memory.registers_mut().lcdstat = MODE_DRAWING | LCDSTAT_HBLANK_INTERRUPT;
+        memory.registers_mut().lcdc &= !LCDC_ENABLED;
+
+        ppu.run_cycle(&mut memory);
+        ppu.run_cycle(&mut memory);
+
+        assert_eq!(memory.registers().ly, 0);
+        assert_eq!(
+            memory.registers().lcdstat,
+            MODE_HBLANK | LCDSTAT_HBLANK_INTERRUPT
+        );
+        assert_eq!(ppu.current_line, 0);
+        assert_eq!(ppu.clocks_on_line, 0);
+        assert_eq!(
+            *recieved_events.borrow(),
+            vec![PPUEvent::LcdPower(LcdPowerEvent::new(false))]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "events")]
+    fn lcd_on_shortens_first_line() {
+        let mut ppu = Ppu::new();
+        let mut memory = create_memory();
+        memory.registers_mut().lcdc &= !LCDC_ENABLED;
+        ppu.run_cycle(&mut memory);
+
+        let recieved_events = Shared::new(Lock::new(Vec::new()));
+        let recvd_events_clone = recieved_events.clone();
+        ppu.events.on(Box::new(move |evt| {
+            recvd_events_clone.borrow_mut().push(evt.clone())
+        }));
+        memory.registers_mut().lcdc |= LCDC_ENABLED;
+        ppu.run_cycle(&mut memory);
+        assert_eq!(memory.registers().lcdstat & MODE_MASK, MODE_HBLANK);
+        assert_eq!(
+            *recieved_events.borrow(),
+            vec![PPUEvent::LcdPower(LcdPowerEvent::new(true))]
+        );
+
+        let mut cycles = 1;
+        while memory.registers().ly == 0 {
+            ppu.run_cycle(&mut memory);
+            cycles += 1;
+        }
+        assert_eq!(cycles, LINE_CYCLES - (FIRST_LINE_START_CLOCKS / 4));
+        assert_eq!(memory.registers().lcdstat & MODE_MASK, MODE_OAMSCAN);
+    }
+
     #[test]
     fn scan_end_update_phase() {
         let mut ppu = Ppu::new();
@@ -723,6 +1096,127 @@ mod test {
         assert_eq!(expected_pixels, Vec::from(&ppu.framebuffer[0..8]));
     }
 
+    #[test]
+    fn draw_phase_palette_change_mid_line() {
+        let mut ppu = Ppu::new();
+        let mut memory = create_memory();
+
+        memory.registers_mut().lcdc = LCDC_ENABLED;
+
+        let [lower, upper] = gameboy_graphics([3, 2, 1, 0, 3, 2, 1, 0]);
+        memory.write_u8(MEM_HIGH_TILES + 0x10, lower).unwrap();
+        memory.write_u8(MEM_HIGH_TILES + 0x11, upper).unwrap();
+        memory.write_u8(MEM_LOW_MAP, 1).unwrap();
+
+        for _ in 0..4 {
+            ppu.draw(&memory);
+        }
+        memory.write_u8(BG_PALETTE_ADDR, 0b0001_1011).unwrap();
+        for _ in 0..4 {
+            ppu.draw(&memory);
+        }
+
+        let colors: Vec<u8> = ppu.framebuffer[0..8].iter().map(|px| px.color).collect();
+        let indexes: Vec<u8> = ppu.framebuffer[0..8].iter().map(|px| px.index).collect();
+        assert_eq!(colors, vec![3, 2, 1, 0, 0, 1, 2, 3]);
+        assert_eq!(indexes, vec![3, 2, 1, 0, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn render_layers_hide_pixels() {
+        let mut ppu = Ppu::new();
+        let mut memory = create_memory();
+        memory.registers_mut().lcdc =
+            LCDC_ENABLED | LCDC_SPRITE_ENABLE | LCDC_WINDOW_ENABLED | LCDC_HIGH_WINDOW_MAP;
+        memory.registers_mut().obp0 = IDENTITY_PALETTE;
+        // Background is colour 3, window colour 1 and sprites colour 2
+        for (tile_base, color) in [(MEM_HIGH_TILES + 0x10, 3), (MEM_HIGH_TILES + 0x20, 1)] {
+            let [lower, upper] = gameboy_graphics([color; 8]);
+            memory.write_u8(tile_base, lower).unwrap();
+            memory.write_u8(tile_base + 1, upper).unwrap();
+        }
+        let [lower, upper] = gameboy_graphics([2; 8]);
+        memory.write_u8(MEM_LOW_TILES + 0x30, lower).unwrap();
+        memory.write_u8(MEM_LOW_TILES + 0x31, upper).unwrap();
+        memory.write_u8(MEM_LOW_MAP, 1).unwrap();
+        memory.write_u8(MEM_HIGH_MAP, 2).unwrap();
+        ppu.line_sprites.push(Sprite {
+            y: 0,
+            x: 0,
+            tile: 3,
+            flags: 0,
+        });
+
+        let mut pixel_with = |layers| {
+            ppu.set_render_layers(layers);
+            ppu.calculate_pixel(&memory, 0, 0)
+        };
+        let all = RenderLayers::default();
+        let no_sprites = RenderLayers {
+            sprites: false,
+            ..all
+        };
+        let no_window = RenderLayers {
+            window: false,
+            ..no_sprites
+        };
+        let no_background = RenderLayers {
+            background: false,
+            ..no_window
+        };
+
+        assert_eq!(pixel_with(all), GBPixel::new(Palette::Sprite0, 2));
+        assert_eq!(pixel_with(no_sprites), GBPixel::new(Palette::Window, 1));
+        assert_eq!(pixel_with(no_window), GBPixel::new(Palette::Background, 3));
+        assert_eq!(
+            pixel_with(no_background),
+            GBPixel::new(Palette::Background, 0)
+        );
+    }
+
+    #[test]
+    fn draw_phase_fine_scroll_penalty() {
+        let mut ppu = Ppu::new();
+        let mut memory = create_memory();
+        memory.registers_mut().lcdc = LCDC_ENABLED;
+        memory.registers_mut().scx = 11;
+        ppu.phase = PPUPhase::ObjectScan;
+        ppu.clocks_on_line = (OAM_SCAN_CYCLES * 4) - 2;
+        ppu.update_phase(&mut memory);
+
+        for _ in 0..3 {
+            ppu.draw(&memory);
+        }
+        assert_eq!(ppu.current_pixel, 0);
+        ppu.draw(&memory);
+        assert_eq!(ppu.current_pixel, 1);
+    }
+
+    #[test]
+    fn mode_lengths() {
+        let mut ppu = Ppu::new();
+        let mut memory = create_memory();
+        memory.registers_mut().lcdc = LCDC_ENABLED | LCDC_WINDOW_ENABLED;
+        memory.registers_mut().scx = 6;
+        memory.registers_mut().wx = 80;
+        ppu.current_line = 10;
+        ppu.clocks_on_line = 2;
+
+        while ppu.current_line == 10 {
+            ppu.run_cycle(&mut memory);
+        }
+
+        assert_eq!(
+            ppu.mode_lengths(),
+            ModeLengths {
+                line: 10,
+                oam_scan: 80,
+                drawing: 160 + 6 + WINDOW_START_PENALTY,
+                hblank: 456 - 80 - 160 - 6 - WINDOW_START_PENALTY,
+            }
+        );
+    }
+
     #[test]
     fn draw_phase_bg_low_tiles_no_window() {
         let mut ppu = Ppu::new();
@@ -783,7 +1277,8 @@ mod test {
             GBPixel::new(Palette::Window, 2),
         ];
 
-        for _ in 0..8 {
+        // Drawing pauses when the window starts
+        for _ in 0..(8 + WINDOW_START_PENALTY) {
             ppu.draw(&memory);
         }
 
@@ -822,7 +1317,8 @@ mod test {
             GBPixel::new(Palette::Window, 2),
         ];
 
-        for _ in 0..8 {
+        // Drawing pauses when the window starts
+        for _ in 0..(8 + WINDOW_START_PENALTY) {
             ppu.draw(&memory);
         }
 
@@ -832,4 +1328,29 @@ mod test {
         );
         assert_eq!(expected_pixels, Vec::from(&ppu.framebuffer[0..8]));
     }
+
+    #[test]
+    fn frame_and_run_hashes() {
+        let mut ppu = Ppu::new();
+        let mut memory = create_memory();
+        let blank_hash = ppu.frame_hash();
+        let initial_run_hash = ppu.run_hash();
+
+        ppu.framebuffer[0] = GBPixel::new(Palette::Background, 3);
+        assert_ne!(ppu.frame_hash(), blank_hash);
+        // Only the displayed shade matters, not which layer drew it
+        let mut other = Ppu::new();
+        other.framebuffer[0] = GBPixel::new(Palette::Sprite0, 3);
+        assert_eq!(ppu.frame_hash(), other.frame_hash());
+
+        ppu.phase = PPUPhase::HBlank;
+        ppu.current_line = VISIBLE_LINES - 1;
+        ppu.clocks_on_line = (LINE_CYCLES * 4) - 1;
+        ppu.current_pixel = VISIBLE_WIDTH;
+        ppu.update_phase(&mut memory);
+        assert_eq!(
+            ppu.run_hash(),
+            fnv1a(initial_run_hash, ppu.frame_hash().to_le_bytes())
+        );
+    }
 }
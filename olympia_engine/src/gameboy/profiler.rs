@@ -0,0 +1,210 @@
+use core::cmp::Reverse;
+use core::fmt;
+use core::time::Duration;
+
+/// Host time spent emulating each part of the gameboy
+///
+/// Time is only recorded when the `profiling` feature is enabled, and only
+/// while running `GameBoy::step`. Time which isn't spent in another component
+/// is counted as CPU time, so `cpu` includes decoding and debugger hooks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CycleProfile {
+    /// Decoding and running instructions
+    pub cpu: Duration,
+    /// Drawing and the PPU state machine
+    pub ppu: Duration,
+    /// DIV and TIMA, along with counting elapsed clocks
+    pub timer: Duration,
+    /// OAM DMA and Game Boy Color HDMA transfers
+    pub dma: Duration,
+    /// Serial transfers
+    pub serial: Duration,
+    /// Cartridge peripherals, such as the real time clock
+    pub cartridge: Duration,
+    /// Steps run while profiling
+    pub steps: u64,
+    /// Machine cycles run while profiling
+    pub cycles: u64,
+}
+
+impl CycleProfile {
+    /// Total time spent emulating
+    pub fn total(&self) -> Duration {
+        self.cpu + self.ppu + self.timer + self.dma + self.serial + self.cartridge
+    }
+
+    /// Each component and the time spent in it, largest first
+    pub fn components(&self) -> [(&'static str, Duration); 6] {
+        let mut components = [
+            ("cpu", self.cpu),
+            ("ppu", self.ppu),
+            ("timer", self.timer),
+            ("dma", self.dma),
+            ("serial", self.serial),
+            ("cartridge", self.cartridge),
+        ];
+        components.sort_unstable_by_key(|(_, time)| Reverse(*time));
+        components
+    }
+}
+
+impl fmt::Display for CycleProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().as_secs_f64();
+        for (name, time) in self.components().iter() {
+            let percent = if total > 0.0 {
+                time.as_secs_f64() * 100.0 / total
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "{:<10} {:>10.3} ms {:>5.1}%",
+                name,
+                time.as_secs_f64() * 1000.0,
+                percent
+            )?;
+        }
+        write!(
+            f,
+            "{:<10} {:>10.3} ms over {} steps and {} cycles",
+            "total",
+            total * 1000.0,
+            self.steps,
+            self.cycles
+        )
+    }
+}
+
+/// Part of the gameboy that time is recorded against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Component {
+    #[cfg_attr(not(feature = "profiling"), allow(dead_code))]
+    Cpu,
+    Ppu,
+    Timer,
+    Dma,
+    Serial,
+    Cartridge,
+}
+
+#[cfg(feature = "profiling")]
+mod imp {
+    use super::{Component, CycleProfile};
+    use std::time::Instant;
+
+    /// Records host time spent in each component
+    pub(crate) struct Profiler {
+        profile: CycleProfile,
+        /// When time was last recorded against a component
+        mark: Instant,
+    }
+
+    impl Profiler {
+        pub(crate) fn new() -> Profiler {
+            Profiler {
+                profile: CycleProfile::default(),
+                mark: Instant::now(),
+            }
+        }
+
+        pub(crate) fn profile(&self) -> Option<CycleProfile> {
+            Some(self.profile)
+        }
+
+        pub(crate) fn reset(&mut self) {
+            self.profile = CycleProfile::default();
+        }
+
+        pub(crate) fn start_step(&mut self) {
+            self.mark = Instant::now();
+        }
+
+        pub(crate) fn finish_step(&mut self) {
+            self.record(Component::Cpu);
+            self.profile.steps += 1;
+        }
+
+        pub(crate) fn start_cycle(&mut self) {
+            self.record(Component::Cpu);
+            self.profile.cycles += 1;
+        }
+
+        /// Count the time since the last record against `component`
+        pub(crate) fn record(&mut self, component: Component) {
+            let now = Instant::now();
+            let elapsed = now - self.mark;
+            self.mark = now;
+            let total = match component {
+                Component::Cpu => &mut self.profile.cpu,
+                Component::Ppu => &mut self.profile.ppu,
+                Component::Timer => &mut self.profile.timer,
+                Component::Dma => &mut self.profile.dma,
+                Component::Serial => &mut self.profile.serial,
+                Component::Cartridge => &mut self.profile.cartridge,
+            };
+            *total += elapsed;
+        }
+    }
+}
+
+#[cfg(not(feature = "profiling"))]
+mod imp {
+    use super::{Component, CycleProfile};
+
+    /// Stands in for the profiler when the `profiling` feature is disabled
+    pub(crate) struct Profiler;
+
+    impl Profiler {
+        pub(crate) fn new() -> Profiler {
+            Profiler
+        }
+
+        pub(crate) fn profile(&self) -> Option<CycleProfile> {
+            None
+        }
+
+        pub(crate) fn reset(&mut self) {}
+
+        #[inline(always)]
+        pub(crate) fn start_step(&mut self) {}
+
+        #[inline(always)]
+        pub(crate) fn finish_step(&mut self) {}
+
+        #[inline(always)]
+        pub(crate) fn start_cycle(&mut self) {}
+
+        #[inline(always)]
+        pub(crate) fn record(&mut self, _component: Component) {}
+    }
+}
+
+pub(crate) use imp::Profiler;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_components_largest_first() {
+        let profile = CycleProfile {
+            cpu: Duration::from_millis(30),
+            ppu: Duration::from_millis(50),
+            timer: Duration::from_millis(10),
+            steps: 100,
+            cycles: 400,
+            ..CycleProfile::default()
+        };
+
+        assert_eq!(profile.total(), Duration::from_millis(90));
+        let names: Vec<_> = profile.components().iter().map(|c| c.0).collect();
+        assert_eq!(names[..3], ["ppu", "cpu", "timer"]);
+        assert!(profile
+            .to_string()
+            .starts_with("ppu            50.000 ms  55.6%\n"));
+    }
+}
@@ -0,0 +1,117 @@
+/// Seed used by a gameboy's [`Rng`] until another is chosen
+pub const DEFAULT_SEED: u64 = 0x4F4C_594D_5049_4121;
+
+/// A seeded pseudo-random number generator for emulating noisy hardware
+///
+/// Anything the emulator fills with noise, such as uninitialized RAM, takes its
+/// values from a gameboy's `Rng`, rather than from the host. The same seed gives
+/// the same values on every run and every platform, so replays stay exact. The
+/// whole state is the current value, so serializing an `Rng` with the rest of an
+/// emulator's state is enough to continue the same sequence after restoring it.
+///
+/// This uses SplitMix64, which is fast and has no bad seeds. It is not suitable
+/// for cryptography.
+///
+/// ```
+/// use olympia_engine::gameboy::Rng;
+///
+/// let mut first = Rng::new(42);
+/// let mut second = Rng::new(42);
+/// assert_eq!(first.next_u64(), second.next_u64());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator which produces the sequence for `seed`
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// The next 64 bits of the sequence
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// The next byte of the sequence
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    /// Fill `dest` with bytes from the sequence
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Rng {
+        Rng::new(DEFAULT_SEED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut first = Rng::new(1234);
+        let mut second = Rng::new(1234);
+        let mut other = Rng::new(1235);
+
+        let first_values: Vec<u64> = (0..8).map(|_| first.next_u64()).collect();
+        let second_values: Vec<u64> = (0..8).map(|_| second.next_u64()).collect();
+        let other_values: Vec<u64> = (0..8).map(|_| other.next_u64()).collect();
+
+        assert_eq!(first_values, second_values);
+        assert_ne!(first_values, other_values);
+    }
+
+    #[test]
+    fn test_known_sequence() {
+        // Reference values for SplitMix64, so the sequence never changes between versions
+        let mut rng = Rng::new(0);
+
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+    }
+
+    #[test]
+    fn test_fill_bytes() {
+        let mut rng = Rng::new(7);
+        let mut expected = Rng::new(7);
+        let mut data = [0u8; 10];
+
+        rng.fill_bytes(&mut data);
+
+        let first = expected.next_u64().to_le_bytes();
+        let second = expected.next_u64().to_le_bytes();
+        assert_eq!(data[..8], first);
+        assert_eq!(data[8..], second[..2]);
+        assert_eq!(rng, expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_restored_continues_sequence() {
+        let mut rng = Rng::new(99);
+        rng.next_u64();
+
+        let json = serde_json::to_string(&rng).unwrap();
+        let mut restored: Rng = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(rng.next_u64(), restored.next_u64());
+    }
+}
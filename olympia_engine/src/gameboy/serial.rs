@@ -0,0 +1,209 @@
+use super::{cpu::Interrupt, memory::Memory};
+use crate::events::{EventEmitter, SerialEvent};
+use crate::logging;
+use crate::shared::MaybeSend;
+use alloc::boxed::Box;
+
+/// Machine cycles to shift out 8 bits at the internal 8192Hz serial clock
+pub const CYCLES_PER_TRANSFER: u32 = 1024;
+
+const TRANSFER_START_MASK: u8 = 0x80;
+const INTERNAL_CLOCK_MASK: u8 = 0x01;
+
+/// A device connected to the gameboy's serial port, such as a printer
+/// or another gameboy
+///
+/// Devices must be `Send` when the `sync` feature is enabled
+pub trait SerialDevice: MaybeSend {
+    /// Exchange a byte with the device
+    ///
+    /// Returns the byte the device sends back to the gameboy
+    fn transfer(&mut self, value: u8) -> u8;
+    /// Check whether the device has clocked a byte in to the gameboy
+    ///
+    /// Called every machine cycle the gameboy is not driving a transfer
+    /// itself. Devices with their own clock, such as another gameboy, return
+    /// the byte they sent, and receive `value` in exchange.
+    fn poll_external(&mut self, _value: u8) -> Option<u8> {
+        None
+    }
+    /// Take any event produced by the last transfer
+    fn take_event(&mut self) -> Option<SerialEvent> {
+        None
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Serial {
+    device: Option<Box<dyn SerialDevice>>,
+    cycles_remaining: Option<u32>,
+    pub(crate) events: EventEmitter<SerialEvent>,
+}
+
+impl Serial {
+    pub(crate) fn connect(&mut self, device: Box<dyn SerialDevice>) {
+        self.device = Some(device);
+    }
+
+    pub(crate) fn disconnect(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.device.take()
+    }
+
+    /// Runs a single machine cycle of any in progress transfer
+    ///
+    /// Transfers using the internal clock are driven here. Otherwise the
+    /// connected device is polled in case it has driven the clock itself.
+    pub(crate) fn run_cycle(&mut self, mem: &mut Memory) {
+        let start_mask = TRANSFER_START_MASK | INTERNAL_CLOCK_MASK;
+        if mem.registers().sc & start_mask != start_mask {
+            self.cycles_remaining = None;
+            self.poll_external(mem);
+            return;
+        }
+        let remaining = self.cycles_remaining.unwrap_or(CYCLES_PER_TRANSFER) - 1;
+        if remaining > 0 {
+            self.cycles_remaining = Some(remaining);
+            return;
+        }
+        self.cycles_remaining = None;
+
+        let registers = mem.registers_mut();
+        let sent = registers.sb;
+        registers.sb = match self.device.as_mut() {
+            Some(device) => device.transfer(sent),
+            None => 0xFF,
+        };
+        log::trace!(target: logging::SERIAL, "Sent {:02X}, received {:02X}", sent, registers.sb);
+        registers.sc &= !TRANSFER_START_MASK;
+        Interrupt::Serial.set(&mut registers.iflag);
+
+        self.emit_device_event();
+    }
+
+    /// Completes a transfer clocked by the connected device, if it sent one
+    ///
+    /// As on hardware, the byte is shifted in even if no transfer was started,
+    /// but the interrupt is only raised for started transfers.
+    fn poll_external(&mut self, mem: &mut Memory) {
+        let registers = mem.registers_mut();
+        let sent = registers.sb;
+        let received = match self.device.as_mut().and_then(|d| d.poll_external(sent)) {
+            Some(received) => received,
+            None => return,
+        };
+        registers.sb = received;
+        log::trace!(target: logging::SERIAL, "Externally clocked: sent {:02X}, received {:02X}", sent, received);
+        if registers.sc & TRANSFER_START_MASK != 0 {
+            registers.sc &= !TRANSFER_START_MASK;
+            Interrupt::Serial.set(&mut registers.iflag);
+        }
+        self.emit_device_event();
+    }
+
+    fn emit_device_event(&mut self) {
+        if let Some(event) = self.device.as_mut().and_then(|d| d.take_event()) {
+            self.events.emit(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::Cartridge;
+
+    struct Echo;
+
+    impl SerialDevice for Echo {
+        fn transfer(&mut self, value: u8) -> u8 {
+            value.wrapping_add(1)
+        }
+    }
+
+    /// Clocks in a single byte the first time it is polled
+    struct ExternalClock {
+        pending: Option<u8>,
+    }
+
+    impl SerialDevice for ExternalClock {
+        fn transfer(&mut self, _value: u8) -> u8 {
+            0xFF
+        }
+
+        fn poll_external(&mut self, _value: u8) -> Option<u8> {
+            self.pending.take()
+        }
+    }
+
+    fn memory() -> Memory {
+        Memory::new(Cartridge::from_data(vec![0u8; 0x8000]).unwrap())
+    }
+
+    #[test]
+    fn test_transfer_with_device() {
+        let mut memory = memory();
+        let mut serial = Serial::default();
+        serial.connect(Box::new(Echo));
+
+        memory.registers_mut().sb = 0x42;
+        memory.registers_mut().sc = 0x81;
+        for _ in 0..CYCLES_PER_TRANSFER - 1 {
+            serial.run_cycle(&mut memory);
+        }
+        assert_eq!(memory.registers().sb, 0x42);
+        assert_eq!(memory.registers().sc, 0x81);
+
+        serial.run_cycle(&mut memory);
+        assert_eq!(memory.registers().sb, 0x43);
+        assert_eq!(memory.registers().sc, 0x01);
+        assert_eq!(memory.registers().iflag, Interrupt::Serial.mask());
+    }
+
+    #[test]
+    fn test_transfer_without_device() {
+        let mut memory = memory();
+        let mut serial = Serial::default();
+
+        memory.registers_mut().sb = 0x42;
+        memory.registers_mut().sc = 0x81;
+        for _ in 0..CYCLES_PER_TRANSFER {
+            serial.run_cycle(&mut memory);
+        }
+        assert_eq!(memory.registers().sb, 0xFF);
+        assert_eq!(memory.registers().sc, 0x01);
+    }
+
+    #[test]
+    fn test_external_clock_waits() {
+        let mut memory = memory();
+        let mut serial = Serial::default();
+        serial.connect(Box::new(Echo));
+
+        memory.registers_mut().sb = 0x42;
+        memory.registers_mut().sc = 0x80;
+        for _ in 0..CYCLES_PER_TRANSFER * 2 {
+            serial.run_cycle(&mut memory);
+        }
+        assert_eq!(memory.registers().sb, 0x42);
+        assert_eq!(memory.registers().sc, 0x80);
+    }
+
+    #[test]
+    fn test_externally_clocked_transfer() {
+        let mut memory = memory();
+        let mut serial = Serial::default();
+        serial.connect(Box::new(ExternalClock {
+            pending: Some(0x24),
+        }));
+
+        memory.registers_mut().sb = 0x42;
+        memory.registers_mut().sc = 0x80;
+        serial.run_cycle(&mut memory);
+        assert_eq!(memory.registers().sb, 0x24);
+        assert_eq!(memory.registers().sc, 0x00);
+        assert_eq!(memory.registers().iflag, Interrupt::Serial.mask());
+
+        serial.run_cycle(&mut memory);
+        assert_eq!(memory.registers().sb, 0x24);
+    }
+}
@@ -12,7 +12,7 @@ pub const TIMER_PERIOD_MASK: u8 = 0b11;
 
 pub const TIMER_DIVISORS: [u64; 4] = [1024, 16, 64, 256];
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Timer {
     gb_ticks: u64,
     timer_ticks: u64,
@@ -52,6 +52,13 @@ impl Timer {
         }
     }
 
+    /// Reset DIV and the internal counter behind it, as the STOP instruction does
+    pub fn reset_divider(&mut self, mem: &mut Memory) {
+        mem.registers_mut().div = 0;
+        self.last_seen_div = 0;
+        self.timer_reset_at = self.gb_ticks;
+    }
+
     fn update_counter(&mut self, mem: &mut Memory, starting_ticks: u64, finishing_ticks: u64) {
         let timer_divisor = TIMER_DIVISORS[self.timer_divisor_selected];
         let old_remainder = (starting_ticks - self.timer_enabled_at) % timer_divisor;
@@ -97,6 +104,25 @@ mod test {
         assert_eq!(memory.registers().div, 1);
     }
 
+    #[test]
+    fn test_reset_divider() {
+        let mut memory = memory();
+        let mut timer = Timer::default();
+
+        for _ in 0..100 {
+            timer.tick(&mut memory, 4);
+        }
+        assert_eq!(memory.registers().div, 1);
+
+        timer.reset_divider(&mut memory);
+        for _ in 0..63 {
+            timer.tick(&mut memory, 4);
+        }
+        assert_eq!(memory.registers().div, 0);
+        timer.tick(&mut memory, 4);
+        assert_eq!(memory.registers().div, 1);
+    }
+
     #[test]
     fn test_timer_increments_counter() {
         let mut memory = memory();
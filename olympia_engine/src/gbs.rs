@@ -0,0 +1,411 @@
+//! Playback of Game Boy Sound (GBS) music files
+//!
+//! A GBS file holds the music code ripped from a game, along with the
+//! addresses of its routines. Load one with [`GbsFile::from_data`], then
+//! create a [`GbsPlayer`] for the track to play. The player calls the init
+//! routine once for the track, and then the play routine at the rate given
+//! in the header.
+//!
+//! Olympia has no APU yet, so tracks play silently. The writes the play
+//! routine makes to the sound registers can be seen through the gameboy's
+//! memory events.
+//!
+//! [`GbsFile::from_data`]: struct.GbsFile.html#method.from_data
+//! [`GbsPlayer`]: struct.GbsPlayer.html
+
+use crate::gameboy::{GameBoy, GameBoyModel, Interrupt, StepResult};
+use crate::registers::WordRegister;
+use crate::rom::{Cartridge, TargetConsole, MBC5};
+use crate::scheduler::{CLOCKS_PER_FRAME, CLOCK_FREQ};
+use alloc::string::String;
+use alloc::vec::Vec;
+use derive_more::Display;
+
+const SIGNATURE: &[u8] = b"GBS";
+const SUPPORTED_VERSION: u8 = 1;
+const HEADER_SIZE: usize = 0x70;
+const TITLE_LOCATION: usize = 0x10;
+const AUTHOR_LOCATION: usize = 0x30;
+const COPYRIGHT_LOCATION: usize = 0x50;
+const TEXT_SIZE: usize = 0x20;
+
+/// Lowest address music code can be loaded at, leaving room for the player
+const MIN_LOAD_ADDRESS: u16 = 0x400;
+const BANK_SIZE: usize = 0x4000;
+/// ROM image size for music code which fits in the first two banks
+const MIN_ROM_SIZE: usize = 2 * BANK_SIZE;
+/// MBC5 with RAM, which gives the music code bank switching and RAM
+const CARTRIDGE_TYPE: u8 = 0x1A;
+const RAM_SIZE: usize = 8 * 1024;
+/// Where the player calls the init routine from
+const START_ADDRESS: u16 = 0x200;
+
+const TAC_ADDR: u16 = 0xFF07;
+const TMA_ADDR: u16 = 0xFF06;
+const INTERRUPT_ENABLE_ADDR: u16 = 0xFFFF;
+/// Bit of the timer control header field which drives play with the timer
+const TAC_USE_TIMER: u8 = 0b100;
+/// Timer frequencies in Hz for each clock select value of the timer control field
+const TIMER_FREQUENCIES: [u64; 4] = [4096, 262_144, 65536, 16384];
+
+#[derive(PartialEq, Eq, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Error reading a GBS file
+pub enum GbsLoadError {
+    /// The file is smaller than a GBS header
+    #[display(fmt = "GBS file too small. Was 0x{:X} bytes", "_0")]
+    FileTooSmall(usize),
+    /// The file does not start with `GBS`
+    #[display(fmt = "Not a GBS file")]
+    InvalidSignature,
+    /// The file uses a version of the format other than 1
+    #[display(fmt = "Unsupported GBS version: {}", "_0")]
+    UnsupportedVersion(u8),
+    /// The music code would overwrite the player, or not fit in ROM
+    #[display(fmt = "Invalid load address: 0x{:04X}", "_0")]
+    InvalidLoadAddress(u16),
+    /// The requested track is not in the file
+    #[display(fmt = "No track {} in a file with {} tracks", "_0 + 1", "_1")]
+    InvalidTrack(u8, u8),
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for GbsLoadError {}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+/// The header of a GBS file
+pub struct GbsHeader {
+    /// Number of tracks in the file
+    pub track_count: u8,
+    /// Track to play first, starting from 0
+    pub first_track: u8,
+    /// Address the music code is loaded at
+    pub load_address: u16,
+    /// Routine which sets up a track, called with the track number in A
+    pub init_address: u16,
+    /// Routine which plays the next part of the current track
+    pub play_address: u16,
+    /// Initial stack pointer
+    pub stack_pointer: u16,
+    /// Value of the timer modulo register (TMA)
+    pub timer_modulo: u8,
+    /// Value of the timer control register (TAC), and whether to use the timer
+    pub timer_control: u8,
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+}
+
+impl GbsHeader {
+    /// Whether the play routine is driven by the timer rather than VBlank
+    pub fn uses_timer(&self) -> bool {
+        self.timer_control & TAC_USE_TIMER != 0
+    }
+
+    /// Clocks between calls of the play routine
+    pub fn play_interval(&self) -> u64 {
+        if self.uses_timer() {
+            let frequency = TIMER_FREQUENCIES[usize::from(self.timer_control & 0b11)];
+            (CLOCK_FREQ / frequency) * (256 - u64::from(self.timer_modulo))
+        } else {
+            CLOCKS_PER_FRAME
+        }
+    }
+}
+
+fn read_text(data: &[u8]) -> String {
+    let text = data
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| {
+            if b.is_ascii_graphic() || *b == b' ' {
+                char::from(*b)
+            } else {
+                '?'
+            }
+        })
+        .collect::<String>();
+    String::from(text.trim_end())
+}
+
+fn read_u16(data: &[u8], location: usize) -> u16 {
+    u16::from_le_bytes([data[location], data[location + 1]])
+}
+
+/// A GBS music file
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct GbsFile {
+    pub header: GbsHeader,
+    /// Music code and data, loaded at the header's load address
+    pub code: Vec<u8>,
+}
+
+impl GbsFile {
+    /// Read a GBS file
+    pub fn from_data(data: &[u8]) -> Result<GbsFile, GbsLoadError> {
+        if data.len() < HEADER_SIZE {
+            return Err(GbsLoadError::FileTooSmall(data.len()));
+        }
+        if !data.starts_with(SIGNATURE) {
+            return Err(GbsLoadError::InvalidSignature);
+        }
+        if data[3] != SUPPORTED_VERSION {
+            return Err(GbsLoadError::UnsupportedVersion(data[3]));
+        }
+        let header = GbsHeader {
+            track_count: data[4],
+            first_track: data[5].saturating_sub(1),
+            load_address: read_u16(data, 6),
+            init_address: read_u16(data, 8),
+            play_address: read_u16(data, 0xA),
+            stack_pointer: read_u16(data, 0xC),
+            timer_modulo: data[0xE],
+            timer_control: data[0xF],
+            title: read_text(&data[TITLE_LOCATION..TITLE_LOCATION + TEXT_SIZE]),
+            author: read_text(&data[AUTHOR_LOCATION..AUTHOR_LOCATION + TEXT_SIZE]),
+            copyright: read_text(&data[COPYRIGHT_LOCATION..COPYRIGHT_LOCATION + TEXT_SIZE]),
+        };
+        if !(MIN_LOAD_ADDRESS..0x8000).contains(&header.load_address) {
+            return Err(GbsLoadError::InvalidLoadAddress(header.load_address));
+        }
+        Ok(GbsFile {
+            header,
+            code: data[HEADER_SIZE..].to_vec(),
+        })
+    }
+
+    /// Build a ROM which plays `track`, with the music code at its load address
+    ///
+    /// Below the load address are the RST vectors, which GBS files expect to
+    /// jump to the load address plus the vector, interrupt vectors which call
+    /// the play routine, and a loop which calls the init routine and then
+    /// waits for interrupts.
+    fn rom_image(&self, track: u8) -> Vec<u8> {
+        let header = &self.header;
+        let load_address = usize::from(header.load_address);
+        let used = load_address + self.code.len();
+        let size = MIN_ROM_SIZE.max(used.div_ceil(BANK_SIZE) * BANK_SIZE);
+        let mut rom = vec![0; size];
+        rom[load_address..used].copy_from_slice(&self.code);
+
+        for vector in (0..0x40).step_by(8) {
+            let [low, high] = (header.load_address + vector).to_le_bytes();
+            let vector = usize::from(vector);
+            rom[vector..vector + 3].copy_from_slice(&[0xC3, low, high]); // JP nn
+        }
+        let [play_low, play_high] = header.play_address.to_le_bytes();
+        for vector in &[
+            Interrupt::VBlank.handler_address(),
+            Interrupt::Timer.handler_address(),
+        ] {
+            let vector = usize::from(vector.0);
+            rom[vector..vector + 4].copy_from_slice(&[
+                0xCD, play_low, play_high, // CALL play
+                0xD9,      // RETI
+            ]);
+        }
+        let [sp_low, sp_high] = header.stack_pointer.to_le_bytes();
+        let [init_low, init_high] = header.init_address.to_le_bytes();
+        let start = usize::from(START_ADDRESS);
+        rom[start..start + 13].copy_from_slice(&[
+            0xF3, // DI
+            0x31, sp_low, sp_high, // LD SP, nn
+            0x3E, track, // LD A, n
+            0xCD, init_low, init_high, // CALL init
+            0xFB,      // EI
+            0x76,      // HALT
+            0x18, 0xFD, // JR -3
+        ]);
+        rom
+    }
+}
+
+/// Plays a track from a GBS file
+pub struct GbsPlayer {
+    gb: GameBoy,
+    header: GbsHeader,
+    track: u8,
+    play_calls: u64,
+}
+
+impl GbsPlayer {
+    /// Set up a gameboy to play `track`, starting from 0
+    pub fn new(file: &GbsFile, track: u8, model: GameBoyModel) -> Result<GbsPlayer, GbsLoadError> {
+        let header = file.header.clone();
+        if track >= header.track_count {
+            return Err(GbsLoadError::InvalidTrack(track, header.track_count));
+        }
+        let mut cartridge = Cartridge::new(
+            file.rom_image(track),
+            MBC5::new(RAM_SIZE, CARTRIDGE_TYPE).into(),
+            TargetConsole::GameBoyOnly,
+        );
+        // Music code expects RAM to be usable without enabling it
+        cartridge
+            .write(0x0000, 0x0A)
+            .expect("MBC5 RAM enable failed");
+        let mut gb = GameBoy::new(cartridge, model);
+        let interrupt = if header.uses_timer() {
+            Interrupt::Timer
+        } else {
+            Interrupt::VBlank
+        };
+        let registers = [
+            (TMA_ADDR, header.timer_modulo),
+            (TAC_ADDR, header.timer_control),
+            (INTERRUPT_ENABLE_ADDR, interrupt.mask()),
+        ];
+        for (address, value) in registers.iter().copied() {
+            gb.set_memory_u8(address, value)
+                .expect("Setting up GBS player registers failed");
+        }
+        gb.write_register_u16(WordRegister::PC, START_ADDRESS);
+        Ok(GbsPlayer {
+            gb,
+            header,
+            track,
+            play_calls: 0,
+        })
+    }
+
+    pub fn header(&self) -> &GbsHeader {
+        &self.header
+    }
+
+    /// The track being played, starting from 0
+    pub fn track(&self) -> u8 {
+        self.track
+    }
+
+    /// The gameboy running the music code
+    pub fn gameboy(&self) -> &GameBoy {
+        &self.gb
+    }
+
+    /// The gameboy running the music code, for adding event handlers
+    pub fn gameboy_mut(&mut self) -> &mut GameBoy {
+        &mut self.gb
+    }
+
+    /// How many times the play routine has been called
+    pub fn play_calls(&self) -> u64 {
+        self.play_calls
+    }
+
+    /// Run a single instruction of the music code
+    pub fn step(&mut self) -> StepResult<()> {
+        self.gb.step()?;
+        if self.gb.read_register_u16(WordRegister::PC) == self.header.play_address {
+            self.play_calls += 1;
+        }
+        Ok(())
+    }
+
+    /// Play for at least `clocks` clocks
+    pub fn run_for(&mut self, clocks: u64) -> StepResult<()> {
+        let start = self.gb.clocks_elapsed();
+        while self.gb.clocks_elapsed() - start < clocks {
+            self.step()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A GBS file whose init routine stores the track in RAM, and whose play
+    /// routine counts its calls in RAM
+    fn gbs_file(timer_control: u8) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        data[0..3].copy_from_slice(SIGNATURE);
+        data[3] = 1;
+        data[4] = 3; // Tracks
+        data[5] = 2; // First track
+        data[6..8].copy_from_slice(&0x400u16.to_le_bytes());
+        data[8..0xA].copy_from_slice(&0x400u16.to_le_bytes());
+        data[0xA..0xC].copy_from_slice(&0x404u16.to_le_bytes());
+        data[0xC..0xE].copy_from_slice(&0xFFFEu16.to_le_bytes());
+        data[0xE] = 0x80;
+        data[0xF] = timer_control;
+        data[TITLE_LOCATION..TITLE_LOCATION + 4].copy_from_slice(b"Song");
+        data[AUTHOR_LOCATION..AUTHOR_LOCATION + 6].copy_from_slice(b"Author");
+        data.extend_from_slice(&[
+            0xEA, 0x00, 0xA0, // 0x400: LD (A000h), A
+            0xC9, // 0x403: RET
+            0x21, 0x01, 0xA0, // 0x404: LD HL, A001h
+            0x34, // 0x407: INC (HL)
+            0xC9, // 0x408: RET
+        ]);
+        data
+    }
+
+    #[test]
+    fn test_header() {
+        let file = GbsFile::from_data(&gbs_file(0)).unwrap();
+
+        assert_eq!(file.header.track_count, 3);
+        assert_eq!(file.header.first_track, 1);
+        assert_eq!(file.header.title, "Song");
+        assert_eq!(file.header.author, "Author");
+        assert_eq!(file.header.copyright, "");
+        assert!(!file.header.uses_timer());
+        assert_eq!(file.header.play_interval(), CLOCKS_PER_FRAME);
+        assert_eq!(file.code.len(), 9);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let mut data = gbs_file(0);
+        assert_eq!(
+            GbsFile::from_data(&data[..0x20]),
+            Err(GbsLoadError::FileTooSmall(0x20))
+        );
+        data[6..8].copy_from_slice(&0x200u16.to_le_bytes());
+        assert_eq!(
+            GbsFile::from_data(&data),
+            Err(GbsLoadError::InvalidLoadAddress(0x200))
+        );
+        data[0] = b'N';
+        assert_eq!(
+            GbsFile::from_data(&data),
+            Err(GbsLoadError::InvalidSignature)
+        );
+
+        let file = GbsFile::from_data(&gbs_file(0)).unwrap();
+        let err = GbsPlayer::new(&file, 3, GameBoyModel::GameBoy)
+            .err()
+            .unwrap();
+        assert_eq!(err, GbsLoadError::InvalidTrack(3, 3));
+    }
+
+    #[test]
+    fn test_plays_at_vblank_rate() {
+        let file = GbsFile::from_data(&gbs_file(0)).unwrap();
+        let mut player = GbsPlayer::new(&file, 2, GameBoyModel::GameBoy).unwrap();
+
+        player.run_for(CLOCKS_PER_FRAME * 5).unwrap();
+
+        let gb = player.gameboy();
+        assert_eq!(gb.get_memory_u8(0xA000).unwrap(), 2);
+        let calls = gb.get_memory_u8(0xA001).unwrap();
+        assert!((4..=5).contains(&calls), "{} calls", calls);
+        assert_eq!(player.play_calls(), u64::from(calls));
+    }
+
+    #[test]
+    fn test_plays_at_timer_rate() {
+        // 4096 Hz timer overflowing every 128 ticks, so 32 calls per second
+        let file = GbsFile::from_data(&gbs_file(0b100)).unwrap();
+        let interval = file.header.play_interval();
+        assert_eq!(interval, CLOCK_FREQ / 32);
+        let mut player = GbsPlayer::new(&file, 0, GameBoyModel::GameBoy).unwrap();
+
+        // TIMA starts from 0, so the first call takes longer
+        player.run_for(interval * 9).unwrap();
+
+        let calls = player.gameboy().get_memory_u8(0xA001).unwrap();
+        assert!((7..=8).contains(&calls), "{} calls", calls);
+    }
+}
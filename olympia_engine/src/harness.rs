@@ -0,0 +1,226 @@
+//! Run emulators headlessly, a frame at a time
+//!
+//! This is intended for automated playtesting and reinforcement learning,
+//! which run many emulators as quickly as possible and control them from
+//! code. Everything runs on the calling thread, without channels or
+//! event handlers.
+//!
+//! ```
+//! use olympia_engine::gameboy::{Button, GameBoyModel};
+//! use olympia_engine::harness::{Harness, SCREEN_HEIGHT, SCREEN_WIDTH};
+//!
+//! let rom = vec![0; 0x8000];
+//! let mut harness = Harness::new(rom, GameBoyModel::GameBoy).unwrap();
+//! harness.set_buttons(&[Button::A, Button::Right]);
+//! harness.run_frame().unwrap();
+//! assert_eq!(harness.frame_count(), 1);
+//! assert_eq!(harness.screen().len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+//! ```
+
+use crate::gameboy::{Button, GameBoy, GameBoyModel, StepResult};
+use crate::rom::{Cartridge, CartridgeLoadError};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// Width of the screen in pixels
+pub const SCREEN_WIDTH: usize = 160;
+/// Height of the screen in pixels
+pub const SCREEN_HEIGHT: usize = 144;
+/// Clocks taken to draw a frame, at normal speed
+const FRAME_CLOCKS: u64 = 70224;
+
+/// A single emulator, controlled a frame at a time
+pub struct Harness {
+    /// Boxed, as a `GameBoy` is large enough that moving several around
+    /// can overflow the stack
+    gb: Box<GameBoy>,
+    screen: Vec<u8>,
+    frames: u64,
+}
+
+impl Harness {
+    /// Create an emulator running the given ROM
+    pub fn new(rom: Vec<u8>, model: GameBoyModel) -> Result<Harness, CartridgeLoadError> {
+        let cartridge = Cartridge::from_data(rom)?;
+        Ok(Harness {
+            gb: Box::new(GameBoy::new(cartridge, model)),
+            screen: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frames: 0,
+        })
+    }
+
+    /// Hold down the given buttons, and release all others
+    pub fn set_buttons(&mut self, pressed: &[Button]) {
+        for button in Button::ALL.iter() {
+            self.gb.set_button(*button, pressed.contains(button));
+        }
+    }
+
+    /// Run until the next frame has been drawn
+    ///
+    /// While the LCD is off no frames are drawn, so this runs for as long
+    /// as a frame would take and leaves the screen unchanged.
+    pub fn run_frame(&mut self) -> StepResult<()> {
+        let start_clocks = self.gb.clocks_elapsed();
+        let timed_out = |gb: &GameBoy| gb.clocks_elapsed() - start_clocks >= FRAME_CLOCKS;
+        // Finish the previous frame's VBlank before waiting for the next one
+        while self.gb.ppu.in_vblank() && !timed_out(&self.gb) {
+            self.gb.step()?;
+        }
+        while !self.gb.ppu.in_vblank() {
+            if timed_out(&self.gb) {
+                return Ok(());
+            }
+            self.gb.step()?;
+        }
+        for (shade, pixel) in self.screen.iter_mut().zip(self.gb.ppu.framebuffer()) {
+            *shade = pixel.color;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// The most recently drawn frame, row by row from the top left
+    ///
+    /// Each byte is a shade from 0 (white) to 3 (black).
+    pub fn screen(&self) -> &[u8] {
+        &self.screen
+    }
+
+    /// The number of frames drawn by `run_frame`
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// The emulator, for reading memory or registers
+    pub fn gameboy(&self) -> &GameBoy {
+        &self.gb
+    }
+
+    /// The emulator, for changing its state directly
+    pub fn gameboy_mut(&mut self) -> &mut GameBoy {
+        &mut self.gb
+    }
+}
+
+/// Many emulators which are run together
+///
+/// ```
+/// use olympia_engine::gameboy::{Button, GameBoyModel};
+/// use olympia_engine::harness::Batch;
+///
+/// let rom = vec![0; 0x8000];
+/// let mut batch = Batch::new(&rom, GameBoyModel::GameBoy, 4).unwrap();
+/// for (i, harness) in batch.instances_mut().iter_mut().enumerate() {
+///     let buttons: &[Button] = if i % 2 == 0 { &[Button::Start] } else { &[] };
+///     harness.set_buttons(buttons);
+/// }
+/// let results = batch.run_frame();
+/// assert!(results.iter().all(|result| result.is_ok()));
+/// ```
+pub struct Batch {
+    instances: Vec<Harness>,
+}
+
+impl Batch {
+    /// Create `count` emulators, each running a copy of the given ROM
+    pub fn new(rom: &[u8], model: GameBoyModel, count: usize) -> Result<Batch, CartridgeLoadError> {
+        let instances = (0..count)
+            .map(|_| Harness::new(rom.to_vec(), model))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Batch { instances })
+    }
+
+    /// Run every emulator until its next frame has been drawn
+    ///
+    /// Returns the result for each emulator, in the same order as `instances`.
+    /// An emulator that fails is left where it stopped, and the others still run.
+    pub fn run_frame(&mut self) -> Vec<StepResult<()>> {
+        self.instances
+            .iter_mut()
+            .map(|harness| harness.run_frame())
+            .collect()
+    }
+
+    pub fn instances(&self) -> &[Harness] {
+        &self.instances
+    }
+
+    pub fn instances_mut(&mut self) -> &mut [Harness] {
+        &mut self.instances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fills the background with tile 1, which is solid colour 3, then loops
+    fn rom() -> Vec<u8> {
+        let mut data = vec![0; 0x8000];
+        let code = [
+            0x3E, 0x00, // LD A, 00h
+            0xE0, 0x40, // LDH (40h), A - LCD off, so VRAM can be written
+            0x3E, 0xFF, // LD A, FFh
+            0x21, 0x10, 0x80, // LD HL, 8010h
+            0x0E, 0x10, // LD C, 10h
+            0x22, // LD (HL+), A
+            0x0D, // DEC C
+            0x20, 0xFC, // JR NZ, -4
+            0x21, 0x00, 0x98, // LD HL, 9800h
+            0x01, 0x00, 0x04, // LD BC, 0400h
+            0x3E, 0x01, // LD A, 01h
+            0x22, // LD (HL+), A
+            0x0B, // DEC BC
+            0x78, // LD A, B
+            0xB1, // OR C
+            0x20, 0xF8, // JR NZ, -8
+            0x3E, 0x91, // LD A, 91h
+            0xE0, 0x40, // LDH (40h), A - LCD on
+            0x18, 0xFE, // JR -2
+        ];
+        data[0x100..0x100 + code.len()].copy_from_slice(&code);
+        data
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut harness = Harness::new(rom(), GameBoyModel::GameBoy).unwrap();
+        assert!(harness.screen().iter().all(|shade| *shade == 0));
+
+        // No frames are drawn while the LCD is off
+        while harness.frame_count() == 0 {
+            harness.run_frame().unwrap();
+        }
+
+        assert_eq!(harness.frame_count(), 1);
+        assert!(harness.screen().iter().all(|shade| *shade == 3));
+    }
+
+    #[test]
+    fn test_run_frame_lcd_off() {
+        let mut harness = Harness::new(rom(), GameBoyModel::GameBoy).unwrap();
+        harness.gameboy_mut().set_memory_u8(0xFF40, 0).unwrap();
+        let start_clocks = harness.gameboy().clocks_elapsed();
+
+        harness.run_frame().unwrap();
+
+        assert_eq!(harness.frame_count(), 0);
+        assert!(harness.gameboy().clocks_elapsed() - start_clocks >= FRAME_CLOCKS);
+    }
+
+    #[test]
+    fn test_set_buttons() {
+        let mut batch = Batch::new(&rom(), GameBoyModel::GameBoy, 2).unwrap();
+        batch.instances_mut()[0].set_buttons(&[Button::A]);
+        batch.instances_mut()[0].set_buttons(&[Button::Down]);
+        let gb = batch.instances_mut()[0].gameboy_mut();
+        gb.set_memory_u8(0xFF00, 0x10).unwrap(); // Select action buttons
+
+        assert_eq!(gb.get_memory_u8(0xFF00).unwrap() & 0x0F, 0x0F);
+        gb.set_memory_u8(0xFF00, 0x20).unwrap(); // Select directions
+        assert_eq!(gb.get_memory_u8(0xFF00).unwrap() & 0x0F, 0x07);
+        assert_eq!(batch.run_frame().len(), 2);
+    }
+}
@@ -1,24 +1,45 @@
 mod alu;
+mod audit;
 mod extended;
 mod jump;
 mod load;
 pub(crate) mod misc;
+mod reference;
 mod stack;
 
+pub use audit::{AuditProblem, AuditReport, AuditedOpcode, INVALID_OPCODES};
+pub use reference::{
+    instruction_set, CycleCount, FlagEffect, FlagEffects, InstructionReference, OpcodeReference,
+};
+
 use crate::disasm::Disassemble;
 use crate::gameboy::StepResult;
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::convert::TryInto;
+use once_cell::race::OnceBox;
 
-use olympia_core::instructions::{Instruction, InstructionOpcode, SerializableInstruction};
+use olympia_core::instructions::{
+    Instruction, InstructionDefinition, InstructionOpcode, SerializableInstruction,
+};
 
 pub trait ExecutableInstruction: Instruction {
     fn execute(&self, gb: &mut crate::gameboy::GameBoy) -> StepResult<()>;
 }
 
-pub trait RuntimeOpcode {
+/// An opcode that can build and run instructions
+///
+/// Opcodes hold no state, so are shared between threads when the
+/// `sync` feature is enabled
+pub trait RuntimeOpcode: Send + Sync {
     fn to_instruction(&self, data: &mut dyn Iterator<Item = u8>) -> Box<dyn RuntimeInstruction>;
+    /// Read this opcode's arguments from PC onwards and execute it
+    ///
+    /// Unlike `to_instruction`, the instruction is built on the stack rather than boxed
+    fn decode_and_execute(&self, gb: &mut crate::gameboy::GameBoy) -> StepResult<()>;
+    /// The definition of the instruction this opcode belongs to
+    fn instruction_definition(&self) -> &'static InstructionDefinition;
     fn all() -> Vec<(u8, Box<dyn RuntimeOpcode>)>
     where
         Self: Sized;
@@ -26,13 +47,22 @@ pub trait RuntimeOpcode {
 
 impl<T, F> RuntimeOpcode for T
 where
-    T: InstructionOpcode<FullInstruction = F> + 'static,
+    T: InstructionOpcode<FullInstruction = F> + Send + Sync + 'static,
     F: RuntimeInstruction + 'static,
 {
     fn to_instruction(&self, data: &mut dyn Iterator<Item = u8>) -> Box<dyn RuntimeInstruction> {
         Box::new(self.build_instruction(data))
     }
 
+    fn decode_and_execute(&self, gb: &mut crate::gameboy::GameBoy) -> StepResult<()> {
+        let instruction = self.build_instruction(&mut gb.cycling_memory_iter());
+        instruction.execute(gb)
+    }
+
+    fn instruction_definition(&self) -> &'static InstructionDefinition {
+        T::definition()
+    }
+
     fn all() -> Vec<(u8, Box<dyn RuntimeOpcode>)> {
         let mut output = vec![];
         for opcode in Self::definition().opcodes {
@@ -59,9 +89,35 @@ where
 {
 }
 
+/// Lookup table from an opcode byte to its prebuilt opcode
+type OpcodeTable<T> = Box<[T; 256]>;
+
+fn opcode_table<T>(entries: Vec<T>) -> OpcodeTable<T> {
+    match entries.into_boxed_slice().try_into() {
+        Ok(table) => table,
+        Err(_) => panic!("Opcode tables must have 256 entries"),
+    }
+}
+
+/// Every opcode of the standard table, with the instruction it belongs to
+///
+/// Opcodes listed more than once are a mistake, found by `RuntimeDecoder::audit`.
+fn instruction_opcodes() -> Vec<(u8, Box<dyn RuntimeOpcode>)> {
+    stack::opcodes()
+        .into_iter()
+        .chain(alu::opcodes())
+        .chain(jump::opcodes())
+        .chain(misc::opcodes())
+        .chain(load::opcodes())
+        .collect()
+}
+
+static SHARED_DECODER: OnceBox<RuntimeDecoder> = OnceBox::new();
+
 pub struct RuntimeDecoder {
-    opcodes: Vec<Option<Box<dyn RuntimeOpcode>>>,
-    extended_opcodes: Vec<Option<Box<dyn RuntimeOpcode>>>,
+    opcodes: OpcodeTable<Option<Box<dyn RuntimeOpcode>>>,
+    /// Every CB prefixed opcode is valid, so this needs no empty entries
+    extended_opcodes: OpcodeTable<Box<dyn RuntimeOpcode>>,
 }
 
 impl RuntimeDecoder {
@@ -72,14 +128,7 @@ impl RuntimeDecoder {
             opcodes.push(None);
             extended_opcodes.push(None);
         }
-        let input_codes = stack::opcodes()
-            .into_iter()
-            .chain(alu::opcodes())
-            .chain(jump::opcodes())
-            .chain(misc::opcodes())
-            .chain(load::opcodes());
-
-        for (value, executable) in input_codes {
+        for (value, executable) in instruction_opcodes() {
             opcodes[value as usize] = Some(executable);
         }
 
@@ -87,12 +136,29 @@ impl RuntimeDecoder {
             extended_opcodes[value as usize] = Some(executable);
         }
 
+        let extended_opcodes = extended_opcodes
+            .into_iter()
+            .enumerate()
+            .map(|(value, opcode)| match opcode {
+                Some(opcode) => opcode,
+                None => panic!("Missing extended opcode 0x{:02X}", value),
+            })
+            .collect();
+
         RuntimeDecoder {
-            opcodes,
-            extended_opcodes,
+            opcodes: opcode_table(opcodes),
+            extended_opcodes: opcode_table(extended_opcodes),
         }
     }
 
+    /// A decoder shared by every emulator in the process
+    ///
+    /// This is built the first time it is used. Prefer this over
+    /// `RuntimeDecoder::new`, which builds a new set of opcode tables.
+    pub fn shared() -> &'static RuntimeDecoder {
+        SHARED_DECODER.get_or_init(|| Box::new(RuntimeDecoder::new()))
+    }
+
     pub fn is_extended(&self, value: u8) -> bool {
         value == 0xCB
     }
@@ -117,7 +183,7 @@ impl RuntimeDecoder {
     }
 
     pub fn decode_extended(&self, value: u8) -> &dyn RuntimeOpcode {
-        self.extended_opcodes[value as usize].as_deref().unwrap()
+        self.extended_opcodes[value as usize].as_ref()
     }
 }
 
@@ -150,3 +216,6 @@ mod stack_tests;
 
 #[cfg(test)]
 mod misc_tests;
+
+#[cfg(test)]
+mod sm83_tests;
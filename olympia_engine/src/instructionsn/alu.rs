@@ -1,3 +1,4 @@
+use crate::gameboy::oam_bug::Access;
 use crate::gameboy::{GameBoy, StepResult};
 use crate::instructions::{ALOp, ByteRegisterTarget};
 use crate::instructionsn::{ExecutableInstruction, RuntimeOpcode};
@@ -223,6 +224,7 @@ impl ExecutableInstruction for Increment16 {
         let reg_value = gb.read_register_u16(self.target.into());
         let (new, _carry) = reg_value.overflowing_add(1);
         gb.write_register_u16(self.target.into(), new);
+        gb.oam_bug_trigger(reg_value, Access::Write);
         gb.cycle();
         Ok(())
     }
@@ -240,6 +242,7 @@ impl ExecutableInstruction for Decrement16 {
         let reg_value = gb.read_register_u16(self.target.into());
         let (new, _carry) = reg_value.overflowing_sub(1);
         gb.write_register_u16(self.target.into(), new);
+        gb.oam_bug_trigger(reg_value, Access::Write);
         gb.cycle();
         Ok(())
     }
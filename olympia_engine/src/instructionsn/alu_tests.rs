@@ -1,7 +1,8 @@
 #![allow(clippy::bool_assert_comparison)]
 
-use crate::gameboy::{testutils::*, StepResult};
+use crate::gameboy::StepResult;
 use crate::registers;
+use crate::testing::*;
 
 #[test]
 fn test_add_no_carry() -> StepResult<()> {
@@ -0,0 +1,281 @@
+//! Checks that every opcode is decoded, and decoded consistently
+//!
+//! Each instruction lists the opcodes it handles, and the decoder's tables
+//! are built from those lists. A mistake in a list can leave an opcode
+//! without an instruction, or let a later instruction silently replace an
+//! earlier one in the table. The audit checks all 512 opcodes against the
+//! lists, the instruction definitions and the decoder's tables.
+
+use super::{extended, instruction_opcodes, RuntimeDecoder, RuntimeOpcode};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use derive_more::Display;
+use olympia_core::instructions::{AppendedParam, ExtensionType, InstructionDefinition, ParamType};
+
+/// Opcodes which no instruction uses, and which lock up the CPU when run
+pub const INVALID_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+/// The opcode that starts every extended instruction
+const EXTENDED_PREFIX: u8 = 0xCB;
+
+/// An opcode in either the standard or extended table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum AuditedOpcode {
+    #[display(fmt = "{:02X}h", "_0")]
+    Standard(u8),
+    #[display(fmt = "CB {:02X}h", "_0")]
+    Extended(u8),
+}
+
+impl AuditedOpcode {
+    fn bytes(self) -> Vec<u8> {
+        match self {
+            AuditedOpcode::Standard(value) => vec![value],
+            AuditedOpcode::Extended(value) => vec![EXTENDED_PREFIX, value],
+        }
+    }
+}
+
+/// A problem found with an opcode by [`RuntimeDecoder::audit`]
+///
+/// [`RuntimeDecoder::audit`]: struct.RuntimeDecoder.html#method.audit
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum AuditProblem {
+    /// No instruction handles the opcode, and it isn't known to be invalid
+    #[display(fmt = "{} has no instruction, and isn't a known invalid opcode", "_0")]
+    Missing(AuditedOpcode),
+    /// An instruction handles an opcode which should be invalid
+    #[display(fmt = "{} should be invalid, but is handled by {}", "_0", "_1")]
+    UnexpectedlyValid(AuditedOpcode, &'static str),
+    /// Two instructions list the same opcode, so only the second is decoded
+    #[display(fmt = "{} is listed by both {} and {}", "_0", "_1", "_2")]
+    Conflict(AuditedOpcode, &'static str, &'static str),
+    /// An instruction handles an opcode its definition doesn't include
+    #[display(fmt = "{} is handled by {}, but isn't in its definition", "_0", "_1")]
+    NotInDefinition(AuditedOpcode, &'static str),
+    /// The decoder's table has a different instruction to the opcode lists
+    #[display(fmt = "{} decodes as {}, but is listed by {}", "_0", "_1", "_2")]
+    TableMismatch(AuditedOpcode, &'static str, &'static str),
+    /// Building the instruction reads a different number of bytes to its definition
+    #[display(
+        fmt = "{} ({}) reads {} bytes after the opcode, but its definition has {}",
+        opcode,
+        label,
+        read,
+        defined
+    )]
+    WrongLength {
+        opcode: AuditedOpcode,
+        label: &'static str,
+        read: usize,
+        defined: usize,
+    },
+    /// The built instruction doesn't encode back to the bytes it was built from
+    #[display(fmt = "{} ({}) encodes as {:02X?}", "_0", "_1", "_2")]
+    WrongEncoding(AuditedOpcode, &'static str, Vec<u8>),
+}
+
+/// The result of checking every opcode
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AuditReport {
+    /// Number of opcodes checked, from both tables
+    pub checked: usize,
+    /// Problems found, with standard opcodes before extended ones
+    pub problems: Vec<AuditProblem>,
+}
+
+impl AuditReport {
+    /// Whether no problems were found
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Checked {} opcodes, found {} problems",
+            self.checked,
+            self.problems.len()
+        )?;
+        for problem in &self.problems {
+            writeln!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bytes of arguments following the opcode, according to the definition
+fn defined_length(opcode: &dyn RuntimeOpcode) -> usize {
+    opcode
+        .instruction_definition()
+        .params
+        .iter()
+        .map(|param| match param.param_type {
+            ParamType::Appended(AppendedParam::LiteralAddress)
+            | ParamType::Appended(AppendedParam::Literal16) => 2,
+            ParamType::Appended(_) => 1,
+            _ => 0,
+        })
+        .sum()
+}
+
+type Definition = &'static InstructionDefinition;
+
+/// Check one table's opcode list, returning the instruction listed for each opcode
+fn audit_table(
+    opcodes: &[(u8, Box<dyn RuntimeOpcode>)],
+    extension_type: ExtensionType,
+    decoded: impl Fn(u8) -> Option<Definition>,
+    problems: &mut Vec<AuditProblem>,
+) -> [Option<Definition>; 256] {
+    let mut listed: [Option<Definition>; 256] = [None; 256];
+    let audited = |value| match extension_type {
+        ExtensionType::None => AuditedOpcode::Standard(value),
+        ExtensionType::Extended => AuditedOpcode::Extended(value),
+    };
+    for (value, opcode) in opcodes {
+        let opcode = opcode.as_ref();
+        let audited = audited(*value);
+        let definition = opcode.instruction_definition();
+        let label = definition.label;
+        if let Some(first) = listed[usize::from(*value)].replace(definition) {
+            problems.push(AuditProblem::Conflict(audited, first.label, label));
+        }
+        if !definition.opcodes.contains(value) || definition.extension_type != extension_type {
+            problems.push(AuditProblem::NotInDefinition(audited, label));
+        }
+
+        let mut read = 0;
+        let bytes = {
+            let mut args = core::iter::repeat(0).inspect(|_| read += 1);
+            opcode.to_instruction(&mut args).as_bytes()
+        };
+        let defined = defined_length(opcode);
+        if read != defined {
+            problems.push(AuditProblem::WrongLength {
+                opcode: audited,
+                label,
+                read,
+                defined,
+            });
+        }
+        let mut expected = audited.bytes();
+        expected.resize(expected.len() + read, 0);
+        if bytes != expected {
+            problems.push(AuditProblem::WrongEncoding(audited, label, bytes));
+        }
+    }
+    for value in 0..=255 {
+        let audited = audited(value);
+        match (listed[usize::from(value)], decoded(value)) {
+            (Some(listed), Some(decoded)) if listed != decoded => problems.push(
+                AuditProblem::TableMismatch(audited, decoded.label, listed.label),
+            ),
+            (Some(listed), None) => problems.push(AuditProblem::TableMismatch(
+                audited,
+                "nothing",
+                listed.label,
+            )),
+            _ => {}
+        }
+    }
+    listed
+}
+
+impl RuntimeDecoder {
+    /// Check that every opcode is either handled or known to be invalid,
+    /// and that each instruction decodes and encodes as its definition says
+    ///
+    /// The decoder's tables are also checked against the instructions'
+    /// opcode lists, so opcodes which are listed twice are found.
+    pub fn audit(&self) -> AuditReport {
+        let mut problems = Vec::new();
+        let listed = audit_table(
+            &instruction_opcodes(),
+            ExtensionType::None,
+            |value| {
+                self.decode(value)
+                    .map(|opcode| opcode.instruction_definition())
+            },
+            &mut problems,
+        );
+        for (value, definition) in listed.iter().enumerate() {
+            let audited = AuditedOpcode::Standard(value as u8);
+            let invalid =
+                value as u8 == EXTENDED_PREFIX || INVALID_OPCODES.contains(&(value as u8));
+            match definition {
+                Some(definition) if invalid => {
+                    problems.push(AuditProblem::UnexpectedlyValid(audited, definition.label))
+                }
+                None if !invalid => problems.push(AuditProblem::Missing(audited)),
+                _ => {}
+            }
+        }
+
+        let listed = audit_table(
+            &extended::opcodes(),
+            ExtensionType::Extended,
+            |value| Some(self.decode_extended(value).instruction_definition()),
+            &mut problems,
+        );
+        for (value, definition) in listed.iter().enumerate() {
+            if definition.is_none() {
+                problems.push(AuditProblem::Missing(AuditedOpcode::Extended(value as u8)));
+            }
+        }
+
+        AuditReport {
+            checked: 512,
+            problems,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_set_complete() {
+        let report = RuntimeDecoder::shared().audit();
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.checked, 512);
+    }
+
+    #[test]
+    fn test_audit_finds_problems() {
+        let mut problems = Vec::new();
+        let mut opcodes = instruction_opcodes();
+        // 00h is NOP, which is now listed twice and claims to be 01h
+        let (_, nop) = opcodes.remove(
+            opcodes
+                .iter()
+                .position(|(value, _)| *value == 0x00)
+                .unwrap(),
+        );
+        opcodes.push((0x01, nop));
+
+        let listed = audit_table(&opcodes, ExtensionType::None, |_| None, &mut problems);
+
+        assert!(listed[0x00].is_none());
+        assert!(problems.contains(&AuditProblem::Conflict(
+            AuditedOpcode::Standard(0x01),
+            "LD",
+            "NOP"
+        )));
+        assert!(problems.contains(&AuditProblem::NotInDefinition(
+            AuditedOpcode::Standard(0x01),
+            "NOP"
+        )));
+        assert!(problems.contains(&AuditProblem::TableMismatch(
+            AuditedOpcode::Standard(0x01),
+            "nothing",
+            "NOP"
+        )));
+    }
+}
@@ -1,5 +1,6 @@
-use crate::gameboy::{cpu::Interrupt, testutils::*, StepResult};
+use crate::gameboy::{cpu::Interrupt, StepResult};
 use crate::registers::{ByteRegister as br, WordRegister as wr};
+use crate::testing::*;
 
 #[test]
 fn test_vblank_handling() -> StepResult<()> {
@@ -10,7 +10,6 @@ use crate::{
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
-use core::convert::TryFrom;
 
 use olympia_derive::OlympiaInstruction;
 
@@ -73,11 +72,8 @@ impl ExecutableInstruction for JumpRegister {
 
 fn relative_jump(gb: &mut GameBoy, offset: i8) {
     let pc = gb.read_register_u16(registers::WordRegister::PC);
-    let new_pc = if offset > 0 {
-        pc.wrapping_add(u16::try_from(offset).unwrap())
-    } else {
-        pc.wrapping_sub(u16::try_from(offset.abs()).unwrap())
-    };
+    // Sign extend the offset, so -128 wraps around like any other offset
+    let new_pc = pc.wrapping_add(i16::from(offset) as u16);
     gb.cycle();
     gb.set_pc(new_pc);
 }
@@ -1,5 +1,6 @@
-use crate::gameboy::{testutils::*, StepResult};
+use crate::gameboy::StepResult;
 use crate::registers;
+use crate::testing::*;
 
 #[test]
 fn test_jump() -> StepResult<()> {
@@ -1,5 +1,6 @@
 use crate::address;
 use crate::disasm::Disassemble;
+use crate::gameboy::oam_bug::Access;
 use crate::gameboy::{GameBoy, StepResult};
 use crate::instructions::{ByteRegisterOffset, Increment};
 use crate::instructionsn::{ExecutableInstruction, RuntimeOpcode};
@@ -72,7 +73,7 @@ pub(crate) struct IndirectA {
 impl ExecutableInstruction for IndirectA {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
         let value = gb.read_register_u8(self.src);
-        gb.write_memory_u8(self.dest, value)?;
+        gb.exec_write_memory_u8(self.dest, value)?;
         gb.cycle();
         Ok(())
     }
@@ -89,7 +90,7 @@ pub(crate) struct AIndirect {
 
 impl ExecutableInstruction for AIndirect {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
-        let value = gb.read_memory_u8(self.src)?;
+        let value = gb.exec_read_memory_u8(self.src)?;
         gb.cycle();
         gb.write_register_u8(self.dest, value);
         Ok(())
@@ -108,7 +109,7 @@ pub(crate) struct HighOffsetA {
 impl ExecutableInstruction for HighOffsetA {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
         let value = gb.read_register_u8(self.src);
-        gb.write_memory_u8(self.dest, value)?;
+        gb.exec_write_memory_u8(self.dest, value)?;
         gb.cycle();
         Ok(())
     }
@@ -125,7 +126,7 @@ pub(crate) struct AHighOffset {
 
 impl ExecutableInstruction for AHighOffset {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
-        let value = gb.read_memory_u8(self.src)?;
+        let value = gb.exec_read_memory_u8(self.src)?;
         gb.cycle();
         gb.write_register_u8(self.dest, value);
         Ok(())
@@ -145,7 +146,7 @@ impl ExecutableInstruction for RegisterOffsetA {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
         let addr = address::HighAddress(gb.read_register_u8(self.dest.into()));
         let value = gb.read_register_u8(self.src);
-        gb.write_memory_u8(addr, value)?;
+        gb.exec_write_memory_u8(addr, value)?;
         gb.cycle();
         Ok(())
     }
@@ -163,7 +164,7 @@ pub(crate) struct ARegisterOffset {
 impl ExecutableInstruction for ARegisterOffset {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
         let addr = address::HighAddress(gb.read_register_u8(self.src.into()));
-        let value = gb.read_memory_u8(addr)?;
+        let value = gb.exec_read_memory_u8(addr)?;
         gb.cycle();
         gb.write_register_u8(self.dest, value);
         Ok(())
@@ -177,7 +178,8 @@ fn increment_16a(
     inc: Increment,
 ) -> StepResult<()> {
     let addr = gb.read_register_u16(dest);
-    gb.write_memory_u8(addr, gb.read_register_u8(src))?;
+    gb.exec_write_memory_u8(addr, gb.read_register_u8(src))?;
+    gb.oam_bug_trigger(addr, Access::Write);
     let new_addr = match inc {
         Increment::Increment => addr.wrapping_add(1),
         Increment::Decrement => addr.wrapping_sub(1),
@@ -194,7 +196,8 @@ fn a_increment_16(
     inc: Increment,
 ) -> StepResult<()> {
     let addr = gb.read_register_u16(src);
-    let value = gb.read_memory_u8(addr)?;
+    let value = gb.exec_read_memory_u8(addr)?;
+    gb.oam_bug_trigger(addr, Access::Read);
     let new_addr = match inc {
         Increment::Increment => addr.wrapping_add(1),
         Increment::Decrement => addr.wrapping_sub(1),
@@ -206,7 +209,7 @@ fn a_increment_16(
 }
 
 #[derive(Debug, OlympiaInstruction)]
-#[olympia(opcode = 0x0010_0010, label = "INC", nodisasm)]
+#[olympia(opcode = 0x0010_0010, label = "LD", nodisasm)]
 pub(crate) struct Increment16A {
     #[olympia(src, constant(ByteRegister::A))]
     src: ByteRegister,
@@ -227,7 +230,7 @@ impl Disassemble for Increment16A {
 }
 
 #[derive(Debug, OlympiaInstruction)]
-#[olympia(opcode = 0x0011_0010, label = "DEC", nodisasm)]
+#[olympia(opcode = 0x0011_0010, label = "LD", nodisasm)]
 pub(crate) struct Decrement16A {
     #[olympia(src, constant(ByteRegister::A))]
     src: ByteRegister,
@@ -248,7 +251,7 @@ impl Disassemble for Decrement16A {
 }
 
 #[derive(Debug, OlympiaInstruction)]
-#[olympia(opcode = 0x0010_1010, label = "INC", nodisasm)]
+#[olympia(opcode = 0x0010_1010, label = "LD", nodisasm)]
 pub(crate) struct AIncrement16 {
     #[olympia(dest, constant(ByteRegister::A))]
     dest: ByteRegister,
@@ -269,7 +272,7 @@ impl Disassemble for AIncrement16 {
 }
 
 #[derive(Debug, OlympiaInstruction)]
-#[olympia(opcode = 0x0011_1010, label = "DEC", nodisasm)]
+#[olympia(opcode = 0x0011_1010, label = "LD", nodisasm)]
 pub(crate) struct ADecrement16 {
     #[olympia(dest, constant(ByteRegister::A))]
     dest: ByteRegister,
@@ -301,7 +304,7 @@ pub(crate) struct AWordTarget {
 impl ExecutableInstruction for AWordTarget {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
         let addr = gb.read_register_u16(self.src.into());
-        let value = gb.read_memory_u8(addr)?;
+        let value = gb.exec_read_memory_u8(addr)?;
         gb.cpu.write_register_u8(self.dest, value);
         gb.cycle();
         Ok(())
@@ -327,7 +330,7 @@ impl ExecutableInstruction for WordTargetA {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
         let value = gb.read_register_u8(self.src);
         let target_addr = gb.read_register_u16(self.dest.into());
-        gb.write_memory_u8(target_addr, value)?;
+        gb.exec_write_memory_u8(target_addr, value)?;
         gb.cycle();
         Ok(())
     }
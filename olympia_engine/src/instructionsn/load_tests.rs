@@ -1,4 +1,5 @@
-use crate::gameboy::{testutils::*, StepResult};
+use crate::gameboy::StepResult;
+use crate::testing::*;
 
 use crate::registers::{ByteRegister as br, WordRegister as wr};
 
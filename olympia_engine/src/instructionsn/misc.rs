@@ -180,89 +180,44 @@ impl ExecutableInstruction for InvertA {
 #[olympia(opcode = 0x0010_0111, label = "DAA")]
 struct AToBCD {}
 
-impl AToBCD {
-    #[allow(clippy::collapsible_if)]
-    fn after_add(carry: bool, half_carry: bool, top_nibble: u8, bottom_nibble: u8) -> (u8, bool) {
-        match (carry, half_carry) {
-            (false, false) => {
-                if bottom_nibble <= 9 {
-                    if top_nibble <= 9 {
-                        (0, false)
-                    } else {
-                        (0x60, true)
-                    }
-                } else if top_nibble <= 8 {
-                    (6, false)
-                } else {
-                    (0x66, true)
-                }
-            }
-            (false, true) => {
-                if bottom_nibble <= 0x3 {
-                    if top_nibble <= 0x9 {
-                        (0x6, false)
-                    } else {
-                        (0x66, true)
-                    }
-                } else {
-                    (0, carry)
-                }
-            }
-            (true, false) => {
-                if top_nibble <= 2 {
-                    if bottom_nibble <= 9 {
-                        (0x60, true)
-                    } else {
-                        (0x66, true)
-                    }
-                } else {
-                    (0, carry)
-                }
-            }
-            (true, true) => {
-                if top_nibble <= 3 && bottom_nibble <= 3 {
-                    (0x66, true)
-                } else {
-                    (0, carry)
-                }
-            }
-        }
+/// Adjust the result of adding or subtracting two BCD numbers back into BCD
+///
+/// `add_subtract`, `half_carry` and `carry` are the flags left by the addition
+/// or subtraction. Returns the adjusted value and the new carry flag. Every one
+/// of the 256 values and 8 flag combinations gives a result, including ones
+/// which aren't valid BCD, matching hardware.
+pub(crate) fn decimal_adjust(
+    value: u8,
+    add_subtract: bool,
+    half_carry: bool,
+    carry: bool,
+) -> (u8, bool) {
+    let mut correction = 0;
+    let mut carry_out = carry;
+    if half_carry || (!add_subtract && value & 0x0F > 0x09) {
+        correction |= 0x06;
     }
-
-    #[allow(clippy::collapsible_if)]
-    fn after_sub(carry: bool, half_carry: bool, top_nibble: u8, bottom_nibble: u8) -> (u8, bool) {
-        if carry {
-            if top_nibble >= 7 && bottom_nibble <= 9 && !half_carry {
-                (0xA0, true)
-            } else if top_nibble >= 6 && bottom_nibble >= 6 && half_carry {
-                (0x9A, true)
-            } else {
-                (0, carry)
-            }
-        } else if top_nibble <= 9 && bottom_nibble <= 9 && !half_carry {
-            (0, false)
-        } else if top_nibble <= 8 && bottom_nibble >= 6 && half_carry {
-            (0xFA, false)
-        } else {
-            (0, carry)
-        }
+    if carry || (!add_subtract && value > 0x99) {
+        correction |= 0x60;
+        carry_out = true;
     }
+    let adjusted = if add_subtract {
+        value.wrapping_sub(correction)
+    } else {
+        value.wrapping_add(correction)
+    };
+    (adjusted, carry_out)
 }
 
 impl ExecutableInstruction for AToBCD {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
-        let carry = gb.read_flag(registers::Flag::Carry);
-        let half_carry = gb.read_flag(registers::Flag::HalfCarry);
-        let add_subtract = gb.read_flag(registers::Flag::AddSubtract);
         let val = gb.read_register_u8(registers::ByteRegister::A);
-        let top_nibble = (val & 0xF0) >> 4;
-        let bottom_nibble = val & 0x0F;
-        let (add, carry) = if add_subtract {
-            AToBCD::after_sub(carry, half_carry, top_nibble, bottom_nibble)
-        } else {
-            AToBCD::after_add(carry, half_carry, top_nibble, bottom_nibble)
-        };
-        let result = val.wrapping_add(add);
+        let (result, carry) = decimal_adjust(
+            val,
+            gb.read_flag(registers::Flag::AddSubtract),
+            gb.read_flag(registers::Flag::HalfCarry),
+            gb.read_flag(registers::Flag::Carry),
+        );
         gb.write_register_u8(registers::ByteRegister::A, result);
         gb.set_flag_to(registers::Flag::Carry, carry);
         gb.set_flag_to(registers::Flag::Zero, result == 0);
@@ -289,7 +244,7 @@ struct Stop {}
 
 impl ExecutableInstruction for Stop {
     fn execute(&self, gb: &mut GameBoy) -> StepResult<()> {
-        gb.set_power_saving_mode(PowerSavingMode::Stop);
+        gb.stop();
         Ok(())
     }
 }
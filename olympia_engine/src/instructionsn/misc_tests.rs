@@ -1,6 +1,7 @@
 #![allow(clippy::bool_assert_comparison)]
 
-use crate::gameboy::{testutils::*, StepResult};
+use crate::gameboy::StepResult;
+use crate::testing::*;
 
 use crate::registers;
 use crate::registers::ByteRegister as br;
@@ -252,8 +253,8 @@ fn test_daa_add() {
     assert_add_daa(0x80, 0x90, true, 0x70);
     // carry, decimal only half carry
     assert_add_daa(0x85, 0x95, true, 0x80);
-    // out of bcd range carry
-    assert_add_daa(0x89, 0x3F, false, 0xC8);
+    // out of bcd range, adjusted for both the half carry and the high nibble
+    assert_add_daa(0x89, 0x3F, true, 0x2E);
     // carry, half carry
     assert_add_daa(0x99, 0x99, true, 0x98);
 }
@@ -269,3 +270,93 @@ fn test_add_sub() {
     assert_sub_daa(0x02, 0x95, true, 0x07);
     assert_sub_daa(0x05, 0x92, true, 0x13);
 }
+
+/// DAA as described for other hardware-accurate emulators, to check against
+fn reference_daa(af: u16) -> u16 {
+    let [flags, a] = af.to_le_bytes();
+    let add_subtract = flags & 0x40 != 0;
+    let half_carry = flags & 0x20 != 0;
+    let mut carry = flags & 0x10 != 0;
+    let mut result = u16::from(a);
+    if add_subtract {
+        if half_carry {
+            result = result.wrapping_sub(0x06) & 0xFF;
+        }
+        if carry {
+            result = result.wrapping_sub(0x60);
+        }
+    } else {
+        if half_carry || result & 0x0F > 0x09 {
+            result += 0x06;
+        }
+        if carry || result > 0x9F {
+            result += 0x60;
+        }
+    }
+    if result & 0x100 != 0 {
+        carry = true;
+    }
+    let result = (result & 0xFF) as u8;
+    let mut flags_out = flags & 0x40;
+    if result == 0 {
+        flags_out |= 0x80;
+    }
+    if carry {
+        flags_out |= 0x10;
+    }
+    u16::from_le_bytes([flags_out, result])
+}
+
+#[test]
+fn test_daa_every_state() -> StepResult<()> {
+    let mut gb = run_program(0, &[0x27])?; // DAA
+    for a in 0..=0xFFu16 {
+        for flags in 0..8u16 {
+            let af = a << 8 | flags << 4;
+            gb.write_register_u16(registers::WordRegister::PC, PROGRAM_START);
+            gb.write_register_u16(registers::WordRegister::AF, af);
+            gb.step()?;
+
+            assert_eq!(
+                gb.read_register_u16(registers::WordRegister::AF),
+                reference_daa(af),
+                "DAA with AF = 0x{:04X}",
+                af
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_daa_bcd_arithmetic() {
+    use crate::instructionsn::misc::decimal_adjust;
+
+    let to_bcd = |value: u8| ((value / 10) << 4) | (value % 10);
+    for x in 0..100u8 {
+        for y in 0..100u8 {
+            let (a, b) = (to_bcd(x), to_bcd(y));
+            let sum = a.wrapping_add(b);
+            let half_carry = (a & 0x0F) + (b & 0x0F) > 0x0F;
+            let carry = u16::from(a) + u16::from(b) > 0xFF;
+            assert_eq!(
+                decimal_adjust(sum, false, half_carry, carry),
+                (to_bcd((x + y) % 100), x + y >= 100),
+                "{} + {}",
+                x,
+                y
+            );
+
+            let difference = a.wrapping_sub(b);
+            let half_carry = a & 0x0F < b & 0x0F;
+            let carry = a < b;
+            assert_eq!(
+                decimal_adjust(difference, true, half_carry, carry),
+                (to_bcd((100 + x - y) % 100), x < y),
+                "{} - {}",
+                x,
+                y
+            );
+        }
+    }
+}
@@ -0,0 +1,446 @@
+use super::{RuntimeDecoder, RuntimeOpcode};
+use crate::gameboy::cpu::{InterruptState, PowerSavingMode};
+use crate::gameboy::{GameBoy, GameBoyModel};
+use crate::registers::{Flag, WordRegister};
+use crate::rom::Cartridge;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use olympia_core::instructions::{
+    AppendedParam, ConstantParam, ExtensionType, InnerParam, InstructionDefinition,
+    ParamDefinition, ParamPosition, ParamType,
+};
+
+/// Where opcodes are placed in memory while timing them
+const TIMING_ADDRESS: u16 = 0xC000;
+/// Memory pointed to by registers while timing opcodes, so they don't touch the opcode
+const TIMING_DATA_ADDRESS: u16 = 0xD000;
+const TIMING_STACK_ADDRESS: u16 = 0xDFF0;
+/// Stack pointer while finding flag effects, with a low nibble which
+/// half carries when offsets are added to it
+const FLAGS_STACK_ADDRESS: u16 = 0xDFF8;
+/// Values given to registers, parameters and memory while finding flag effects
+const FLAG_TEST_VALUES: [u8; 7] = [0x00, 0x01, 0x0F, 0x10, 0x80, 0x99, 0xFF];
+
+/// Number of M-cycles an opcode takes to run
+///
+/// Conditional jumps, calls and returns take longer when the condition is met,
+/// so `min` and `max` differ for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleCount {
+    pub min: u8,
+    pub max: u8,
+}
+
+impl fmt::Display for CycleCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}-{}", self.min, self.max)
+        }
+    }
+}
+
+/// How running an opcode changes a flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagEffect {
+    Unchanged,
+    /// Always cleared
+    Reset,
+    /// Always set
+    Set,
+    /// Set or cleared depending on the result
+    Modified,
+}
+
+impl FlagEffect {
+    /// Work out the effect from the flag before and after each run
+    fn from_samples(samples: impl Iterator<Item = (bool, bool)>) -> FlagEffect {
+        let (mut unchanged, mut always_set, mut always_clear) = (true, true, true);
+        for (before, after) in samples {
+            unchanged &= before == after;
+            always_set &= after;
+            always_clear &= !after;
+        }
+        if unchanged {
+            FlagEffect::Unchanged
+        } else if always_set {
+            FlagEffect::Set
+        } else if always_clear {
+            FlagEffect::Reset
+        } else {
+            FlagEffect::Modified
+        }
+    }
+}
+
+/// How running an opcode changes each flag
+///
+/// This displays in the usual `Z N H C` style, with `-` for unchanged flags,
+/// `0` and `1` for flags which are always reset or set, and the flag's letter
+/// for flags which depend on the result, such as `Z - 0 C` for `DAA`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlagEffects {
+    pub zero: FlagEffect,
+    pub add_subtract: FlagEffect,
+    pub half_carry: FlagEffect,
+    pub carry: FlagEffect,
+}
+
+impl fmt::Display for FlagEffects {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (self.zero, 'Z'),
+            (self.add_subtract, 'N'),
+            (self.half_carry, 'H'),
+            (self.carry, 'C'),
+        ];
+        for (i, (effect, letter)) in flags.iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            let symbol = match effect {
+                FlagEffect::Unchanged => '-',
+                FlagEffect::Reset => '0',
+                FlagEffect::Set => '1',
+                FlagEffect::Modified => *letter,
+            };
+            write!(f, "{}", symbol)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single opcode of an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpcodeReference {
+    /// The opcode, following the CB prefix for extended instructions
+    pub opcode: u8,
+    /// How the disassembler shows this opcode, with zero for any parameters
+    /// that follow it
+    pub disassembly: String,
+    pub cycles: CycleCount,
+    /// How the flags changed when running this opcode with a range of
+    /// register and memory values
+    pub flags: FlagEffects,
+}
+
+/// An instruction the emulator can run, and details of each of its opcodes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionReference {
+    pub definition: &'static InstructionDefinition,
+    /// Every opcode of this instruction, in the same order as `definition.opcodes`
+    pub opcodes: Vec<OpcodeReference>,
+}
+
+impl InstructionReference {
+    /// The instruction with placeholders for its parameters, such as `LD r8, n8`
+    ///
+    /// Placeholders are `r8` and `r16` for registers, `n8` and `n16` for
+    /// literals, `a8` and `a16` for addresses, `e8` for signed offsets,
+    /// `cc` for conditions and `u3` for bit numbers and reset vectors.
+    ///
+    /// Parameters are shown as they are stored, so memory accessed through a
+    /// register such as `LD (BC), A` shows as `LD r16, A`. Use the
+    /// disassembly of each opcode to see how it is written.
+    pub fn syntax(&self) -> String {
+        let params = self.definition.params;
+        let param = |pos| {
+            params
+                .iter()
+                .find(|param: &&ParamDefinition| param.pos == pos)
+                .map(|param| param_placeholder(param.param_type))
+        };
+        let label = self.definition.label;
+        match (
+            param(ParamPosition::Single),
+            param(ParamPosition::Dest),
+            param(ParamPosition::Src),
+            param(ParamPosition::AddSrc),
+        ) {
+            (Some(single), _, _, _) => format!("{} {}", label, single),
+            (None, Some(dest), Some(src), Some(addsrc)) => {
+                format!("{} {}, {} + {}", label, dest, src, addsrc)
+            }
+            (None, Some(dest), Some(src), None) => format!("{} {}, {}", label, dest, src),
+            (None, Some(param), None, _) | (None, None, Some(param), _) => {
+                format!("{} {}", label, param)
+            }
+            _ => String::from(label),
+        }
+    }
+}
+
+fn param_placeholder(param_type: ParamType) -> String {
+    match param_type {
+        ParamType::Appended(appended) => String::from(match appended {
+            AppendedParam::LiteralAddress => "a16",
+            AppendedParam::HighAddress => "a8",
+            AppendedParam::AddressOffset | AppendedParam::LiteralSigned8 => "e8",
+            AppendedParam::Literal16 => "n16",
+            AppendedParam::Literal8 => "n8",
+        }),
+        ParamType::Inner { ty, .. } => String::from(match ty {
+            InnerParam::ByteRegisterTarget => "r8",
+            InnerParam::AccRegister | InnerParam::StackRegister => "r16",
+            InnerParam::Condition => "cc",
+            InnerParam::Literal8 => "u3",
+            InnerParam::ALOp => "op",
+            InnerParam::Increment => "inc",
+            InnerParam::RotateDirection => "dir",
+            InnerParam::Carry => "carry",
+        }),
+        ParamType::Constant(constant) => match constant {
+            ConstantParam::ByteRegister(reg) => format!("{:?}", reg),
+            ConstantParam::WordRegister(reg) => format!("{:?}", reg),
+            ConstantParam::ByteRegisterOffset(reg) => format!("[{:?}]", reg),
+            ConstantParam::LiteralAddress(addr) => format!("{:X}h", addr.0),
+        },
+    }
+}
+
+/// Run a single opcode with the given flags, and count the cycles it took
+fn time_opcode(extension_type: ExtensionType, opcode: u8, flags: u8) -> u8 {
+    let cartridge = Cartridge::from_data(vec![0u8; 0x8000]).expect("Blank ROMs are valid");
+    let mut gb = GameBoy::new(cartridge, GameBoyModel::GameBoy);
+    let mut bytes = Vec::new();
+    if extension_type == ExtensionType::Extended {
+        bytes.push(0xCB);
+    }
+    bytes.push(opcode);
+    // Zeroed parameters, so jumps and loads don't depend on leftover memory
+    bytes.extend_from_slice(&[0, 0]);
+    gb.set_memory_chunk(TIMING_ADDRESS, &bytes)
+        .expect("Work RAM is writable");
+    gb.write_register_u16(WordRegister::PC, TIMING_ADDRESS);
+    gb.write_register_u16(WordRegister::SP, TIMING_STACK_ADDRESS);
+    for reg in &[WordRegister::BC, WordRegister::DE, WordRegister::HL] {
+        gb.write_register_u16(*reg, TIMING_DATA_ADDRESS);
+    }
+    gb.write_register_u16(WordRegister::AF, u16::from(flags));
+
+    let start = gb.clocks_elapsed();
+    gb.step()
+        .expect("Every decodable opcode should run from work RAM");
+    ((gb.clocks_elapsed() - start) / 4) as u8
+}
+
+/// Run an opcode with each combination of test values and flags, and see how the flags change
+fn find_flag_effects(extension_type: ExtensionType, opcode: u8) -> FlagEffects {
+    let cartridge = Cartridge::from_data(vec![0u8; 0x8000]).expect("Blank ROMs are valid");
+    let mut gb = GameBoy::new(cartridge, GameBoyModel::GameBoy);
+    let mut samples = Vec::new();
+    for &a in FLAG_TEST_VALUES.iter() {
+        for &value in FLAG_TEST_VALUES.iter() {
+            for &flags in [0x00, 0xF0].iter() {
+                // Undo anything the last run did which would stop this one running the opcode
+                gb.set_interrupt_state(InterruptState::Disabled);
+                gb.set_power_saving_mode(PowerSavingMode::None);
+                let mut bytes = Vec::new();
+                if extension_type == ExtensionType::Extended {
+                    bytes.push(0xCB);
+                }
+                bytes.extend_from_slice(&[opcode, value, value]);
+                gb.set_memory_chunk(TIMING_ADDRESS, &bytes)
+                    .expect("Work RAM is writable");
+                let word = u16::from_le_bytes([value, value]);
+                // Memory outside RAM may not be writable, which is fine
+                let _ = gb.set_memory_chunk(word, &[value]);
+                let _ = gb.set_memory_chunk(FLAGS_STACK_ADDRESS, &[value, value]);
+                gb.write_register_u16(WordRegister::PC, TIMING_ADDRESS);
+                gb.write_register_u16(WordRegister::SP, FLAGS_STACK_ADDRESS);
+                for reg in &[WordRegister::BC, WordRegister::DE, WordRegister::HL] {
+                    gb.write_register_u16(*reg, word);
+                }
+                gb.write_register_u16(WordRegister::AF, u16::from_le_bytes([flags, a]));
+                let before = gb.read_register_u16(WordRegister::AF);
+                if gb.step().is_ok() {
+                    samples.push((before, gb.read_register_u16(WordRegister::AF)));
+                }
+            }
+        }
+    }
+    let effect = |flag: Flag| {
+        let mask = 1 << flag.bit();
+        FlagEffect::from_samples(
+            samples
+                .iter()
+                .map(|(before, after)| (before & mask != 0, after & mask != 0)),
+        )
+    };
+    FlagEffects {
+        zero: effect(Flag::Zero),
+        add_subtract: effect(Flag::AddSubtract),
+        half_carry: effect(Flag::HalfCarry),
+        carry: effect(Flag::Carry),
+    }
+}
+
+fn opcode_reference(
+    extension_type: ExtensionType,
+    opcode: u8,
+    runtime_opcode: &dyn RuntimeOpcode,
+) -> OpcodeReference {
+    let disassembly = runtime_opcode
+        .to_instruction(&mut core::iter::repeat(0))
+        .disassemble();
+    // With no flags set NZ and NC are met, and with all set Z and C are met
+    let clear = time_opcode(extension_type, opcode, 0x00);
+    let set = time_opcode(extension_type, opcode, 0xF0);
+    OpcodeReference {
+        opcode,
+        disassembly,
+        cycles: CycleCount {
+            min: clear.min(set),
+            max: clear.max(set),
+        },
+        flags: find_flag_effects(extension_type, opcode),
+    }
+}
+
+/// Every instruction the emulator can run, with the cycles each opcode takes and
+/// how it changes the flags
+///
+/// This is generated from the instructions the emulator decodes, and timings and
+/// flag effects are measured by running each opcode, so it always matches how instructions
+/// are emulated. Every opcode is run several times, so this is fairly slow.
+/// Instructions are ordered by their first opcode, with CB
+/// prefixed instructions last.
+pub fn instruction_set() -> Vec<InstructionReference> {
+    let decoder = RuntimeDecoder::shared();
+    let standard = (0..=0xFF).filter_map(|value| decoder.decode(value));
+    let extended = (0..=0xFF).map(|value| decoder.decode_extended(value));
+    let mut definitions: Vec<&'static InstructionDefinition> = Vec::new();
+    for opcode in standard.chain(extended) {
+        let definition = opcode.instruction_definition();
+        if !definitions.contains(&definition) {
+            definitions.push(definition);
+        }
+    }
+
+    definitions
+        .into_iter()
+        .map(|definition| {
+            let opcodes = definition
+                .opcodes
+                .iter()
+                .map(|&opcode| {
+                    let runtime_opcode = match definition.extension_type {
+                        ExtensionType::None => decoder
+                            .decode(opcode)
+                            .expect("Every defined opcode is decodable"),
+                        ExtensionType::Extended => decoder.decode_extended(opcode),
+                    };
+                    opcode_reference(definition.extension_type, opcode, runtime_opcode)
+                })
+                .collect();
+            InstructionReference {
+                definition,
+                opcodes,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn find<'a>(
+        instructions: &'a [InstructionReference],
+        syntax: &str,
+    ) -> &'a InstructionReference {
+        instructions
+            .iter()
+            .find(|instruction| instruction.syntax() == syntax)
+            .unwrap_or_else(|| panic!("No instruction {}", syntax))
+    }
+
+    fn find_opcode(instruction: &InstructionReference, opcode: u8) -> &OpcodeReference {
+        instruction
+            .opcodes
+            .iter()
+            .find(|reference| reference.opcode == opcode)
+            .unwrap_or_else(|| panic!("No opcode {:02X}", opcode))
+    }
+
+    #[test]
+    fn test_instruction_set() {
+        let instructions = instruction_set();
+        let opcode_count = |extension_type| -> usize {
+            instructions
+                .iter()
+                .filter(|instruction| instruction.definition.extension_type == extension_type)
+                .map(|instruction| instruction.opcodes.len())
+                .sum()
+        };
+
+        // 11 opcodes are unused, and 0xCB is the prefix for extended opcodes
+        assert_eq!(opcode_count(ExtensionType::None), 244);
+        assert_eq!(opcode_count(ExtensionType::Extended), 256);
+        assert_eq!(instructions[0].syntax(), "NOP");
+        assert_eq!(
+            instructions[0].opcodes,
+            vec![OpcodeReference {
+                opcode: 0x00,
+                disassembly: String::from("NOP"),
+                cycles: CycleCount { min: 1, max: 1 },
+                flags: FlagEffects {
+                    zero: FlagEffect::Unchanged,
+                    add_subtract: FlagEffect::Unchanged,
+                    half_carry: FlagEffect::Unchanged,
+                    carry: FlagEffect::Unchanged,
+                },
+            }]
+        );
+
+        let jump = find_opcode(find(&instructions, "JP cc, a16"), 0xC2);
+        assert_eq!(jump.disassembly, "JP NZ, $0h");
+        assert_eq!(jump.cycles, CycleCount { min: 3, max: 4 });
+        assert_eq!(jump.cycles.to_string(), "3-4");
+
+        let load = find(&instructions, "LD r8, r8");
+        assert_eq!(
+            find_opcode(load, 0x41).cycles,
+            CycleCount { min: 1, max: 1 }
+        );
+        assert_eq!(
+            find_opcode(load, 0x46).cycles,
+            CycleCount { min: 2, max: 2 }
+        );
+
+        let bit = find_opcode(find(&instructions, "BIT u3, r8"), 0x46);
+        assert_eq!(bit.disassembly, "BIT 0h, (HL)");
+        assert_eq!(bit.cycles, CycleCount { min: 3, max: 3 });
+
+        assert_eq!(
+            find(&instructions, "LD HL, SP + e8").opcodes[0].cycles,
+            CycleCount { min: 3, max: 3 }
+        );
+    }
+
+    #[test]
+    fn test_flag_effects() {
+        let instructions = instruction_set();
+        let flags = |syntax: &str| find(&instructions, syntax).opcodes[0].flags.to_string();
+
+        assert_eq!(flags("DAA"), "Z - 0 C");
+        assert_eq!(flags("SCF"), "- 0 0 1");
+        assert_eq!(flags("CCF"), "- 0 0 C");
+        assert_eq!(flags("CPL"), "- 1 1 -");
+        assert_eq!(flags("AND r8"), "Z 0 1 0");
+        assert_eq!(flags("SUB n8"), "Z 1 H C");
+        assert_eq!(flags("BIT u3, r8"), "Z 0 1 -");
+        assert_eq!(flags("LD r8, r8"), "- - - -");
+        assert_eq!(flags("POP r16"), "- - - -");
+        assert_eq!(
+            find_opcode(find(&instructions, "POP r16"), 0xF1)
+                .flags
+                .to_string(),
+            "Z N H C"
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Runs single instruction CPU test vectors, checking each machine cycle
+//!
+//! Vectors use the JSON layout of the SM83 test suites: a list of tests,
+//! each with an `initial` and `final` CPU state and the bus activity of every
+//! machine cycle in `cycles`. The initial `pc` points at the opcode, so the
+//! first cycle is the opcode fetch. Each cycle is either `null` for an
+//! internal cycle, or `[address, value, pins]` where pins mark a read with `r`
+//! or a write with `w`.
+//!
+//! The vectors are run against a flat 64KiB bus rather than the Game Boy memory
+//! map, as they place code and data at any address.
+//!
+//! A small set of vectors is kept inline. To run a full suite, point
+//! `OLYMPIA_SM83_TESTS` at a directory of its JSON files, one per opcode.
+//! `OLYMPIA_SM83_OPCODES` can limit this to a comma separated list of opcodes,
+//! such as `00,CB 37`.
+
+use crate::gameboy::{cpu::InterruptState, GameBoy};
+use crate::gameboy::{GameBoyModel, MicroOp};
+use crate::registers::{ByteRegister as br, WordRegister as wr};
+use crate::testing::make_cartridge_with;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde_json::Value;
+
+const INLINE_VECTORS: &str = r#"[
+    {
+        "name": "00 nop",
+        "initial": {
+            "pc": 49152, "sp": 65534, "a": 1, "b": 2, "c": 3, "d": 4,
+            "e": 5, "f": 176, "h": 6, "l": 7, "ime": 0,
+            "ram": [[49152, 0]]
+        },
+        "final": {
+            "pc": 49153, "sp": 65534, "a": 1, "b": 2, "c": 3, "d": 4,
+            "e": 5, "f": 176, "h": 6, "l": 7, "ime": 0,
+            "ram": [[49152, 0]]
+        },
+        "cycles": [[49152, 0, "r-m"]]
+    },
+    {
+        "name": "01 ld bc, d16",
+        "initial": {
+            "pc": 256, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0,
+            "e": 0, "f": 0, "h": 0, "l": 0,
+            "ram": [[256, 1], [257, 52], [258, 18]]
+        },
+        "final": {
+            "pc": 259, "sp": 65534, "a": 0, "b": 18, "c": 52, "d": 0,
+            "e": 0, "f": 0, "h": 0, "l": 0,
+            "ram": [[256, 1], [257, 52], [258, 18]]
+        },
+        "cycles": [[256, 1, "r-m"], [257, 52, "r-m"], [258, 18, "r-m"]]
+    },
+    {
+        "name": "03 inc bc",
+        "initial": {
+            "pc": 4660, "sp": 65534, "a": 0, "b": 0, "c": 255, "d": 0,
+            "e": 0, "f": 80, "h": 0, "l": 0,
+            "ram": [[4660, 3]]
+        },
+        "final": {
+            "pc": 4661, "sp": 65534, "a": 0, "b": 1, "c": 0, "d": 0,
+            "e": 0, "f": 80, "h": 0, "l": 0,
+            "ram": [[4660, 3]]
+        },
+        "cycles": [[4660, 3, "r-m"], null]
+    },
+    {
+        "name": "77 ld (hl), a",
+        "initial": {
+            "pc": 49152, "sp": 65534, "a": 90, "b": 0, "c": 0, "d": 0,
+            "e": 0, "f": 0, "h": 208, "l": 0,
+            "ram": [[49152, 119], [53248, 0]]
+        },
+        "final": {
+            "pc": 49153, "sp": 65534, "a": 90, "b": 0, "c": 0, "d": 0,
+            "e": 0, "f": 0, "h": 208, "l": 0,
+            "ram": [[49152, 119], [53248, 90]]
+        },
+        "cycles": [[49152, 119, "r-m"], [53248, 90, "-wm"]]
+    },
+    {
+        "name": "c1 pop bc",
+        "initial": {
+            "pc": 49152, "sp": 57328, "a": 0, "b": 0, "c": 0, "d": 0,
+            "e": 0, "f": 0, "h": 0, "l": 0,
+            "ram": [[49152, 193], [57328, 52], [57329, 18]]
+        },
+        "final": {
+            "pc": 49153, "sp": 57330, "a": 0, "b": 18, "c": 52, "d": 0,
+            "e": 0, "f": 0, "h": 0, "l": 0,
+            "ram": [[49152, 193], [57328, 52], [57329, 18]]
+        },
+        "cycles": [[49152, 193, "r-m"], [57328, 52, "r-m"], [57329, 18, "r-m"]]
+    },
+    {
+        "name": "cb c6 set 0, (hl)",
+        "initial": {
+            "pc": 40000, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0,
+            "e": 0, "f": 0, "h": 208, "l": 0,
+            "ram": [[40000, 203], [40001, 198], [53248, 16]]
+        },
+        "final": {
+            "pc": 40002, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0,
+            "e": 0, "f": 0, "h": 208, "l": 0,
+            "ram": [[40000, 203], [40001, 198], [53248, 17]]
+        },
+        "cycles": [
+            [40000, 203, "r-m"], [40001, 198, "r-m"],
+            [53248, 16, "r-m"], [53248, 17, "-wm"]
+        ]
+    }
+]"#;
+
+const BYTE_REGISTERS: [(&str, br); 8] = [
+    ("a", br::A),
+    ("b", br::B),
+    ("c", br::C),
+    ("d", br::D),
+    ("e", br::E),
+    ("f", br::F),
+    ("h", br::H),
+    ("l", br::L),
+];
+
+fn field(state: &Value, name: &str) -> Result<u64, String> {
+    state[name]
+        .as_u64()
+        .ok_or_else(|| format!("missing or invalid field {}", name))
+}
+
+fn ram(state: &Value) -> Result<Vec<(u16, u8)>, String> {
+    let entries = state["ram"].as_array().ok_or("missing ram")?;
+    entries
+        .iter()
+        .map(|entry| match (entry[0].as_u64(), entry[1].as_u64()) {
+            (Some(address), Some(value)) => Ok((address as u16, value as u8)),
+            _ => Err(format!("invalid ram entry {}", entry)),
+        })
+        .collect()
+}
+
+/// The bus activity expected for a single cycle
+fn expected_cycle(cycle: &Value) -> Result<MicroOp, String> {
+    if cycle.is_null() {
+        return Ok(MicroOp::Internal);
+    }
+    let pins = cycle[2].as_str().unwrap_or("");
+    match (cycle[0].as_u64(), cycle[1].as_u64()) {
+        (Some(address), Some(value)) if pins.contains('r') => Ok(MicroOp::Read {
+            address: address as u16,
+            value: value as u8,
+        }),
+        (Some(address), Some(value)) if pins.contains('w') => Ok(MicroOp::Write {
+            address: address as u16,
+            value: value as u8,
+        }),
+        _ if !pins.contains('r') && !pins.contains('w') => Ok(MicroOp::Internal),
+        _ => Err(format!("invalid cycle {}", cycle)),
+    }
+}
+
+fn setup(initial: &Value) -> Result<GameBoy, String> {
+    let mut gb = GameBoy::new(make_cartridge_with(&[]), GameBoyModel::GameBoy);
+    gb.mem.flat_ram = Some(vec![0u8; 0x10000].into_boxed_slice());
+    gb.mem.restrict_ppu_access = false;
+    for (name, register) in BYTE_REGISTERS.iter() {
+        gb.write_register_u8(*register, field(initial, name)? as u8);
+    }
+    gb.write_register_u16(wr::PC, field(initial, "pc")? as u16);
+    gb.write_register_u16(wr::SP, field(initial, "sp")? as u16);
+    if initial["ime"].as_u64() == Some(1) {
+        gb.cpu.interrupts_enabled = InterruptState::Enabled;
+    }
+    let registers = gb.mem.registers_mut();
+    registers.ie = initial["ie"].as_u64().unwrap_or(0) as u8;
+    registers.iflag = 0;
+    for (address, value) in ram(initial)? {
+        gb.set_memory_u8(address, value)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(gb)
+}
+
+fn check_state(gb: &GameBoy, expected: &Value) -> Result<(), String> {
+    for (name, register) in BYTE_REGISTERS.iter() {
+        let actual = u64::from(gb.read_register_u8(*register));
+        let expected = field(expected, name)?;
+        if actual != expected {
+            return Err(format!(
+                "{} was {:02X}, expected {:02X}",
+                name, actual, expected
+            ));
+        }
+    }
+    for (name, register) in [("pc", wr::PC), ("sp", wr::SP)].iter() {
+        let actual = u64::from(gb.read_register_u16(*register));
+        let expected = field(expected, name)?;
+        if actual != expected {
+            return Err(format!(
+                "{} was {:04X}, expected {:04X}",
+                name, actual, expected
+            ));
+        }
+    }
+    if let Some(ime) = expected["ime"].as_u64() {
+        let enabled = gb.cpu.interrupts_enabled == InterruptState::Enabled;
+        if enabled != (ime == 1) {
+            return Err(format!("ime was {}, expected {}", enabled as u8, ime));
+        }
+    }
+    for (address, value) in ram(expected)? {
+        let actual = gb.get_memory_u8(address).map_err(|e| e.to_string())?;
+        if actual != value {
+            return Err(format!(
+                "{:04X} was {:02X}, expected {:02X}",
+                address, actual, value
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Run a single vector, describing the first difference found
+fn run_vector(vector: &Value) -> Result<(), String> {
+    let mut gb = setup(&vector["initial"])?;
+    let trace = gb.traced_step().map_err(|e| e.to_string())?;
+    let actual: Vec<MicroOp> = trace.into_iter().filter(MicroOp::takes_cycle).collect();
+    let expected = vector["cycles"]
+        .as_array()
+        .ok_or("missing cycles")?
+        .iter()
+        .map(expected_cycle)
+        .collect::<Result<Vec<_>, _>>()?;
+    if actual != expected {
+        return Err(format!("cycles were {:?}, expected {:?}", actual, expected));
+    }
+    check_state(&gb, &vector["final"])
+}
+
+/// Run every vector in `json`, returning how many ran and a description of each failure
+fn run_vectors(json: &str) -> (usize, Vec<String>) {
+    let vectors: Vec<Value> = match serde_json::from_str(json) {
+        Ok(vectors) => vectors,
+        Err(e) => return (0, vec![format!("invalid vectors: {}", e)]),
+    };
+    let failures = vectors
+        .iter()
+        .filter_map(|vector| {
+            run_vector(vector)
+                .err()
+                .map(|e| format!("{}: {}", vector["name"].as_str().unwrap_or("?"), e))
+        })
+        .collect();
+    (vectors.len(), failures)
+}
+
+/// The results of running the vectors for a single opcode
+#[cfg(feature = "std")]
+struct OpcodeReport {
+    opcode: String,
+    total: usize,
+    failures: Vec<String>,
+}
+
+#[cfg(feature = "std")]
+impl OpcodeReport {
+    /// Run the vectors in `path`, which suites name after the opcode they test
+    fn run(path: &std::path::Path) -> OpcodeReport {
+        let opcode = opcode_name(path);
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) => {
+                return OpcodeReport {
+                    opcode,
+                    total: 0,
+                    failures: vec![format!("could not read {}: {}", path.display(), e)],
+                }
+            }
+        };
+        let result = std::panic::catch_unwind(|| run_vectors(&json));
+        let (total, failures) = result.unwrap_or_else(|_| (0, vec![String::from("panicked")]));
+        OpcodeReport {
+            opcode,
+            total,
+            failures,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for OpcodeReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{}: {} of {} failed",
+            self.opcode,
+            self.failures.len(),
+            self.total
+        )?;
+        if let Some(first) = self.failures.first() {
+            write!(f, ", first: {}", first)?;
+        }
+        Ok(())
+    }
+}
+
+/// The opcode tested by the vectors in `path`, such as `CB 37` for `cb 37.json`
+#[cfg(feature = "std")]
+fn opcode_name(path: &std::path::Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_uppercase())
+        .unwrap_or_default()
+}
+
+/// Whether `opcode` was chosen with `OLYMPIA_SM83_OPCODES`, a comma separated list
+#[cfg(feature = "std")]
+fn opcode_selected(opcode: &str) -> bool {
+    match std::env::var("OLYMPIA_SM83_OPCODES") {
+        Ok(selected) => selected
+            .split(',')
+            .any(|selected| selected.trim().eq_ignore_ascii_case(opcode)),
+        Err(_) => true,
+    }
+}
+
+#[test]
+fn test_inline_vectors() {
+    let (total, failures) = run_vectors(INLINE_VECTORS);
+    assert_eq!(total, 6);
+    assert!(failures.is_empty(), "{:#?}", failures);
+}
+
+#[test]
+fn test_mismatched_cycles_reported() {
+    let vectors =
+        INLINE_VECTORS.replacen(r#"[[49152, 0, "r-m"]]"#, r#"[[49152, 0, "r-m"], null]"#, 1);
+    let (_, failures) = run_vectors(&vectors);
+    assert_eq!(failures.len(), 1);
+    assert!(failures[0].starts_with("00 nop: cycles were"));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_opcode_report() {
+    let dir = std::env::temp_dir().join(format!("olympia-sm83-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cb c6.json");
+    let vectors = INLINE_VECTORS.replacen("[53248, 17]]", "[53248, 18]]", 1);
+    std::fs::write(&path, vectors).unwrap();
+
+    let report = OpcodeReport::run(&path);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(report.opcode, "CB C6");
+    assert_eq!(report.total, 6);
+    assert_eq!(
+        report.to_string(),
+        "CB C6: 1 of 6 failed, first: cb c6 set 0, (hl): D000 was 11, expected 12"
+    );
+}
+
+/// Run a community SM83 suite, with a JSON file of vectors for each opcode
+///
+/// This only runs if `OLYMPIA_SM83_TESTS` is set to the suite's directory.
+/// Every opcode is reported with how many of its vectors failed, and the
+/// first failure.
+#[cfg(feature = "std")]
+#[test]
+fn test_vector_suite() {
+    let dir = match std::env::var_os("OLYMPIA_SM83_TESTS") {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => return,
+    };
+    let mut paths: Vec<_> = std::fs::read_dir(&dir)
+        .expect("Could not read test vector directory")
+        .map(|entry| entry.expect("Could not read test vector entry").path())
+        .filter(|path| path.extension() == Some("json".as_ref()))
+        .filter(|path| opcode_selected(&opcode_name(path)))
+        .collect();
+    paths.sort();
+    let reports: Vec<OpcodeReport> = paths.iter().map(|path| OpcodeReport::run(path)).collect();
+    let failed: Vec<String> = reports
+        .iter()
+        .filter(|report| !report.failures.is_empty())
+        .map(|report| report.to_string())
+        .collect();
+    assert!(
+        failed.is_empty(),
+        "{} of {} opcodes failed:\n{}",
+        failed.len(),
+        reports.len(),
+        failed.join("\n")
+    );
+}
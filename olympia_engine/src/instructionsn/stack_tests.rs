@@ -1,6 +1,7 @@
 #![allow(clippy::bool_assert_comparison)]
 
-use crate::gameboy::{testutils::*, StepResult};
+use crate::gameboy::StepResult;
+use crate::testing::*;
 
 use crate::registers;
 
@@ -11,10 +11,31 @@
 //! * `std` - This feature can be enabled in a `std` environment to enable niceties
 //!   like `Display`/`Error` implementations on error types.
 //! * `disassembler` - This feature can be enabled in any environment to enable support
-//!   for dissambling gameboy instructions.
+//!   for dissambling gameboy instructions. Enabled by default.
+//! * `events` - This feature lets handlers listen to [`events`] from the emulator.
+//!   Without it, events are dropped as soon as they are emitted. Enabled by default.
+//! * `monitor` - This feature adds breakpoints and other debugging support in
+//!   [`monitor`]. This implies `disassembler` and `events`. Enabled by default.
+//! * `remote` - This feature adds [`remote`] emulators controlled by commands from
+//!   another thread. This implies `monitor`. Enabled by default.
+//! * `sync` - This feature makes [`gameboy::GameBoy`] `Send`, so it can be moved between
+//!   threads. It replaces `Rc` with `Arc` in shared state, and requires event handlers,
+//!   serial devices and cartridge peripherals to be `Send`. This implies `std`. Handlers
+//!   that share state with a frontend can use [`shared::Shared`] and [`shared::Lock`]
+//!   to build with and without this feature.
+//! * `futures` - This feature adds a transport for [`remote`] emulators built on
+//!   `futures` channels, for frontends using async runtimes. This implies `std` and `remote`.
+//! * `zip` - This feature lets [`romfile`] read ROMs from zip archives. This implies `std`.
+//! * `profiling` - This feature records how much host time [`gameboy::GameBoy`] spends
+//!   in each component, such as the CPU and PPU. It slows down emulation, so is only meant
+//!   for finding where to optimize the engine. This implies `std`.
 //!
 //! [`gameboy`]: gameboy/index.html
 //! [`rom`]: rom/index.html
+//! [`events`]: events/index.html
+//! [`monitor`]: monitor/index.html
+//! [`remote`]: remote/index.html
+//! [`romfile`]: romfile/index.html
 
 #[macro_use]
 extern crate alloc;
@@ -24,10 +45,24 @@ pub use olympia_core::disasm;
 pub use olympia_core::instructions;
 pub use olympia_core::registers;
 
+pub mod console;
+#[cfg(feature = "disassembler")]
 pub mod disassembler;
 pub mod events;
 pub mod gameboy;
+pub mod gbs;
+pub mod harness;
 pub mod instructionsn;
+pub mod logging;
+#[cfg(feature = "monitor")]
 pub mod monitor;
+pub mod printer;
+#[cfg(feature = "remote")]
 pub mod remote;
 pub mod rom;
+#[cfg(feature = "std")]
+pub mod romfile;
+pub mod scheduler;
+pub mod shared;
+pub mod testing;
+pub mod video;
@@ -0,0 +1,114 @@
+//! Log targets used by the engine, and control over what gets logged
+//!
+//! Every message the engine logs uses a target from a hierarchy under
+//! `olympia`, such as `olympia::rom::mbc1`. A level set for a target also
+//! applies to every target below it, so `olympia::rom=info` shows bank
+//! switches for all memory controllers. The same syntax works in `RUST_LOG`
+//! for frontends using `env_logger`.
+//!
+//! | Target                        | Logs                                          |
+//! |-------------------------------|-----------------------------------------------|
+//! | `olympia::cpu`                | Interrupt and power saving mode changes       |
+//! | `olympia::cpu::step`          | Every instruction run                         |
+//! | `olympia::cpu::registers`     | Every register read and write                 |
+//! | `olympia::cpu::flags`         | Every flag read and write                     |
+//! | `olympia::cpu::interrupts`    | Interrupt requests and dispatches             |
+//! | `olympia::ppu`                | PPU mode changes and the LCD turning on/off   |
+//! | `olympia::dma`                | Game Boy Color HDMA transfers                 |
+//! | `olympia::serial`             | Bytes sent and received over the link cable   |
+//! | `olympia::serial::printer`    | Game Boy Printer packets and images           |
+//! | `olympia::rom::mbc1` to `mbc5`| Bank switches and RAM enables for each MBC    |
+//! | `olympia::rom::rumble`        | Rumble motor changes                          |
+//! | `olympia::monitor`            | Breakpoint conditions met and invalid changes |
+//! | `olympia::remote::thread`     | Emulator thread errors and breakpoints        |
+//! | `olympia::remote::watchdog`   | Commands running over their budget            |
+//! | `olympia::remote::save`       | Battery backed RAM being loaded and saved     |
+//!
+//! With the `std` feature, [`install`] sets up a logger whose levels can be
+//! changed while running with [`set_level`], and a [`LogCapture`] collects
+//! log records so frontends can show them. `EmulatorThread` streams them as
+//! [`LogEvent`]s once asked to with `RemoteEmulator::stream_logs`.
+//!
+//! [`install`]: fn.install.html
+//! [`set_level`]: fn.set_level.html
+//! [`LogCapture`]: struct.LogCapture.html
+//! [`LogEvent`]: ../events/struct.LogEvent.html
+
+/// The parent of every target logged by the engine
+pub const ROOT: &str = "olympia";
+
+/// Changes to the CPU's interrupt and power saving modes
+pub const CPU: &str = "olympia::cpu";
+/// Every instruction run
+pub const CPU_STEP: &str = "olympia::cpu::step";
+/// Every register read and write
+pub const CPU_REGISTERS: &str = "olympia::cpu::registers";
+/// Every flag read and write
+pub const CPU_FLAGS: &str = "olympia::cpu::flags";
+/// Interrupt requests and dispatches
+pub const CPU_INTERRUPTS: &str = "olympia::cpu::interrupts";
+
+/// PPU mode changes, and the LCD turning on or off
+pub const PPU: &str = "olympia::ppu";
+/// Game Boy Color HDMA transfers
+pub const DMA: &str = "olympia::dma";
+/// Bytes sent and received over the link cable
+pub const SERIAL: &str = "olympia::serial";
+/// Game Boy Printer packets and images
+pub const PRINTER: &str = "olympia::serial::printer";
+
+/// The parent of every cartridge target
+pub const ROM: &str = "olympia::rom";
+pub const MBC1: &str = "olympia::rom::mbc1";
+pub const MBC2: &str = "olympia::rom::mbc2";
+pub const MBC3: &str = "olympia::rom::mbc3";
+pub const MBC5: &str = "olympia::rom::mbc5";
+/// Rumble motor changes
+pub const RUMBLE: &str = "olympia::rom::rumble";
+
+/// Breakpoint conditions being met, and changes to breakpoints that don't exist
+pub const MONITOR: &str = "olympia::monitor";
+
+/// The parent of every remote emulator target
+pub const REMOTE: &str = "olympia::remote";
+/// Errors and breakpoints in `EmulatorThread`
+pub const EMULATOR_THREAD: &str = "olympia::remote::thread";
+/// Commands running over the budget set by a `WatchdogPolicy`
+pub const WATCHDOG: &str = "olympia::remote::watchdog";
+/// Battery backed RAM being loaded from and saved to save files
+pub const SAVE: &str = "olympia::remote::save";
+
+/// Whether `target` is `parent` or one of the targets below it
+pub fn is_within(target: &str, parent: &str) -> bool {
+    match target.strip_prefix(parent) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+#[cfg(feature = "std")]
+mod runtime;
+
+#[cfg(feature = "std")]
+pub use runtime::{
+    default_level, install, level, set_default_level, set_level, set_levels_from_spec,
+    LevelSpecError, LogCapture, MAX_CAPTURED_RECORDS,
+};
+
+#[cfg(all(test, feature = "std", feature = "remote"))]
+pub(crate) use runtime::tests::install_test_logger;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_within() {
+        assert!(is_within(MBC1, ROM));
+        assert!(is_within(MBC1, MBC1));
+        assert!(is_within(PRINTER, ROOT));
+        assert!(!is_within(ROM, MBC1));
+        assert!(!is_within("olympia::romfile", ROM));
+        assert!(!is_within("olympia_native", ROOT));
+    }
+}
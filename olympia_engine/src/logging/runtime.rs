@@ -0,0 +1,318 @@
+use super::is_within;
+use crate::events::LogEvent;
+
+use derive_more::Display;
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+/// Most records a [`LogCapture`] holds before dropping the oldest
+///
+/// [`LogCapture`]: struct.LogCapture.html
+pub const MAX_CAPTURED_RECORDS: usize = 1000;
+
+/// The level used for targets without one of their own
+const INITIAL_LEVEL: LevelFilter = LevelFilter::Error;
+
+/// Levels for each target, and the targets below them
+struct Levels {
+    default: LevelFilter,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl Levels {
+    fn level(&self, target: &str) -> LevelFilter {
+        // The most specific target that applies wins
+        self.targets
+            .iter()
+            .filter(|(parent, _)| is_within(target, parent))
+            .max_by_key(|(parent, _)| parent.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn set_level(&mut self, target: &str, level: LevelFilter) {
+        match self.targets.iter_mut().find(|(parent, _)| parent == target) {
+            Some(existing) => existing.1 = level,
+            None => self.targets.push((String::from(target), level)),
+        }
+    }
+
+    /// The most verbose level of any target
+    fn max_level(&self) -> LevelFilter {
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+static LEVELS: RwLock<Levels> = RwLock::new(Levels {
+    default: INITIAL_LEVEL,
+    targets: Vec::new(),
+});
+
+type CaptureBuffer = Arc<Mutex<VecDeque<LogEvent>>>;
+
+/// Buffers of every running capture
+static CAPTURES: Mutex<Vec<CaptureBuffer>> = Mutex::new(Vec::new());
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Log records are independent, so a panic elsewhere can't leave them half updated
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn update_levels(f: impl FnOnce(&mut Levels)) {
+    let mut levels = LEVELS.write().unwrap_or_else(PoisonError::into_inner);
+    f(&mut levels);
+    log::set_max_level(levels.max_level());
+}
+
+/// The level messages for `target` are logged at
+pub fn level(target: &str) -> LevelFilter {
+    LEVELS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .level(target)
+}
+
+/// The level used for targets that haven't had a level set
+pub fn default_level() -> LevelFilter {
+    LEVELS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .default
+}
+
+/// Log messages for `target`, and targets below it without a level of their own, at `level`
+pub fn set_level(target: &str, level: LevelFilter) {
+    update_levels(|levels| levels.set_level(target, level));
+}
+
+/// Set the level used for targets that haven't had a level set
+pub fn set_default_level(level: LevelFilter) {
+    update_levels(|levels| levels.default = level);
+}
+
+#[derive(Debug, Display, PartialEq, Eq)]
+/// A level in a log spec was not recognised
+#[display(fmt = "Invalid log level: {}", "_0")]
+pub struct LevelSpecError(pub String);
+
+impl std::error::Error for LevelSpecError {}
+
+/// Read the targets and levels in a spec such as `warn,olympia::rom=info`
+///
+/// An entry without a target is the default level, and a target without a
+/// level is logged at every level.
+fn parse_spec(spec: &str) -> Result<Vec<(Option<&str>, LevelFilter)>, LevelSpecError> {
+    let parse_level = |level: &str| {
+        LevelFilter::from_str(level.trim()).map_err(|_| LevelSpecError(String::from(level)))
+    };
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((target, level)) => Ok((Some(target.trim()), parse_level(level)?)),
+            None => match parse_level(entry) {
+                Ok(level) => Ok((None, level)),
+                Err(_) => Ok((Some(entry), LevelFilter::Trace)),
+            },
+        })
+        .collect()
+}
+
+/// Replace all levels with those in a spec such as `warn,olympia::rom=info`
+///
+/// This uses the same syntax as `RUST_LOG` for `env_logger`, without
+/// regex filters. Targets not in the spec are logged at the default level
+/// from the spec, or only for errors if there isn't one.
+pub fn set_levels_from_spec(spec: &str) -> Result<(), LevelSpecError> {
+    let entries = parse_spec(spec)?;
+    update_levels(|levels| {
+        levels.default = INITIAL_LEVEL;
+        levels.targets.clear();
+        for (target, level) in entries {
+            match target {
+                Some(target) => levels.set_level(target, level),
+                None => levels.default = level,
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Sends records allowed by the current levels to a frontend's logger, and to any captures
+struct EngineLogger {
+    output: Box<dyn Log>,
+}
+
+impl Log for EngineLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.output.log(record);
+        let captures = lock(&CAPTURES);
+        if captures.is_empty() {
+            return;
+        }
+        let event = LogEvent::new(
+            record.level(),
+            String::from(record.target()),
+            record.args().to_string(),
+        );
+        for capture in captures.iter() {
+            let mut records = lock(capture);
+            if records.len() == MAX_CAPTURED_RECORDS {
+                records.pop_front();
+            }
+            records.push_back(event.clone());
+        }
+    }
+
+    fn flush(&self) {
+        self.output.flush();
+    }
+}
+
+/// Set the global logger to one whose levels can be changed while running
+///
+/// Records are written by `output`, which should accept every record it is
+/// given, as levels are checked before records reach it. Call this once,
+/// before any other logger is set.
+pub fn install(output: Box<dyn Log>) -> Result<(), SetLoggerError> {
+    log::set_logger(Box::leak(Box::new(EngineLogger { output })))?;
+    update_levels(|_| {});
+    Ok(())
+}
+
+/// Collects log records from every thread, so frontends can show them
+///
+/// Only records allowed by the current levels are collected, and only once
+/// the logger has been set with [`install`]. At most [`MAX_CAPTURED_RECORDS`]
+/// are kept between calls to `take`. Records stop being collected once this
+/// is dropped.
+///
+/// [`install`]: fn.install.html
+/// [`MAX_CAPTURED_RECORDS`]: constant.MAX_CAPTURED_RECORDS.html
+pub struct LogCapture {
+    records: CaptureBuffer,
+}
+
+impl LogCapture {
+    /// Start collecting log records
+    pub fn start() -> LogCapture {
+        let records = Arc::new(Mutex::new(VecDeque::new()));
+        lock(&CAPTURES).push(records.clone());
+        LogCapture { records }
+    }
+
+    /// Take the records collected since the last call, oldest first
+    pub fn take(&self) -> Vec<LogEvent> {
+        lock(&self.records).drain(..).collect()
+    }
+}
+
+impl Drop for LogCapture {
+    fn drop(&mut self) {
+        lock(&CAPTURES).retain(|records| !Arc::ptr_eq(records, &self.records));
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::logging::{MBC1, MBC3, PPU, ROM};
+    use log::Level;
+
+    struct DiscardLogger;
+
+    impl Log for DiscardLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, _record: &Record) {}
+
+        fn flush(&self) {}
+    }
+
+    /// Install the engine logger, if an earlier test hasn't already
+    pub(crate) fn install_test_logger() {
+        let _ = install(Box::new(DiscardLogger));
+    }
+
+    #[test]
+    fn test_most_specific_level() {
+        let mut levels = Levels {
+            default: LevelFilter::Warn,
+            targets: Vec::new(),
+        };
+        levels.set_level(ROM, LevelFilter::Info);
+        levels.set_level(MBC1, LevelFilter::Off);
+
+        assert_eq!(levels.level(MBC1), LevelFilter::Off);
+        assert_eq!(levels.level(MBC3), LevelFilter::Info);
+        assert_eq!(levels.level(PPU), LevelFilter::Warn);
+        assert_eq!(levels.max_level(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            parse_spec("warn, olympia::rom=info,olympia::ppu").unwrap(),
+            vec![
+                (None, LevelFilter::Warn),
+                (Some("olympia::rom"), LevelFilter::Info),
+                (Some("olympia::ppu"), LevelFilter::Trace),
+            ]
+        );
+        assert_eq!(parse_spec("").unwrap(), vec![]);
+        assert_eq!(
+            parse_spec("olympia::rom=loud"),
+            Err(LevelSpecError(String::from("loud")))
+        );
+    }
+
+    #[test]
+    fn test_capture() {
+        install_test_logger();
+        let target = "olympia::tests::capture";
+        set_level(target, LevelFilter::Info);
+        let capture = LogCapture::start();
+        // Other tests may log at the same time
+        let take = || -> Vec<LogEvent> {
+            let records = capture.take().into_iter();
+            records.filter(|record| record.target == target).collect()
+        };
+
+        log::info!(target: target, "Shown {}", 1);
+        log::debug!(target: target, "Hidden");
+        assert_eq!(
+            take(),
+            vec![LogEvent::new(
+                Level::Info,
+                String::from(target),
+                String::from("Shown 1")
+            )]
+        );
+        assert_eq!(take(), vec![]);
+
+        for i in 0..=MAX_CAPTURED_RECORDS {
+            log::info!(target: target, "{}", i);
+        }
+        let records = take();
+        assert!(records.len() <= MAX_CAPTURED_RECORDS);
+        assert_eq!(
+            records[records.len() - 1].message,
+            MAX_CAPTURED_RECORDS.to_string()
+        );
+        assert_ne!(records[0].message, "0");
+    }
+}
@@ -3,6 +3,7 @@
 use crate::address;
 use crate::events::{Event, MemoryEvent, RegisterWriteEvent};
 use crate::gameboy;
+use crate::logging;
 use crate::registers;
 
 use alloc::string::String;
@@ -10,6 +11,36 @@ use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::str::FromStr;
 use derive_more::{Display, From, Into};
+use hashbrown::HashMap;
+
+mod address_spec;
+mod bank_history;
+mod dirty;
+mod expression;
+mod heatmap;
+mod interrupt_stats;
+mod metrics;
+mod post_mortem;
+mod protection;
+mod stack_guard;
+mod symbols;
+mod verify;
+
+pub use address_spec::AddressSpec;
+pub use bank_history::BankHistory;
+pub use dirty::{DirtyBlocks, DIRTY_BLOCK_SIZE};
+pub use expression::{EvalError, Expression, ExpressionParseError, Operator, Token};
+pub use heatmap::{AccessCount, AccessHeatmap};
+pub use interrupt_stats::{InterruptStats, InterruptSummary};
+pub use metrics::{MetricSample, MetricsSampler};
+pub use post_mortem::{PostMortem, TraceEntry};
+pub use protection::{
+    MemoryAccess, MemoryProtection, ProtectedRegion, Protection, ProtectionParseError,
+    ProtectionViolation,
+};
+pub use stack_guard::{StackGuard, StackViolation};
+pub use symbols::{SymbolParseError, SymbolTable};
+pub use verify::{verify_trace, Divergence, TraceLine, TraceParseError, VerifyError};
 
 /// Parse a user provided number
 ///
@@ -20,6 +51,12 @@ use derive_more::{Display, From, Into};
 ///
 /// All others are attempted to be
 /// parsed as base 10
+///
+/// To accept addresses with banks, symbols or arithmetic as well, use
+/// [`AddressSpec`] or [`RWTarget::parse_with`].
+///
+/// [`AddressSpec`]: struct.AddressSpec.html
+/// [`RWTarget::parse_with`]: enum.RWTarget.html#method.parse_with
 pub fn parse_number(src: &str) -> Result<u16, core::num::ParseIntError> {
     let lowered = src.to_lowercase();
     if lowered.starts_with("0x") {
@@ -37,12 +74,88 @@ pub fn parse_number(src: &str) -> Result<u16, core::num::ParseIntError> {
     }
 }
 
+/// A memory location pointed to by a 16-bit register, such as `[HL]` or `[SP+1]`
+///
+/// The address is worked out from the register each time the location is
+/// read or written, so breakpoints follow the register as it changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndirectAddress {
+    pub register: registers::WordRegister,
+    /// Added to the register's value to get the address
+    pub offset: i16,
+}
+
+impl IndirectAddress {
+    pub fn new(register: registers::WordRegister, offset: i16) -> IndirectAddress {
+        IndirectAddress { register, offset }
+    }
+
+    /// The address currently pointed to
+    pub fn resolve(&self, gb: &gameboy::GameBoy) -> address::LiteralAddress {
+        let base = gb.read_register_u16(self.register);
+        address::LiteralAddress(base.wrapping_add(self.offset as u16))
+    }
+
+    /// The location read by an expression such as `[HL]`, `[SP+1]` or `[HL-2]`
+    ///
+    /// Returns `None` for any other expression.
+    pub fn from_expression(expr: &Expression) -> Option<IndirectAddress> {
+        let inner = match expr {
+            Expression::Deref(inner) => inner.as_ref(),
+            _ => return None,
+        };
+        let (register, offset) = match inner {
+            Expression::WordRegister(reg) => (*reg, 0),
+            Expression::Binary(Operator::Add, left, right) => {
+                match (left.as_ref(), right.as_ref()) {
+                    (Expression::WordRegister(reg), Expression::Constant(offset))
+                    | (Expression::Constant(offset), Expression::WordRegister(reg)) => {
+                        (*reg, i16::try_from(*offset).ok()?)
+                    }
+                    _ => return None,
+                }
+            }
+            Expression::Binary(Operator::Subtract, left, right) => {
+                match (left.as_ref(), right.as_ref()) {
+                    (Expression::WordRegister(reg), Expression::Constant(offset)) => {
+                        (*reg, i16::try_from(*offset).ok()?.checked_neg()?)
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some(IndirectAddress::new(register, offset))
+    }
+}
+
+impl core::fmt::Display for IndirectAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.offset {
+            0 => write!(f, "[{:?}]", self.register),
+            offset if offset < 0 => write!(f, "[{:?}-{:X}h]", self.register, -i32::from(offset)),
+            offset => write!(f, "[{:?}+{:X}h]", self.register, offset),
+        }
+    }
+}
+
 #[derive(Debug, From, Clone, Copy, Display, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Types of value that can be read or written
 pub enum RWTarget {
     /// Byte at the given memory location
     #[display(fmt = "memory location {}", "_0")]
     Address(address::LiteralAddress),
+    /// Byte at the given memory location, only while the given bank is mapped
+    #[display(fmt = "memory location {}", "_0")]
+    BankedAddress(address::BankedAddress),
+    /// Byte at the memory location a register currently points to
+    ///
+    /// Breakpoints on these are checked after every step, and only support
+    /// value and `Changed` conditions.
+    #[display(fmt = "memory location {}", "_0")]
+    Indirect(IndirectAddress),
     /// Byte in the given 8-bit register
     #[display(fmt = "register {:?}", "_0")]
     ByteRegister(registers::ByteRegister),
@@ -59,6 +172,8 @@ pub enum RWTarget {
 pub enum ReadError {
     #[display(fmt = "Could not read from the address at {}", "_0")]
     Memory(address::LiteralAddress),
+    #[display(fmt = "The bank for {} is not currently mapped", "_0")]
+    BankNotMapped(address::BankedAddress),
 }
 
 #[cfg(feature = "std")]
@@ -68,6 +183,8 @@ impl std::error::Error for ReadError {}
 pub enum WriteError {
     #[display(fmt = "Could not write to the address at {}", "_0")]
     Memory(address::LiteralAddress),
+    #[display(fmt = "The bank for {} is not currently mapped", "_0")]
+    BankNotMapped(address::BankedAddress),
     #[display(fmt = "The value {:X} is too large for the destination", "_0")]
     ValueTooLarge(u16),
     #[display(fmt = "Cannot update locations of this type")]
@@ -85,6 +202,20 @@ impl RWTarget {
                 .read_memory_u8(addr)
                 .map(u64::from)
                 .map_err(|_| addr.into()),
+            RWTarget::BankedAddress(banked) => {
+                if gb.banked_address(banked.addr) != banked {
+                    return Err(ReadError::BankNotMapped(banked));
+                }
+                gb.read_memory_u8(banked.addr)
+                    .map(u64::from)
+                    .map_err(|_| banked.addr.into())
+            }
+            RWTarget::Indirect(indirect) => {
+                let addr = indirect.resolve(gb);
+                gb.read_memory_u8(addr)
+                    .map(u64::from)
+                    .map_err(|_| addr.into())
+            }
             RWTarget::ByteRegister(reg) => Ok(u64::from(gb.read_register_u8(reg))),
             RWTarget::WordRegister(reg) => Ok(u64::from(gb.read_register_u16(reg))),
             RWTarget::Cycles => Ok(gb.cycles_elapsed()),
@@ -102,6 +233,20 @@ impl RWTarget {
                 gb.write_memory_u8(addr, value)
                     .map_err(|_| WriteError::Memory(addr))?;
             }
+            RWTarget::BankedAddress(banked) => {
+                if gb.banked_address(banked.addr) != banked {
+                    return Err(WriteError::BankNotMapped(banked));
+                }
+                let value = u8::try_from(val).map_err(|_| WriteError::ValueTooLarge(val))?;
+                gb.write_memory_u8(banked.addr, value)
+                    .map_err(|_| WriteError::Memory(banked.addr))?;
+            }
+            RWTarget::Indirect(indirect) => {
+                let addr = indirect.resolve(gb);
+                let value = u8::try_from(val).map_err(|_| WriteError::ValueTooLarge(val))?;
+                gb.write_memory_u8(addr, value)
+                    .map_err(|_| WriteError::Memory(addr))?;
+            }
             RWTarget::ByteRegister(reg) => {
                 let value = u8::try_from(val).map_err(|_| WriteError::ValueTooLarge(val))?;
                 gb.write_register_u8(reg, value);
@@ -119,6 +264,20 @@ impl RWTarget {
         match (self, other) {
             (RWTarget::WordRegister(wr), RWTarget::ByteRegister(br)) => wr.contains(br),
             (RWTarget::ByteRegister(br), RWTarget::WordRegister(wr)) => wr.contains(*br),
+            (RWTarget::Address(addr), RWTarget::BankedAddress(banked)) => *addr == banked.addr,
+            (RWTarget::BankedAddress(banked), RWTarget::Address(addr)) => addr == banked.addr,
+            _ => false,
+        }
+    }
+
+    /// Indicates whether the CPU is about to execute the instruction at this target
+    ///
+    /// Always false for targets that are not memory locations
+    pub fn is_executing(&self, gb: &gameboy::GameBoy) -> bool {
+        let pc = gb.read_pc();
+        match self {
+            RWTarget::Address(addr) => *addr == pc,
+            RWTarget::BankedAddress(banked) => gb.banked_address(pc) == *banked,
             _ => false,
         }
     }
@@ -129,14 +288,26 @@ impl RWTarget {
 #[display(fmt = "{} is not a valid register or memory location", _0)]
 pub struct TargetParseError(String);
 
-impl FromStr for RWTarget {
-    type Err = TargetParseError;
-
-    fn from_str(s: &str) -> Result<RWTarget, TargetParseError> {
+impl RWTarget {
+    /// Parse a register, `cycles`, `time`, or a memory location in any
+    /// format accepted by [`AddressSpec`]
+    ///
+    /// Memory locations are resolved against `gb` and `symbols`, so
+    /// `PC+2` is the address two bytes after the current instruction.
+    /// Without an emulator, memory locations can only use constants and symbols.
+    ///
+    /// [`AddressSpec`]: struct.AddressSpec.html
+    pub fn parse_with(
+        s: &str,
+        gb: Option<&gameboy::GameBoy>,
+        symbols: &SymbolTable,
+    ) -> Result<RWTarget, TargetParseError> {
         if s == "cycles" {
             return Ok(RWTarget::Cycles);
         } else if s == "time" {
             return Ok(RWTarget::Time);
+        } else if let Ok(banked) = s.parse::<address::BankedAddress>() {
+            return Ok(RWTarget::BankedAddress(banked));
         }
         parse_number(s)
             .map(|val| address::LiteralAddress(val).into())
@@ -151,11 +322,37 @@ impl FromStr for RWTarget {
                     .map(|br| br.into())
                     .map_err(|_| ())
             })
+            .or_else(|_| {
+                s.parse::<Expression>()
+                    .ok()
+                    .as_ref()
+                    .and_then(IndirectAddress::from_expression)
+                    .map(RWTarget::Indirect)
+                    .ok_or(())
+            })
+            .or_else(|_| {
+                s.parse::<AddressSpec>()
+                    .map_err(|_| ())?
+                    .resolve(gb, symbols)
+                    .map_err(|_| ())
+            })
             .map_err(|_| TargetParseError(s.into()))
     }
 }
 
+impl FromStr for RWTarget {
+    type Err = TargetParseError;
+
+    /// Parse a target without symbols or emulator state, see [`RWTarget::parse_with`]
+    ///
+    /// [`RWTarget::parse_with`]: #method.parse_with
+    fn from_str(s: &str) -> Result<RWTarget, TargetParseError> {
+        RWTarget::parse_with(s, None, &SymbolTable::new())
+    }
+}
+
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Comparison {
     #[display(fmt = ">")]
     GreaterThan,
@@ -201,6 +398,7 @@ impl FromStr for Comparison {
 }
 
 #[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BreakpointCondition {
     #[display(fmt = "{} {:X}", "_0", "_1")]
     Test(Comparison, u64),
@@ -208,11 +406,71 @@ pub enum BreakpointCondition {
     Read,
     #[display(fmt = "Write")]
     Write,
+    /// The instruction at the monitored memory location is about to execute
+    #[display(fmt = "Execute")]
+    Execute,
+    /// The monitored value differs from its value at the previous check
+    #[display(fmt = "Changed")]
+    Changed,
+    /// The given interrupt was just dispatched, and its handler is about to run
+    #[display(fmt = "{} interrupt", "_0")]
+    Interrupt(gameboy::Interrupt),
+    /// The function running when the breakpoint was first checked has returned
+    #[display(fmt = "Return")]
+    Return,
 }
 
-#[derive(Debug, Display, Clone, PartialEq, Eq)]
+/// Follows calls and returns to find when the current function returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct CallTracker {
+    /// Calls made since tracking started which haven't returned yet
+    depth: u32,
+    /// The opcode that was about to execute at the last check
+    opcode: u8,
+    /// SP at the last check, to tell if conditional calls and returns were taken
+    sp: u16,
+}
+
+impl CallTracker {
+    fn new(gb: &gameboy::GameBoy) -> CallTracker {
+        CallTracker::at_depth(gb, 0)
+    }
+
+    fn at_depth(gb: &gameboy::GameBoy, depth: u32) -> CallTracker {
+        let pc = gb.read_register_u16(registers::WordRegister::PC);
+        CallTracker {
+            depth,
+            opcode: gb.get_memory_u8(pc).unwrap_or(0),
+            sp: gb.read_register_u16(registers::WordRegister::SP),
+        }
+    }
+
+    /// The call depth after the last step, or `None` if the tracked function returned
+    fn depth_after_step(&self, gb: &gameboy::GameBoy) -> Option<u32> {
+        let sp = gb.read_register_u16(registers::WordRegister::SP);
+        let pushed = sp == self.sp.wrapping_sub(2);
+        let popped = sp == self.sp.wrapping_add(2);
+        if gb.dispatched_interrupt().is_some() {
+            // The interrupt was handled instead of running the instruction
+            return Some(self.depth + 1);
+        }
+        match self.opcode {
+            // CALL, CALL cc
+            0xCD | 0xC4 | 0xCC | 0xD4 | 0xDC if pushed => Some(self.depth + 1),
+            // RST
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => Some(self.depth + 1),
+            // RET, RETI, RET cc
+            0xC9 | 0xD9 => self.depth.checked_sub(1),
+            0xC0 | 0xC8 | 0xD0 | 0xD8 if popped => self.depth.checked_sub(1),
+            _ => Some(self.depth),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A breakpoint that triggers when a monitored value is set to a given value.
-#[display(fmt = "Breakpoint: {} {}", monitor, condition)]
 pub struct Breakpoint {
     /// The value that should be checked
     pub monitor: RWTarget,
@@ -221,12 +479,38 @@ pub struct Breakpoint {
     pub condition: BreakpointCondition,
     /// Whether the breakpoint should be considered
     pub active: bool,
+    /// The value seen at the last check, used by `Changed` conditions
+    last_value: Option<u64>,
+    /// Calls seen since the first check, used by `Return` conditions
+    calls: Option<CallTracker>,
+    temporary: bool,
+}
+
+impl core::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.condition {
+            // The monitored value doesn't matter for interrupts or returns
+            BreakpointCondition::Interrupt(_) => write!(f, "Breakpoint: {}", self.condition),
+            BreakpointCondition::Return => write!(f, "Breakpoint: return to caller"),
+            _ => write!(f, "Breakpoint: {} {}", self.monitor, self.condition),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, From, Into, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BreakpointIdentifier(u32);
 
+/// Indicates a breakpoint's condition can never be met for the value it monitors
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+#[display(fmt = "Cannot break on {} for {}", _0, _1)]
+pub struct UnsupportedBreakpoint(BreakpointCondition, RWTarget);
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnsupportedBreakpoint {}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BreakpointState {
     Inactive,
     HitBreakpoint(Breakpoint),
@@ -239,10 +523,66 @@ impl Breakpoint {
             monitor,
             condition,
             active: true,
+            last_value: None,
+            calls: None,
+            temporary: false,
+        }
+    }
+
+    /// New breakpoint which triggers when the current function returns to its caller
+    ///
+    /// Calls and returns are followed from the first time the breakpoint is
+    /// checked, so this should be checked before the next step.
+    pub fn function_return() -> Breakpoint {
+        Breakpoint::new(
+            registers::WordRegister::PC.into(),
+            BreakpointCondition::Return,
+        )
+    }
+
+    /// Make this a temporary breakpoint
+    ///
+    /// Temporary breakpoints are removed from a [`DebugMonitor`] when any
+    /// breakpoint is hit.
+    ///
+    /// [`DebugMonitor`]: ./struct.DebugMonitor.html
+    pub fn temporary(mut self) -> Breakpoint {
+        self.temporary = true;
+        self
+    }
+
+    /// Whether this breakpoint should be removed once any breakpoint is hit
+    pub fn is_temporary(&self) -> bool {
+        self.temporary
+    }
+
+    /// New breakpoint which triggers when the given interrupt is dispatched,
+    /// before the first instruction of its handler runs
+    pub fn interrupt(interrupt: gameboy::Interrupt) -> Breakpoint {
+        Breakpoint::new(
+            registers::WordRegister::PC.into(),
+            BreakpointCondition::Interrupt(interrupt),
+        )
+    }
+
+    /// Checks that this breakpoint's condition can be met for the value it monitors
+    ///
+    /// Memory events don't say which register pointed at the address, so
+    /// `Read` and `Write` conditions can't watch an `RWTarget::Indirect`.
+    pub fn validate(&self) -> Result<(), UnsupportedBreakpoint> {
+        match (self.condition, self.monitor) {
+            (BreakpointCondition::Read, RWTarget::Indirect(_))
+            | (BreakpointCondition::Write, RWTarget::Indirect(_)) => {
+                Err(UnsupportedBreakpoint(self.condition, self.monitor))
+            }
+            _ => Ok(()),
         }
     }
 
     /// Returns whether this breakpoint is active
+    ///
+    /// `Changed` conditions compare against the value recorded by the last
+    /// call to [`check`](#method.check).
     pub fn should_break(&self, gb: &gameboy::GameBoy) -> bool {
         let read_result = self.monitor.read(gb);
         use BreakpointCondition::*;
@@ -251,26 +591,144 @@ impl Breakpoint {
                 Test(cmp, reference_value) => cmp.test(value, reference_value),
                 Read => false,
                 Write => false,
+                Execute => self.monitor.is_executing(gb),
+                Changed => matches!(self.last_value, Some(last) if last != value),
+                Interrupt(interrupt) => gb.dispatched_interrupt() == Some(interrupt),
+                Return => matches!(self.calls, Some(calls) if calls.depth_after_step(gb).is_none()),
             }
         } else {
             false
         }
     }
+
+    /// Returns whether this breakpoint is active, and records the current
+    /// value of the target for later `Changed` checks
+    ///
+    /// The first check of a `Changed` or `Return` breakpoint never triggers,
+    /// as there is no earlier state to compare against.
+    pub fn check(&mut self, gb: &gameboy::GameBoy) -> bool {
+        let result = self.should_break(gb);
+        match self.condition {
+            BreakpointCondition::Changed => self.last_value = self.monitor.read(gb).ok(),
+            BreakpointCondition::Return => {
+                self.calls = match self.calls {
+                    Some(calls) => calls
+                        .depth_after_step(gb)
+                        .map(|depth| CallTracker::at_depth(gb, depth)),
+                    None => Some(CallTracker::new(gb)),
+                }
+            }
+            _ => {}
+        }
+        result
+    }
+
+    /// Indicates if this breakpoint can only be checked with access to
+    /// the gameboy, rather than from emitted events alone
+    fn needs_polling(&self) -> bool {
+        self.condition == BreakpointCondition::Execute
+            || self.condition == BreakpointCondition::Changed
+            || self.condition == BreakpointCondition::Return
+            || matches!(self.condition, BreakpointCondition::Interrupt(_))
+            || matches!(
+                self.monitor,
+                RWTarget::BankedAddress(_) | RWTarget::Indirect(_)
+            )
+    }
+}
+
+/// Positions of active breakpoints in `DebugMonitor::breakpoints`,
+/// grouped by what needs to happen for them to be hit
+///
+/// This avoids checking every breakpoint after each event and step,
+/// which would dominate emulation time with many breakpoints set.
+#[derive(Debug, Default)]
+struct BreakpointIndex {
+    /// Read, write and value breakpoints on memory, by address
+    memory: HashMap<u16, Vec<usize>>,
+    /// Read, write and value breakpoints on registers
+    registers: Vec<usize>,
+    /// Execute breakpoints, by address
+    execute: HashMap<u16, Vec<usize>>,
+    /// Breakpoints that need to be checked after every step
+    polled: Vec<usize>,
+}
+
+impl BreakpointIndex {
+    fn new(breakpoints: &[(BreakpointIdentifier, Breakpoint)]) -> BreakpointIndex {
+        let mut index = BreakpointIndex::default();
+        for (i, (_id, bp)) in breakpoints.iter().enumerate() {
+            if !bp.active {
+                continue;
+            }
+            use BreakpointCondition::*;
+            match (bp.condition, bp.monitor) {
+                (Execute, RWTarget::Address(addr)) => {
+                    index.execute.entry(addr.0).or_default().push(i)
+                }
+                (Execute, RWTarget::BankedAddress(banked)) => {
+                    index.execute.entry(banked.addr.0).or_default().push(i)
+                }
+                // Only memory locations can be executed
+                (Execute, _) => {}
+                (Changed, _) | (Return, _) | (Interrupt(_), _) => index.polled.push(i),
+                // Events don't include which bank is mapped
+                (Test(..), RWTarget::BankedAddress(_)) => index.polled.push(i),
+                (_, RWTarget::Address(addr)) => index.memory.entry(addr.0).or_default().push(i),
+                (_, RWTarget::BankedAddress(banked)) => {
+                    index.memory.entry(banked.addr.0).or_default().push(i)
+                }
+                (_, RWTarget::ByteRegister(_)) | (_, RWTarget::WordRegister(_)) => {
+                    index.registers.push(i)
+                }
+                // Rejected by `Breakpoint::validate`
+                (Read, RWTarget::Indirect(_)) | (Write, RWTarget::Indirect(_)) => {}
+                // The address depends on the register's value at the time
+                (_, RWTarget::Indirect(_)) => index.polled.push(i),
+                // Cycles and time are never read or written by events
+                (_, RWTarget::Cycles) | (_, RWTarget::Time) => {}
+            }
+        }
+        index
+    }
+
+    /// Breakpoints which may be hit by an event accessing `target`
+    fn event_candidates(&self, target: RWTarget) -> &[usize] {
+        match target {
+            RWTarget::Address(addr) => self.memory.get(&addr.0).map_or(&[], Vec::as_slice),
+            RWTarget::ByteRegister(_) | RWTarget::WordRegister(_) => &self.registers,
+            _ => &[],
+        }
+    }
+
+    /// Breakpoints which may be hit by the current gameboy state, in the order they were added
+    fn poll_candidates(&self, pc: u16) -> Vec<usize> {
+        let mut candidates = self.polled.clone();
+        if let Some(execute) = self.execute.get(&pc) {
+            candidates.extend_from_slice(execute);
+            candidates.sort_unstable();
+        }
+        candidates
+    }
 }
 
 #[derive(Debug)]
 pub struct DebugMonitor {
     breakpoints: Vec<(BreakpointIdentifier, Breakpoint)>,
+    index: BreakpointIndex,
     state: BreakpointState,
     next_identifier: u32,
+    unconfirmed: Vec<Breakpoint>,
 }
 
 impl DebugMonitor {
     pub fn new() -> DebugMonitor {
         DebugMonitor {
             breakpoints: Vec::new(),
+            index: BreakpointIndex::default(),
             state: BreakpointState::Inactive,
             next_identifier: 0,
+            unconfirmed: Vec::new(),
         }
     }
 
@@ -295,11 +753,72 @@ impl DebugMonitor {
         }
     }
 
-    pub fn add_breakpoint(&mut self, bp: Breakpoint) -> BreakpointIdentifier {
+    /// Check breakpoints that depend on the current gameboy state
+    ///
+    /// Events do not include which banks are mapped, so breakpoints on banked
+    /// addresses and execution breakpoints are only evaluated here. This should
+    /// be called after every step.
+    ///
+    /// Only execution breakpoints on the current address are checked, along
+    /// with breakpoints that can't be checked from events, so this stays fast
+    /// with many breakpoints set.
+    ///
+    /// Temporary breakpoints are removed once any breakpoint is hit.
+    pub fn poll(&mut self, gb: &gameboy::GameBoy) -> bool {
+        let hit = self.poll_breakpoints(gb);
+        if hit && self.breakpoints.iter().any(|(_, bp)| bp.temporary) {
+            self.breakpoints.retain(|(_, bp)| !bp.temporary);
+            self.reindex();
+        }
+        hit
+    }
+
+    fn poll_breakpoints(&mut self, gb: &gameboy::GameBoy) -> bool {
+        if let BreakpointState::HitBreakpoint(_) = self.state {
+            self.unconfirmed.clear();
+            return true;
+        }
+        let unconfirmed = core::mem::take(&mut self.unconfirmed);
+        for bp in unconfirmed {
+            if let RWTarget::BankedAddress(banked) = bp.monitor {
+                if gb.banked_address(banked.addr) == banked {
+                    self.state = BreakpointState::HitBreakpoint(bp);
+                    return true;
+                }
+            }
+        }
+        for i in self.index.poll_candidates(gb.read_pc().0) {
+            let (_id, bp) = &mut self.breakpoints[i];
+            if bp.check(gb) {
+                self.state = BreakpointState::HitBreakpoint(bp.clone());
+                return true;
+            }
+        }
+        false
+    }
+
+    fn reindex(&mut self) {
+        self.index = BreakpointIndex::new(&self.breakpoints);
+    }
+
+    /// All breakpoints and their identifiers, in the order they were added
+    pub fn breakpoints(&self) -> &[(BreakpointIdentifier, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    /// Add a breakpoint, unless its condition can never be met
+    ///
+    /// See [`Breakpoint::validate`](struct.Breakpoint.html#method.validate).
+    pub fn add_breakpoint(
+        &mut self,
+        bp: Breakpoint,
+    ) -> Result<BreakpointIdentifier, UnsupportedBreakpoint> {
+        bp.validate()?;
         let identifier = BreakpointIdentifier(self.next_identifier);
         self.breakpoints.push((identifier, bp));
         self.next_identifier += 1;
-        identifier
+        self.reindex();
+        Ok(identifier)
     }
 
     pub fn remove_breakpoint(&mut self, id_to_remove: BreakpointIdentifier) -> Option<Breakpoint> {
@@ -309,6 +828,7 @@ impl DebugMonitor {
             .position(|(id, _)| *id == id_to_remove)?;
 
         let (_, bp) = self.breakpoints.remove(idx);
+        self.reindex();
         Some(bp)
     }
 
@@ -324,9 +844,11 @@ impl DebugMonitor {
 
         if let Some((_, bp)) = breakpoint {
             bp.active = state;
+            self.reindex();
             Some(state)
         } else {
             log::warn!(
+                target: logging::MONITOR,
                 "Tried to set state of invalid breakpoint {:?}",
                 id_to_update
             );
@@ -335,11 +857,13 @@ impl DebugMonitor {
     }
 
     fn handle_read(&mut self, target: RWTarget) -> bool {
-        for (_id, bp) in self.breakpoints.iter() {
-            if !bp.active {
-                continue;
-            }
+        for &i in self.index.event_candidates(target) {
+            let (_id, bp) = &self.breakpoints[i];
             if bp.condition == BreakpointCondition::Read && target.overlaps(bp.monitor) {
+                if bp.needs_polling() {
+                    self.unconfirmed.push(bp.clone());
+                    continue;
+                }
                 self.state = BreakpointState::HitBreakpoint(bp.clone());
                 return true;
             }
@@ -348,13 +872,17 @@ impl DebugMonitor {
     }
 
     fn handle_write(&mut self, target: RWTarget, value: u64) -> bool {
-        for (_id, bp) in self.breakpoints.iter() {
-            if !bp.active {
-                continue;
-            }
+        for &i in self.index.event_candidates(target) {
+            let (_id, bp) = &self.breakpoints[i];
             if bp.condition == BreakpointCondition::Write && target.overlaps(bp.monitor) {
+                if bp.needs_polling() {
+                    self.unconfirmed.push(bp.clone());
+                    continue;
+                }
                 self.state = BreakpointState::HitBreakpoint(bp.clone());
                 return true;
+            } else if bp.needs_polling() {
+                continue;
             } else if let BreakpointCondition::Test(cmp, reference_value) = bp.condition {
                 if target.overlaps(bp.monitor) {
                     let test_value = if let RWTarget::ByteRegister(br) = target {
@@ -366,7 +894,13 @@ impl DebugMonitor {
                         value
                     };
                     if cmp.test(test_value, reference_value) {
-                        log::info!("Broke on bp {} {} {}", value, cmp, reference_value);
+                        log::info!(
+                            target: logging::MONITOR,
+                            "Broke on bp {} {} {}",
+                            value,
+                            cmp,
+                            reference_value
+                        );
                         self.state = BreakpointState::HitBreakpoint(bp.clone());
                         return true;
                     }
@@ -382,3 +916,322 @@ impl Default for DebugMonitor {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{GameBoy, GameBoyModel};
+    use crate::rom::Cartridge;
+    use crate::shared::{Lock, Shared};
+    use alloc::boxed::Box;
+    use alloc::string::ToString;
+
+    fn make_mbc1_gameboy() -> GameBoy {
+        let mut data = vec![0u8; 0x10000];
+        data[0x147] = 1;
+        GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy)
+    }
+
+    #[test]
+    fn test_parse_banked_target() {
+        assert_eq!(
+            "3:4123".parse::<RWTarget>().unwrap(),
+            RWTarget::BankedAddress(address::BankedAddress::new(3, 0x4123))
+        );
+        assert!("3:nope".parse::<RWTarget>().is_err());
+    }
+
+    #[test]
+    fn test_poll_banked_execute() {
+        let mut gb = make_mbc1_gameboy();
+        let mut monitor = DebugMonitor::new();
+        let target = address::BankedAddress::new(3, 0x4123);
+        monitor
+            .add_breakpoint(Breakpoint::new(
+                RWTarget::BankedAddress(target),
+                BreakpointCondition::Execute,
+            ))
+            .unwrap();
+
+        gb.write_register_u16(registers::WordRegister::PC, 0x4123);
+        assert!(!monitor.poll(&gb));
+
+        gb.set_memory_u8(0x2000, 3).unwrap();
+        assert!(monitor.poll(&gb));
+        assert_eq!(
+            monitor.state(),
+            BreakpointState::HitBreakpoint(Breakpoint::new(
+                RWTarget::BankedAddress(target),
+                BreakpointCondition::Execute,
+            ))
+        );
+    }
+
+    #[test]
+    fn test_banked_read_needs_mapped_bank() {
+        let mut gb = make_mbc1_gameboy();
+        let target = RWTarget::BankedAddress(address::BankedAddress::new(2, 0x4000));
+
+        assert!(target.read(&gb).is_err());
+        gb.set_memory_u8(0x2000, 2).unwrap();
+        assert_eq!(target.read(&gb).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_poll_indirect() {
+        let mut gb = make_mbc1_gameboy();
+        let mut monitor = DebugMonitor::new();
+        let target: RWTarget = "[HL+1]".parse().unwrap();
+        assert_eq!(
+            target,
+            RWTarget::Indirect(IndirectAddress::new(registers::WordRegister::HL, 1))
+        );
+        monitor
+            .add_breakpoint(Breakpoint::new(
+                target,
+                BreakpointCondition::Test(Comparison::Equal, 0x42),
+            ))
+            .unwrap();
+        gb.set_memory_u8(0xC011, 0x42).unwrap();
+
+        gb.write_register_u16(registers::WordRegister::HL, 0xC000);
+        assert!(!monitor.poll(&gb));
+        gb.write_register_u16(registers::WordRegister::HL, 0xC010);
+        assert!(monitor.poll(&gb));
+        assert_eq!(target.to_string(), "memory location [HL+1h]");
+    }
+
+    #[test]
+    fn test_indirect_read_write_unsupported() {
+        let mut monitor = DebugMonitor::new();
+        let target: RWTarget = "[HL]".parse().unwrap();
+
+        for condition in [BreakpointCondition::Read, BreakpointCondition::Write] {
+            let err = monitor
+                .add_breakpoint(Breakpoint::new(target, condition))
+                .unwrap_err();
+            assert_eq!(err, UnsupportedBreakpoint(condition, target));
+        }
+        assert_eq!(
+            UnsupportedBreakpoint(BreakpointCondition::Write, target).to_string(),
+            "Cannot break on Write for memory location [HL]"
+        );
+        assert!(monitor.breakpoints().is_empty());
+        assert!(monitor
+            .add_breakpoint(Breakpoint::new(target, BreakpointCondition::Changed))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_parse_indirect_target() {
+        assert_eq!(
+            "[sp - 2]".parse::<RWTarget>().unwrap(),
+            RWTarget::Indirect(IndirectAddress::new(registers::WordRegister::SP, -2))
+        );
+        assert_eq!(
+            "[HL]".parse::<RWTarget>().unwrap().to_string(),
+            "memory location [HL]"
+        );
+        // Other dereferences are read once, when parsed
+        assert!("[HL*2]".parse::<RWTarget>().is_err());
+    }
+
+    #[test]
+    fn test_poll_changed() {
+        let mut gb = make_mbc1_gameboy();
+        let mut monitor = DebugMonitor::new();
+        let target = RWTarget::WordRegister(registers::WordRegister::SP);
+        monitor
+            .add_breakpoint(Breakpoint::new(target, BreakpointCondition::Changed))
+            .unwrap();
+
+        assert!(!monitor.poll(&gb));
+        assert!(!monitor.poll(&gb));
+
+        gb.write_register_u16(registers::WordRegister::SP, 0x1234);
+        assert!(monitor.poll(&gb));
+        match monitor.state() {
+            BreakpointState::HitBreakpoint(bp) => {
+                assert_eq!(bp.monitor, target);
+                assert_eq!(bp.condition, BreakpointCondition::Changed);
+            }
+            state => panic!("Expected breakpoint hit, got {:?}", state),
+        }
+
+        monitor.resume();
+        assert!(!monitor.poll(&gb));
+    }
+
+    #[test]
+    fn test_poll_interrupt() {
+        let mut data = vec![0u8; 0x8000];
+        data[0x100] = 0xFB; // EI
+        let mut gb = GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy);
+        let mut monitor = DebugMonitor::new();
+        monitor
+            .add_breakpoint(Breakpoint::interrupt(gameboy::Interrupt::Timer))
+            .unwrap();
+        gb.set_memory_u8(0xFFFF, 0x05).unwrap();
+        gb.set_memory_u8(0xFF0F, 0x04).unwrap();
+
+        gb.step().unwrap();
+        assert!(!monitor.poll(&gb));
+        gb.step().unwrap();
+        assert!(!monitor.poll(&gb));
+        gb.step().unwrap();
+        assert!(monitor.poll(&gb));
+        assert_eq!(gb.read_register_u16(registers::WordRegister::PC), 0x50);
+        assert_eq!(
+            monitor.state(),
+            BreakpointState::HitBreakpoint(Breakpoint::interrupt(gameboy::Interrupt::Timer))
+        );
+        assert_eq!(
+            Breakpoint::interrupt(gameboy::Interrupt::Timer).to_string(),
+            "Breakpoint: Timer interrupt"
+        );
+    }
+
+    #[test]
+    fn test_poll_function_return() {
+        let mut data = vec![0u8; 0x8000];
+        data[0x100..0x103].copy_from_slice(&[0xCD, 0x00, 0x02]); // CALL 200h
+        data[0x200..0x204].copy_from_slice(&[0xCD, 0x00, 0x03, 0xC9]); // CALL 300h, RET
+        data[0x300] = 0xC9; // RET
+        let mut gb = GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy);
+        let mut monitor = DebugMonitor::new();
+        gb.step().unwrap();
+
+        let mut finish = Breakpoint::function_return().temporary();
+        assert!(!finish.check(&gb));
+        monitor.add_breakpoint(finish).unwrap();
+        monitor
+            .add_breakpoint(Breakpoint::new(
+                RWTarget::Address(0x4000.into()),
+                BreakpointCondition::Execute,
+            ))
+            .unwrap();
+        monitor
+            .add_breakpoint(
+                Breakpoint::new(
+                    RWTarget::Address(0x5000.into()),
+                    BreakpointCondition::Execute,
+                )
+                .temporary(),
+            )
+            .unwrap();
+
+        gb.step().unwrap();
+        assert!(!monitor.poll(&gb));
+        gb.step().unwrap();
+        assert!(!monitor.poll(&gb));
+        gb.step().unwrap();
+        assert!(monitor.poll(&gb));
+        assert_eq!(gb.read_register_u16(registers::WordRegister::PC), 0x103);
+        assert_eq!(monitor.breakpoints().len(), 1);
+        assert_eq!(
+            Breakpoint::function_return().to_string(),
+            "Breakpoint: return to caller"
+        );
+    }
+
+    #[test]
+    fn test_blocked_write_does_not_break() {
+        let mut data = vec![0u8; 0x8000];
+        data[0x100..0x10A].copy_from_slice(&[
+            0x21, 0x00, 0x80, // LD HL, 8000h
+            0x36, 0x56, // LD (HL), 56h
+            0x21, 0x00, 0xFE, // LD HL, FE00h
+            0x36, 0x56, // LD (HL), 56h
+        ]);
+        let cartridge = Cartridge::from_data(data).unwrap();
+        let mut gb = GameBoy::new(cartridge, GameBoyModel::GameBoy);
+        let events = Shared::new(Lock::new(Vec::new()));
+        let handler_events = Shared::clone(&events);
+        gb.events.on(Box::new(move |evt| {
+            if let Event::Memory(MemoryEvent::Write { .. } | MemoryEvent::BlockedWrite { .. }) = evt
+            {
+                handler_events.borrow_mut().push(evt.clone())
+            }
+        }));
+        let mut monitor = DebugMonitor::new();
+        monitor
+            .add_breakpoint(Breakpoint::new(
+                RWTarget::Address(0x8000.into()),
+                BreakpointCondition::Test(Comparison::Equal, 0x56),
+            ))
+            .unwrap();
+        monitor
+            .add_breakpoint(Breakpoint::new(
+                RWTarget::Address(0xFE00.into()),
+                BreakpointCondition::Write,
+            ))
+            .unwrap();
+
+        for _ in 0..2 {
+            gb.step().unwrap();
+            // The PPU locks VRAM and OAM while drawing
+            gb.mem.registers_mut().lcdstat = 0b11;
+            gb.step().unwrap();
+        }
+        assert_eq!(gb.get_memory_u8(0x8000), Ok(0));
+
+        let events = events.borrow();
+        assert_eq!(
+            *events,
+            vec![
+                Event::from(MemoryEvent::blocked_write(0x8000.into(), 0x56)),
+                Event::from(MemoryEvent::blocked_write(0xFE00.into(), 0x56)),
+            ]
+        );
+        for evt in events.iter() {
+            assert!(!monitor.handle_event(evt));
+        }
+    }
+
+    #[test]
+    fn test_debugger_access_ignores_ppu_lock() {
+        let mut gb = make_mbc1_gameboy();
+        let target = RWTarget::Address(0x8000.into());
+
+        gb.mem.registers_mut().lcdstat = 0b11;
+        target.write(&mut gb, 0x12).unwrap();
+        assert_eq!(target.read(&gb).unwrap(), 0x12);
+    }
+
+    #[test]
+    fn test_index_follows_breakpoint_changes() {
+        let mut monitor = DebugMonitor::new();
+        let write = Event::from(MemoryEvent::write(0xC000.into(), 0x12, 0x12));
+        let register_write =
+            Event::from(RegisterWriteEvent::new(registers::WordRegister::BC, 0x1234));
+        let write_id = monitor
+            .add_breakpoint(Breakpoint::new(
+                RWTarget::Address(0xC000.into()),
+                BreakpointCondition::Write,
+            ))
+            .unwrap();
+        monitor
+            .add_breakpoint(Breakpoint::new(
+                RWTarget::ByteRegister(registers::ByteRegister::B),
+                BreakpointCondition::Test(Comparison::Equal, 0x1234),
+            ))
+            .unwrap();
+
+        assert!(!monitor.handle_event(&MemoryEvent::write(0xC001.into(), 0, 0).into()));
+        assert!(monitor.handle_event(&write));
+        monitor.resume();
+        assert!(monitor.handle_event(&register_write));
+        monitor.resume();
+
+        monitor.set_breakpoint_state(write_id, false);
+        assert!(!monitor.handle_event(&write));
+        monitor.set_breakpoint_state(write_id, true);
+        assert!(monitor.handle_event(&write));
+        monitor.resume();
+
+        monitor.remove_breakpoint(write_id);
+        assert!(!monitor.handle_event(&write));
+        // Indexes shift down after removing a breakpoint
+        assert!(monitor.handle_event(&register_write));
+    }
+}
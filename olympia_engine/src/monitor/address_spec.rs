@@ -0,0 +1,177 @@
+use super::{EvalError, Expression, ExpressionParseError, RWTarget, SymbolTable};
+use crate::address;
+use crate::gameboy;
+
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+
+/// Regions where the bank of a symbol matters, as several banks can be mapped there
+const SWITCHABLE_REGIONS: [(u16, u16); 2] = [(0x4000, 0x7FFF), (0xA000, 0xBFFF)];
+
+/// A memory location typed by a user, such as `0x150`, `3:4123`, `LoadLevel+2` or `PC+2`
+///
+/// The address is an [`Expression`], optionally preceded by a bank and `:`.
+/// A plain number after a bank is hexadecimal, as in `3:4123`, while
+/// numbers in arithmetic use the formats of [`parse_number`]. Without an
+/// explicit bank, an address relative to a symbol in switchable ROM or
+/// cartridge RAM uses the bank the symbol is in.
+///
+/// This is the syntax the CLI debugger and the GTK address entries accept.
+/// Use [`RWTarget::parse_with`] to also accept registers.
+///
+/// [`Expression`]: enum.Expression.html
+/// [`parse_number`]: fn.parse_number.html
+/// [`RWTarget::parse_with`]: enum.RWTarget.html#method.parse_with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressSpec {
+    /// The bank given before the `:`, if any
+    pub bank: Option<u16>,
+    pub expr: Expression,
+}
+
+impl AddressSpec {
+    /// Find the memory location this refers to
+    ///
+    /// Without an emulator, the address can only use constants and symbols.
+    pub fn resolve(
+        &self,
+        gb: Option<&gameboy::GameBoy>,
+        symbols: &SymbolTable,
+    ) -> Result<RWTarget, EvalError> {
+        let value = self.expr.evaluate_in(gb, symbols)?;
+        let addr = u16::try_from(value).map_err(|_| EvalError::InvalidAddress(value))?;
+        let bank = self.bank.or_else(|| {
+            let switchable = SWITCHABLE_REGIONS
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&addr));
+            let symbol = self.expr.base_symbol().and_then(|name| symbols.get(name))?;
+            Some(symbol.bank).filter(|_| switchable)
+        });
+        Ok(match bank {
+            Some(bank) => RWTarget::BankedAddress(address::BankedAddress::new(bank, addr)),
+            None => RWTarget::Address(address::LiteralAddress(addr)),
+        })
+    }
+}
+
+impl fmt::Display for AddressSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:X}:{}", bank, self.expr),
+            None => write!(f, "{}", self.expr),
+        }
+    }
+}
+
+impl FromStr for AddressSpec {
+    type Err = ExpressionParseError;
+
+    fn from_str(s: &str) -> Result<AddressSpec, ExpressionParseError> {
+        if let Ok(banked) = s.trim().parse::<address::BankedAddress>() {
+            return Ok(AddressSpec {
+                bank: Some(banked.bank),
+                expr: Expression::Constant(banked.addr.0),
+            });
+        }
+        let (bank, expr) = match s.split_once(':') {
+            Some((bank, expr)) => {
+                let bank = bank.trim();
+                let bank = u16::from_str_radix(bank, 16)
+                    .map_err(|_| ExpressionParseError::InvalidValue(bank.into()))?;
+                (Some(bank), expr)
+            }
+            None => (None, s),
+        };
+        Ok(AddressSpec {
+            bank,
+            expr: expr.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{GameBoy, GameBoyModel};
+    use crate::registers::WordRegister as wr;
+    use crate::rom;
+    use alloc::string::ToString;
+
+    fn symbols() -> SymbolTable {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("LoadLevel", address::BankedAddress::new(3, 0x4123));
+        symbols.insert("wPlayerX", address::BankedAddress::new(0, 0xC0A0));
+        symbols
+    }
+
+    fn resolve(src: &str) -> Result<RWTarget, EvalError> {
+        src.parse::<AddressSpec>()
+            .unwrap()
+            .resolve(None, &symbols())
+    }
+
+    #[test]
+    fn test_resolve_constant() {
+        assert_eq!(
+            resolve("0x150 + 4"),
+            Ok(RWTarget::Address(address::LiteralAddress(0x154)))
+        );
+        assert_eq!(
+            resolve("3:4123"),
+            Ok(RWTarget::BankedAddress(address::BankedAddress::new(
+                3, 0x4123
+            )))
+        );
+        assert_eq!(
+            resolve("03:0x4000 + 0x10"),
+            Ok(RWTarget::BankedAddress(address::BankedAddress::new(
+                3, 0x4010
+            )))
+        );
+        assert_eq!(
+            resolve("0xFFFF + 1"),
+            Err(EvalError::InvalidAddress(0x10000))
+        );
+    }
+
+    #[test]
+    fn test_resolve_symbol() {
+        assert_eq!(
+            resolve("LoadLevel + 2"),
+            Ok(RWTarget::BankedAddress(address::BankedAddress::new(
+                3, 0x4125
+            )))
+        );
+        assert_eq!(
+            resolve("4:LoadLevel"),
+            Ok(RWTarget::BankedAddress(address::BankedAddress::new(
+                4, 0x4123
+            )))
+        );
+        assert_eq!(
+            resolve("wPlayerX"),
+            Ok(RWTarget::Address(address::LiteralAddress(0xC0A0)))
+        );
+        assert_eq!(
+            resolve("Missing"),
+            Err(EvalError::UnknownSymbol("Missing".into()))
+        );
+        assert!("nope:4000".parse::<AddressSpec>().is_err());
+    }
+
+    #[test]
+    fn test_resolve_registers() {
+        let cartridge = rom::Cartridge::from_data(vec![0u8; 0x8000]).unwrap();
+        let mut gb = GameBoy::new(cartridge, GameBoyModel::GameBoy);
+        gb.write_register_u16(wr::PC, 0x200);
+        let spec: AddressSpec = "PC+2".parse().unwrap();
+
+        assert_eq!(
+            spec.resolve(Some(&gb), &symbols()),
+            Ok(RWTarget::Address(address::LiteralAddress(0x202)))
+        );
+        assert_eq!(spec.resolve(None, &symbols()), Err(EvalError::NoEmulator));
+        assert_eq!(spec.to_string(), "(PC + 2h)");
+    }
+}
@@ -0,0 +1,123 @@
+use crate::events::{BankSwitchEvent, Event, TimestampedEvent};
+
+use alloc::collections::VecDeque;
+
+/// The most recent bank switches made by a cartridge controller
+///
+/// Pass every event from `GameBoy::events` to [`record`], using
+/// `EventEmitter::on_timestamped` so each switch is stored with the CPU
+/// clock it happened at. Only the most recent `limit` switches are kept.
+///
+/// ```
+/// use olympia_engine::events::BankKind;
+/// use olympia_engine::gameboy::{GameBoy, GameBoyModel};
+/// use olympia_engine::monitor::BankHistory;
+/// use olympia_engine::rom::Cartridge;
+/// use std::sync::{Arc, Mutex};
+///
+/// let mut data = vec![0u8; 0x10000];
+/// data[0x147] = 1; // MBC1
+/// data[0x100..0x105].copy_from_slice(&[0x3E, 0x03, 0xEA, 0x00, 0x20]); // LD A, 3; LD (2000h), A
+/// let mut gb = GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy);
+/// let history = Arc::new(Mutex::new(BankHistory::new(16)));
+/// let handler_history = history.clone();
+/// gb.events
+///     .on_timestamped(Box::new(move |evt| handler_history.lock().unwrap().record(evt)));
+///
+/// gb.step().unwrap();
+/// gb.step().unwrap();
+/// let history = history.lock().unwrap();
+/// let switch = history.iter().next().unwrap();
+/// assert_eq!(switch.event.kind, BankKind::SwitchableRom);
+/// assert_eq!((switch.event.old, switch.event.new), (1, 3));
+/// ```
+///
+/// [`record`]: #method.record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BankHistory {
+    limit: usize,
+    switches: VecDeque<TimestampedEvent<BankSwitchEvent>>,
+}
+
+impl BankHistory {
+    /// Create a history keeping at most `limit` switches
+    pub fn new(limit: usize) -> BankHistory {
+        BankHistory {
+            limit,
+            switches: VecDeque::new(),
+        }
+    }
+
+    /// Store the event if it is a bank switch
+    pub fn record(&mut self, event: &TimestampedEvent<Event>) {
+        let switch = match event.event {
+            Event::BankSwitch(switch) => switch,
+            _ => return,
+        };
+        if self.limit == 0 {
+            return;
+        }
+        if self.switches.len() == self.limit {
+            self.switches.pop_front();
+        }
+        self.switches.push_back(TimestampedEvent {
+            timestamp: event.timestamp,
+            event: switch,
+        });
+    }
+
+    /// Recorded switches, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &TimestampedEvent<BankSwitchEvent>> {
+        self.switches.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.switches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.switches.is_empty()
+    }
+
+    /// Forget all recorded switches
+    pub fn clear(&mut self) {
+        self.switches.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{BankKind, EventTimestamp, RumbleEvent};
+    use alloc::vec::Vec;
+
+    fn timestamped(clocks: u64, event: Event) -> TimestampedEvent<Event> {
+        TimestampedEvent {
+            timestamp: EventTimestamp {
+                sequence: clocks,
+                clocks,
+            },
+            event,
+        }
+    }
+
+    #[test]
+    fn test_keeps_most_recent() {
+        let mut history = BankHistory::new(2);
+
+        for bank in 2..5 {
+            let switch = BankSwitchEvent::new(bank - 1, bank, BankKind::SwitchableRom);
+            history.record(&timestamped(u64::from(bank), switch.into()));
+        }
+        history.record(&timestamped(10, RumbleEvent::new(true).into()));
+
+        let switches: Vec<_> = history
+            .iter()
+            .map(|switch| (switch.timestamp.clocks, switch.event.new))
+            .collect();
+        assert_eq!(switches, vec![(3, 3), (4, 4)]);
+
+        history.clear();
+        assert!(history.is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::address;
+use crate::events::{Event, MemoryEvent};
+
+use alloc::vec::Vec;
+
+/// Size in bytes of the blocks memory changes are tracked in
+pub const DIRTY_BLOCK_SIZE: u16 = 64;
+
+const BLOCK_COUNT: usize = 0x10000 / DIRTY_BLOCK_SIZE as usize;
+const WORD_BITS: usize = 64;
+
+/// Tracks which 64-byte blocks of memory have been written to
+///
+/// Pass it events from [`GameBoy::events`] to record writes, and call
+/// [`take`] once per refresh to find which parts of a memory view need
+/// to be redrawn. Changes made without emitting events, such as loading
+/// a new ROM, should be recorded with [`mark_all`].
+///
+/// ```
+/// use olympia_engine::events::{Event, MemoryEvent};
+/// use olympia_engine::monitor::DirtyBlocks;
+///
+/// let mut dirty = DirtyBlocks::new();
+/// dirty.record(&Event::Memory(MemoryEvent::Write {
+///     address: 0xC042.into(),
+///     value: 0x12,
+///     new_value: 0x12,
+/// }));
+/// assert_eq!(dirty.take(), vec![0xC040]);
+/// assert!(dirty.take().is_empty());
+/// ```
+///
+/// [`GameBoy::events`]: ../gameboy/struct.GameBoy.html#structfield.events
+/// [`take`]: #method.take
+/// [`mark_all`]: #method.mark_all
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirtyBlocks {
+    bits: [u64; BLOCK_COUNT / WORD_BITS],
+}
+
+impl DirtyBlocks {
+    /// Create a tracker with no dirty blocks
+    pub fn new() -> DirtyBlocks {
+        DirtyBlocks {
+            bits: [0; BLOCK_COUNT / WORD_BITS],
+        }
+    }
+
+    /// Mark the block written to if the event is a memory write
+    pub fn record(&mut self, event: &Event) {
+        if let Event::Memory(MemoryEvent::Write { address, .. }) = event {
+            self.mark(*address);
+        }
+    }
+
+    /// Mark the block containing an address as dirty
+    pub fn mark(&mut self, address: address::LiteralAddress) {
+        let block = usize::from(address.0 / DIRTY_BLOCK_SIZE);
+        self.bits[block / WORD_BITS] |= 1 << (block % WORD_BITS);
+    }
+
+    /// Mark every block as dirty
+    pub fn mark_all(&mut self) {
+        self.bits = [u64::MAX; BLOCK_COUNT / WORD_BITS];
+    }
+
+    /// Whether the block containing an address has been written to
+    pub fn is_dirty(&self, address: address::LiteralAddress) -> bool {
+        let block = usize::from(address.0 / DIRTY_BLOCK_SIZE);
+        self.bits[block / WORD_BITS] & (1 << (block % WORD_BITS)) != 0
+    }
+
+    /// Start addresses of dirty blocks, in address order
+    pub fn dirty_blocks(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..BLOCK_COUNT)
+            .filter(move |block| self.bits[block / WORD_BITS] & (1 << (block % WORD_BITS)) != 0)
+            .map(|block| block as u16 * DIRTY_BLOCK_SIZE)
+    }
+
+    /// Start addresses of dirty blocks, in address order, clearing them
+    pub fn take(&mut self) -> Vec<u16> {
+        let blocks = self.dirty_blocks().collect();
+        self.clear();
+        blocks
+    }
+
+    /// Mark every block as clean
+    pub fn clear(&mut self) {
+        self.bits = [0; BLOCK_COUNT / WORD_BITS];
+    }
+}
+
+impl Default for DirtyBlocks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dirty_blocks() {
+        let mut dirty = DirtyBlocks::new();
+        dirty.record(&MemoryEvent::write(0xFFFF.into(), 0, 0).into());
+        dirty.record(&MemoryEvent::write(0x8000.into(), 0, 0).into());
+        dirty.record(&MemoryEvent::write(0x803F.into(), 0, 0).into());
+        dirty.record(&MemoryEvent::read(0xC000.into(), 0).into());
+
+        assert!(dirty.is_dirty(0x8010.into()));
+        assert!(!dirty.is_dirty(0x8040.into()));
+        assert!(!dirty.is_dirty(0xC000.into()));
+        assert_eq!(dirty.take(), vec![0x8000, 0xFFC0]);
+        assert_eq!(dirty.take(), Vec::<u16>::new());
+
+        dirty.mark_all();
+        assert_eq!(dirty.dirty_blocks().count(), 1024);
+        assert_eq!(dirty.dirty_blocks().last(), Some(0xFFC0));
+    }
+}
@@ -0,0 +1,403 @@
+use super::{parse_number, SymbolTable};
+use crate::address;
+use crate::gameboy;
+use crate::registers;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::iter::Peekable;
+use core::str::FromStr;
+use derive_more::Display;
+
+/// Arithmetic operators supported in expressions
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    #[display(fmt = "+")]
+    Add,
+    #[display(fmt = "-")]
+    Subtract,
+    #[display(fmt = "*")]
+    Multiply,
+    #[display(fmt = "/")]
+    Divide,
+    #[display(fmt = "&")]
+    And,
+    #[display(fmt = "|")]
+    Or,
+}
+
+impl Operator {
+    fn apply(self, left: i64, right: i64) -> Result<i64, EvalError> {
+        Ok(match self {
+            Operator::Add => left.wrapping_add(right),
+            Operator::Subtract => left.wrapping_sub(right),
+            Operator::Multiply => left.wrapping_mul(right),
+            Operator::Divide => left.checked_div(right).ok_or(EvalError::DivideByZero)?,
+            Operator::And => left & right,
+            Operator::Or => left | right,
+        })
+    }
+}
+
+/// An expression over registers, memory and constants
+///
+/// Expressions support `+`, `-`, `*`, `/`, `&` and `|` with the usual
+/// precedence, parentheses, and memory dereferences such as `[HL+2]`,
+/// which read the byte at the given address. Numbers use the same formats
+/// as [`parse_number`], so hex values that look like register names
+/// need a `0x` prefix. Other names, such as `wPlayerX` or `Level.load`,
+/// are symbols, which are looked up in a [`SymbolTable`] when evaluated.
+///
+/// [`SymbolTable`]: struct.SymbolTable.html
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+pub enum Expression {
+    #[display(fmt = "{:X}h", "_0")]
+    Constant(u16),
+    #[display(fmt = "{:?}", "_0")]
+    ByteRegister(registers::ByteRegister),
+    #[display(fmt = "{:?}", "_0")]
+    WordRegister(registers::WordRegister),
+    /// The address of a symbol
+    #[display(fmt = "{}", "_0")]
+    Symbol(String),
+    /// The byte at the address given by the inner expression
+    #[display(fmt = "[{}]", "_0")]
+    Deref(Box<Expression>),
+    #[display(fmt = "({} {} {})", "_1", "_0", "_2")]
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+/// Indicates an expression could not be evaluated
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    #[display(fmt = "Division by zero")]
+    DivideByZero,
+    #[display(fmt = "{:X} is not a valid memory address", "_0")]
+    InvalidAddress(i64),
+    #[display(fmt = "Could not read from the address at {}", "_0")]
+    Memory(address::LiteralAddress),
+    #[display(fmt = "No symbol named {}", "_0")]
+    UnknownSymbol(String),
+    #[display(fmt = "Registers and memory can't be read without an emulator")]
+    NoEmulator,
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EvalError {}
+
+impl Expression {
+    /// Evaluate this expression against the current emulator state
+    ///
+    /// Memory is read without triggering read breakpoints. Symbols
+    /// can't be used, see [`evaluate_in`] to look them up.
+    ///
+    /// [`evaluate_in`]: #method.evaluate_in
+    pub fn evaluate(&self, gb: &gameboy::GameBoy) -> Result<i64, EvalError> {
+        self.evaluate_in(Some(gb), &SymbolTable::new())
+    }
+
+    /// Evaluate this expression, looking up symbols in `symbols`
+    ///
+    /// Without an emulator, only constants and symbols can be used, which
+    /// lets frontends resolve addresses without waiting on the emulator.
+    pub fn evaluate_in(
+        &self,
+        gb: Option<&gameboy::GameBoy>,
+        symbols: &SymbolTable,
+    ) -> Result<i64, EvalError> {
+        match self {
+            Expression::Constant(value) => Ok(i64::from(*value)),
+            Expression::ByteRegister(reg) => gb
+                .map(|gb| i64::from(gb.read_register_u8(*reg)))
+                .ok_or(EvalError::NoEmulator),
+            Expression::WordRegister(reg) => gb
+                .map(|gb| i64::from(gb.read_register_u16(*reg)))
+                .ok_or(EvalError::NoEmulator),
+            Expression::Symbol(name) => symbols
+                .get(name)
+                .map(|addr| i64::from(addr.addr.0))
+                .ok_or_else(|| EvalError::UnknownSymbol(name.clone())),
+            Expression::Deref(inner) => {
+                let value = inner.evaluate_in(gb, symbols)?;
+                let addr = u16::try_from(value).map_err(|_| EvalError::InvalidAddress(value))?;
+                gb.ok_or(EvalError::NoEmulator)?
+                    .get_memory_u8(addr)
+                    .map(i64::from)
+                    .map_err(|_| EvalError::Memory(addr.into()))
+            }
+            Expression::Binary(op, left, right) => op.apply(
+                left.evaluate_in(gb, symbols)?,
+                right.evaluate_in(gb, symbols)?,
+            ),
+        }
+    }
+
+    /// The symbol an address is relative to, such as `Level` in `Level + 4`
+    pub(crate) fn base_symbol(&self) -> Option<&str> {
+        match self {
+            Expression::Symbol(name) => Some(name),
+            Expression::Binary(Operator::Add, left, right) => {
+                left.base_symbol().or_else(|| right.base_symbol())
+            }
+            Expression::Binary(Operator::Subtract, left, _) => left.base_symbol(),
+            _ => None,
+        }
+    }
+}
+
+/// Indicates a value could not be parsed as an expression
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+pub enum ExpressionParseError {
+    #[display(fmt = "Unexpected character '{}'", "_0")]
+    UnexpectedChar(char),
+    #[display(fmt = "Unexpected '{}'", "_0")]
+    UnexpectedToken(Token),
+    #[display(fmt = "{} is not a register, number or symbol", "_0")]
+    InvalidValue(String),
+    #[display(fmt = "Unexpected end of expression")]
+    UnexpectedEnd,
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ExpressionParseError {}
+
+/// A single piece of an expression
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+pub enum Token {
+    #[display(fmt = "{}", "_0")]
+    Value(String),
+    #[display(fmt = "{}", "_0")]
+    Operator(Operator),
+    #[display(fmt = "(")]
+    OpenParen,
+    #[display(fmt = ")")]
+    CloseParen,
+    #[display(fmt = "[")]
+    OpenBracket,
+    #[display(fmt = "]")]
+    CloseBracket,
+}
+
+/// Characters in numbers, registers and symbol names
+fn is_value_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, ExpressionParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '+' => Token::Operator(Operator::Add),
+            '-' => Token::Operator(Operator::Subtract),
+            '*' => Token::Operator(Operator::Multiply),
+            '/' => Token::Operator(Operator::Divide),
+            '&' => Token::Operator(Operator::And),
+            '|' => Token::Operator(Operator::Or),
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            c if is_value_char(c) => {
+                let mut value = String::new();
+                value.push(c);
+                while let Some(next) = chars.peek().filter(|next| is_value_char(**next)) {
+                    value.push(*next);
+                    chars.next();
+                }
+                Token::Value(value)
+            }
+            c => return Err(ExpressionParseError::UnexpectedChar(c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_value(src: &str) -> Result<Expression, ExpressionParseError> {
+    let upper = src.to_uppercase();
+    if let Ok(reg) = upper.parse::<registers::WordRegister>() {
+        Ok(Expression::WordRegister(reg))
+    } else if let Ok(reg) = upper.parse::<registers::ByteRegister>() {
+        Ok(Expression::ByteRegister(reg))
+    } else if let Ok(value) = parse_number(src) {
+        Ok(Expression::Constant(value))
+    } else if src.starts_with(|c: char| c.is_ascii_digit()) {
+        Err(ExpressionParseError::InvalidValue(src.into()))
+    } else {
+        Ok(Expression::Symbol(src.into()))
+    }
+}
+
+struct Parser<I: Iterator<Item = Token>> {
+    tokens: Peekable<I>,
+}
+
+impl<I: Iterator<Item = Token>> Parser<I> {
+    /// Operators that can appear at each precedence level, loosest first
+    const PRECEDENCE: [&'static [Operator]; 4] = [
+        &[Operator::Or],
+        &[Operator::And],
+        &[Operator::Add, Operator::Subtract],
+        &[Operator::Multiply, Operator::Divide],
+    ];
+
+    fn expect(&mut self, expected: Token) -> Result<(), ExpressionParseError> {
+        match self.tokens.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(ExpressionParseError::UnexpectedToken(token)),
+            None => Err(ExpressionParseError::UnexpectedEnd),
+        }
+    }
+
+    fn parse_level(&mut self, level: usize) -> Result<Expression, ExpressionParseError> {
+        let operators = match Self::PRECEDENCE.get(level) {
+            Some(operators) => operators,
+            None => return self.parse_atom(),
+        };
+        let mut expr = self.parse_level(level + 1)?;
+        while let Some(Token::Operator(op)) = self.tokens.peek() {
+            let op = *op;
+            if !operators.contains(&op) {
+                break;
+            }
+            self.tokens.next();
+            let right = self.parse_level(level + 1)?;
+            expr = Expression::Binary(op, Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_atom(&mut self) -> Result<Expression, ExpressionParseError> {
+        match self.tokens.next() {
+            Some(Token::Value(value)) => parse_value(&value),
+            Some(Token::OpenParen) => {
+                let expr = self.parse_level(0)?;
+                self.expect(Token::CloseParen)?;
+                Ok(expr)
+            }
+            Some(Token::OpenBracket) => {
+                let expr = self.parse_level(0)?;
+                self.expect(Token::CloseBracket)?;
+                Ok(Expression::Deref(Box::new(expr)))
+            }
+            Some(token) => Err(ExpressionParseError::UnexpectedToken(token)),
+            None => Err(ExpressionParseError::UnexpectedEnd),
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ExpressionParseError;
+
+    fn from_str(s: &str) -> Result<Expression, ExpressionParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let expr = parser.parse_level(0)?;
+        match parser.tokens.next() {
+            Some(token) => Err(ExpressionParseError::UnexpectedToken(token)),
+            None => Ok(expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{GameBoy, GameBoyModel};
+    use crate::registers::WordRegister as wr;
+    use crate::rom;
+
+    fn make_gameboy() -> GameBoy {
+        let cartridge = rom::Cartridge::from_data(vec![0u8; 0x8000]).unwrap();
+        GameBoy::new(cartridge, GameBoyModel::GameBoy)
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let expr: Expression = "[HL+2] + BC*2 | 0x10".parse().unwrap();
+        assert_eq!(format!("{}", expr), "(([(HL + 2h)] + (BC * 2h)) | 10h)");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            "BC +".parse::<Expression>(),
+            Err(ExpressionParseError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "[HL".parse::<Expression>(),
+            Err(ExpressionParseError::UnexpectedEnd)
+        );
+        assert_eq!(
+            "BC % 2".parse::<Expression>(),
+            Err(ExpressionParseError::UnexpectedChar('%'))
+        );
+        assert_eq!(
+            "12XY".parse::<Expression>(),
+            Err(ExpressionParseError::InvalidValue("12XY".into()))
+        );
+        assert_eq!(
+            "(A) B".parse::<Expression>(),
+            Err(ExpressionParseError::UnexpectedToken(Token::Value(
+                "B".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut gb = make_gameboy();
+        gb.write_register_u16(wr::HL, 0xC000);
+        gb.write_register_u16(wr::BC, 0x0010);
+        gb.set_memory_u8(0xC002, 0x25).unwrap();
+
+        let expr: Expression = "[HL+2] + BC*2".parse().unwrap();
+        assert_eq!(expr.evaluate(&gb), Ok(0x45));
+        let expr: Expression = "(2 - BC) / 2".parse().unwrap();
+        assert_eq!(expr.evaluate(&gb), Ok(-7));
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let gb = make_gameboy();
+
+        let expr: Expression = "BC / (A - A)".parse().unwrap();
+        assert_eq!(expr.evaluate(&gb), Err(EvalError::DivideByZero));
+        let expr: Expression = "[0xFFFF + 1]".parse().unwrap();
+        assert_eq!(expr.evaluate(&gb), Err(EvalError::InvalidAddress(0x10000)));
+        let expr: Expression = "[0xFF4C]".parse().unwrap();
+        assert_eq!(
+            expr.evaluate(&gb),
+            Err(EvalError::Memory(address::LiteralAddress(0xFF4C)))
+        );
+        let expr: Expression = "XY + 2".parse().unwrap();
+        assert_eq!(
+            expr.evaluate(&gb),
+            Err(EvalError::UnknownSymbol("XY".into()))
+        );
+        let expr: Expression = "[0xC000] + 2".parse().unwrap();
+        assert_eq!(
+            expr.evaluate_in(None, &SymbolTable::new()),
+            Err(EvalError::NoEmulator)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_symbols() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("wPlayer.x", address::BankedAddress::new(0, 0xC0A0));
+        let mut gb = make_gameboy();
+        gb.set_memory_u8(0xC0A2, 0x12).unwrap();
+
+        let expr: Expression = "[wPlayer.x + 2] + 1".parse().unwrap();
+        assert_eq!(expr.evaluate_in(Some(&gb), &symbols), Ok(0x13));
+        let expr: Expression = "wPlayer.x - 0x10".parse().unwrap();
+        assert_eq!(expr.evaluate_in(None, &symbols), Ok(0xC090));
+        assert_eq!(expr.base_symbol(), Some("wPlayer.x"));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::address;
+use crate::events::{Event, MemoryEvent};
+
+use alloc::vec::Vec;
+use core::fmt;
+
+const ADDRESS_COUNT: usize = 0x10000;
+
+/// How many times an address was read and written
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AccessCount {
+    pub reads: u32,
+    pub writes: u32,
+}
+
+/// Counts reads and writes to each address in the memory map
+///
+/// Pass it events from [`GameBoy::events`] to record them. Instruction fetches
+/// are reads, so frequently run code shows up as well as frequently used
+/// variables. Accesses made by a debugger without emitting events, such as
+/// `GameBoy::get_memory_u8`, are not counted.
+///
+/// ```
+/// use olympia_engine::events::{Event, MemoryEvent};
+/// use olympia_engine::monitor::AccessHeatmap;
+///
+/// let mut heatmap = AccessHeatmap::new();
+/// heatmap.record(&Event::Memory(MemoryEvent::Read {
+///     address: 0xC000.into(),
+///     value: 0x12,
+/// }));
+/// assert_eq!(heatmap.get(0xC000.into()).reads, 1);
+/// ```
+///
+/// [`GameBoy::events`]: ../gameboy/struct.GameBoy.html#structfield.events
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessHeatmap {
+    counts: Vec<AccessCount>,
+}
+
+impl AccessHeatmap {
+    /// Create a heatmap with no recorded accesses
+    pub fn new() -> AccessHeatmap {
+        AccessHeatmap {
+            counts: vec![AccessCount::default(); ADDRESS_COUNT],
+        }
+    }
+
+    /// Count the event if it is a memory access
+    ///
+    /// Counts stop increasing once they reach `u32::MAX`.
+    pub fn record(&mut self, event: &Event) {
+        match event {
+            Event::Memory(MemoryEvent::Read { address, .. }) => {
+                let count = &mut self.counts[usize::from(address.0)];
+                count.reads = count.reads.saturating_add(1);
+            }
+            Event::Memory(MemoryEvent::Write { address, .. }) => {
+                let count = &mut self.counts[usize::from(address.0)];
+                count.writes = count.writes.saturating_add(1);
+            }
+            _ => {}
+        }
+    }
+
+    /// Accesses recorded for an address
+    pub fn get(&self, address: address::LiteralAddress) -> AccessCount {
+        self.counts[usize::from(address.0)]
+    }
+
+    /// Forget all recorded accesses
+    pub fn clear(&mut self) {
+        for count in self.counts.iter_mut() {
+            *count = AccessCount::default();
+        }
+    }
+
+    /// Addresses which have been accessed at least once, in address order
+    pub fn accessed(&self) -> impl Iterator<Item = (address::LiteralAddress, AccessCount)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count != AccessCount::default())
+            .map(|(addr, count)| (address::LiteralAddress(addr as u16), *count))
+    }
+
+    /// Write accessed addresses as CSV, with an `address,reads,writes` header
+    ///
+    /// Addresses are written in hex without a prefix, such as `C000`.
+    pub fn write_csv(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+        writeln!(out, "address,reads,writes")?;
+        for (addr, count) in self.accessed() {
+            writeln!(out, "{:04X},{},{}", addr.0, count.reads, count.writes)?;
+        }
+        Ok(())
+    }
+
+    /// Write accessed addresses as a JSON array of objects
+    ///
+    /// Each object has `address`, `reads` and `writes` keys. Addresses are
+    /// hex strings, the same as in the CSV output.
+    pub fn write_json(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+        write!(out, "[")?;
+        for (i, (addr, count)) in self.accessed().enumerate() {
+            if i != 0 {
+                write!(out, ",")?;
+            }
+            write!(
+                out,
+                "\n  {{\"address\": \"{:04X}\", \"reads\": {}, \"writes\": {}}}",
+                addr.0, count.reads, count.writes
+            )?;
+        }
+        writeln!(out, "\n]")
+    }
+}
+
+impl Default for AccessHeatmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{GameBoy, GameBoyModel};
+    use crate::rom::Cartridge;
+    use crate::shared::{Lock, Shared};
+    use alloc::boxed::Box;
+    use alloc::string::String;
+
+    #[test]
+    fn test_records_gameboy_accesses() {
+        let mut data = vec![0u8; 0x8000];
+        data[0x100..0x104].copy_from_slice(&[0xEA, 0x00, 0xC0, 0x00]); // LD [C000h], A; NOP
+        let mut gb = GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy);
+        let heatmap = Shared::new(Lock::new(AccessHeatmap::new()));
+        let handler_heatmap = heatmap.clone();
+        gb.events.on(Box::new(move |evt| {
+            handler_heatmap.borrow_mut().record(evt)
+        }));
+
+        gb.step().unwrap();
+        gb.step().unwrap();
+
+        let heatmap = heatmap.borrow();
+        assert_eq!(
+            heatmap.get(0x100.into()),
+            AccessCount {
+                reads: 1,
+                writes: 0
+            }
+        );
+        assert_eq!(
+            heatmap.get(0xC000.into()),
+            AccessCount {
+                reads: 0,
+                writes: 1
+            }
+        );
+        // The operand and the following NOP were fetched too
+        assert_eq!(heatmap.get(0x102.into()).reads, 1);
+        assert_eq!(heatmap.get(0x103.into()).reads, 1);
+    }
+
+    #[test]
+    fn test_export() {
+        let mut heatmap = AccessHeatmap::new();
+        heatmap.record(&MemoryEvent::read(0xC000.into(), 0).into());
+        heatmap.record(&MemoryEvent::read(0xC000.into(), 0).into());
+        heatmap.record(&MemoryEvent::write(0xFF80.into(), 1, 1).into());
+
+        let mut csv = String::new();
+        heatmap.write_csv(&mut csv).unwrap();
+        assert_eq!(csv, "address,reads,writes\nC000,2,0\nFF80,0,1\n");
+
+        let mut json = String::new();
+        heatmap.write_json(&mut json).unwrap();
+        assert_eq!(
+            json,
+            concat!(
+                "[\n",
+                "  {\"address\": \"C000\", \"reads\": 2, \"writes\": 0},\n",
+                "  {\"address\": \"FF80\", \"reads\": 0, \"writes\": 1}\n",
+                "]\n"
+            )
+        );
+
+        heatmap.clear();
+        assert_eq!(heatmap.accessed().count(), 0);
+    }
+}
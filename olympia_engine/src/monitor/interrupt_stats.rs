@@ -0,0 +1,190 @@
+use crate::events::{Event, InterruptDispatchEvent};
+use crate::gameboy::Interrupt;
+
+/// Interrupts in priority order, which is also the order of their flag bits
+const INTERRUPTS: [Interrupt; 5] = [
+    Interrupt::VBlank,
+    Interrupt::LCDStatus,
+    Interrupt::Timer,
+    Interrupt::Serial,
+    Interrupt::Input,
+];
+
+/// Dispatches recorded for a single interrupt
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptSummary {
+    /// How many times the interrupt was dispatched
+    pub count: u64,
+    /// How many of the dispatches have a known latency
+    pub timed: u64,
+    /// The longest latency of any dispatch, in clocks
+    pub worst_latency: Option<u64>,
+    /// The sum of all known latencies, in clocks
+    pub total_latency: u64,
+}
+
+impl InterruptSummary {
+    /// The mean latency of dispatches with a known latency, in clocks
+    pub fn average_latency(&self) -> Option<u64> {
+        self.total_latency.checked_div(self.timed)
+    }
+}
+
+/// Counts interrupt dispatches, and how long each took to be serviced
+///
+/// Enable `GameBoy::set_interrupt_tracing`, and pass it events from
+/// [`GameBoy::events`] to record them. Latency is measured from the interrupt
+/// being requested to it being dispatched, which is delayed while interrupts
+/// are disabled or a higher priority interrupt is pending.
+///
+/// ```
+/// use olympia_engine::events::{Event, InterruptDispatchEvent};
+/// use olympia_engine::gameboy::Interrupt;
+/// use olympia_engine::monitor::InterruptStats;
+///
+/// let mut stats = InterruptStats::new();
+/// stats.record(&Event::InterruptDispatch(InterruptDispatchEvent::new(
+///     Interrupt::VBlank,
+///     70224,
+///     0x150,
+///     0x40,
+///     Some(24),
+/// )));
+/// assert_eq!(stats.get(Interrupt::VBlank).count, 1);
+/// assert_eq!(stats.get(Interrupt::VBlank).worst_latency, Some(24));
+/// ```
+///
+/// [`GameBoy::events`]: ../gameboy/struct.GameBoy.html#structfield.events
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InterruptStats {
+    summaries: [InterruptSummary; 5],
+}
+
+impl InterruptStats {
+    /// Create stats with no recorded dispatches
+    pub fn new() -> InterruptStats {
+        InterruptStats::default()
+    }
+
+    /// Count the event if it is an interrupt dispatch
+    pub fn record(&mut self, event: &Event) {
+        let dispatch: &InterruptDispatchEvent = match event {
+            Event::InterruptDispatch(dispatch) => dispatch,
+            _ => return,
+        };
+        let summary = &mut self.summaries[index(dispatch.interrupt)];
+        summary.count += 1;
+        if let Some(latency) = dispatch.latency {
+            summary.timed += 1;
+            summary.total_latency += latency;
+            summary.worst_latency = summary.worst_latency.max(Some(latency));
+        }
+    }
+
+    /// Dispatches recorded for an interrupt
+    pub fn get(&self, interrupt: Interrupt) -> InterruptSummary {
+        self.summaries[index(interrupt)]
+    }
+
+    /// Interrupts which have been dispatched at least once, in priority order
+    pub fn dispatched(&self) -> impl Iterator<Item = (Interrupt, InterruptSummary)> + '_ {
+        INTERRUPTS
+            .iter()
+            .copied()
+            .zip(self.summaries.iter().copied())
+            .filter(|(_, summary)| summary.count > 0)
+    }
+
+    /// Forget all recorded dispatches
+    pub fn clear(&mut self) {
+        self.summaries = Default::default();
+    }
+}
+
+fn index(interrupt: Interrupt) -> usize {
+    interrupt.mask().trailing_zeros() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::{GameBoy, GameBoyModel};
+    use crate::registers::WordRegister;
+    use crate::rom::Cartridge;
+    use crate::shared::{Lock, Shared};
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    fn dispatch(interrupt: Interrupt, latency: Option<u64>) -> Event {
+        InterruptDispatchEvent::new(interrupt, 0, 0, 0, latency).into()
+    }
+
+    #[test]
+    fn test_summaries() {
+        let mut stats = InterruptStats::new();
+
+        stats.record(&dispatch(Interrupt::Timer, Some(8)));
+        stats.record(&dispatch(Interrupt::Timer, Some(20)));
+        stats.record(&dispatch(Interrupt::Timer, None));
+        stats.record(&dispatch(Interrupt::VBlank, Some(4)));
+
+        let timer = stats.get(Interrupt::Timer);
+        assert_eq!(timer.count, 3);
+        assert_eq!(timer.worst_latency, Some(20));
+        assert_eq!(timer.average_latency(), Some(14));
+        assert_eq!(
+            stats
+                .dispatched()
+                .map(|(interrupt, _)| interrupt)
+                .collect::<Vec<_>>(),
+            vec![Interrupt::VBlank, Interrupt::Timer]
+        );
+
+        stats.clear();
+        assert_eq!(stats.dispatched().count(), 0);
+    }
+
+    #[test]
+    fn test_dispatch_latency() {
+        let mut gb = GameBoy::new(
+            Cartridge::from_data(vec![0u8; 0x8000]).unwrap(),
+            GameBoyModel::GameBoy,
+        );
+        let stats = Shared::new(Lock::new(InterruptStats::new()));
+        let dispatches: Shared<Lock<Vec<InterruptDispatchEvent>>> =
+            Shared::new(Lock::new(Vec::new()));
+        let handler_stats = Shared::clone(&stats);
+        let handler_dispatches = Shared::clone(&dispatches);
+        gb.events.on(Box::new(move |evt| {
+            handler_stats.borrow_mut().record(evt);
+            if let Event::InterruptDispatch(dispatch) = evt {
+                handler_dispatches.borrow_mut().push(*dispatch);
+            }
+        }));
+        gb.set_interrupt_tracing(true);
+        gb.write_register_u16(WordRegister::PC, 0xC000);
+        // DI; NOP; EI; NOP
+        gb.set_memory_chunk(0xC000, &[0xF3, 0x00, 0xFB, 0x00])
+            .unwrap();
+        gb.set_memory_u8(0xFFFF, Interrupt::Timer.mask()).unwrap();
+
+        gb.step().unwrap();
+        let requested_at = gb.clocks_elapsed();
+        gb.set_memory_u8(0xFF0F, Interrupt::Timer.mask()).unwrap();
+        for _ in 0..4 {
+            gb.step().unwrap();
+        }
+
+        let dispatches = dispatches.borrow();
+        assert_eq!(dispatches.len(), 1);
+        let dispatch = dispatches[0];
+        assert_eq!(dispatch.interrupt, Interrupt::Timer);
+        assert_eq!(dispatch.handler, 0x50);
+        assert_eq!(dispatch.interrupted_pc, 0xC004);
+        assert_eq!(dispatch.latency, Some(dispatch.clocks - requested_at));
+        assert_eq!(
+            stats.borrow().get(Interrupt::Timer).worst_latency,
+            dispatch.latency
+        );
+    }
+}
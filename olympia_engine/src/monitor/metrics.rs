@@ -0,0 +1,141 @@
+use crate::gameboy::GameBoy;
+use crate::monitor::RWTarget;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// The values of watched targets at the start of one frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetricSample {
+    /// Number of times VBlank had started when the sample was taken
+    pub frame: u64,
+    /// The value of each target, in the same order as the targets
+    ///
+    /// `None` marks a target that couldn't be read, such as a banked
+    /// address while another bank is mapped.
+    pub values: Vec<Option<u64>>,
+}
+
+/// Records the values of registers and memory locations once per frame
+///
+/// Call [`poll`] after each step. When a frame has started since the last
+/// sample, each target is read and the values stored. Only the most recent
+/// `limit` samples are kept, so frontends can plot values such as a player's
+/// health over time without an event for every change. No samples are taken
+/// while the LCD is off, as no frames start.
+///
+/// [`poll`]: #method.poll
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricsSampler {
+    targets: Vec<RWTarget>,
+    limit: usize,
+    samples: VecDeque<MetricSample>,
+    /// Frame count when the last sample was taken
+    last_frame: Option<u64>,
+}
+
+impl MetricsSampler {
+    /// Create a sampler keeping at most `limit` samples of `targets`
+    pub fn new(targets: Vec<RWTarget>, limit: usize) -> MetricsSampler {
+        MetricsSampler {
+            targets,
+            limit,
+            samples: VecDeque::new(),
+            last_frame: None,
+        }
+    }
+
+    /// The targets read in each sample
+    pub fn targets(&self) -> &[RWTarget] {
+        &self.targets
+    }
+
+    /// Take a sample if a frame has started since the last one
+    ///
+    /// Returns whether a sample was taken. Reading memory emits read events,
+    /// which aren't from the game and can be discarded.
+    pub fn poll(&mut self, gb: &GameBoy) -> bool {
+        let frame = gb.ppu.frame_count();
+        if self.last_frame == Some(frame) {
+            return false;
+        }
+        let first = self.last_frame.is_none();
+        self.last_frame = Some(frame);
+        // Sampling starts at the next frame, rather than part way through this one
+        if first || self.targets.is_empty() || self.limit == 0 {
+            return false;
+        }
+        if self.samples.len() == self.limit {
+            self.samples.pop_front();
+        }
+        let values = self
+            .targets
+            .iter()
+            .map(|target| target.read(gb).ok())
+            .collect();
+        self.samples.push_back(MetricSample { frame, values });
+        true
+    }
+
+    /// Recorded samples, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &MetricSample> {
+        self.samples.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Forget all recorded samples
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::LiteralAddress;
+    use crate::gameboy::GameBoyModel;
+    use crate::registers::WordRegister;
+    use crate::rom::Cartridge;
+
+    #[test]
+    fn test_samples_once_per_frame() {
+        // JR -2
+        let mut data = vec![0u8; 0x8000];
+        data[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        let mut gb = GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy);
+        let targets = vec![
+            RWTarget::Address(LiteralAddress(0xC000)),
+            RWTarget::WordRegister(WordRegister::PC),
+        ];
+        let mut sampler = MetricsSampler::new(targets, 2);
+
+        sampler.poll(&gb);
+        for value in 1..=3 {
+            gb.set_memory_u8(0xC000, value).unwrap();
+            let frame = gb.ppu.frame_count();
+            while gb.ppu.frame_count() == frame {
+                gb.step().unwrap();
+                sampler.poll(&gb);
+            }
+        }
+
+        let values: Vec<_> = sampler.iter().map(|sample| sample.values.clone()).collect();
+        assert_eq!(
+            values,
+            vec![vec![Some(2), Some(0x100)], vec![Some(3), Some(0x100)]]
+        );
+        let frames: Vec<_> = sampler.iter().map(|sample| sample.frame).collect();
+        assert_eq!(frames[1], frames[0] + 1);
+
+        sampler.clear();
+        assert!(sampler.is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::disassembler::decode_instructions;
+use crate::gameboy::{ErrorContext, GameBoy, StepError};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Bytes of memory shown before the row containing PC
+const MEMORY_BEFORE_PC: u16 = 0x20;
+/// Rows of 16 bytes of memory included in a report
+const MEMORY_ROWS: usize = 5;
+/// Longest instruction, so the whole instruction at a traced PC is decoded
+const MAX_INSTRUCTION_SIZE: u16 = 3;
+
+/// A step in the trace of a post-mortem report
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEntry {
+    /// PC at the start of the step
+    pub pc: u16,
+    /// The instruction at `pc`, decoded from memory when the report was made
+    pub instruction: String,
+}
+
+/// The state of a gameboy after emulation failed, for bug reports
+///
+/// The trace covers the steps that can be undone with
+/// `GameBoy::reverse_step`, so is empty unless history is enabled with
+/// `GameBoy::set_history_limit`. Write the report out with its `Display`
+/// implementation, or with [`save`] when the `std` feature is enabled.
+///
+/// ```
+/// use olympia_engine::gameboy::{GameBoy, GameBoyModel};
+/// use olympia_engine::monitor::PostMortem;
+/// use olympia_engine::rom::Cartridge;
+///
+/// let mut data = vec![0u8; 0x8000];
+/// data[0x100] = 0xD3; // Invalid opcode
+/// let mut gb = GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy);
+/// let error = gb.step().unwrap_err();
+///
+/// let report = PostMortem::capture(&gb, Some(error));
+/// assert_eq!(report.context.pc, 0x100);
+/// assert!(report.to_string().contains("invalid opcode D3"));
+/// ```
+///
+/// [`save`]: #method.save
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PostMortem {
+    /// The error emulation stopped with, if any
+    pub error: Option<StepError>,
+    /// Where the error happened, and the registers at the time
+    ///
+    /// For errors which don't record their context, this is the state
+    /// when the report was made.
+    pub context: ErrorContext,
+    pub clocks_elapsed: u64,
+    /// The most recent steps, oldest first
+    pub trace: Vec<TraceEntry>,
+    /// Address of the first byte of `memory`
+    pub memory_start: u16,
+    /// Memory around PC, with `None` for bytes which can't be read
+    pub memory: Vec<Option<u8>>,
+}
+
+impl PostMortem {
+    /// Record the state of a gameboy, which stopped with `error` if given
+    pub fn capture(gb: &GameBoy, error: Option<StepError>) -> PostMortem {
+        let context = error
+            .as_ref()
+            .and_then(StepError::context)
+            .copied()
+            .unwrap_or_else(|| gb.error_context(gb.read_pc()));
+        let read = |addr: u16| gb.get_memory_u8(addr).ok();
+        let trace = gb
+            .history_pcs()
+            .map(|pc| {
+                let bytes: Vec<u8> = (0..MAX_INSTRUCTION_SIZE)
+                    .map(|offset| read(pc.wrapping_add(offset)).unwrap_or(0xFF))
+                    .collect();
+                let instruction = decode_instructions(&bytes, pc)
+                    .into_iter()
+                    .next()
+                    .map(|instruction| instruction.text)
+                    .unwrap_or_default();
+                TraceEntry { pc, instruction }
+            })
+            .collect();
+        let memory_start = (context.pc & 0xFFF0).saturating_sub(MEMORY_BEFORE_PC);
+        let memory_len = (MEMORY_ROWS * 16).min(0x10000 - usize::from(memory_start));
+        let memory = (0..memory_len)
+            .map(|offset| read(memory_start + offset as u16))
+            .collect();
+        PostMortem {
+            error,
+            context,
+            clocks_elapsed: gb.clocks_elapsed(),
+            trace,
+            memory_start,
+            memory,
+        }
+    }
+
+    /// Write the report to a file
+    #[cfg(feature = "std")]
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for PostMortem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Olympia post-mortem report")?;
+        match &self.error {
+            Some(error) => writeln!(f, "Error: {}", error)?,
+            None => writeln!(f, "Error: none")?,
+        }
+        writeln!(f, "PC: {}", self.context)?;
+        writeln!(f, "Clocks elapsed: {}", self.clocks_elapsed)?;
+        let registers = &self.context.registers;
+        writeln!(
+            f,
+            "Registers: AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X}",
+            registers.af, registers.bc, registers.de, registers.hl, registers.sp, registers.pc
+        )?;
+
+        writeln!(f)?;
+        writeln!(f, "Trace (oldest first):")?;
+        if self.trace.is_empty() {
+            writeln!(f, "  (not recorded)")?;
+        }
+        for entry in self.trace.iter() {
+            writeln!(f, "  {:04X}: {}", entry.pc, entry.instruction)?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Memory:")?;
+        for (row, bytes) in self.memory.chunks(16).enumerate() {
+            write!(f, "  {:04X}:", self.memory_start + (row * 16) as u16)?;
+            for byte in bytes {
+                match byte {
+                    Some(byte) => write!(f, " {:02X}", byte)?,
+                    None => write!(f, " --")?,
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::GameBoyModel;
+    use crate::rom::Cartridge;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_post_mortem() {
+        let mut data = vec![0u8; 0x8000];
+        data[0x100..0x104].copy_from_slice(&[0x3E, 0x12, 0x00, 0xD3]); // LD A, 12h; NOP; DAT D3h
+        let mut gb = GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy);
+        gb.set_history_limit(10);
+        gb.step().unwrap();
+        gb.step().unwrap();
+        let error = gb.step().unwrap_err();
+
+        let report = PostMortem::capture(&gb, Some(error.clone()));
+
+        assert_eq!(report.error, Some(error));
+        assert_eq!(report.context.pc, 0x103);
+        assert_eq!(report.context.registers.af >> 8, 0x12);
+        assert_eq!(
+            report.trace,
+            vec![
+                TraceEntry {
+                    pc: 0x100,
+                    instruction: String::from("LD A, 12h"),
+                },
+                TraceEntry {
+                    pc: 0x102,
+                    instruction: String::from("NOP"),
+                },
+                TraceEntry {
+                    pc: 0x103,
+                    instruction: String::from("DAT D3h"),
+                },
+            ]
+        );
+        assert_eq!(report.memory_start, 0xE0);
+        assert_eq!(report.memory.len(), 80);
+        assert_eq!(report.memory[0x23], Some(0xD3));
+
+        let text = report.to_string();
+        assert!(text.contains("Error: invalid opcode D3 at 0x0103 (bank 0)\n"));
+        assert!(text.contains("\n  0100: LD A, 12h\n"));
+        assert!(text.contains("\n  0100: 3E 12 00 D3 00"));
+    }
+
+    #[test]
+    fn test_post_mortem_end_of_memory() {
+        let mut gb = GameBoy::new(
+            Cartridge::from_data(vec![0u8; 0x8000]).unwrap(),
+            GameBoyModel::GameBoy,
+        );
+        gb.write_register_u16(crate::registers::WordRegister::PC, 0xFFF8);
+
+        let report = PostMortem::capture(&gb, None);
+
+        assert_eq!(report.error, None);
+        assert_eq!(report.memory_start, 0xFFD0);
+        assert_eq!(report.memory.len(), 0x30);
+        assert!(report
+            .to_string()
+            .contains("Trace (oldest first):\n  (not recorded)\n"));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::events::{Event, MemoryEvent};
+use crate::gameboy::GameBoy;
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+use derive_more::Display;
+
+/// How a protected region of memory may be used
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Protection {
+    /// The region may be read and executed, but not written
+    #[display(fmt = "read-only")]
+    ReadOnly,
+    /// The region may only be executed. Writes, and reads other than fetching
+    /// the instruction being run, are violations.
+    #[display(fmt = "execute-only")]
+    ExecuteOnly,
+}
+
+/// Indicates a value is not a known kind of protection
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+#[display(fmt = "Unknown protection, expected read-only or execute-only")]
+pub struct ProtectionParseError;
+
+#[cfg(feature = "std")]
+impl std::error::Error for ProtectionParseError {}
+
+impl FromStr for Protection {
+    type Err = ProtectionParseError;
+
+    fn from_str(s: &str) -> Result<Protection, ProtectionParseError> {
+        match s {
+            "read-only" | "ro" => Ok(Protection::ReadOnly),
+            "execute-only" | "xo" => Ok(Protection::ExecuteOnly),
+            _ => Err(ProtectionParseError),
+        }
+    }
+}
+
+/// A range of addresses with restricted access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtectedRegion {
+    pub start: u16,
+    /// The last address in the region
+    pub end: u16,
+    pub protection: Protection,
+}
+
+impl ProtectedRegion {
+    pub fn contains(&self, addr: u16) -> bool {
+        (self.start..=self.end).contains(&addr)
+    }
+
+    /// Whether any address is in both this region and `start..=end`
+    pub fn overlaps(&self, start: u16, end: u16) -> bool {
+        self.start <= end && start <= self.end
+    }
+}
+
+impl fmt::Display for ProtectedRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} region {:04X}-{:04X}",
+            self.protection, self.start, self.end
+        )
+    }
+}
+
+/// A kind of memory access
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MemoryAccess {
+    #[display(fmt = "Read")]
+    Read,
+    #[display(fmt = "Write")]
+    Write,
+}
+
+/// An access to memory not allowed by a protected region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtectionViolation {
+    pub region: ProtectedRegion,
+    pub access: MemoryAccess,
+    pub address: u16,
+    /// The value read, or the value the instruction tried to write
+    pub value: u8,
+    /// Address of the instruction which made the access
+    pub pc: u16,
+}
+
+impl fmt::Display for ProtectionViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let preposition = match self.access {
+            MemoryAccess::Read => "from",
+            MemoryAccess::Write => "to",
+        };
+        write!(
+            f,
+            "{} of {:02X}h {} {:04X}h by the instruction at {:04X}h, in {}",
+            self.access, self.value, preposition, self.address, self.pc, self.region
+        )
+    }
+}
+
+/// Checks memory accesses against regions protected by a debugger
+///
+/// Pass every event from `GameBoy::events` to [`record`], and call
+/// [`begin_step`] before every step so the bytes of the instruction being
+/// run can be told apart from data reads. The first violation since the last
+/// call to [`take_violation`] is kept.
+///
+/// ```
+/// use olympia_engine::gameboy::{GameBoy, GameBoyModel};
+/// use olympia_engine::monitor::{MemoryProtection, Protection};
+/// use olympia_engine::rom::Cartridge;
+/// use std::sync::{Arc, Mutex};
+///
+/// let mut data = vec![0u8; 0x8000];
+/// data[0x100..0x103].copy_from_slice(&[0xEA, 0x00, 0xC0]); // LD (C000h), A
+/// let mut gb = GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy);
+/// let protection = Arc::new(Mutex::new(MemoryProtection::new()));
+/// protection.lock().unwrap().protect(0xC000, 0xC0FF, Protection::ExecuteOnly);
+/// let handler_protection = protection.clone();
+/// gb.events.on(Box::new(move |evt| handler_protection.lock().unwrap().record(evt)));
+///
+/// protection.lock().unwrap().begin_step(&gb);
+/// gb.step().unwrap();
+/// let violation = protection.lock().unwrap().take_violation().unwrap();
+/// assert_eq!(violation.address, 0xC000);
+/// assert_eq!(violation.pc, 0x100);
+/// ```
+///
+/// [`record`]: #method.record
+/// [`begin_step`]: #method.begin_step
+/// [`take_violation`]: #method.take_violation
+#[derive(Debug, Default, Clone)]
+pub struct MemoryProtection {
+    regions: Vec<ProtectedRegion>,
+    /// Address of the instruction being run
+    pc: u16,
+    /// Length of the instruction being run, whose bytes are fetched rather than read as data
+    instruction_len: u16,
+    violation: Option<ProtectionViolation>,
+}
+
+impl MemoryProtection {
+    pub fn new() -> MemoryProtection {
+        MemoryProtection::default()
+    }
+
+    /// Restrict access to the addresses `start..=end`
+    pub fn protect(&mut self, start: u16, end: u16, protection: Protection) {
+        self.regions.push(ProtectedRegion {
+            start,
+            end,
+            protection,
+        });
+    }
+
+    /// Remove every region which overlaps `start..=end`, returning how many were removed
+    pub fn unprotect(&mut self, start: u16, end: u16) -> usize {
+        let before = self.regions.len();
+        self.regions.retain(|region| !region.overlaps(start, end));
+        before - self.regions.len()
+    }
+
+    /// All protected regions, in the order they were added
+    pub fn regions(&self) -> &[ProtectedRegion] {
+        &self.regions
+    }
+
+    /// Note the instruction the gameboy is about to run
+    pub fn begin_step(&mut self, gb: &GameBoy) {
+        if self.regions.is_empty() {
+            return;
+        }
+        self.pc = gb.read_pc().0;
+        self.instruction_len = gb
+            .current_instruction()
+            .map_or(1, |instruction| instruction.as_bytes().len() as u16);
+    }
+
+    fn is_fetch(&self, addr: u16) -> bool {
+        addr.wrapping_sub(self.pc) < self.instruction_len
+    }
+
+    /// Check a memory access against the protected regions
+    pub fn record(&mut self, event: &Event) {
+        if self.violation.is_some() {
+            return;
+        }
+        let (access, address, value) = match event {
+            Event::Memory(MemoryEvent::Read { address, value }) => {
+                (MemoryAccess::Read, address.0, *value)
+            }
+            Event::Memory(MemoryEvent::Write { address, value, .. }) => {
+                (MemoryAccess::Write, address.0, *value)
+            }
+            _ => return,
+        };
+        let allowed = |region: &ProtectedRegion| match (region.protection, access) {
+            (_, MemoryAccess::Write) => false,
+            (Protection::ReadOnly, MemoryAccess::Read) => true,
+            (Protection::ExecuteOnly, MemoryAccess::Read) => self.is_fetch(address),
+        };
+        let violated = self
+            .regions
+            .iter()
+            .find(|region| region.contains(address) && !allowed(region));
+        if let Some(region) = violated {
+            self.violation = Some(ProtectionViolation {
+                region: *region,
+                access,
+                address,
+                value,
+                pc: self.pc,
+            });
+        }
+    }
+
+    /// The first violation since this was last called
+    pub fn take_violation(&mut self) -> Option<ProtectionViolation> {
+        self.violation.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::GameBoyModel;
+    use crate::registers::WordRegister;
+    use crate::rom::Cartridge;
+    use crate::shared::{Lock, Shared};
+    use alloc::boxed::Box;
+    use alloc::string::ToString;
+
+    fn run(code: &[u8], protection: &Shared<Lock<MemoryProtection>>) -> GameBoy {
+        let cartridge = Cartridge::from_data(vec![0u8; 0x8000]).unwrap();
+        let mut gb = GameBoy::new(cartridge, GameBoyModel::GameBoy);
+        gb.set_memory_chunk(0xC000, code).unwrap();
+        gb.write_register_u16(WordRegister::PC, 0xC000);
+        gb.write_register_u16(WordRegister::HL, 0xC001);
+        let handler_protection = protection.clone();
+        gb.events.on(Box::new(move |evt| {
+            handler_protection.borrow_mut().record(evt)
+        }));
+        while gb.read_pc().0 < 0xC000 + code.len() as u16 {
+            protection.borrow_mut().begin_step(&gb);
+            gb.step().unwrap();
+            if protection.borrow().violation.is_some() {
+                break;
+            }
+        }
+        gb
+    }
+
+    #[test]
+    fn test_execute_only() {
+        let protection = Shared::new(Lock::new(MemoryProtection::new()));
+        protection
+            .borrow_mut()
+            .protect(0xC000, 0xC0FF, Protection::ExecuteOnly);
+
+        // LD A, 12h; NOP; LD A, (HL)
+        run(&[0x3E, 0x12, 0x00, 0x7E], &protection);
+
+        let violation = protection.borrow_mut().take_violation().unwrap();
+        assert_eq!(
+            violation,
+            ProtectionViolation {
+                region: ProtectedRegion {
+                    start: 0xC000,
+                    end: 0xC0FF,
+                    protection: Protection::ExecuteOnly,
+                },
+                access: MemoryAccess::Read,
+                address: 0xC001,
+                value: 0x12,
+                pc: 0xC003,
+            }
+        );
+        assert_eq!(
+            violation.to_string(),
+            "Read of 12h from C001h by the instruction at C003h, in execute-only region C000-C0FF"
+        );
+        assert_eq!(protection.borrow_mut().take_violation(), None);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let protection = Shared::new(Lock::new(MemoryProtection::new()));
+        protection
+            .borrow_mut()
+            .protect(0xC000, 0xC00F, Protection::ReadOnly);
+
+        // LD A, (HL); INC A; LD (HL), A
+        let gb = run(&[0x7E, 0x3C, 0x77], &protection);
+
+        let violation = protection.borrow_mut().take_violation().unwrap();
+        assert_eq!(violation.access, MemoryAccess::Write);
+        assert_eq!(violation.pc, 0xC002);
+        assert_eq!(violation.value, 0x3D);
+        // The write still happens
+        assert_eq!(gb.get_memory_u8(0xC001), Ok(0x3D));
+
+        assert_eq!(protection.borrow_mut().unprotect(0xC00F, 0xC010), 1);
+        assert!(protection.borrow().regions().is_empty());
+    }
+
+    #[test]
+    fn test_parse_protection() {
+        assert_eq!("read-only".parse(), Ok(Protection::ReadOnly));
+        assert_eq!("xo".parse(), Ok(Protection::ExecuteOnly));
+        assert_eq!("rw".parse::<Protection>(), Err(ProtectionParseError));
+    }
+}
@@ -0,0 +1,220 @@
+use crate::events::{Event, RegisterWriteEvent};
+use crate::registers::WordRegister;
+
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A misuse of the stack found by a [`StackGuard`]
+///
+/// [`StackGuard`]: ./struct.StackGuard.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StackViolation {
+    /// SP moved outside the range the stack is allowed to use, usually
+    /// because too much was pushed
+    OutOfRange { sp: u16, start: u16, end: u16 },
+    /// A value was popped from above the base of the stack, so more was
+    /// popped than was pushed
+    Underflow { sp: u16, base: u16 },
+}
+
+impl fmt::Display for StackViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StackViolation::OutOfRange { sp, start, end } => write!(
+                f,
+                "SP moved to {:04X}h, outside the stack range {:04X}-{:04X}",
+                sp, start, end
+            ),
+            StackViolation::Underflow { sp, base } => write!(
+                f,
+                "Popped past the base of the stack at {:04X}h, SP moved to {:04X}h",
+                base, sp
+            ),
+        }
+    }
+}
+
+/// Tracks SP to find stack overflows and underflows
+///
+/// Pass every event from `GameBoy::events` to [`record`]. A violation is
+/// recorded when SP moves from inside the guarded range to outside it, or
+/// when a POP or RET leaves SP above the base of the stack. The base starts
+/// as the end of the guarded range, and moves to SP whenever SP is set by
+/// anything other than a push or pop, such as `LD SP, nn` at the start of a ROM.
+///
+/// ```
+/// use olympia_engine::gameboy::{GameBoy, GameBoyModel};
+/// use olympia_engine::monitor::{StackGuard, StackViolation};
+/// use olympia_engine::registers::WordRegister;
+/// use olympia_engine::rom::Cartridge;
+/// use std::sync::{Arc, Mutex};
+///
+/// let mut data = vec![0u8; 0x8000];
+/// data[0x100] = 0xC1; // POP BC
+/// let mut gb = GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy);
+/// let sp = gb.read_register_u16(WordRegister::SP);
+/// let guard = Arc::new(Mutex::new(StackGuard::new(0xFF80, 0xFFFE, sp)));
+/// let handler_guard = guard.clone();
+/// gb.events.on(Box::new(move |evt| handler_guard.lock().unwrap().record(evt)));
+///
+/// gb.step().unwrap();
+/// let violations = guard.lock().unwrap().take_violations();
+/// assert_eq!(
+///     violations,
+///     vec![
+///         StackViolation::OutOfRange { sp: 0, start: 0xFF80, end: 0xFFFE },
+///         StackViolation::Underflow { sp: 0, base: 0xFFFE },
+///     ]
+/// );
+/// ```
+///
+/// [`record`]: #method.record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackGuard {
+    start: u16,
+    end: u16,
+    /// SP as of the last event
+    sp: u16,
+    /// The highest SP a pop may leave the stack at
+    base: u16,
+    violations: Vec<StackViolation>,
+}
+
+impl StackGuard {
+    /// Guard a stack which may use SP values `start..=end`, and currently has SP `sp`
+    pub fn new(start: u16, end: u16, sp: u16) -> StackGuard {
+        StackGuard {
+            start,
+            end,
+            sp,
+            base: end,
+            violations: Vec::new(),
+        }
+    }
+
+    /// The lowest SP the stack may use
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// The highest SP the stack may use
+    pub fn end(&self) -> u16 {
+        self.end
+    }
+
+    /// The highest SP a pop may leave the stack at
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    fn in_range(&self, sp: u16) -> bool {
+        (self.start..=self.end).contains(&sp)
+    }
+
+    /// Check a write to SP against the guarded range and the base of the stack
+    pub fn record(&mut self, event: &Event) {
+        let sp = match event {
+            Event::RegisterWrite(RegisterWriteEvent {
+                reg: WordRegister::SP,
+                value,
+            }) => *value,
+            _ => return,
+        };
+        let previous = self.sp;
+        self.sp = sp;
+
+        if self.in_range(previous) && !self.in_range(sp) {
+            self.violations.push(StackViolation::OutOfRange {
+                sp,
+                start: self.start,
+                end: self.end,
+            });
+        }
+        if sp == previous.wrapping_add(2) {
+            if sp > self.base || sp < previous {
+                self.violations.push(StackViolation::Underflow {
+                    sp,
+                    base: self.base,
+                });
+            }
+        } else if sp != previous.wrapping_sub(2) {
+            self.base = sp;
+        }
+    }
+
+    /// Violations since this was last called, oldest first
+    pub fn take_violations(&mut self) -> Vec<StackViolation> {
+        core::mem::take(&mut self.violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn sp_write(value: u16) -> Event {
+        RegisterWriteEvent::new(WordRegister::SP, value).into()
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut guard = StackGuard::new(0xDF00, 0xDFFF, 0xDFFF);
+
+        guard.record(&sp_write(0xDF01));
+        guard.record(&sp_write(0xDEFF));
+        guard.record(&sp_write(0xDEFD));
+        guard.record(&sp_write(0xDEFF));
+        guard.record(&sp_write(0xDF01));
+        guard.record(&sp_write(0xDEFF));
+
+        let overflow = StackViolation::OutOfRange {
+            sp: 0xDEFF,
+            start: 0xDF00,
+            end: 0xDFFF,
+        };
+        assert_eq!(guard.take_violations(), vec![overflow, overflow]);
+        assert_eq!(
+            overflow.to_string(),
+            "SP moved to DEFFh, outside the stack range DF00-DFFF"
+        );
+        assert_eq!(guard.take_violations(), vec![]);
+    }
+
+    #[test]
+    fn test_underflow() {
+        let mut guard = StackGuard::new(0xC000, 0xFFFE, 0xFFFE);
+        assert_eq!(guard.base(), 0xFFFE);
+
+        // LD SP, E000h
+        guard.record(&sp_write(0xE000));
+        assert_eq!(guard.base(), 0xE000);
+        // PUSH, POP, POP
+        guard.record(&sp_write(0xDFFE));
+        guard.record(&sp_write(0xE000));
+        guard.record(&sp_write(0xE002));
+
+        let violations = guard.take_violations();
+        assert_eq!(
+            violations,
+            vec![StackViolation::Underflow {
+                sp: 0xE002,
+                base: 0xE000
+            }]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "Popped past the base of the stack at E000h, SP moved to E002h"
+        );
+    }
+
+    #[test]
+    fn test_ignores_other_registers() {
+        let mut guard = StackGuard::new(0xC000, 0xDFFF, 0xDFFF);
+
+        guard.record(&RegisterWriteEvent::new(WordRegister::HL, 0x1234).into());
+
+        assert_eq!(guard.take_violations(), vec![]);
+    }
+}
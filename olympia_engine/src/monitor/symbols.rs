@@ -0,0 +1,149 @@
+use crate::address::BankedAddress;
+
+use alloc::string::{String, ToString};
+use core::str::FromStr;
+use derive_more::Display;
+use hashbrown::HashMap;
+
+/// Names for addresses in a ROM, as produced by assemblers
+///
+/// Symbol files use the `<bank>:<address> <name>` format written by
+/// RGBDS and read by most Game Boy debuggers, with both numbers in hex.
+/// Lines starting with `;` are comments.
+///
+/// ```
+/// use olympia_engine::address::BankedAddress;
+/// use olympia_engine::monitor::SymbolTable;
+///
+/// let symbols: SymbolTable = "; Generated by rgblink\n00:0150 Start\n03:4123 LoadLevel"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(symbols.get("LoadLevel"), Some(BankedAddress::new(3, 0x4123)));
+/// assert_eq!(symbols.name_of(BankedAddress::new(0, 0x150)), Some("Start"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    addresses: HashMap<String, BankedAddress>,
+    /// The first name given to each (bank, address)
+    names: HashMap<(u16, u16), String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// Name an address, replacing any earlier address for the name
+    pub fn insert<S: Into<String>>(&mut self, name: S, addr: BankedAddress) {
+        let name = name.into();
+        self.names
+            .entry((addr.bank, addr.addr.0))
+            .or_insert_with(|| name.clone());
+        self.addresses.insert(name, addr);
+    }
+
+    /// The address with the given name. Names are case sensitive.
+    pub fn get(&self, name: &str) -> Option<BankedAddress> {
+        self.addresses.get(name).copied()
+    }
+
+    /// The name of an address, if it has one
+    pub fn name_of(&self, addr: BankedAddress) -> Option<&str> {
+        self.names
+            .get(&(addr.bank, addr.addr.0))
+            .map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+}
+
+/// Indicates a line of a symbol file could not be parsed
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+#[display(
+    fmt = "Line {}: '{}' is not in the format <bank>:<address> <name>",
+    line,
+    text
+)]
+pub struct SymbolParseError {
+    /// The line number, starting from 1
+    pub line: usize,
+    pub text: String,
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SymbolParseError {}
+
+fn parse_symbol(line: &str) -> Option<(&str, BankedAddress)> {
+    let mut parts = line.split_whitespace();
+    let addr = parts.next()?.parse::<BankedAddress>().ok()?;
+    let name = parts.next()?;
+    match parts.next() {
+        Some(_) => None,
+        None => Some((name, addr)),
+    }
+}
+
+impl FromStr for SymbolTable {
+    type Err = SymbolParseError;
+
+    fn from_str(s: &str) -> Result<SymbolTable, SymbolParseError> {
+        let mut symbols = SymbolTable::new();
+        for (index, line) in s.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, addr) = parse_symbol(line).ok_or_else(|| SymbolParseError {
+                line: index + 1,
+                text: line.to_string(),
+            })?;
+            symbols.insert(name, addr);
+        }
+        Ok(symbols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_symbols() {
+        let symbols: SymbolTable = "; File generated by rgblink\n\
+            00:0150 Start\n\
+            00:0150 Main ; same address\n\
+            \n\
+            01:4000 Level.load\n\
+            00:c0a0 wPlayerX\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.get("Main"), Some(BankedAddress::new(0, 0x150)));
+        assert_eq!(
+            symbols.get("Level.load"),
+            Some(BankedAddress::new(1, 0x4000))
+        );
+        assert_eq!(symbols.get("wplayerx"), None);
+        assert_eq!(symbols.name_of(BankedAddress::new(0, 0x150)), Some("Start"));
+        assert_eq!(symbols.name_of(BankedAddress::new(2, 0x4000)), None);
+    }
+
+    #[test]
+    fn test_parse_symbol_errors() {
+        assert_eq!(
+            "00:0150 Start\n0150 Main".parse::<SymbolTable>(),
+            Err(SymbolParseError {
+                line: 2,
+                text: "0150 Main".into()
+            })
+        );
+        assert!("00:0150 Two Names".parse::<SymbolTable>().is_err());
+    }
+}
@@ -0,0 +1,370 @@
+use crate::gameboy::{GameBoy, RegisterSnapshot, StepError};
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+use derive_more::Display;
+
+/// The CPU state before an instruction runs, as one line of a trace log
+///
+/// Lines use the format shared by several emulators' trace logs, such as
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`,
+/// where `PCMEM` is the 4 bytes of memory starting at PC. When parsing, fields
+/// may be in any order, `PCMEM` is optional and other fields are ignored.
+///
+/// ```
+/// use olympia_engine::monitor::TraceLine;
+///
+/// let line: TraceLine = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(line.registers.af, 0x01B0);
+/// assert_eq!(line.pcmem, None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceLine {
+    pub registers: RegisterSnapshot,
+    /// The 4 bytes of memory starting at PC, if recorded
+    pub pcmem: Option<[u8; 4]>,
+}
+
+impl TraceLine {
+    /// Record the current state of a gameboy
+    ///
+    /// Memory which can't be read is recorded as `0xFF`.
+    pub fn capture(gb: &GameBoy) -> TraceLine {
+        let registers = gb.register_snapshot();
+        let mut pcmem = [0xFF; 4];
+        for (offset, byte) in pcmem.iter_mut().enumerate() {
+            let addr = registers.pc.wrapping_add(offset as u16);
+            *byte = gb.get_memory_u8(addr).unwrap_or(0xFF);
+        }
+        TraceLine {
+            registers,
+            pcmem: Some(pcmem),
+        }
+    }
+
+    /// The value of each field, in the order they are written
+    fn fields(&self) -> [(&'static str, u16, usize); 10] {
+        let registers = &self.registers;
+        [
+            ("A", registers.af >> 8, 2),
+            ("F", registers.af & 0xFF, 2),
+            ("B", registers.bc >> 8, 2),
+            ("C", registers.bc & 0xFF, 2),
+            ("D", registers.de >> 8, 2),
+            ("E", registers.de & 0xFF, 2),
+            ("H", registers.hl >> 8, 2),
+            ("L", registers.hl & 0xFF, 2),
+            ("SP", registers.sp, 4),
+            ("PC", registers.pc, 4),
+        ]
+    }
+
+    /// Fields which don't match `actual`, as name, expected and actual values
+    ///
+    /// `PCMEM` is only compared if it was recorded in both lines.
+    pub fn differences(&self, actual: &TraceLine) -> Vec<(&'static str, String, String)> {
+        let mut differences: Vec<_> = self
+            .fields()
+            .iter()
+            .zip(actual.fields().iter())
+            .filter(|(expected, actual)| expected.1 != actual.1)
+            .map(|(&(name, expected, width), &(_, actual, _))| {
+                (
+                    name,
+                    format!("{:01$X}", expected, width),
+                    format!("{:01$X}", actual, width),
+                )
+            })
+            .collect();
+        if let (Some(expected), Some(actual)) = (self.pcmem, actual.pcmem) {
+            if expected != actual {
+                differences.push(("PCMEM", format_pcmem(&expected), format_pcmem(&actual)));
+            }
+        }
+        differences
+    }
+}
+
+fn format_pcmem(pcmem: &[u8; 4]) -> String {
+    let bytes: Vec<String> = pcmem.iter().map(|byte| format!("{:02X}", byte)).collect();
+    bytes.join(",")
+}
+
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, value, width)) in self.fields().iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}:{:02$X}", name, value, width)?;
+        }
+        if let Some(pcmem) = &self.pcmem {
+            write!(f, " PCMEM:{}", format_pcmem(pcmem))?;
+        }
+        Ok(())
+    }
+}
+
+/// Indicates a line of a trace log could not be parsed
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+pub enum TraceParseError {
+    #[display(fmt = "Missing {} field", "_0")]
+    MissingField(&'static str),
+    #[display(fmt = "Invalid value for {}: {}", "_0", "_1")]
+    InvalidValue(&'static str, String),
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TraceParseError {}
+
+const FIELD_NAMES: [&str; 10] = ["A", "F", "B", "C", "D", "E", "H", "L", "SP", "PC"];
+
+impl FromStr for TraceLine {
+    type Err = TraceParseError;
+
+    fn from_str(src: &str) -> Result<TraceLine, TraceParseError> {
+        let mut values: [Option<u16>; 10] = [None; 10];
+        let mut pcmem = None;
+        for field in src.split_whitespace() {
+            let (name, value) = match field.find(':') {
+                Some(index) => (&field[..index], &field[index + 1..]),
+                None => continue,
+            };
+            let name = name.to_ascii_uppercase();
+            if name == "PCMEM" {
+                let mut bytes = [0; 4];
+                let mut parts = value.split(',');
+                for byte in bytes.iter_mut() {
+                    *byte = parts
+                        .next()
+                        .and_then(|part| u8::from_str_radix(part, 16).ok())
+                        .ok_or_else(|| TraceParseError::InvalidValue("PCMEM", value.to_string()))?;
+                }
+                pcmem = Some(bytes);
+            } else if let Some(index) = FIELD_NAMES.iter().position(|field| *field == name) {
+                let max = if index < 8 { 0xFF } else { 0xFFFF };
+                let parsed = u16::from_str_radix(value, 16)
+                    .ok()
+                    .filter(|parsed| *parsed <= max)
+                    .ok_or_else(|| {
+                        TraceParseError::InvalidValue(FIELD_NAMES[index], value.to_string())
+                    })?;
+                values[index] = Some(parsed);
+            }
+        }
+
+        let mut registers = [0u16; 10];
+        for (index, value) in values.iter().enumerate() {
+            registers[index] = value.ok_or(TraceParseError::MissingField(FIELD_NAMES[index]))?;
+        }
+        let pair = |high: usize| (registers[high] << 8) | registers[high + 1];
+        Ok(TraceLine {
+            registers: RegisterSnapshot {
+                af: pair(0),
+                bc: pair(2),
+                de: pair(4),
+                hl: pair(6),
+                sp: registers[8],
+                pc: registers[9],
+            },
+            pcmem,
+        })
+    }
+}
+
+/// The first point where emulation stopped matching a trace log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Line number in the log, starting from 1
+    pub line: usize,
+    /// The number of instructions run before the divergence
+    pub steps: u64,
+    pub expected: TraceLine,
+    pub actual: TraceLine,
+    /// The PC of the last instruction run, which most likely caused the divergence
+    pub previous_pc: Option<u16>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.previous_pc {
+            Some(pc) => writeln!(
+                f,
+                "Diverged from line {} after {} instructions, running the instruction at {:04X}",
+                self.line, self.steps, pc
+            )?,
+            None => writeln!(
+                f,
+                "Diverged from line {} before running any instructions",
+                self.line
+            )?,
+        }
+        writeln!(f, "Expected: {}", self.expected)?;
+        writeln!(f, "Actual:   {}", self.actual)?;
+        for (name, expected, actual) in self.expected.differences(&self.actual) {
+            writeln!(f, "  {}: expected {}, was {}", name, expected, actual)?;
+        }
+        Ok(())
+    }
+}
+
+/// Indicates emulation did not match a trace log
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    #[display(fmt = "Line {}: {}", line, error)]
+    Parse { line: usize, error: TraceParseError },
+    #[display(fmt = "{}", "_0")]
+    Diverged(Divergence),
+    #[display(fmt = "Line {}: emulation failed: {}", line, error)]
+    Step { line: usize, error: StepError },
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VerifyError {}
+
+/// Run a gameboy, checking its state before each instruction against a trace log
+///
+/// Each line of the log is compared with the current state, then one instruction
+/// is run before comparing the next line. Blank lines are skipped. Stops at the
+/// first line which doesn't match, otherwise returns the number of lines compared.
+///
+/// Logs from other emulators usually start at `0100` after the boot ROM, and some
+/// expect LY to always read `90`, so ROMs which wait for VBlank may not match them.
+pub fn verify_trace<I, S>(gb: &mut GameBoy, log: I) -> Result<u64, VerifyError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut verified = 0;
+    let mut previous_pc = None;
+    for (index, src) in log.into_iter().enumerate() {
+        let line = index + 1;
+        let src = src.as_ref().trim();
+        if src.is_empty() {
+            continue;
+        }
+        let expected: TraceLine = src
+            .parse()
+            .map_err(|error| VerifyError::Parse { line, error })?;
+        if previous_pc.is_some() {
+            gb.step()
+                .map_err(|error| VerifyError::Step { line, error })?;
+        }
+        let actual = TraceLine::capture(gb);
+        if !expected.differences(&actual).is_empty() {
+            return Err(VerifyError::Diverged(Divergence {
+                line,
+                steps: verified,
+                expected,
+                actual,
+                previous_pc,
+            }));
+        }
+        previous_pc = Some(actual.registers.pc);
+        verified += 1;
+    }
+    Ok(verified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::GameBoyModel;
+    use crate::rom::Cartridge;
+
+    fn gameboy() -> GameBoy {
+        let mut data = vec![0u8; 0x8000];
+        // LD A, 12h; INC A; NOP
+        data[0x100..0x104].copy_from_slice(&[0x3E, 0x12, 0x3C, 0x00]);
+        GameBoy::new(Cartridge::from_data(data).unwrap(), GameBoyModel::GameBoy)
+    }
+
+    fn log() -> Vec<String> {
+        let mut gb = gameboy();
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            lines.push(TraceLine::capture(&gb).to_string());
+            gb.step().unwrap();
+        }
+        lines
+    }
+
+    #[test]
+    fn test_trace_line_round_trip() {
+        let src = "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02";
+        let line: TraceLine = src.parse().unwrap();
+
+        assert_eq!(
+            line.registers,
+            RegisterSnapshot {
+                af: 0x01B0,
+                bc: 0x0013,
+                de: 0x00D8,
+                hl: 0x014D,
+                sp: 0xFFFE,
+                pc: 0x0100,
+            }
+        );
+        assert_eq!(line.pcmem, Some([0x00, 0xC3, 0x13, 0x02]));
+        assert_eq!(line.to_string(), src);
+    }
+
+    #[test]
+    fn test_trace_line_parse_errors() {
+        assert_eq!(
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D PC:0100".parse::<TraceLine>(),
+            Err(TraceParseError::MissingField("SP"))
+        );
+        assert_eq!(
+            "A:101 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100".parse::<TraceLine>(),
+            Err(TraceParseError::InvalidValue("A", String::from("101")))
+        );
+        assert_eq!(
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3"
+                .parse::<TraceLine>(),
+            Err(TraceParseError::InvalidValue(
+                "PCMEM",
+                String::from("00,C3")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_verify_trace() {
+        let mut gb = gameboy();
+        let log = log();
+
+        assert_eq!(verify_trace(&mut gb, &log), Ok(3));
+    }
+
+    #[test]
+    fn test_verify_trace_divergence() {
+        let mut gb = gameboy();
+        let mut log = log();
+        // The oracle's INC A gave a different result
+        log[2] = log[2].replace("A:13", "A:14");
+
+        let divergence = match verify_trace(&mut gb, &log) {
+            Err(VerifyError::Diverged(divergence)) => divergence,
+            result => panic!("Unexpected result {:?}", result),
+        };
+
+        assert_eq!(divergence.line, 3);
+        assert_eq!(divergence.steps, 2);
+        assert_eq!(divergence.previous_pc, Some(0x102));
+        assert_eq!(
+            divergence.expected.differences(&divergence.actual),
+            vec![("A", String::from("14"), String::from("13"))]
+        );
+        assert!(divergence.to_string().starts_with(
+            "Diverged from line 3 after 2 instructions, running the instruction at 0102\n"
+        ));
+        assert!(divergence
+            .to_string()
+            .ends_with("\n  A: expected 14, was 13\n"));
+    }
+}
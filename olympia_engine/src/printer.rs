@@ -0,0 +1,329 @@
+//! Emulation of the Game Boy Printer serial accessory
+//!
+//! Connect a [`GameBoyPrinter`] with [`GameBoy::connect_serial`], then
+//! listen for [`PrintEvent`]s to receive printed images.
+//!
+//! [`GameBoyPrinter`]: struct.GameBoyPrinter.html
+//! [`GameBoy::connect_serial`]: ../gameboy/struct.GameBoy.html#method.connect_serial
+//! [`PrintEvent`]: ../events/struct.PrintEvent.html
+
+use crate::events::{PrintEvent, SerialEvent};
+use crate::gameboy::SerialDevice;
+use crate::logging;
+use alloc::vec::Vec;
+
+/// Width of printed images in pixels
+pub const PRINT_WIDTH: usize = 160;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const TILE_BYTES: usize = 16;
+const TILES_PER_ROW: usize = PRINT_WIDTH / 8;
+const MAX_DATA_LENGTH: u16 = 0x280;
+const DEFAULT_PALETTE: u8 = 0xE4;
+const ALIVE: u8 = 0x81;
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+
+/// The packet checksum did not match its contents
+pub const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+/// Image data has been received but not yet printed
+pub const STATUS_UNPRINTED_DATA: u8 = 0x08;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum PacketState {
+    Magic(usize),
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+/// A Game Boy Printer
+///
+/// Images are emitted as a [`PrintEvent`] once the print command is received,
+/// as if printing completed instantly.
+///
+/// [`PrintEvent`]: ../events/struct.PrintEvent.html
+pub struct GameBoyPrinter {
+    state: PacketState,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    expected_checksum: u16,
+    image_data: Vec<u8>,
+    status: u8,
+    pending_event: Option<SerialEvent>,
+}
+
+impl Default for GameBoyPrinter {
+    fn default() -> GameBoyPrinter {
+        GameBoyPrinter {
+            state: PacketState::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            expected_checksum: 0,
+            image_data: Vec::new(),
+            status: 0,
+            pending_event: None,
+        }
+    }
+}
+
+impl GameBoyPrinter {
+    pub fn new() -> GameBoyPrinter {
+        GameBoyPrinter::default()
+    }
+
+    /// The status byte reported to the gameboy at the end of each packet
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    fn add_to_checksum(&mut self, value: u8) {
+        self.checksum = self.checksum.wrapping_add(u16::from(value));
+    }
+
+    fn receive(&mut self, value: u8) -> PacketState {
+        match self.state {
+            PacketState::Magic(idx) if value != MAGIC[idx] => PacketState::Magic(0),
+            PacketState::Magic(idx) if idx + 1 < MAGIC.len() => PacketState::Magic(idx + 1),
+            PacketState::Magic(_) => {
+                self.checksum = 0;
+                self.data.clear();
+                PacketState::Command
+            }
+            PacketState::Command => {
+                self.command = value;
+                self.add_to_checksum(value);
+                PacketState::Compression
+            }
+            PacketState::Compression => {
+                self.compressed = value & 1 != 0;
+                self.add_to_checksum(value);
+                PacketState::LengthLow
+            }
+            PacketState::LengthLow => {
+                self.length = u16::from(value);
+                self.add_to_checksum(value);
+                PacketState::LengthHigh
+            }
+            PacketState::LengthHigh => {
+                self.length |= u16::from(value) << 8;
+                self.add_to_checksum(value);
+                if self.length == 0 {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::Data => {
+                self.data.push(value);
+                self.add_to_checksum(value);
+                if self.data.len() == usize::from(self.length) {
+                    PacketState::ChecksumLow
+                } else {
+                    PacketState::Data
+                }
+            }
+            PacketState::ChecksumLow => {
+                self.expected_checksum = u16::from(value);
+                PacketState::ChecksumHigh
+            }
+            PacketState::ChecksumHigh => {
+                self.expected_checksum |= u16::from(value) << 8;
+                self.process_packet();
+                PacketState::Alive
+            }
+            PacketState::Alive => PacketState::Status,
+            PacketState::Status => PacketState::Magic(0),
+        }
+    }
+
+    fn process_packet(&mut self) {
+        if self.checksum != self.expected_checksum {
+            log::warn!(
+                target: logging::PRINTER,
+                "Checksum mismatch: expected {:04X}, calculated {:04X}",
+                self.expected_checksum, self.checksum
+            );
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+        match self.command {
+            COMMAND_INIT => {
+                self.image_data.clear();
+                self.status = 0;
+            }
+            COMMAND_DATA if self.length <= MAX_DATA_LENGTH => {
+                if self.compressed {
+                    let data = decompress(&self.data);
+                    self.image_data.extend_from_slice(&data);
+                } else {
+                    self.image_data.extend_from_slice(&self.data);
+                }
+                if !self.image_data.is_empty() {
+                    self.status |= STATUS_UNPRINTED_DATA;
+                }
+            }
+            COMMAND_PRINT => {
+                let palette = self.data.get(2).copied().unwrap_or(DEFAULT_PALETTE);
+                let event = decode_image(&self.image_data, palette);
+                log::info!(target: logging::PRINTER, "Printed {}x{} image", event.width, event.height);
+                self.pending_event = Some(event.into());
+                self.image_data.clear();
+                self.status &= !STATUS_UNPRINTED_DATA;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl SerialDevice for GameBoyPrinter {
+    fn transfer(&mut self, value: u8) -> u8 {
+        let response = match self.state {
+            PacketState::Alive => ALIVE,
+            PacketState::Status => self.status,
+            _ => 0x00,
+        };
+        self.state = self.receive(value);
+        response
+    }
+
+    fn take_event(&mut self) -> Option<SerialEvent> {
+        self.pending_event.take()
+    }
+}
+
+/// Expand the run length encoding used by compressed data packets
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut iter = data.iter().copied();
+    while let Some(control) = iter.next() {
+        if control & 0x80 != 0 {
+            let run_length = usize::from(control & 0x7F) + 2;
+            if let Some(value) = iter.next() {
+                output.resize(output.len() + run_length, value);
+            }
+        } else {
+            let literal_length = usize::from(control) + 1;
+            output.extend(iter.by_ref().take(literal_length));
+        }
+    }
+    output
+}
+
+/// Convert 2bpp tile data laid out in rows of 20 tiles into pixel shades
+fn decode_image(data: &[u8], palette: u8) -> PrintEvent {
+    let palette = if palette == 0 {
+        DEFAULT_PALETTE
+    } else {
+        palette
+    };
+    let tile_rows = data.len() / (TILES_PER_ROW * TILE_BYTES);
+    let height = tile_rows * 8;
+    let mut pixels = Vec::with_capacity(PRINT_WIDTH * height);
+    for y in 0..height {
+        for x in 0..PRINT_WIDTH {
+            let tile = ((y / 8) * TILES_PER_ROW) + (x / 8);
+            let row_addr = (tile * TILE_BYTES) + ((y % 8) * 2);
+            let bit = 7 - (x % 8);
+            let low = (data[row_addr] >> bit) & 1;
+            let high = (data[row_addr + 1] >> bit) & 1;
+            let colour = (high << 1) | low;
+            pixels.push((palette >> (colour * 2)) & 0b11);
+        }
+    }
+    PrintEvent::new(PRINT_WIDTH, height, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send_packet(
+        printer: &mut GameBoyPrinter,
+        command: u8,
+        compressed: bool,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let length = data.len() as u16;
+        let mut packet = vec![0x88, 0x33, command, compressed as u8];
+        packet.extend_from_slice(&length.to_le_bytes());
+        packet.extend_from_slice(data);
+        let checksum = packet[2..]
+            .iter()
+            .fold(0u16, |sum, &b| sum.wrapping_add(u16::from(b)));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        packet.iter().map(|&b| printer.transfer(b)).collect()
+    }
+
+    #[test]
+    fn test_print_image() {
+        let mut printer = GameBoyPrinter::new();
+        let mut tiles = vec![0u8; 0x280];
+        // First row of the first tile: colour 1, 2, 3, 0, 0, 0, 0, 0
+        tiles[0] = 0b1010_0000;
+        tiles[1] = 0b0110_0000;
+
+        let response = send_packet(&mut printer, COMMAND_INIT, false, &[]);
+        assert_eq!(&response[response.len() - 2..], &[ALIVE, 0x00]);
+        send_packet(&mut printer, COMMAND_DATA, false, &tiles);
+        let response = send_packet(&mut printer, COMMAND_DATA, false, &[]);
+        assert_eq!(
+            &response[response.len() - 2..],
+            &[ALIVE, STATUS_UNPRINTED_DATA]
+        );
+        assert_eq!(printer.take_event(), None);
+
+        send_packet(&mut printer, COMMAND_PRINT, false, &[1, 0x13, 0xE4, 0x40]);
+        let event = printer.take_event();
+        match event {
+            Some(SerialEvent::Print(print)) => {
+                assert_eq!(print.width, 160);
+                assert_eq!(print.height, 16);
+                assert_eq!(print.pixels.len(), 160 * 16);
+                assert_eq!(&print.pixels[0..5], &[1, 2, 3, 0, 0]);
+            }
+            _ => panic!("Expected print event, got {:?}", event),
+        }
+        assert_eq!(printer.status(), 0);
+    }
+
+    #[test]
+    fn test_compressed_data() {
+        let mut printer = GameBoyPrinter::new();
+        // 2 literal bytes, then two runs of 0x81 repeated bytes
+        let compressed = [0x01, 0xFF, 0x00, 0xFF, 0xFF, 0xFF, 0xFF];
+
+        send_packet(&mut printer, COMMAND_DATA, true, &compressed);
+        assert_eq!(printer.image_data.len(), 2 + (2 * 0x81));
+        assert_eq!(&printer.image_data[..3], &[0xFF, 0x00, 0xFF]);
+        assert_eq!(decompress(&[0x82, 0x12]), vec![0x12; 4]);
+    }
+
+    #[test]
+    fn test_checksum_error() {
+        let mut printer = GameBoyPrinter::new();
+        let packet = [0x88, 0x33, COMMAND_INIT, 0, 0, 0, 0x02, 0x00, 0, 0];
+
+        let response: Vec<u8> = packet.iter().map(|&b| printer.transfer(b)).collect();
+        assert_eq!(
+            &response[response.len() - 2..],
+            &[ALIVE, STATUS_CHECKSUM_ERROR]
+        );
+    }
+}
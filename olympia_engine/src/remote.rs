@@ -11,20 +11,71 @@
 //!
 //! The front end can then use methods on [`RemoteEmulator`] to control the emulator
 //!
+//! With the `std` feature, [`EmulatorThread`] runs the emulator side of the channel,
+//! so front ends only need to provide the transport. [`mpsc_channel`] provides a transport
+//! using `std::sync::mpsc` channels, with [`MpscEventListeners`] to handle events on the
+//! frontend's thread.
+//!
+//! [`NetLink`] links two emulators' serial ports over the network, so
+//! two players can play together from separate emulator instances.
+//!
+//! With the `futures` feature, [`futures_channel`] provides a ready made transport
+//! using bounded `futures` channels, which can be awaited from any async runtime.
+//!
 //! [`RemoteEmulatorChannel`]: ./trait.RemoteEmulatorChannel.html
 //! [`RemoteEventListeners`]: ./trait.RemoteEventListeners.html
 //! [`RemoteEmulator`]: ./struct.RemoteEmulator.html
+//! [`EmulatorThread`]: ./struct.EmulatorThread.html
+//! [`mpsc_channel`]: ./fn.mpsc_channel.html
+//! [`MpscEventListeners`]: ./struct.MpscEventListeners.html
+//! [`NetLink`]: ./struct.NetLink.html
+//! [`futures_channel`]: ./fn.futures_channel.html
 
+#[cfg(feature = "std")]
+mod autosave;
 mod commands;
+#[cfg(feature = "std")]
+mod emulator_thread;
 mod events;
+#[cfg(feature = "futures")]
+mod futures_transport;
+#[cfg(feature = "std")]
+mod idle;
+#[cfg(feature = "std")]
+mod netlink;
 mod remote_emulator;
+#[cfg(feature = "std")]
+mod std_channel;
+#[cfg(feature = "std")]
+mod watchdog;
 
 pub use commands::{
-    CommandId, EmulatorCommand, EmulatorResponse, Error, ExecMode, ExecTime, LoadRomError,
-    QueryMemoryResponse, QueryRegistersResponse, RemoteEmulatorOutput, Result,
-    ToggleBreakpointResponse,
+    AutoSavePolicy, Capability, CommandId, EmulatorCommand, EmulatorResponse, Error, ExecMode,
+    ExecTime, IdlePolicy, LoadRomError, MetricsPolicy, PauseTiming, QueryBreakpointsResponse,
+    QueryCapabilitiesResponse, QueryDirtyMemoryResponse, QueryDisassemblyResponse,
+    QueryFrameHashResponse, QueryMemoryResponse, QueryMetricsResponse, QueryRegistersResponse,
+    RegisterValue, RemoteEmulatorOutput, Result, ReverseStepResponse, RunCyclesResponse,
+    SetButtonResponse, SetFlagResponse, SetSaveFileResponse, SetSpeedResponse,
+    ToggleBreakpointResponse, WatchdogPolicy, PROTOCOL_VERSION,
 };
 
-pub use events::{AdapterEventWrapper, Event, EventSendError, RemoteEventListeners, Sender};
+pub use events::{
+    AdapterEventWrapper, Event, EventSendError, InterceptListener, RemoteEventListeners, Sender,
+};
 
 pub use remote_emulator::{EmulatorCommandExecution, RemoteEmulator, RemoteEmulatorChannel};
+
+#[cfg(feature = "std")]
+pub use emulator_thread::{CommandReceiver, EmulatorThread};
+
+#[cfg(feature = "std")]
+pub use netlink::{NetLink, DEFAULT_LINK_TIMEOUT};
+
+#[cfg(feature = "std")]
+pub use std_channel::{mpsc_channel, MpscEmulatorChannel, MpscEventListeners, OutputQueue};
+
+#[cfg(feature = "futures")]
+pub use futures_transport::{
+    futures_channel, FuturesEmulatorChannel, FuturesEmulatorHost, FuturesEventSender,
+    PendingCommand, TransportError,
+};
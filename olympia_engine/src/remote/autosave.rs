@@ -0,0 +1,211 @@
+use crate::events::BatterySaveEvent;
+use crate::gameboy::GameBoy;
+use crate::logging;
+use crate::remote::commands::{self, AutoSavePolicy, SetSaveFileResponse};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Saves battery backed RAM to a file, as set by an [`AutoSavePolicy`]
+pub(crate) struct AutoSaver {
+    policy: AutoSavePolicy,
+    /// The save file for the loaded ROM, if any
+    path: Option<PathBuf>,
+    /// When RAM was last saved, or the save file was set
+    last_save: Instant,
+}
+
+impl AutoSaver {
+    pub(crate) fn new(policy: AutoSavePolicy) -> AutoSaver {
+        AutoSaver {
+            policy,
+            path: None,
+            last_save: Instant::now(),
+        }
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: AutoSavePolicy) {
+        self.policy = policy;
+    }
+
+    /// Save to `path` from now on, first loading RAM from it if it exists
+    ///
+    /// Call [`flush`] first, so changes aren't lost from any earlier save file.
+    ///
+    /// [`flush`]: #method.flush
+    pub(crate) fn attach(
+        &mut self,
+        gb: &mut GameBoy,
+        path: PathBuf,
+    ) -> commands::Result<SetSaveFileResponse> {
+        let mut response = SetSaveFileResponse {
+            path: Some(path.display().to_string()),
+            has_battery: gb.battery_ram().is_some(),
+            loaded: false,
+        };
+        if response.has_battery {
+            match fs::read(&path) {
+                Ok(data) => {
+                    gb.load_battery_ram(&data)
+                        .map_err(|err| commands::Error::Save(err.to_string()))?;
+                    log::info!(target: logging::SAVE, "Loaded save from {}", path.display());
+                    response.loaded = true;
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(commands::Error::Save(err.to_string())),
+            }
+        }
+        self.path = Some(path);
+        self.last_save = Instant::now();
+        Ok(response)
+    }
+
+    /// Stop saving to the current save file
+    pub(crate) fn detach(&mut self) {
+        self.path = None;
+    }
+
+    /// Save RAM if it has changed and the interval has passed since the last save
+    pub(crate) fn check(&mut self, gb: &mut GameBoy) -> Option<BatterySaveEvent> {
+        let interval = self.policy.interval?;
+        if self.last_save.elapsed() < interval {
+            return None;
+        }
+        self.flush(gb)
+    }
+
+    /// Save RAM now if it has changed
+    ///
+    /// Returns an event describing the save, if one was attempted.
+    pub(crate) fn flush(&mut self, gb: &mut GameBoy) -> Option<BatterySaveEvent> {
+        let path = self.path.as_ref()?;
+        self.last_save = Instant::now();
+        if !gb.battery_ram_modified() {
+            return None;
+        }
+        let ram = gb.battery_ram()?;
+        let error = match write_save(path, ram) {
+            Ok(()) => {
+                gb.mark_battery_ram_saved();
+                log::debug!(target: logging::SAVE, "Saved to {}", path.display());
+                None
+            }
+            Err(err) => {
+                // Still modified, so the next check tries again
+                log::error!(target: logging::SAVE, "Could not save to {}: {}", path.display(), err);
+                Some(err.to_string())
+            }
+        };
+        Some(BatterySaveEvent::new(path.display().to_string(), error))
+    }
+}
+
+/// Write a save without leaving a partly written file if interrupted
+fn write_save(path: &Path, ram: &[u8]) -> io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    fs::write(&temp_path, ram)?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::GameBoyModel;
+    use crate::rom::Cartridge;
+    use std::time::Duration;
+
+    const CARTRIDGE_TYPE_LOCATION: usize = 0x147;
+    const RAM_SIZE_LOCATION: usize = 0x149;
+
+    /// An MBC5 cartridge with 8KiB of battery backed RAM
+    fn battery_rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[CARTRIDGE_TYPE_LOCATION] = 0x1B;
+        rom[RAM_SIZE_LOCATION] = 2;
+        rom
+    }
+
+    fn battery_gameboy() -> GameBoy {
+        let cartridge = Cartridge::from_data(battery_rom()).unwrap();
+        GameBoy::new(cartridge, GameBoyModel::GameBoy)
+    }
+
+    fn write_ram(gb: &mut GameBoy, value: u8) {
+        gb.set_memory_u8(0x0000, 0x0A).unwrap();
+        gb.set_memory_u8(0xA000, value).unwrap();
+    }
+
+    fn save_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "olympia-autosave-{}-{}.sav",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_flush_only_when_modified() {
+        let path = save_path("flush");
+        let mut gb = battery_gameboy();
+        let mut saver = AutoSaver::new(AutoSavePolicy::default());
+        assert_eq!(saver.flush(&mut gb), None, "No save file set");
+
+        let response = saver.attach(&mut gb, path.clone()).unwrap();
+        assert!(response.has_battery);
+        assert!(!response.loaded);
+        assert_eq!(saver.flush(&mut gb), None, "RAM is unchanged");
+        assert!(!path.exists());
+
+        write_ram(&mut gb, 0x42);
+        let event = saver.flush(&mut gb).unwrap();
+        assert_eq!(event.error, None);
+        assert_eq!(fs::read(&path).unwrap()[0], 0x42);
+        assert_eq!(saver.flush(&mut gb), None, "Already saved");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_existing_save() {
+        let path = save_path("load");
+        let mut save = vec![0; 0x2000];
+        save[0] = 0x24;
+        fs::write(&path, &save).unwrap();
+        let mut gb = battery_gameboy();
+        let mut saver = AutoSaver::new(AutoSavePolicy::default());
+
+        let response = saver.attach(&mut gb, path.clone()).unwrap();
+
+        assert!(response.loaded);
+        assert_eq!(gb.battery_ram().unwrap()[0], 0x24);
+        assert!(!gb.battery_ram_modified());
+        fs::write(&path, &save[..0x10]).unwrap();
+        assert!(matches!(
+            saver.attach(&mut gb, path.clone()),
+            Err(commands::Error::Save(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_check_waits_for_interval() {
+        let path = save_path("interval");
+        let mut gb = battery_gameboy();
+        let mut saver = AutoSaver::new(AutoSavePolicy::every(Duration::from_secs(3600)));
+        saver.attach(&mut gb, path.clone()).unwrap();
+        write_ram(&mut gb, 0x42);
+
+        assert_eq!(saver.check(&mut gb), None);
+        saver.set_policy(AutoSavePolicy::every(Duration::from_millis(0)));
+        assert!(saver.check(&mut gb).is_some());
+        saver.set_policy(AutoSavePolicy { interval: None });
+        write_ram(&mut gb, 0x43);
+        assert_eq!(saver.check(&mut gb), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
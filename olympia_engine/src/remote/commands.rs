@@ -4,17 +4,27 @@ use derive_more::{Display, From, TryInto};
 use derive_more::Error;
 
 use alloc::{string::String, vec::Vec};
+use log::LevelFilter;
 
 use crate::{
-    gameboy::StepError,
-    monitor::{Breakpoint, BreakpointIdentifier},
-    registers::WordRegister,
+    disassembler::DisassemblyLine,
+    gameboy::{Button, CycleProfile, RenderLayers, StepError},
+    monitor::{Breakpoint, BreakpointIdentifier, MetricSample, PostMortem, RWTarget},
+    registers::{ByteRegister, Flag, WordRegister},
     remote::Event,
     rom::CartridgeLoadError,
 };
 
+/// Version of the remote protocol spoken by this engine
+///
+/// This goes up when commands or responses change in a way older frontends
+/// can't handle. Added commands or capabilities don't change it, as
+/// frontends find those with `QueryCapabilities`.
+pub const PROTOCOL_VERSION: u32 = 1;
+
 /// The running/not running state of the remote emulator
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExecMode {
     /// The emulator is not running as it has not yet loaded
     Unloaded,
@@ -26,9 +36,12 @@ pub enum ExecMode {
     Standard,
     /// The emulator is running as fast as possible
     Uncapped,
+    /// The emulator is not running as a step failed
+    Error(StepError),
 }
 
 #[derive(PartialEq, Eq, From, Display, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "std", derive(Error))]
 /// A failure to load a ROM
 pub enum LoadRomError {
@@ -41,6 +54,7 @@ pub enum LoadRomError {
 }
 
 #[derive(Debug, Display, From, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "std", derive(Error))]
 /// A problem encountered by a remote emulator
 pub enum Error {
@@ -50,6 +64,14 @@ pub enum Error {
     Load(LoadRomError),
     #[display(fmt = "Action cannot be performed without a ROM loaded")]
     NoRomLoaded,
+    #[display(fmt = "No earlier state to step back to")]
+    NoHistory,
+    #[display(fmt = "Emulator was built without the profiling feature")]
+    ProfilingDisabled,
+    #[display(fmt = "Could not use save file: {}", "_0")]
+    #[from(ignore)]
+    #[cfg_attr(feature = "std", error(ignore))]
+    Save(String),
 }
 
 /// Result of a remote emulator operation
@@ -57,6 +79,7 @@ pub type Result<T> = core::result::Result<T, Error>;
 
 /// The values of all 16-bit registers
 #[derive(Debug, PartialEq, Eq, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct QueryRegistersResponse {
     pub af: u16,
     pub bc: u16,
@@ -80,6 +103,7 @@ impl QueryRegistersResponse {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// The memory data at a requested address
 pub struct QueryMemoryResponse {
     /// The first address in memory represented by the data
@@ -90,7 +114,28 @@ pub struct QueryMemoryResponse {
     pub data: Vec<Option<u8>>,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Memory that has been written to since it was last queried
+pub struct QueryDirtyMemoryResponse {
+    /// Start addresses of each changed block, in address order
+    ///
+    /// Each block is [`monitor::DIRTY_BLOCK_SIZE`] bytes long.
+    ///
+    /// [`monitor::DIRTY_BLOCK_SIZE`]: ../monitor/constant.DIRTY_BLOCK_SIZE.html
+    pub blocks: Vec<u16>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The instructions decoded from a requested address
+pub struct QueryDisassemblyResponse {
+    /// Decoded instructions, in address order
+    pub instructions: Vec<DisassemblyLine>,
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A single command for the remote emulator execute
 pub enum EmulatorCommand {
     /// Load a rom from a given file path
@@ -100,21 +145,127 @@ pub enum EmulatorCommand {
     /// Query memory from the start address (inclusive)
     /// to end address (inclusive)
     QueryMemory(u16, u16),
+    /// Query which blocks of memory have been written to since
+    /// this was last queried
+    QueryDirtyMemory,
+    /// Disassemble `count` instructions, starting with the one at `start`
+    QueryDisassembly { start: u16, count: u16 },
+    /// Write a value to a CPU register
+    SetRegister(RegisterValue),
+    /// Set or clear a CPU flag
+    SetFlag(Flag, bool),
     /// Run a single step
     Step,
+    /// Run whole steps for at most `budget` clock cycles, stopping early at
+    /// a breakpoint if `stop_on_breakpoint` is set
+    ///
+    /// This runs regardless of the exec mode, so frontends can pause the
+    /// emulator and drive it in slices, such as from an audio callback.
+    ///
+    /// A step only starts while at least 24 clocks of the budget remain, as
+    /// that is the most a step can take. So a run can stop up to 23 clocks
+    /// short of the budget, and a budget under 24 runs nothing. Frontends
+    /// that need to keep pace can add the unused clocks to the next budget.
+    RunCycles {
+        budget: u64,
+        stop_on_breakpoint: bool,
+    },
+    /// Undo the most recent step
+    ReverseStep,
+    /// Set how many steps are recorded so they can be undone with `ReverseStep`
+    ///
+    /// Recording slows down emulation, so is off until this is set above 0.
+    /// This stays in effect when another ROM is loaded.
+    SetHistoryLimit(usize),
     /// Find out how much time has elapsed in the emulation core
     QueryExecTime,
     /// Set the exec mode - paused, 1x speed or fast forward
     SetMode(ExecMode),
+    /// Set the speed of the standard exec mode, as a percentage
+    /// of gameboy speed
+    SetSpeed(u32),
     /// Add a breakpoint
     AddBreakpoint(Breakpoint),
     /// Set the active state of a breakpoint
     SetBreakpointActive(BreakpointIdentifier, bool),
     /// Remove a breakpoint
     RemoveBreakpoint(BreakpointIdentifier),
+    /// Query all breakpoints
+    QueryBreakpoints,
+    /// Press or release a joypad button
+    SetButton(Button, bool),
+    /// Choose which layers are drawn into the framebuffer
+    ///
+    /// This stays in effect when another ROM is loaded.
+    SetRenderLayers(RenderLayers),
+    /// Report the state of the emulator, and the error it stopped with if any
+    QueryPostMortem,
+    /// Query hashes of the current frame and of every frame so far
+    QueryFrameHash,
+    /// Choose when the running game is treated as idle, and whether to pause then
+    ///
+    /// This stays in effect when another ROM is loaded.
+    SetIdlePolicy(IdlePolicy),
+    /// Choose how long a single command may run for, and whether to pause after
+    ///
+    /// This stays in effect when another ROM is loaded.
+    SetWatchdogPolicy(WatchdogPolicy),
+    /// Run extra CPU cycles in each line of the display
+    ///
+    /// This stays in effect when another ROM is loaded.
+    SetOverclock(u16),
+    /// Report host time spent in each component, and optionally start again from zero
+    ///
+    /// This needs the `profiling` feature.
+    QueryProfile { reset: bool },
+    /// Start or stop sending a `LogEvent` for each message logged
+    ///
+    /// Messages from every thread are sent, not just the emulator's, and only
+    /// once a logger is set with `logging::install`.
+    StreamLogs(bool),
+    /// Log messages for a target, and the targets below it, at the given level
+    ///
+    /// Levels apply to every emulator in the process.
+    SetLogLevel(String, LevelFilter),
+    /// Keep the loaded ROM's battery backed RAM in a file, or `None` to stop
+    ///
+    /// RAM is loaded from the file if it already exists. Changed RAM is saved
+    /// at the interval set by the `AutoSavePolicy`, whenever emulation stops
+    /// running, before another ROM is loaded, and when the emulator exits.
+    /// Loading another ROM stops saving to the file.
+    SetSaveFile(Option<String>),
+    /// Choose how often changed battery backed RAM is saved while running
+    ///
+    /// This stays in effect when another ROM is loaded.
+    SetAutoSavePolicy(AutoSavePolicy),
+    /// Save battery backed RAM now if it has changed, such as before the frontend exits
+    SaveBatteryRam,
+    /// Report the protocol version and the optional features this engine supports
+    ///
+    /// This works before a ROM is loaded, so frontends can check it when they
+    /// connect. Engines older than this command don't respond to it, and
+    /// frontends should treat those as protocol version 0.
+    QueryCapabilities,
+    /// Choose whether pausing takes effect straight away or once the frame ends
+    ///
+    /// This stays in effect when another ROM is loaded.
+    SetPauseTiming(PauseTiming),
+    /// Run several commands in order, with no instructions run in between
+    ///
+    /// The response has the response to each command, in the same order.
+    /// Later commands still run if an earlier one fails.
+    Batch(Vec<EmulatorCommand>),
+    /// Choose which registers and memory locations are sampled once per frame
+    ///
+    /// This stays in effect when another ROM is loaded, but samples from the
+    /// previous ROM are discarded.
+    SetMetricsPolicy(MetricsPolicy),
+    /// Report the values sampled each frame, and optionally discard them
+    QueryMetrics { clear: bool },
 }
 
 #[derive(Debug, PartialEq, PartialOrd, From)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// The time the emulator has been running
 pub struct ExecTime(f64);
 
@@ -125,8 +276,237 @@ impl ExecTime {
     }
 }
 
+/// When a remote emulator treats the running game as idle, and what it does then
+///
+/// A frame is idle if the CPU spends most of it halted and it looks the same
+/// as the frame before. After `frames` idle frames in a row, the emulator sends
+/// an `IdleEvent`, and another once a frame isn't idle or a button is pressed.
+/// Detection is off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IdlePolicy {
+    /// Idle frames in a row before the game is idle, or `None` to never detect it
+    pub frames: Option<u32>,
+    /// Pause emulation once the game is idle
+    pub auto_pause: bool,
+}
+
+impl IdlePolicy {
+    /// Treat the game as idle after `frames` idle frames, without pausing
+    pub fn after_frames(frames: u32) -> IdlePolicy {
+        IdlePolicy {
+            frames: Some(frames),
+            auto_pause: false,
+        }
+    }
+
+    /// The same policy, but pausing emulation once the game is idle
+    pub fn pausing(self) -> IdlePolicy {
+        IdlePolicy {
+            auto_pause: true,
+            ..self
+        }
+    }
+}
+
+/// How long a remote emulator lets a single command run, and what it does after
+///
+/// The budget covers `RunCycles` commands, and runs started with `SetMode`
+/// while a breakpoint is active, which may never stop if the breakpoint can't
+/// be reached. Once a command goes over `budget`, the emulator sends a
+/// `WatchdogEvent`. The watchdog is off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WatchdogPolicy {
+    /// Wall-clock time a command may take, or `None` to never stop it
+    pub budget: Option<core::time::Duration>,
+    /// Pause emulation once a command goes over budget
+    pub auto_pause: bool,
+}
+
+impl WatchdogPolicy {
+    /// Warn about commands which take longer than `budget`, without pausing
+    pub fn after(budget: core::time::Duration) -> WatchdogPolicy {
+        WatchdogPolicy {
+            budget: Some(budget),
+            auto_pause: false,
+        }
+    }
+
+    /// The same policy, but pausing emulation once a command goes over budget
+    pub fn pausing(self) -> WatchdogPolicy {
+        WatchdogPolicy {
+            auto_pause: true,
+            ..self
+        }
+    }
+}
+
+/// When a request to pause a running remote emulator takes effect
+///
+/// Pausing straight away stops wherever the running frame has got to, so the
+/// picture may be part drawn. Pausing at the next frame keeps running until
+/// VBlank starts, so frame advance and screenshots always see whole frames.
+/// Only `SetMode(ExecMode::Paused)` waits for the frame to end. Breakpoints,
+/// errors and the idle and watchdog policies pause straight away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PauseTiming {
+    /// Pause after the instruction running when the request arrives
+    #[default]
+    Immediate,
+    /// Pause when VBlank next starts, or straight away if the LCD is off
+    NextFrame,
+}
+
+/// Which values a remote emulator samples once per frame, for frontends to plot
+///
+/// Each time VBlank starts, every target is read and the values stored. Only
+/// the most recent `frames` samples are kept, and frontends fetch them with
+/// `QueryMetrics` as often as they redraw. Nothing is sampled by default.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetricsPolicy {
+    /// Registers and memory locations to read
+    pub targets: Vec<RWTarget>,
+    /// Number of samples kept, one per frame
+    pub frames: usize,
+}
+
+impl MetricsPolicy {
+    /// Sample `targets`, keeping the most recent `frames` samples
+    pub fn new(targets: Vec<RWTarget>, frames: usize) -> MetricsPolicy {
+        MetricsPolicy { targets, frames }
+    }
+}
+
+/// Values sampled once per frame by a remote emulator
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueryMetricsResponse {
+    /// The targets sampled, in the same order as the values in each sample
+    pub targets: Vec<RWTarget>,
+    /// Samples, oldest first
+    pub samples: Vec<MetricSample>,
+}
+
+/// How often a remote emulator saves changed battery backed RAM while running
+///
+/// This only applies once a save file is set. RAM is always saved when
+/// emulation stops running, so games don't lose progress when paused, and
+/// saving at an interval limits what is lost if the frontend crashes.
+/// By default, RAM is saved every ten seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AutoSavePolicy {
+    /// Time between saves, or `None` to only save when emulation stops running
+    pub interval: Option<core::time::Duration>,
+}
+
+impl AutoSavePolicy {
+    /// Save changed RAM every `interval` while running
+    pub fn every(interval: core::time::Duration) -> AutoSavePolicy {
+        AutoSavePolicy {
+            interval: Some(interval),
+        }
+    }
+}
+
+impl Default for AutoSavePolicy {
+    fn default() -> AutoSavePolicy {
+        AutoSavePolicy::every(core::time::Duration::from_secs(10))
+    }
+}
+
+/// The save file used by a remote emulator
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetSaveFileResponse {
+    /// The save file, if any
+    pub path: Option<String>,
+    /// Whether the loaded ROM has battery backed RAM to save
+    pub has_battery: bool,
+    /// Whether RAM was loaded from an existing save file
+    pub loaded: bool,
+}
+
+/// An optional feature of the remote protocol
+///
+/// Frontends should hide or disable functionality the engine doesn't report,
+/// rather than sending commands it can't handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Capability {
+    /// Completed frames are sent to the frontend as they are drawn
+    ///
+    /// This engine doesn't send frames yet, so never reports this.
+    FramebufferStreaming,
+    /// Audio samples are sent to the frontend
+    ///
+    /// This engine doesn't emulate sound yet, so never reports this.
+    Audio,
+    /// Steps can be undone with `ReverseStep`
+    Rewind,
+    /// Host time spent in each component is reported by `QueryProfile`
+    Profiling,
+    /// Battery backed RAM can be kept in a file with `SetSaveFile`
+    SaveFiles,
+    /// Log messages can be sent as events with `StreamLogs`
+    LogStreaming,
+    /// Values can be sampled once per frame with `SetMetricsPolicy`
+    Metrics,
+    /// A capability added in a later version of the engine
+    #[cfg_attr(feature = "serde", serde(other))]
+    Unknown,
+}
+
+/// The protocol version and optional features supported by a remote emulator
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueryCapabilitiesResponse {
+    /// The engine's `PROTOCOL_VERSION`
+    pub protocol_version: u32,
+    /// Optional features the engine supports
+    pub capabilities: Vec<Capability>,
+}
+
+impl QueryCapabilitiesResponse {
+    /// The capabilities of this build of the engine
+    pub fn current() -> QueryCapabilitiesResponse {
+        let mut capabilities = vec![
+            Capability::Rewind,
+            Capability::SaveFiles,
+            Capability::LogStreaming,
+            Capability::Metrics,
+        ];
+        if cfg!(feature = "profiling") {
+            capabilities.push(Capability::Profiling);
+        }
+        QueryCapabilitiesResponse {
+            protocol_version: PROTOCOL_VERSION,
+            capabilities,
+        }
+    }
+
+    /// Whether the engine supports the given capability
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+/// Hashes of the frames drawn by the emulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueryFrameHashResponse {
+    /// Hash of the framebuffer as it is now
+    pub frame_hash: u64,
+    /// Hash of every frame completed since the ROM was loaded
+    pub run_hash: u64,
+}
+
 /// Identifier of a newly added breakpoint
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AddBreakpointResponse {
     pub id: BreakpointIdentifier,
 }
@@ -137,7 +517,39 @@ impl From<BreakpointIdentifier> for AddBreakpointResponse {
     }
 }
 
+/// Result of undoing a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReverseStepResponse {
+    /// How many more steps can be undone
+    pub steps_remaining: usize,
+}
+
+impl ReverseStepResponse {
+    pub fn new(steps_remaining: usize) -> ReverseStepResponse {
+        ReverseStepResponse { steps_remaining }
+    }
+}
+
+/// Result of running for a budget of clock cycles
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RunCyclesResponse {
+    /// Clock cycles that were run, which is never more than the budget,
+    /// and less than 24 under it unless a breakpoint or STOP ended the run
+    pub cycles: u64,
+    /// The breakpoint that stopped the run early, if any
+    pub breakpoint: Option<Breakpoint>,
+}
+
+impl RunCyclesResponse {
+    pub fn new(cycles: u64, breakpoint: Option<Breakpoint>) -> RunCyclesResponse {
+        RunCyclesResponse { cycles, breakpoint }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ToggleBreakpointResponse {
     pub id: BreakpointIdentifier,
     pub new_state: bool,
@@ -150,6 +562,7 @@ impl ToggleBreakpointResponse {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RemoveBreakpointRespnse {
     pub id: BreakpointIdentifier,
 }
@@ -160,25 +573,122 @@ impl From<BreakpointIdentifier> for RemoveBreakpointRespnse {
     }
 }
 
+/// All breakpoints set in the emulator
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueryBreakpointsResponse {
+    pub breakpoints: Vec<(BreakpointIdentifier, Breakpoint)>,
+}
+
+impl From<Vec<(BreakpointIdentifier, Breakpoint)>> for QueryBreakpointsResponse {
+    fn from(breakpoints: Vec<(BreakpointIdentifier, Breakpoint)>) -> QueryBreakpointsResponse {
+        QueryBreakpointsResponse { breakpoints }
+    }
+}
+
+/// The new state of a joypad button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetButtonResponse {
+    pub button: Button,
+    pub pressed: bool,
+}
+
+impl SetButtonResponse {
+    pub fn new(button: Button, pressed: bool) -> SetButtonResponse {
+        SetButtonResponse { button, pressed }
+    }
+}
+
+/// The value of an 8-bit or 16-bit CPU register
+///
+/// After writing to a register, the response has the value the register
+/// holds, which can differ from the value written as the lower 4 bits of F
+/// are always 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, From)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RegisterValue {
+    Byte(ByteRegister, u8),
+    Word(WordRegister, u16),
+}
+
+/// The new state of a CPU flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetFlagResponse {
+    pub flag: Flag,
+    pub set: bool,
+}
+
+impl SetFlagResponse {
+    pub fn new(flag: Flag, set: bool) -> SetFlagResponse {
+        SetFlagResponse { flag, set }
+    }
+}
+
+/// The new speed of the standard exec mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetSpeedResponse {
+    /// Speed as a percentage of gameboy speed
+    pub speed_percent: u32,
+}
+
+impl SetSpeedResponse {
+    pub fn new(speed_percent: u32) -> SetSpeedResponse {
+        SetSpeedResponse { speed_percent }
+    }
+}
+
 #[derive(Debug, From, TryInto, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A response to an emulator command
 pub enum EmulatorResponse {
     LoadRom(core::result::Result<(), LoadRomError>),
     QueryRegisters(Result<QueryRegistersResponse>),
     QueryMemory(Result<QueryMemoryResponse>),
+    QueryDirtyMemory(Result<QueryDirtyMemoryResponse>),
+    QueryDisassembly(Result<QueryDisassemblyResponse>),
+    SetRegister(Result<RegisterValue>),
+    SetFlag(Result<SetFlagResponse>),
     Step(Result<()>),
+    RunCycles(Result<RunCyclesResponse>),
+    ReverseStep(Result<ReverseStepResponse>),
+    SetHistoryLimit(core::result::Result<usize, ()>),
     QueryExecTime(Result<ExecTime>),
     SetMode(core::result::Result<ExecMode, ()>),
+    SetSpeed(core::result::Result<SetSpeedResponse, ()>),
     AddBreakpoint(core::result::Result<AddBreakpointResponse, ()>),
     ToggleBreakpoint(core::result::Result<ToggleBreakpointResponse, ()>),
     RemoveBreakpoint(core::result::Result<RemoveBreakpointRespnse, ()>),
+    QueryBreakpoints(core::result::Result<QueryBreakpointsResponse, ()>),
+    SetButton(Result<SetButtonResponse>),
+    SetRenderLayers(core::result::Result<RenderLayers, ()>),
+    QueryPostMortem(Result<PostMortem>),
+    QueryFrameHash(Result<QueryFrameHashResponse>),
+    SetIdlePolicy(core::result::Result<IdlePolicy, ()>),
+    SetWatchdogPolicy(core::result::Result<WatchdogPolicy, ()>),
+    SetOverclock(core::result::Result<u16, ()>),
+    QueryProfile(Result<CycleProfile>),
+    StreamLogs(core::result::Result<bool, ()>),
+    SetLogLevel(core::result::Result<LevelFilter, ()>),
+    SetSaveFile(Result<SetSaveFileResponse>),
+    SetAutoSavePolicy(core::result::Result<AutoSavePolicy, ()>),
+    SaveBatteryRam(Result<bool>),
+    QueryCapabilities(core::result::Result<QueryCapabilitiesResponse, ()>),
+    Batch(core::result::Result<Vec<EmulatorResponse>, ()>),
+    SetPauseTiming(core::result::Result<PauseTiming, ()>),
+    SetMetricsPolicy(core::result::Result<MetricsPolicy, ()>),
+    QueryMetrics(core::result::Result<QueryMetricsResponse, ()>),
 }
 
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// An identifier for a running command
 pub struct CommandId(pub u64);
 
 #[derive(From, TryInto)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Events, Errors and Responses from a remote emulator
 pub enum RemoteEmulatorOutput {
     Event(Event),
@@ -227,4 +737,69 @@ mod tests {
         assert_ne!(io_error_a2, io_error_b);
         assert_ne!(invalid_rom_a1, io_error_a1);
     }
+
+    #[test]
+    fn current_capabilities() {
+        let response = QueryCapabilitiesResponse::current();
+
+        assert_eq!(response.protocol_version, PROTOCOL_VERSION);
+        assert!(response.supports(Capability::Rewind));
+        assert!(!response.supports(Capability::Audio));
+        assert_eq!(
+            response.supports(Capability::Profiling),
+            cfg!(feature = "profiling")
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn unknown_capability_deserializes() {
+        let json = r#"{"protocol_version":2,"capabilities":["Rewind","Teleport"]}"#;
+        let response: QueryCapabilitiesResponse = serde_json::from_str(json).unwrap();
+
+        assert_eq!(
+            response.capabilities,
+            vec![Capability::Rewind, Capability::Unknown]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn command_serde_roundtrip() {
+        use crate::address::LiteralAddress;
+        use crate::monitor::{BreakpointCondition, RWTarget};
+
+        let breakpoint = Breakpoint::new(
+            RWTarget::Address(LiteralAddress(0x150)),
+            BreakpointCondition::Execute,
+        );
+        let command = EmulatorCommand::AddBreakpoint(breakpoint.clone());
+
+        let json = serde_json::to_string(&command).unwrap();
+        match serde_json::from_str(&json).unwrap() {
+            EmulatorCommand::AddBreakpoint(actual) => assert_eq!(actual, breakpoint),
+            other => panic!("Expected AddBreakpoint, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn response_serde_roundtrip() {
+        let responses = vec![
+            EmulatorResponse::QueryMemory(Ok(QueryMemoryResponse {
+                start_addr: 0x7FFF,
+                data: vec![Some(0x12), None],
+            })),
+            EmulatorResponse::LoadRom(Err(LoadRomError::InvalidRom(
+                CartridgeLoadError::CartridgeTooSmall(0x10),
+            ))),
+            EmulatorResponse::SetButton(Err(Error::NoRomLoaded)),
+        ];
+
+        for response in responses {
+            let json = serde_json::to_string(&response).unwrap();
+            let actual: EmulatorResponse = serde_json::from_str(&json).unwrap();
+            assert_eq!(actual, response);
+        }
+    }
 }
@@ -0,0 +1,1817 @@
+use crate::{
+    disassembler::disassemble_memory,
+    events::{
+        BreakpointsChangedEvent, EmulationErrorEvent, EmulationSpeedEvent, Event, IdleEvent,
+        ModeChangeEvent,
+    },
+    gameboy::{
+        Button, CycleProfile, ErrorContext, GameBoy, GameBoyModel, RenderLayers, StepError,
+        MAX_OVERCLOCK_CYCLES,
+    },
+    logging::{self, LogCapture},
+    monitor::{BreakpointState, DebugMonitor, DirtyBlocks, MetricsSampler, PostMortem},
+    registers::{Flag, WordRegister},
+    remote::{
+        autosave::AutoSaver,
+        commands::{
+            self, AutoSavePolicy, CommandId, EmulatorCommand, EmulatorResponse, ExecMode, ExecTime,
+            IdlePolicy, LoadRomError, MetricsPolicy, PauseTiming, QueryCapabilitiesResponse,
+            QueryDirtyMemoryResponse, QueryDisassemblyResponse, QueryFrameHashResponse,
+            QueryMemoryResponse, QueryMetricsResponse, QueryRegistersResponse, RegisterValue,
+            RemoteEmulatorOutput, ReverseStepResponse, RunCyclesResponse, SetButtonResponse,
+            SetFlagResponse, SetSaveFileResponse, SetSpeedResponse, ToggleBreakpointResponse,
+            WatchdogPolicy,
+        },
+        events::{Event as RemoteEvent, EventSendError, Sender},
+        idle::IdleDetector,
+        watchdog::Watchdog,
+    },
+    rom::Cartridge,
+    scheduler::{FrameScheduler, Schedule, StdClock},
+    shared::{Lock, Shared},
+};
+
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the emulation speed is reported while running
+const SPEED_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait before checking for commands again while not running
+const IDLE_SLEEP: Duration = Duration::from_millis(10);
+/// Most clocks a single step can take, for a conditional call that is taken
+/// or an interrupt being dispatched
+const MAX_STEP_CLOCKS: u64 = 24;
+
+/// A source of commands for an [`EmulatorThread`]
+///
+/// [`EmulatorThread`]: ./struct.EmulatorThread.html
+pub trait CommandReceiver {
+    /// Take the next command if one is queued, without blocking
+    ///
+    /// Returns an error once no more commands can be sent.
+    fn try_recv(&mut self) -> Result<Option<(CommandId, EmulatorCommand)>, EventSendError>;
+}
+
+impl CommandReceiver for mpsc::Receiver<(CommandId, EmulatorCommand)> {
+    fn try_recv(&mut self) -> Result<Option<(CommandId, EmulatorCommand)>, EventSendError> {
+        match mpsc::Receiver::try_recv(self) {
+            Ok(command) => Ok(Some(command)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => Err(EventSendError::ClosedChannelError),
+        }
+    }
+}
+
+impl Sender<RemoteEmulatorOutput> for mpsc::Sender<RemoteEmulatorOutput> {
+    fn send(&self, output: RemoteEmulatorOutput) -> Result<(), EventSendError> {
+        mpsc::Sender::send(self, output).map_err(|_| EventSendError::ClosedChannelError)
+    }
+}
+
+/// Checks breakpoints against the gameboy and the events it emits
+struct Monitor {
+    debug: DebugMonitor,
+    /// Events emitted by the gameboy since they were last handled
+    events: Shared<Lock<Vec<Event>>>,
+    /// Handled events which have not been sent yet
+    unsent: Vec<RemoteEvent>,
+    /// Memory written since it was last queried
+    dirty: DirtyBlocks,
+    /// Values sampled once per frame
+    metrics: MetricsSampler,
+}
+
+impl Monitor {
+    fn handle_events(&mut self) {
+        for evt in self.events.replace(Vec::new()) {
+            self.debug.handle_event(&evt);
+            self.dirty.record(&evt);
+            self.unsent.push(evt.into());
+        }
+    }
+
+    fn step(&mut self, gb: &mut GameBoy, inital_mode: ExecMode) -> Result<ExecMode, StepError> {
+        gb.step()?;
+        self.handle_events();
+        let breakpoint_count = self.debug.breakpoints().len();
+        self.debug.poll(gb);
+        self.metrics.poll(gb);
+        // Memory reads made while checking breakpoints and sampling aren't from the game
+        self.events.replace(Vec::new());
+        if self.debug.breakpoints().len() != breakpoint_count {
+            // Temporary breakpoints were removed
+            let breakpoints = self.debug.breakpoints().to_vec();
+            self.unsent
+                .push(BreakpointsChangedEvent::new(breakpoints).into());
+        }
+        if let BreakpointState::HitBreakpoint(bp) = self.debug.state() {
+            log::info!(target: logging::EMULATOR_THREAD, "Hit breakpoint: {:?}", bp);
+            return Ok(ExecMode::HitBreakpoint(bp));
+        }
+        Ok(inital_mode)
+    }
+
+    /// Sample watched values if a frame has started, without checking breakpoints
+    fn sample_metrics(&mut self, gb: &GameBoy) {
+        if self.metrics.poll(gb) {
+            // Memory reads made while sampling aren't from the game
+            self.events.replace(Vec::new());
+        }
+    }
+}
+
+struct EmulatorState {
+    gameboy: Option<GameBoy>,
+    monitor: Monitor,
+    /// Layers to draw, kept when another ROM is loaded
+    render_layers: RenderLayers,
+    /// Extra CPU cycles per line, kept when another ROM is loaded
+    overclock: u16,
+    /// Number of steps recorded to undo, kept when another ROM is loaded
+    history_limit: usize,
+    /// Values to sample once per frame, kept when another ROM is loaded
+    metrics_policy: MetricsPolicy,
+}
+
+impl EmulatorState {
+    fn new() -> EmulatorState {
+        EmulatorState {
+            gameboy: None,
+            monitor: Monitor {
+                debug: DebugMonitor::new(),
+                events: Shared::new(Lock::new(Vec::new())),
+                unsent: Vec::new(),
+                dirty: DirtyBlocks::new(),
+                metrics: MetricsSampler::new(Vec::new(), 0),
+            },
+            render_layers: RenderLayers::default(),
+            overclock: 0,
+            history_limit: 0,
+            metrics_policy: MetricsPolicy::default(),
+        }
+    }
+
+    fn step(&mut self) -> commands::Result<()> {
+        if let Some(gb) = self.gameboy.as_mut() {
+            gb.step().map_err(commands::Error::Exec)
+        } else {
+            Err(commands::Error::NoRomLoaded)
+        }
+    }
+
+    /// Run whole steps until another step could go over `budget` clocks
+    ///
+    /// When `stop_on_breakpoint` is false, breakpoints are still checked
+    /// against memory accesses, but are reset once the run ends. The run also
+    /// ends early once `over_budget` returns true.
+    fn run_cycles(
+        &mut self,
+        budget: u64,
+        stop_on_breakpoint: bool,
+        mut over_budget: impl FnMut() -> bool,
+    ) -> commands::Result<RunCyclesResponse> {
+        let EmulatorState {
+            gameboy, monitor, ..
+        } = self;
+        let gb = gameboy.as_mut().ok_or(commands::Error::NoRomLoaded)?;
+        monitor.debug.resume();
+        let start_clocks = gb.clocks_elapsed();
+        let mut breakpoint = None;
+        while budget.saturating_sub(gb.clocks_elapsed() - start_clocks) >= MAX_STEP_CLOCKS {
+            let step_start = gb.clocks_elapsed();
+            if stop_on_breakpoint {
+                if let ExecMode::HitBreakpoint(bp) = monitor.step(gb, ExecMode::Paused)? {
+                    breakpoint = Some(bp);
+                    break;
+                }
+            } else {
+                gb.step()?;
+                monitor.handle_events();
+                monitor.sample_metrics(gb);
+            }
+            if gb.clocks_elapsed() == step_start {
+                // Stopped by a STOP instruction, so no time passes until a button is pressed
+                break;
+            }
+            if over_budget() {
+                break;
+            }
+        }
+        if !stop_on_breakpoint {
+            monitor.debug.resume();
+        }
+        let cycles = gb.clocks_elapsed() - start_clocks;
+        Ok(RunCyclesResponse::new(cycles, breakpoint))
+    }
+
+    fn set_button(&mut self, button: Button, pressed: bool) -> commands::Result<SetButtonResponse> {
+        if let Some(gb) = self.gameboy.as_ref() {
+            // Latched at the next frame, so the game sees the same input
+            // however long the command took to arrive
+            gb.input_queue().push(button, pressed);
+            Ok(SetButtonResponse::new(button, pressed))
+        } else {
+            Err(commands::Error::NoRomLoaded)
+        }
+    }
+
+    fn set_render_layers(&mut self, layers: RenderLayers) -> RenderLayers {
+        self.render_layers = layers;
+        if let Some(gb) = self.gameboy.as_mut() {
+            gb.set_render_layers(layers);
+        }
+        layers
+    }
+
+    fn set_overclock(&mut self, extra_cycles_per_line: u16) -> u16 {
+        self.overclock = extra_cycles_per_line.min(MAX_OVERCLOCK_CYCLES);
+        if let Some(gb) = self.gameboy.as_mut() {
+            gb.set_overclock(self.overclock);
+        }
+        self.overclock
+    }
+
+    fn set_history_limit(&mut self, steps: usize) -> usize {
+        self.history_limit = steps;
+        if let Some(gb) = self.gameboy.as_mut() {
+            gb.set_history_limit(steps);
+        }
+        steps
+    }
+
+    fn reverse_step(&mut self) -> commands::Result<ReverseStepResponse> {
+        if let Some(gb) = self.gameboy.as_mut() {
+            if gb.reverse_step() {
+                // Restoring history doesn't emit write events
+                self.monitor.dirty.mark_all();
+                Ok(ReverseStepResponse::new(gb.history_len()))
+            } else {
+                Err(commands::Error::NoHistory)
+            }
+        } else {
+            Err(commands::Error::NoRomLoaded)
+        }
+    }
+
+    fn load_rom(&mut self, data: Vec<u8>) -> Result<(), LoadRomError> {
+        let mut gb = GameBoy::new(Cartridge::from_data(data)?, GameBoyModel::GameBoy);
+        gb.set_history_limit(self.history_limit);
+        gb.set_render_layers(self.render_layers);
+        gb.set_overclock(self.overclock);
+        let events = self.monitor.events.clone();
+        gb.events.on(Box::new(move |evt| {
+            events.borrow_mut().push(evt.clone());
+        }));
+        self.gameboy = Some(gb);
+        self.monitor.dirty.mark_all();
+        self.reset_metrics();
+        Ok(())
+    }
+
+    fn set_metrics_policy(&mut self, policy: MetricsPolicy) -> MetricsPolicy {
+        self.metrics_policy = policy.clone();
+        self.reset_metrics();
+        policy
+    }
+
+    /// Start sampling again with no samples recorded
+    fn reset_metrics(&mut self) {
+        let policy = &self.metrics_policy;
+        self.monitor.metrics = MetricsSampler::new(policy.targets.clone(), policy.frames);
+    }
+
+    fn query_metrics(&mut self, clear: bool) -> QueryMetricsResponse {
+        let metrics = &mut self.monitor.metrics;
+        let response = QueryMetricsResponse {
+            targets: metrics.targets().to_vec(),
+            samples: metrics.iter().cloned().collect(),
+        };
+        if clear {
+            metrics.clear();
+        }
+        response
+    }
+
+    fn exec_time(&mut self) -> commands::Result<ExecTime> {
+        if let Some(gb) = self.gameboy.as_ref() {
+            Ok(gb.time_elapsed().into())
+        } else {
+            Err(commands::Error::NoRomLoaded)
+        }
+    }
+
+    fn query_registers(&mut self) -> commands::Result<QueryRegistersResponse> {
+        if let Some(gb) = self.gameboy.as_ref() {
+            Ok(QueryRegistersResponse {
+                af: gb.read_register_u16(WordRegister::AF),
+                bc: gb.read_register_u16(WordRegister::BC),
+                de: gb.read_register_u16(WordRegister::DE),
+                hl: gb.read_register_u16(WordRegister::HL),
+                sp: gb.read_register_u16(WordRegister::SP),
+                pc: gb.read_register_u16(WordRegister::PC),
+            })
+        } else {
+            Err(commands::Error::NoRomLoaded)
+        }
+    }
+
+    fn set_register(&mut self, value: RegisterValue) -> commands::Result<RegisterValue> {
+        let gb = self.gameboy.as_mut().ok_or(commands::Error::NoRomLoaded)?;
+        Ok(match value {
+            RegisterValue::Byte(reg, value) => {
+                gb.write_register_u8(reg, value);
+                RegisterValue::Byte(reg, gb.read_register_u8(reg))
+            }
+            RegisterValue::Word(reg, value) => {
+                gb.write_register_u16(reg, value);
+                RegisterValue::Word(reg, gb.read_register_u16(reg))
+            }
+        })
+    }
+
+    fn set_flag(&mut self, flag: Flag, set: bool) -> commands::Result<SetFlagResponse> {
+        let gb = self.gameboy.as_mut().ok_or(commands::Error::NoRomLoaded)?;
+        // Written as a whole register, as flag changes don't emit register events
+        let af = gb.read_register_u16(WordRegister::AF);
+        let mask = 1 << flag.bit();
+        let new_af = if set { af | mask } else { af & !mask };
+        gb.write_register_u16(WordRegister::AF, new_af);
+        Ok(SetFlagResponse::new(flag, set))
+    }
+
+    fn query_memory(
+        &mut self,
+        start_addr: u16,
+        end_addr: u16,
+    ) -> commands::Result<QueryMemoryResponse> {
+        let mut data: Vec<Option<u8>> = Vec::with_capacity((end_addr - start_addr) as usize + 1);
+        if let Some(gb) = self.gameboy.as_ref() {
+            for addr in start_addr..=end_addr {
+                data.push(gb.get_memory_u8(addr).ok())
+            }
+            Ok(QueryMemoryResponse { start_addr, data })
+        } else {
+            Err(commands::Error::NoRomLoaded)
+        }
+    }
+
+    fn query_dirty_memory(&mut self) -> commands::Result<QueryDirtyMemoryResponse> {
+        if self.gameboy.is_some() {
+            self.monitor.handle_events();
+            Ok(QueryDirtyMemoryResponse {
+                blocks: self.monitor.dirty.take(),
+            })
+        } else {
+            Err(commands::Error::NoRomLoaded)
+        }
+    }
+
+    /// Where an error happened, for errors which don't record it
+    fn error_context(&self, error: &StepError) -> Option<ErrorContext> {
+        let gb = self.gameboy.as_ref()?;
+        Some(
+            error
+                .context()
+                .copied()
+                .unwrap_or_else(|| gb.error_context(gb.read_pc())),
+        )
+    }
+
+    fn query_frame_hash(&self) -> commands::Result<QueryFrameHashResponse> {
+        let gb = self.gameboy.as_ref().ok_or(commands::Error::NoRomLoaded)?;
+        Ok(QueryFrameHashResponse {
+            frame_hash: gb.frame_hash(),
+            run_hash: gb.run_hash(),
+        })
+    }
+
+    fn query_profile(&mut self, reset: bool) -> commands::Result<CycleProfile> {
+        let gb = self.gameboy.as_mut().ok_or(commands::Error::NoRomLoaded)?;
+        let profile = gb.profile().ok_or(commands::Error::ProfilingDisabled)?;
+        if reset {
+            gb.reset_profile();
+        }
+        Ok(profile)
+    }
+
+    fn query_post_mortem(&self, error: Option<StepError>) -> commands::Result<PostMortem> {
+        match self.gameboy.as_ref() {
+            Some(gb) => Ok(PostMortem::capture(gb, error)),
+            None => Err(commands::Error::NoRomLoaded),
+        }
+    }
+
+    fn query_disassembly(
+        &self,
+        start: u16,
+        count: u16,
+    ) -> commands::Result<QueryDisassemblyResponse> {
+        let gb = self.gameboy.as_ref().ok_or(commands::Error::NoRomLoaded)?;
+        Ok(QueryDisassemblyResponse {
+            instructions: disassemble_memory(gb, start, usize::from(count)),
+        })
+    }
+}
+
+/// Runs an emulator in response to commands from a [`RemoteEmulator`]
+///
+/// This runs the emulator at the requested speed, checks breakpoints
+/// after each step, and sends responses and emulator events to `output`.
+/// Frontends only need to provide the channels, and then either call
+/// [`EmulatorThread::spawn`] or call [`EmulatorThread::run`] on a thread
+/// of their own.
+///
+/// [`RemoteEmulator`]: ./struct.RemoteEmulator.html
+/// [`EmulatorThread::spawn`]: #method.spawn
+/// [`EmulatorThread::run`]: #method.run
+pub struct EmulatorThread<R, S> {
+    state: EmulatorState,
+    commands: R,
+    output: S,
+    exec_mode: ExecMode,
+    scheduler: FrameScheduler<StdClock>,
+    frames: u32,
+    last_fps: u32,
+    last_speed_report: Instant,
+    idle: IdleDetector,
+    watchdog: Watchdog,
+    /// Log records to stream, while streaming logs
+    log_capture: Option<LogCapture>,
+    saver: AutoSaver,
+    pause_timing: PauseTiming,
+    /// Frame count when a pause was requested, while waiting for that frame to end
+    pause_frame: Option<u64>,
+}
+
+impl<R, S> EmulatorThread<R, S>
+where
+    R: CommandReceiver,
+    S: Sender<RemoteEmulatorOutput>,
+{
+    /// Create an emulator with no ROM loaded, controlled by `commands`
+    pub fn new(commands: R, output: S) -> EmulatorThread<R, S> {
+        EmulatorThread {
+            state: EmulatorState::new(),
+            commands,
+            output,
+            exec_mode: ExecMode::Unloaded,
+            scheduler: FrameScheduler::new(StdClock::new()),
+            frames: 0,
+            last_fps: 0,
+            last_speed_report: Instant::now(),
+            idle: IdleDetector::new(IdlePolicy::default()),
+            watchdog: Watchdog::new(WatchdogPolicy::default()),
+            log_capture: None,
+            saver: AutoSaver::new(AutoSavePolicy::default()),
+            pause_timing: PauseTiming::default(),
+            pause_frame: None,
+        }
+    }
+
+    fn send_event<E: Into<RemoteEvent>>(&self, event: E) -> Result<(), EventSendError> {
+        self.output.send(RemoteEmulatorOutput::Event(event.into()))
+    }
+
+    /// Send events emitted by the gameboy since the last call
+    fn send_pending_events(&mut self) -> Result<(), EventSendError> {
+        self.state.monitor.handle_events();
+        let events = core::mem::take(&mut self.state.monitor.unsent);
+        for event in events {
+            if let RemoteEvent::VBlank(_) = event {
+                self.frames += 1;
+            }
+            self.send_event(event)?;
+        }
+        if let Some(capture) = &self.log_capture {
+            for record in capture.take() {
+                self.send_event(record)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn send_breakpoints_changed(&self) -> Result<(), EventSendError> {
+        let breakpoints = self.state.monitor.debug.breakpoints().to_vec();
+        self.send_event(BreakpointsChangedEvent::new(breakpoints))
+    }
+
+    fn send_speed(&self) -> Result<(), EventSendError> {
+        self.send_event(EmulationSpeedEvent::new(
+            self.scheduler.speed_percent(),
+            self.last_fps,
+        ))
+    }
+
+    /// Save battery backed RAM if it has changed, reporting the result
+    fn save_battery_ram(&mut self) -> Result<(), EventSendError> {
+        match self.state.gameboy.as_mut() {
+            Some(gb) => match self.saver.flush(gb) {
+                Some(evt) => self.send_event(evt),
+                None => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    fn set_mode(&mut self, mode: ExecMode) -> Result<(), EventSendError> {
+        if !is_running(&mode) {
+            self.pause_frame = None;
+            self.watchdog.stop();
+            if is_running(&self.exec_mode) {
+                self.save_battery_ram()?;
+            }
+        }
+        if mode != self.exec_mode {
+            let old_mode = core::mem::replace(&mut self.exec_mode, mode.clone());
+            self.send_event(ModeChangeEvent::new(old_mode, mode))?;
+        }
+        Ok(())
+    }
+
+    /// Whether a request to pause should wait for the running frame to end
+    fn pause_at_frame_end(&self) -> bool {
+        let lcd_enabled = match self.state.gameboy.as_ref() {
+            Some(gb) => gb.ppu.is_enabled(&gb.mem),
+            None => false,
+        };
+        self.pause_timing == PauseTiming::NextFrame && is_running(&self.exec_mode) && lcd_enabled
+    }
+
+    /// Start counting idle frames again, reporting that the game is no longer idle
+    fn wake(&mut self) -> Result<(), EventSendError> {
+        if self.idle.reset() {
+            self.send_event(IdleEvent::new(false))?;
+        }
+        Ok(())
+    }
+
+    /// Report the emulated frame rate once per interval while running
+    fn report_speed(&mut self) -> Result<(), EventSendError> {
+        let elapsed = self.last_speed_report.elapsed();
+        if elapsed < SPEED_REPORT_INTERVAL {
+            return Ok(());
+        }
+        let frames = core::mem::replace(&mut self.frames, 0);
+        self.last_fps = (f64::from(frames) / elapsed.as_secs_f64()).round() as u32;
+        self.last_speed_report = Instant::now();
+        self.send_speed()
+    }
+
+    fn handle_command(&mut self, cmd: EmulatorCommand) -> Result<EmulatorResponse, EventSendError> {
+        Ok(match cmd {
+            EmulatorCommand::LoadRom(data) => {
+                self.wake()?;
+                self.save_battery_ram()?;
+                self.saver.detach();
+                let resp = EmulatorResponse::LoadRom(self.state.load_rom(data));
+                // Always report the ROM was loaded, even if one was already running
+                self.exec_mode = ExecMode::Unloaded;
+                self.set_mode(ExecMode::Paused)?;
+                resp
+            }
+            EmulatorCommand::QueryMemory(start_index, end_index) => {
+                EmulatorResponse::QueryMemory(self.state.query_memory(start_index, end_index))
+            }
+            EmulatorCommand::QueryDirtyMemory => {
+                EmulatorResponse::QueryDirtyMemory(self.state.query_dirty_memory())
+            }
+            EmulatorCommand::QueryDisassembly { start, count } => {
+                EmulatorResponse::QueryDisassembly(self.state.query_disassembly(start, count))
+            }
+            EmulatorCommand::QueryRegisters => {
+                EmulatorResponse::QueryRegisters(self.state.query_registers())
+            }
+            EmulatorCommand::SetRegister(value) => {
+                EmulatorResponse::SetRegister(self.state.set_register(value))
+            }
+            EmulatorCommand::SetFlag(flag, set) => {
+                EmulatorResponse::SetFlag(self.state.set_flag(flag, set))
+            }
+            EmulatorCommand::Step => EmulatorResponse::Step(self.state.step()),
+            EmulatorCommand::RunCycles {
+                budget,
+                stop_on_breakpoint,
+            } => {
+                let EmulatorThread {
+                    state,
+                    output,
+                    watchdog,
+                    ..
+                } = self;
+                let mut send_result = Ok(());
+                watchdog.start();
+                let resp =
+                    state.run_cycles(budget, stop_on_breakpoint, || match watchdog.check() {
+                        Some(evt) => {
+                            send_result = output.send(RemoteEmulatorOutput::Event(evt.into()));
+                            evt.paused
+                        }
+                        None => false,
+                    });
+                watchdog.stop();
+                send_result?;
+                if let Ok(RunCyclesResponse {
+                    breakpoint: Some(bp),
+                    ..
+                }) = &resp
+                {
+                    // Events from the run arrive before the mode change, as they do while running
+                    self.send_pending_events()?;
+                    self.set_mode(ExecMode::HitBreakpoint(bp.clone()))?;
+                }
+                EmulatorResponse::RunCycles(resp)
+            }
+            EmulatorCommand::ReverseStep => {
+                EmulatorResponse::ReverseStep(self.state.reverse_step())
+            }
+            EmulatorCommand::SetHistoryLimit(steps) => {
+                EmulatorResponse::SetHistoryLimit(Ok(self.state.set_history_limit(steps)))
+            }
+            EmulatorCommand::QueryExecTime => {
+                EmulatorResponse::QueryExecTime(self.state.exec_time())
+            }
+            EmulatorCommand::SetMode(ExecMode::Paused) if self.pause_at_frame_end() => {
+                if let Some(gb) = self.state.gameboy.as_ref() {
+                    self.pause_frame = Some(gb.ppu.frame_count());
+                }
+                EmulatorResponse::SetMode(Ok(self.exec_mode.clone()))
+            }
+            EmulatorCommand::SetMode(mode) => {
+                self.pause_frame = None;
+                if is_running(&mode) {
+                    self.state.monitor.debug.resume();
+                    self.wake()?;
+                }
+                let breakpoints = self.state.monitor.debug.breakpoints();
+                if is_running(&mode) && breakpoints.iter().any(|(_, bp)| bp.active) {
+                    // May never stop if the breakpoint can't be reached
+                    self.watchdog.start();
+                } else {
+                    self.watchdog.stop();
+                }
+                if !is_running(&mode) {
+                    self.save_battery_ram()?;
+                }
+                let old_mode = core::mem::replace(&mut self.exec_mode, mode.clone());
+                self.send_event(ModeChangeEvent::new(old_mode, mode))?;
+                EmulatorResponse::SetMode(Ok(self.exec_mode.clone()))
+            }
+            EmulatorCommand::SetSpeed(speed_percent) => {
+                if speed_percent == 0 {
+                    EmulatorResponse::SetSpeed(Err(()))
+                } else {
+                    self.scheduler.set_speed(speed_percent);
+                    self.send_speed()?;
+                    EmulatorResponse::SetSpeed(Ok(SetSpeedResponse::new(speed_percent)))
+                }
+            }
+            EmulatorCommand::AddBreakpoint(mut bp) => {
+                let EmulatorState {
+                    gameboy, monitor, ..
+                } = &mut self.state;
+                if let Some(gb) = gameboy {
+                    // Record the starting state for `Changed` and `Return` breakpoints
+                    bp.check(gb);
+                    monitor.events.replace(Vec::new());
+                }
+                match monitor.debug.add_breakpoint(bp) {
+                    Ok(id) => {
+                        self.send_breakpoints_changed()?;
+                        EmulatorResponse::AddBreakpoint(Ok(id.into()))
+                    }
+                    Err(e) => {
+                        log::warn!(target: logging::MONITOR, "Rejected breakpoint: {}", e);
+                        EmulatorResponse::AddBreakpoint(Err(()))
+                    }
+                }
+            }
+            EmulatorCommand::RemoveBreakpoint(id) => {
+                let resp = self.state.monitor.debug.remove_breakpoint(id);
+                if resp.is_none() {
+                    log::info!(target: logging::MONITOR, "Tried to remove invalid breakpoint {:?}", id);
+                } else {
+                    self.send_breakpoints_changed()?;
+                }
+                EmulatorResponse::RemoveBreakpoint(Ok(id.into()))
+            }
+            EmulatorCommand::SetBreakpointActive(id, state) => {
+                let resp = self.state.monitor.debug.set_breakpoint_state(id, state);
+                if let Some(state) = resp {
+                    self.send_breakpoints_changed()?;
+                    EmulatorResponse::ToggleBreakpoint(Ok(ToggleBreakpointResponse::new(id, state)))
+                } else {
+                    EmulatorResponse::ToggleBreakpoint(Err(()))
+                }
+            }
+            EmulatorCommand::SetButton(button, pressed) => {
+                self.wake()?;
+                EmulatorResponse::SetButton(self.state.set_button(button, pressed))
+            }
+            EmulatorCommand::SetRenderLayers(layers) => {
+                EmulatorResponse::SetRenderLayers(Ok(self.state.set_render_layers(layers)))
+            }
+            EmulatorCommand::QueryBreakpoints => {
+                let breakpoints = self.state.monitor.debug.breakpoints().to_vec();
+                EmulatorResponse::QueryBreakpoints(Ok(breakpoints.into()))
+            }
+            EmulatorCommand::QueryPostMortem => {
+                let error = match &self.exec_mode {
+                    ExecMode::Error(e) => Some(e.clone()),
+                    _ => None,
+                };
+                EmulatorResponse::QueryPostMortem(self.state.query_post_mortem(error))
+            }
+            EmulatorCommand::QueryFrameHash => {
+                EmulatorResponse::QueryFrameHash(self.state.query_frame_hash())
+            }
+            EmulatorCommand::QueryProfile { reset } => {
+                EmulatorResponse::QueryProfile(self.state.query_profile(reset))
+            }
+            EmulatorCommand::SetIdlePolicy(policy) => {
+                if self.idle.set_policy(policy) {
+                    self.send_event(IdleEvent::new(false))?;
+                }
+                EmulatorResponse::SetIdlePolicy(Ok(policy))
+            }
+            EmulatorCommand::SetWatchdogPolicy(policy) => {
+                self.watchdog.set_policy(policy);
+                EmulatorResponse::SetWatchdogPolicy(Ok(policy))
+            }
+            EmulatorCommand::SetOverclock(extra_cycles_per_line) => {
+                EmulatorResponse::SetOverclock(Ok(self.state.set_overclock(extra_cycles_per_line)))
+            }
+            EmulatorCommand::StreamLogs(stream) => {
+                if stream != self.log_capture.is_some() {
+                    self.log_capture = if stream {
+                        Some(LogCapture::start())
+                    } else {
+                        None
+                    };
+                }
+                EmulatorResponse::StreamLogs(Ok(stream))
+            }
+            EmulatorCommand::SetLogLevel(target, level) => {
+                logging::set_level(&target, level);
+                EmulatorResponse::SetLogLevel(Ok(level))
+            }
+            EmulatorCommand::SetSaveFile(path) => {
+                self.save_battery_ram()?;
+                self.saver.detach();
+                let resp = match (self.state.gameboy.as_mut(), path) {
+                    (None, _) => Err(commands::Error::NoRomLoaded),
+                    (Some(gb), Some(path)) => self.saver.attach(gb, PathBuf::from(path)),
+                    (Some(gb), None) => Ok(SetSaveFileResponse {
+                        path: None,
+                        has_battery: gb.battery_ram().is_some(),
+                        loaded: false,
+                    }),
+                };
+                EmulatorResponse::SetSaveFile(resp)
+            }
+            EmulatorCommand::SetAutoSavePolicy(policy) => {
+                self.saver.set_policy(policy);
+                EmulatorResponse::SetAutoSavePolicy(Ok(policy))
+            }
+            EmulatorCommand::SaveBatteryRam => {
+                let resp = match self.state.gameboy.as_mut() {
+                    Some(gb) => match self.saver.flush(gb) {
+                        Some(evt) => {
+                            let saved = evt.error.is_none();
+                            self.send_event(evt)?;
+                            Ok(saved)
+                        }
+                        None => Ok(false),
+                    },
+                    None => Err(commands::Error::NoRomLoaded),
+                };
+                EmulatorResponse::SaveBatteryRam(resp)
+            }
+            EmulatorCommand::QueryCapabilities => {
+                EmulatorResponse::QueryCapabilities(Ok(QueryCapabilitiesResponse::current()))
+            }
+            EmulatorCommand::SetPauseTiming(timing) => {
+                self.pause_timing = timing;
+                EmulatorResponse::SetPauseTiming(Ok(timing))
+            }
+            EmulatorCommand::Batch(commands) => {
+                let mut responses = Vec::with_capacity(commands.len());
+                for command in commands {
+                    responses.push(self.handle_command(command)?);
+                }
+                EmulatorResponse::Batch(Ok(responses))
+            }
+            EmulatorCommand::SetMetricsPolicy(policy) => {
+                EmulatorResponse::SetMetricsPolicy(Ok(self.state.set_metrics_policy(policy)))
+            }
+            EmulatorCommand::QueryMetrics { clear } => {
+                EmulatorResponse::QueryMetrics(Ok(self.state.query_metrics(clear)))
+            }
+        })
+    }
+
+    fn handle_commands(&mut self) -> Result<(), EventSendError> {
+        while let Some((id, cmd)) = self.commands.try_recv()? {
+            let resp = self.handle_command(cmd)?;
+            // Events caused by the command should arrive before its response
+            self.send_pending_events()?;
+            self.output.send(RemoteEmulatorOutput::Response(id, resp))?;
+        }
+        Ok(())
+    }
+
+    /// Handle any waiting commands, then run the emulator for a short time
+    /// in the current exec mode
+    ///
+    /// When paused, or when running at normal speed but nothing is due to run
+    /// yet, this sleeps instead. Returns an error once either channel is closed.
+    pub fn tick(&mut self) -> Result<(), EventSendError> {
+        self.handle_commands()?;
+        let EmulatorState {
+            gameboy, monitor, ..
+        } = &mut self.state;
+        let gb = match gameboy.as_mut() {
+            Some(gb) => gb,
+            None => {
+                thread::sleep(IDLE_SLEEP);
+                return Ok(());
+            }
+        };
+        let start_time = Instant::now();
+        let idle = &mut self.idle;
+        let mut idle_change = None;
+        let pause_frame = self.pause_frame;
+        let mut frame_ended = false;
+        // Stops running early if the game becomes idle and should be paused,
+        // or the frame a pause is waiting for has ended. Frames stop counting
+        // while the LCD is off, so switching it off ends the wait too.
+        let mut record_step = |gb: &GameBoy| {
+            if let Some(now_idle) = idle.record_step(gb) {
+                idle_change = Some(now_idle);
+            }
+            frame_ended = matches!(pause_frame, Some(frame)
+                if gb.ppu.frame_count() != frame || !gb.ppu.is_enabled(&gb.mem));
+            frame_ended || (idle_change == Some(true) && idle.policy().auto_pause)
+        };
+        let result = match &self.exec_mode {
+            ExecMode::Paused
+            | ExecMode::Unloaded
+            | ExecMode::HitBreakpoint(_)
+            | ExecMode::Error(_) => {
+                thread::sleep(IDLE_SLEEP);
+                self.frames = 0;
+                self.last_speed_report = start_time;
+                self.scheduler.reset();
+                Ok(self.exec_mode.clone())
+            }
+            ExecMode::Standard => match self.scheduler.poll() {
+                Schedule::Sleep(duration) => {
+                    thread::sleep(duration);
+                    gb.add_exec_time(start_time.elapsed().as_secs_f64());
+                    Ok(self.exec_mode.clone())
+                }
+                Schedule::Run(clocks) => {
+                    let start_clocks = gb.clocks_elapsed();
+                    let mut step_result = Ok(ExecMode::Standard);
+                    while gb.clocks_elapsed() - start_clocks < clocks {
+                        step_result = monitor.step(gb, ExecMode::Standard);
+                        if step_result != Ok(ExecMode::Standard) || record_step(gb) {
+                            break;
+                        }
+                    }
+                    self.scheduler.completed(gb.clocks_elapsed() - start_clocks);
+                    gb.add_exec_time(start_time.elapsed().as_secs_f64());
+                    step_result
+                }
+            },
+            ExecMode::Uncapped => {
+                let step_result = monitor.step(gb, self.exec_mode.clone());
+                record_step(gb);
+                gb.add_exec_time(start_time.elapsed().as_secs_f64());
+                step_result
+            }
+        };
+        self.send_pending_events()?;
+        let result = match idle_change {
+            Some(now_idle) => {
+                self.send_event(IdleEvent::new(now_idle))?;
+                match result {
+                    Ok(ExecMode::Standard) | Ok(ExecMode::Uncapped)
+                        if now_idle && self.idle.policy().auto_pause =>
+                    {
+                        Ok(ExecMode::Paused)
+                    }
+                    result => result,
+                }
+            }
+            None => result,
+        };
+        let result = match result {
+            Ok(mode) if frame_ended && is_running(&mode) => Ok(ExecMode::Paused),
+            result => result,
+        };
+        let result = match result {
+            Ok(mode) if is_running(&mode) => match self.watchdog.check() {
+                Some(evt) => {
+                    self.send_event(evt)?;
+                    Ok(if evt.paused { ExecMode::Paused } else { mode })
+                }
+                None => Ok(mode),
+            },
+            result => result,
+        };
+        if let Ok(mode) = &result {
+            if is_running(mode) {
+                let saver = &mut self.saver;
+                if let Some(evt) = self.state.gameboy.as_mut().and_then(|gb| saver.check(gb)) {
+                    self.send_event(evt)?;
+                }
+            }
+        }
+        match result {
+            Err(e) => {
+                log::error!(target: logging::EMULATOR_THREAD, "Stopped due to error: {}", e);
+                self.output.send(commands::Error::Exec(e.clone()).into())?;
+                if let Some(context) = self.state.error_context(&e) {
+                    self.send_event(EmulationErrorEvent::new(e.clone(), context))?;
+                }
+                // Stop rather than failing the same way on every step
+                self.set_mode(ExecMode::Error(e))?;
+            }
+            Ok(mode) => self.set_mode(mode)?,
+        }
+        self.report_speed()
+    }
+
+    /// Run the emulator until either channel is closed
+    pub fn run(mut self) {
+        while self.tick().is_ok() {}
+    }
+}
+
+impl<R, S> Drop for EmulatorThread<R, S> {
+    fn drop(&mut self) {
+        // The frontend may already be gone, so only log the result
+        if let Some(gb) = self.state.gameboy.as_mut() {
+            self.saver.flush(gb);
+        }
+    }
+}
+
+fn is_running(mode: &ExecMode) -> bool {
+    matches!(mode, ExecMode::Standard | ExecMode::Uncapped)
+}
+
+impl<R, S> EmulatorThread<R, S>
+where
+    R: CommandReceiver + Send + 'static,
+    S: Sender<RemoteEmulatorOutput> + Send + 'static,
+{
+    /// Start a new thread running an emulator controlled by `commands`
+    ///
+    /// The thread stops once either channel is closed.
+    pub fn spawn(commands: R, output: S) -> thread::JoinHandle<()> {
+        thread::spawn(move || EmulatorThread::new(commands, output).run())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::LiteralAddress;
+    use crate::disassembler::DisassemblyLine;
+    use crate::events::{BatterySaveEvent, RegisterWriteEvent, VBlankEvent, WatchdogEvent};
+    use crate::monitor::{Breakpoint, BreakpointCondition, Comparison, IndirectAddress, RWTarget};
+    use crate::registers::ByteRegister;
+    use log::LevelFilter;
+
+    struct TestEmulator {
+        commands: mpsc::Sender<(CommandId, EmulatorCommand)>,
+        output: mpsc::Receiver<RemoteEmulatorOutput>,
+        handle: thread::JoinHandle<()>,
+        next_id: u64,
+    }
+
+    impl TestEmulator {
+        fn start() -> TestEmulator {
+            let (commands, command_rx) = mpsc::channel();
+            let (output_tx, output) = mpsc::channel();
+            TestEmulator {
+                commands,
+                output,
+                handle: EmulatorThread::spawn(command_rx, output_tx),
+                next_id: 0,
+            }
+        }
+
+        fn recv(&self) -> RemoteEmulatorOutput {
+            self.output
+                .recv_timeout(Duration::from_secs(10))
+                .expect("No output from emulator thread")
+        }
+
+        /// Send a command and wait for its response, collecting events sent before it
+        fn execute(&mut self, command: EmulatorCommand) -> (EmulatorResponse, Vec<RemoteEvent>) {
+            let id = CommandId(self.next_id);
+            self.next_id += 1;
+            self.commands.send((id, command)).unwrap();
+            let mut events = Vec::new();
+            loop {
+                match self.recv() {
+                    RemoteEmulatorOutput::Response(resp_id, resp) if resp_id == id => {
+                        return (resp, events)
+                    }
+                    RemoteEmulatorOutput::Event(evt) => events.push(evt),
+                    RemoteEmulatorOutput::Response(resp_id, _) => {
+                        panic!("Unexpected response for {:?}", resp_id)
+                    }
+                    RemoteEmulatorOutput::Error(e) => panic!("Emulator error {:?}", e),
+                }
+            }
+        }
+
+        fn query_pc(&mut self) -> u16 {
+            match self.execute(EmulatorCommand::QueryRegisters).0 {
+                EmulatorResponse::QueryRegisters(Ok(registers)) => registers.pc,
+                resp => panic!("Unexpected response {:?}", resp),
+            }
+        }
+
+        fn query_dirty_blocks(&mut self) -> Vec<u16> {
+            match self.execute(EmulatorCommand::QueryDirtyMemory).0 {
+                EmulatorResponse::QueryDirtyMemory(Ok(dirty)) => dirty.blocks,
+                resp => panic!("Unexpected response {:?}", resp),
+            }
+        }
+    }
+
+    fn rom() -> Vec<u8> {
+        // NOPs followed by an infinite loop
+        let mut data = vec![0; 0x8000];
+        data[0x110] = 0x18; // JR -2h
+        data[0x111] = 0xFE;
+        data
+    }
+
+    fn halt_rom() -> Vec<u8> {
+        // NOPs followed by halting forever, as no interrupts are enabled
+        let mut data = rom();
+        data[0x110] = 0x76; // HALT
+        data[0x111] = 0x18; // JR -3h
+        data[0x112] = 0xFD;
+        data
+    }
+
+    #[test]
+    fn test_step() {
+        let mut emu = TestEmulator::start();
+
+        let (resp, _) = emu.execute(EmulatorCommand::Step);
+        assert_eq!(
+            resp,
+            EmulatorResponse::Step(Err(commands::Error::NoRomLoaded))
+        );
+        let (resp, events) = emu.execute(EmulatorCommand::LoadRom(rom()));
+        assert_eq!(resp, EmulatorResponse::LoadRom(Ok(())));
+        assert_eq!(
+            events,
+            vec![ModeChangeEvent::new(ExecMode::Unloaded, ExecMode::Paused).into()]
+        );
+        let (resp, _) = emu.execute(EmulatorCommand::Step);
+        assert_eq!(resp, EmulatorResponse::Step(Ok(())));
+        assert_eq!(emu.query_pc(), 0x101);
+
+        drop(emu.commands);
+        emu.handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_reverse_step_needs_history() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+
+        emu.execute(EmulatorCommand::Step);
+        let (resp, _) = emu.execute(EmulatorCommand::ReverseStep);
+        assert_eq!(
+            resp,
+            EmulatorResponse::ReverseStep(Err(commands::Error::NoHistory))
+        );
+
+        let (resp, _) = emu.execute(EmulatorCommand::SetHistoryLimit(10));
+        assert_eq!(resp, EmulatorResponse::SetHistoryLimit(Ok(10)));
+        emu.execute(EmulatorCommand::Step);
+        let (resp, _) = emu.execute(EmulatorCommand::ReverseStep);
+        assert_eq!(
+            resp,
+            EmulatorResponse::ReverseStep(Ok(ReverseStepResponse::new(0)))
+        );
+        assert_eq!(emu.query_pc(), 0x101);
+    }
+
+    #[test]
+    fn test_set_register() {
+        let mut emu = TestEmulator::start();
+        let set_af = EmulatorCommand::SetRegister(RegisterValue::Word(WordRegister::AF, 0x12FF));
+
+        let (resp, _) = emu.execute(set_af.clone());
+        assert_eq!(
+            resp,
+            EmulatorResponse::SetRegister(Err(commands::Error::NoRomLoaded))
+        );
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let (resp, events) = emu.execute(set_af);
+        assert_eq!(
+            resp,
+            EmulatorResponse::SetRegister(Ok(RegisterValue::Word(WordRegister::AF, 0x12F0)))
+        );
+        assert_eq!(
+            events,
+            vec![RegisterWriteEvent::new(WordRegister::AF, 0x12F0).into()]
+        );
+        let set_l = EmulatorCommand::SetRegister(RegisterValue::Byte(ByteRegister::L, 0x10));
+        let (resp, _) = emu.execute(set_l);
+        assert_eq!(
+            resp,
+            EmulatorResponse::SetRegister(Ok(RegisterValue::Byte(ByteRegister::L, 0x10)))
+        );
+        let set_pc = EmulatorCommand::SetRegister(RegisterValue::Word(WordRegister::PC, 0x110));
+        emu.execute(set_pc);
+        assert_eq!(emu.query_pc(), 0x110);
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+
+        let (resp, _) = emu.execute(EmulatorCommand::Batch(vec![
+            EmulatorCommand::SetRegister(RegisterValue::Word(WordRegister::PC, 0x110)),
+            EmulatorCommand::SetMode(ExecMode::Uncapped),
+            EmulatorCommand::QueryRegisters,
+            EmulatorCommand::SetMode(ExecMode::Paused),
+        ]));
+        let responses = match resp {
+            EmulatorResponse::Batch(Ok(responses)) => responses,
+            resp => panic!("Unexpected response {:?}", resp),
+        };
+        assert_eq!(responses.len(), 4);
+        assert_eq!(
+            responses[0],
+            EmulatorResponse::SetRegister(Ok(RegisterValue::Word(WordRegister::PC, 0x110)))
+        );
+        match &responses[2] {
+            EmulatorResponse::QueryRegisters(Ok(registers)) => assert_eq!(registers.pc, 0x110),
+            resp => panic!("Unexpected response {:?}", resp),
+        }
+        assert_eq!(
+            responses[3],
+            EmulatorResponse::SetMode(Ok(ExecMode::Paused))
+        );
+        assert_eq!(emu.query_pc(), 0x110);
+    }
+
+    #[test]
+    fn test_set_flag() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let set_af = EmulatorCommand::SetRegister(RegisterValue::Word(WordRegister::AF, 0x1280));
+        emu.execute(set_af);
+
+        let (resp, events) = emu.execute(EmulatorCommand::SetFlag(Flag::Carry, true));
+        assert_eq!(
+            resp,
+            EmulatorResponse::SetFlag(Ok(SetFlagResponse::new(Flag::Carry, true)))
+        );
+        assert_eq!(
+            events,
+            vec![RegisterWriteEvent::new(WordRegister::AF, 0x1290).into()]
+        );
+        emu.execute(EmulatorCommand::SetFlag(Flag::Zero, false));
+        match emu.execute(EmulatorCommand::QueryRegisters).0 {
+            EmulatorResponse::QueryRegisters(Ok(registers)) => assert_eq!(registers.af, 0x1210),
+            resp => panic!("Unexpected response {:?}", resp),
+        }
+    }
+
+    #[test]
+    fn test_query_disassembly() {
+        let mut emu = TestEmulator::start();
+        let query = EmulatorCommand::QueryDisassembly {
+            start: 0x10F,
+            count: 3,
+        };
+        let (resp, _) = emu.execute(query.clone());
+        assert_eq!(
+            resp,
+            EmulatorResponse::QueryDisassembly(Err(commands::Error::NoRomLoaded))
+        );
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        emu.execute(EmulatorCommand::SetRegister(RegisterValue::Word(
+            WordRegister::PC,
+            0x110,
+        )));
+
+        let line = |address, bytes: &[u8], text: &str, is_current_pc| DisassemblyLine {
+            address,
+            bytes: bytes.to_vec(),
+            text: String::from(text),
+            is_current_pc,
+        };
+        let (resp, _) = emu.execute(query);
+        assert_eq!(
+            resp,
+            EmulatorResponse::QueryDisassembly(Ok(QueryDisassemblyResponse {
+                instructions: vec![
+                    line(0x10F, &[0x00], "NOP", false),
+                    line(0x110, &[0x18, 0xFE], "JR -2h", true),
+                    line(0x112, &[0x00], "NOP", false),
+                ]
+            }))
+        );
+    }
+
+    #[test]
+    fn test_add_unsupported_breakpoint() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let target = RWTarget::Indirect(IndirectAddress::new(WordRegister::HL, 0));
+        let bp = Breakpoint::new(target, BreakpointCondition::Read);
+
+        let (resp, events) = emu.execute(EmulatorCommand::AddBreakpoint(bp));
+        assert_eq!(resp, EmulatorResponse::AddBreakpoint(Err(())));
+        assert!(events.is_empty());
+        let (resp, _) = emu.execute(EmulatorCommand::QueryBreakpoints);
+        assert_eq!(
+            resp,
+            EmulatorResponse::QueryBreakpoints(Ok(Vec::new().into()))
+        );
+    }
+
+    #[test]
+    fn test_run_to_breakpoint() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let bp = Breakpoint::new(
+            WordRegister::PC.into(),
+            BreakpointCondition::Test(Comparison::Equal, 0x110),
+        );
+        emu.execute(EmulatorCommand::AddBreakpoint(bp.clone()));
+
+        emu.execute(EmulatorCommand::SetMode(ExecMode::Standard));
+        let mut saw_vblank = false;
+        loop {
+            match emu.recv() {
+                RemoteEmulatorOutput::Event(RemoteEvent::ModeChange(evt)) => {
+                    assert_eq!(
+                        evt,
+                        ModeChangeEvent::new(ExecMode::Standard, ExecMode::HitBreakpoint(bp))
+                    );
+                    break;
+                }
+                RemoteEmulatorOutput::Event(RemoteEvent::VBlank(VBlankEvent)) => {
+                    saw_vblank = true;
+                }
+                RemoteEmulatorOutput::Event(_) => {}
+                RemoteEmulatorOutput::Response(resp_id, _) => {
+                    panic!("Unexpected response for {:?}", resp_id)
+                }
+                RemoteEmulatorOutput::Error(e) => panic!("Emulator error {:?}", e),
+            }
+        }
+        assert!(!saw_vblank);
+        assert_eq!(emu.query_pc(), 0x110);
+    }
+
+    #[test]
+    fn test_idle_auto_pause() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(halt_rom()));
+        let policy = IdlePolicy::after_frames(2).pausing();
+        let (resp, _) = emu.execute(EmulatorCommand::SetIdlePolicy(policy));
+        assert_eq!(resp, EmulatorResponse::SetIdlePolicy(Ok(policy)));
+
+        emu.execute(EmulatorCommand::SetMode(ExecMode::Uncapped));
+        let mut saw_idle = false;
+        loop {
+            match emu.recv() {
+                RemoteEmulatorOutput::Event(RemoteEvent::Idle(evt)) => {
+                    assert_eq!(evt, IdleEvent::new(true));
+                    saw_idle = true;
+                }
+                RemoteEmulatorOutput::Event(RemoteEvent::ModeChange(evt)) => {
+                    assert_eq!(
+                        evt,
+                        ModeChangeEvent::new(ExecMode::Uncapped, ExecMode::Paused)
+                    );
+                    break;
+                }
+                RemoteEmulatorOutput::Event(_) => {}
+                RemoteEmulatorOutput::Response(resp_id, _) => {
+                    panic!("Unexpected response for {:?}", resp_id)
+                }
+                RemoteEmulatorOutput::Error(e) => panic!("Emulator error {:?}", e),
+            }
+        }
+        assert!(saw_idle);
+
+        let (_, events) = emu.execute(EmulatorCommand::SetButton(Button::A, true));
+        assert!(events.contains(&IdleEvent::new(false).into()));
+    }
+
+    #[test]
+    fn test_pause_at_next_frame() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let timing = PauseTiming::NextFrame;
+        let (resp, _) = emu.execute(EmulatorCommand::SetPauseTiming(timing));
+        assert_eq!(resp, EmulatorResponse::SetPauseTiming(Ok(timing)));
+        emu.execute(EmulatorCommand::SetMode(ExecMode::Uncapped));
+
+        let (resp, events) = emu.execute(EmulatorCommand::SetMode(ExecMode::Paused));
+        assert_eq!(resp, EmulatorResponse::SetMode(Ok(ExecMode::Uncapped)));
+        assert!(events
+            .iter()
+            .all(|evt| !matches!(evt, RemoteEvent::ModeChange(_))));
+        loop {
+            match emu.recv() {
+                RemoteEmulatorOutput::Event(RemoteEvent::ModeChange(evt)) => {
+                    assert_eq!(
+                        evt,
+                        ModeChangeEvent::new(ExecMode::Uncapped, ExecMode::Paused)
+                    );
+                    break;
+                }
+                RemoteEmulatorOutput::Event(_) => {}
+                RemoteEmulatorOutput::Response(resp_id, _) => {
+                    panic!("Unexpected response for {:?}", resp_id)
+                }
+                RemoteEmulatorOutput::Error(e) => panic!("Emulator error {:?}", e),
+            }
+        }
+
+        let (resp, _) = emu.execute(EmulatorCommand::QueryMemory(0xFF44, 0xFF44));
+        assert_eq!(
+            resp,
+            EmulatorResponse::QueryMemory(Ok(QueryMemoryResponse {
+                start_addr: 0xFF44,
+                data: vec![Some(144)],
+            }))
+        );
+    }
+
+    #[test]
+    fn test_pause_at_next_frame_lcd_off() {
+        let mut emu = TestEmulator::start();
+        let mut data = rom();
+        data[0x200] = 0xAF; // XOR A
+        data[0x201] = 0xE0; // LDH (40h), A
+        data[0x202] = 0x40;
+        data[0x203] = 0x18; // JR -2h
+        data[0x204] = 0xFE;
+        emu.execute(EmulatorCommand::LoadRom(data));
+        emu.execute(EmulatorCommand::SetPauseTiming(PauseTiming::NextFrame));
+        emu.execute(EmulatorCommand::SetMode(ExecMode::Uncapped));
+
+        // Switch the LCD off while the pause is waiting for the frame to end
+        let (resp, _) = emu.execute(EmulatorCommand::Batch(vec![
+            EmulatorCommand::SetMode(ExecMode::Paused),
+            EmulatorCommand::SetRegister(RegisterValue::Word(WordRegister::PC, 0x200)),
+        ]));
+        match resp {
+            EmulatorResponse::Batch(Ok(responses)) => assert_eq!(
+                responses[0],
+                EmulatorResponse::SetMode(Ok(ExecMode::Uncapped))
+            ),
+            resp => panic!("Unexpected response {:?}", resp),
+        }
+        loop {
+            match emu.recv() {
+                RemoteEmulatorOutput::Event(RemoteEvent::ModeChange(evt)) => {
+                    assert_eq!(
+                        evt,
+                        ModeChangeEvent::new(ExecMode::Uncapped, ExecMode::Paused)
+                    );
+                    break;
+                }
+                RemoteEmulatorOutput::Event(_) => {}
+                RemoteEmulatorOutput::Response(resp_id, _) => {
+                    panic!("Unexpected response for {:?}", resp_id)
+                }
+                RemoteEmulatorOutput::Error(e) => panic!("Emulator error {:?}", e),
+            }
+        }
+
+        let (resp, _) = emu.execute(EmulatorCommand::QueryMemory(0xFF40, 0xFF40));
+        assert_eq!(
+            resp,
+            EmulatorResponse::QueryMemory(Ok(QueryMemoryResponse {
+                start_addr: 0xFF40,
+                data: vec![Some(0)],
+            }))
+        );
+    }
+
+    #[test]
+    fn test_watchdog_stops_run_cycles() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let policy = WatchdogPolicy::after(Duration::from_millis(50)).pausing();
+        let (resp, _) = emu.execute(EmulatorCommand::SetWatchdogPolicy(policy));
+        assert_eq!(resp, EmulatorResponse::SetWatchdogPolicy(Ok(policy)));
+
+        let (resp, events) = emu.execute(EmulatorCommand::RunCycles {
+            budget: u64::MAX,
+            stop_on_breakpoint: true,
+        });
+
+        match resp {
+            EmulatorResponse::RunCycles(Ok(RunCyclesResponse {
+                cycles,
+                breakpoint: None,
+            })) => assert!(cycles > 0),
+            resp => panic!("Unexpected response {:?}", resp),
+        }
+        let watchdog_events: Vec<WatchdogEvent> = events
+            .into_iter()
+            .filter_map(|evt| match evt {
+                RemoteEvent::Watchdog(evt) => Some(evt),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(watchdog_events.len(), 1);
+        assert!(watchdog_events[0].elapsed > Duration::from_millis(50));
+        assert!(watchdog_events[0].paused);
+    }
+
+    #[test]
+    fn test_watchdog_pauses_unreachable_breakpoint() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let policy = WatchdogPolicy::after(Duration::from_millis(50)).pausing();
+        emu.execute(EmulatorCommand::SetWatchdogPolicy(policy));
+        let bp = Breakpoint::new(
+            WordRegister::PC.into(),
+            BreakpointCondition::Test(Comparison::Equal, 0x4000),
+        );
+        emu.execute(EmulatorCommand::AddBreakpoint(bp));
+
+        emu.execute(EmulatorCommand::SetMode(ExecMode::Uncapped));
+        let mut saw_watchdog = false;
+        loop {
+            match emu.recv() {
+                RemoteEmulatorOutput::Event(RemoteEvent::Watchdog(evt)) => {
+                    assert!(evt.paused);
+                    saw_watchdog = true;
+                }
+                RemoteEmulatorOutput::Event(RemoteEvent::ModeChange(evt)) => {
+                    assert_eq!(
+                        evt,
+                        ModeChangeEvent::new(ExecMode::Uncapped, ExecMode::Paused)
+                    );
+                    break;
+                }
+                RemoteEmulatorOutput::Event(_) => {}
+                RemoteEmulatorOutput::Response(resp_id, _) => {
+                    panic!("Unexpected response for {:?}", resp_id)
+                }
+                RemoteEmulatorOutput::Error(e) => panic!("Emulator error {:?}", e),
+            }
+        }
+        assert!(saw_watchdog);
+    }
+
+    #[test]
+    fn test_run_cycles() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let run_cycles = |budget, stop_on_breakpoint| EmulatorCommand::RunCycles {
+            budget,
+            stop_on_breakpoint,
+        };
+
+        // 16 NOPs take 64 clocks, then each JR takes 12
+        let (resp, _) = emu.execute(run_cycles(100, false));
+        assert_eq!(
+            resp,
+            EmulatorResponse::RunCycles(Ok(RunCyclesResponse::new(88, None)))
+        );
+        assert_eq!(emu.query_pc(), 0x110);
+        let (resp, _) = emu.execute(run_cycles(10, false));
+        assert_eq!(
+            resp,
+            EmulatorResponse::RunCycles(Ok(RunCyclesResponse::new(0, None)))
+        );
+
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let bp = Breakpoint::new(
+            WordRegister::PC.into(),
+            BreakpointCondition::Test(Comparison::Equal, 0x108),
+        );
+        emu.execute(EmulatorCommand::AddBreakpoint(bp.clone()));
+        let (resp, events) = emu.execute(run_cycles(1000, true));
+        assert_eq!(
+            resp,
+            EmulatorResponse::RunCycles(Ok(RunCyclesResponse::new(32, Some(bp.clone()))))
+        );
+        let mode_change = ModeChangeEvent::new(ExecMode::Paused, ExecMode::HitBreakpoint(bp));
+        assert_eq!(events.last(), Some(&mode_change.into()));
+        let (resp, _) = emu.execute(run_cycles(100, false));
+        assert_eq!(
+            resp,
+            EmulatorResponse::RunCycles(Ok(RunCyclesResponse::new(80, None)))
+        );
+    }
+
+    #[test]
+    fn test_run_cycles_below_step_limit() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let run_cycles = |budget| EmulatorCommand::RunCycles {
+            budget,
+            stop_on_breakpoint: false,
+        };
+
+        // Too little for the longest step, so nothing runs
+        let (resp, _) = emu.execute(run_cycles(23));
+        assert_eq!(
+            resp,
+            EmulatorResponse::RunCycles(Ok(RunCyclesResponse::new(0, None)))
+        );
+        assert_eq!(emu.query_pc(), 0x100);
+
+        // A single NOP runs, as only 20 clocks are left after it
+        let (resp, _) = emu.execute(run_cycles(24));
+        assert_eq!(
+            resp,
+            EmulatorResponse::RunCycles(Ok(RunCyclesResponse::new(4, None)))
+        );
+        assert_eq!(emu.query_pc(), 0x101);
+    }
+
+    #[test]
+    fn test_render_layers_kept_on_load() {
+        let mut state = EmulatorState::new();
+        let layers = RenderLayers {
+            sprites: false,
+            ..RenderLayers::default()
+        };
+
+        assert_eq!(state.set_render_layers(layers), layers);
+        state.load_rom(rom()).unwrap();
+        assert_eq!(state.gameboy.as_ref().unwrap().render_layers(), layers);
+    }
+
+    #[test]
+    fn test_query_capabilities() {
+        let mut emu = TestEmulator::start();
+        let (resp, _) = emu.execute(EmulatorCommand::QueryCapabilities);
+        assert_eq!(
+            resp,
+            EmulatorResponse::QueryCapabilities(Ok(QueryCapabilitiesResponse::current()))
+        );
+    }
+
+    #[test]
+    fn test_query_profile() {
+        let mut emu = TestEmulator::start();
+        let query = EmulatorCommand::QueryProfile { reset: true };
+        let (resp, _) = emu.execute(query.clone());
+        assert_eq!(
+            resp,
+            EmulatorResponse::QueryProfile(Err(commands::Error::NoRomLoaded))
+        );
+
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        emu.execute(EmulatorCommand::Step);
+        let (resp, _) = emu.execute(query.clone());
+        if cfg!(feature = "profiling") {
+            let profile = match resp {
+                EmulatorResponse::QueryProfile(Ok(profile)) => profile,
+                other => panic!("Unexpected response {:?}", other),
+            };
+            assert_eq!(profile.steps, 1);
+            let (resp, _) = emu.execute(query);
+            assert_eq!(
+                resp,
+                EmulatorResponse::QueryProfile(Ok(CycleProfile::default()))
+            );
+        } else {
+            assert_eq!(
+                resp,
+                EmulatorResponse::QueryProfile(Err(commands::Error::ProfilingDisabled))
+            );
+        }
+    }
+
+    #[test]
+    fn test_overclock_kept_on_load() {
+        let mut state = EmulatorState::new();
+
+        assert_eq!(state.set_overclock(u16::MAX), MAX_OVERCLOCK_CYCLES);
+        assert_eq!(state.set_overclock(57), 57);
+        state.load_rom(rom()).unwrap();
+        assert_eq!(state.gameboy.as_ref().unwrap().overclock(), 57);
+    }
+
+    #[test]
+    fn test_stream_logs() {
+        logging::install_test_logger();
+        let mut emu = TestEmulator::start();
+        let target = "olympia::tests::stream_logs";
+        // Other tests may log at the same time
+        let logged = |events: Vec<RemoteEvent>| -> Vec<String> {
+            let records = events.into_iter().filter_map(|evt| match evt {
+                RemoteEvent::Log(record) if record.target == target => Some(record.message),
+                _ => None,
+            });
+            records.collect()
+        };
+
+        let (resp, _) = emu.execute(EmulatorCommand::SetLogLevel(
+            String::from(target),
+            LevelFilter::Info,
+        ));
+        assert_eq!(resp, EmulatorResponse::SetLogLevel(Ok(LevelFilter::Info)));
+        assert_eq!(logging::level(target), LevelFilter::Info);
+
+        log::info!(target: target, "Before streaming");
+        let (resp, events) = emu.execute(EmulatorCommand::StreamLogs(true));
+        assert_eq!(resp, EmulatorResponse::StreamLogs(Ok(true)));
+        assert_eq!(logged(events), Vec::<String>::new());
+
+        log::info!(target: target, "While streaming");
+        log::debug!(target: target, "Below the level");
+        let (_, events) = emu.execute(EmulatorCommand::QueryBreakpoints);
+        assert_eq!(logged(events), vec!["While streaming"]);
+
+        emu.execute(EmulatorCommand::StreamLogs(false));
+        log::info!(target: target, "After streaming");
+        let (_, events) = emu.execute(EmulatorCommand::QueryBreakpoints);
+        assert_eq!(logged(events), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_query_dirty_memory() {
+        let mut emu = TestEmulator::start();
+        let mut data = rom();
+        data[0x100..0x103].copy_from_slice(&[0xEA, 0x42, 0xC0]); // LD [C042h], A
+        emu.execute(EmulatorCommand::LoadRom(data));
+
+        // All of memory changes when a ROM is loaded
+        assert_eq!(emu.query_dirty_blocks().len(), 1024);
+        assert_eq!(emu.query_dirty_blocks(), Vec::<u16>::new());
+        emu.execute(EmulatorCommand::Step);
+        assert_eq!(emu.query_dirty_blocks(), vec![0xC040]);
+        assert_eq!(emu.query_dirty_blocks(), Vec::<u16>::new());
+    }
+
+    #[test]
+    fn test_error_stops_emulation() {
+        let mut emu = TestEmulator::start();
+        let mut data = rom();
+        data[0x101] = 0xD3; // Invalid opcode
+        emu.execute(EmulatorCommand::LoadRom(data));
+        emu.execute(EmulatorCommand::SetHistoryLimit(10));
+
+        emu.execute(EmulatorCommand::SetMode(ExecMode::Uncapped));
+        let mut error = None;
+        let mut events = Vec::new();
+        while error.is_none() || !matches!(events.last(), Some(RemoteEvent::ModeChange(_))) {
+            match emu.recv() {
+                RemoteEmulatorOutput::Error(commands::Error::Exec(e)) => error = Some(e),
+                RemoteEmulatorOutput::Event(evt) => events.push(evt),
+                _ => panic!("Unexpected output"),
+            }
+        }
+        let error = error.unwrap();
+        assert_eq!(error.context().map(|context| context.pc), Some(0x101));
+        let error_event = EmulationErrorEvent::new(error.clone(), *error.context().unwrap());
+        let mode_change = ModeChangeEvent::new(ExecMode::Uncapped, ExecMode::Error(error.clone()));
+        assert_eq!(events.last(), Some(&mode_change.into()));
+        assert!(events.contains(&error_event.into()));
+
+        let report = match emu.execute(EmulatorCommand::QueryPostMortem).0 {
+            EmulatorResponse::QueryPostMortem(Ok(report)) => report,
+            resp => panic!("Unexpected response {:?}", resp),
+        };
+        assert_eq!(report.error, Some(error));
+        assert_eq!(report.context.pc, 0x101);
+        assert_eq!(report.trace.first().map(|entry| entry.pc), Some(0x100));
+    }
+
+    #[test]
+    fn test_query_frame_hash() {
+        let mut emu = TestEmulator::start();
+        let (resp, _) = emu.execute(EmulatorCommand::QueryFrameHash);
+        assert_eq!(
+            resp,
+            EmulatorResponse::QueryFrameHash(Err(commands::Error::NoRomLoaded))
+        );
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+
+        let query_hashes =
+            |emu: &mut TestEmulator| match emu.execute(EmulatorCommand::QueryFrameHash).0 {
+                EmulatorResponse::QueryFrameHash(Ok(hashes)) => hashes,
+                resp => panic!("Unexpected response {:?}", resp),
+            };
+        let start = query_hashes(&mut emu);
+        emu.execute(EmulatorCommand::RunCycles {
+            budget: 70224,
+            stop_on_breakpoint: false,
+        });
+        let after_frame = query_hashes(&mut emu);
+        assert_ne!(after_frame.run_hash, start.run_hash);
+    }
+    #[test]
+    fn test_metrics() {
+        let mut emu = TestEmulator::start();
+        emu.execute(EmulatorCommand::LoadRom(rom()));
+        let policy = MetricsPolicy::new(
+            vec![
+                RWTarget::WordRegister(WordRegister::PC),
+                RWTarget::Address(LiteralAddress(0xC000)),
+            ],
+            2,
+        );
+        emu.execute(EmulatorCommand::SetMetricsPolicy(policy.clone()));
+
+        let run_frames = |emu: &mut TestEmulator, frames: u64, stop_on_breakpoint| {
+            emu.execute(EmulatorCommand::RunCycles {
+                budget: 70224 * frames,
+                stop_on_breakpoint,
+            });
+            match emu.execute(EmulatorCommand::QueryMetrics { clear: true }).0 {
+                EmulatorResponse::QueryMetrics(Ok(metrics)) => metrics,
+                resp => panic!("Unexpected response {:?}", resp),
+            }
+        };
+        let metrics = run_frames(&mut emu, 4, false);
+        assert_eq!(metrics.targets, policy.targets);
+        assert_eq!(
+            metrics.samples.len(),
+            2,
+            "Only the most recent frames are kept"
+        );
+        assert_eq!(metrics.samples[1].frame, metrics.samples[0].frame + 1);
+        assert_eq!(metrics.samples[1].values[0], Some(0x110));
+
+        let metrics = run_frames(&mut emu, 1, true);
+        assert_eq!(metrics.samples.len(), 1, "Samples are cleared once queried");
+    }
+
+    #[test]
+    fn test_save_battery_ram() {
+        // MBC5 with 8KiB of battery backed RAM, which enables RAM and writes 42h to it
+        let mut data = vec![0; 0x8000];
+        data[0x147] = 0x1B;
+        data[0x149] = 2;
+        data[0x100..0x10C].copy_from_slice(&[
+            0x3E, 0x0A, // LD A, 0Ah
+            0xEA, 0x00, 0x00, // LD (0000h), A
+            0x3E, 0x42, // LD A, 42h
+            0xEA, 0x00, 0xA0, // LD (A000h), A
+            0x18, 0xFE, // JR -2h
+        ]);
+        let path = std::env::temp_dir().join(format!("olympia-thread-{}.sav", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path_str = path.display().to_string();
+        let mut emu = TestEmulator::start();
+        let (resp, _) = emu.execute(EmulatorCommand::SetSaveFile(Some(path_str.clone())));
+        assert_eq!(
+            resp,
+            EmulatorResponse::SetSaveFile(Err(commands::Error::NoRomLoaded))
+        );
+        emu.execute(EmulatorCommand::LoadRom(data));
+        emu.execute(EmulatorCommand::SetAutoSavePolicy(AutoSavePolicy {
+            interval: None,
+        }));
+
+        let (resp, _) = emu.execute(EmulatorCommand::SetSaveFile(Some(path_str.clone())));
+        assert_eq!(
+            resp,
+            EmulatorResponse::SetSaveFile(Ok(SetSaveFileResponse {
+                path: Some(path_str.clone()),
+                has_battery: true,
+                loaded: false,
+            }))
+        );
+        emu.execute(EmulatorCommand::RunCycles {
+            budget: 100,
+            stop_on_breakpoint: false,
+        });
+        let (resp, events) = emu.execute(EmulatorCommand::SaveBatteryRam);
+
+        assert_eq!(resp, EmulatorResponse::SaveBatteryRam(Ok(true)));
+        assert_eq!(
+            events,
+            vec![RemoteEvent::BatterySave(BatterySaveEvent::new(
+                path_str, None
+            ))]
+        );
+        assert_eq!(std::fs::read(&path).unwrap()[0], 0x42);
+        let (resp, _) = emu.execute(EmulatorCommand::SaveBatteryRam);
+        assert_eq!(resp, EmulatorResponse::SaveBatteryRam(Ok(false)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,6 +1,9 @@
 use crate::events::{
-    Event as EngineEvent, EventHandlerId, HBlankEvent, ManualStepEvent, MemoryEvent,
-    ModeChangeEvent, RegisterWriteEvent, Repeat, RomLoadedEvent, StepCompleteEvent, VBlankEvent,
+    BankSwitchEvent, BatterySaveEvent, BreakpointsChangedEvent, EmulationErrorEvent,
+    EmulationSpeedEvent, Event as EngineEvent, EventHandlerId, HBlankEvent, IdleEvent,
+    InterruptDispatchEvent, InterruptEvent, LcdPowerEvent, LogEvent, ManualStepEvent, MemoryEvent,
+    ModeChangeEvent, PrintEvent, Priority, Propagation, RegisterWriteEvent, Repeat, RomLoadedEvent,
+    RumbleEvent, SerialOutputEvent, StepCompleteEvent, VBlankEvent, WatchdogEvent,
 };
 use alloc::boxed::Box;
 use core::{
@@ -12,16 +15,31 @@ use derive_more::Error;
 use derive_more::{Display, From, TryInto};
 
 #[derive(Debug, Clone, PartialEq, Eq, From, TryInto)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Events from a remote emulator
 pub enum Event {
     ModeChange(ModeChangeEvent),
     VBlank(VBlankEvent),
     HBlank(HBlankEvent),
+    LcdPower(LcdPowerEvent),
     ManualStep(ManualStepEvent),
     StepComplete(StepCompleteEvent),
+    Interrupt(InterruptEvent),
+    InterruptDispatch(InterruptDispatchEvent),
     RegisterWrite(RegisterWriteEvent),
     Memory(MemoryEvent),
     RomLoaded(RomLoadedEvent),
+    Rumble(RumbleEvent),
+    BankSwitch(BankSwitchEvent),
+    Print(PrintEvent),
+    SerialOutput(SerialOutputEvent),
+    BreakpointsChanged(BreakpointsChangedEvent),
+    EmulationSpeed(EmulationSpeedEvent),
+    EmulationError(EmulationErrorEvent),
+    Idle(IdleEvent),
+    Watchdog(WatchdogEvent),
+    Log(LogEvent),
+    BatterySave(BatterySaveEvent),
 }
 
 impl Event {
@@ -32,11 +50,25 @@ impl Event {
             ModeChange(_) => TypeId::of::<ModeChangeEvent>(),
             VBlank(_) => TypeId::of::<VBlankEvent>(),
             HBlank(_) => TypeId::of::<HBlankEvent>(),
+            LcdPower(_) => TypeId::of::<LcdPowerEvent>(),
             ManualStep(_) => TypeId::of::<ManualStepEvent>(),
             StepComplete(_) => TypeId::of::<StepCompleteEvent>(),
+            Interrupt(_) => TypeId::of::<InterruptEvent>(),
+            InterruptDispatch(_) => TypeId::of::<InterruptDispatchEvent>(),
             RegisterWrite(_) => TypeId::of::<RegisterWriteEvent>(),
             Memory(_) => TypeId::of::<MemoryEvent>(),
             RomLoaded(_) => TypeId::of::<RomLoadedEvent>(),
+            Rumble(_) => TypeId::of::<RumbleEvent>(),
+            BankSwitch(_) => TypeId::of::<BankSwitchEvent>(),
+            Print(_) => TypeId::of::<PrintEvent>(),
+            SerialOutput(_) => TypeId::of::<SerialOutputEvent>(),
+            BreakpointsChanged(_) => TypeId::of::<BreakpointsChangedEvent>(),
+            EmulationSpeed(_) => TypeId::of::<EmulationSpeedEvent>(),
+            EmulationError(_) => TypeId::of::<EmulationErrorEvent>(),
+            Idle(_) => TypeId::of::<IdleEvent>(),
+            Watchdog(_) => TypeId::of::<WatchdogEvent>(),
+            Log(_) => TypeId::of::<LogEvent>(),
+            BatterySave(_) => TypeId::of::<BatterySaveEvent>(),
         }
     }
 }
@@ -48,9 +80,16 @@ impl From<EngineEvent> for Event {
         match evt {
             ee::VBlank(e) => re::VBlank(e),
             ee::HBlank(e) => re::HBlank(e),
+            ee::LcdPower(e) => re::LcdPower(e),
             ee::RegisterWrite(e) => re::RegisterWrite(e),
             ee::Memory(e) => re::Memory(e),
             ee::StepComplete(e) => re::StepComplete(e),
+            ee::Interrupt(e) => re::Interrupt(e),
+            ee::InterruptDispatch(e) => re::InterruptDispatch(e),
+            ee::Rumble(e) => re::Rumble(e),
+            ee::BankSwitch(e) => re::BankSwitch(e),
+            ee::Print(e) => re::Print(e),
+            ee::SerialOutput(e) => re::SerialOutput(e),
         }
     }
 }
@@ -84,24 +123,47 @@ impl<T> Sender<T> for Box<dyn Sender<T>> {
     }
 }
 
-fn wrapped_handler<E, F>(f: F) -> Box<dyn Fn(Event) -> Repeat + 'static>
+/// A method to handle a remote event, which may stop it reaching other listeners
+pub type InterceptListener = Box<dyn Fn(Event) -> (Repeat, Propagation) + 'static>;
+
+fn wrapped_handler<E, F>(f: F) -> InterceptListener
 where
     E: TryFrom<Event>,
-    F: Fn(E) -> Repeat + 'static,
+    F: Fn(E) -> (Repeat, Propagation) + 'static,
 {
     Box::new(move |evt| match evt.try_into() {
         Ok(evt) => f(evt),
-        Err(_) => Repeat(false),
+        Err(_) => (Repeat(false), Propagation::Continue),
     })
 }
 
 /// A mechanism for listening to remote events
+///
+/// Listeners for an event type are called in order of their [`Priority`],
+/// and in the order they were added for the same priority. A listener
+/// returning `Propagation::Stop` consumes the event, so later listeners
+/// aren't called for it.
+///
+/// [`Priority`]: ../events/struct.Priority.html
 pub trait RemoteEventListeners {
-    /// Listen to events of type `event_type_id`
+    /// Listen to events of type `event_type_id` with the default priority
     fn on(
         &mut self,
         event_type_id: TypeId,
         f: Box<dyn Fn(Event) -> Repeat + 'static>,
+    ) -> EventHandlerId {
+        self.intercept(
+            event_type_id,
+            Priority::default(),
+            Box::new(move |evt| (f(evt), Propagation::Continue)),
+        )
+    }
+    /// Listen to events of type `event_type_id` before lower priority listeners
+    fn intercept(
+        &mut self,
+        event_type_id: TypeId,
+        priority: Priority,
+        f: InterceptListener,
     ) -> EventHandlerId;
     /// Notify listeners of a given event
     fn emit(&mut self, evt: Event);
@@ -123,7 +185,20 @@ impl AdapterEventWrapper {
         T: TryFrom<Event> + 'static,
         F: Fn(T) -> Repeat + 'static,
     {
-        self.inner.on(TypeId::of::<T>(), wrapped_handler(f))
+        self.intercept(Priority::default(), move |evt| {
+            (f(evt), Propagation::Continue)
+        })
+    }
+
+    /// Register an event handler which runs before lower priority handlers,
+    /// and may consume the event
+    pub fn intercept<T, F>(&mut self, priority: Priority, f: F) -> EventHandlerId
+    where
+        T: TryFrom<Event> + 'static,
+        F: Fn(T) -> (Repeat, Propagation) + 'static,
+    {
+        self.inner
+            .intercept(TypeId::of::<T>(), priority, wrapped_handler(f))
     }
 
     /// Notify listeners of a given event
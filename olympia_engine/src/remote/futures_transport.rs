@@ -0,0 +1,256 @@
+use crate::remote::{
+    commands::{CommandId, EmulatorCommand, EmulatorResponse},
+    events::{Event as RemoteEvent, EventSendError, Sender},
+};
+
+use core::{
+    convert::TryFrom,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use derive_more::{Display, Error};
+use futures_channel::{mpsc, oneshot};
+use futures_core::Stream;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Failures communicating with an emulator over a futures channel
+#[derive(Debug, Display, Error, Clone, Copy, PartialEq, Eq)]
+pub enum TransportError {
+    #[display(fmt = "Remote emulator has shut down")]
+    /// The other end of the channel has gone away
+    Disconnected,
+    #[display(fmt = "Invalid response recieved for command {:?}", "_0")]
+    /// The emulator responded with a response for a different command type
+    InvalidResponse(#[error(not(source))] CommandId),
+}
+
+/// Adapts a poll function into a future
+struct PollFn<F>(F);
+
+impl<T, F> Future for PollFn<F>
+where
+    F: FnMut(&mut Context) -> Poll<T> + Unpin,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        (self.0)(cx)
+    }
+}
+
+/// A command waiting for a response from the emulator
+pub struct PendingCommand {
+    /// The ID assigned to this command by the sender
+    pub id: CommandId,
+    /// The command to execute
+    pub command: EmulatorCommand,
+    responder: oneshot::Sender<EmulatorResponse>,
+}
+
+impl PendingCommand {
+    /// Reply to the command.
+    ///
+    /// If the sender is no longer waiting for the response,
+    /// the response is returned as the error.
+    pub fn respond(self, response: EmulatorResponse) -> Result<(), EmulatorResponse> {
+        self.responder.send(response)
+    }
+}
+
+/// Sends commands to an emulator over a bounded futures channel
+///
+/// Each command gets its own oneshot channel for the response, so commands
+/// can be `await`-ed from any executor. This can be cloned to send commands
+/// from multiple tasks.
+#[derive(Clone)]
+pub struct FuturesEmulatorChannel {
+    commands: mpsc::Sender<PendingCommand>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl FuturesEmulatorChannel {
+    /// Send a command to the emulator and wait for its response
+    ///
+    /// This waits for space in the command queue if the emulator
+    /// is behind on processing commands.
+    pub async fn send(&self, command: EmulatorCommand) -> Result<EmulatorResponse, TransportError> {
+        self.send_with_id(self.next_id(), command).await
+    }
+
+    /// Send a command to the emulator and wait for a response of type `T`
+    pub async fn execute<T>(&self, command: EmulatorCommand) -> Result<T, TransportError>
+    where
+        T: TryFrom<EmulatorResponse>,
+    {
+        let id = self.next_id();
+        let response = self.send_with_id(id, command).await?;
+        T::try_from(response).map_err(|_| TransportError::InvalidResponse(id))
+    }
+
+    fn next_id(&self) -> CommandId {
+        CommandId(self.next_id.fetch_add(1, Ordering::SeqCst))
+    }
+
+    async fn send_with_id(
+        &self,
+        id: CommandId,
+        command: EmulatorCommand,
+    ) -> Result<EmulatorResponse, TransportError> {
+        let (responder, response) = oneshot::channel();
+        let mut commands = self.commands.clone();
+        PollFn(|cx: &mut Context| commands.poll_ready(cx))
+            .await
+            .map_err(|_| TransportError::Disconnected)?;
+        commands
+            .start_send(PendingCommand {
+                id,
+                command,
+                responder,
+            })
+            .map_err(|_| TransportError::Disconnected)?;
+        response.await.map_err(|_| TransportError::Disconnected)
+    }
+}
+
+/// Sends events from the emulator to a [`FuturesEmulatorChannel`] user
+#[derive(Clone)]
+pub struct FuturesEventSender(mpsc::UnboundedSender<RemoteEvent>);
+
+impl Sender<RemoteEvent> for FuturesEventSender {
+    fn send(&self, event: RemoteEvent) -> Result<(), EventSendError> {
+        self.0
+            .unbounded_send(event)
+            .map_err(|_| EventSendError::ClosedChannelError)
+    }
+}
+
+/// The emulator's end of a futures channel
+pub struct FuturesEmulatorHost {
+    commands: mpsc::Receiver<PendingCommand>,
+    events: FuturesEventSender,
+}
+
+impl FuturesEmulatorHost {
+    /// Wait for the next command, or `None` once all senders are dropped
+    pub async fn next_command(&mut self) -> Option<PendingCommand> {
+        let commands = &mut self.commands;
+        PollFn(|cx: &mut Context| Pin::new(&mut *commands).poll_next(cx)).await
+    }
+
+    /// Take the next command if one is queued, without waiting.
+    ///
+    /// This is useful for an emulator running its own loop on a thread.
+    pub fn try_next_command(&mut self) -> Result<Option<PendingCommand>, TransportError> {
+        match self.commands.try_recv() {
+            Ok(command) => Ok(Some(command)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Closed) => Err(TransportError::Disconnected),
+        }
+    }
+
+    /// A sender for events to the listening frontend
+    pub fn events(&self) -> FuturesEventSender {
+        self.events.clone()
+    }
+}
+
+/// Create a futures based transport to a remote emulator.
+///
+/// At most `buffer` commands can be queued for the emulator before
+/// [`FuturesEmulatorChannel::send`] waits for space. Events are unbounded, so the
+/// emulator never blocks on a slow frontend. Returns the frontend's channel, a stream
+/// of events for the frontend, and the emulator's end of the channel.
+pub fn futures_channel(
+    buffer: usize,
+) -> (
+    FuturesEmulatorChannel,
+    mpsc::UnboundedReceiver<RemoteEvent>,
+    FuturesEmulatorHost,
+) {
+    let (command_tx, command_rx) = mpsc::channel(buffer);
+    let (event_tx, event_rx) = mpsc::unbounded();
+    let channel = FuturesEmulatorChannel {
+        commands: command_tx,
+        next_id: Arc::new(AtomicU64::new(0)),
+    };
+    let host = FuturesEmulatorHost {
+        commands: command_rx,
+        events: FuturesEventSender(event_tx),
+    };
+    (channel, event_rx, host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::VBlankEvent;
+    use crate::remote::commands::{self, ExecMode};
+    use futures_executor::block_on;
+
+    fn run_host(mut host: FuturesEmulatorHost) -> std::thread::JoinHandle<u64> {
+        std::thread::spawn(move || {
+            let mut handled = 0;
+            while let Some(pending) = block_on(host.next_command()) {
+                let response = match pending.command {
+                    EmulatorCommand::Step => {
+                        host.events().send(VBlankEvent.into()).unwrap();
+                        EmulatorResponse::Step(Ok(()))
+                    }
+                    EmulatorCommand::SetMode(ref mode) => {
+                        EmulatorResponse::SetMode(Ok(mode.clone()))
+                    }
+                    _ => EmulatorResponse::Step(Err(commands::Error::NoRomLoaded)),
+                };
+                pending.respond(response).unwrap();
+                handled += 1;
+            }
+            handled
+        })
+    }
+
+    #[test]
+    fn test_execute_commands() {
+        let (channel, mut events, host) = futures_channel(1);
+        let handle = run_host(host);
+
+        let step: commands::Result<()> = block_on(channel.execute(EmulatorCommand::Step)).unwrap();
+        assert_eq!(step, Ok(()));
+        let mode: Result<ExecMode, ()> =
+            block_on(channel.execute(EmulatorCommand::SetMode(ExecMode::Paused))).unwrap();
+        assert_eq!(mode, Ok(ExecMode::Paused));
+        let invalid: Result<Result<ExecMode, ()>, _> =
+            block_on(channel.execute(EmulatorCommand::QueryRegisters));
+        assert_eq!(invalid, Err(TransportError::InvalidResponse(CommandId(2))));
+
+        drop(channel);
+        assert_eq!(handle.join().unwrap(), 3);
+        assert_eq!(events.try_recv(), Ok(VBlankEvent.into()));
+        assert_eq!(events.try_recv(), Err(mpsc::TryRecvError::Closed));
+    }
+
+    #[test]
+    fn test_disconnected() {
+        let (channel, _events, mut host) = futures_channel(1);
+        assert!(host.try_next_command().unwrap().is_none());
+        let sent = std::thread::spawn(move || block_on(channel.send(EmulatorCommand::Step)));
+
+        let pending = loop {
+            if let Some(pending) = host.try_next_command().unwrap() {
+                break pending;
+            }
+            std::thread::yield_now();
+        };
+        assert_eq!(pending.id, CommandId(0));
+        drop(pending);
+
+        assert_eq!(sent.join().unwrap(), Err(TransportError::Disconnected));
+        assert_eq!(
+            host.try_next_command().err(),
+            Some(TransportError::Disconnected)
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::gameboy::{cpu::PowerSavingMode, GameBoy};
+use crate::remote::commands::IdlePolicy;
+
+/// Share of a frame's clocks the CPU must spend halted for the frame to be idle
+const IDLE_HALT_PERCENT: u64 = 90;
+
+/// Watches a running gameboy for frames where nothing seems to happen
+///
+/// A frame is idle if the CPU spent most of it halted or stopped, and it drew
+/// the same picture as the frame before. Once enough idle frames pass in a row,
+/// as set by the [`IdlePolicy`], the game is treated as idle until a frame is not.
+pub(crate) struct IdleDetector {
+    policy: IdlePolicy,
+    /// Clocks elapsed at the last step recorded
+    last_clocks: Option<u64>,
+    /// Whether the CPU was halted after the last step recorded
+    was_halted: bool,
+    /// Frame count at the last step recorded
+    frame: Option<u64>,
+    frame_clocks: u64,
+    halted_clocks: u64,
+    last_frame_hash: Option<u64>,
+    idle_frames: u32,
+    idle: bool,
+}
+
+impl IdleDetector {
+    pub(crate) fn new(policy: IdlePolicy) -> IdleDetector {
+        IdleDetector {
+            policy,
+            last_clocks: None,
+            was_halted: false,
+            frame: None,
+            frame_clocks: 0,
+            halted_clocks: 0,
+            last_frame_hash: None,
+            idle_frames: 0,
+            idle: false,
+        }
+    }
+
+    pub(crate) fn policy(&self) -> IdlePolicy {
+        self.policy
+    }
+
+    /// Change the policy, and start counting idle frames again
+    ///
+    /// Returns whether the game was idle.
+    pub(crate) fn set_policy(&mut self, policy: IdlePolicy) -> bool {
+        self.policy = policy;
+        self.reset()
+    }
+
+    /// Start counting idle frames again, such as after input
+    ///
+    /// Returns whether the game was idle.
+    pub(crate) fn reset(&mut self) -> bool {
+        let was_idle = self.idle;
+        *self = IdleDetector::new(self.policy);
+        was_idle
+    }
+
+    /// Record a step that `gb` has just run
+    ///
+    /// Returns the new idle state when the game becomes idle or stops being idle.
+    pub(crate) fn record_step(&mut self, gb: &GameBoy) -> Option<bool> {
+        let idle_after = self.policy.frames?;
+        let clocks = gb.clocks_elapsed();
+        if let Some(last_clocks) = self.last_clocks {
+            let elapsed = clocks.saturating_sub(last_clocks);
+            self.frame_clocks += elapsed;
+            if self.was_halted {
+                self.halted_clocks += elapsed;
+            }
+        }
+        self.last_clocks = Some(clocks);
+        self.was_halted = gb.power_saving_mode() != PowerSavingMode::None;
+
+        let frame = gb.ppu.frame_count();
+        match self.frame.replace(frame) {
+            Some(last_frame) if last_frame != frame => {}
+            _ => return None,
+        }
+
+        let frame_hash = gb.frame_hash();
+        let halted = self.halted_clocks * 100 >= self.frame_clocks * IDLE_HALT_PERCENT;
+        let unchanged = self.last_frame_hash.replace(frame_hash) == Some(frame_hash);
+        self.frame_clocks = 0;
+        self.halted_clocks = 0;
+        if halted && unchanged {
+            self.idle_frames = self.idle_frames.saturating_add(1);
+        } else {
+            self.idle_frames = 0;
+        }
+
+        let idle = self.idle_frames >= idle_after;
+        if idle == self.idle {
+            None
+        } else {
+            self.idle = idle;
+            Some(idle)
+        }
+    }
+}
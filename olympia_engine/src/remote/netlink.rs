@@ -0,0 +1,243 @@
+use crate::gameboy::SerialDevice;
+use crate::logging;
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for the other emulator to answer a transfer by default
+pub const DEFAULT_LINK_TIMEOUT: Duration = Duration::from_secs(5);
+
+const TRANSFER_MESSAGE: u8 = 0;
+const REPLY_MESSAGE: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Message {
+    /// The sender clocked a transfer, sending this byte
+    Transfer(u8),
+    /// The answer to a transfer, with the byte that was shifted out
+    Reply(u8),
+}
+
+impl Message {
+    fn encode(self) -> [u8; 2] {
+        match self {
+            Message::Transfer(value) => [TRANSFER_MESSAGE, value],
+            Message::Reply(value) => [REPLY_MESSAGE, value],
+        }
+    }
+
+    fn decode(bytes: [u8; 2]) -> Option<Message> {
+        match bytes[0] {
+            TRANSFER_MESSAGE => Some(Message::Transfer(bytes[1])),
+            REPLY_MESSAGE => Some(Message::Reply(bytes[1])),
+            _ => None,
+        }
+    }
+}
+
+/// A link cable to an emulator on another machine, over TCP
+///
+/// Connect it to a gameboy's serial port with [`GameBoy::connect_serial`].
+/// Each byte is exchanged in lockstep: the side using its internal clock sends
+/// its byte and waits for the other side's byte in return, while the other side
+/// picks the transfer up on its next cycle and answers immediately. This is
+/// enough for games which take turns to clock transfers, such as two player
+/// Tetris or trading Pokémon.
+///
+/// If the other side does not answer within the timeout, or has disconnected,
+/// the transfer reads `0xFF` as if no cable was connected.
+///
+/// ```no_run
+/// use olympia_engine::gameboy::{GameBoy, GameBoyModel};
+/// use olympia_engine::remote::NetLink;
+/// use olympia_engine::rom::Cartridge;
+///
+/// let cartridge = Cartridge::from_data(std::fs::read("tetris.gb").unwrap()).unwrap();
+/// let mut gb = GameBoy::new(cartridge, GameBoyModel::GameBoy);
+/// // The other player runs NetLink::join("<this machine>:5000")
+/// gb.connect_serial(NetLink::host("0.0.0.0:5000").unwrap());
+/// ```
+///
+/// [`GameBoy::connect_serial`]: ../gameboy/struct.GameBoy.html#method.connect_serial
+pub struct NetLink {
+    stream: TcpStream,
+    incoming: mpsc::Receiver<Message>,
+    timeout: Duration,
+    /// Replies still to arrive for transfers both sides clocked at once
+    stale_replies: usize,
+}
+
+impl NetLink {
+    /// Wait for another emulator to join on `addr`, and link to it
+    pub fn host<A: ToSocketAddrs>(addr: A) -> io::Result<NetLink> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        NetLink::from_stream(stream)
+    }
+
+    /// Link to an emulator hosting on `addr`
+    pub fn join<A: ToSocketAddrs>(addr: A) -> io::Result<NetLink> {
+        NetLink::from_stream(TcpStream::connect(addr)?)
+    }
+
+    /// Link over an already connected stream
+    ///
+    /// Messages are read on a background thread, which stops once the
+    /// stream is closed.
+    pub fn from_stream(stream: TcpStream) -> io::Result<NetLink> {
+        // Messages are two bytes, so should not wait to be batched together
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut bytes = [0; 2];
+            while reader.read_exact(&mut bytes).is_ok() {
+                match Message::decode(bytes) {
+                    Some(message) => {
+                        if tx.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    None => {
+                        log::warn!(target: logging::SERIAL, "Invalid link message {:?}", bytes);
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(NetLink {
+            stream,
+            incoming: rx,
+            timeout: DEFAULT_LINK_TIMEOUT,
+            stale_replies: 0,
+        })
+    }
+
+    /// Set how long to wait for the other emulator to answer a transfer
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn send(&mut self, message: Message) -> io::Result<()> {
+        self.stream.write_all(&message.encode())
+    }
+
+    fn drop_reply(&mut self) {
+        self.stale_replies = self.stale_replies.saturating_sub(1);
+    }
+}
+
+impl SerialDevice for NetLink {
+    fn transfer(&mut self, value: u8) -> u8 {
+        if let Err(e) = self.send(Message::Transfer(value)) {
+            log::warn!(target: logging::SERIAL, "Link cable disconnected: {}", e);
+            return 0xFF;
+        }
+        loop {
+            match self.incoming.recv_timeout(self.timeout) {
+                Ok(Message::Reply(_)) if self.stale_replies > 0 => self.drop_reply(),
+                Ok(Message::Reply(received)) => return received,
+                Ok(Message::Transfer(received)) => {
+                    // Both sides clocked a transfer at once, so each gets the
+                    // other's byte and their replies are ignored
+                    self.stale_replies += 1;
+                    let _ = self.send(Message::Reply(value));
+                    return received;
+                }
+                Err(e) => {
+                    log::warn!(target: logging::SERIAL, "No answer over link cable: {}", e);
+                    return 0xFF;
+                }
+            }
+        }
+    }
+
+    fn poll_external(&mut self, value: u8) -> Option<u8> {
+        loop {
+            match self.incoming.try_recv().ok()? {
+                Message::Transfer(received) => {
+                    let _ = self.send(Message::Reply(value));
+                    return Some(received);
+                }
+                Message::Reply(_) => self.drop_reply(),
+            }
+        }
+    }
+}
+
+impl Drop for NetLink {
+    fn drop(&mut self) {
+        // Stops the reader thread, and lets the other side know
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linked_pair() -> (NetLink, NetLink) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let joined = NetLink::join(addr).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (NetLink::from_stream(stream).unwrap(), joined)
+    }
+
+    fn poll_until_transfer(link: &mut NetLink, value: u8) -> u8 {
+        loop {
+            if let Some(received) = link.poll_external(value) {
+                return received;
+            }
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_lockstep_transfer() {
+        let (mut host, mut joined) = linked_pair();
+
+        let other = thread::spawn(move || {
+            let received = poll_until_transfer(&mut joined, 0x24);
+            (joined, received)
+        });
+        assert_eq!(host.transfer(0x42), 0x24);
+        let (mut joined, received) = other.join().unwrap();
+        assert_eq!(received, 0x42);
+
+        // Either side can drive the clock
+        let other = thread::spawn(move || poll_until_transfer(&mut host, 0x99));
+        assert_eq!(joined.transfer(0x11), 0x99);
+        assert_eq!(other.join().unwrap(), 0x11);
+    }
+
+    #[test]
+    fn test_simultaneous_transfers() {
+        let (mut host, mut joined) = linked_pair();
+
+        let other = thread::spawn(move || {
+            let received = joined.transfer(0x24);
+            (joined, received)
+        });
+        assert_eq!(host.transfer(0x42), 0x24);
+        let (mut joined, received) = other.join().unwrap();
+        assert_eq!(received, 0x42);
+
+        // The replies to the simultaneous transfers don't answer later ones
+        let other = thread::spawn(move || poll_until_transfer(&mut joined, 0x99));
+        assert_eq!(host.transfer(0x11), 0x99);
+        assert_eq!(other.join().unwrap(), 0x11);
+    }
+
+    #[test]
+    fn test_disconnected_link() {
+        let (mut host, joined) = linked_pair();
+        drop(joined);
+
+        assert_eq!(host.transfer(0x42), 0xFF);
+        assert_eq!(host.poll_external(0x42), None);
+    }
+}
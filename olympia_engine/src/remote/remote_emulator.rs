@@ -1,18 +1,24 @@
 use crate::{
-    events::{EventHandlerId, ManualStepEvent, Repeat, RomLoadedEvent},
-    monitor::{Breakpoint, BreakpointIdentifier},
+    events::{EventHandlerId, ManualStepEvent, Priority, Propagation, Repeat, RomLoadedEvent},
+    gameboy::{Button, CycleProfile, RenderLayers},
+    monitor::{Breakpoint, BreakpointIdentifier, PostMortem},
+    registers::Flag,
     remote::{
         commands,
         commands::{
-            CommandId, EmulatorCommand, EmulatorResponse, ExecMode, ExecTime, LoadRomError,
-            QueryMemoryResponse, QueryRegistersResponse, RemoteEmulatorOutput,
-            ToggleBreakpointResponse,
+            AutoSavePolicy, CommandId, EmulatorCommand, EmulatorResponse, ExecMode, ExecTime,
+            IdlePolicy, LoadRomError, MetricsPolicy, PauseTiming, QueryBreakpointsResponse,
+            QueryCapabilitiesResponse, QueryDirtyMemoryResponse, QueryDisassemblyResponse,
+            QueryFrameHashResponse, QueryMemoryResponse, QueryMetricsResponse,
+            QueryRegistersResponse, RegisterValue, RemoteEmulatorOutput, ReverseStepResponse,
+            RunCyclesResponse, SetButtonResponse, SetFlagResponse, SetSaveFileResponse,
+            SetSpeedResponse, ToggleBreakpointResponse, WatchdogPolicy,
         },
         events::{AdapterEventWrapper, Event as RemoteEvent, RemoteEventListeners},
     },
 };
 
-use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use alloc::{boxed::Box, rc::Rc, string::String, vec::Vec};
 use core::{
     cell::RefCell,
     convert::{TryFrom, TryInto},
@@ -22,6 +28,7 @@ use core::{
     task::{Context, Poll, Waker},
 };
 use hashbrown::HashMap;
+use log::LevelFilter;
 
 use super::commands::{AddBreakpointResponse, RemoveBreakpointRespnse};
 
@@ -188,6 +195,25 @@ impl RemoteEmulator {
         self.adapter.event_listeners.borrow_mut().on(f)
     }
 
+    /// Listen to events before listeners with a lower priority, optionally consuming them
+    ///
+    /// Returning `Propagation::Stop` stops the event reaching listeners with a
+    /// lower priority, such as those added with [`on`], which have the default
+    /// priority. This lets a hook such as a script intercept events before the
+    /// frontend handles them.
+    ///
+    /// [`on`]: #method.on
+    pub fn intercept<E, F>(&self, priority: Priority, f: F) -> EventHandlerId
+    where
+        E: TryFrom<RemoteEvent> + 'static,
+        F: Fn(E) -> (Repeat, Propagation) + 'static,
+    {
+        self.adapter
+            .event_listeners
+            .borrow_mut()
+            .intercept(priority, f)
+    }
+
     /// Listen to events and pass the widget held in a weakref to the callback.
     ///
     /// The Rc provided is downgraded to a weakref. When the event handler is called,
@@ -236,6 +262,31 @@ impl RemoteEmulator {
             .await
     }
 
+    /// Query which blocks of memory have been written to since this was last called
+    ///
+    /// This allows memory views to only refresh the parts of memory which
+    /// have changed. Every block is reported after a ROM is loaded or a
+    /// step is undone.
+    pub async fn query_dirty_memory(&self) -> commands::Result<QueryDirtyMemoryResponse> {
+        self.adapter
+            .send_command(EmulatorCommand::QueryDirtyMemory)
+            .await
+    }
+
+    /// Disassemble `count` instructions, starting at a given address
+    ///
+    /// Decoding starts at `start`, so this should be the address
+    /// of the first byte of an instruction.
+    pub async fn query_disassembly(
+        &self,
+        start: u16,
+        count: u16,
+    ) -> commands::Result<QueryDisassemblyResponse> {
+        self.adapter
+            .send_command(EmulatorCommand::QueryDisassembly { start, count })
+            .await
+    }
+
     /// Query how long the emulator has been running.
     pub async fn exec_time(&self) -> commands::Result<ExecTime> {
         self.adapter
@@ -255,6 +306,25 @@ impl RemoteEmulator {
         result
     }
 
+    /// Write a value to an 8-bit or 16-bit CPU register
+    ///
+    /// The response has the value the register holds afterwards.
+    pub async fn set_register<V: Into<RegisterValue>>(
+        &self,
+        value: V,
+    ) -> commands::Result<RegisterValue> {
+        self.adapter
+            .send_command(EmulatorCommand::SetRegister(value.into()))
+            .await
+    }
+
+    /// Set or clear a single CPU flag
+    pub async fn set_flag(&self, flag: Flag, set: bool) -> commands::Result<SetFlagResponse> {
+        self.adapter
+            .send_command(EmulatorCommand::SetFlag(flag, set))
+            .await
+    }
+
     /// Convenience method to find the last recorded PC
     pub fn cached_pc(&self) -> u16 {
         self.cached_registers.borrow().pc
@@ -270,6 +340,241 @@ impl RemoteEmulator {
         result
     }
 
+    /// Run the remote emulator for at most `budget` clock cycles
+    ///
+    /// Only whole instructions are run, so fewer cycles than the budget
+    /// may be used. The response has the exact number of cycles run, so
+    /// any left over can be added to the next budget.
+    pub async fn run_cycles(
+        &self,
+        budget: u64,
+        stop_on_breakpoint: bool,
+    ) -> commands::Result<RunCyclesResponse> {
+        self.adapter
+            .send_command(EmulatorCommand::RunCycles {
+                budget,
+                stop_on_breakpoint,
+            })
+            .await
+    }
+
+    /// Undo the most recent CPU instruction in the remote emulator
+    ///
+    /// Only instructions recorded since history was enabled with
+    /// `set_history_limit` can be undone.
+    pub async fn reverse_step(&self) -> commands::Result<ReverseStepResponse> {
+        let result = self
+            .adapter
+            .send_command(EmulatorCommand::ReverseStep)
+            .await;
+        self.adapter
+            .event_listeners
+            .borrow_mut()
+            .emit(ManualStepEvent);
+        result
+    }
+
+    /// Set how many instructions are recorded so they can be undone with `reverse_step`
+    ///
+    /// Recording slows down emulation, so is off until this is set above 0.
+    /// The limit stays in effect when another ROM is loaded.
+    pub async fn set_history_limit(&self, steps: usize) -> Result<usize, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetHistoryLimit(steps))
+            .await
+    }
+
+    /// Press or release a joypad button
+    ///
+    /// The change is queued, and reaches the game when the next frame starts.
+    pub async fn set_button(
+        &self,
+        button: Button,
+        pressed: bool,
+    ) -> commands::Result<SetButtonResponse> {
+        self.adapter
+            .send_command(EmulatorCommand::SetButton(button, pressed))
+            .await
+    }
+
+    /// Choose which of the background, window and sprites are drawn
+    ///
+    /// This only changes the picture, to help find which layer a glitch
+    /// comes from, and stays in effect when another ROM is loaded.
+    pub async fn set_render_layers(&self, layers: RenderLayers) -> Result<RenderLayers, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetRenderLayers(layers))
+            .await
+    }
+
+    /// Choose when the running game is treated as idle, and whether to pause then
+    ///
+    /// The emulator sends an `IdleEvent` when the game becomes idle or stops
+    /// being idle. The policy stays in effect when another ROM is loaded.
+    pub async fn set_idle_policy(&self, policy: IdlePolicy) -> Result<IdlePolicy, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetIdlePolicy(policy))
+            .await
+    }
+
+    /// Choose how long a single command may run for, and whether to pause after
+    ///
+    /// The emulator sends a `WatchdogEvent` when a `RunCycles` command, or a run
+    /// towards an active breakpoint, takes longer than the budget. This keeps a
+    /// breakpoint which is never hit from leaving a frontend waiting forever.
+    pub async fn set_watchdog_policy(&self, policy: WatchdogPolicy) -> Result<WatchdogPolicy, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetWatchdogPolicy(policy))
+            .await
+    }
+
+    /// Run extra CPU cycles in each line of the display, to reduce slowdown
+    ///
+    /// This is experimental, and stays in effect when another ROM is loaded.
+    /// Frames and timers keep their usual timing. Returns the number of extra
+    /// cycles used, which is limited to `MAX_OVERCLOCK_CYCLES`.
+    pub async fn set_overclock(&self, extra_cycles_per_line: u16) -> Result<u16, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetOverclock(extra_cycles_per_line))
+            .await
+    }
+
+    /// Report the state of the emulator for debugging a failure
+    ///
+    /// While in `ExecMode::Error`, the report includes the error emulation
+    /// stopped with. Save it to a file with `PostMortem::save`.
+    pub async fn query_post_mortem(&self) -> commands::Result<PostMortem> {
+        self.adapter
+            .send_command(EmulatorCommand::QueryPostMortem)
+            .await
+    }
+
+    /// Query hashes of the current frame and of every frame so far
+    ///
+    /// These can be compared with a previous run to check it drew the same frames.
+    pub async fn query_frame_hash(&self) -> commands::Result<QueryFrameHashResponse> {
+        self.adapter
+            .send_command(EmulatorCommand::QueryFrameHash)
+            .await
+    }
+
+    /// Report host time spent emulating each component, to guide optimizing the engine
+    ///
+    /// This fails with `Error::ProfilingDisabled` unless the emulator was built
+    /// with the `profiling` feature. With `reset`, profiling starts again from zero.
+    pub async fn query_profile(&self, reset: bool) -> commands::Result<CycleProfile> {
+        self.adapter
+            .send_command(EmulatorCommand::QueryProfile { reset })
+            .await
+    }
+
+    /// Start or stop receiving a `LogEvent` for each message logged
+    ///
+    /// Messages from every thread in the emulator's process are sent, once
+    /// a logger has been set with `logging::install`.
+    pub async fn stream_logs(&self, stream: bool) -> Result<bool, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::StreamLogs(stream))
+            .await
+    }
+
+    /// Log messages for `target`, and the targets below it, at `level`
+    ///
+    /// See the `logging` module for the targets the engine logs to.
+    pub async fn set_log_level(&self, target: &str, level: LevelFilter) -> Result<LevelFilter, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetLogLevel(String::from(target), level))
+            .await
+    }
+
+    /// Keep the loaded ROM's battery backed RAM in the file at `path`
+    ///
+    /// RAM is first loaded from the file if it exists. After that, RAM is
+    /// saved when it changes, on the auto-save interval and whenever emulation
+    /// stops running. `None` stops saving. Loading another ROM also stops
+    /// saving, so set the save file again after each ROM is loaded.
+    pub async fn set_save_file(
+        &self,
+        path: Option<String>,
+    ) -> commands::Result<SetSaveFileResponse> {
+        self.adapter
+            .send_command(EmulatorCommand::SetSaveFile(path))
+            .await
+    }
+
+    /// Choose how often changed battery backed RAM is saved while running
+    ///
+    /// The emulator sends a `BatterySaveEvent` for each save. The policy stays
+    /// in effect when another ROM is loaded.
+    pub async fn set_auto_save_policy(&self, policy: AutoSavePolicy) -> Result<AutoSavePolicy, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetAutoSavePolicy(policy))
+            .await
+    }
+
+    /// Save battery backed RAM now, if it has changed since the last save
+    ///
+    /// Call this before the frontend exits. Returns whether RAM was saved.
+    pub async fn save_battery_ram(&self) -> commands::Result<bool> {
+        self.adapter
+            .send_command(EmulatorCommand::SaveBatteryRam)
+            .await
+    }
+
+    /// Query the protocol version and optional features of the remote emulator
+    ///
+    /// Frontends can check this when connecting, and disable features the
+    /// engine doesn't support, rather than sending commands it can't handle.
+    pub async fn query_capabilities(&self) -> Result<QueryCapabilitiesResponse, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::QueryCapabilities)
+            .await
+    }
+
+    /// Run several commands in one round trip, with no instructions run in between
+    ///
+    /// For example, registers can be set and the emulator resumed without
+    /// a frame running part way through. The responses are in the same order
+    /// as the commands, and can be converted to the expected response type
+    /// with `TryInto`.
+    pub async fn batch(&self, commands: Vec<EmulatorCommand>) -> Result<Vec<EmulatorResponse>, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::Batch(commands))
+            .await
+    }
+
+    /// Choose whether pausing takes effect straight away or once the frame ends
+    ///
+    /// With `PauseTiming::NextFrame`, `set_mode(ExecMode::Paused)` responds with
+    /// the mode still running, and a `ModeChangeEvent` is sent once VBlank starts
+    /// and emulation pauses. The timing stays in effect when another ROM is loaded.
+    pub async fn set_pause_timing(&self, timing: PauseTiming) -> Result<PauseTiming, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetPauseTiming(timing))
+            .await
+    }
+
+    /// Choose which registers and memory locations are sampled once per frame
+    ///
+    /// Samples are fetched with `query_metrics`, so values such as a player's
+    /// health can be plotted over time. Setting a policy discards any samples
+    /// already taken. The policy stays in effect when another ROM is loaded.
+    pub async fn set_metrics_policy(&self, policy: MetricsPolicy) -> Result<MetricsPolicy, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetMetricsPolicy(policy))
+            .await
+    }
+
+    /// Fetch the values sampled once per frame, oldest first
+    ///
+    /// With `clear`, the samples are discarded once fetched, so the next
+    /// query only has samples from later frames.
+    pub async fn query_metrics(&self, clear: bool) -> Result<QueryMetricsResponse, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::QueryMetrics { clear })
+            .await
+    }
+
     /// Set the running mode to the given exec mode
     pub async fn set_mode(&self, mode: ExecMode) -> Result<ExecMode, ()> {
         let result: Result<ExecMode, ()> = self
@@ -279,6 +584,15 @@ impl RemoteEmulator {
         result
     }
 
+    /// Set the speed of the standard exec mode as a percentage of gameboy speed
+    ///
+    /// Fails if the speed is 0
+    pub async fn set_speed(&self, speed_percent: u32) -> Result<SetSpeedResponse, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::SetSpeed(speed_percent))
+            .await
+    }
+
     /// Add a breakpoint to the remote emulator
     pub async fn add_breakpoint(
         &self,
@@ -309,9 +623,16 @@ impl RemoteEmulator {
             .send_command(EmulatorCommand::RemoveBreakpoint(id))
             .await
     }
+
+    /// Query all breakpoints set in the remote emulator
+    pub async fn query_breakpoints(&self) -> Result<QueryBreakpointsResponse, ()> {
+        self.adapter
+            .send_command(EmulatorCommand::QueryBreakpoints)
+            .await
+    }
 }
 
 mod test {
-    // Note that engine doesn't contain an implementation of remote emulator
-    // See olympia_native::emulator::glib for most of the tests for this module
+    // See remote::std_channel and olympia_native::emulator::glib for
+    // most of the tests for this module
 }
@@ -0,0 +1,304 @@
+use crate::{
+    events::{EventHandlerId, Priority, Propagation, Repeat},
+    remote::{
+        commands::{CommandId, EmulatorCommand, Error, RemoteEmulatorOutput},
+        events::{Event as RemoteEvent, EventSendError, InterceptListener, RemoteEventListeners},
+        remote_emulator::RemoteEmulatorChannel,
+    },
+};
+
+use std::any::TypeId;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+type OutputHandler = Box<dyn Fn(RemoteEmulatorOutput) -> Repeat>;
+type CommandSender = mpsc::Sender<(CommandId, EmulatorCommand)>;
+type CommandReceiver = mpsc::Receiver<(CommandId, EmulatorCommand)>;
+
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    // Safety: the vtable functions do nothing, so can't misuse the null data pointer
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+}
+
+/// Output from the emulator, waiting to be handled
+///
+/// Output is only handled when asked for, so that event handlers and
+/// responses run on the frontend's thread. Errors reported by the emulator
+/// are kept until [`take_errors`] is called.
+///
+/// [`take_errors`]: #method.take_errors
+pub struct OutputQueue {
+    output: mpsc::Receiver<RemoteEmulatorOutput>,
+    handler: RefCell<Option<OutputHandler>>,
+    errors: RefCell<Vec<Error>>,
+}
+
+impl OutputQueue {
+    fn handle(&self, output: RemoteEmulatorOutput) {
+        match output {
+            RemoteEmulatorOutput::Error(e) => self.errors.borrow_mut().push(e),
+            output => {
+                if let Some(handler) = self.handler.borrow().as_ref() {
+                    handler(output);
+                }
+            }
+        }
+    }
+
+    /// Handle all output that has already arrived, without blocking
+    pub fn handle_pending(&self) {
+        while let Ok(output) = self.output.try_recv() {
+            self.handle(output);
+        }
+    }
+
+    /// Wait for output to arrive and handle it
+    ///
+    /// Fails once the emulator has stopped.
+    pub fn handle_next(&self) -> Result<(), EventSendError> {
+        let output = self
+            .output
+            .recv()
+            .map_err(|_| EventSendError::ClosedChannelError)?;
+        self.handle(output);
+        Ok(())
+    }
+
+    /// Handle output until `future` completes, and return its output
+    ///
+    /// This allows commands from [`RemoteEmulator`] to be waited for without
+    /// an async executor.
+    ///
+    /// [`RemoteEmulator`]: ./struct.RemoteEmulator.html
+    pub fn block_on<F: Future>(&self, future: F) -> Result<F::Output, EventSendError> {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            if let Poll::Ready(output) = Pin::as_mut(&mut future).poll(&mut cx) {
+                return Ok(output);
+            }
+            self.handle_next()?;
+        }
+    }
+
+    /// Errors reported by the emulator since this was last called
+    pub fn take_errors(&self) -> Vec<Error> {
+        self.errors.replace(Vec::new())
+    }
+}
+
+/// Sends commands to an emulator over a `std::sync::mpsc` channel
+///
+/// Create one with [`mpsc_channel`].
+///
+/// [`mpsc_channel`]: ./fn.mpsc_channel.html
+pub struct MpscEmulatorChannel {
+    commands: CommandSender,
+    queue: Rc<OutputQueue>,
+    next_id: Cell<u64>,
+}
+
+impl MpscEmulatorChannel {
+    /// The queue of output from the emulator, which must be handled for
+    /// commands to complete
+    pub fn output_queue(&self) -> Rc<OutputQueue> {
+        self.queue.clone()
+    }
+}
+
+impl RemoteEmulatorChannel for MpscEmulatorChannel {
+    fn send(&self, cmd: EmulatorCommand) -> CommandId {
+        let id = CommandId(self.next_id.get());
+        self.next_id.set(id.0 + 1);
+        // If the emulator has stopped, waiting for the response reports the error
+        let _ = self.commands.send((id, cmd));
+        id
+    }
+
+    fn handle_output(&mut self, f: OutputHandler) {
+        self.queue.handler.replace(Some(f));
+    }
+}
+
+/// Event listeners which run on the thread that handles emulator output
+#[derive(Default)]
+pub struct MpscEventListeners {
+    /// Listeners for each event type, in the order they are called
+    listeners: HashMap<TypeId, Vec<(EventHandlerId, Priority, InterceptListener)>>,
+    next_listener_id: u64,
+}
+
+impl RemoteEventListeners for MpscEventListeners {
+    fn intercept(
+        &mut self,
+        event_type_id: TypeId,
+        priority: Priority,
+        f: InterceptListener,
+    ) -> EventHandlerId {
+        let id = EventHandlerId(self.next_listener_id);
+        self.next_listener_id += 1;
+        let listeners = self.listeners.entry(event_type_id).or_default();
+        let index = listeners
+            .iter()
+            .position(|(_, listener_priority, _)| *listener_priority < priority)
+            .unwrap_or(listeners.len());
+        listeners.insert(index, (id, priority, f));
+        id
+    }
+
+    fn emit(&mut self, evt: RemoteEvent) {
+        if let Some(listeners) = self.listeners.get_mut(&evt.event_type_id()) {
+            let mut consumed = false;
+            listeners.retain(|(_, _, listener)| {
+                if consumed {
+                    return true;
+                }
+                let (repeat, propagation) = listener(evt.clone());
+                consumed = propagation == Propagation::Stop;
+                repeat.0
+            });
+        }
+    }
+}
+
+/// Create a transport to a remote emulator using `std::sync::mpsc` channels
+///
+/// Returns the frontend's channel, and the command receiver and output sender
+/// to pass to [`EmulatorThread::spawn`]. Use [`MpscEventListeners`] for the
+/// event listeners of the [`RemoteEmulator`].
+///
+/// ```
+/// use olympia_engine::remote::{mpsc_channel, EmulatorThread, MpscEventListeners, RemoteEmulator};
+///
+/// let (channel, commands, output) = mpsc_channel();
+/// let queue = channel.output_queue();
+/// let thread = EmulatorThread::spawn(commands, output);
+/// let emulator = RemoteEmulator::new(Box::new(MpscEventListeners::default()), Box::new(channel));
+///
+/// let step = queue.block_on(emulator.step()).unwrap();
+/// assert!(step.is_err(), "No ROM is loaded");
+///
+/// // Dropping the emulator closes the command channel, which stops the thread
+/// drop(emulator);
+/// thread.join().unwrap();
+/// ```
+///
+/// [`EmulatorThread::spawn`]: ./struct.EmulatorThread.html#method.spawn
+/// [`MpscEventListeners`]: ./struct.MpscEventListeners.html
+/// [`RemoteEmulator`]: ./struct.RemoteEmulator.html
+pub fn mpsc_channel() -> (
+    MpscEmulatorChannel,
+    CommandReceiver,
+    mpsc::Sender<RemoteEmulatorOutput>,
+) {
+    let (command_tx, command_rx) = mpsc::channel();
+    let (output_tx, output_rx) = mpsc::channel();
+    let queue = Rc::new(OutputQueue {
+        output: output_rx,
+        handler: RefCell::new(None),
+        errors: RefCell::new(Vec::new()),
+    });
+    let channel = MpscEmulatorChannel {
+        commands: command_tx,
+        queue,
+        next_id: Cell::new(0),
+    };
+    (channel, command_rx, output_tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ModeChangeEvent;
+    use crate::remote::{EmulatorThread, ExecMode, RemoteEmulator};
+
+    fn rom() -> Vec<u8> {
+        let mut data = vec![0; 0x8000];
+        data[0x100] = 0x3E; // LD A, 12h
+        data[0x101] = 0x12;
+        data
+    }
+
+    #[test]
+    fn test_remote_emulator() {
+        let (channel, commands, output) = mpsc_channel();
+        let queue = channel.output_queue();
+        let thread = EmulatorThread::spawn(commands, output);
+        let emulator =
+            RemoteEmulator::new(Box::new(MpscEventListeners::default()), Box::new(channel));
+        let modes = Rc::new(RefCell::new(Vec::new()));
+        let handler_modes = modes.clone();
+        emulator.on(move |evt: ModeChangeEvent| {
+            handler_modes.borrow_mut().push(evt.new_mode);
+            Repeat(true)
+        });
+
+        queue.block_on(emulator.load_rom(rom())).unwrap().unwrap();
+        queue.block_on(emulator.step()).unwrap().unwrap();
+        let registers = queue.block_on(emulator.query_registers()).unwrap().unwrap();
+
+        assert_eq!(registers.pc, 0x102);
+        assert_eq!(registers.af >> 8, 0x12);
+        assert_eq!(*modes.borrow(), vec![ExecMode::Paused]);
+        assert!(queue.take_errors().is_empty());
+
+        drop(emulator);
+        thread.join().unwrap();
+        assert_eq!(queue.handle_next(), Err(EventSendError::ClosedChannelError));
+    }
+    #[test]
+    fn test_intercept_listeners() {
+        let mut listeners = MpscEventListeners::default();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let wrapper_calls = calls.clone();
+        let mode_change = TypeId::of::<ModeChangeEvent>();
+        listeners.on(
+            mode_change,
+            Box::new(move |_| {
+                wrapper_calls.borrow_mut().push("default");
+                Repeat(true)
+            }),
+        );
+        let hook_calls = calls.clone();
+        listeners.intercept(
+            mode_change,
+            Priority(1),
+            Box::new(move |evt| {
+                hook_calls.borrow_mut().push("hook");
+                let consume = matches!(
+                    evt,
+                    RemoteEvent::ModeChange(ModeChangeEvent {
+                        new_mode: ExecMode::Uncapped,
+                        ..
+                    })
+                );
+                if consume {
+                    (Repeat(true), Propagation::Stop)
+                } else {
+                    (Repeat(true), Propagation::Continue)
+                }
+            }),
+        );
+
+        listeners.emit(ModeChangeEvent::new(ExecMode::Paused, ExecMode::Uncapped).into());
+        listeners.emit(ModeChangeEvent::new(ExecMode::Uncapped, ExecMode::Paused).into());
+        listeners.emit(ModeChangeEvent::new(ExecMode::Paused, ExecMode::Uncapped).into());
+
+        assert_eq!(
+            *calls.borrow(),
+            vec!["hook", "hook", "default", "hook"],
+            "Uncapped mode changes are consumed by the hook"
+        );
+    }
+}
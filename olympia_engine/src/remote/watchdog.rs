@@ -0,0 +1,62 @@
+use crate::events::WatchdogEvent;
+use crate::logging;
+use crate::remote::commands::WatchdogPolicy;
+
+use std::time::Instant;
+
+/// Times a command against the budget set by a [`WatchdogPolicy`]
+pub(crate) struct Watchdog {
+    policy: WatchdogPolicy,
+    /// When the command being timed started, if any
+    started: Option<Instant>,
+    /// Whether the command being timed has already gone over budget
+    tripped: bool,
+}
+
+impl Watchdog {
+    pub(crate) fn new(policy: WatchdogPolicy) -> Watchdog {
+        Watchdog {
+            policy,
+            started: None,
+            tripped: false,
+        }
+    }
+
+    /// Change the policy, keeping the time of any command being timed
+    pub(crate) fn set_policy(&mut self, policy: WatchdogPolicy) {
+        self.policy = policy;
+        self.tripped = false;
+    }
+
+    /// Start timing a command, if there is a budget to time it against
+    pub(crate) fn start(&mut self) {
+        if self.policy.budget.is_some() {
+            self.started = Some(Instant::now());
+            self.tripped = false;
+        }
+    }
+
+    /// Stop timing the current command
+    pub(crate) fn stop(&mut self) {
+        self.started = None;
+    }
+
+    /// Check the current command against the budget
+    ///
+    /// Returns the event to send the first time the command goes over budget.
+    pub(crate) fn check(&mut self) -> Option<WatchdogEvent> {
+        let budget = self.policy.budget?;
+        let elapsed = self.started?.elapsed();
+        if self.tripped || elapsed <= budget {
+            return None;
+        }
+        self.tripped = true;
+        log::warn!(
+            target: logging::WATCHDOG,
+            "Command has run for {:?}, over its budget of {:?}",
+            elapsed,
+            budget
+        );
+        Some(WatchdogEvent::new(elapsed, self.policy.auto_pause))
+    }
+}
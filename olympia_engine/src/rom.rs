@@ -1,17 +1,34 @@
 //! ROM and Cartridge handling code
 
+mod patches;
+mod peripherals;
+
+pub use patches::{GameGenieError, RomPatch};
+pub use peripherals::{Peripheral, Rumble};
+
+use crate::events::{BankKind, BankSwitchEvent, CartridgeEvent, EventEmitter};
 use crate::gameboy::memory;
+use crate::logging;
+use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::ops::Range;
 use derive_more::Display;
 use enum_dispatch::enum_dispatch;
 
+const TITLE_LOCATION: usize = 0x134;
 const TARGET_CONSOLE_LOCATION: usize = 0x143;
 const CARTRIDGE_TYPE_LOCATION: usize = 0x147;
+const ROM_SIZE_LOCATION: usize = 0x148;
 const RAM_SIZE_LOCATION: usize = 0x149;
+/// RAM size assumed when a cartridge type with RAM declares none
+const DEFAULT_RAM_SIZE: usize = 8 * 1024;
+/// Value read from ROM padding, as from a ROM chip with nothing written
+const ROM_PADDING: u8 = 0xFF;
 
 #[derive(PartialEq, Eq, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Error turning ROMs into cartridges
 pub enum CartridgeLoadError {
     /// The ROM's cartridge type (at 0x147) is not known or supported
@@ -31,6 +48,43 @@ pub enum CartridgeLoadError {
 #[cfg(feature = "std")]
 impl std::error::Error for CartridgeLoadError {}
 
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A problem with a ROM's cartridge header that doesn't stop it from loading
+pub enum CartridgeWarning {
+    /// The header declares RAM (at 0x149) for a cartridge type without external RAM
+    #[display(
+        fmt = "Cartridge type 0x{:02X} has no external RAM, but the header declares {} KiB. Ignoring it",
+        "_0",
+        "_1 / 1024"
+    )]
+    RamSizeIgnored(u8, usize),
+    /// The header declares no RAM (at 0x149) for a cartridge type with RAM
+    #[display(
+        fmt = "Cartridge type 0x{:02X} has RAM, but the header declares none. Assuming {} KiB",
+        "_0",
+        "_1 / 1024"
+    )]
+    RamSizeMissing(u8, usize),
+    /// The ROM data ends part way through a bank, or before the size in the header (at 0x148)
+    #[display(
+        fmt = "ROM is only 0x{:X} bytes, padding it to 0x{:X} bytes with 0xFF",
+        "_0",
+        "_1"
+    )]
+    RomPadded(usize, usize),
+}
+
+/// Size ROM data needs padding to, so each bank is complete
+///
+/// This is the size in the header, and at least two banks so both ROM
+/// regions are filled.
+fn padded_rom_size(data_size: usize, header_size: usize) -> usize {
+    let bank_size = usize::from(memory::SWITCHABLE_ROM.len);
+    let banks = data_size.div_ceil(bank_size).max(2);
+    header_size.max(banks * bank_size)
+}
+
 #[derive(PartialEq, Eq, Debug, Display)]
 /// Cartridge Read/Write errors
 pub enum CartridgeIOError {
@@ -49,22 +103,136 @@ pub enum CartridgeIOError {
     /// Attempted IO to cart RAM address space when cart RAM is disabled at runtime
     #[display(fmt = "RAM disabled on current cartridge")]
     CartridgeRamDisabled,
+    /// Save data is smaller than the cartridge's battery backed RAM
+    #[display(
+        fmt = "Save data is 0x{:X} bytes, smaller than the 0x{:X} bytes of cart RAM",
+        "_0",
+        "_1"
+    )]
+    SaveTooSmall(usize, usize),
+    /// Attempted to patch an address not in cart ROM address space
+    #[display(fmt = "Cannot patch non-ROM address 0x{:X}", "_0")]
+    NotRomAddress(u16),
 }
 
 #[cfg(feature = "std")]
 impl std::error::Error for CartridgeIOError {}
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display)]
 /// Indicates if a ROM uses GameBoy Color features
 pub enum TargetConsole {
     /// The ROM does not use Color features
+    #[display(fmt = "Game Boy")]
     GameBoyOnly,
     /// The ROM uses Color features where supported
+    #[display(fmt = "Game Boy Color enhanced")]
     ColorEnhanced,
     /// The ROM requires Color features
+    #[display(fmt = "Game Boy Color only")]
     ColorOnly,
 }
 
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display)]
+/// The memory controller named by a ROM's cartridge type
+pub enum ControllerType {
+    #[display(fmt = "ROM only")]
+    StaticRom,
+    #[display(fmt = "MBC1")]
+    MBC1,
+    #[display(fmt = "MBC2")]
+    MBC2,
+    #[display(fmt = "MBC3")]
+    MBC3,
+    #[display(fmt = "MBC5")]
+    MBC5,
+    /// A cartridge type that is not supported
+    #[display(fmt = "Unknown (0x{:X})", "_0")]
+    Unknown(u8),
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+/// Metadata from a ROM's cartridge header
+pub struct CartridgeHeader {
+    /// Game title, with any padding removed
+    pub title: String,
+    /// Cartridge type (at 0x147)
+    pub cartridge_type: u8,
+    /// Memory controller used by the cartridge type
+    pub controller: ControllerType,
+    /// Size of the ROM in bytes
+    pub rom_size: usize,
+    /// Size of the cartridge RAM in bytes
+    ///
+    /// This is corrected to match the cartridge type, when the two disagree.
+    pub ram_size: usize,
+    /// Game Boy Color support
+    pub target: TargetConsole,
+    /// Problems found with the header, such as a RAM size the cartridge type can't have
+    pub warnings: Vec<CartridgeWarning>,
+}
+
+impl CartridgeHeader {
+    /// Read the cartridge header from ROM data
+    ///
+    /// Unlike `Cartridge::from_data`, this succeeds for unsupported cartridge types
+    pub fn from_data(data: &[u8]) -> CartridgeLoadResult<CartridgeHeader> {
+        if data.len() < 0x200 {
+            return Err(CartridgeLoadError::CartridgeTooSmall(data.len()));
+        }
+        let target = lookup_target(data[TARGET_CONSOLE_LOCATION]);
+        // Color games reuse the last byte of the title for the target console
+        let title_end = match target {
+            TargetConsole::GameBoyOnly => TARGET_CONSOLE_LOCATION + 1,
+            _ => TARGET_CONSOLE_LOCATION,
+        };
+        let title = data[TITLE_LOCATION..title_end]
+            .iter()
+            .take_while(|b| **b != 0)
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    char::from(*b)
+                } else {
+                    '?'
+                }
+            })
+            .collect::<String>();
+        let cartridge_type = data[CARTRIDGE_TYPE_LOCATION];
+        let rom_size = match data[ROM_SIZE_LOCATION] {
+            size_id @ 0..=8 => (32 * 1024) << size_id,
+            _ => data.len(),
+        };
+        let declared_ram_size = lookup_ram_size(data[RAM_SIZE_LOCATION])?;
+        let mut warnings = Vec::new();
+        let ram_size = match (has_external_ram(cartridge_type), declared_ram_size) {
+            (Some(false), size) if size != 0 => {
+                warnings.push(CartridgeWarning::RamSizeIgnored(cartridge_type, size));
+                0
+            }
+            (Some(true), 0) => {
+                warnings.push(CartridgeWarning::RamSizeMissing(
+                    cartridge_type,
+                    DEFAULT_RAM_SIZE,
+                ));
+                DEFAULT_RAM_SIZE
+            }
+            (_, size) => size,
+        };
+        let padded_size = padded_rom_size(data.len(), rom_size);
+        if padded_size > data.len() {
+            warnings.push(CartridgeWarning::RomPadded(data.len(), padded_size));
+        }
+        Ok(CartridgeHeader {
+            title: String::from(title.trim_end()),
+            cartridge_type,
+            controller: lookup_controller_type(cartridge_type),
+            rom_size,
+            ram_size,
+            target,
+            warnings,
+        })
+    }
+}
+
 /// Result of cartridge load operations
 pub type CartridgeLoadResult<T> = Result<T, CartridgeLoadError>;
 /// Result of cartridge read/write operations
@@ -75,15 +243,94 @@ pub struct Cartridge {
     pub data: Vec<u8>,
     pub controller: ControllerEnum,
     pub target: TargetConsole,
+    /// Devices on the cartridge other than the controller
+    pub peripherals: Vec<Box<dyn Peripheral>>,
+    /// Changes to values read from ROM
+    patches: Vec<RomPatch>,
+    pub events: EventEmitter<CartridgeEvent>,
+    warnings: Vec<CartridgeWarning>,
+    /// Whether battery backed RAM has changed since it was last saved
+    battery_ram_modified: bool,
 }
 
 impl Cartridge {
+    /// Create a cartridge with no peripherals
+    pub fn new(data: Vec<u8>, controller: ControllerEnum, target: TargetConsole) -> Cartridge {
+        Cartridge {
+            data,
+            controller,
+            target,
+            peripherals: Vec::new(),
+            patches: Vec::new(),
+            events: EventEmitter::new(),
+            warnings: Vec::new(),
+            battery_ram_modified: false,
+        }
+    }
+
+    /// Problems found with the cartridge header when it was loaded
+    ///
+    /// The cartridge still loads, but may not behave as the header describes.
+    pub fn warnings(&self) -> &[CartridgeWarning] {
+        &self.warnings
+    }
+
+    /// RAM kept by the cartridge's battery while the gameboy is off
+    ///
+    /// Returns `None` if the cartridge has no battery backed RAM.
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        let ram = self.controller.ram();
+        if self.controller.has_battery() && !ram.is_empty() {
+            Some(ram)
+        } else {
+            None
+        }
+    }
+
+    /// Restore battery backed RAM from a save
+    ///
+    /// Any data past the end of cartridge RAM is ignored, such as the real
+    /// time clock state that some emulators append to saves.
+    pub fn load_battery_ram(&mut self, data: &[u8]) -> CartridgeIOResult<()> {
+        if self.battery_ram().is_none() {
+            return Err(CartridgeIOError::NoCartridgeRam);
+        }
+        let ram = self.controller.ram_mut();
+        if data.len() < ram.len() {
+            return Err(CartridgeIOError::SaveTooSmall(data.len(), ram.len()));
+        }
+        let len = ram.len();
+        ram.copy_from_slice(&data[..len]);
+        self.battery_ram_modified = false;
+        Ok(())
+    }
+
+    /// Whether battery backed RAM has changed since it was loaded or last saved
+    pub fn battery_ram_modified(&self) -> bool {
+        self.battery_ram_modified
+    }
+
+    /// Record that battery backed RAM has been saved as it is now
+    pub fn mark_battery_ram_saved(&mut self) {
+        self.battery_ram_modified = false;
+    }
+
+    /// Attach a peripheral to this cartridge
+    pub fn add_peripheral<P: Peripheral + 'static>(&mut self, peripheral: P) {
+        self.peripherals.push(Box::new(peripheral));
+    }
+
     /// Read a byte from address space controlled by the cart
     pub fn read(&self, loc: u16) -> CartridgeIOResult<u8> {
+        if let Some(value) = self.peripherals.iter().find_map(|p| p.read(loc)) {
+            return Ok(value);
+        }
         if memory::STATIC_ROM.contains(loc) {
-            self.controller.read_static_rom(loc, &self.data)
+            let value = self.controller.read_static_rom(loc, &self.data)?;
+            Ok(self.patched(loc, value))
         } else if memory::SWITCHABLE_ROM.contains(loc) {
-            self.controller.read_switchable_rom(loc, &self.data)
+            let value = self.controller.read_switchable_rom(loc, &self.data)?;
+            Ok(self.patched(loc, value))
         } else if memory::CARTRIDGE_RAM.contains(loc) {
             self.controller.read_switchable_ram(loc)
         } else {
@@ -91,38 +338,176 @@ impl Cartridge {
         }
     }
 
+    /// The value read from ROM at `loc` once patches are applied
+    fn patched(&self, loc: u16, value: u8) -> u8 {
+        if self.patches.is_empty() {
+            return value;
+        }
+        let bank = self.rom_bank(loc);
+        self.patches
+            .iter()
+            .find(|patch| patch.address == loc && patch.applies(bank, value))
+            .map_or(value, |patch| patch.value)
+    }
+
+    /// Change the value read from ROM without changing the ROM data
+    ///
+    /// Replaces any existing patch for the same address, bank and compare value.
+    pub fn add_patch(&mut self, patch: RomPatch) -> CartridgeIOResult<()> {
+        if self.rom_bank(patch.address).is_none() {
+            return Err(CartridgeIOError::NotRomAddress(patch.address));
+        }
+        self.patches.retain(|existing| {
+            existing.address != patch.address
+                || existing.bank != patch.bank
+                || existing.compare != patch.compare
+        });
+        self.patches.push(patch);
+        Ok(())
+    }
+
+    /// Remove every patch for an address, returning how many were removed
+    pub fn remove_patches(&mut self, address: u16) -> usize {
+        let before = self.patches.len();
+        self.patches.retain(|patch| patch.address != address);
+        before - self.patches.len()
+    }
+
+    /// Remove every patch, restoring the values read from ROM
+    pub fn clear_patches(&mut self) {
+        self.patches.clear();
+    }
+
+    /// Patches applied to values read from ROM
+    pub fn patches(&self) -> &[RomPatch] {
+        &self.patches
+    }
+
+    /// The ROM bank mapped at a given address
+    ///
+    /// Returns `None` if the address is not in cartridge ROM
+    pub fn rom_bank(&self, loc: u16) -> Option<u16> {
+        if memory::STATIC_ROM.contains(loc) {
+            Some(self.controller.static_rom_bank())
+        } else if memory::SWITCHABLE_ROM.contains(loc) {
+            Some(self.controller.switchable_rom_bank())
+        } else {
+            None
+        }
+    }
+
+    /// The RAM bank mapped at a given address
+    ///
+    /// Returns `None` if the address is not in cartridge RAM
+    pub fn ram_bank(&self, loc: u16) -> Option<u16> {
+        if memory::CARTRIDGE_RAM.contains(loc) {
+            Some(self.controller.ram_bank())
+        } else {
+            None
+        }
+    }
+
+    /// The banks currently mapped to each region of cartridge memory
+    fn banks(&self) -> [(BankKind, u16); 3] {
+        [
+            (BankKind::StaticRom, self.controller.static_rom_bank()),
+            (
+                BankKind::SwitchableRom,
+                self.controller.switchable_rom_bank(),
+            ),
+            (BankKind::Ram, self.controller.ram_bank()),
+        ]
+    }
+
     /// Write a byte to address space controlled by the cart
+    ///
+    /// Emits a `BankSwitchEvent` for each region which has a different
+    /// bank mapped after the write.
     pub fn write(&mut self, loc: u16, value: u8) -> CartridgeIOResult<()> {
-        self.controller.write(loc, value)
+        for peripheral in self.peripherals.iter_mut() {
+            if let Some(event) = peripheral.write(loc, value) {
+                self.events.emit(event.into());
+            }
+        }
+        let before = self.banks();
+        // Disabled RAM ignores writes, so compare values to see if anything changed
+        let ram_before = if memory::CARTRIDGE_RAM.contains(loc) {
+            self.controller.read_switchable_ram(loc).ok()
+        } else {
+            None
+        };
+        let result = self.controller.write(loc, value);
+        if ram_before.is_some() && self.controller.read_switchable_ram(loc).ok() != ram_before {
+            self.battery_ram_modified |= self.controller.has_battery();
+        }
+        for ((kind, old), (_, new)) in before.iter().zip(self.banks().iter()) {
+            if old != new {
+                self.events
+                    .emit(BankSwitchEvent::new(*old, *new, *kind).into());
+            }
+        }
+        result
     }
 
-    /// Build a cartridge from ROM data
-    pub fn from_data(data: Vec<u8>) -> CartridgeLoadResult<Cartridge> {
-        if data.len() < 0x200 {
-            return Err(CartridgeLoadError::CartridgeTooSmall(data.len()));
+    /// Run a single machine cycle for any cartridge peripherals
+    pub fn tick(&mut self) {
+        for peripheral in self.peripherals.iter_mut() {
+            if let Some(event) = peripheral.tick() {
+                self.events.emit(event.into());
+            }
         }
-        let cartridge_type_id = data[CARTRIDGE_TYPE_LOCATION];
-        let ram_size = lookup_ram_size(data[RAM_SIZE_LOCATION])?;
-        let target = lookup_target(data[TARGET_CONSOLE_LOCATION]);
+    }
+
+    /// Build a cartridge from ROM data
+    ///
+    /// A RAM size that doesn't match the cartridge type is corrected, and
+    /// ROM data which ends part way through a bank, or before the size in
+    /// the header, is padded with 0xFF so reads past the end don't fail.
+    /// Both are reported by [`Cartridge::warnings`].
+    ///
+    /// [`Cartridge::warnings`]: #method.warnings
+    pub fn from_data(mut data: Vec<u8>) -> CartridgeLoadResult<Cartridge> {
+        let header = CartridgeHeader::from_data(&data)?;
+        let padded_size = padded_rom_size(data.len(), header.rom_size);
+        data.resize(data.len().max(padded_size), ROM_PADDING);
+        let cartridge_type_id = header.cartridge_type;
+        let ram_size = header.ram_size;
+        let target = header.target;
         let controller = match cartridge_type_id {
             0 => StaticRom.into(),
             1..=3 => MBC1::new(ram_size, cartridge_type_id).into(),
             5 | 6 => MBC2::new(cartridge_type_id).into(),
             0x10..=0x13 => MBC3::new(ram_size, cartridge_type_id).into(),
+            0x19..=0x1E => MBC5::new(ram_size, cartridge_type_id).into(),
             _ => {
                 return Err(CartridgeLoadError::UnsupportedCartridgeType(
                     cartridge_type_id,
                 ))
             }
         };
-        Ok(Cartridge {
-            controller,
-            data,
-            target,
-        })
+        let mut cartridge = Cartridge::new(data, controller, target);
+        cartridge.warnings = header.warnings;
+        if (0x1C..=0x1E).contains(&cartridge_type_id) {
+            cartridge.add_peripheral(Rumble::new());
+        }
+        Ok(cartridge)
     }
 }
 
+/// The values held by a controller's bank select and RAM enable registers
+///
+/// Controllers only use the fields they have registers for.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct BankRegisters {
+    /// Selected ROM bank
+    pub rom: u16,
+    /// Selected RAM bank, or on MBC1, the upper bits of the ROM bank
+    pub ram: u8,
+    pub ram_enabled: bool,
+    /// Whether an MBC1 is in its large RAM banking mode
+    pub large_ram_mode: bool,
+}
+
 /// Type of cartidge controller
 #[enum_dispatch]
 pub enum ControllerEnum {
@@ -134,6 +519,8 @@ pub enum ControllerEnum {
     Type2(MBC2),
     /// Uses the MBC 3 controller chip
     Type3(MBC3),
+    /// Uses the MBC 5 controller chip
+    Type5(MBC5),
 }
 
 /// Represents a cartridge controller
@@ -161,6 +548,32 @@ pub trait CartridgeController {
     }
     /// Indicates the size of onboard RAM, or 0 if absent
     fn ram_size(&self) -> usize;
+    /// The contents of onboard RAM, across every bank
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+    /// The contents of onboard RAM, for restoring a save
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+    /// The ROM bank currently mapped to 0x0000-0x3FFF
+    fn static_rom_bank(&self) -> u16 {
+        0
+    }
+    /// The ROM bank currently mapped to 0x4000-0x7FFF
+    fn switchable_rom_bank(&self) -> u16 {
+        1
+    }
+    /// The RAM bank currently mapped to 0xA000-0xBFFF
+    fn ram_bank(&self) -> u16 {
+        0
+    }
+    /// The values of the bank select and RAM enable registers
+    fn bank_registers(&self) -> BankRegisters {
+        BankRegisters::default()
+    }
+    /// Restore registers saved with `bank_registers`, such as to undo a step
+    fn restore_bank_registers(&mut self, _registers: BankRegisters) {}
 }
 
 /// A cartridge that contains only a static ROM w/o controller
@@ -301,26 +714,26 @@ impl CartridgeController for MBC1 {
     fn write(&mut self, loc: u16, value: u8) -> CartridgeIOResult<()> {
         if MBC1::ram_enable_area().contains(&loc) {
             self.ram_enabled = value == 0b1010;
-            log::info!(target: "rom::mbc1", "Toggled RAM enabled: {}", self.ram_enabled);
+            log::info!(target: logging::MBC1, "Toggled RAM enabled: {}", self.ram_enabled);
             Ok(())
         } else if MBC1::rom_select_area().contains(&loc) {
             self.selected_rom = value & 0x1F;
             if self.selected_rom == 0 {
                 self.selected_rom = 1
             }
-            log::info!(target: "rom::mbc1", "Selected Cart ROM bank: {}", self.selected_rom_bank());
+            log::info!(target: logging::MBC1, "Selected Cart ROM bank: {}", self.selected_rom_bank());
             Ok(())
         } else if MBC1::high_select_area().contains(&loc) {
             self.selected_high = value & 0x3;
             if self.page_mode == MBC1PageMode::LargeRom {
                 log::info!(
-                    target: "rom::mbc1",
+                    target: logging::MBC1,
                     "Selected Static ROM bank: {}, Cart ROM bank: {}",
                     self.selected_static_rom_bank(), self.selected_rom_bank()
                 );
             } else {
                 log::info!(
-                    target: "rom::mbc1",
+                    target: logging::MBC1,
                     "Selected RAM page: {}, Cart ROM bank: {}",
                     self.selected_ram_bank(), self.selected_rom_bank()
                 );
@@ -332,7 +745,7 @@ impl CartridgeController for MBC1 {
             } else {
                 self.page_mode = MBC1PageMode::LargeRam
             }
-            log::info!(target: "rom::mbc1", "Toggled Page Mode: {:?}", self.page_mode);
+            log::info!(target: logging::MBC1, "Toggled Page Mode: {:?}", self.page_mode);
             Ok(())
         } else if memory::CARTRIDGE_RAM.contains(loc) {
             if self.ram_enabled {
@@ -356,6 +769,46 @@ impl CartridgeController for MBC1 {
     fn ram_size(&self) -> usize {
         self.ram.len()
     }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn static_rom_bank(&self) -> u16 {
+        u16::from(self.selected_static_rom_bank())
+    }
+
+    fn switchable_rom_bank(&self) -> u16 {
+        u16::from(self.selected_rom_bank())
+    }
+
+    fn ram_bank(&self) -> u16 {
+        u16::from(self.selected_ram_bank())
+    }
+
+    fn bank_registers(&self) -> BankRegisters {
+        BankRegisters {
+            rom: u16::from(self.selected_rom),
+            ram: self.selected_high,
+            ram_enabled: self.ram_enabled,
+            large_ram_mode: self.page_mode == MBC1PageMode::LargeRam,
+        }
+    }
+
+    fn restore_bank_registers(&mut self, registers: BankRegisters) {
+        self.selected_rom = registers.rom as u8;
+        self.selected_high = registers.ram;
+        self.ram_enabled = registers.ram_enabled;
+        self.page_mode = if registers.large_ram_mode {
+            MBC1PageMode::LargeRam
+        } else {
+            MBC1PageMode::LargeRom
+        };
+    }
 }
 
 /// MBC2 cartridge controller
@@ -413,10 +866,10 @@ impl CartridgeController for MBC2 {
         if memory::STATIC_ROM.contains(loc) {
             if loc & 0x100 == 0x100 {
                 self.selected_rom = value & 0xF;
-                log::info!(target: "rom::mbc2", "Selected ROM bank {}", self.selected_rom_bank());
+                log::info!(target: logging::MBC2, "Selected ROM bank {}", self.selected_rom_bank());
             } else {
                 self.ram_enabled = (value & 0xF) == 0b1010;
-                log::info!(target: "rom::mbc2", "Toggled RAM {}", self.ram_enabled);
+                log::info!(target: logging::MBC2, "Toggled RAM {}", self.ram_enabled);
             }
             Ok(())
         } else if memory::CARTRIDGE_RAM.contains(loc) && self.ram_enabled {
@@ -440,6 +893,31 @@ impl CartridgeController for MBC2 {
     fn ram_size(&self) -> usize {
         512
     }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn switchable_rom_bank(&self) -> u16 {
+        u16::from(self.selected_rom_bank())
+    }
+
+    fn bank_registers(&self) -> BankRegisters {
+        BankRegisters {
+            rom: u16::from(self.selected_rom),
+            ram_enabled: self.ram_enabled,
+            ..BankRegisters::default()
+        }
+    }
+
+    fn restore_bank_registers(&mut self, registers: BankRegisters) {
+        self.selected_rom = registers.rom as u8;
+        self.ram_enabled = registers.ram_enabled;
+    }
 }
 
 fn lookup_ram_size(ram_size_id: u8) -> CartridgeLoadResult<usize> {
@@ -454,6 +932,26 @@ fn lookup_ram_size(ram_size_id: u8) -> CartridgeLoadResult<usize> {
     }
 }
 
+fn lookup_controller_type(cartridge_type_id: u8) -> ControllerType {
+    match cartridge_type_id {
+        0 => ControllerType::StaticRom,
+        1..=3 => ControllerType::MBC1,
+        5 | 6 => ControllerType::MBC2,
+        0x10..=0x13 => ControllerType::MBC3,
+        0x19..=0x1E => ControllerType::MBC5,
+        _ => ControllerType::Unknown(cartridge_type_id),
+    }
+}
+
+/// Whether a cartridge type has RAM outside the controller, if the type is known
+fn has_external_ram(cartridge_type_id: u8) -> Option<bool> {
+    match cartridge_type_id {
+        0 | 1 | 5 | 6 | 0x11 | 0x19 | 0x1C => Some(false),
+        2 | 3 | 0x10 | 0x12 | 0x13 | 0x1A | 0x1B | 0x1D | 0x1E => Some(true),
+        _ => None,
+    }
+}
+
 fn lookup_target(target_id: u8) -> TargetConsole {
     match target_id {
         0xC0 => TargetConsole::ColorOnly,
@@ -550,18 +1048,18 @@ impl CartridgeController for MBC3 {
     fn write(&mut self, loc: u16, value: u8) -> CartridgeIOResult<()> {
         if MBC3::ram_enable_area().contains(&loc) {
             self.ram_enabled = (value & 0xF) == 0b1010;
-            log::info!(target: "rom::mbc3", "Toggled ram: {}", self.ram_enabled);
+            log::info!(target: logging::MBC3, "Toggled ram: {}", self.ram_enabled);
             Ok(())
         } else if MBC3::rom_select_area().contains(&loc) {
             self.selected_rom = value & 0x7F;
             if self.selected_rom == 0 {
                 self.selected_rom = 1
             }
-            log::info!(target: "rom::mbc3", "Selected ROM bank: {}", self.selected_rom);
+            log::info!(target: logging::MBC3, "Selected ROM bank: {}", self.selected_rom);
             Ok(())
         } else if MBC3::ram_select_area().contains(&loc) {
             self.selected_ram = value & 0x3;
-            log::info!(target: "rom::mbc3", "Selected RAM bank: {}", self.selected_ram);
+            log::info!(target: logging::MBC3, "Selected RAM bank: {}", self.selected_ram);
             Ok(())
         } else if MBC3::timer_latch_area().contains(&loc) {
             Ok(())
@@ -588,14 +1086,204 @@ impl CartridgeController for MBC3 {
         self.ram.len()
     }
 
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
     fn has_battery(&self) -> bool {
         self.has_battery
     }
+
+    fn switchable_rom_bank(&self) -> u16 {
+        u16::from(self.selected_rom_bank())
+    }
+
+    fn ram_bank(&self) -> u16 {
+        u16::from(self.selected_ram_bank())
+    }
+
+    fn bank_registers(&self) -> BankRegisters {
+        BankRegisters {
+            rom: u16::from(self.selected_rom),
+            ram: self.selected_ram,
+            ram_enabled: self.ram_enabled,
+            ..BankRegisters::default()
+        }
+    }
+
+    fn restore_bank_registers(&mut self, registers: BankRegisters) {
+        self.selected_rom = registers.rom as u8;
+        self.selected_ram = registers.ram;
+        self.ram_enabled = registers.ram_enabled;
+    }
+}
+
+/// MBC5 cartridge controller
+pub struct MBC5 {
+    selected_rom: u16,
+    selected_ram: u8,
+    ram_enabled: bool,
+    has_ram: bool,
+    has_battery: bool,
+    has_rumble: bool,
+    ram: Vec<u8>,
+}
+
+impl MBC5 {
+    pub fn new(ram_size: usize, cartridge_type_id: u8) -> MBC5 {
+        let has_rumble = (0x1C..=0x1E).contains(&cartridge_type_id);
+        let has_ram = matches!(cartridge_type_id, 0x1A | 0x1B | 0x1D | 0x1E);
+        let has_battery = matches!(cartridge_type_id, 0x1B | 0x1E);
+        let ram = if has_ram {
+            vec![0x00; ram_size]
+        } else {
+            Vec::new()
+        };
+        MBC5 {
+            selected_rom: 1,
+            selected_ram: 0,
+            ram_enabled: false,
+            has_ram,
+            has_battery,
+            has_rumble,
+            ram,
+        }
+    }
+
+    /// Indicates if the cartridge contains a rumble motor
+    pub fn has_rumble(&self) -> bool {
+        self.has_rumble
+    }
+
+    fn ram_addr(&self, loc: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let bank = usize::from(self.selected_ram);
+        let bank_addr = usize::from(loc - memory::CARTRIDGE_RAM.start);
+        Some(((bank * usize::from(memory::CARTRIDGE_RAM.len)) + bank_addr) % self.ram.len())
+    }
+
+    const fn ram_enable_area() -> Range<u16> {
+        0x0000..0x2000
+    }
+
+    const fn rom_select_low_area() -> Range<u16> {
+        0x2000..0x3000
+    }
+
+    const fn rom_select_high_area() -> Range<u16> {
+        0x3000..0x4000
+    }
+
+    const fn ram_select_area() -> Range<u16> {
+        0x4000..0x6000
+    }
+}
+
+impl CartridgeController for MBC5 {
+    fn read_static_rom(&self, loc: u16, rom: &[u8]) -> CartridgeIOResult<u8> {
+        rom.get(usize::from(loc))
+            .copied()
+            .ok_or(CartridgeIOError::NoDataInRom(loc))
+    }
+
+    fn read_switchable_rom(&self, loc: u16, rom: &[u8]) -> CartridgeIOResult<u8> {
+        let bank_addr = loc - memory::SWITCHABLE_ROM.start;
+        let bank = u32::from(self.selected_rom);
+        let rom_addr = (bank * u32::from(memory::SWITCHABLE_ROM.len)) + u32::from(bank_addr);
+        rom.get(usize::try_from(rom_addr).expect("ROM too large for host platform"))
+            .copied()
+            .ok_or(CartridgeIOError::NoDataInRom(loc))
+    }
+
+    fn read_switchable_ram(&self, loc: u16) -> CartridgeIOResult<u8> {
+        if self.has_ram && self.ram_enabled {
+            self.ram_addr(loc)
+                .map(|addr| self.ram[addr])
+                .ok_or(CartridgeIOError::ExceedsCartridgeRam(loc))
+        } else if self.has_ram {
+            Err(CartridgeIOError::CartridgeRamDisabled)
+        } else {
+            Err(CartridgeIOError::NoCartridgeRam)
+        }
+    }
+
+    fn write(&mut self, loc: u16, value: u8) -> CartridgeIOResult<()> {
+        if MBC5::ram_enable_area().contains(&loc) {
+            self.ram_enabled = (value & 0xF) == 0b1010;
+            log::info!(target: logging::MBC5, "Toggled ram: {}", self.ram_enabled);
+        } else if MBC5::rom_select_low_area().contains(&loc) {
+            self.selected_rom = (self.selected_rom & 0x100) | u16::from(value);
+            log::info!(target: logging::MBC5, "Selected ROM bank: {}", self.selected_rom);
+        } else if MBC5::rom_select_high_area().contains(&loc) {
+            self.selected_rom = (self.selected_rom & 0xFF) | (u16::from(value & 1) << 8);
+            log::info!(target: logging::MBC5, "Selected ROM bank: {}", self.selected_rom);
+        } else if MBC5::ram_select_area().contains(&loc) {
+            // On rumble carts, bit 3 drives the motor instead of selecting RAM
+            let mask = if self.has_rumble { 0x7 } else { 0xF };
+            self.selected_ram = value & mask;
+            log::info!(target: logging::MBC5, "Selected RAM bank: {}", self.selected_ram);
+        } else if memory::CARTRIDGE_RAM.contains(loc) && self.ram_enabled {
+            if let Some(addr) = self.ram_addr(loc) {
+                self.ram[addr] = value;
+            }
+        }
+        Ok(())
+    }
+
+    fn has_ram(&self) -> bool {
+        self.has_ram
+    }
+
+    fn has_battery(&self) -> bool {
+        self.has_battery
+    }
+
+    fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn switchable_rom_bank(&self) -> u16 {
+        self.selected_rom
+    }
+
+    fn ram_bank(&self) -> u16 {
+        u16::from(self.selected_ram)
+    }
+
+    fn bank_registers(&self) -> BankRegisters {
+        BankRegisters {
+            rom: self.selected_rom,
+            ram: self.selected_ram,
+            ram_enabled: self.ram_enabled,
+            ..BankRegisters::default()
+        }
+    }
+
+    fn restore_bank_registers(&mut self, registers: BankRegisters) {
+        self.selected_rom = registers.rom;
+        self.selected_ram = registers.ram;
+        self.ram_enabled = registers.ram_enabled;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::string::ToString;
 
     #[test]
     fn test_static_rom() {
@@ -757,6 +1445,9 @@ mod tests {
         assert_ne!(cartridge.read(0xA111), Ok(0x43));
         cartridge.write(0x4001, 0x0)?;
         assert_eq!(cartridge.read(0xA111), Ok(0x43));
+        assert_eq!(cartridge.ram_bank(0xA111), Some(0));
+        cartridge.write(0x4001, 0x2)?;
+        assert_eq!(cartridge.ram_bank(0xA111), Some(2));
         assert!(cartridge.controller.has_ram());
         assert_eq!(cartridge.controller.ram_size(), 32 * 1024);
         Ok(())
@@ -859,6 +1550,219 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_mbc5_rom_bank_switch() -> CartridgeIOResult<()> {
+        let mut rom_data = vec![0x12; 8 * 1024 * 1024];
+        rom_data[0x0001] = 0x11;
+        rom_data[0x4001] = 0x33;
+        rom_data[0x8001] = 0x99;
+        rom_data[0x400001] = 0x66;
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 0x19;
+        rom_data[RAM_SIZE_LOCATION] = 0;
+        let mut cartridge = Cartridge::from_data(rom_data).unwrap();
+
+        assert_eq!(cartridge.read(0x4001)?, 0x33, "Default to bank 1");
+        cartridge.write(0x2001, 2)?;
+        assert_eq!(cartridge.read(0x4001)?, 0x99, "Switch to bank 2");
+        cartridge.write(0x2001, 0)?;
+        assert_eq!(cartridge.read(0x4001)?, 0x11, "Bank 0 can be selected");
+        cartridge.write(0x3001, 1)?;
+        assert_eq!(cartridge.read(0x4001)?, 0x66, "9th bit selects bank 256");
+        assert_eq!(cartridge.rom_bank(0x4001), Some(0x100));
+        assert!(!cartridge.controller.has_ram());
+        assert!(cartridge.peripherals.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_mbc5_ram_bank_switch() -> CartridgeIOResult<()> {
+        let mut rom_data = vec![0x12; 512 * 1024];
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 0x1B;
+        rom_data[RAM_SIZE_LOCATION] = 4;
+        let mut cartridge = Cartridge::from_data(rom_data).unwrap();
+
+        assert_eq!(
+            cartridge.read(0xA111),
+            Err(CartridgeIOError::CartridgeRamDisabled)
+        );
+        cartridge.write(0x00ff, 0b1010)?;
+        cartridge.write(0xA111, 0x43)?;
+        assert_eq!(cartridge.read(0xA111), Ok(0x43));
+        cartridge.write(0x4001, 0xF)?;
+        assert_eq!(cartridge.ram_bank(0xA111), Some(0xF));
+        assert_ne!(cartridge.read(0xA111), Ok(0x43));
+        cartridge.write(0x4001, 0x0)?;
+        assert_eq!(cartridge.read(0xA111), Ok(0x43));
+        assert!(cartridge.controller.has_battery());
+        assert_eq!(cartridge.controller.ram_size(), 128 * 1024);
+        Ok(())
+    }
+
+    #[test]
+    fn test_battery_ram() -> CartridgeIOResult<()> {
+        let mut rom_data = vec![0x12; 512 * 1024];
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 0x1B;
+        rom_data[RAM_SIZE_LOCATION] = 2;
+        let mut cartridge = Cartridge::from_data(rom_data).unwrap();
+
+        cartridge.write(0xA111, 0x43)?;
+        assert!(!cartridge.battery_ram_modified(), "RAM is disabled");
+        cartridge.write(0x00ff, 0b1010)?;
+        cartridge.write(0xA111, 0x43)?;
+        assert!(cartridge.battery_ram_modified());
+        assert_eq!(cartridge.battery_ram().unwrap()[0x111], 0x43);
+
+        cartridge.mark_battery_ram_saved();
+        cartridge.write(0xA111, 0x43)?;
+        assert!(!cartridge.battery_ram_modified(), "Value is unchanged");
+
+        let mut save = vec![0x00; 0x2000 + 48];
+        save[0x111] = 0x21;
+        cartridge.load_battery_ram(&save)?;
+        assert_eq!(cartridge.read(0xA111), Ok(0x21));
+        assert_eq!(
+            cartridge.load_battery_ram(&save[..0x100]),
+            Err(CartridgeIOError::SaveTooSmall(0x100, 0x2000))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ram_without_battery() -> CartridgeIOResult<()> {
+        let mut rom_data = vec![0x12; 512 * 1024];
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 0x1A;
+        rom_data[RAM_SIZE_LOCATION] = 2;
+        let mut cartridge = Cartridge::from_data(rom_data).unwrap();
+
+        cartridge.write(0x00ff, 0b1010)?;
+        cartridge.write(0xA111, 0x43)?;
+        assert!(!cartridge.battery_ram_modified());
+        assert_eq!(cartridge.battery_ram(), None);
+        assert_eq!(
+            cartridge.load_battery_ram(&[0; 0x2000]),
+            Err(CartridgeIOError::NoCartridgeRam)
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "events")]
+    fn test_mbc5_rumble() -> CartridgeIOResult<()> {
+        use crate::events::RumbleEvent;
+        use crate::shared::{Lock, Shared};
+
+        let mut rom_data = vec![0x12; 512 * 1024];
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 0x1E;
+        rom_data[RAM_SIZE_LOCATION] = 3;
+        let mut cartridge = Cartridge::from_data(rom_data).unwrap();
+        let events = Shared::new(Lock::new(Vec::new()));
+        let handler_events = events.clone();
+        cartridge.events.on(Box::new(move |evt: &CartridgeEvent| {
+            if let CartridgeEvent::Rumble(_) = evt {
+                handler_events.borrow_mut().push(*evt);
+            }
+        }));
+
+        cartridge.write(0x4001, 0x0B)?;
+        assert_eq!(
+            cartridge.ram_bank(0xA111),
+            Some(3),
+            "Rumble bit not used for RAM"
+        );
+        cartridge.write(0x4001, 0x09)?;
+        cartridge.write(0x4001, 0x01)?;
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                RumbleEvent::new(true).into(),
+                RumbleEvent::new(false).into()
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "events")]
+    fn test_bank_switch_events() -> CartridgeIOResult<()> {
+        use crate::shared::{Lock, Shared};
+
+        let mut rom_data = vec![0x12; 1024 * 1024];
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 1;
+        rom_data[RAM_SIZE_LOCATION] = 0;
+        let mut cartridge = Cartridge::from_data(rom_data).unwrap();
+        let events = Shared::new(Lock::new(Vec::new()));
+        let handler_events = events.clone();
+        cartridge.events.on(Box::new(move |evt: &CartridgeEvent| {
+            handler_events.borrow_mut().push(*evt);
+        }));
+
+        cartridge.write(0x2000, 0x02)?;
+        cartridge.write(0x2000, 0x02)?;
+        cartridge.write(0x4000, 0x01)?;
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                BankSwitchEvent::new(1, 2, BankKind::SwitchableRom).into(),
+                BankSwitchEvent::new(0, 0x20, BankKind::StaticRom).into(),
+                BankSwitchEvent::new(2, 0x22, BankKind::SwitchableRom).into(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rom_bank_lookup() -> CartridgeIOResult<()> {
+        let mut rom_data = vec![0x12; 1024 * 1024];
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 1;
+        rom_data[RAM_SIZE_LOCATION] = 0;
+        let mut cartridge = Cartridge::from_data(rom_data).unwrap();
+
+        assert_eq!(cartridge.rom_bank(0x0150), Some(0));
+        assert_eq!(cartridge.rom_bank(0x4123), Some(1));
+        cartridge.write(0x2001, 3)?;
+        assert_eq!(cartridge.rom_bank(0x4123), Some(3));
+        cartridge.write(0x4001, 1)?;
+        assert_eq!(cartridge.rom_bank(0x0150), Some(0x20));
+        assert_eq!(cartridge.rom_bank(0x4123), Some(0x23));
+        assert_eq!(cartridge.rom_bank(0xC000), None);
+        assert_eq!(cartridge.ram_bank(0x4123), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rom_patches() -> CartridgeIOResult<()> {
+        let mut rom_data = vec![0x12; 128 * 1024];
+        rom_data[0x0150] = 0x00;
+        rom_data[0x8124] = 0x34;
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 0x19;
+        rom_data[RAM_SIZE_LOCATION] = 0;
+        let mut cartridge = Cartridge::from_data(rom_data).unwrap();
+
+        cartridge.add_patch(RomPatch::new(0x0150, 0x18))?;
+        cartridge.add_patch(RomPatch::new(0x4123, 0x56).in_bank(2))?;
+        cartridge.add_patch(RomPatch::new(0x4124, 0x78).replacing(0x34))?;
+        assert_eq!(cartridge.read(0x0150)?, 0x18);
+        assert_eq!(cartridge.data[0x0150], 0x00, "ROM data is unchanged");
+        assert_eq!(cartridge.read(0x4123)?, 0x12, "Bank 2 is not mapped");
+        assert_eq!(cartridge.read(0x4124)?, 0x12, "Value does not match");
+        cartridge.write(0x2000, 2)?;
+        assert_eq!(cartridge.read(0x4123)?, 0x56, "Bank 2 is mapped");
+        assert_eq!(cartridge.read(0x4124)?, 0x78, "Value matches");
+
+        cartridge.add_patch(RomPatch::new(0x0150, 0x20))?;
+        assert_eq!(cartridge.read(0x0150)?, 0x20, "Patch is replaced");
+        assert_eq!(cartridge.patches().len(), 3);
+        assert_eq!(cartridge.remove_patches(0x0150), 1);
+        assert_eq!(cartridge.read(0x0150)?, 0x00);
+        assert_eq!(
+            cartridge.add_patch(RomPatch::new(0xA000, 0x00)),
+            Err(CartridgeIOError::NotRomAddress(0xA000))
+        );
+        cartridge.clear_patches();
+        assert!(cartridge.patches().is_empty());
+        Ok(())
+    }
+
     #[test]
     fn test_target_detection() {
         let mut rom_data = vec![0x12; 512 * 1024];
@@ -878,4 +1782,94 @@ mod tests {
 
         assert_eq!(cartridge.target, TargetConsole::ColorEnhanced);
     }
+
+    #[test]
+    fn test_cartridge_header() {
+        let mut rom_data = vec![0x00; 64 * 1024];
+        rom_data[TITLE_LOCATION..TITLE_LOCATION + 6].copy_from_slice(b"OLYMP\x01");
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 0x13;
+        rom_data[ROM_SIZE_LOCATION] = 1;
+        rom_data[RAM_SIZE_LOCATION] = 3;
+        rom_data[TARGET_CONSOLE_LOCATION] = 0x80;
+
+        let header = CartridgeHeader::from_data(&rom_data).unwrap();
+
+        assert_eq!(
+            header,
+            CartridgeHeader {
+                title: String::from("OLYMP?"),
+                cartridge_type: 0x13,
+                controller: ControllerType::MBC3,
+                rom_size: 64 * 1024,
+                ram_size: 32 * 1024,
+                target: TargetConsole::ColorEnhanced,
+                warnings: Vec::new(),
+            }
+        );
+
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 0xFC;
+        let header = CartridgeHeader::from_data(&rom_data).unwrap();
+        assert_eq!(header.controller, ControllerType::Unknown(0xFC));
+        assert_eq!(header.controller.to_string(), "Unknown (0xFC)");
+    }
+
+    #[test]
+    fn test_truncated_rom_padded() {
+        // Declares 64 KiB, but the last bank is half missing
+        let mut rom_data = vec![0x00; 0xA000];
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 1;
+        rom_data[ROM_SIZE_LOCATION] = 1;
+
+        let mut cartridge = Cartridge::from_data(rom_data.clone()).unwrap();
+        assert_eq!(
+            cartridge.warnings(),
+            &[CartridgeWarning::RomPadded(0xA000, 0x10000)]
+        );
+        assert_eq!(cartridge.data.len(), 0x10000);
+        cartridge.write(0x2000, 3).unwrap();
+        assert_eq!(cartridge.read(0x7FFF), Ok(0xFF));
+        cartridge.write(0x2000, 2).unwrap();
+        assert_eq!(cartridge.read(0x5FFF), Ok(0x00));
+        assert_eq!(cartridge.read(0x6000), Ok(0xFF));
+
+        // Unknown sizes are rounded up to whole banks
+        rom_data[ROM_SIZE_LOCATION] = 0xFF;
+        let cartridge = Cartridge::from_data(rom_data).unwrap();
+        assert_eq!(cartridge.data.len(), 0xC000);
+
+        let cartridge = Cartridge::from_data(vec![0x00; 0x8000]).unwrap();
+        assert!(cartridge.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_ram_size_warnings() {
+        let mut rom_data = vec![0x00; 32 * 1024];
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 5;
+        rom_data[RAM_SIZE_LOCATION] = 2;
+
+        let cartridge = Cartridge::from_data(rom_data.clone()).unwrap();
+        assert_eq!(
+            cartridge.warnings(),
+            &[CartridgeWarning::RamSizeIgnored(5, 8 * 1024)]
+        );
+        assert_eq!(
+            cartridge.warnings()[0].to_string(),
+            "Cartridge type 0x05 has no external RAM, but the header declares 8 KiB. Ignoring it"
+        );
+        assert_eq!(CartridgeHeader::from_data(&rom_data).unwrap().ram_size, 0);
+
+        rom_data[CARTRIDGE_TYPE_LOCATION] = 0x1B;
+        rom_data[RAM_SIZE_LOCATION] = 0;
+        let cartridge = Cartridge::from_data(rom_data.clone()).unwrap();
+        assert_eq!(
+            cartridge.warnings(),
+            &[CartridgeWarning::RamSizeMissing(0x1B, 8 * 1024)]
+        );
+        assert_eq!(cartridge.controller.ram_size(), 8 * 1024);
+
+        rom_data[RAM_SIZE_LOCATION] = 3;
+        let cartridge = Cartridge::from_data(rom_data).unwrap();
+        assert!(cartridge.warnings().is_empty());
+        assert_eq!(cartridge.controller.ram_size(), 32 * 1024);
+    }
 }
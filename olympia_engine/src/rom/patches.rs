@@ -0,0 +1,159 @@
+//! Changes to the values read from cartridge ROM
+//!
+//! Patches are applied as ROM is read, so the ROM data itself is never
+//! changed and removing a patch restores the original value. Debuggers use
+//! them to change code while a game is running, and Game Genie codes are
+//! decoded into them.
+
+use crate::gameboy::memory;
+use alloc::vec::Vec;
+use derive_more::Display;
+
+/// Digits in a Game Genie code without a compare value
+const SHORT_CODE_DIGITS: usize = 6;
+/// Digits in a Game Genie code with a compare value
+const LONG_CODE_DIGITS: usize = 9;
+
+/// A change to a byte read from cartridge ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RomPatch {
+    /// Address in ROM the patch applies to
+    pub address: u16,
+    /// Only apply while this bank is mapped at `address`, or in any bank if `None`
+    pub bank: Option<u16>,
+    /// Value read instead of the ROM's value
+    pub value: u8,
+    /// Only apply when the ROM holds this value, or whatever it holds if `None`
+    pub compare: Option<u8>,
+}
+
+impl RomPatch {
+    /// Read `value` from `address`, whichever bank is mapped there
+    pub fn new(address: u16, value: u8) -> RomPatch {
+        RomPatch {
+            address,
+            bank: None,
+            value,
+            compare: None,
+        }
+    }
+
+    /// The same patch, but only while `bank` is mapped at its address
+    pub fn in_bank(self, bank: u16) -> RomPatch {
+        RomPatch {
+            bank: Some(bank),
+            ..self
+        }
+    }
+
+    /// The same patch, but only when the ROM holds `compare` at its address
+    ///
+    /// This is how Game Genie codes pick out the right bank.
+    pub fn replacing(self, compare: u8) -> RomPatch {
+        RomPatch {
+            compare: Some(compare),
+            ..self
+        }
+    }
+
+    /// Decode a Game Genie code, such as `00A-17B-C49` or `00A-17B`
+    ///
+    /// Codes are `ABC-DEF-GHI`, where `AB` is the new value, `FCDE` is the
+    /// address with the top digit inverted, and `GI` is the value to replace,
+    /// scrambled. `H` isn't used. Codes without the last three digits replace
+    /// whatever value the ROM holds. Dashes are optional.
+    pub fn from_game_genie(code: &str) -> Result<RomPatch, GameGenieError> {
+        let digits = code
+            .chars()
+            .filter(|c| *c != '-')
+            .map(|c| {
+                c.to_digit(16)
+                    .map(|digit| digit as u8)
+                    .ok_or(GameGenieError::InvalidDigit(c))
+            })
+            .collect::<Result<Vec<u8>, GameGenieError>>()?;
+        if digits.len() != SHORT_CODE_DIGITS && digits.len() != LONG_CODE_DIGITS {
+            return Err(GameGenieError::InvalidLength(digits.len()));
+        }
+        let byte = |high: usize, low: usize| (digits[high] << 4) | digits[low];
+        let address = (u16::from(digits[5] ^ 0xF) << 12)
+            | (u16::from(digits[2]) << 8)
+            | u16::from(byte(3, 4));
+        if !memory::STATIC_ROM.contains(address) && !memory::SWITCHABLE_ROM.contains(address) {
+            return Err(GameGenieError::NotRomAddress(address));
+        }
+        let patch = RomPatch::new(address, byte(0, 1));
+        if digits.len() == LONG_CODE_DIGITS {
+            Ok(patch.replacing(byte(6, 8).rotate_right(2) ^ 0xBA))
+        } else {
+            Ok(patch)
+        }
+    }
+
+    /// Whether this patch changes the value read when `bank` is mapped
+    /// and the ROM holds `original`
+    pub(crate) fn applies(&self, bank: Option<u16>, original: u8) -> bool {
+        (self.bank.is_none() || self.bank == bank)
+            && (self.compare.is_none() || self.compare == Some(original))
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Display)]
+/// A Game Genie code which can't be decoded
+pub enum GameGenieError {
+    /// The code isn't 6 or 9 digits long, not counting dashes
+    #[display(fmt = "Game Genie codes have 6 or 9 digits, not {}", "_0")]
+    InvalidLength(usize),
+    /// The code has a character other than a hex digit or dash
+    #[display(fmt = "Invalid character in Game Genie code: {:?}", "_0")]
+    InvalidDigit(char),
+    /// The code's address is outside of cartridge ROM
+    #[display(fmt = "Game Genie code address 0x{:04X} is not in ROM", "_0")]
+    NotRomAddress(u16),
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for GameGenieError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_genie_code() {
+        assert_eq!(
+            RomPatch::from_game_genie("00A-17B-C49"),
+            Ok(RomPatch::new(0x4A17, 0x00).replacing(0xC8))
+        );
+        assert_eq!(
+            RomPatch::from_game_genie("3e1d7b"),
+            Ok(RomPatch::new(0x41D7, 0x3E))
+        );
+    }
+
+    #[test]
+    fn test_invalid_game_genie_code() {
+        assert_eq!(
+            RomPatch::from_game_genie("00A-17B-C4"),
+            Err(GameGenieError::InvalidLength(8))
+        );
+        assert_eq!(
+            RomPatch::from_game_genie("00A-17B-C4X"),
+            Err(GameGenieError::InvalidDigit('X'))
+        );
+        assert_eq!(
+            RomPatch::from_game_genie("00A-170"),
+            Err(GameGenieError::NotRomAddress(0xFA17))
+        );
+    }
+
+    #[test]
+    fn test_patch_applies() {
+        let patch = RomPatch::new(0x4000, 0x12);
+        assert!(patch.applies(Some(3), 0x00));
+        assert!(!patch.in_bank(2).applies(Some(3), 0x00));
+        assert!(patch.in_bank(3).replacing(0x00).applies(Some(3), 0x00));
+        assert!(!patch.replacing(0x01).applies(Some(3), 0x00));
+    }
+}
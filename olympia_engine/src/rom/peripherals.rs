@@ -0,0 +1,89 @@
+//! Devices on a cartridge other than its memory controller
+
+use crate::events::{PeripheralEvent, RumbleEvent};
+use crate::logging;
+use crate::shared::MaybeSend;
+
+/// A device on a cartridge alongside its memory controller, such
+/// as a rumble motor, camera or tilt sensor.
+///
+/// Peripherals see every IO operation to the cartridge address space,
+/// and are ticked once per machine cycle. Peripherals must be `Send`
+/// when the `sync` feature is enabled.
+pub trait Peripheral: MaybeSend {
+    /// Read a value from the cartridge address space
+    ///
+    /// Returning `Some` overrides the value from the cartridge controller
+    fn read(&self, _loc: u16) -> Option<u8> {
+        None
+    }
+    /// Observe a write to the cartridge address space
+    ///
+    /// The write is also passed on to the cartridge controller
+    fn write(&mut self, loc: u16, value: u8) -> Option<PeripheralEvent>;
+    /// Run a single machine cycle
+    fn tick(&mut self) -> Option<PeripheralEvent> {
+        None
+    }
+}
+
+/// Rumble motor found on MBC5 rumble cartridges
+///
+/// The motor is controlled by bit 3 of the RAM bank select register
+#[derive(Debug, Default)]
+pub struct Rumble {
+    active: bool,
+}
+
+impl Rumble {
+    pub fn new() -> Rumble {
+        Rumble::default()
+    }
+
+    /// Indicates if the rumble motor is currently on
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+impl Peripheral for Rumble {
+    fn write(&mut self, loc: u16, value: u8) -> Option<PeripheralEvent> {
+        if !(0x4000..0x6000).contains(&loc) {
+            return None;
+        }
+        let active = value & 0x08 != 0;
+        if active == self.active {
+            return None;
+        }
+        self.active = active;
+        log::info!(target: logging::RUMBLE, "Toggled rumble: {}", active);
+        Some(RumbleEvent::new(active).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rumble_toggle() {
+        let mut rumble = Rumble::new();
+
+        assert_eq!(
+            rumble.write(0x4000, 0x08),
+            Some(RumbleEvent::new(true).into())
+        );
+        assert!(rumble.is_active());
+        assert_eq!(rumble.write(0x5FFF, 0x0A), None, "Already active");
+        assert_eq!(
+            rumble.write(0x2000, 0x00),
+            None,
+            "Not the RAM bank register"
+        );
+        assert_eq!(
+            rumble.write(0x4000, 0x02),
+            Some(RumbleEvent::new(false).into())
+        );
+        assert!(!rumble.is_active());
+    }
+}
@@ -0,0 +1,148 @@
+//! Reading ROM files for frontends
+//!
+//! ROMs can be loaded directly, or from a `.zip` archive containing a
+//! `.gb` or `.gbc` file when the `zip` feature is enabled.
+
+use derive_more::{Display, From};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The bytes every zip archive starts with
+const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+/// File extensions of ROMs found in zip archives
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+#[derive(Debug, Display, From)]
+/// A failure to read a ROM file
+pub enum RomFileError {
+    /// The file could not be read
+    #[display(fmt = "{}", "_0")]
+    Io(io::Error),
+    /// The file looks like a zip archive, but could not be read as one
+    #[display(fmt = "Invalid zip archive: {}", "_0")]
+    #[from(ignore)]
+    InvalidArchive(String),
+    /// The zip archive contains no `.gb` or `.gbc` file
+    #[display(fmt = "No .gb or .gbc file found in zip archive")]
+    NoRomInArchive,
+    /// The file is a zip archive, but the `zip` feature is disabled
+    #[display(fmt = "Loading ROMs from zip archives is not supported in this build")]
+    ZipNotSupported,
+}
+
+impl std::error::Error for RomFileError {}
+
+/// Result of reading ROM files
+pub type RomFileResult<T> = Result<T, RomFileError>;
+
+/// Read ROM data from a file
+///
+/// If the file is a zip archive, the first `.gb` or `.gbc` file in it is
+/// read instead.
+pub fn read(path: &Path) -> RomFileResult<Vec<u8>> {
+    from_data(fs::read(path)?)
+}
+
+/// Get ROM data from the contents of a ROM file
+///
+/// Data which isn't a zip archive is returned as is.
+pub fn from_data(data: Vec<u8>) -> RomFileResult<Vec<u8>> {
+    if data.starts_with(ZIP_SIGNATURE) {
+        extract_zip(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Whether a file name in an archive looks like a ROM
+fn is_rom_name(name: &str) -> bool {
+    let extension = match name.rsplit_once('.') {
+        Some((_, extension)) => extension,
+        None => return false,
+    };
+    ROM_EXTENSIONS
+        .iter()
+        .any(|rom_extension| extension.eq_ignore_ascii_case(rom_extension))
+}
+
+#[cfg(feature = "zip")]
+fn extract_zip(data: &[u8]) -> RomFileResult<Vec<u8>> {
+    use std::io::Read;
+
+    let invalid = |e: zip::result::ZipError| RomFileError::InvalidArchive(e.to_string());
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(data)).map_err(invalid)?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(invalid)?;
+        if file.is_file() && is_rom_name(file.name()) {
+            let mut rom = Vec::new();
+            file.read_to_end(&mut rom)?;
+            return Ok(rom);
+        }
+    }
+    Err(RomFileError::NoRomInArchive)
+}
+
+#[cfg(not(feature = "zip"))]
+fn extract_zip(_data: &[u8]) -> RomFileResult<Vec<u8>> {
+    Err(RomFileError::ZipNotSupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rom_names() {
+        assert!(is_rom_name("game.gb"));
+        assert!(is_rom_name("roms/GAME.GBC"));
+        assert!(!is_rom_name("readme.txt"));
+        assert!(!is_rom_name("gb"));
+    }
+
+    #[test]
+    fn test_plain_rom() {
+        let data = vec![0x00, 0xC3, 0x50, 0x01];
+        assert_eq!(from_data(data.clone()).unwrap(), data);
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_zipped_rom() {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+
+        let mut archive = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = FileOptions::default();
+        archive.start_file("readme.txt", options).unwrap();
+        archive.write_all(b"Not a ROM").unwrap();
+        archive.start_file("game.gb", options).unwrap();
+        archive.write_all(&[0x00, 0xC3, 0x50, 0x01]).unwrap();
+        archive.start_file("game2.gbc", options).unwrap();
+        archive.write_all(&[0x01]).unwrap();
+        let data = archive.finish().unwrap().into_inner();
+
+        assert_eq!(from_data(data).unwrap(), vec![0x00, 0xC3, 0x50, 0x01]);
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_zip_without_rom() {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+
+        let mut archive = ZipWriter::new(io::Cursor::new(Vec::new()));
+        archive
+            .start_file("readme.txt", FileOptions::default())
+            .unwrap();
+        archive.write_all(b"Not a ROM").unwrap();
+        let data = archive.finish().unwrap().into_inner();
+
+        assert!(matches!(from_data(data), Err(RomFileError::NoRomInArchive)));
+        assert!(matches!(
+            from_data(ZIP_SIGNATURE.to_vec()),
+            Err(RomFileError::InvalidArchive(_))
+        ));
+    }
+}
@@ -0,0 +1,386 @@
+//! Paces emulation so that it runs in time with the host
+//!
+//! Frontends provide a [`HostClock`] and then repeatedly ask a [`FrameScheduler`]
+//! what to do next: either run the emulator for some clocks, or sleep. The
+//! scheduler compares the total clocks run against the total host time since
+//! emulation started, rather than timing each step, so inaccurate sleeps are
+//! made up for later instead of building up into drift.
+//!
+//! Frontends which use an audio callback can instead use an [`AudioScheduler`],
+//! which treats the audio device as the master clock.
+//!
+//! ```
+//! use olympia_engine::scheduler::{FrameScheduler, HostClock, Schedule};
+//! use core::time::Duration;
+//!
+//! struct FixedClock;
+//!
+//! impl HostClock for FixedClock {
+//!     fn now(&self) -> Duration {
+//!         Duration::from_millis(0)
+//!     }
+//! }
+//!
+//! let mut scheduler = FrameScheduler::new(FixedClock);
+//! // No time has passed, so there is nothing to run yet
+//! assert!(matches!(scheduler.poll(), Schedule::Sleep(_)));
+//! ```
+//!
+//! [`HostClock`]: ./trait.HostClock.html
+//! [`FrameScheduler`]: ./struct.FrameScheduler.html
+//! [`AudioScheduler`]: ./struct.AudioScheduler.html
+
+use crate::gameboy::cpu::{CLOCKS_PER_CYCLE, CYCLE_FREQ};
+
+use core::time::Duration;
+
+/// Number of CPU clocks the Game Boy runs each second at normal speed
+pub const CLOCK_FREQ: u64 = CYCLE_FREQ as u64 * CLOCKS_PER_CYCLE as u64;
+/// Number of CPU clocks taken to draw a frame
+pub const CLOCKS_PER_FRAME: u64 = 70224;
+/// Fewest clocks worth running at once, so the host isn't asked for
+/// lots of very short sleeps
+const MIN_BATCH_CLOCKS: u64 = 4096;
+/// Most frames emulation can fall behind by before the missed time is skipped
+const MAX_LAG_FRAMES: u64 = 6;
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// A source of time on the host
+pub trait HostClock {
+    /// Time elapsed since some fixed point, which must never go backwards
+    fn now(&self) -> Duration;
+}
+
+/// A host clock using the standard library's monotonic clock
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct StdClock {
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl StdClock {
+    pub fn new() -> StdClock {
+        StdClock {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> StdClock {
+        StdClock::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl HostClock for StdClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// What to do next to keep emulation in time with the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// Run the emulator for at least this many clocks, and then report how
+    /// many were actually run with [`FrameScheduler::completed`]
+    ///
+    /// [`FrameScheduler::completed`]: ./struct.FrameScheduler.html#method.completed
+    Run(u64),
+    /// Nothing is due to run yet, so sleep for this long
+    Sleep(Duration),
+}
+
+/// Tracks how much emulation is due based on the time passed on the host
+///
+/// If the emulator falls more than a few frames behind, such as when the host
+/// is suspended or too slow, the missed time is skipped rather than running
+/// flat out to catch up.
+pub struct FrameScheduler<C: HostClock> {
+    clock: C,
+    speed_percent: u32,
+    /// Host time emulation is being timed from
+    epoch: Duration,
+    /// Clocks run since `epoch`
+    clocks_run: u64,
+}
+
+impl<C: HostClock> FrameScheduler<C> {
+    /// Create a scheduler which runs at normal speed from now
+    pub fn new(clock: C) -> FrameScheduler<C> {
+        let epoch = clock.now();
+        FrameScheduler {
+            clock,
+            speed_percent: 100,
+            epoch,
+            clocks_run: 0,
+        }
+    }
+
+    /// Speed emulation runs at, as a percentage of Game Boy speed
+    pub fn speed_percent(&self) -> u32 {
+        self.speed_percent
+    }
+
+    /// Change the speed emulation runs at, as a percentage of Game Boy speed
+    ///
+    /// Speeds below 1% are treated as 1%. This also restarts timing from now.
+    pub fn set_speed(&mut self, speed_percent: u32) {
+        self.speed_percent = speed_percent.max(1);
+        self.reset();
+    }
+
+    /// Restart timing from now, such as after emulation was paused
+    pub fn reset(&mut self) {
+        self.epoch = self.clock.now();
+        self.clocks_run = 0;
+    }
+
+    fn clocks_in(&self, time: Duration) -> u64 {
+        let clocks_per_sec = u128::from(CLOCK_FREQ) * u128::from(self.speed_percent) / 100;
+        (time.as_nanos() * clocks_per_sec / NANOS_PER_SEC) as u64
+    }
+
+    fn time_for(&self, clocks: u64) -> Duration {
+        let clocks_per_sec = u128::from(CLOCK_FREQ) * u128::from(self.speed_percent) / 100;
+        Duration::from_nanos((u128::from(clocks) * NANOS_PER_SEC / clocks_per_sec) as u64)
+    }
+
+    /// Decide whether to run the emulator or sleep
+    ///
+    /// At most one frame is run at a time, so callers can handle input in between.
+    pub fn poll(&mut self) -> Schedule {
+        let elapsed = self.clock.now().checked_sub(self.epoch).unwrap_or_default();
+        let due = self.clocks_in(elapsed);
+        if due < self.clocks_run + MIN_BATCH_CLOCKS {
+            let wake_time = self.time_for(self.clocks_run + MIN_BATCH_CLOCKS);
+            return Schedule::Sleep(wake_time.checked_sub(elapsed).unwrap_or_default());
+        }
+        let behind = due - self.clocks_run;
+        if behind > MAX_LAG_FRAMES * CLOCKS_PER_FRAME {
+            self.clocks_run = due - CLOCKS_PER_FRAME;
+        }
+        Schedule::Run(behind.min(CLOCKS_PER_FRAME))
+    }
+
+    /// Record that the emulator ran for `clocks`
+    ///
+    /// This may be more than was asked for, as instructions take several clocks.
+    pub fn completed(&mut self, clocks: u64) {
+        self.clocks_run += clocks;
+    }
+}
+
+/// Tracks how much emulation is due based on the audio samples the host has asked for
+///
+/// Each time the audio device asks for more samples, pass how many to
+/// [`request`], run the emulator for the clocks it returns, such as with
+/// `EmulatorCommand::RunCycles`, and report how many were actually run with
+/// [`completed`]. Totals are kept since timing started, so rounding samples
+/// to clocks and instructions not fitting the budget exactly are made up
+/// for on later requests instead of building up into drift.
+///
+/// There is no APU yet, so the samples themselves have to come from
+/// elsewhere. This only keeps emulation in time with the audio device.
+///
+/// ```
+/// use olympia_engine::scheduler::AudioScheduler;
+///
+/// let mut scheduler = AudioScheduler::new(48000);
+/// let clocks = scheduler.request(1024);
+/// assert_eq!(clocks, 89478);
+/// // Run the emulator for `clocks`, which may run slightly fewer
+/// scheduler.completed(89476);
+/// assert_eq!(scheduler.request(1024), 89480);
+/// ```
+///
+/// [`request`]: #method.request
+/// [`completed`]: #method.completed
+#[derive(Debug, Clone)]
+pub struct AudioScheduler {
+    sample_rate: u32,
+    speed_percent: u32,
+    /// Samples requested since timing started
+    samples_requested: u64,
+    /// Clocks run since timing started
+    clocks_run: u64,
+}
+
+impl AudioScheduler {
+    /// Create a scheduler for an audio device playing `sample_rate` samples
+    /// per second, running at normal speed
+    ///
+    /// Sample rates below 1 are treated as 1.
+    pub fn new(sample_rate: u32) -> AudioScheduler {
+        AudioScheduler {
+            sample_rate: sample_rate.max(1),
+            speed_percent: 100,
+            samples_requested: 0,
+            clocks_run: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Speed emulation runs at, as a percentage of Game Boy speed
+    pub fn speed_percent(&self) -> u32 {
+        self.speed_percent
+    }
+
+    /// Change the speed emulation runs at, as a percentage of Game Boy speed
+    ///
+    /// Speeds below 1% are treated as 1%. This also restarts timing.
+    pub fn set_speed(&mut self, speed_percent: u32) {
+        self.speed_percent = speed_percent.max(1);
+        self.reset();
+    }
+
+    /// Restart timing, such as after emulation was paused
+    pub fn reset(&mut self) {
+        self.samples_requested = 0;
+        self.clocks_run = 0;
+    }
+
+    fn clocks_for(&self, samples: u64) -> u64 {
+        let clocks_per_sec = u128::from(CLOCK_FREQ) * u128::from(self.speed_percent) / 100;
+        (u128::from(samples) * clocks_per_sec / u128::from(self.sample_rate)) as u64
+    }
+
+    /// Record that the host asked for `samples` more samples, and return
+    /// how many clocks to run to produce them
+    ///
+    /// If emulation has fallen more than a few frames behind, such as when
+    /// a breakpoint was hit, the missed time is skipped.
+    pub fn request(&mut self, samples: u32) -> u64 {
+        self.samples_requested += u64::from(samples);
+        let due = self.clocks_for(self.samples_requested);
+        let behind = due.saturating_sub(self.clocks_run);
+        if behind > MAX_LAG_FRAMES * CLOCKS_PER_FRAME {
+            self.clocks_run = due - CLOCKS_PER_FRAME;
+            return CLOCKS_PER_FRAME;
+        }
+        behind
+    }
+
+    /// Record that the emulator ran for `clocks`
+    pub fn completed(&mut self, clocks: u64) {
+        self.clocks_run += clocks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    #[derive(Default)]
+    struct TestClock(Cell<Duration>);
+
+    impl TestClock {
+        fn advance(&self, time: Duration) {
+            self.0.set(self.0.get() + time);
+        }
+    }
+
+    impl HostClock for &TestClock {
+        fn now(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_runs_what_is_due() {
+        let clock = TestClock::default();
+        let mut scheduler = FrameScheduler::new(&clock);
+
+        assert_eq!(
+            scheduler.poll(),
+            Schedule::Sleep(Duration::from_nanos(976_562))
+        );
+        clock.advance(Duration::from_millis(2));
+        assert_eq!(scheduler.poll(), Schedule::Run(8388));
+        // Instructions can overshoot the requested clocks
+        scheduler.completed(8392);
+        clock.advance(Duration::from_micros(500));
+        assert_eq!(
+            scheduler.poll(),
+            Schedule::Sleep(Duration::from_nanos(477_371))
+        );
+        clock.advance(Duration::from_micros(500));
+        assert_eq!(scheduler.poll(), Schedule::Run(4190));
+    }
+
+    #[test]
+    fn test_speed() {
+        let clock = TestClock::default();
+        let mut scheduler = FrameScheduler::new(&clock);
+
+        scheduler.set_speed(200);
+        clock.advance(Duration::from_millis(2));
+        assert_eq!(scheduler.poll(), Schedule::Run(16777));
+        scheduler.set_speed(0);
+        assert_eq!(scheduler.speed_percent(), 1);
+    }
+
+    #[test]
+    fn test_runs_at_most_a_frame() {
+        let clock = TestClock::default();
+        let mut scheduler = FrameScheduler::new(&clock);
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(scheduler.poll(), Schedule::Run(CLOCKS_PER_FRAME));
+        scheduler.completed(CLOCKS_PER_FRAME);
+        assert_eq!(scheduler.poll(), Schedule::Run(CLOCKS_PER_FRAME));
+    }
+
+    #[test]
+    fn test_skips_lost_time() {
+        let clock = TestClock::default();
+        let mut scheduler = FrameScheduler::new(&clock);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(scheduler.poll(), Schedule::Run(CLOCKS_PER_FRAME));
+        scheduler.completed(CLOCKS_PER_FRAME);
+        assert!(matches!(scheduler.poll(), Schedule::Sleep(_)));
+    }
+
+    #[test]
+    fn test_audio_carries_remainder() {
+        let mut scheduler = AudioScheduler::new(44100);
+
+        // 4194304 clocks per second is 95.1 clocks per sample
+        assert_eq!(scheduler.request(1), 95);
+        scheduler.completed(92);
+        assert_eq!(scheduler.request(1), 98);
+        scheduler.completed(100);
+        // Overshooting is made up for too
+        assert_eq!(scheduler.request(1), 93);
+        scheduler.completed(93);
+        let mut total = 0;
+        for _ in 0..441 {
+            let clocks = scheduler.request(100);
+            scheduler.completed(clocks);
+            total += clocks;
+        }
+        assert_eq!(total + 92 + 100 + 93, 4194304 * 44103 / 44100);
+    }
+
+    #[test]
+    fn test_audio_speed_and_lag() {
+        let mut scheduler = AudioScheduler::new(48000);
+
+        scheduler.set_speed(200);
+        assert_eq!(scheduler.request(48), 8388);
+        scheduler.set_speed(0);
+        assert_eq!(scheduler.speed_percent(), 1);
+
+        scheduler.set_speed(100);
+        assert_eq!(scheduler.request(48000), CLOCKS_PER_FRAME);
+        scheduler.completed(CLOCKS_PER_FRAME);
+        assert_eq!(scheduler.request(0), 0);
+    }
+}
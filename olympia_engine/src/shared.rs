@@ -0,0 +1,85 @@
+//! Types for sharing emulator state, which are thread safe when the
+//! `sync` feature is enabled
+//!
+//! Without the `sync` feature, these are the cheaper single threaded types
+//! from `core` and `alloc`.
+
+#[cfg(feature = "sync")]
+pub use alloc::sync::Arc as Shared;
+
+#[cfg(not(feature = "sync"))]
+pub use alloc::rc::Rc as Shared;
+
+/// Implemented by all types, or only by `Send` types when the
+/// `sync` feature is enabled
+#[cfg(feature = "sync")]
+pub trait MaybeSend: Send {}
+
+#[cfg(feature = "sync")]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// Implemented by all types, or only by `Send` types when the
+/// `sync` feature is enabled
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSend {}
+
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> MaybeSend for T {}
+
+/// Interior mutability which is a `RefCell`, or a `Mutex` when the
+/// `sync` feature is enabled
+///
+/// Unlike a `RefCell`, a value borrowed with the `sync` feature cannot be
+/// borrowed again until the first borrow ends.
+#[derive(Debug, Default)]
+pub struct Lock<T> {
+    #[cfg(feature = "sync")]
+    inner: std::sync::Mutex<T>,
+    #[cfg(not(feature = "sync"))]
+    inner: core::cell::RefCell<T>,
+}
+
+#[cfg(feature = "sync")]
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Lock<T> {
+        Lock {
+            inner: std::sync::Mutex::new(value),
+        }
+    }
+
+    pub fn borrow(&self) -> std::sync::MutexGuard<'_, T> {
+        self.borrow_mut()
+    }
+
+    pub fn borrow_mut(&self) -> std::sync::MutexGuard<'_, T> {
+        // A panic while locked can't leave emulator state half updated
+        // any more than a panic during a RefCell borrow can
+        self.inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(not(feature = "sync"))]
+impl<T> Lock<T> {
+    pub fn new(value: T) -> Lock<T> {
+        Lock {
+            inner: core::cell::RefCell::new(value),
+        }
+    }
+
+    pub fn borrow(&self) -> core::cell::Ref<'_, T> {
+        self.inner.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> core::cell::RefMut<'_, T> {
+        self.inner.borrow_mut()
+    }
+}
+
+impl<T> Lock<T> {
+    /// Replace the contained value, returning the old value
+    pub fn replace(&self, value: T) -> T {
+        core::mem::replace(&mut *self.borrow_mut(), value)
+    }
+}
@@ -0,0 +1,216 @@
+//! Run small programs and check the results, for emulator backed unit tests
+//!
+//! Programs are given as machine code, and placed in an otherwise empty
+//! ROM with no memory controller. Execution starts at [`PROGRAM_START`],
+//! rather than running the cartridge header.
+//!
+//! ```
+//! use olympia_engine::registers::{ByteRegister, Flag, WordRegister};
+//! use olympia_engine::testing::{self, Program};
+//!
+//! let gb = Program::new(&[
+//!     0x3E, 0x0F, // LD A, 0Fh
+//!     0xC6, 0x01, // ADD A, 1h
+//!     0xEA, 0x00, 0xC0, // LD (C000h), A
+//! ])
+//! .run(3)
+//! .unwrap();
+//!
+//! testing::assert_byte_register(&gb, ByteRegister::A, 0x10);
+//! testing::assert_word_register(&gb, WordRegister::PC, testing::PROGRAM_START + 7);
+//! testing::assert_flags(&gb, &[(Flag::HalfCarry, true), (Flag::Carry, false)]);
+//! testing::assert_memory(&gb, 0xC000, &[0x10]);
+//! ```
+//!
+//! [`PROGRAM_START`]: constant.PROGRAM_START.html
+
+use crate::address::LiteralAddress;
+use crate::gameboy::{GameBoy, GameBoyModel, StepResult};
+use crate::registers::{ByteRegister, Flag, WordRegister};
+use crate::rom::Cartridge;
+
+use alloc::vec::Vec;
+
+/// Address programs are placed at, and where execution starts
+pub const PROGRAM_START: u16 = 0x200;
+/// [`PROGRAM_START`] as an address
+///
+/// [`PROGRAM_START`]: constant.PROGRAM_START.html
+pub const PROG_MEMORY_OFFSET: LiteralAddress = LiteralAddress(PROGRAM_START);
+/// Size of the ROM programs are placed in
+const ROM_SIZE: usize = 0x8000;
+
+/// Bytes to place in ROM, starting at the given address
+pub type ProgramSegment<'a> = (LiteralAddress, &'a [u8]);
+
+/// A program, and any data it needs, laid out in ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    rom: Vec<u8>,
+    model: GameBoyModel,
+}
+
+impl Program {
+    /// A program with `code` at `PROGRAM_START`
+    pub fn new(code: &[u8]) -> Program {
+        Program {
+            rom: vec![0u8; ROM_SIZE],
+            model: GameBoyModel::GameBoy,
+        }
+        .with_segment(PROG_MEMORY_OFFSET, code)
+    }
+
+    /// The same program, with `data` placed in ROM at `addr`
+    ///
+    /// This can add interrupt handlers at their vectors, or data the program
+    /// reads. Panics if the data doesn't fit in the ROM.
+    pub fn with_segment<A: Into<LiteralAddress>>(mut self, addr: A, data: &[u8]) -> Program {
+        let start = usize::from(addr.into().0);
+        self.rom[start..start + data.len()].copy_from_slice(data);
+        self
+    }
+
+    /// The same program, run on a different model of Game Boy
+    pub fn on_model(self, model: GameBoyModel) -> Program {
+        Program { model, ..self }
+    }
+
+    /// The ROM holding the program
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// A Game Boy ready to run the program's first instruction
+    pub fn load(&self) -> GameBoy {
+        let cartridge = Cartridge::from_data(self.rom.clone()).unwrap();
+        let mut gb = GameBoy::new(cartridge, self.model);
+        gb.write_register_u16(WordRegister::PC, PROGRAM_START);
+        gb
+    }
+
+    /// Run `steps` instructions of the program
+    pub fn run(&self, steps: u64) -> StepResult<GameBoy> {
+        let mut gb = self.load();
+        run_steps(&mut gb, steps)?;
+        Ok(gb)
+    }
+}
+
+/// A program made of the given segments, with nothing at `PROGRAM_START` unless given
+fn program_with(segments: &[ProgramSegment]) -> Program {
+    segments
+        .iter()
+        .fold(Program::new(&[]), |program, (addr, data)| {
+            program.with_segment(*addr, data)
+        })
+}
+
+/// A cartridge with each segment placed in an otherwise empty ROM
+pub fn make_cartridge_with(segments: &[ProgramSegment]) -> Cartridge {
+    Cartridge::from_data(program_with(segments).rom).unwrap()
+}
+
+/// Run `steps` instructions of a program made of the given segments
+pub fn run_program_with(steps: u64, segments: &[ProgramSegment]) -> StepResult<GameBoy> {
+    program_with(segments).run(steps)
+}
+
+/// Run `steps` instructions of a program placed at `PROGRAM_START`
+pub fn run_program(steps: u64, program: &[u8]) -> StepResult<GameBoy> {
+    Program::new(program).run(steps)
+}
+
+/// Run `steps` more instructions
+pub fn run_steps(gb: &mut GameBoy, steps: u64) -> StepResult<()> {
+    for _ in 0..steps {
+        gb.step()?;
+    }
+    Ok(())
+}
+
+/// Panic unless an 8-bit register holds `expected`
+#[track_caller]
+pub fn assert_byte_register(gb: &GameBoy, register: ByteRegister, expected: u8) {
+    let actual = gb.read_register_u8(register);
+    assert!(
+        actual == expected,
+        "Register {:?} is {:02X}h, expected {:02X}h",
+        register,
+        actual,
+        expected
+    );
+}
+
+/// Panic unless a 16-bit register holds `expected`
+#[track_caller]
+pub fn assert_word_register(gb: &GameBoy, register: WordRegister, expected: u16) {
+    let actual = gb.read_register_u16(register);
+    assert!(
+        actual == expected,
+        "Register {:?} is {:04X}h, expected {:04X}h",
+        register,
+        actual,
+        expected
+    );
+}
+
+/// Panic unless each flag is set or cleared as given
+///
+/// Flags not listed aren't checked.
+#[track_caller]
+pub fn assert_flags(gb: &GameBoy, expected: &[(Flag, bool)]) {
+    for (flag, set) in expected.iter().copied() {
+        assert!(
+            gb.read_flag(flag) == set,
+            "Flag {:?} is {}, expected {}",
+            flag,
+            if set { "clear" } else { "set" },
+            if set { "set" } else { "clear" }
+        );
+    }
+}
+
+/// Panic unless memory starting at `addr` holds `expected`
+///
+/// Memory is read without emitting events or triggering breakpoints.
+#[track_caller]
+pub fn assert_memory<A: Into<LiteralAddress>>(gb: &GameBoy, addr: A, expected: &[u8]) {
+    let start = addr.into().0;
+    for (offset, expected) in expected.iter().copied().enumerate() {
+        let addr = start.wrapping_add(offset as u16);
+        match gb.get_memory_u8(addr) {
+            Ok(actual) => assert!(
+                actual == expected,
+                "Memory at {:04X}h is {:02X}h, expected {:02X}h",
+                addr,
+                actual,
+                expected
+            ),
+            Err(e) => panic!("Could not read memory at {:04X}h: {:?}", addr, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_program_segments() {
+        // JP 150h, with LD A, 42h there
+        let program = Program::new(&[0xC3, 0x50, 0x01]).with_segment(0x150, &[0x3E, 0x42]);
+        let mut gb = program.run(2).unwrap();
+
+        assert_byte_register(&gb, ByteRegister::A, 0x42);
+        assert_word_register(&gb, WordRegister::PC, 0x152);
+        run_steps(&mut gb, 1).unwrap();
+        assert_word_register(&gb, WordRegister::PC, 0x153);
+    }
+
+    #[test]
+    #[should_panic(expected = "Memory at C001h is 00h, expected 12h")]
+    fn test_assert_memory_message() {
+        let gb = Program::new(&[]).load();
+        assert_memory(&gb, 0xC000, &[0x00, 0x12]);
+    }
+}
@@ -0,0 +1,116 @@
+//! Converts the shades drawn by the PPU into RGBA images for display
+//!
+//! Frontends turn a frame of shades into an [`RgbaImage`] with
+//! [`RgbaImage::from_shades`], and can then scale it or add effects with
+//! the [`filters`] in this module before drawing it.
+//!
+//! [`RgbaImage`]: ./struct.RgbaImage.html
+//! [`RgbaImage::from_shades`]: ./struct.RgbaImage.html#method.from_shades
+//! [`filters`]: ./filters/index.html
+
+pub mod filters;
+
+use alloc::vec::Vec;
+
+/// A colour as red, green, blue and alpha components
+pub type Rgba = [u8; 4];
+
+/// Bytes used by each pixel of an [`RgbaImage`]
+///
+/// [`RgbaImage`]: ./struct.RgbaImage.html
+pub const BYTES_PER_PIXEL: usize = 4;
+
+/// Shades of grey to show each shade from 0 (white) to 3 (black) with
+pub const GREYSCALE: [Rgba; 4] = [
+    [255, 255, 255, 255],
+    [176, 176, 176, 255],
+    [128, 128, 128, 255],
+    [0, 0, 0, 255],
+];
+
+/// An image made of RGBA pixels, stored in rows from the top left
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Create a transparent black image
+    pub fn new(width: usize, height: usize) -> RgbaImage {
+        RgbaImage {
+            width,
+            height,
+            data: vec![0; width * height * BYTES_PER_PIXEL],
+        }
+    }
+
+    /// Create an image from shades from 0 (white) to 3 (black), in rows
+    /// from the top left, showing each shade as the matching colour in `colors`
+    ///
+    /// Pixels past the end of `shades` are left transparent.
+    pub fn from_shades<I>(width: usize, height: usize, shades: I, colors: &[Rgba; 4]) -> RgbaImage
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        let mut image = RgbaImage::new(width, height);
+        let pixels = image.data.chunks_exact_mut(BYTES_PER_PIXEL);
+        for (pixel, shade) in pixels.zip(shades) {
+            pixel.copy_from_slice(&colors[usize::from(shade & 0b11)]);
+        }
+        image
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The RGBA bytes of every pixel, in rows from the top left
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn offset(&self, x: usize, y: usize) -> usize {
+        assert!(x < self.width && y < self.height, "Pixel out of bounds");
+        (y * self.width + x) * BYTES_PER_PIXEL
+    }
+
+    /// The colour of the pixel at `x`, `y`
+    ///
+    /// Panics if the pixel is outside of the image.
+    pub fn pixel(&self, x: usize, y: usize) -> Rgba {
+        let offset = self.offset(x, y);
+        let mut pixel = [0; BYTES_PER_PIXEL];
+        pixel.copy_from_slice(&self.data[offset..offset + BYTES_PER_PIXEL]);
+        pixel
+    }
+
+    /// Set the colour of the pixel at `x`, `y`
+    ///
+    /// Panics if the pixel is outside of the image.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgba) {
+        let offset = self.offset(x, y);
+        self.data[offset..offset + BYTES_PER_PIXEL].copy_from_slice(&color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_shades() {
+        let image = RgbaImage::from_shades(2, 2, vec![0, 3, 1], &GREYSCALE);
+
+        assert_eq!(image.pixel(0, 0), GREYSCALE[0]);
+        assert_eq!(image.pixel(1, 0), GREYSCALE[3]);
+        assert_eq!(image.pixel(0, 1), GREYSCALE[1]);
+        assert_eq!(image.pixel(1, 1), [0, 0, 0, 0]);
+        assert_eq!(image.data().len(), 16);
+    }
+}
@@ -0,0 +1,483 @@
+//! Scalers and effects applied to frames before they are displayed
+//!
+//! Each [`Filter`] turns one [`RgbaImage`] into another, and several can
+//! be chained in a [`FilterPipeline`]. Frontends can let users choose
+//! filters by name with [`FilterKind`].
+//!
+//! ```
+//! use olympia_engine::video::filters::{FilterKind, FilterPipeline};
+//! use olympia_engine::video::{RgbaImage, GREYSCALE};
+//!
+//! let mut pipeline = FilterPipeline::new();
+//! pipeline.push("scale2x".parse::<FilterKind>().unwrap().build());
+//! pipeline.push(FilterKind::Ghosting.build());
+//!
+//! let frame = RgbaImage::from_shades(160, 144, vec![0; 160 * 144], &GREYSCALE);
+//! let output = pipeline.apply(&frame);
+//! assert_eq!((output.width(), output.height()), (320, 288));
+//! ```
+//!
+//! [`Filter`]: ./trait.Filter.html
+//! [`RgbaImage`]: ../struct.RgbaImage.html
+//! [`FilterPipeline`]: ./struct.FilterPipeline.html
+//! [`FilterKind`]: ./enum.FilterKind.html
+
+use super::{Rgba, RgbaImage};
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::str::FromStr;
+use derive_more::Display;
+
+/// Percentage each channel is darkened by on the grid lines of `FilterKind::LcdGrid`
+const DEFAULT_GRID_DARKNESS: u8 = 40;
+/// Percentage of the previous frame kept by `FilterKind::Ghosting`
+const DEFAULT_GHOSTING_STRENGTH: u8 = 50;
+
+/// Transforms a frame before it is displayed
+pub trait Filter {
+    /// Create a filtered copy of `input`
+    ///
+    /// Filters may keep state between frames, so this should be called once
+    /// per frame, in order.
+    fn apply(&mut self, input: &RgbaImage) -> RgbaImage;
+}
+
+/// Scales each pixel up to a square of `scale` pixels, without smoothing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nearest {
+    pub scale: usize,
+}
+
+impl Filter for Nearest {
+    fn apply(&mut self, input: &RgbaImage) -> RgbaImage {
+        let scale = self.scale.max(1);
+        let mut output = RgbaImage::new(input.width() * scale, input.height() * scale);
+        for y in 0..output.height() {
+            for x in 0..output.width() {
+                output.set_pixel(x, y, input.pixel(x / scale, y / scale));
+            }
+        }
+        output
+    }
+}
+
+/// The colour of the pixel offset by `dx`, `dy` from `x`, `y`, using
+/// the nearest edge pixel for pixels outside of the image
+fn neighbour(image: &RgbaImage, x: usize, y: usize, dx: isize, dy: isize) -> Rgba {
+    let clamp = |value: usize, delta: isize, len: usize| {
+        (value as isize + delta).max(0).min(len as isize - 1) as usize
+    };
+    image.pixel(clamp(x, dx, image.width()), clamp(y, dy, image.height()))
+}
+
+/// Doubles the size of the image, rounding off diagonal edges rather than
+/// making them blocky
+///
+/// This is the Scale2x algorithm, also known as EPX. It only copies
+/// existing colours, so pixel art keeps its palette.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scale2x;
+
+impl Filter for Scale2x {
+    fn apply(&mut self, input: &RgbaImage) -> RgbaImage {
+        let mut output = RgbaImage::new(input.width() * 2, input.height() * 2);
+        for y in 0..input.height() {
+            for x in 0..input.width() {
+                let e = input.pixel(x, y);
+                let b = neighbour(input, x, y, 0, -1);
+                let d = neighbour(input, x, y, -1, 0);
+                let f = neighbour(input, x, y, 1, 0);
+                let h = neighbour(input, x, y, 0, 1);
+                let (e0, e1, e2, e3) = if b != h && d != f {
+                    (
+                        if d == b { d } else { e },
+                        if b == f { f } else { e },
+                        if d == h { d } else { e },
+                        if h == f { f } else { e },
+                    )
+                } else {
+                    (e, e, e, e)
+                };
+                output.set_pixel(x * 2, y * 2, e0);
+                output.set_pixel(x * 2 + 1, y * 2, e1);
+                output.set_pixel(x * 2, y * 2 + 1, e2);
+                output.set_pixel(x * 2 + 1, y * 2 + 1, e3);
+            }
+        }
+        output
+    }
+}
+
+/// Triples the size of the image, rounding off diagonal edges rather than
+/// making them blocky
+///
+/// This is the Scale3x algorithm, which like [`Scale2x`] only copies
+/// existing colours.
+///
+/// [`Scale2x`]: ./struct.Scale2x.html
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scale3x;
+
+impl Filter for Scale3x {
+    fn apply(&mut self, input: &RgbaImage) -> RgbaImage {
+        let mut output = RgbaImage::new(input.width() * 3, input.height() * 3);
+        for y in 0..input.height() {
+            for x in 0..input.width() {
+                let a = neighbour(input, x, y, -1, -1);
+                let b = neighbour(input, x, y, 0, -1);
+                let c = neighbour(input, x, y, 1, -1);
+                let d = neighbour(input, x, y, -1, 0);
+                let e = input.pixel(x, y);
+                let f = neighbour(input, x, y, 1, 0);
+                let g = neighbour(input, x, y, -1, 1);
+                let h = neighbour(input, x, y, 0, 1);
+                let i = neighbour(input, x, y, 1, 1);
+                let block = if b != h && d != f {
+                    [
+                        [
+                            if d == b { d } else { e },
+                            if (d == b && e != c) || (b == f && e != a) {
+                                b
+                            } else {
+                                e
+                            },
+                            if b == f { f } else { e },
+                        ],
+                        [
+                            if (d == b && e != g) || (d == h && e != a) {
+                                d
+                            } else {
+                                e
+                            },
+                            e,
+                            if (b == f && e != i) || (h == f && e != c) {
+                                f
+                            } else {
+                                e
+                            },
+                        ],
+                        [
+                            if d == h { d } else { e },
+                            if (d == h && e != i) || (h == f && e != g) {
+                                h
+                            } else {
+                                e
+                            },
+                            if h == f { f } else { e },
+                        ],
+                    ]
+                } else {
+                    [[e; 3]; 3]
+                };
+                for (row, colors) in block.iter().enumerate() {
+                    for (column, color) in colors.iter().enumerate() {
+                        output.set_pixel(x * 3 + column, y * 3 + row, *color);
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+/// Scales each pixel up to a square of `scale` pixels, with darker lines
+/// between them like the gaps between pixels on the Game Boy's LCD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LcdGrid {
+    pub scale: usize,
+    /// Percentage each colour channel is darkened by on the grid lines
+    pub darkness: u8,
+}
+
+impl Filter for LcdGrid {
+    fn apply(&mut self, input: &RgbaImage) -> RgbaImage {
+        let scale = self.scale.max(2);
+        let brightness = u16::from(100 - self.darkness.min(100));
+        let mut output = Nearest { scale }.apply(input);
+        for y in 0..output.height() {
+            for x in 0..output.width() {
+                if x % scale != scale - 1 && y % scale != scale - 1 {
+                    continue;
+                }
+                let mut color = output.pixel(x, y);
+                for channel in color.iter_mut().take(3) {
+                    *channel = (u16::from(*channel) * brightness / 100) as u8;
+                }
+                output.set_pixel(x, y, color);
+            }
+        }
+        output
+    }
+}
+
+/// Blends each frame with the previous ones, like the slow response of
+/// the Game Boy's LCD
+///
+/// This smooths out games which flicker sprites on alternate frames to
+/// show more of them or to make them look transparent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ghosting {
+    /// Percentage of the previous output kept in each frame
+    pub strength: u8,
+    previous: Option<RgbaImage>,
+}
+
+impl Ghosting {
+    pub fn new(strength: u8) -> Ghosting {
+        Ghosting {
+            strength: strength.min(100),
+            previous: None,
+        }
+    }
+}
+
+impl Filter for Ghosting {
+    fn apply(&mut self, input: &RgbaImage) -> RgbaImage {
+        let strength = u16::from(self.strength.min(100));
+        let output = match self.previous.take() {
+            Some(mut previous)
+                if previous.width() == input.width() && previous.height() == input.height() =>
+            {
+                for (old, new) in previous.data.iter_mut().zip(input.data()) {
+                    let blended = u16::from(*old) * strength + u16::from(*new) * (100 - strength);
+                    *old = ((blended + 50) / 100) as u8;
+                }
+                previous
+            }
+            // The first frame, or the size changed, so there is nothing to blend with
+            _ => input.clone(),
+        };
+        self.previous = Some(output.clone());
+        output
+    }
+}
+
+/// Applies several filters in order, each to the output of the one before
+#[derive(Default)]
+pub struct FilterPipeline {
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl FilterPipeline {
+    /// Create a pipeline which leaves frames unchanged
+    pub fn new() -> FilterPipeline {
+        FilterPipeline::default()
+    }
+
+    /// Add a filter after the existing ones
+    pub fn push(&mut self, filter: Box<dyn Filter>) {
+        self.filters.push(filter);
+    }
+
+    /// Remove all filters
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Run `input` through every filter
+    pub fn apply(&mut self, input: &RgbaImage) -> RgbaImage {
+        let mut output = input.clone();
+        for filter in self.filters.iter_mut() {
+            output = filter.apply(&output);
+        }
+        output
+    }
+}
+
+/// A filter a user can choose, with default settings
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FilterKind {
+    #[display(fmt = "nearest2x")]
+    Nearest2x,
+    #[display(fmt = "nearest3x")]
+    Nearest3x,
+    #[display(fmt = "scale2x")]
+    Scale2x,
+    #[display(fmt = "scale3x")]
+    Scale3x,
+    /// Triples the size of the image, darkening the grid lines by 40%
+    #[display(fmt = "lcd-grid")]
+    LcdGrid,
+    /// Keeps 50% of the previous frame
+    #[display(fmt = "ghosting")]
+    Ghosting,
+}
+
+impl FilterKind {
+    /// Every kind of filter, for frontends to offer as choices
+    pub const ALL: [FilterKind; 6] = [
+        FilterKind::Nearest2x,
+        FilterKind::Nearest3x,
+        FilterKind::Scale2x,
+        FilterKind::Scale3x,
+        FilterKind::LcdGrid,
+        FilterKind::Ghosting,
+    ];
+
+    /// Create a filter of this kind
+    pub fn build(self) -> Box<dyn Filter> {
+        match self {
+            FilterKind::Nearest2x => Box::new(Nearest { scale: 2 }),
+            FilterKind::Nearest3x => Box::new(Nearest { scale: 3 }),
+            FilterKind::Scale2x => Box::new(Scale2x),
+            FilterKind::Scale3x => Box::new(Scale3x),
+            FilterKind::LcdGrid => Box::new(LcdGrid {
+                scale: 3,
+                darkness: DEFAULT_GRID_DARKNESS,
+            }),
+            FilterKind::Ghosting => Box::new(Ghosting::new(DEFAULT_GHOSTING_STRENGTH)),
+        }
+    }
+}
+
+/// Indicates a value is not the name of a filter
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+#[display(
+    fmt = "Unknown filter, expected nearest2x, nearest3x, scale2x, scale3x, lcd-grid or ghosting"
+)]
+pub struct FilterParseError;
+
+#[cfg(feature = "std")]
+impl std::error::Error for FilterParseError {}
+
+impl FromStr for FilterKind {
+    type Err = FilterParseError;
+
+    fn from_str(s: &str) -> Result<FilterKind, FilterParseError> {
+        FilterKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.to_string() == s)
+            .ok_or(FilterParseError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::GREYSCALE;
+    use alloc::string::String;
+
+    /// Build an image from rows of shades, such as `"0123"`
+    fn image(rows: &[&str]) -> RgbaImage {
+        let shades = rows
+            .iter()
+            .flat_map(|row| row.bytes())
+            .map(|shade| shade - b'0');
+        RgbaImage::from_shades(rows[0].len(), rows.len(), shades, &GREYSCALE)
+    }
+
+    /// Turn an image back into rows of shades, with `?` for other colours
+    fn rows(image: &RgbaImage) -> Vec<String> {
+        (0..image.height())
+            .map(|y| {
+                (0..image.width())
+                    .map(|x| {
+                        let pixel = image.pixel(x, y);
+                        match GREYSCALE.iter().position(|color| *color == pixel) {
+                            Some(shade) => char::from(b'0' + shade as u8),
+                            None => '?',
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nearest() {
+        let input = image(&["03", "12"]);
+
+        let output = Nearest { scale: 2 }.apply(&input);
+
+        assert_eq!(rows(&output), vec!["0033", "0033", "1122", "1122"]);
+    }
+
+    #[test]
+    fn test_scale2x() {
+        let input = image(&["300", "330", "333"]);
+
+        let output = Scale2x.apply(&input);
+
+        assert_eq!(
+            rows(&output),
+            vec!["330000", "333000", "333000", "333330", "333333", "333333"]
+        );
+    }
+
+    #[test]
+    fn test_scale3x() {
+        let input = image(&["30", "33"]);
+
+        let output = Scale3x.apply(&input);
+
+        assert_eq!(
+            rows(&output),
+            vec!["333000", "333300", "333330", "333333", "333333", "333333"]
+        );
+    }
+
+    #[test]
+    fn test_lcd_grid() {
+        let input = image(&["03"]);
+
+        let output = LcdGrid {
+            scale: 2,
+            darkness: 50,
+        }
+        .apply(&input);
+
+        // Black stays black however much it is darkened
+        assert_eq!(rows(&output), vec!["0?33", "??33"]);
+        assert_eq!(output.pixel(1, 0), [127, 127, 127, 255]);
+        assert_eq!(output.pixel(3, 1), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_ghosting() {
+        let mut ghosting = Ghosting::new(50);
+
+        let first = ghosting.apply(&image(&["3"]));
+        let second = ghosting.apply(&image(&["0"]));
+        let third = ghosting.apply(&image(&["0"]));
+
+        assert_eq!(rows(&first), vec!["3"]);
+        assert_eq!(second.pixel(0, 0), [128, 128, 128, 255]);
+        assert_eq!(third.pixel(0, 0), [192, 192, 192, 255]);
+        let resized = ghosting.apply(&image(&["33"]));
+        assert_eq!(rows(&resized), vec!["33"]);
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let mut pipeline = FilterPipeline::new();
+        pipeline.push(FilterKind::Nearest2x.build());
+        pipeline.push(FilterKind::Nearest3x.build());
+
+        let output = pipeline.apply(&image(&["3"]));
+
+        assert_eq!(rows(&output), vec!["333333"; 6]);
+        pipeline.clear();
+        assert!(pipeline.is_empty());
+    }
+
+    #[test]
+    fn test_parse_filter_kind() {
+        for kind in FilterKind::ALL.iter() {
+            assert_eq!(kind.to_string().parse(), Ok(*kind));
+        }
+        assert_eq!("lcd-grid".parse(), Ok(FilterKind::LcdGrid));
+        assert_eq!("hq2x".parse::<FilterKind>(), Err(FilterParseError));
+    }
+}
@@ -0,0 +1,322 @@
+//! Frontend settings, persisted in the user's config directory
+
+use gtk::glib;
+use olympia_engine::gameboy::Button;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+const CONFIG_DIR: &str = "olympia";
+const CONFIG_FILE: &str = "olympia_native.ini";
+const KEY_BINDINGS_GROUP: &str = "KeyBindings";
+const RECENT_ROMS_GROUP: &str = "RecentRoms";
+const EMULATION_GROUP: &str = "Emulation";
+const BACKGROUND_BEHAVIOR_KEY: &str = "BackgroundBehavior";
+const OVERCLOCK_KEY: &str = "Overclock";
+/// Number of ROMs kept in the recent ROMs list
+pub(crate) const MAX_RECENT_ROMS: usize = 10;
+
+/// Which keyboard key triggers each joypad button
+///
+/// Keys are stored as GDK key names, such as `Return` or `z`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct KeyBindings {
+    keys: HashMap<Button, String>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        let defaults = [
+            (Button::Right, "Right"),
+            (Button::Left, "Left"),
+            (Button::Up, "Up"),
+            (Button::Down, "Down"),
+            (Button::A, "x"),
+            (Button::B, "z"),
+            (Button::Select, "BackSpace"),
+            (Button::Start, "Return"),
+        ];
+        KeyBindings {
+            keys: defaults
+                .iter()
+                .map(|(button, key)| (*button, String::from(*key)))
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// The key bound to a button, if any
+    pub(crate) fn key(&self, button: Button) -> Option<&str> {
+        self.keys.get(&button).map(String::as_str)
+    }
+
+    /// The button triggered by a key, if any
+    pub(crate) fn button(&self, key: &str) -> Option<Button> {
+        self.keys
+            .iter()
+            .find(|(_, bound_key)| bound_key.as_str() == key)
+            .map(|(button, _)| *button)
+    }
+
+    /// Bind a key to a button, unbinding it from any other button
+    pub(crate) fn set_key(&mut self, button: Button, key: &str) {
+        self.keys.retain(|_, bound_key| bound_key != key);
+        self.keys.insert(button, String::from(key));
+    }
+
+    /// Remove the key bound to a button
+    pub(crate) fn clear_key(&mut self, button: Button) {
+        self.keys.remove(&button);
+    }
+}
+
+/// What the emulator does while its window is in the background
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum BackgroundBehavior {
+    /// Pause, and resume when the window is focused again
+    #[default]
+    Pause,
+    /// Keep running without sound. There is no audio output yet, so this
+    /// currently runs the same as `KeepRunning`
+    Mute,
+    /// Keep running as if the window was focused
+    KeepRunning,
+}
+
+impl BackgroundBehavior {
+    pub(crate) const ALL: [BackgroundBehavior; 3] = [
+        BackgroundBehavior::Pause,
+        BackgroundBehavior::Mute,
+        BackgroundBehavior::KeepRunning,
+    ];
+
+    /// Name used in the config file and as the menu action target
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            BackgroundBehavior::Pause => "pause",
+            BackgroundBehavior::Mute => "mute",
+            BackgroundBehavior::KeepRunning => "keep-running",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<BackgroundBehavior> {
+        BackgroundBehavior::ALL
+            .iter()
+            .copied()
+            .find(|behavior| behavior.name() == name)
+    }
+}
+
+/// Settings shared by all parts of the frontend
+pub(crate) struct Config {
+    path: PathBuf,
+    key_file: glib::KeyFile,
+}
+
+impl Config {
+    /// Load settings from the default location
+    ///
+    /// Missing or unreadable settings files result in default settings
+    pub(crate) fn load() -> Config {
+        let mut path = glib::user_config_dir();
+        path.push(CONFIG_DIR);
+        path.push(CONFIG_FILE);
+        Config::load_from(path)
+    }
+
+    pub(crate) fn load_from<P: AsRef<Path>>(path: P) -> Config {
+        let key_file = glib::KeyFile::new();
+        if path.as_ref().exists() {
+            if let Err(e) = key_file.load_from_file(&path, glib::KeyFileFlags::KEEP_COMMENTS) {
+                log::warn!("Could not read config {}: {}", path.as_ref().display(), e);
+            }
+        }
+        Config {
+            path: path.as_ref().to_owned(),
+            key_file,
+        }
+    }
+
+    /// Write settings back to the file they were loaded from
+    pub(crate) fn save(&self) -> Result<(), glib::Error> {
+        if let Some(parent) = self.path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                log::warn!("Could not create config dir {}: {}", parent.display(), e);
+            }
+        }
+        self.key_file.save_to_file(&self.path)
+    }
+
+    /// Configured key bindings, using defaults for any unset buttons
+    pub(crate) fn key_bindings(&self) -> KeyBindings {
+        let mut bindings = KeyBindings::default();
+        for button in Button::ALL.iter() {
+            let key = self
+                .key_file
+                .string(KEY_BINDINGS_GROUP, &button.to_string());
+            match key {
+                Ok(key) if key.is_empty() => bindings.clear_key(*button),
+                Ok(key) => bindings.set_key(*button, key.as_str()),
+                Err(_) => {}
+            }
+        }
+        bindings
+    }
+
+    pub(crate) fn set_key_bindings(&mut self, bindings: &KeyBindings) {
+        for button in Button::ALL.iter() {
+            let key = bindings.key(*button).unwrap_or_default();
+            self.key_file
+                .set_string(KEY_BINDINGS_GROUP, &button.to_string(), key);
+        }
+    }
+
+    /// What to do while the window is in the background, defaulting to pausing
+    pub(crate) fn background_behavior(&self) -> BackgroundBehavior {
+        self.key_file
+            .string(EMULATION_GROUP, BACKGROUND_BEHAVIOR_KEY)
+            .ok()
+            .and_then(|name| BackgroundBehavior::from_name(name.as_str()))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn set_background_behavior(&mut self, behavior: BackgroundBehavior) {
+        self.key_file
+            .set_string(EMULATION_GROUP, BACKGROUND_BEHAVIOR_KEY, behavior.name());
+    }
+
+    /// Extra CPU cycles to run in each line of the display, defaulting to none
+    ///
+    /// This is experimental, and can only be changed by editing the config file.
+    pub(crate) fn overclock(&self) -> u16 {
+        self.key_file
+            .integer(EMULATION_GROUP, OVERCLOCK_KEY)
+            .ok()
+            .and_then(|cycles| u16::try_from(cycles).ok())
+            .unwrap_or_default()
+    }
+
+    /// Recently loaded ROMs, most recent first
+    pub(crate) fn recent_roms(&self) -> Vec<PathBuf> {
+        (0..MAX_RECENT_ROMS)
+            .map(|index| {
+                self.key_file
+                    .string(RECENT_ROMS_GROUP, &recent_rom_key(index))
+            })
+            .take_while(Result::is_ok)
+            .filter_map(Result::ok)
+            .map(|path| PathBuf::from(path.as_str()))
+            .collect()
+    }
+
+    /// Move a ROM to the top of the recent ROMs list
+    pub(crate) fn add_recent_rom(&mut self, path: &Path) {
+        let mut recent_roms = self.recent_roms();
+        recent_roms.retain(|recent| recent != path);
+        recent_roms.insert(0, path.to_owned());
+        recent_roms.truncate(MAX_RECENT_ROMS);
+
+        // The group may not exist yet, which is fine
+        let _ = self.key_file.remove_group(RECENT_ROMS_GROUP);
+        let paths = recent_roms.iter().filter_map(|path| path.to_str());
+        for (index, path) in paths.enumerate() {
+            self.key_file
+                .set_string(RECENT_ROMS_GROUP, &recent_rom_key(index), path);
+        }
+    }
+}
+
+fn recent_rom_key(index: usize) -> String {
+    format!("Rom{}", index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebind_key() {
+        let mut bindings = KeyBindings::default();
+
+        bindings.set_key(Button::A, "z");
+
+        assert_eq!(bindings.key(Button::A), Some("z"));
+        assert_eq!(bindings.key(Button::B), None);
+        assert_eq!(bindings.button("z"), Some(Button::A));
+        assert_eq!(bindings.button("x"), None);
+    }
+
+    #[test]
+    fn test_save_key_bindings() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("olympia_config_test_{}", std::process::id()));
+        path.push(CONFIG_FILE);
+
+        let mut bindings = KeyBindings::default();
+        bindings.set_key(Button::Start, "space");
+        let mut config = Config::load_from(&path);
+        config.set_key_bindings(&bindings);
+        config.save().unwrap();
+
+        let loaded = Config::load_from(&path);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded.key_bindings(), bindings);
+    }
+
+    #[test]
+    fn test_recent_roms() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("olympia_recent_test_{}", std::process::id()));
+        path.push(CONFIG_FILE);
+
+        let mut config = Config::load_from(&path);
+        for index in 0..=MAX_RECENT_ROMS {
+            config.add_recent_rom(Path::new(&format!("/roms/{}.gb", index)));
+        }
+        config.add_recent_rom(Path::new("/roms/5.gb"));
+
+        let recent_roms = config.recent_roms();
+        assert_eq!(recent_roms.len(), MAX_RECENT_ROMS);
+        assert_eq!(recent_roms[0], PathBuf::from("/roms/5.gb"));
+        assert_eq!(recent_roms[1], PathBuf::from("/roms/10.gb"));
+        assert!(!recent_roms.contains(&PathBuf::from("/roms/0.gb")));
+    }
+
+    #[test]
+    fn test_background_behavior() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("olympia_background_test_{}", std::process::id()));
+        path.push(CONFIG_FILE);
+
+        let mut config = Config::load_from(&path);
+        assert_eq!(config.background_behavior(), BackgroundBehavior::Pause);
+        config.set_background_behavior(BackgroundBehavior::KeepRunning);
+        config.save().unwrap();
+
+        let mut loaded = Config::load_from(&path);
+        assert_eq!(
+            loaded.background_behavior(),
+            BackgroundBehavior::KeepRunning
+        );
+        loaded
+            .key_file
+            .set_string(EMULATION_GROUP, BACKGROUND_BEHAVIOR_KEY, "sometimes");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded.background_behavior(), BackgroundBehavior::Pause);
+    }
+
+    #[test]
+    fn test_overclock() {
+        let mut config = Config::load_from("/nonexistent/olympia_native.ini");
+        assert_eq!(config.overclock(), 0);
+        config
+            .key_file
+            .set_integer(EMULATION_GROUP, OVERCLOCK_KEY, 57);
+        assert_eq!(config.overclock(), 57);
+        config
+            .key_file
+            .set_integer(EMULATION_GROUP, OVERCLOCK_KEY, -1);
+        assert_eq!(config.overclock(), 0);
+    }
+}
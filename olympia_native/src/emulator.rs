@@ -1,2 +1 @@
-mod emu_thread;
 pub(crate) mod glib;
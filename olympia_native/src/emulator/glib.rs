@@ -1,17 +1,17 @@
-use crate::emulator::emu_thread::EmulatorThread;
-
 use gtk::glib;
 
 use olympia_engine::{
-    events::{EventHandlerId, Repeat},
+    events::{EventHandlerId, Priority, Propagation, Repeat},
     remote::{
-        CommandId, EmulatorCommand, Event as RemoteEvent, EventSendError, RemoteEmulator,
-        RemoteEmulatorChannel, RemoteEmulatorOutput, RemoteEventListeners, Sender,
+        CommandId, EmulatorCommand, EmulatorThread, Event as RemoteEvent, EventSendError,
+        InterceptListener, RemoteEmulator, RemoteEmulatorChannel, RemoteEmulatorOutput,
+        RemoteEventListeners, Sender,
     },
 };
 
 use std::{
     any::TypeId,
+    cell::RefCell,
     collections::HashMap,
     convert::{TryFrom, TryInto},
     rc::Rc,
@@ -30,7 +30,9 @@ pub(crate) struct GlibEmulatorChannel {
 
 impl GlibEmulatorChannel {
     pub(crate) fn new(ctx: glib::MainContext) -> GlibEmulatorChannel {
-        let (_thread_handle, tx, rx) = EmulatorThread::start();
+        let (tx, command_rx) = mpsc::channel();
+        let (output_tx, rx) = glib::MainContext::channel(glib::source::PRIORITY_DEFAULT);
+        EmulatorThread::spawn(command_rx, WrappedGlibSender(output_tx));
         GlibEmulatorChannel {
             tx,
             ctx,
@@ -75,57 +77,83 @@ impl RemoteEmulatorChannel for GlibEmulatorChannel {
     }
 }
 
+type Listener = Rc<dyn Fn(RemoteEvent) -> (Repeat, Propagation)>;
+/// Listeners for each event type, in the order they are called
+type ListenerMap = HashMap<TypeId, Vec<(EventHandlerId, Priority, Listener)>>;
+
 pub(crate) struct GlibAdapterEventListeners {
-    listeners: HashMap<TypeId, HashMap<EventHandlerId, Box<dyn Sender<RemoteEvent>>>>,
-    context: glib::MainContext,
+    listeners: Rc<RefCell<ListenerMap>>,
+    tx: glib::Sender<RemoteEvent>,
     next_listener_id: u64,
 }
 
 impl GlibAdapterEventListeners {
     pub(crate) fn new(context: glib::MainContext) -> GlibAdapterEventListeners {
+        let listeners = Rc::new(RefCell::new(HashMap::new()));
+        // Events are handled on the next main loop iteration, so listeners can
+        // use the emulator, or add more listeners, while handling them
+        let (tx, rx) = glib::MainContext::channel::<RemoteEvent>(glib::PRIORITY_DEFAULT);
+        let dispatch_listeners = Rc::clone(&listeners);
+        rx.attach(Some(&context), move |evt| {
+            dispatch(&dispatch_listeners, evt);
+            glib::Continue(true)
+        });
         GlibAdapterEventListeners {
-            context,
-            listeners: HashMap::new(),
+            listeners,
+            tx,
             next_listener_id: 0,
         }
     }
 }
 
+/// Call the listeners for an event in order, until one consumes it
+fn dispatch(listeners: &RefCell<ListenerMap>, evt: RemoteEvent) {
+    let event_type_id = evt.event_type_id();
+    // Listeners are called without holding the borrow, as they may add listeners
+    let to_call: Vec<(EventHandlerId, Listener)> = match listeners.borrow().get(&event_type_id) {
+        Some(listeners) => listeners
+            .iter()
+            .map(|(id, _, listener)| (*id, Rc::clone(listener)))
+            .collect(),
+        None => return,
+    };
+    let mut finished = Vec::new();
+    for (id, listener) in to_call {
+        let (repeat, propagation) = listener(evt.clone());
+        if !repeat.0 {
+            finished.push(id);
+        }
+        if propagation == Propagation::Stop {
+            break;
+        }
+    }
+    if let Some(listeners) = listeners.borrow_mut().get_mut(&event_type_id) {
+        listeners.retain(|(id, _, _)| !finished.contains(id));
+    }
+}
+
 impl RemoteEventListeners for GlibAdapterEventListeners {
-    fn on(
+    fn intercept(
         &mut self,
         event_type_id: TypeId,
-        f: Box<dyn Fn(RemoteEvent) -> Repeat + 'static>,
+        priority: Priority,
+        f: InterceptListener,
     ) -> EventHandlerId {
         let event_handler_id = EventHandlerId(self.next_listener_id);
-        let (tx, rx) = glib::MainContext::channel::<RemoteEvent>(glib::PRIORITY_DEFAULT);
-        let wrapped = Box::new(WrappedGlibSender(tx));
-        let map = self
-            .listeners
-            .entry(event_type_id)
-            .or_insert_with(HashMap::new);
-        map.insert(event_handler_id, wrapped);
         self.next_listener_id += 1;
-
-        rx.attach(Some(&self.context), move |evt| glib::Continue(f(evt).0));
-
+        let mut listeners = self.listeners.borrow_mut();
+        let listeners = listeners.entry(event_type_id).or_insert_with(Vec::new);
+        let index = listeners
+            .iter()
+            .position(|(_, listener_priority, _)| *listener_priority < priority)
+            .unwrap_or(listeners.len());
+        listeners.insert(index, (event_handler_id, priority, Rc::from(f)));
         event_handler_id
     }
 
     fn emit(&mut self, evt: RemoteEvent) {
-        let event_type_id = evt.event_type_id();
-        if let Some(listeners) = self.listeners.get_mut(&event_type_id) {
-            let mut listener_ids_to_remove = Vec::new();
-            for (id, listener) in listeners.iter_mut() {
-                let send_result = listener.send(evt.clone());
-                if send_result.is_err() {
-                    listener_ids_to_remove.push(*id);
-                    log::warn!(target: "emu_thread", "Removing listener {:?} due to closed channel", id);
-                }
-            }
-            for id in listener_ids_to_remove {
-                listeners.remove(&id);
-            }
+        if self.tx.send(evt).is_err() {
+            log::warn!("Dropping event as the main loop has stopped");
         }
     }
 }
@@ -144,7 +172,10 @@ mod tests {
     use super::*;
     use crate::utils::test_utils;
     use olympia_engine::{
-        events::{ManualStepEvent, ModeChangeEvent, RomLoadedEvent},
+        events::{
+            BreakpointsChangedEvent, EmulationSpeedEvent, ManualStepEvent, ModeChangeEvent,
+            RomLoadedEvent,
+        },
         monitor::{Breakpoint, BreakpointCondition, Comparison},
         registers::WordRegister,
         remote,
@@ -257,6 +288,22 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_query_disassembly() {
+        test_utils::with_context(|context| {
+            let emu = test_utils::get_loaded_remote_emu(context.clone());
+            let task = async { emu.query_disassembly(0x00, 3).await };
+            let disassembly = test_utils::wait_for_task(context, task).unwrap();
+            let text: Vec<&str> = disassembly
+                .instructions
+                .iter()
+                .map(|instr| instr.text.as_str())
+                .collect();
+            assert_eq!(text, vec!["RET", "NOP", "NOP"]);
+            assert_eq!(disassembly.instructions[2].address, 0x02);
+        });
+    }
+
     #[test]
     fn test_query_register() {
         test_utils::with_context(|context| {
@@ -361,4 +408,51 @@ mod tests {
             // assert!(dbg!(Duration::from(emulation_time)) <= dbg!(actual_gb_time));
         });
     }
+
+    #[test]
+    fn test_query_breakpoints() {
+        test_utils::with_unloaded_emu(|context, emu| {
+            let (f, events) = track_event();
+            emu.on::<BreakpointsChangedEvent, _>(f);
+            let bp = Breakpoint::new(
+                WordRegister::PC.into(),
+                BreakpointCondition::Test(Comparison::Equal, 0x150),
+            );
+            let task = async {
+                let first = emu.add_breakpoint(bp.clone()).await.unwrap();
+                let second = emu.add_breakpoint(bp.clone()).await.unwrap();
+                emu.set_breakpoint_state(first.id, false).await.unwrap();
+                emu.remove_breakpoint(second.id).await.unwrap();
+                (first.id, emu.query_breakpoints().await.unwrap())
+            };
+            let (id, resp) = test_utils::wait_for_task(&context, task);
+            let mut inactive_bp = bp.clone();
+            inactive_bp.active = false;
+            assert_eq!(resp.breakpoints, vec![(id, inactive_bp)]);
+
+            let events = events.borrow();
+            assert_eq!(events.len(), 4);
+            assert_eq!(events[3].breakpoints, resp.breakpoints);
+        });
+    }
+
+    #[test]
+    fn test_set_speed() {
+        test_utils::with_unloaded_emu(|context, emu| {
+            let (f, events) = track_event();
+            emu.on::<EmulationSpeedEvent, _>(f);
+            let task = async {
+                let invalid = emu.set_speed(0).await;
+                let resp = emu.set_speed(400).await.unwrap();
+                (invalid, resp)
+            };
+            let (invalid, resp) = test_utils::wait_for_task(&context, task);
+            assert_eq!(invalid, Err(()));
+            assert_eq!(resp.speed_percent, 400);
+            assert_eq!(
+                events.borrow().clone(),
+                vec![EmulationSpeedEvent::new(400, 0)]
+            );
+        });
+    }
 }
@@ -1,4 +1,5 @@
 mod builder;
+mod config;
 mod emulator;
 mod screens;
 mod utils;
@@ -7,6 +8,7 @@ mod widgets;
 use gtk::gio;
 use gtk::prelude::*;
 use gtk::Application;
+use olympia_engine::logging;
 
 struct EmulatorApp {
     gtk_app: Application,
@@ -14,7 +16,10 @@ struct EmulatorApp {
 
 impl EmulatorApp {
     fn new() -> EmulatorApp {
-        let gtk_app = Application::new(Some("com.tonyfinn.olympia_native"), Default::default());
+        let gtk_app = Application::new(
+            Some("com.tonyfinn.olympia_native"),
+            gio::ApplicationFlags::HANDLES_OPEN,
+        );
 
         let mut emu = EmulatorApp { gtk_app };
         emu.register_events();
@@ -26,16 +31,20 @@ impl EmulatorApp {
             let quit = gio::SimpleAction::new("quit", None);
             quit.connect_activate(|_, _| std::process::exit(0));
             app.add_action(&quit);
-
-            let menu_builder = gtk::Builder::from_string(include_str!("../res/menu.ui"));
-            let app_main_menu: gio::Menu = menu_builder.object("MainMenu").unwrap();
-            app.set_menubar(Some(&app_main_menu));
         });
 
         self.gtk_app.connect_activate(|app| {
             let debugger_window = screens::Debugger::new(app);
             debugger_window.show_all();
         });
+
+        self.gtk_app.connect_open(|app, files, _hint| {
+            let debugger_window = screens::Debugger::new(app);
+            debugger_window.show_all();
+            if let Some(path) = files.first().and_then(|file| file.path()) {
+                debugger_window.open_rom(path);
+            }
+        });
     }
 
     fn start(self) {
@@ -43,8 +52,21 @@ impl EmulatorApp {
     }
 }
 
+/// Log through the engine's logger, so the event log can show log messages
+fn init_logging() {
+    let output = pretty_env_logger::formatted_builder()
+        .filter_level(log::LevelFilter::Trace)
+        .build();
+    if let Ok(spec) = std::env::var("RUST_LOG") {
+        if let Err(e) = logging::set_levels_from_spec(&spec) {
+            eprintln!("Ignoring RUST_LOG: {}", e);
+        }
+    }
+    logging::install(Box::new(output)).expect("No other logger should be set yet");
+}
+
 fn main() {
-    pretty_env_logger::init();
+    init_logging();
     let app = EmulatorApp::new();
     widgets::register();
     app.start();
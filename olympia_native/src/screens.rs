@@ -1,3 +1,5 @@
 mod debugger;
+mod keybindings;
+mod rom_properties;
 
 pub(crate) use debugger::Debugger;
@@ -1,22 +1,39 @@
+use gtk::gdk;
 use gtk::gio;
 use gtk::glib;
 use gtk::glib::clone;
 use gtk::prelude::*;
 use gtk::CssProvider;
+use gtk::Inhibit;
 use gtk::StyleContext;
 use gtk::STYLE_PROVIDER_PRIORITY_APPLICATION;
 use gtk::{Application, ApplicationWindow};
-use std::path::PathBuf;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+use crate::config::{BackgroundBehavior, Config, KeyBindings};
 use crate::emulator::glib::glib_remote_emulator;
+use crate::screens::keybindings::KeyBindingDialog;
+use crate::screens::rom_properties::RomPropertiesDialog;
 use crate::utils;
 use crate::widgets::{
-    common::EmulatorWidget, BreakpointViewer, Disassembler, EmulatorDisplay, MemoryViewer,
-    PlaybackControls, RegisterLabels, TilesetViewer,
+    common::EmulatorWidget, BreakpointViewer, Disassembler, EmulatorDisplay, EventLog,
+    MemoryViewer, PlaybackControls, RegisterLabels, SpeedIndicator, TilesetViewer,
 };
 
-use olympia_engine::remote::{LoadRomError, RemoteEmulator};
+use olympia_engine::events::ModeChangeEvent;
+use olympia_engine::gameboy::RenderLayers;
+use olympia_engine::remote::{ExecMode, IdlePolicy, LoadRomError, RemoteEmulator};
+use olympia_engine::rom::CartridgeHeader;
+use olympia_engine::romfile;
+
+/// Key held down to temporarily run at turbo speed
+const TURBO_KEY: &str = "Tab";
+const TURBO_SPEED_PERCENT: u32 = 400;
+const SLOW_MOTION_SPEED_PERCENT: u32 = 50;
+/// Frames with nothing happening before pausing when idle, about ten seconds
+const IDLE_PAUSE_FRAMES: u32 = 600;
 
 #[allow(dead_code)]
 pub(crate) struct Debugger {
@@ -24,10 +41,27 @@ pub(crate) struct Debugger {
     breakpoint_viewer: Rc<BreakpointViewer>,
     disassembler: Disassembler,
     emulator_display: Rc<EmulatorDisplay>,
+    event_log: Rc<EventLog>,
     memory_viewer: Rc<MemoryViewer>,
     register_labels: Rc<RegisterLabels>,
     playback_controls: Rc<PlaybackControls>,
+    speed_indicator: Rc<SpeedIndicator>,
     window: ApplicationWindow,
+    config: RefCell<Config>,
+    key_bindings: RefCell<KeyBindings>,
+    turbo_held: Cell<bool>,
+    turbo_enabled: Cell<bool>,
+    slow_motion_enabled: Cell<bool>,
+    background_behavior: Cell<BackgroundBehavior>,
+    render_layers: Cell<RenderLayers>,
+    exec_mode: RefCell<ExecMode>,
+    /// The mode to resume when focused again, if paused by losing focus
+    background_paused_mode: RefCell<Option<ExecMode>>,
+    recent_roms_menu: gio::Menu,
+    rom_properties_action: gio::SimpleAction,
+    rom_header: RefCell<Option<CartridgeHeader>>,
+    /// Whether battery RAM was saved after the window was asked to close
+    saved_on_close: Cell<bool>,
 }
 
 fn create_child<C: IsA<gtk::Widget> + IsA<glib::Object>>(
@@ -43,6 +77,23 @@ fn create_child<C: IsA<gtk::Widget> + IsA<glib::Object>>(
     child_builder
 }
 
+/// The local file dropped onto the window, if any
+fn dropped_rom_path(data: &gtk::SelectionData) -> Option<PathBuf> {
+    let uris = data.uris();
+    let uri = uris.first()?;
+    glib::filename_from_uri(uri).ok().map(|(path, _)| path)
+}
+
+/// Flip the state of a boolean stateful action, returning the new state
+fn toggle_action(action: &gio::SimpleAction) -> bool {
+    let enabled = !action
+        .state()
+        .and_then(|state| state.get::<bool>())
+        .unwrap_or(false);
+    action.set_state(&enabled.to_variant());
+    enabled
+}
+
 impl Debugger {
     pub(crate) fn new(app: &Application) -> Rc<Debugger> {
         let ctx = glib::MainContext::ref_thread_default();
@@ -66,6 +117,7 @@ impl Debugger {
         let open_action = gio::SimpleAction::new("open", None);
         let emulator_display =
             EmulatorDisplay::from_builder(&root_builder, ctx.clone(), emu.clone());
+        let speed_indicator = SpeedIndicator::from_builder(&root_builder, emu.clone());
 
         let register_builder = create_child::<gtk::Box>(
             &root_builder,
@@ -99,19 +151,94 @@ impl Debugger {
         let breakpoint_viewer =
             BreakpointViewer::from_builder(&bpv_builder, ctx.clone(), emu.clone());
 
+        let event_log_builder = create_child::<gtk::Box>(
+            &root_builder,
+            include_str!("../../res/event_log.ui"),
+            "EventLogContainer",
+            "EventLog",
+        );
+        let event_log = EventLog::from_builder(&event_log_builder, emu.clone());
+
+        let menu_builder = gtk::Builder::from_string(include_str!("../../res/menu.ui"));
+        let main_menu: gio::Menu = menu_builder.object("MainMenu").unwrap();
+        let recent_roms_menu: gio::Menu = menu_builder.object("RecentRomsMenu").unwrap();
+        app.set_menubar(Some(&main_menu));
+
+        let open_recent_action =
+            gio::SimpleAction::new("open-recent", Some(&String::static_variant_type()));
+        let rom_properties_action = gio::SimpleAction::new("rom-properties", None);
+        rom_properties_action.set_enabled(false);
+        let keybindings_action = gio::SimpleAction::new("keybindings", None);
+        let turbo_action = gio::SimpleAction::new_stateful("turbo", None, &false.to_variant());
+        let slow_motion_action =
+            gio::SimpleAction::new_stateful("slow-motion", None, &false.to_variant());
+        let pause_when_idle_action =
+            gio::SimpleAction::new_stateful("pause-when-idle", None, &false.to_variant());
+        let show_background_action =
+            gio::SimpleAction::new_stateful("show-background", None, &true.to_variant());
+        let show_window_action =
+            gio::SimpleAction::new_stateful("show-window", None, &true.to_variant());
+        let show_sprites_action =
+            gio::SimpleAction::new_stateful("show-sprites", None, &true.to_variant());
+        let config = Config::load();
+        let key_bindings = config.key_bindings();
+        let background_behavior = config.background_behavior();
+        let background_behavior_action = gio::SimpleAction::new_stateful(
+            "background-behavior",
+            Some(&String::static_variant_type()),
+            &background_behavior.name().to_variant(),
+        );
+
         window.set_application(Some(app));
         window.add_action(&open_action);
+        window.add_action(&open_recent_action);
+        window.add_action(&rom_properties_action);
+        window.add_action(&keybindings_action);
+        window.add_action(&turbo_action);
+        window.add_action(&slow_motion_action);
+        window.add_action(&pause_when_idle_action);
+        window.add_action(&background_behavior_action);
+        window.add_action(&show_background_action);
+        window.add_action(&show_window_action);
+        window.add_action(&show_sprites_action);
+        app.set_accels_for_action("win.turbo", &["<Primary>t"]);
+        app.set_accels_for_action("win.slow-motion", &["<Primary>m"]);
 
         let debugger = Rc::new(Debugger {
             emu,
             breakpoint_viewer,
             disassembler,
             emulator_display,
+            event_log,
             memory_viewer,
             playback_controls,
             register_labels,
+            speed_indicator,
             window: window.clone(),
+            config: RefCell::new(config),
+            key_bindings: RefCell::new(key_bindings),
+            turbo_held: Cell::new(false),
+            turbo_enabled: Cell::new(false),
+            slow_motion_enabled: Cell::new(false),
+            background_behavior: Cell::new(background_behavior),
+            render_layers: Cell::new(RenderLayers::default()),
+            exec_mode: RefCell::new(ExecMode::Unloaded),
+            background_paused_mode: RefCell::new(None),
+            recent_roms_menu,
+            rom_properties_action: rom_properties_action.clone(),
+            rom_header: RefCell::new(None),
+            saved_on_close: Cell::new(false),
         });
+        debugger.refresh_recent_roms(&debugger.config.borrow());
+
+        let overclock = debugger.config.borrow().overclock();
+        if overclock > 0 {
+            log::info!("Running {} extra CPU cycles per line", overclock);
+            let emu = debugger.emu.clone();
+            ctx.spawn_local(async move {
+                utils::run_infallible(emu.set_overclock(overclock)).await;
+            });
+        }
 
         open_action.connect_activate(
             clone!(@strong debugger, @strong window, @strong ctx => move |_, _| {
@@ -129,18 +256,308 @@ impl Debugger {
             }),
         );
 
+        open_recent_action.connect_activate(clone!(@weak debugger, @strong ctx => move |_, path| {
+            if let Some(path) = path.and_then(|path| path.str()) {
+                ctx.spawn_local(debugger.clone().load_rom(PathBuf::from(path)));
+            }
+        }));
+
+        rom_properties_action.connect_activate(clone!(@weak debugger, @strong ctx => move |_, _| {
+            ctx.spawn_local(debugger.clone().show_rom_properties());
+        }));
+
+        let uri_target = gtk::TargetEntry::new("text/uri-list", gtk::TargetFlags::OTHER_APP, 0);
+        window.drag_dest_set(gtk::DestDefaults::ALL, &[uri_target], gdk::DragAction::COPY);
+        window.connect_drag_data_received(clone!(@weak debugger => move |_, _, _, _, data, _, _| {
+            match dropped_rom_path(data) {
+                Some(path) => debugger.open_rom(path),
+                None => log::warn!("Ignored drop without a local file: {:?}", data.uris()),
+            }
+        }));
+
+        keybindings_action.connect_activate(clone!(@weak debugger, @strong ctx => move |_, _| {
+            ctx.spawn_local(debugger.clone().configure_key_bindings());
+        }));
+
+        turbo_action.connect_activate(clone!(@weak debugger => move |action, _| {
+            debugger.turbo_enabled.set(toggle_action(action));
+            debugger.apply_speed();
+        }));
+
+        slow_motion_action.connect_activate(clone!(@weak debugger => move |action, _| {
+            debugger.slow_motion_enabled.set(toggle_action(action));
+            debugger.apply_speed();
+        }));
+
+        pause_when_idle_action.connect_activate(clone!(@weak debugger => move |action, _| {
+            debugger.set_pause_when_idle(toggle_action(action));
+        }));
+
+        background_behavior_action.connect_activate(clone!(@weak debugger => move |action, name| {
+            let behavior = name
+                .and_then(|name| name.str())
+                .and_then(BackgroundBehavior::from_name);
+            if let Some(behavior) = behavior {
+                action.set_state(&behavior.name().to_variant());
+                debugger.set_background_behavior(behavior);
+            }
+        }));
+
+        show_background_action.connect_activate(clone!(@weak debugger => move |action, _| {
+            let background = toggle_action(action);
+            debugger.update_render_layers(|layers| layers.background = background);
+        }));
+
+        show_window_action.connect_activate(clone!(@weak debugger => move |action, _| {
+            let window = toggle_action(action);
+            debugger.update_render_layers(|layers| layers.window = window);
+        }));
+
+        show_sprites_action.connect_activate(clone!(@weak debugger => move |action, _| {
+            let sprites = toggle_action(action);
+            debugger.update_render_layers(|layers| layers.sprites = sprites);
+        }));
+
+        debugger
+            .emu
+            .on_widget(debugger.clone(), |debugger, evt: ModeChangeEvent| {
+                debugger.exec_mode.replace(evt.new_mode);
+            });
+        window.connect_is_active_notify(clone!(@weak debugger => move |window| {
+            debugger.focus_changed(window.is_active());
+        }));
+
+        window.connect_key_press_event(
+            clone!(@weak debugger => @default-return Inhibit(false), move |_, evt| {
+                debugger.handle_key(evt, true)
+            }),
+        );
+        window.connect_key_release_event(
+            clone!(@weak debugger => @default-return Inhibit(false), move |_, evt| {
+                debugger.handle_key(evt, false)
+            }),
+        );
+        window.connect_delete_event(
+            clone!(@weak debugger => @default-return Inhibit(false), move |_, _| {
+                debugger.close_requested()
+            }),
+        );
+
         debugger
     }
 
-    async fn load_rom_fs(&self, path: PathBuf) -> Result<(), LoadRomError> {
-        let data = std::fs::read(path).map_err(|err| LoadRomError::Io(format!("{}", err)))?;
-        self.emu.load_rom(data).await
+    /// Save battery RAM before the window closes, as the emulator exits with it
+    fn close_requested(self: &Rc<Self>) -> Inhibit {
+        if self.saved_on_close.get() {
+            return Inhibit(false);
+        }
+        let debugger = self.clone();
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+            if let Err(e) = debugger.emu.save_battery_ram().await {
+                log::debug!("Not saved on close: {}", e);
+            }
+            debugger.saved_on_close.set(true);
+            debugger.window.close();
+        });
+        Inhibit(true)
+    }
+
+    /// Forward key presses for bound keys to the emulated joypad
+    ///
+    /// Holding the turbo key runs at turbo speed, unless it is bound to a button.
+    /// Keys are left alone while a text field has focus, so that addresses
+    /// and values can still be typed into the debugging panels.
+    fn handle_key(&self, evt: &gdk::EventKey, pressed: bool) -> Inhibit {
+        let typing = self
+            .window
+            .focus()
+            .map_or(false, |widget| widget.is::<gtk::Entry>());
+        if typing {
+            return Inhibit(false);
+        }
+        let key_name = match evt.keyval().name() {
+            Some(name) => name,
+            None => return Inhibit(false),
+        };
+        let button = self.key_bindings.borrow().button(key_name.as_str());
+        if button.is_none() && key_name.as_str() == TURBO_KEY {
+            if self.turbo_held.get() != pressed {
+                self.turbo_held.set(pressed);
+                self.apply_speed();
+            }
+            return Inhibit(true);
+        }
+        match button {
+            Some(button) => {
+                let emu = self.emu.clone();
+                glib::MainContext::ref_thread_default().spawn_local(async move {
+                    if let Err(e) = emu.set_button(button, pressed).await {
+                        log::debug!("Ignored input for {}: {}", button, e);
+                    }
+                });
+                Inhibit(true)
+            }
+            None => Inhibit(false),
+        }
+    }
+
+    /// Speed to run at, with turbo taking priority over slow motion
+    fn target_speed(&self) -> u32 {
+        if self.turbo_held.get() || self.turbo_enabled.get() {
+            TURBO_SPEED_PERCENT
+        } else if self.slow_motion_enabled.get() {
+            SLOW_MOTION_SPEED_PERCENT
+        } else {
+            100
+        }
+    }
+
+    fn apply_speed(&self) {
+        let emu = self.emu.clone();
+        let speed = self.target_speed();
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+            utils::run_infallible(emu.set_speed(speed)).await;
+        });
+    }
+
+    /// Pause once the game has been idle for a while, to save battery
+    fn set_pause_when_idle(&self, enabled: bool) {
+        let policy = if enabled {
+            IdlePolicy::after_frames(IDLE_PAUSE_FRAMES).pausing()
+        } else {
+            IdlePolicy::default()
+        };
+        let emu = self.emu.clone();
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+            utils::run_infallible(emu.set_idle_policy(policy)).await;
+        });
+    }
+
+    /// Change which layers are drawn, to help find which layer a glitch comes from
+    fn update_render_layers(&self, update: impl FnOnce(&mut RenderLayers)) {
+        let mut layers = self.render_layers.get();
+        update(&mut layers);
+        self.render_layers.set(layers);
+        let emu = self.emu.clone();
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+            utils::run_infallible(emu.set_render_layers(layers)).await;
+        });
+    }
+
+    fn set_background_behavior(&self, behavior: BackgroundBehavior) {
+        self.background_behavior.set(behavior);
+        let mut config = self.config.borrow_mut();
+        config.set_background_behavior(behavior);
+        if let Err(e) = config.save() {
+            log::error!("Failed to save background behavior: {}", e);
+        }
+    }
+
+    /// Pause when the window loses focus, if configured to, and resume
+    /// when it regains focus
+    ///
+    /// Emulation is only resumed if it is still paused, so pausing or
+    /// running it by hand in the meantime is not overridden.
+    fn focus_changed(&self, active: bool) {
+        let new_mode = if active {
+            match self.background_paused_mode.take() {
+                Some(mode) if *self.exec_mode.borrow() == ExecMode::Paused => mode,
+                _ => return,
+            }
+        } else {
+            let mode = self.exec_mode.borrow().clone();
+            let running = matches!(mode, ExecMode::Standard | ExecMode::Uncapped);
+            if !running || self.background_behavior.get() != BackgroundBehavior::Pause {
+                return;
+            }
+            self.background_paused_mode.replace(Some(mode));
+            ExecMode::Paused
+        };
+        let emu = self.emu.clone();
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+            utils::run_infallible(emu.set_mode(new_mode)).await;
+        });
+    }
+
+    async fn configure_key_bindings(self: Rc<Self>) {
+        let current = self.key_bindings.borrow().clone();
+        let dialog = KeyBindingDialog::new(&self.window, current);
+        if let Some(bindings) = dialog.run().await {
+            let mut config = self.config.borrow_mut();
+            config.set_key_bindings(&bindings);
+            if let Err(e) = config.save() {
+                log::error!("Failed to save key bindings: {}", e);
+            }
+            self.key_bindings.replace(bindings);
+        }
+    }
+
+    async fn show_rom_properties(self: Rc<Self>) {
+        let header = self.rom_header.borrow().clone();
+        if let Some(header) = header {
+            RomPropertiesDialog::new(&self.window, &header).run().await;
+        }
+    }
+
+    /// Rebuild the recent ROMs menu from the config
+    fn refresh_recent_roms(&self, config: &Config) {
+        self.recent_roms_menu.remove_all();
+        for path in config.recent_roms() {
+            let label = match path.file_name() {
+                // Underscores would otherwise be treated as mnemonics
+                Some(name) => name.to_string_lossy().replace('_', "__"),
+                None => continue,
+            };
+            let item = gio::MenuItem::new(Some(&label), None);
+            item.set_action_and_target_value(
+                Some("win.open-recent"),
+                Some(&path.to_string_lossy().to_variant()),
+            );
+            self.recent_roms_menu.append_item(&item);
+        }
+    }
+
+    fn add_recent_rom(&self, path: &Path) {
+        let mut config = self.config.borrow_mut();
+        config.add_recent_rom(path);
+        if let Err(e) = config.save() {
+            log::error!("Failed to save recent ROMs: {}", e);
+        }
+        self.refresh_recent_roms(&config);
+    }
+
+    async fn load_rom_fs(&self, path: &Path) -> Result<CartridgeHeader, LoadRomError> {
+        let data = romfile::read(path).map_err(|err| LoadRomError::Io(format!("{}", err)))?;
+        let header = CartridgeHeader::from_data(&data)?;
+        self.emu.load_rom(data).await?;
+        Ok(header)
+    }
+
+    /// Load a ROM, showing an error dialog if it cannot be loaded
+    pub(crate) fn open_rom(self: &Rc<Self>, path: PathBuf) {
+        glib::MainContext::ref_thread_default().spawn_local(self.clone().load_rom(path));
     }
 
     async fn load_rom(self: Rc<Self>, path: PathBuf) {
-        let res = utils::run_fallible(self.load_rom_fs(path), Some(&self.window)).await;
-        if let Err(e) = res {
-            log::error!("Failed to load rom: {}", e);
+        let res = utils::run_fallible(self.load_rom_fs(&path), Some(&self.window)).await;
+        match res {
+            Ok(header) => {
+                self.rom_header.replace(Some(header));
+                self.rom_properties_action.set_enabled(true);
+                self.add_recent_rom(&path);
+                self.set_save_file(&path).await;
+            }
+            Err(e) => log::error!("Failed to load rom: {}", e),
+        }
+    }
+
+    /// Keep battery RAM next to the ROM, as `<rom name>.sav`
+    async fn set_save_file(&self, rom_path: &Path) {
+        let save_path = rom_path.with_extension("sav").display().to_string();
+        match self.emu.set_save_file(Some(save_path)).await {
+            Ok(resp) if resp.loaded => log::info!("Loaded save from {:?}", resp.path),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to use save file: {}", e),
         }
     }
 
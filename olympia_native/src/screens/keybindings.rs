@@ -0,0 +1,150 @@
+use crate::builder_struct;
+use crate::config::KeyBindings;
+
+use gtk::gdk;
+use gtk::glib;
+use gtk::glib::clone;
+use gtk::prelude::*;
+use gtk::Inhibit;
+use olympia_engine::gameboy::Button;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+const UNBOUND_LABEL: &str = "(None)";
+const WAITING_LABEL: &str = "Press a key...";
+
+builder_struct!(
+    pub(crate) struct KeyBindingDialogWidget {
+        #[ogtk(id = "KeyBindingDialog")]
+        dialog: gtk::Dialog,
+        #[ogtk(id = "KeyBindingGrid")]
+        grid: gtk::Grid,
+    }
+);
+
+/// Dialog to choose which keyboard keys trigger each joypad button
+pub(crate) struct KeyBindingDialog {
+    widget: KeyBindingDialogWidget,
+    bindings: RefCell<KeyBindings>,
+    key_buttons: Vec<(Button, gtk::Button)>,
+    /// The joypad button waiting for a key to be pressed, if any
+    listening: Cell<Option<Button>>,
+}
+
+impl KeyBindingDialog {
+    pub(crate) fn new(
+        parent: &gtk::ApplicationWindow,
+        bindings: KeyBindings,
+    ) -> Rc<KeyBindingDialog> {
+        let builder = gtk::Builder::from_string(include_str!("../../res/keybindings.ui"));
+        let widget = KeyBindingDialogWidget::from_builder(&builder).unwrap();
+        widget.dialog.set_transient_for(Some(parent));
+
+        let key_buttons = Button::ALL
+            .iter()
+            .enumerate()
+            .map(|(row, button)| {
+                let label = gtk::Label::new(Some(&button.to_string()));
+                label.set_halign(gtk::Align::Start);
+                let key_button = gtk::Button::new();
+                widget.grid.attach(&label, 0, row as i32, 1, 1);
+                widget.grid.attach(&key_button, 1, row as i32, 1, 1);
+                (*button, key_button)
+            })
+            .collect();
+
+        let dialog = Rc::new(KeyBindingDialog {
+            widget,
+            bindings: RefCell::new(bindings),
+            key_buttons,
+            listening: Cell::new(None),
+        });
+        dialog.refresh_labels();
+        dialog.connect_ui_events();
+        dialog
+    }
+
+    fn refresh_labels(&self) {
+        let bindings = self.bindings.borrow();
+        for (button, key_button) in self.key_buttons.iter() {
+            let label = if self.listening.get() == Some(*button) {
+                WAITING_LABEL
+            } else {
+                bindings.key(*button).unwrap_or(UNBOUND_LABEL)
+            };
+            key_button.set_label(label);
+        }
+    }
+
+    fn key_pressed(&self, key: &gdk::keys::Key) -> Inhibit {
+        match (self.listening.take(), key.name()) {
+            (Some(button), Some(name)) => {
+                self.bindings.borrow_mut().set_key(button, name.as_str());
+                self.refresh_labels();
+                Inhibit(true)
+            }
+            _ => {
+                self.refresh_labels();
+                Inhibit(false)
+            }
+        }
+    }
+
+    fn connect_ui_events(self: &Rc<Self>) {
+        for (button, key_button) in self.key_buttons.iter() {
+            let button = *button;
+            key_button.connect_clicked(clone!(@weak self as dialog => move |_| {
+                dialog.listening.set(Some(button));
+                dialog.refresh_labels();
+            }));
+        }
+
+        self.widget.dialog.connect_key_press_event(
+            clone!(@weak self as dialog => @default-return Inhibit(false), move |_, evt| {
+                dialog.key_pressed(&evt.keyval())
+            }),
+        );
+    }
+
+    /// Show the dialog, returning the new bindings if they were saved
+    pub(crate) async fn run(&self) -> Option<KeyBindings> {
+        self.widget.dialog.show_all();
+        let response = self.widget.dialog.run_future().await;
+        self.widget.dialog.close();
+        if response == gtk::ResponseType::Accept {
+            Some(self.bindings.borrow().clone())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils;
+
+    #[test]
+    fn gtk_test_rebind_button() {
+        test_utils::with_gtk_lock(|| {
+            let window = gtk::ApplicationWindow::builder().build();
+            let dialog = KeyBindingDialog::new(&window, KeyBindings::default());
+            let (_, start_button) = dialog
+                .key_buttons
+                .iter()
+                .find(|(button, _)| *button == Button::Start)
+                .unwrap();
+            assert_eq!(start_button.label().unwrap().as_str(), "Return");
+
+            start_button.clicked();
+            assert_eq!(start_button.label().unwrap().as_str(), WAITING_LABEL);
+            dialog.key_pressed(&gdk::keys::Key::from_name("space"));
+
+            assert_eq!(start_button.label().unwrap().as_str(), "space");
+            assert_eq!(
+                dialog.bindings.borrow().button("space"),
+                Some(Button::Start)
+            );
+        });
+    }
+}
@@ -0,0 +1,115 @@
+use crate::builder_struct;
+
+use gtk::prelude::*;
+use olympia_engine::rom::CartridgeHeader;
+
+builder_struct!(
+    pub(crate) struct RomPropertiesDialogWidget {
+        #[ogtk(id = "RomPropertiesDialog")]
+        dialog: gtk::Dialog,
+        #[ogtk(id = "RomPropertiesGrid")]
+        grid: gtk::Grid,
+    }
+);
+
+fn format_size(bytes: usize) -> String {
+    if bytes == 0 {
+        String::from("None")
+    } else {
+        format!("{} KiB", bytes / 1024)
+    }
+}
+
+/// Property names and values shown for a cartridge header
+fn header_properties(header: &CartridgeHeader) -> Vec<(&'static str, String)> {
+    let mut properties = vec![
+        ("Title", header.title.clone()),
+        (
+            "Cartridge type",
+            format!("{} (0x{:02X})", header.controller, header.cartridge_type),
+        ),
+        ("ROM size", format_size(header.rom_size)),
+        ("RAM size", format_size(header.ram_size)),
+        ("Console", header.target.to_string()),
+    ];
+    if !header.warnings.is_empty() {
+        let warnings: Vec<String> = header.warnings.iter().map(ToString::to_string).collect();
+        properties.push(("Warnings", warnings.join("\n")));
+    }
+    properties
+}
+
+/// Dialog showing the cartridge header of the loaded ROM
+pub(crate) struct RomPropertiesDialog {
+    widget: RomPropertiesDialogWidget,
+}
+
+impl RomPropertiesDialog {
+    pub(crate) fn new(
+        parent: &gtk::ApplicationWindow,
+        header: &CartridgeHeader,
+    ) -> RomPropertiesDialog {
+        let builder = gtk::Builder::from_string(include_str!("../../res/rom_properties.ui"));
+        let widget = RomPropertiesDialogWidget::from_builder(&builder).unwrap();
+        widget.dialog.set_transient_for(Some(parent));
+
+        for (row, (name, value)) in header_properties(header).iter().enumerate() {
+            let name_label = gtk::Label::new(Some(name));
+            name_label.set_halign(gtk::Align::Start);
+            let value_label = gtk::Label::new(Some(value));
+            value_label.set_halign(gtk::Align::Start);
+            value_label.set_selectable(true);
+            widget.grid.attach(&name_label, 0, row as i32, 1, 1);
+            widget.grid.attach(&value_label, 1, row as i32, 1, 1);
+        }
+
+        RomPropertiesDialog { widget }
+    }
+
+    pub(crate) async fn run(&self) {
+        self.widget.dialog.show_all();
+        self.widget.dialog.run_future().await;
+        self.widget.dialog.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils;
+
+    #[test]
+    fn test_header_properties() {
+        let header = CartridgeHeader::from_data(&test_utils::fizzbuzz_rom()).unwrap();
+        let properties = header_properties(&header);
+
+        assert_eq!(properties[0], ("Title", String::from("FIZZBUZZ")));
+        assert_eq!(
+            properties[1],
+            ("Cartridge type", String::from("ROM only (0x00)"))
+        );
+        assert_eq!(properties[2], ("ROM size", String::from("32 KiB")));
+        assert_eq!(properties[3], ("RAM size", String::from("None")));
+        assert_eq!(properties[4], ("Console", String::from("Game Boy")));
+        assert_eq!(properties.len(), 5);
+    }
+
+    #[test]
+    fn test_header_warnings() {
+        let mut data = test_utils::fizzbuzz_rom();
+        data[0x149] = 2;
+        let header = CartridgeHeader::from_data(&data).unwrap();
+        let properties = header_properties(&header);
+
+        assert_eq!(properties[3], ("RAM size", String::from("None")));
+        assert_eq!(
+            properties[5],
+            (
+                "Warnings",
+                String::from(
+                    "Cartridge type 0x00 has no external RAM, but the header declares 8 KiB. Ignoring it"
+                )
+            )
+        );
+    }
+}
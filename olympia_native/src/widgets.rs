@@ -3,18 +3,22 @@ mod breakpoint_viewer;
 pub(crate) mod common;
 mod disassembly_viewer;
 mod emulator_display;
+mod event_log;
 mod memory_viewer;
 mod playback_controls;
 mod register_labels;
+mod speed_indicator;
 mod tileset_viewer;
 
 pub(crate) use address_picker::AddressPicker;
 pub(crate) use breakpoint_viewer::BreakpointViewer;
 pub(crate) use disassembly_viewer::Disassembler;
 pub(crate) use emulator_display::EmulatorDisplay;
+pub(crate) use event_log::EventLog;
 pub(crate) use memory_viewer::MemoryViewer;
 pub(crate) use playback_controls::PlaybackControls;
 pub(crate) use register_labels::RegisterLabels;
+pub(crate) use speed_indicator::SpeedIndicator;
 pub(crate) use tileset_viewer::TilesetViewer;
 
 use gtk::prelude::StaticType;
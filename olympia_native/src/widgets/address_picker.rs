@@ -1,4 +1,7 @@
-use olympia_engine::{monitor::parse_number, registers::WordRegister};
+use olympia_engine::{
+    monitor::{AddressSpec, RWTarget, SymbolTable},
+    registers::WordRegister,
+};
 
 use gtk::glib::{
     self, clone,
@@ -24,6 +27,16 @@ use crate::{
 
 use super::common::EmulatorWidget;
 
+/// Parse an address typed into the entry, such as `0x150`, `3:4123` or `0x8000 + 0x10`
+fn parse_address(text: &str) -> Option<u16> {
+    let spec = text.parse::<AddressSpec>().ok()?;
+    match spec.resolve(None, &SymbolTable::new()).ok()? {
+        RWTarget::Address(addr) => Some(addr.0),
+        RWTarget::BankedAddress(addr) => Some(addr.addr.0),
+        _ => None,
+    }
+}
+
 #[derive(CompositeTemplate, Default)]
 #[template(file = "../../res/address_picker.ui")]
 pub struct AddressPickerInternal {
@@ -134,7 +147,7 @@ impl ObjectImpl for AddressPickerInternal {
                 let widget = Self::from_instance(&obj);
                 let text = widget.address_entry.text();
 
-                if let Ok(addr) = parse_number(&text) {
+                if let Some(addr) = parse_address(&text) {
                     widget.address_selected.store(addr, Ordering::Relaxed);
                     obj.notify(ADDRESS_PROPERTY);
                     widget.go_button.get().set_sensitive(true);
@@ -172,7 +185,7 @@ impl ObjectImpl for AddressPickerInternal {
             }
             ADDRESS_PROPERTY => {
                 let address: &str = value.unwrap();
-                if let Ok(numeric) = parse_number(address) {
+                if let Some(numeric) = parse_address(address) {
                     self.address_selected.store(numeric, Ordering::Relaxed);
                     self.go_button.get().set_sensitive(true);
                 } else {
@@ -5,9 +5,11 @@ use derive_more::{Display, Error, From, Into};
 use gtk::glib;
 use gtk::glib::{clone, GBoxed};
 use gtk::prelude::*;
+use olympia_engine::events::BreakpointsChangedEvent;
 use olympia_engine::monitor::BreakpointCondition;
 use olympia_engine::monitor::BreakpointIdentifier;
 use olympia_engine::monitor::Comparison;
+use olympia_engine::monitor::UnsupportedBreakpoint;
 use olympia_engine::{
     monitor::{Breakpoint, RWTarget},
     remote::RemoteEmulator,
@@ -44,6 +46,8 @@ pub enum BreakpointParseError {
     Value(#[error(not(source))] String),
     #[display(fmt = "Invalid target {0:?} and invalid value {1:?}", _0, _1)]
     TargetAndValue(String, String),
+    #[display(fmt = "{}", _0)]
+    Unsupported(UnsupportedBreakpoint),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, GBoxed, From, Into)]
@@ -69,6 +73,7 @@ impl BreakpointViewer {
         });
 
         bpv.connect_ui_events();
+        bpv.connect_adapter_events();
         bpv
     }
 
@@ -96,23 +101,6 @@ impl BreakpointViewer {
             .map(|x| self.widget.store.value(&x, column_index))
     }
 
-    fn set_tree_value<T: ToValue>(
-        self: &Rc<Self>,
-        path: &gtk::TreePath,
-        column_index: i32,
-        value: T,
-    ) {
-        let iter = self.widget.store.iter(path);
-
-        let iter = match iter {
-            Some(x) => x,
-            _ => return,
-        };
-        self.widget
-            .store
-            .set_value(&iter, column_index as u32, &value.to_value());
-    }
-
     fn bp_active_toggled(self: &Rc<Self>, path: gtk::TreePath) {
         self.context
             .spawn_local(self.clone().toggle_breakpoint(path));
@@ -129,13 +117,34 @@ impl BreakpointViewer {
             .unwrap_or_default();
         let new_state = !previous_state;
         let result = self.emu.set_breakpoint_state(id.into(), new_state).await;
-        if let Ok(resp) = result {
+        if result.is_ok() {
             log::debug!(
                 "Toggled breakpoint from {} to {}",
                 previous_state,
                 new_state
             );
-            self.set_tree_value(&path, ACTIVE_COLUMN_INDEX, resp.new_state);
+        }
+    }
+
+    /// Replace the displayed breakpoints with those held by the emulator
+    fn show_breakpoints(&self, breakpoints: &[(BreakpointIdentifier, Breakpoint)]) {
+        self.widget.store.clear();
+        for (id, breakpoint) in breakpoints {
+            self.widget.store.insert_with_values(
+                None,
+                &[
+                    (ACTIVE_COLUMN_INDEX as u32, &breakpoint.active),
+                    (
+                        MONITOR_COLUMN_INDEX as u32,
+                        &format!("{}", breakpoint.monitor),
+                    ),
+                    (
+                        CONDITION_COLUMN_INDEX as u32,
+                        &format!("{}", breakpoint.condition),
+                    ),
+                    (ID_COLUMN_INDEX as u32, &u32::from(*id)),
+                ],
+            );
         }
     }
 
@@ -144,11 +153,21 @@ impl BreakpointViewer {
 
         if let Some(active_id) = id {
             log::debug!("Condition changed: {}", active_id);
-            let has_value = !(active_id == "Read" || active_id == "Write");
+            let has_value = !(active_id == "Read"
+                || active_id == "Write"
+                || active_id == "Execute"
+                || active_id == "Changed");
             self.widget.value_input.set_visible(has_value);
         }
     }
 
+    fn connect_adapter_events(self: &Rc<Self>) {
+        self.emu
+            .on_widget(self.clone(), |bpv, evt: BreakpointsChangedEvent| {
+                bpv.show_breakpoints(&evt.breakpoints);
+            });
+    }
+
     pub fn connect_ui_events(self: &Rc<Self>) {
         self.widget
             .add_button
@@ -196,6 +215,10 @@ impl BreakpointViewer {
                         Some(BreakpointCondition::Read)
                     } else if id == "Write" {
                         Some(BreakpointCondition::Write)
+                    } else if id == "Execute" {
+                        Some(BreakpointCondition::Execute)
+                    } else if id == "Changed" {
+                        Some(BreakpointCondition::Changed)
                     } else {
                         None
                     }
@@ -203,7 +226,13 @@ impl BreakpointViewer {
             }
         });
         match (target, condition) {
-            (Some(t), Some(c)) => Ok(Breakpoint::new(t, c)),
+            (Some(t), Some(c)) => {
+                let breakpoint = Breakpoint::new(t, c);
+                breakpoint
+                    .validate()
+                    .map_err(BreakpointParseError::Unsupported)?;
+                Ok(breakpoint)
+            }
             (None, Some(_cond)) => Err(BreakpointParseError::Target(target_text)),
             (Some(_target), None) => Err(BreakpointParseError::Value(value_text)),
             (None, None) => Err(BreakpointParseError::TargetAndValue(
@@ -215,21 +244,7 @@ impl BreakpointViewer {
 
     async fn add_parsed_breakpoint(&self, breakpoint: &Breakpoint) {
         let resp = utils::run_infallible(self.emu.add_breakpoint(breakpoint.clone())).await;
-        self.widget.store.insert_with_values(
-            None,
-            &[
-                (ACTIVE_COLUMN_INDEX as u32, &breakpoint.active),
-                (
-                    MONITOR_COLUMN_INDEX as u32,
-                    &format!("{}", breakpoint.monitor),
-                ),
-                (
-                    CONDITION_COLUMN_INDEX as u32,
-                    &format!("{}", breakpoint.condition),
-                ),
-                (ID_COLUMN_INDEX as u32, &u32::from(resp.id)),
-            ],
-        );
+        log::debug!("Added breakpoint {:?}", resp.id);
     }
 
     async fn add_breakpoint(self: Rc<Self>) {
@@ -358,4 +373,24 @@ mod tests {
             assert!(!active);
         });
     }
+
+    #[test]
+    fn test_parse_indirect_read_breakpoint() {
+        test_utils::with_loaded_emu(|context, emu| {
+            let builder = gtk::Builder::from_string(include_str!("../../res/breakpoints.ui"));
+            let component = BreakpointViewer::from_builder(&builder, context, emu);
+            component.widget.monitor_input.set_text("[HL]");
+            component
+                .widget
+                .condition_picker
+                .set_active_id(Some("Read"));
+
+            let err = component.parse_breakpoint().unwrap_err();
+            assert!(matches!(err, BreakpointParseError::Unsupported(_)));
+            assert_eq!(
+                err.to_string(),
+                "Cannot break on Read for memory location [HL]"
+            );
+        });
+    }
 }
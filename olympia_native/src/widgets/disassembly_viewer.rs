@@ -1,6 +1,6 @@
 use super::common::EMU_PROPERTY;
 use crate::subclass_widget;
-use crate::utils::{EmulatorHandle, GValueExt};
+use crate::utils::{self, EmulatorHandle, GValueExt};
 use crate::widgets::common::{emu_param_spec, EmulatorWidget};
 use crate::widgets::AddressPicker;
 
@@ -11,8 +11,20 @@ use gtk::{
     prelude::*,
     TextBufferBuilder,
 };
-use olympia_engine::disassembler::{DisassemblyFormat, DisassemblyIterator};
+use olympia_engine::{
+    address::LiteralAddress,
+    disassembler::DisassemblyLine,
+    events::{BreakpointsChangedEvent, ManualStepEvent, ModeChangeEvent, Repeat, RomLoadedEvent},
+    monitor::{Breakpoint, BreakpointCondition, BreakpointIdentifier, RWTarget},
+    remote::{Event as RemoteEvent, ExecMode},
+};
 use std::cell::RefCell;
+use std::convert::TryFrom;
+
+/// Number of instructions disassembled when moving to a new address
+const WINDOW_SIZE: u16 = 0x100;
+/// Reload the window when PC is within this many instructions of the end
+const FOLLOW_MARGIN: usize = 16;
 
 #[derive(CompositeTemplate, Default)]
 #[template(file = "../../res/disassembly.ui")]
@@ -22,6 +34,8 @@ pub struct DisassemblerInternal {
     #[template_child(id = "DisassemblyAddressPicker")]
     address_picker: TemplateChild<AddressPicker>,
     emu: RefCell<Option<EmulatorHandle>>,
+    instructions: RefCell<Vec<DisassemblyLine>>,
+    breakpoints: RefCell<Vec<(BreakpointIdentifier, Breakpoint)>>,
 }
 
 subclass_widget!(DisassemblerInternal, gtk::Box, Disassembler);
@@ -33,6 +47,22 @@ impl ObjectImpl for DisassemblerInternal {
             .build();
 
         self.text_view.set_monospace(true);
+        self.text_view.connect_button_press_event(
+            glib::clone!(@weak obj => @default-return Inhibit(false), move |text_view, evt| {
+                if evt.button() == 1 {
+                    let (x, y) = evt.position();
+                    let (x, y) = text_view.window_to_buffer_coords(
+                        gtk::TextWindowType::Widget,
+                        x as i32,
+                        y as i32,
+                    );
+                    if let Some(iter) = text_view.iter_at_location(x, y) {
+                        obj.line_clicked(iter.line());
+                    }
+                }
+                Inhibit(false)
+            }),
+        );
 
         let obj = obj.clone();
         self.address_picker
@@ -46,7 +76,7 @@ impl ObjectImpl for DisassemblerInternal {
 
     fn set_property(
         &self,
-        _obj: &Self::Type,
+        obj: &Self::Type,
         _id: usize,
         value: &glib::Value,
         pspec: &glib::ParamSpec,
@@ -54,6 +84,7 @@ impl ObjectImpl for DisassemblerInternal {
         match pspec.name() {
             EMU_PROPERTY => {
                 self.emu.replace(Some(value.unwrap()));
+                obj.connect_emu_events();
             }
             _ => unimplemented!(),
         }
@@ -84,6 +115,23 @@ glib::wrapper! {
         @implements gtk::Buildable, gtk::Orientable;
 }
 
+fn execute_breakpoint_at(
+    breakpoints: &[(BreakpointIdentifier, Breakpoint)],
+    address: u16,
+) -> Option<&(BreakpointIdentifier, Breakpoint)> {
+    breakpoints.iter().find(|(_, bp)| {
+        bp.condition == BreakpointCondition::Execute
+            && bp.monitor == RWTarget::Address(LiteralAddress(address))
+    })
+}
+
+fn is_stopped(mode: &ExecMode) -> bool {
+    matches!(
+        mode,
+        ExecMode::Paused | ExecMode::HitBreakpoint(_) | ExecMode::Error(_)
+    )
+}
+
 impl Disassembler {
     pub fn goto_address(&self, address: u16) {
         glib::MainContext::ref_thread_default()
@@ -91,21 +139,150 @@ impl Disassembler {
     }
 
     async fn goto_address_internal(self, address: u16) {
+        self.load_window(address).await;
+    }
+
+    /// Show the instructions starting at `address`
+    async fn load_window(&self, address: u16) {
         let emu = self.emu_handle();
 
-        let query_response = emu.query_memory(address, address.saturating_add(600)).await;
-
-        if let Ok(memory_region) = query_response {
-            let data = memory_region.data.iter().map(|b| b.unwrap_or(0));
-            let disasm_iter =
-                DisassemblyIterator::new(data, DisassemblyFormat::Columnar, address.into());
-            let lines: Vec<String> = disasm_iter.take(200).collect();
-            let disasm: String = lines.join("\n");
-            let buffer = TextBufferBuilder::new().text(&disasm).build();
-            let tv: gtk::TextView = DisassemblerInternal::from_instance(&self).text_view.get();
-            tv.set_buffer(Some(&buffer));
+        let query_response = emu.query_disassembly(address, WINDOW_SIZE).await;
+
+        if let Ok(disassembly) = query_response {
+            DisassemblerInternal::from_instance(self)
+                .instructions
+                .replace(disassembly.instructions);
+            self.render();
         }
     }
+
+    fn follow_pc(&self) {
+        glib::MainContext::ref_thread_default().spawn_local(self.clone().follow_pc_internal());
+    }
+
+    /// Move the window to the current PC if it is not already visible
+    async fn follow_pc_internal(self) {
+        let emu = self.emu_handle();
+        let pc = match emu.query_registers().await {
+            Ok(registers) => registers.pc,
+            Err(_) => return,
+        };
+        let internal = DisassemblerInternal::from_instance(&self);
+        let visible = {
+            let mut instructions = internal.instructions.borrow_mut();
+            for instr in instructions.iter_mut() {
+                instr.is_current_pc = instr.address == pc;
+            }
+            instructions
+                .iter()
+                .position(|instr| instr.is_current_pc)
+                .map_or(false, |idx| idx + FOLLOW_MARGIN < instructions.len())
+        };
+        if visible {
+            self.render();
+        } else {
+            self.load_window(pc).await;
+        }
+    }
+
+    fn render(&self) {
+        let internal = DisassemblerInternal::from_instance(self);
+        let breakpoints = internal.breakpoints.borrow();
+        let instructions = internal.instructions.borrow();
+        let lines: Vec<String> = instructions
+            .iter()
+            .map(|instr| {
+                let bp_marker = match execute_breakpoint_at(&breakpoints, instr.address) {
+                    Some((_, bp)) if bp.active => "●",
+                    Some(_) => "○",
+                    None => " ",
+                };
+                let pc_marker = if instr.is_current_pc { "▶" } else { " " };
+                let numeric: String = instr.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                format!(
+                    "{}{} {:04X}:{:>8}    {}",
+                    bp_marker, pc_marker, instr.address, numeric, instr.text
+                )
+            })
+            .collect();
+        let buffer = TextBufferBuilder::new().text(&lines.join("\n")).build();
+        internal.text_view.set_buffer(Some(&buffer));
+
+        let pc_line = instructions.iter().position(|i| i.is_current_pc);
+        if let Some(line) = pc_line {
+            let mut iter = buffer.iter_at_line(line as i32);
+            internal
+                .text_view
+                .scroll_to_iter(&mut iter, 0.0, true, 0.0, 0.3);
+        }
+    }
+
+    fn line_clicked(&self, line: i32) {
+        let address = DisassemblerInternal::from_instance(self)
+            .instructions
+            .borrow()
+            .get(line as usize)
+            .map(|instr| instr.address);
+        if let Some(address) = address {
+            glib::MainContext::ref_thread_default()
+                .spawn_local(self.clone().toggle_breakpoint(address));
+        }
+    }
+
+    /// Add an execution breakpoint at `address`, or remove it if one exists
+    async fn toggle_breakpoint(self, address: u16) {
+        let emu = self.emu_handle();
+        let existing = execute_breakpoint_at(
+            &DisassemblerInternal::from_instance(&self)
+                .breakpoints
+                .borrow(),
+            address,
+        )
+        .map(|(id, _)| *id);
+        match existing {
+            Some(id) => {
+                utils::run_infallible(emu.remove_breakpoint(id)).await;
+            }
+            None => {
+                let breakpoint = Breakpoint::new(
+                    RWTarget::Address(LiteralAddress(address)),
+                    BreakpointCondition::Execute,
+                );
+                utils::run_infallible(emu.add_breakpoint(breakpoint)).await;
+            }
+        }
+    }
+
+    fn on_event<E, F>(&self, f: F)
+    where
+        E: TryFrom<RemoteEvent> + 'static,
+        F: Fn(&Disassembler, E) + 'static,
+    {
+        let weak = self.downgrade();
+        self.emu_handle().on(move |evt| match weak.upgrade() {
+            Some(disassembler) => {
+                f(&disassembler, evt);
+                Repeat(true)
+            }
+            None => Repeat(false),
+        });
+    }
+
+    fn connect_emu_events(&self) {
+        self.on_event(|disassembler, _: ManualStepEvent| disassembler.follow_pc());
+        self.on_event(|disassembler, _: RomLoadedEvent| disassembler.follow_pc());
+        self.on_event(|disassembler, evt: ModeChangeEvent| {
+            if is_stopped(&evt.new_mode) {
+                disassembler.follow_pc();
+            }
+        });
+        self.on_event(|disassembler, evt: BreakpointsChangedEvent| {
+            DisassemblerInternal::from_instance(disassembler)
+                .breakpoints
+                .replace(evt.breakpoints);
+            disassembler.render();
+        });
+    }
 }
 
 impl EmulatorWidget for Disassembler {}
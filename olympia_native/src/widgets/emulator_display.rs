@@ -6,7 +6,7 @@ use gtk::glib::clone;
 use gtk::prelude::*;
 use log::trace;
 use olympia_engine::{
-    events::{HBlankEvent, VBlankEvent},
+    events::{HBlankEvent, LcdPowerEvent, VBlankEvent},
     gameboy::GBPixel,
     remote::RemoteEmulator,
 };
@@ -48,7 +48,7 @@ impl GBDisplayBuffer {
     }
 
     pub(crate) fn draw_pixel(&mut self, gb_x: usize, gb_y: usize, pixel: &GBPixel) {
-        let color = COLORS[usize::from(pixel.index)];
+        let color = COLORS[usize::from(pixel.color)];
         if gb_x >= self.width {
             panic!("X co-ord too large {}", gb_x);
         }
@@ -82,7 +82,7 @@ impl GBDisplayBuffer {
                 .unwrap(),
         );
         if let Err(e) = result {
-            log::error!(target: "emulator_display", "Image surface build error: {}", e);
+            log::error!("Image surface build error: {}", e);
         }
 
         result
@@ -94,6 +94,17 @@ impl GBDisplayBuffer {
         }
     }
 
+    /// Show a blank screen, as when the LCD is off
+    pub(crate) fn blank(&mut self) {
+        let blank_line = vec![GBPixel::default(); self.width];
+        for y in 0..self.height {
+            for (x, pixel) in blank_line.iter().enumerate() {
+                self.draw_pixel(x, y, pixel);
+            }
+        }
+        self.swap_buffers();
+    }
+
     pub(crate) fn swap_buffers(&mut self) {
         std::mem::swap(&mut self.front, &mut self.back);
         std::mem::swap(&mut self.front_pixels, &mut self.back_pixels);
@@ -161,6 +172,17 @@ impl EmulatorDisplay {
 
     pub(crate) fn vblank(&self) {
         self.buffer.borrow_mut().swap_buffers();
+        self.queue_draw();
+    }
+
+    pub(crate) fn lcd_power(&self, evt: LcdPowerEvent) {
+        if !evt.enabled {
+            self.buffer.borrow_mut().blank();
+            self.queue_draw();
+        }
+    }
+
+    fn queue_draw(&self) {
         let scale = self.buffer.borrow().scale;
         self.widget.drawing_area.queue_draw_area(
             0,
@@ -178,6 +200,10 @@ impl EmulatorDisplay {
             .on_widget(self.clone(), |display, evt: HBlankEvent| {
                 display.hblank(evt);
             });
+        self.emu
+            .on_widget(self.clone(), |display, evt: LcdPowerEvent| {
+                display.lcd_power(evt);
+            });
     }
 
     pub(crate) fn from_builder(
@@ -210,6 +236,21 @@ mod tests {
         surface.data().map(|data| Vec::from(&data[idx..idx + bpp]))
     }
 
+    #[test]
+    fn test_blank_buffer() {
+        let mut buffer = GBDisplayBuffer::new(2, 2, 1);
+        buffer.render_line(0, &[bg_pixel(3), bg_pixel(3)]);
+        buffer.render_line(1, &[bg_pixel(3), bg_pixel(3)]);
+        buffer.swap_buffers();
+        buffer.blank();
+
+        let surface = buffer.image_surface.as_mut().unwrap();
+        let (r, g, b) = COLORS[0];
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)].iter() {
+            assert_eq!(pixel_data_at(surface, *x, *y).unwrap(), vec![r, g, b, 0]);
+        }
+    }
+
     #[test]
     fn test_render_buffer() {
         let mut buffer = GBDisplayBuffer::new(4, 4, 2);
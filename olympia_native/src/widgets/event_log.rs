@@ -0,0 +1,354 @@
+use crate::{builder_struct, utils};
+use gtk::glib::{self, clone};
+use gtk::prelude::*;
+use log::LevelFilter;
+use olympia_engine::{
+    events::{BankSwitchEvent, InterruptEvent, LogEvent, MemoryEvent, ModeChangeEvent, PrintEvent},
+    logging,
+    remote::{ExecMode, RemoteEmulator},
+};
+use std::rc::Rc;
+use std::str::FromStr;
+
+const CATEGORY_COLUMN_INDEX: u32 = 0;
+const MESSAGE_COLUMN_INDEX: u32 = 1;
+/// Most entries kept before the oldest are dropped
+const MAX_ENTRIES: i32 = 1000;
+
+const DMA_ADDR: u16 = 0xFF46;
+const HDMA_CONTROL_ADDR: u16 = 0xFF55;
+const SERIAL_CONTROL_ADDR: u16 = 0xFF02;
+/// Bit of the serial control register that starts a transfer
+const SERIAL_TRANSFER_START: u8 = 0x80;
+
+builder_struct!(
+    pub(crate) struct EventLogWidget {
+        #[ogtk(id = "EventLogStore")]
+        store: gtk::ListStore,
+        #[ogtk(id = "EventLogFilter")]
+        filter: gtk::TreeModelFilter,
+        #[ogtk(id = "EventLogModeToggle")]
+        mode_toggle: gtk::CheckButton,
+        #[ogtk(id = "EventLogBanksToggle")]
+        banks_toggle: gtk::CheckButton,
+        #[ogtk(id = "EventLogInterruptsToggle")]
+        interrupts_toggle: gtk::CheckButton,
+        #[ogtk(id = "EventLogDmaToggle")]
+        dma_toggle: gtk::CheckButton,
+        #[ogtk(id = "EventLogSerialToggle")]
+        serial_toggle: gtk::CheckButton,
+        #[ogtk(id = "EventLogLogToggle")]
+        log_toggle: gtk::CheckButton,
+        #[ogtk(id = "EventLogLevel")]
+        log_level: gtk::ComboBoxText,
+        #[ogtk(id = "EventLogClear")]
+        clear_button: gtk::Button,
+    }
+);
+
+/// Kinds of event that can be shown or hidden in the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventCategory {
+    Mode,
+    Bank,
+    Interrupt,
+    Dma,
+    Serial,
+    /// Messages logged by the engine and frontend
+    Log,
+}
+
+impl EventCategory {
+    const ALL: [EventCategory; 6] = [
+        EventCategory::Mode,
+        EventCategory::Bank,
+        EventCategory::Interrupt,
+        EventCategory::Dma,
+        EventCategory::Serial,
+        EventCategory::Log,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            EventCategory::Mode => "Mode",
+            EventCategory::Bank => "Bank",
+            EventCategory::Interrupt => "Interrupt",
+            EventCategory::Dma => "DMA",
+            EventCategory::Serial => "Serial",
+            EventCategory::Log => "Log",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<EventCategory> {
+        EventCategory::ALL
+            .iter()
+            .copied()
+            .find(|category| category.name() == name)
+    }
+}
+
+impl EventLogWidget {
+    fn toggle(&self, category: EventCategory) -> &gtk::CheckButton {
+        match category {
+            EventCategory::Mode => &self.mode_toggle,
+            EventCategory::Bank => &self.banks_toggle,
+            EventCategory::Interrupt => &self.interrupts_toggle,
+            EventCategory::Dma => &self.dma_toggle,
+            EventCategory::Serial => &self.serial_toggle,
+            EventCategory::Log => &self.log_toggle,
+        }
+    }
+}
+
+fn mode_name(mode: &ExecMode) -> String {
+    match mode {
+        ExecMode::Unloaded => String::from("Unloaded"),
+        ExecMode::Paused => String::from("Paused"),
+        ExecMode::HitBreakpoint(_) => String::from("Hit breakpoint"),
+        ExecMode::Standard => String::from("Running"),
+        ExecMode::Uncapped => String::from("Running uncapped"),
+        ExecMode::Error(e) => format!("Error: {}", e),
+    }
+}
+
+/// Describe writes which start a DMA or serial transfer
+fn describe_write(evt: MemoryEvent) -> Option<(EventCategory, String)> {
+    let (address, value) = match evt {
+        MemoryEvent::Write { address, value, .. } => (address.0, value),
+        MemoryEvent::Read { .. } | MemoryEvent::BlockedWrite { .. } => return None,
+    };
+    match address {
+        DMA_ADDR => Some((EventCategory::Dma, format!("OAM DMA from {:02X}00h", value))),
+        HDMA_CONTROL_ADDR => Some((
+            EventCategory::Dma,
+            format!(
+                "VRAM DMA of {} bytes",
+                (usize::from(value & 0x7F) + 1) * 0x10
+            ),
+        )),
+        SERIAL_CONTROL_ADDR if value & SERIAL_TRANSFER_START != 0 => Some((
+            EventCategory::Serial,
+            String::from("Serial transfer started"),
+        )),
+        _ => None,
+    }
+}
+
+fn describe_log(evt: &LogEvent) -> String {
+    format!("{} {}: {}", evt.level, evt.target, evt.message)
+}
+
+/// A log of recent emulator events, which can be filtered by category
+///
+/// Newest events are shown first, and only the most recent 1000 are kept.
+/// Log messages are streamed from the emulator while the Log category is shown.
+pub(crate) struct EventLog {
+    emu: Rc<RemoteEmulator>,
+    widget: EventLogWidget,
+}
+
+impl EventLog {
+    pub(crate) fn from_widget(emu: Rc<RemoteEmulator>, widget: EventLogWidget) -> Rc<EventLog> {
+        let log = Rc::new(EventLog { emu, widget });
+        log.connect_ui_events();
+        log.connect_adapter_events(&log.emu);
+        log.stream_logs();
+        log
+    }
+
+    pub(crate) fn from_builder(builder: &gtk::Builder, emu: Rc<RemoteEmulator>) -> Rc<EventLog> {
+        let widget = EventLogWidget::from_builder(builder).unwrap();
+        EventLog::from_widget(emu, widget)
+    }
+
+    fn connect_ui_events(self: &Rc<Self>) {
+        self.widget.filter.set_visible_func(
+            clone!(@weak self as log => @default-return true, move |model, iter| {
+                let category = model
+                    .value(iter, CATEGORY_COLUMN_INDEX as i32)
+                    .get::<String>()
+                    .ok()
+                    .and_then(|name| EventCategory::from_name(&name));
+                category.map_or(true, |category| log.is_shown(category))
+            }),
+        );
+        for category in EventCategory::ALL.iter() {
+            self.widget
+                .toggle(*category)
+                .connect_toggled(clone!(@weak self as log => move |_| {
+                    log.widget.filter.refilter();
+                }));
+        }
+        self.widget
+            .log_toggle
+            .connect_toggled(clone!(@weak self as log => move |_| {
+                log.stream_logs();
+            }));
+        self.widget
+            .log_level
+            .connect_changed(clone!(@weak self as log => move |_| {
+                log.log_level_changed();
+            }));
+        self.widget
+            .clear_button
+            .connect_clicked(clone!(@weak self as log => move |_| {
+                log.widget.store.clear();
+            }));
+    }
+
+    fn connect_adapter_events(self: &Rc<Self>, emu: &RemoteEmulator) {
+        emu.on_widget(self.clone(), |log, evt: ModeChangeEvent| {
+            let message = format!(
+                "{} to {}",
+                mode_name(&evt.old_mode),
+                mode_name(&evt.new_mode)
+            );
+            log.add_entry(EventCategory::Mode, &message);
+        });
+        emu.on_widget(self.clone(), |log, evt: BankSwitchEvent| {
+            let message = format!("{} bank {} to {}", evt.kind, evt.old, evt.new);
+            log.add_entry(EventCategory::Bank, &message);
+        });
+        emu.on_widget(self.clone(), |log, evt: InterruptEvent| {
+            log.add_entry(EventCategory::Interrupt, &evt.interrupt.to_string());
+        });
+        emu.on_widget(self.clone(), |log, evt: MemoryEvent| {
+            if let Some((category, message)) = describe_write(evt) {
+                log.add_entry(category, &message);
+            }
+        });
+        emu.on_widget(self.clone(), |log, evt: PrintEvent| {
+            let message = format!("Printed {}x{} image", evt.width, evt.height);
+            log.add_entry(EventCategory::Serial, &message);
+        });
+        emu.on_widget(self.clone(), |log, evt: LogEvent| {
+            log.add_entry(EventCategory::Log, &describe_log(&evt));
+        });
+    }
+
+    /// Stream log messages from the emulator while they are shown
+    fn stream_logs(&self) {
+        let stream = self.is_shown(EventCategory::Log);
+        let emu = self.emu.clone();
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+            utils::run_infallible(emu.stream_logs(stream)).await;
+        });
+    }
+
+    fn log_level_changed(&self) {
+        let level = self
+            .widget
+            .log_level
+            .active_id()
+            .and_then(|id| LevelFilter::from_str(&id).ok());
+        if let Some(level) = level {
+            let emu = self.emu.clone();
+            glib::MainContext::ref_thread_default().spawn_local(async move {
+                utils::run_infallible(emu.set_log_level(logging::ROOT, level)).await;
+            });
+        }
+    }
+
+    fn is_shown(&self, category: EventCategory) -> bool {
+        self.widget.toggle(category).is_active()
+    }
+
+    /// Add an entry at the top of the log, dropping the oldest if it is full
+    fn add_entry(&self, category: EventCategory, message: &str) {
+        let store = &self.widget.store;
+        store.insert_with_values(
+            Some(0),
+            &[
+                (CATEGORY_COLUMN_INDEX, &category.name()),
+                (MESSAGE_COLUMN_INDEX, &message),
+            ],
+        );
+        let len = store.iter_n_children(None);
+        if len > MAX_ENTRIES {
+            if let Some(oldest) = store.iter_nth_child(None, len - 1) {
+                store.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils;
+
+    fn messages(model: &impl IsA<gtk::TreeModel>) -> Vec<String> {
+        let mut messages = Vec::new();
+        if let Some(iter) = model.iter_first() {
+            loop {
+                let message = model.value(&iter, MESSAGE_COLUMN_INDEX as i32);
+                messages.push(message.get().unwrap());
+                if !model.iter_next(&iter) {
+                    break;
+                }
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn test_describe_write() {
+        let write = |address: u16, value| MemoryEvent::Write {
+            address: address.into(),
+            value,
+            new_value: value,
+        };
+
+        assert_eq!(
+            describe_write(write(0xFF46, 0xC1)),
+            Some((EventCategory::Dma, String::from("OAM DMA from C100h")))
+        );
+        assert_eq!(
+            describe_write(write(0xFF55, 0x83)),
+            Some((EventCategory::Dma, String::from("VRAM DMA of 64 bytes")))
+        );
+        assert_eq!(
+            describe_write(write(0xFF02, 0x81)).map(|(category, _)| category),
+            Some(EventCategory::Serial)
+        );
+        assert_eq!(describe_write(write(0xFF02, 0x01)), None);
+        assert_eq!(describe_write(write(0xC000, 0x81)), None);
+    }
+
+    #[test]
+    fn test_describe_log() {
+        let evt = LogEvent::new(
+            log::Level::Warn,
+            String::from(logging::MBC1),
+            String::from("Selected ROM bank: 0"),
+        );
+        assert_eq!(
+            describe_log(&evt),
+            "WARN olympia::rom::mbc1: Selected ROM bank: 0"
+        );
+    }
+
+    #[test]
+    fn gtk_test_filter_and_limit() {
+        test_utils::with_unloaded_emu(|_context, emu| {
+            let builder = gtk::Builder::from_string(include_str!("../../res/event_log.ui"));
+            let log = EventLog::from_builder(&builder, emu);
+
+            log.add_entry(EventCategory::Interrupt, "VBlank");
+            log.add_entry(EventCategory::Dma, "OAM DMA from C100h");
+            assert_eq!(
+                messages(&log.widget.filter),
+                vec!["OAM DMA from C100h", "VBlank"]
+            );
+
+            log.widget.interrupts_toggle.set_active(false);
+            assert_eq!(messages(&log.widget.filter), vec!["OAM DMA from C100h"]);
+
+            for _ in 0..MAX_ENTRIES {
+                log.add_entry(EventCategory::Mode, "Paused to Running");
+            }
+            assert_eq!(log.widget.store.iter_n_children(None), MAX_ENTRIES);
+            assert!(!messages(&log.widget.store).contains(&String::from("VBlank")));
+        });
+    }
+}
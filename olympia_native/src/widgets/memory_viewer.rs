@@ -9,6 +9,7 @@ use gtk::prelude::*;
 use olympia_engine::{
     address::LiteralAddress,
     events::{ManualStepEvent, MemoryEvent, RomLoadedEvent},
+    monitor::DIRTY_BLOCK_SIZE,
     remote::{QueryMemoryResponse, RemoteEmulator},
 };
 use std::cell::RefCell;
@@ -212,10 +213,14 @@ impl MemoryViewer {
         self.context.spawn_local(self.clone().refresh());
     }
 
+    fn refresh_changed_locations(self: &Rc<Self>) {
+        self.context.spawn_local(self.clone().refresh_dirty());
+    }
+
     fn connect_adapter_events(self: &Rc<Self>) {
         self.emu
             .on_widget(self.clone(), move |viewer, _evt: ManualStepEvent| {
-                viewer.refresh_all_locations()
+                viewer.refresh_changed_locations()
             });
         self.emu
             .on_widget(self.clone(), move |viewer, _evt: RomLoadedEvent| {
@@ -241,6 +246,32 @@ impl MemoryViewer {
         }
     }
 
+    /// Refresh only the visible rows in memory written since the last refresh
+    async fn refresh_dirty(self: Rc<Self>) {
+        let dirty = match self.emu.query_dirty_memory().await {
+            Ok(dirty) => dirty,
+            Err(_) => return,
+        };
+        let (start_addr, end_addr) = self.address_range();
+        let pc = self.emu.cached_pc();
+        for block_start in dirty.blocks {
+            let block_end = block_start.saturating_add(DIRTY_BLOCK_SIZE - 1);
+            if block_end < start_addr || block_start >= end_addr {
+                continue;
+            }
+            let query_start = block_start.max(start_addr);
+            let query_end = block_end.min(end_addr);
+            if let Ok(mem_response) = self.emu.query_memory(query_start, query_end).await {
+                for row_offset in (query_start..query_end).step_by(0x10) {
+                    let row_index = (row_offset - start_addr) / 0x10;
+                    if let Some(row) = self.row(usize::from(row_index)) {
+                        row.update(row_offset, pc, &mem_response);
+                    }
+                }
+            }
+        }
+    }
+
     fn goto_address(self: Rc<Self>, address: u16) {
         let ctx = self.context.clone();
         self.offset.replace(self.resolve(address));
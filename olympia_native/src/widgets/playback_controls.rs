@@ -56,7 +56,7 @@ impl PlaybackControls {
         match utils::run_fallible(self.emu.step(), None).await {
             Ok(_) => {}
             Err(e) => {
-                log::warn!(target: "playback_controls", "Failed to step on manual click: {}", e)
+                log::warn!("Failed to step on manual click: {}", e)
             }
         };
     }
@@ -1,11 +1,13 @@
 use crate::builder_struct;
 use gtk::glib;
+use gtk::glib::clone;
 use gtk::prelude::*;
 use olympia_engine::{
-    events::{ManualStepEvent, RegisterWriteEvent, RomLoadedEvent},
-    registers::WordRegister,
-    remote::{QueryRegistersResponse, RemoteEmulator},
+    events::{ManualStepEvent, ModeChangeEvent, RegisterWriteEvent, RomLoadedEvent},
+    registers::{Flag, WordRegister},
+    remote::{ExecMode, QueryRegistersResponse, RegisterValue, RemoteEmulator},
 };
+use std::cell::Cell;
 use std::rc::Rc;
 
 builder_struct!(
@@ -22,6 +24,14 @@ builder_struct!(
         hl_input: gtk::Entry,
         #[ogtk(id = "PCInput")]
         pc_input: gtk::Entry,
+        #[ogtk(id = "ZeroFlagToggle")]
+        zero_flag: gtk::CheckButton,
+        #[ogtk(id = "SubtractFlagToggle")]
+        subtract_flag: gtk::CheckButton,
+        #[ogtk(id = "HalfCarryFlagToggle")]
+        half_carry_flag: gtk::CheckButton,
+        #[ogtk(id = "CarryFlagToggle")]
+        carry_flag: gtk::CheckButton,
     }
 );
 
@@ -34,14 +44,29 @@ impl Default for RegisterLabelsWidget {
             hl_input: Default::default(),
             pc_input: Default::default(),
             sp_input: Default::default(),
+            zero_flag: Default::default(),
+            subtract_flag: Default::default(),
+            half_carry_flag: Default::default(),
+            carry_flag: Default::default(),
         }
     }
 }
 
+const FLAGS: [Flag; 4] = [Flag::Zero, Flag::AddSubtract, Flag::HalfCarry, Flag::Carry];
+
+/// Shows the CPU registers, and lets them be edited while paused
+///
+/// Type a value in hex and press Enter to write it to a register. The
+/// flag checkboxes each change a single bit of F.
 pub(crate) struct RegisterLabels {
     context: glib::MainContext,
     emu: Rc<RemoteEmulator>,
     widget: RegisterLabelsWidget,
+    /// Whether registers could be read from the emulator
+    loaded: Cell<bool>,
+    paused: Cell<bool>,
+    /// The last value shown for AF, which the flag checkboxes change
+    af: Cell<u16>,
 }
 
 impl RegisterLabels {
@@ -54,8 +79,13 @@ impl RegisterLabels {
             context,
             emu,
             widget,
+            loaded: Cell::new(false),
+            paused: Cell::new(true),
+            af: Cell::new(0),
         });
 
+        labels.set_editable(false);
+        labels.connect_ui_events();
         labels.connect_adapter_events();
         labels
     }
@@ -73,7 +103,27 @@ impl RegisterLabels {
         self.context.spawn_local(self.clone().update());
     }
 
+    fn connect_ui_events(self: &Rc<Self>) {
+        for (input, reg) in self.register_inputs() {
+            input.connect_activate(clone!(@weak self as labels => move |input| {
+                labels.register_edited(reg, input);
+            }));
+        }
+        for flag in FLAGS.iter().copied() {
+            self.flag_toggle(flag)
+                .connect_toggled(clone!(@weak self as labels => move |toggle| {
+                    labels.flag_toggled(flag, toggle.is_active());
+                }));
+        }
+    }
+
     fn connect_adapter_events(self: &Rc<Self>) {
+        self.emu
+            .on_widget(self.clone(), move |labels, evt: ModeChangeEvent| {
+                let paused = matches!(evt.new_mode, ExecMode::Paused | ExecMode::HitBreakpoint(_));
+                labels.paused.set(paused);
+                labels.set_editable(paused && labels.loaded.get());
+            });
         self.emu
             .on_widget(self.clone(), move |labels, _evt: ManualStepEvent| {
                 labels.refresh_all_labels()
@@ -99,6 +149,15 @@ impl RegisterLabels {
         }
     }
 
+    fn flag_toggle(&self, flag: Flag) -> &gtk::CheckButton {
+        match flag {
+            Flag::Zero => &self.widget.zero_flag,
+            Flag::AddSubtract => &self.widget.subtract_flag,
+            Flag::HalfCarry => &self.widget.half_carry_flag,
+            Flag::Carry => &self.widget.carry_flag,
+        }
+    }
+
     fn register_inputs(&self) -> Vec<(&gtk::Entry, WordRegister)> {
         WordRegister::all()
             .iter()
@@ -109,19 +168,69 @@ impl RegisterLabels {
     fn handle_register_write(&self, reg: WordRegister, value: u16) {
         self.label_for_register(reg)
             .set_text(&format!("{:04X}", value));
+        if reg == WordRegister::AF {
+            // Update the value first, so the toggles don't write it back
+            self.af.set(value);
+            for flag in FLAGS.iter().copied() {
+                let set = value & (1 << flag.bit()) != 0;
+                self.flag_toggle(flag).set_active(set);
+            }
+        }
     }
 
     fn set_editable(&self, editable: bool) {
         for (input, _) in self.register_inputs().iter_mut() {
             input.set_editable(editable);
         }
+        for flag in FLAGS.iter().copied() {
+            self.flag_toggle(flag).set_sensitive(editable);
+        }
+    }
+
+    fn register_edited(self: &Rc<Self>, reg: WordRegister, input: &gtk::Entry) {
+        if !input.is_editable() {
+            return;
+        }
+        match u16::from_str_radix(input.text().trim(), 16) {
+            Ok(value) => self.write_register(reg, value),
+            Err(_) => {
+                log::warn!("Invalid value for {:?}: {:?}", reg, input.text());
+                self.refresh_all_labels();
+            }
+        }
+    }
+
+    fn flag_toggled(self: &Rc<Self>, flag: Flag, set: bool) {
+        let was_set = self.af.get() & (1 << flag.bit()) != 0;
+        if was_set == set {
+            return;
+        }
+        // AF is redrawn from the register write event this causes
+        let labels = self.clone();
+        self.context.spawn_local(async move {
+            if let Err(e) = labels.emu.set_flag(flag, set).await {
+                log::warn!("Failed to set {:?}: {}", flag, e);
+            }
+        });
+    }
+
+    fn write_register(self: &Rc<Self>, reg: WordRegister, value: u16) {
+        let labels = self.clone();
+        self.context.spawn_local(async move {
+            match labels.emu.set_register((reg, value)).await {
+                // The written value may differ, such as the lower bits of F
+                Ok(RegisterValue::Word(reg, value)) => labels.handle_register_write(reg, value),
+                Ok(RegisterValue::Byte(..)) => labels.refresh_all_labels(),
+                Err(e) => log::warn!("Failed to write {:?}: {}", reg, e),
+            }
+        });
     }
 
     fn render(&self, registers: QueryRegistersResponse) {
-        self.set_editable(true);
-        for (input, register) in self.register_inputs().iter_mut() {
-            let value = registers.read_u16(*register);
-            input.set_text(&format!("{:04X}", value));
+        self.loaded.set(true);
+        self.set_editable(self.paused.get());
+        for register in WordRegister::all().iter() {
+            self.handle_register_write(*register, registers.read_u16(*register));
         }
     }
 
@@ -129,7 +238,10 @@ impl RegisterLabels {
         let register_result = self.emu.query_registers().await;
         match register_result {
             Ok(registers) => self.render(registers),
-            Err(_) => self.set_editable(false),
+            Err(_) => {
+                self.loaded.set(false);
+                self.set_editable(false);
+            }
         }
     }
 }
@@ -200,6 +312,28 @@ mod tests {
         });
     }
 
+    #[test]
+    fn gtk_edit_registers() {
+        test_utils::with_loaded_emu(|context, emu| {
+            let builder = gtk::Builder::from_string(include_str!("../../res/registers.ui"));
+            let component = RegisterLabels::from_builder(&builder, context.clone(), emu.clone());
+            test_utils::wait_for_task(&context, component.clone().update());
+            let carry = component.widget.carry_flag.is_active();
+
+            component.widget.bc_input.set_text("12ab");
+            component.widget.bc_input.activate();
+            test_utils::digest_events(&context);
+            component.widget.carry_flag.set_active(!carry);
+            test_utils::digest_events(&context);
+
+            let registers = test_utils::wait_for_task(&context, emu.query_registers()).unwrap();
+            assert_eq!(registers.bc, 0x12AB);
+            assert_eq!(registers.af & 0x10 != 0, !carry);
+            let af_text: String = component.widget.af_input.text().into();
+            assert_eq!(af_text, format!("{:04X}", registers.af));
+        });
+    }
+
     #[test]
     fn gtk_handle_write() {
         test_utils::with_loaded_emu(|context, emu| {
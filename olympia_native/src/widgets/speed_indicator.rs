@@ -0,0 +1,101 @@
+use crate::builder_struct;
+use gtk::prelude::*;
+use olympia_engine::{
+    events::{EmulationSpeedEvent, ModeChangeEvent},
+    remote::{ExecMode, RemoteEmulator},
+};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+builder_struct!(
+    pub(crate) struct SpeedIndicatorWidget {
+        #[ogtk(id = "SpeedIndicator")]
+        label: gtk::Label,
+    }
+);
+
+/// Shows the target emulation speed and the emulated frame rate
+pub(crate) struct SpeedIndicator {
+    widget: SpeedIndicatorWidget,
+    speed_percent: Cell<u32>,
+    mode: RefCell<ExecMode>,
+}
+
+impl SpeedIndicator {
+    pub(crate) fn from_widget(
+        emu: Rc<RemoteEmulator>,
+        widget: SpeedIndicatorWidget,
+    ) -> Rc<SpeedIndicator> {
+        let indicator = Rc::new(SpeedIndicator {
+            widget,
+            speed_percent: Cell::new(100),
+            mode: RefCell::new(ExecMode::Unloaded),
+        });
+        indicator.show_speed(None);
+        indicator.connect_adapter_events(&emu);
+        indicator
+    }
+
+    pub(crate) fn from_builder(
+        builder: &gtk::Builder,
+        emu: Rc<RemoteEmulator>,
+    ) -> Rc<SpeedIndicator> {
+        let widget = SpeedIndicatorWidget::from_builder(builder).unwrap();
+        SpeedIndicator::from_widget(emu, widget)
+    }
+
+    fn connect_adapter_events(self: &Rc<Self>, emu: &RemoteEmulator) {
+        emu.on_widget(self.clone(), |indicator, evt: EmulationSpeedEvent| {
+            indicator.speed_percent.set(evt.speed_percent);
+            let running = matches!(
+                *indicator.mode.borrow(),
+                ExecMode::Standard | ExecMode::Uncapped
+            );
+            indicator.show_speed(if running { Some(evt.fps) } else { None });
+        });
+        emu.on_widget(self.clone(), |indicator, evt: ModeChangeEvent| {
+            indicator.mode.replace(evt.new_mode);
+            indicator.show_speed(None);
+        });
+    }
+
+    /// Update the label, showing no frame rate if it's not yet known
+    fn show_speed(&self, fps: Option<u32>) {
+        let speed = match *self.mode.borrow() {
+            ExecMode::Uncapped => String::from("Uncapped"),
+            _ => format!("{}%", self.speed_percent.get()),
+        };
+        let fps = fps.map_or_else(|| String::from("--"), |fps| fps.to_string());
+        self.widget
+            .label
+            .set_text(&format!("Speed: {} | {} FPS", speed, fps));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils;
+
+    #[test]
+    fn gtk_test_show_speed() {
+        test_utils::with_unloaded_emu(|context, emu| {
+            let builder = gtk::Builder::from_string(include_str!("../../res/debugger.ui"));
+            let indicator = SpeedIndicator::from_builder(&builder, emu.clone());
+            assert_eq!(
+                indicator.widget.label.text().as_str(),
+                "Speed: 100% | -- FPS"
+            );
+
+            let task = async {
+                emu.load_rom(test_utils::fizzbuzz_rom()).await.unwrap();
+                emu.set_mode(ExecMode::Standard).await.unwrap();
+                emu.set_speed(50).await.unwrap();
+            };
+            test_utils::wait_for_task(&context, task);
+            test_utils::digest_events(&context);
+
+            assert_eq!(indicator.widget.label.text().as_str(), "Speed: 50% | 0 FPS");
+        });
+    }
+}